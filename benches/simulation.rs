@@ -0,0 +1,171 @@
+//! simulation throughput, tracked to catch performance regressions: `cargo bench`, or `cargo bench --features jit`
+//! to also measure the JIT engine
+use criterion::{BatchSize, Criterion, Throughput, criterion_group, criterion_main};
+use riscv_on_rust::risc_soc::benchmark::measure;
+use riscv_on_rust::risc_soc::cache::Cache;
+use riscv_on_rust::risc_soc::memory_management_unit::{Address, MemoryDevice, MemoryDeviceType, MemoryRequest};
+use riscv_on_rust::risc_soc::risc_soc::{RiscCore, RiscWord};
+use riscv_on_rust::rv32i_baremetal::core::{DRAM_ADDRESS, init_core};
+use riscv_on_rust::rv32i_baremetal::mcu_cache::MCUCache;
+use std::hint::black_box;
+
+const PROGRAM_ADDRESS: Address = 0x8000_0000;
+
+/// Dhrystone-like loop: record updates, a string copy and calls to a comparison function
+const DHRYSTONE_LIKE: &str = "
+    start:
+        la s0, record
+        li s1, 100
+    loop:
+        lw t0, 0(s0)
+        addi t0, t0, 1
+        sw t0, 0(s0)
+        lw t1, 4(s0)
+        add t1, t1, t0
+        sw t1, 4(s0)
+        la a2, source
+        la a3, target
+        li a4, 4
+    copy:
+        lw t2, 0(a2)
+        sw t2, 0(a3)
+        addi a2, a2, 4
+        addi a3, a3, 4
+        addi a4, a4, -1
+        bnez a4, copy
+        mv a0, t0
+        mv a1, t1
+        call compare
+        addi s1, s1, -1
+        bnez s1, loop
+        j start
+    compare:
+        blt a0, a1, less
+        li a0, 1
+        ret
+    less:
+        li a0, 0
+        ret
+    record: .word 0
+        .word 0
+    source: .word 0x44485259
+        .word 0x53544F4E
+        .word 0x45205052
+        .word 0x4F47524D
+    target: .word 0
+        .word 0
+        .word 0
+        .word 0
+";
+
+/// CoreMark-like loop: a bitwise CRC followed by a dot product using the multiplier
+const COREMARK_LIKE: &str = "
+    start:
+        li s0, 0
+        li s1, 64
+        li t1, 0xEDB88320
+    crc:
+        andi t0, s0, 1
+        srli s0, s0, 1
+        beqz t0, skip
+        xor s0, s0, t1
+    skip:
+        xor s0, s0, s1
+        addi s1, s1, -1
+        bnez s1, crc
+        la a0, vector
+        li a1, 4
+        li a2, 0
+    dot:
+        lw t2, 0(a0)
+        lw t3, 4(a0)
+        mul t4, t2, t3
+        add a2, a2, t4
+        addi a0, a0, 8
+        addi a1, a1, -1
+        bnez a1, dot
+        j start
+    vector: .word 1
+        .word 2
+        .word 3
+        .word 4
+        .word 5
+        .word 6
+        .word 7
+        .word 8
+";
+
+const PROGRAMS: [(&str, &str); 2] = [("dhrystone_like", DHRYSTONE_LIKE), ("coremark_like", COREMARK_LIKE)];
+
+fn build(program: &str) -> RiscCore {
+    let mut core = init_core(None);
+    core.load_assembly(program, PROGRAM_ADDRESS).unwrap();
+    core.set_reset_vector(PROGRAM_ADDRESS as RiscWord);
+    core
+}
+
+/// instructions per second of the pipeline, on the stage threads and on the calling thread
+fn pipeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+    for (name, program) in PROGRAMS {
+        for (threaded, cycles) in [(false, 20_000), (true, 2_000)] {
+            // the throughput is counted in retired instructions, which is the same on every run of the program
+            let instructions = measure(&mut build(program), cycles, threaded).instructions;
+            group.throughput(Throughput::Elements(instructions));
+            let id = format!("{name}/{}", if threaded { "threaded" } else { "sequential" });
+            group.bench_function(id, |b| {
+                b.iter_batched(|| build(program), |mut core| measure(&mut core, cycles, threaded), BatchSize::LargeInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+/// instructions per second of the JIT engine, which runs on the architectural state only: the hot blocks are translated
+/// and the rest is interpreted
+#[cfg(feature = "jit")]
+fn jit(c: &mut Criterion) {
+    use riscv_on_rust::rv32i_baremetal::jit::JitEngine;
+
+    const INSTRUCTIONS: u64 = 100_000;
+    let mut group = c.benchmark_group("jit");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    for (name, program) in PROGRAMS {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || build(program),
+                |core| JitEngine::default().run(&core, INSTRUCTIONS).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "jit"))]
+fn jit(_c: &mut Criterion) {}
+
+/// accesses per second of the memory subsystems on the path of every load and store
+fn memory(c: &mut Criterion) {
+    let mut group = c.benchmark_group("memory");
+    group.throughput(Throughput::Elements(1));
+    let core = build(DHRYSTONE_LIKE);
+    group.bench_function("mmu_dispatch", |b| {
+        b.iter(|| core.mmu.write().unwrap().process_memory_request(black_box(MemoryRequest::read_u32(DRAM_ADDRESS))))
+    });
+    let mut cache = MCUCache::new_with_lines(MemoryDeviceType::L1DCACHE, 64, 1024, PROGRAM_ADDRESS);
+    group.bench_function("cache_load", |b| {
+        b.iter(|| cache.send_data_request(black_box(MemoryRequest::read_u32(PROGRAM_ADDRESS + 0x40))))
+    });
+    group.bench_function("cache_store", |b| {
+        b.iter(|| cache.send_data_request(black_box(MemoryRequest::write_u32(PROGRAM_ADDRESS + 0x40, 0x1234_5678))))
+    });
+    group.bench_function("core_data_request", |b| {
+        b.iter(|| core.data_request(black_box(MemoryRequest::read_u32(DRAM_ADDRESS))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, pipeline, jit, memory);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+pub mod risc_soc;
+pub mod rv32i_baremetal;
+pub mod rv32i_ooo;
+pub mod user_mode;
@@ -1,16 +1,378 @@
-mod risc_soc;
-mod rv32i_baremetal;
+use riscv_on_rust::{risc_soc, rv32i_baremetal, rv32i_ooo, user_mode};
 use tracing_subscriber::{EnvFilter, fmt};
 
 fn main() {
-    fmt::fmt()
-        .with_env_filter(EnvFilter::new("info"))
-        .with_writer(std::io::stderr)
-        .compact()
-        .init();
+    // the dashboard owns the terminal, so the log would only garble it
+    let tui = cfg!(feature = "tui") && std::env::args().any(|arg| arg == "--tui");
+    if !tui {
+        fmt::fmt()
+            .with_env_filter(EnvFilter::new("info"))
+            .with_writer(std::io::stderr)
+            .compact()
+            .init();
+    }
 
     tracing::info!("Initializing RISCV32 runtime environment");
-    let mut rv32i_core = rv32i_baremetal::core::init_core(None);
-    rv32i_baremetal::core::load_elf(&mut rv32i_core, "./qemu_playground/test_microblaze.elf");
-    rv32i_core.run(Some(48));
+    // statically linked Linux programs run with their system calls emulated: --user <elf> [args...]
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--user") {
+        let Some(program) = args.get(index + 1) else {
+            tracing::error!("--user expects the path of the program to run");
+            std::process::exit(1);
+        };
+        let program_args: Vec<&str> = args[index + 2..].iter().map(String::as_str).collect();
+        let (mut core, _) = match user_mode::process::init_user_core(program, &program_args, &[]) {
+            Ok(process) => process,
+            Err(e) => {
+                tracing::error!("Failed to load program: {e}");
+                std::process::exit(1);
+            }
+        };
+        if let risc_soc::run_control::StopReason::Error(error) = core.run(None) {
+            tracing::error!("Simulation stopped: {error}");
+            std::process::exit(1);
+        }
+        match core.exit_status() {
+            Some(risc_soc::risc_soc::ExitStatus::Pass) => std::process::exit(0),
+            Some(risc_soc::risc_soc::ExitStatus::Fail(code)) => std::process::exit(code as i32),
+            _ => {
+                if let Some(trap) = core.pending_trap() {
+                    tracing::error!("Program stopped on {trap}");
+                }
+                std::process::exit(1);
+            }
+        }
+    }
+    // memory map of a real board, so firmware linked for it runs unchanged: --board qemu-virt or --board fe310
+    let mut board = match args.iter().position(|arg| arg == "--board") {
+        Some(index) => match args.get(index + 1).and_then(|name| rv32i_baremetal::core::BoardConfig::preset(name)) {
+            Some(board) => board,
+            None => {
+                tracing::error!("--board expects qemu-virt or fe310");
+                std::process::exit(1);
+            }
+        },
+        None => rv32i_baremetal::core::BoardConfig::DEFAULT,
+    };
+    // the out-of-order core shares the memories and devices of the MCU, so the same programs run on both
+    let mut rv32i_core = if std::env::args().any(|arg| arg == "--ooo") {
+        if board != rv32i_baremetal::core::BoardConfig::DEFAULT {
+            tracing::warn!("The out-of-order core only uses the default memory map");
+            board = rv32i_baremetal::core::BoardConfig::DEFAULT;
+        }
+        rv32i_ooo::core::init_core(None)
+    } else {
+        rv32i_baremetal::core::init_board(&board, None)
+    };
+    // latency and throughput of the instruction classes, to approximate the cycle counts of another core: --timing <json>
+    if let Some(index) = args.iter().position(|arg| arg == "--timing") {
+        let timing = args.get(index + 1).ok_or("--timing expects the path of a JSON table".to_string());
+        match timing.and_then(|path| risc_soc::timing::TimingModel::load(path)) {
+            Ok(timing) if !args.iter().any(|arg| arg == "--ooo") => rv32i_baremetal::core::set_timing_model(&rv32i_core, timing),
+            Ok(_) => tracing::warn!("The out-of-order core does not use a timing table"),
+            Err(e) => {
+                tracing::error!("Failed to load the timing table: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+    // vector register file of the MCU, for programs using Zve32x: --vlen <bits>
+    if let Some(index) = args.iter().position(|arg| arg == "--vlen") {
+        match args.get(index + 1).and_then(|vlen| vlen.parse::<usize>().ok()).filter(|vlen| vlen.is_power_of_two() && (32..=65536).contains(vlen)) {
+            Some(_) if args.iter().any(|arg| arg == "--ooo") => tracing::warn!("The out-of-order core does not execute vector instructions"),
+            Some(vlen) => rv32i_core.enable_vector(vlen),
+            None => {
+                tracing::error!("--vlen expects a power of two between 32 and 65536 bits");
+                std::process::exit(1);
+            }
+        }
+    }
+    // console of the board on a terminal instead of stdin and stdout: --serial tcp:<address> for telnet or nc, --serial pty for picocom or minicom
+    if let Some(index) = args.iter().position(|arg| arg == "--serial") {
+        let spec = args.get(index + 1).map(String::as_str).unwrap_or_default();
+        let connection = match spec.strip_prefix("tcp:") {
+            Some(address) => rv32i_baremetal::serial::SerialConnection::tcp(address),
+            #[cfg(unix)]
+            None if spec == "pty" => rv32i_baremetal::serial::SerialConnection::pty().map(|(connection, _path)| connection),
+            None => Err(std::io::Error::other("--serial expects tcp:<address> or pty")),
+        };
+        match connection {
+            Ok(connection) => rv32i_baremetal::core::attach_serial(&rv32i_core, board.console, connection),
+            Err(e) => {
+                tracing::error!("Failed to open the serial port: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+    // NIC bridged to the host: --net user for the user mode stack (UDP through host sockets), --net tap:<interface> on Linux
+    if let Some(index) = args.iter().position(|arg| arg == "--net") {
+        let spec = args.get(index + 1).map(String::as_str).unwrap_or_default();
+        let backend: std::io::Result<Box<dyn rv32i_baremetal::network::NetworkBackend>> = match spec.strip_prefix("tap:") {
+            #[cfg(target_os = "linux")]
+            Some(name) => rv32i_baremetal::network::TapBackend::open(name).map(|tap| Box::new(tap) as _),
+            None if spec == "user" => Ok(Box::new(rv32i_baremetal::network::UserNetwork::new())),
+            _ => Err(std::io::Error::other("--net expects user or tap:<interface>")),
+        };
+        match backend {
+            Ok(backend) => rv32i_baremetal::core::add_network_device(&rv32i_core, backend),
+            Err(e) => {
+                tracing::error!("Failed to open the network backend: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+    // load the PT_LOAD segments instead of the usual sections, for custom linker scripts: --load-segments
+    if std::env::args().any(|arg| arg == "--load-segments") {
+        rv32i_core.set_elf_load_mode(risc_soc::load_error::ElfLoadMode::Segments);
+    }
+    // start from another address than the entry point of the ELF: --entry <address>
+    if let Some(index) = args.iter().position(|arg| arg == "--entry") {
+        let entry = args.get(index + 1).and_then(|value| match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        });
+        match entry {
+            Some(entry) => rv32i_core.set_entry_override(Some(entry as risc_soc::risc_soc::RiscWord)),
+            None => {
+                tracing::error!("--entry expects an address");
+                std::process::exit(1);
+            }
+        }
+    }
+    // warn about the loads of bytes of the data memory and DRAM never written or loaded: --shadow-memory
+    if std::env::args().any(|arg| arg == "--shadow-memory") {
+        let dram = rv32i_baremetal::core::DRAM_ADDRESS;
+        let mut shadow = risc_soc::shadow_memory::ShadowMemory::default().with_range(dram, dram + rv32i_baremetal::core::DRAM_SIZE);
+        if let Some(dcache) = &rv32i_core.dcache {
+            let (start, end) = dcache.read().unwrap().start_end_addresses();
+            shadow = shadow.with_range(start, end);
+        }
+        rv32i_core.enable_shadow_memory(shadow);
+    }
+    // the presets keep the program in the memories of the board, the default map splits it between the L1 memories
+    let loaded = if board == rv32i_baremetal::core::BoardConfig::DEFAULT {
+        rv32i_baremetal::core::load_elf(&mut rv32i_core, "./qemu_playground/test_microblaze.elf")
+    } else {
+        rv32i_core.load_binary("./qemu_playground/test_microblaze.elf", risc_soc::memory_management_unit::MemoryDeviceType::DRAM)
+    };
+    if let Err(e) = loaded {
+        tracing::error!("Failed to load program: {e}");
+        return;
+    }
+    // registers and memory preloaded in place of a crt0, ex. the stack pointer: --init <json>
+    if let Some(index) = args.iter().position(|arg| arg == "--init") {
+        let state = args.get(index + 1).ok_or("--init expects the path of a JSON description".to_string());
+        if let Err(e) = state.and_then(|path| risc_soc::initial_state::InitialState::load(path)).and_then(|state| rv32i_core.set_initial_state(state)) {
+            tracing::error!("Failed to set the initial state: {e}");
+            std::process::exit(1);
+        }
+    }
+    // programs built with --specs=semihost.specs print through EBREAK sequences instead of a UART driver
+    if std::env::args().any(|arg| arg == "--semihosting") {
+        rv32i_core.enable_semihosting("test_microblaze.elf");
+    }
+    // mtime follows the time of the host, so timeouts of the firmware last as long as on the real board
+    if std::env::args().any(|arg| arg == "--real-time") {
+        let clock = risc_soc::clock::HostClock::new(rv32i_baremetal::core::CLINT_TIMEBASE_HZ, 1.0);
+        rv32i_core.mmu.write().unwrap().set_host_clock(risc_soc::memory_management_unit::MemoryDeviceType::CLINT, clock);
+    }
+    // clock frequency the stages are throttled to, for demos: --speed <hz>
+    if let Some(index) = args.iter().position(|arg| arg == "--speed") {
+        match args.get(index + 1).and_then(|hz| hz.parse().ok()).filter(|hz| (1..=1_000_000_000).contains(hz)) {
+            Some(hz) => rv32i_core.set_speed(hz),
+            None => {
+                tracing::error!("--speed expects a clock frequency in Hz");
+                std::process::exit(1);
+            }
+        }
+    }
+    // host speed of both run loops on the loaded program: --bench <cycles>
+    if let Some(index) = args.iter().position(|arg| arg == "--bench") {
+        let cycles = args.get(index + 1).and_then(|cycles| cycles.parse().ok()).unwrap_or(1_000_000);
+        for threaded in [false, true] {
+            rv32i_core.reset(false);
+            let speed = risc_soc::benchmark::measure(&mut rv32i_core, cycles, threaded);
+            println!("{}: {speed}", if threaded { "threaded" } else { "sequential" });
+        }
+        return;
+    }
+    if std::env::args().any(|arg| arg == "--interactive") {
+        let mut debugger = risc_soc::debugger::Debugger::new(&mut rv32i_core);
+        if let Err(e) = debugger.repl(std::io::stdin().lock(), std::io::stdout()) {
+            tracing::error!("Debugger stopped: {e}");
+        }
+        return;
+    }
+    #[cfg(feature = "tui")]
+    if tui {
+        if let Err(e) = risc_soc::dashboard::Dashboard::new(&mut rv32i_core).run() {
+            eprintln!("Dashboard stopped: {e}");
+        }
+        return;
+    }
+    #[cfg(feature = "jit")]
+    if std::env::args().any(|arg| arg == "--jit") {
+        let mut jit = rv32i_baremetal::jit::JitEngine::default();
+        match jit.run(&rv32i_core, 1_000_000) {
+            Ok(executed) => tracing::info!(
+                "Executed {executed} instructions ({} translated, {} interpreted)",
+                jit.translated_instructions,
+                jit.interpreted_instructions
+            ),
+            Err(e) => tracing::error!("Execution stopped: {e}"),
+        }
+        return;
+    }
+    // accesses of the data port written next to the memory dump, for diffing two runs: --access-log
+    if std::env::args().any(|arg| arg == "--access-log") {
+        rv32i_core.enable_access_log();
+    }
+    // symbolized backtrace of the program logged on exceptions and errors of the model: --backtrace
+    if std::env::args().any(|arg| arg == "--backtrace") {
+        rv32i_core.enable_backtraces();
+    }
+    // stop on the accesses right outside the stack or heap of the program: --stack-guard <top>:<size> --heap-guard <start>:<end>
+    let mut guard = risc_soc::memory_guard::MemoryGuard::default();
+    for flag in ["--stack-guard", "--heap-guard"] {
+        let Some(index) = args.iter().position(|arg| arg == flag) else {
+            continue;
+        };
+        let parse = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        };
+        let range = args.get(index + 1).and_then(|range| range.split_once(':')).and_then(|(first, second)| Some((parse(first)?, parse(second)?)));
+        guard = match (flag, range) {
+            ("--stack-guard", Some((top, size))) if size < top => guard.with_stack(top, size, risc_soc::memory_guard::DEFAULT_GUARD_SIZE),
+            ("--heap-guard", Some((start, end))) if start < end => guard.with_heap(start, end, risc_soc::memory_guard::DEFAULT_GUARD_SIZE),
+            _ => {
+                tracing::error!("--stack-guard expects <top>:<size> and --heap-guard <start>:<end>");
+                std::process::exit(1);
+            }
+        };
+    }
+    if !guard.regions().is_empty() {
+        rv32i_core.enable_memory_guard(guard);
+    }
+    // allocations, leaks and peak usage of the heap, through the functions of newlib or the ones given by address:
+    // --heap-profile, --heap-hooks malloc=<address>,free=<address>[,calloc=<address>,realloc=<address>]
+    let heap_tracker = if let Some(index) = args.iter().position(|arg| arg == "--heap-hooks") {
+        let parse = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        };
+        let hooks: Option<Vec<(risc_soc::heap_tracker::HeapFunction, u64)>> = args.get(index + 1).and_then(|hooks| {
+            hooks
+                .split(',')
+                .map(|hook| {
+                    let (name, address) = hook.split_once('=')?;
+                    let function = match name {
+                        "malloc" => risc_soc::heap_tracker::HeapFunction::Malloc,
+                        "calloc" => risc_soc::heap_tracker::HeapFunction::Calloc,
+                        "realloc" => risc_soc::heap_tracker::HeapFunction::Realloc,
+                        "free" => risc_soc::heap_tracker::HeapFunction::Free,
+                        _ => return None,
+                    };
+                    Some((function, parse(address)?))
+                })
+                .collect()
+        });
+        let Some(hooks) = hooks else {
+            tracing::error!("--heap-hooks expects a list of <function>=<address> with malloc, calloc, realloc or free");
+            std::process::exit(1);
+        };
+        let tracker = hooks
+            .into_iter()
+            .fold(risc_soc::heap_tracker::HeapTracker::default(), |tracker, (function, address)| tracker.with_hook(function, address));
+        Some(rv32i_core.enable_heap_tracker(tracker))
+    } else if std::env::args().any(|arg| arg == "--heap-profile") {
+        match risc_soc::heap_tracker::HeapTracker::newlib(&rv32i_core) {
+            Ok(tracker) => Some(rv32i_core.enable_heap_tracker(tracker)),
+            Err(e) => {
+                tracing::error!("Cannot profile the heap: {e}");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    // retired instructions, branches, traps, cache misses and MMIO accesses as JSON lines: --event-log <path>
+    let event_log = match args.iter().position(|arg| arg == "--event-log") {
+        Some(index) => match args.get(index + 1).map(std::fs::File::create) {
+            Some(Ok(file)) => Some(rv32i_core.enable_event_log(std::io::BufWriter::new(file))),
+            Some(Err(e)) => {
+                tracing::error!("Failed to create the event log: {e}");
+                None
+            }
+            None => {
+                tracing::error!("--event-log expects the path of the log");
+                None
+            }
+        },
+        None => None,
+    };
+    // stage activity and device accesses for Perfetto: --chrome-trace <path>
+    let chrome_trace = args.iter().position(|arg| arg == "--chrome-trace").map(|index| (rv32i_core.enable_chrome_trace(), args.get(index + 1)));
+    // skip the cycles the program sleeps in WFI until the timer or a device wakes it up
+    let stop = if std::env::args().any(|arg| arg == "--event-driven") {
+        rv32i_core.run_event_driven(Some(48))
+    } else {
+        rv32i_core.run(Some(48))
+    };
+    if let Some(Err(e)) = event_log.map(|log| log.finish()) {
+        tracing::error!("Failed to write the event log: {e}");
+    }
+    match chrome_trace {
+        Some((trace, Some(path))) => {
+            if let Err(e) = std::fs::File::create(path).and_then(|file| trace.write_json(std::io::BufWriter::new(file))) {
+                tracing::error!("Failed to write the trace: {e}");
+            }
+        }
+        Some((_, None)) => tracing::error!("--chrome-trace expects the path of the trace"),
+        None => {}
+    }
+    if let risc_soc::run_control::StopReason::Error(error) = stop {
+        tracing::error!("Simulation stopped: {error}");
+        std::process::exit(1);
+    }
+    // memory at the end of the run as a hex dump: --dump-memory <path> <start>:<length>[,<start>:<length>...]
+    if let Some(index) = args.iter().position(|arg| arg == "--dump-memory") {
+        let parse = |value: &str| match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).ok(),
+            None => value.parse().ok(),
+        };
+        let ranges: Option<Vec<(u64, usize)>> = args.get(index + 2).and_then(|ranges| {
+            ranges
+                .split(',')
+                .map(|range| {
+                    let (start, len) = range.split_once(':')?;
+                    Some((parse(start)?, parse(len)? as usize))
+                })
+                .collect()
+        });
+        match (args.get(index + 1), ranges) {
+            (Some(path), Some(ranges)) => {
+                if let Err(e) = rv32i_core.dump_memory(path, &ranges) {
+                    tracing::error!("Failed to dump the memory: {e}");
+                }
+            }
+            _ => tracing::error!("--dump-memory expects a path and a list of <start>:<length> ranges"),
+        }
+    }
+    // cycles, models and accesses of each device at the end of the run: --stats
+    if std::env::args().any(|arg| arg == "--stats") {
+        print!("{}", rv32i_core.statistics_report());
+    }
+    if let Some(tracker) = heap_tracker {
+        print!("{}", tracker.report());
+    }
+    match rv32i_core.exit_status() {
+        Some(risc_soc::risc_soc::ExitStatus::Pass) => tracing::info!("Program finished with PASS"),
+        Some(risc_soc::risc_soc::ExitStatus::Fail(code)) => {
+            tracing::error!("Program finished with FAIL, exit code {code}");
+            std::process::exit(if code == 0 { 1 } else { code as i32 });
+        }
+        Some(risc_soc::risc_soc::ExitStatus::Reset) => tracing::info!("Program requested a reset"),
+        None => {}
+    }
 }
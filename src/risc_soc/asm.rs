@@ -0,0 +1,484 @@
+use crate::risc_soc::disasm::CSR_NAMES;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::register_index;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// source line that could not be assembled, lines are numbered from 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub reason: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// machine code of a program together with the addresses of its labels
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    pub base_address: Address,
+    pub words: Vec<u32>,
+    pub labels: HashMap<String, Address>,
+}
+
+impl Assembly {
+    pub fn bytes(&self) -> Vec<u8> {
+        self.words.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+}
+
+/// assemble a program placed at `base_address`, one statement per line
+/// supports RV32IMA (with the Zicsr and Zifencei instructions), `.word`, labels, `#` comments
+/// and the common pseudo instructions (li, la, mv, not, neg, j, jr, call, ret, beqz, bnez, csrr, csrw, ...)
+pub fn assemble(source: &str, base_address: Address) -> Result<Assembly, AsmError> {
+    // the first pass places the statements, the second one encodes them once every label is known
+    let mut statements = vec![];
+    let mut labels = HashMap::new();
+    let mut address = base_address;
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |reason: String| AsmError { line: line_number, reason };
+        let mut text = line.split('#').next().unwrap().trim();
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.') {
+                return Err(error(format!("invalid label: {label}")));
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(error(format!("label {label} is defined twice")));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, operands.split(',').map(str::trim).collect()),
+            None => (text, vec![]),
+        };
+        let statement = Statement { line: line_number, address, mnemonic: mnemonic.to_lowercase(), operands };
+        address += 4 * statement.size().map_err(error)? as Address;
+        statements.push(statement);
+    }
+
+    let mut words = vec![];
+    for statement in &statements {
+        let encoded = statement
+            .encode(&labels)
+            .map_err(|reason| AsmError { line: statement.line, reason })?;
+        assert_eq!(encoded.len(), statement.size().unwrap());
+        words.extend(encoded);
+    }
+    Ok(Assembly { base_address, words, labels })
+}
+
+struct Statement<'a> {
+    line: usize,
+    address: Address,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(&hex.replace('_', ""), 16).ok()?,
+        None => digits.replace('_', "").parse().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// 32-bit value, given either as signed or as unsigned
+fn parse_word(text: &str) -> Result<u32, String> {
+    match parse_number(text) {
+        Some(value) if (i32::MIN as i64..=u32::MAX as i64).contains(&value) => Ok(value as u32),
+        Some(_) => Err(format!("{text} does not fit in 32 bits")),
+        None => Err(format!("invalid number: {text}")),
+    }
+}
+
+fn parse_reg(text: &str) -> Result<u32, String> {
+    register_index(text).map(|index| index as u32).ok_or(format!("invalid register: {text}"))
+}
+
+/// signed immediate of the given width
+fn parse_imm(text: &str, bits: u32) -> Result<u32, String> {
+    let value = parse_number(text).ok_or(format!("invalid immediate: {text}"))?;
+    check_signed(value, bits).ok_or(format!("immediate {text} does not fit in {bits} bits"))
+}
+
+fn check_signed(value: i64, bits: u32) -> Option<u32> {
+    let limit = 1i64 << (bits - 1);
+    (-limit..limit).contains(&value).then_some(value as u32 & ((1u64 << bits) - 1) as u32)
+}
+
+/// `offset(register)`, the offset may be omitted as in the operands of the atomic instructions
+fn parse_address(text: &str) -> Result<(u32, u32), String> {
+    let (offset, register) = text
+        .strip_suffix(')')
+        .and_then(|text| text.split_once('('))
+        .ok_or(format!("expected offset(register): {text}"))?;
+    let offset = if offset.trim().is_empty() { 0 } else { parse_imm(offset.trim(), 12)? };
+    Ok((offset, parse_reg(register.trim())?))
+}
+
+fn parse_csr(text: &str) -> Result<u32, String> {
+    if let Some(&(number, _)) = CSR_NAMES.iter().find(|&&(_, name)| name == text) {
+        return Ok(number);
+    }
+    match parse_number(text) {
+        Some(number) if (0..0x1000).contains(&number) => Ok(number as u32),
+        _ => Err(format!("invalid CSR: {text}")),
+    }
+}
+
+pub(crate) fn r_type(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+pub(crate) fn i_type(imm: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm & 0xFFF) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+pub(crate) fn s_type(imm: u32, rs2: u32, rs1: u32, funct3: u32, opcode: u32) -> u32 {
+    (((imm >> 5) & 0x7F) << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | ((imm & 0x1F) << 7) | opcode
+}
+
+pub(crate) fn b_type(imm: u32, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    (((imm >> 12) & 0x1) << 31)
+        | (((imm >> 5) & 0x3F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | (((imm >> 1) & 0xF) << 8)
+        | (((imm >> 11) & 0x1) << 7)
+        | 0x63
+}
+
+pub(crate) fn u_type(imm: u32, rd: u32, opcode: u32) -> u32 {
+    (imm << 12) | (rd << 7) | opcode
+}
+
+pub(crate) fn j_type(imm: u32, rd: u32) -> u32 {
+    (((imm >> 20) & 0x1) << 31)
+        | (((imm >> 1) & 0x3FF) << 21)
+        | (((imm >> 11) & 0x1) << 20)
+        | (((imm >> 12) & 0xFF) << 12)
+        | (rd << 7)
+        | 0x6F
+}
+
+/// upper and lower parts of a value, the lower 12 bits being sign extended by addi/loads/stores
+fn split_hi_lo(value: u32) -> (u32, u32) {
+    let hi = value.wrapping_add(0x800) >> 12;
+    (hi & 0xF_FFFF, value.wrapping_sub(hi << 12) & 0xFFF)
+}
+
+/// li as a single addi when the value fits in 12 bits, otherwise as lui (+ addi)
+pub(crate) fn load_immediate(rd: u32, value: u32) -> Vec<u32> {
+    if check_signed(value as i32 as i64, 12).is_some() {
+        return vec![i_type(value, 0, 0x0, rd, 0x13)];
+    }
+    let (hi, lo) = split_hi_lo(value);
+    let mut words = vec![u_type(hi, rd, 0x37)];
+    if lo != 0 {
+        words.push(i_type(lo, rd, 0x0, rd, 0x13));
+    }
+    words
+}
+
+const BRANCHES: [(&str, u32); 6] = [("beq", 0x0), ("bne", 0x1), ("blt", 0x4), ("bge", 0x5), ("bltu", 0x6), ("bgeu", 0x7)];
+const LOADS: [(&str, u32); 5] = [("lb", 0x0), ("lh", 0x1), ("lw", 0x2), ("lbu", 0x4), ("lhu", 0x5)];
+const STORES: [(&str, u32); 3] = [("sb", 0x0), ("sh", 0x1), ("sw", 0x2)];
+const ALU_IMM: [(&str, u32); 6] = [("addi", 0x0), ("slti", 0x2), ("sltiu", 0x3), ("xori", 0x4), ("ori", 0x6), ("andi", 0x7)];
+const ALU: [(&str, u32, u32); 18] = [
+    ("add", 0x00, 0x0),
+    ("sub", 0x20, 0x0),
+    ("sll", 0x00, 0x1),
+    ("slt", 0x00, 0x2),
+    ("sltu", 0x00, 0x3),
+    ("xor", 0x00, 0x4),
+    ("srl", 0x00, 0x5),
+    ("sra", 0x20, 0x5),
+    ("or", 0x00, 0x6),
+    ("and", 0x00, 0x7),
+    ("mul", 0x01, 0x0),
+    ("mulh", 0x01, 0x1),
+    ("mulhsu", 0x01, 0x2),
+    ("mulhu", 0x01, 0x3),
+    ("div", 0x01, 0x4),
+    ("divu", 0x01, 0x5),
+    ("rem", 0x01, 0x6),
+    ("remu", 0x01, 0x7),
+];
+const AMOS: [(&str, u32); 11] = [
+    ("lr", 0x02),
+    ("sc", 0x03),
+    ("amoswap", 0x01),
+    ("amoadd", 0x00),
+    ("amoxor", 0x04),
+    ("amoand", 0x0C),
+    ("amoor", 0x08),
+    ("amomin", 0x10),
+    ("amomax", 0x14),
+    ("amominu", 0x18),
+    ("amomaxu", 0x1C),
+];
+const CSR_OPS: [(&str, u32); 6] =
+    [("csrrw", 0x1), ("csrrs", 0x2), ("csrrc", 0x3), ("csrrwi", 0x5), ("csrrsi", 0x6), ("csrrci", 0x7)];
+
+fn find<T: Copy>(table: &[(&str, T)], mnemonic: &str) -> Option<T> {
+    table.iter().find(|(name, _)| *name == mnemonic).map(|&(_, value)| value)
+}
+
+impl Statement<'_> {
+    /// number of words the statement assembles to, known before the labels are
+    fn size(&self) -> Result<usize, String> {
+        Ok(match self.mnemonic.as_str() {
+            "la" => 2,
+            "li" => {
+                self.expect(2)?;
+                load_immediate(0, parse_word(self.operands[1])?).len()
+            }
+            _ => 1,
+        })
+    }
+
+    fn expect(&self, count: usize) -> Result<(), String> {
+        if self.operands.len() != count {
+            return Err(format!("{} expects {count} operands, found {}", self.mnemonic, self.operands.len()));
+        }
+        Ok(())
+    }
+
+    fn reg(&self, index: usize) -> Result<u32, String> {
+        parse_reg(self.operands[index])
+    }
+
+    /// offset from this statement to a label (or to a numeric address), checked against the reach of the instruction
+    fn offset(&self, target: &str, labels: &HashMap<String, Address>, bits: u32) -> Result<u32, String> {
+        let target = match labels.get(target) {
+            Some(&address) => address,
+            None => parse_number(target).ok_or(format!("undefined label: {target}"))? as Address,
+        };
+        let offset = target.wrapping_sub(self.address) as i64 as i32 as i64;
+        if offset % 2 != 0 {
+            return Err(format!("target 0x{target:X} is not aligned"));
+        }
+        check_signed(offset, bits).ok_or(format!("target 0x{target:X} is out of reach"))
+    }
+
+    fn encode(&self, labels: &HashMap<String, Address>) -> Result<Vec<u32>, String> {
+        let mnemonic = self.mnemonic.as_str();
+        let ops = &self.operands;
+        let word = match mnemonic {
+            ".word" => {
+                self.expect(1)?;
+                match labels.get(ops[0]) {
+                    Some(&address) => address as u32,
+                    None => parse_word(ops[0])?,
+                }
+            }
+            "nop" => {
+                self.expect(0)?;
+                i_type(0, 0, 0x0, 0, 0x13)
+            }
+            "li" => {
+                self.expect(2)?;
+                return Ok(load_immediate(self.reg(0)?, parse_word(ops[1])?));
+            }
+            "la" => {
+                self.expect(2)?;
+                let target = *labels.get(ops[1]).ok_or(format!("undefined label: {}", ops[1]))?;
+                let rd = self.reg(0)?;
+                let (hi, lo) = split_hi_lo(target.wrapping_sub(self.address) as u32);
+                return Ok(vec![u_type(hi, rd, 0x17), i_type(lo, rd, 0x0, rd, 0x13)]);
+            }
+            "mv" => {
+                self.expect(2)?;
+                i_type(0, self.reg(1)?, 0x0, self.reg(0)?, 0x13)
+            }
+            "not" => {
+                self.expect(2)?;
+                i_type(0xFFF, self.reg(1)?, 0x4, self.reg(0)?, 0x13)
+            }
+            "neg" => {
+                self.expect(2)?;
+                r_type(0x20, self.reg(1)?, 0, 0x0, self.reg(0)?, 0x33)
+            }
+            "seqz" => {
+                self.expect(2)?;
+                i_type(1, self.reg(1)?, 0x3, self.reg(0)?, 0x13)
+            }
+            "snez" => {
+                self.expect(2)?;
+                r_type(0x00, self.reg(1)?, 0, 0x3, self.reg(0)?, 0x33)
+            }
+            "lui" | "auipc" => {
+                self.expect(2)?;
+                let imm = parse_number(ops[1])
+                    .filter(|imm| (0..0x10_0000).contains(imm))
+                    .ok_or(format!("invalid upper immediate: {}", ops[1]))?;
+                u_type(imm as u32, self.reg(0)?, if mnemonic == "lui" { 0x37 } else { 0x17 })
+            }
+            "jal" | "j" | "call" => {
+                let (rd, target) = match (mnemonic, ops.len()) {
+                    ("jal", 2) => (self.reg(0)?, ops[1]),
+                    ("jal", 1) | ("call", 1) => (1, ops[0]),
+                    ("j", 1) => (0, ops[0]),
+                    _ => return Err(format!("wrong number of operands for {mnemonic}")),
+                };
+                j_type(self.offset(target, labels, 21)?, rd)
+            }
+            "jalr" => match ops.len() {
+                1 => i_type(0, self.reg(0)?, 0x0, 1, 0x67),
+                2 => {
+                    let (offset, rs1) = parse_address(ops[1])?;
+                    i_type(offset, rs1, 0x0, self.reg(0)?, 0x67)
+                }
+                3 => i_type(parse_imm(ops[2], 12)?, self.reg(1)?, 0x0, self.reg(0)?, 0x67),
+                _ => return Err("wrong number of operands for jalr".to_string()),
+            },
+            "jr" => {
+                self.expect(1)?;
+                i_type(0, self.reg(0)?, 0x0, 0, 0x67)
+            }
+            "ret" => {
+                self.expect(0)?;
+                i_type(0, 1, 0x0, 0, 0x67)
+            }
+            "beqz" | "bnez" | "bltz" | "bgez" | "blez" | "bgtz" => {
+                self.expect(2)?;
+                let rs = self.reg(0)?;
+                let offset = self.offset(ops[1], labels, 13)?;
+                match mnemonic {
+                    "beqz" => b_type(offset, 0, rs, 0x0),
+                    "bnez" => b_type(offset, 0, rs, 0x1),
+                    "bltz" => b_type(offset, 0, rs, 0x4),
+                    "bgez" => b_type(offset, 0, rs, 0x5),
+                    // zero < rs and zero >= rs
+                    "bgtz" => b_type(offset, rs, 0, 0x4),
+                    _ => b_type(offset, rs, 0, 0x5),
+                }
+            }
+            "bgt" | "ble" | "bgtu" | "bleu" => {
+                // same as the branches with swapped operands
+                self.expect(3)?;
+                let funct3 = match mnemonic {
+                    "bgt" => 0x4,
+                    "ble" => 0x5,
+                    "bgtu" => 0x6,
+                    _ => 0x7,
+                };
+                b_type(self.offset(ops[2], labels, 13)?, self.reg(0)?, self.reg(1)?, funct3)
+            }
+            _ if find(&BRANCHES, mnemonic).is_some() => {
+                self.expect(3)?;
+                let funct3 = find(&BRANCHES, mnemonic).unwrap();
+                b_type(self.offset(ops[2], labels, 13)?, self.reg(1)?, self.reg(0)?, funct3)
+            }
+            _ if find(&LOADS, mnemonic).is_some() => {
+                self.expect(2)?;
+                let (offset, rs1) = parse_address(ops[1])?;
+                i_type(offset, rs1, find(&LOADS, mnemonic).unwrap(), self.reg(0)?, 0x03)
+            }
+            _ if find(&STORES, mnemonic).is_some() => {
+                self.expect(2)?;
+                let (offset, rs1) = parse_address(ops[1])?;
+                s_type(offset, self.reg(0)?, rs1, find(&STORES, mnemonic).unwrap(), 0x23)
+            }
+            _ if find(&ALU_IMM, mnemonic).is_some() => {
+                self.expect(3)?;
+                i_type(parse_imm(ops[2], 12)?, self.reg(1)?, find(&ALU_IMM, mnemonic).unwrap(), self.reg(0)?, 0x13)
+            }
+            "slli" | "srli" | "srai" => {
+                self.expect(3)?;
+                let shamt = parse_number(ops[2])
+                    .filter(|shamt| (0..32).contains(shamt))
+                    .ok_or(format!("invalid shift amount: {}", ops[2]))? as u32;
+                let (funct7, funct3) = match mnemonic {
+                    "slli" => (0x00, 0x1),
+                    "srli" => (0x00, 0x5),
+                    _ => (0x20, 0x5),
+                };
+                r_type(funct7, shamt, self.reg(1)?, funct3, self.reg(0)?, 0x13)
+            }
+            _ if ALU.iter().any(|(name, _, _)| *name == mnemonic) => {
+                self.expect(3)?;
+                let &(_, funct7, funct3) = ALU.iter().find(|(name, _, _)| *name == mnemonic).unwrap();
+                r_type(funct7, self.reg(2)?, self.reg(1)?, funct3, self.reg(0)?, 0x33)
+            }
+            "fence" => i_type(0x0FF, 0, 0x0, 0, 0x0F),
+            "fence.i" => i_type(0, 0, 0x1, 0, 0x0F),
+            "ecall" => i_type(0x000, 0, 0x0, 0, 0x73),
+            "ebreak" => i_type(0x001, 0, 0x0, 0, 0x73),
+            "sret" => i_type(0x102, 0, 0x0, 0, 0x73),
+            "mret" => i_type(0x302, 0, 0x0, 0, 0x73),
+            "wfi" => i_type(0x105, 0, 0x0, 0, 0x73),
+            "csrr" => {
+                self.expect(2)?;
+                i_type(parse_csr(ops[1])?, 0, 0x2, self.reg(0)?, 0x73)
+            }
+            "csrw" | "csrs" | "csrc" => {
+                self.expect(2)?;
+                let funct3 = find(&CSR_OPS, &format!("csrr{}", &mnemonic[3..])).unwrap();
+                i_type(parse_csr(ops[0])?, self.reg(1)?, funct3, 0, 0x73)
+            }
+            _ if find(&CSR_OPS, mnemonic).is_some() => {
+                self.expect(3)?;
+                let funct3 = find(&CSR_OPS, mnemonic).unwrap();
+                let source = if funct3 >= 0x5 {
+                    parse_number(ops[2])
+                        .filter(|uimm| (0..32).contains(uimm))
+                        .ok_or(format!("invalid CSR immediate: {}", ops[2]))? as u32
+                } else {
+                    self.reg(2)?
+                };
+                i_type(parse_csr(ops[1])?, source, funct3, self.reg(0)?, 0x73)
+            }
+            _ => return self.encode_atomic(),
+        };
+        Ok(vec![word])
+    }
+
+    /// lr.w rd, (rs1), sc.w rd, rs2, (rs1) and amo<op>.w rd, rs2, (rs1), with the optional .aq, .rl or .aqrl ordering
+    fn encode_atomic(&self) -> Result<Vec<u32>, String> {
+        let unknown = || format!("unknown instruction: {}", self.mnemonic);
+        let mut parts = self.mnemonic.split('.');
+        let funct5 = find(&AMOS, parts.next().unwrap()).ok_or_else(unknown)?;
+        if parts.next() != Some("w") {
+            return Err(unknown());
+        }
+        let ordering = match parts.next() {
+            None => 0x0,
+            Some("rl") => 0x1,
+            Some("aq") => 0x2,
+            Some("aqrl") => 0x3,
+            Some(_) => return Err(unknown()),
+        };
+        if parts.next().is_some() {
+            return Err(unknown());
+        }
+        let (rs2, address) = if funct5 == 0x02 {
+            self.expect(2)?;
+            (0, self.operands[1])
+        } else {
+            self.expect(3)?;
+            (self.reg(1)?, self.operands[2])
+        };
+        let (offset, rs1) = parse_address(address)?;
+        if offset != 0 {
+            return Err("atomic instructions take no offset".to_string());
+        }
+        Ok(vec![r_type((funct5 << 2) | ordering, rs2, rs1, 0x2, self.reg(0)?, 0x2F)])
+    }
+}
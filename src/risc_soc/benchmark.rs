@@ -0,0 +1,77 @@
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::run_control::RunControl;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// host speed of a run, to compare the loops of the simulator (and their changes) on the same program
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulationSpeed {
+    pub cycles: u64,
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl SimulationSpeed {
+    pub fn cycles_per_second(&self) -> f64 {
+        self.cycles as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// millions of retired instructions per second of host time
+    pub fn mips(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64() / 1e6
+    }
+}
+
+impl Display for SimulationSpeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} cycles and {} instructions in {:?}: {:.0} cycles/s, {:.3} MIPS",
+            self.cycles,
+            self.instructions,
+            self.elapsed,
+            self.cycles_per_second(),
+            self.mips()
+        )
+    }
+}
+
+/// clock cycles and instructions of a run, counted as they end and retire since a reset may restart the cycle counters
+#[derive(Default)]
+struct RunCounter {
+    cycles: AtomicU64,
+    instructions: AtomicU64,
+}
+
+impl SimulationObserver for RunCounter {
+    fn on_instruction_retired(&self, _core: &RiscCore, _pc: RiscWord, _instruction: u32) {
+        self.instructions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn on_cycle(&self, _core: &RiscCore, _clock_cycle: u64) {
+        self.cycles.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// run the program loaded in the core for the given number of clock cycles, on the stage threads or on the calling thread
+/// the run stops earlier if the program halts, only the cycles actually run are counted
+pub fn measure(core: &mut RiscCore, cycles: u64, threaded: bool) -> SimulationSpeed {
+    let counter = Arc::new(RunCounter::default());
+    core.add_observer(counter.clone());
+    let start = Instant::now();
+    if threaded {
+        core.run_with(RunControl::cycles(cycles));
+    } else {
+        core.run_sequential_with(RunControl::cycles(cycles));
+    }
+    let elapsed = start.elapsed();
+    core.observers.pop();
+    SimulationSpeed {
+        cycles: counter.cycles.load(Ordering::Relaxed),
+        instructions: counter.instructions.load(Ordering::Relaxed),
+        elapsed,
+    }
+}
@@ -25,8 +25,12 @@ pub trait Cache: MemoryDevice {
 
     /// for both load and store functions we pass the address, which is the responsability of the underlaying implementation to handle how it uses it
     fn load_data(&self, address: Address) -> CacheResponse;
-    fn store_data(&mut self, address: Address, data: Vec<u8>) -> CacheResponse;
+    fn store_data(&mut self, address: Address, data: &[u8]) -> CacheResponse;
 
     /// function to validate address (ex. tag) report a cache hit or miss, and provide the index and tag of the given address
     fn translate_address(&self, address: Address) -> CacheResponse;
+
+    /// drop all the lines held by the cache so that following requests are served again by the next memory level
+    /// used by FENCE.I on the instruction cache; caches acting as the actual memory have nothing to drop
+    fn invalidate(&mut self) {}
 }
@@ -0,0 +1,100 @@
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::sim_error::SimError;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+
+const OP_JAL: u32 = 0b1101111;
+const OP_JALR: u32 = 0b1100111;
+/// ra and t0, the link registers of the calling convention
+const LINK_REGISTERS: [u32; 2] = [1, 5];
+
+/// Call sites of the functions the guest is currently in, from the outermost one, rebuilt from the instructions that retire:
+/// a call (JAL/JALR linking ra or t0) pushes its address and a return (JALR to ra or t0) pops it, as in `Profiler`
+/// tail calls and longjmps are not seen, so the frames are a best effort for diagnostics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallStack {
+    calls: Vec<RiscWord>,
+}
+
+impl CallStack {
+    pub fn retire(&mut self, pc: RiscWord, instruction: u32) {
+        let opcode = instruction & 0x7F;
+        let rd = (instruction >> 7) & 0x1F;
+        let rs1 = (instruction >> 15) & 0x1F;
+        if (opcode == OP_JAL || opcode == OP_JALR) && LINK_REGISTERS.contains(&rd) {
+            self.calls.push(pc);
+        } else if opcode == OP_JALR && rd == 0 && LINK_REGISTERS.contains(&rs1) {
+            self.calls.pop();
+        }
+    }
+
+    /// address of the call instructions, the innermost one last
+    pub fn calls(&self) -> &[RiscWord] {
+        &self.calls
+    }
+
+    pub fn clear(&mut self) {
+        self.calls.clear();
+    }
+
+    /// `pc`, then the call sites from the innermost one, in the `0x80000010 function+0x8` notation of the traces
+    /// without any call seen (ex. the stack was followed from the middle of a run) the caller is guessed from ra,
+    /// which holds the return address until the function calls another one
+    pub fn backtrace(&self, core: &RiscCore, pc: RiscWord) -> Vec<String> {
+        let ra = core.read_reg_by_name("ra");
+        let guessed = (self.calls.is_empty() && ra >= 4 && core.lookup_symbol(ra as Address - 4).is_some()).then(|| ra - 4);
+        std::iter::once(pc)
+            .chain(self.calls.iter().rev().copied())
+            .chain(guessed)
+            .map(|address| match core.lookup_symbol(address as Address) {
+                Some(symbol) => format!("0x{address:08X} {symbol}"),
+                None => format!("0x{address:08X}"),
+            })
+            .collect()
+    }
+}
+
+/// Backtrace of the program logged when an exception or an error of the model stops it, registered with `RiscCore::enable_backtraces`
+#[derive(Debug, Default)]
+pub struct Backtraces {
+    call_stack: Mutex<CallStack>,
+    last: Mutex<Option<Vec<String>>>,
+}
+
+impl Backtraces {
+    /// backtrace at the last exception or error, from the instruction raising it
+    pub fn last(&self) -> Option<Vec<String>> {
+        self.last.lock().unwrap().clone()
+    }
+
+    /// backtrace from `pc` with the frames of the program as they are now
+    pub fn backtrace(&self, core: &RiscCore, pc: RiscWord) -> Vec<String> {
+        self.call_stack.lock().unwrap().backtrace(core, pc)
+    }
+
+    fn record(&self, core: &RiscCore, pc: RiscWord, reason: &str) {
+        let backtrace = self.backtrace(core, pc);
+        let frames: Vec<String> = backtrace.iter().enumerate().map(|(index, frame)| format!("  #{index} {frame}")).collect();
+        tracing::error!("Backtrace of the program at {reason}:\n{}", frames.join("\n"));
+        *self.last.lock().unwrap() = Some(backtrace);
+    }
+}
+
+impl SimulationObserver for Backtraces {
+    fn on_instruction_retired(&self, _core: &RiscCore, pc: RiscWord, instruction: u32) {
+        self.call_stack.lock().unwrap().retire(pc, instruction);
+    }
+
+    fn on_trap(&self, core: &RiscCore, trap: &Trap) {
+        self.record(core, trap.pc as RiscWord, &trap.to_string());
+    }
+
+    /// the errors not tied to an instruction are reported from the one accessing memory
+    fn on_sim_error(&self, core: &RiscCore, error: &SimError) {
+        let pc = error.pc.unwrap_or(core.data_access_pc.load(Ordering::SeqCst) as RiscWord);
+        self.record(core, pc, &error.kind.to_string());
+    }
+}
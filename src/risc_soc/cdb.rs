@@ -1,9 +1,7 @@
 use ahash::AHashMap;
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
 
-use crate::risc_soc::wire::Wire;
-
-/// Logic for Common Data Bus shared by Pipeline stages to forward data directly between them
-/// It should simulate the behaviour of a wire assignment in Verilog
+use crate::risc_soc::wire::{Signal, Wire};
 
 /*
 
@@ -30,23 +28,73 @@ LOGIC | | LOGIC | | LOGIC | | LOGIC
 
 
 
-type DataLanes = Vec<Wire>;
+type DataLanes = Vec<Signal>;
 type StageIndex = usize;
+/// Logic for Common Data Bus shared by Pipeline stages to forward data directly between them
+/// It should simulate the behaviour of a wire assignment in Verilog
 pub struct CommonDataBus {
-   pub bus: AHashMap<StageIndex, DataLanes>
+   pub bus: AHashMap<StageIndex, DataLanes>,
+   /// shared by all the wires of the bus
+   aborted: Arc<AtomicBool>,
 }
 
 impl CommonDataBus {
     pub fn new(num_stages: usize, critical_path: Option<u128>, debug: bool) -> Self {
         let mut bus = AHashMap::new();
+        let aborted = Arc::new(AtomicBool::new(false));
         for i in 0..num_stages {
             let mut data_lane = DataLanes::with_capacity(num_stages);
             for l in 0..num_stages {
-                data_lane.push(Wire::new(critical_path, debug));
+                // undeclared wires are named after the stages they connect
+                data_lane.push(Signal::new(format!("wire_{i}_{l}"), Wire::new(critical_path, debug).with_abort_flag(aborted.clone())));
             }
             bus.insert(i, data_lane);
         }
-        Self { bus }
+        Self { bus, aborted }
+    }
+
+    /// release every stage blocked on a wire, their reads return meaningless data from then on
+    /// used to stop a deadlocked simulation, which would otherwise never reach the end of its clock cycle
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        for data_lane in self.bus.values() {
+            for wire in data_lane {
+                wire.wake_up();
+            }
+        }
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// wires wait for their value again, at the start of a new run
+    pub fn resume(&self) {
+        self.aborted.store(false, Ordering::SeqCst);
+    }
+
+    /// give a name and a width (in bits) to the wire going from a stage to an earlier one
+    pub fn declare(&mut self, from: StageIndex, to: StageIndex, name: &str, width: usize) {
+        assert!(
+            self.find(name).is_none_or(|wire| wire == (from, to)),
+            "Signal {name} is already declared on another wire"
+        );
+        let data_lane = self.bus.get_mut(&from).unwrap();
+        assert!(to < data_lane.len());
+        data_lane[to].declare(name, width);
+    }
+
+    /// stages connected by the signal with the given name
+    pub fn find(&self, name: &str) -> Option<(StageIndex, StageIndex)> {
+        self.bus.iter().find_map(|(from, data_lane)| {
+            data_lane.iter().position(|signal| signal.name == name).map(|to| (*from, to))
+        })
+    }
+
+    pub fn signal(&self, from: StageIndex, to: StageIndex) -> &Signal {
+        let data_lane = self.bus.get(&from).unwrap();
+        assert!(to < data_lane.len());
+        &data_lane[to]
     }
 
     pub fn assign(&self, from: StageIndex, to: StageIndex, data: super::pipeline_stage::PipelineData) {
@@ -63,6 +111,13 @@ impl CommonDataBus {
         wire.read() 
     }
 
+    /// value currently assigned to a wire, None if it was not assigned in this clock cycle
+    pub fn peek(&self, from: StageIndex, to: StageIndex) -> Option<super::pipeline_stage::PipelineData> {
+        let data_lane = self.bus.get(&from).unwrap();
+        assert!(to < data_lane.len());
+        data_lane[to].peek()
+    }
+
     pub fn clear(&self, stage: StageIndex) {
         let data_lanes = self.bus.get(&stage).unwrap();
         for wire in data_lanes {
@@ -0,0 +1,195 @@
+use crate::risc_soc::disasm::disassemble;
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::pipeline_stage::ClockCycle;
+use crate::risc_soc::risc_soc::RiscCore;
+use std::io::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+/// process of the trace holding one thread per pipeline stage
+const PIPELINE_PID: u32 = 0;
+/// process of the trace holding one thread per device reached by the data accesses, and the caches
+const DEVICES_PID: u32 = 1;
+
+/// what a stage did during a clock cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StageActivity {
+    /// processed an instruction and wrote its output register
+    Busy(u32),
+    /// kept its output register because of a stall
+    Stalled,
+    /// its output register was flushed
+    Flushed,
+}
+
+/// cycles [start, end) in which a stage kept the same activity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageInterval {
+    pub stage: usize,
+    pub activity: StageActivity,
+    pub start: ClockCycle,
+    pub end: ClockCycle,
+}
+
+/// access of the data port to a device, or a cache miss
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceEvent {
+    pub cycle: ClockCycle,
+    /// name of the device, or of the cache that missed
+    pub device: String,
+    pub name: String,
+    pub address: Address,
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    intervals: Vec<StageInterval>,
+    /// interval each stage is currently in, closed once its activity changes
+    open: Vec<Option<StageInterval>>,
+    devices: Vec<DeviceEvent>,
+    traps: Vec<(ClockCycle, Trap)>,
+}
+
+/// Busy, stall and flush intervals of the stages and the activity of the devices, written in the Chrome trace event format
+/// to be opened in Perfetto (ui.perfetto.dev) or chrome://tracing, registered with `RiscCore::enable_chrome_trace`
+/// one microsecond of the trace is one clock cycle, the stages are threads of a `pipeline` process and the devices of a `devices` process
+#[derive(Debug)]
+pub struct ChromeTrace {
+    stage_names: Vec<String>,
+    state: Mutex<TraceState>,
+}
+
+impl ChromeTrace {
+    pub fn new(stage_names: Vec<String>) -> Self {
+        let open = vec![None; stage_names.len()];
+        Self { stage_names, state: Mutex::new(TraceState { open, ..Default::default() }) }
+    }
+
+    /// called by the run loops for every stage at every clock cycle, idle cycles of a stage holding no instruction are not recorded
+    pub fn sample_stage(&self, clock_cycle: ClockCycle, stage: usize, instruction: u32, reset: bool, enabled: bool) {
+        let activity = match (reset, enabled) {
+            (true, _) => Some(StageActivity::Flushed),
+            (false, false) => Some(StageActivity::Stalled),
+            (false, true) if instruction != 0x0 => Some(StageActivity::Busy(instruction)),
+            (false, true) => None,
+        };
+        let mut state = self.state.lock().unwrap();
+        let open = &mut state.open[stage];
+        match (open.as_mut(), activity) {
+            (Some(interval), Some(activity)) if interval.activity == activity && interval.end == clock_cycle => {
+                interval.end += 1;
+                return;
+            }
+            _ => {}
+        }
+        let closed = std::mem::replace(open, activity.map(|activity| StageInterval { stage, activity, start: clock_cycle, end: clock_cycle + 1 }));
+        state.intervals.extend(closed);
+    }
+
+    /// intervals of all the stages, ordered by stage and start cycle
+    pub fn intervals(&self) -> Vec<StageInterval> {
+        let state = self.state.lock().unwrap();
+        let mut intervals: Vec<_> = state.intervals.iter().chain(state.open.iter().flatten()).copied().collect();
+        intervals.sort_by_key(|interval| (interval.stage, interval.start));
+        intervals
+    }
+
+    pub fn device_events(&self) -> Vec<DeviceEvent> {
+        self.state.lock().unwrap().devices.clone()
+    }
+
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = TraceState { open: vec![None; self.stage_names.len()], ..Default::default() };
+    }
+
+    /// the trace as a JSON object with its `traceEvents`
+    pub fn json(&self) -> json::JsonValue {
+        let mut events = json::JsonValue::new_array();
+        let metadata = |pid: u32, tid: usize, kind: &str, name: &str| {
+            json::object! { name: kind, ph: "M", pid: pid, tid: tid, args: json::object! { name: name } }
+        };
+        events.push(metadata(PIPELINE_PID, 0, "process_name", "pipeline")).unwrap();
+        events.push(metadata(DEVICES_PID, 0, "process_name", "devices")).unwrap();
+        for (index, name) in self.stage_names.iter().enumerate() {
+            events.push(metadata(PIPELINE_PID, index, "thread_name", name)).unwrap();
+            events.push(json::object! { name: "thread_sort_index", ph: "M", pid: PIPELINE_PID, tid: index, args: json::object! { sort_index: index } }).unwrap();
+        }
+        for interval in self.intervals() {
+            let (name, category, args) = match interval.activity {
+                StageActivity::Busy(instruction) => {
+                    (disassemble(instruction), "busy", json::object! { instruction: format!("0x{instruction:08x}") })
+                }
+                StageActivity::Stalled => ("stall".to_string(), "stall", json::object! {}),
+                StageActivity::Flushed => ("flush".to_string(), "flush", json::object! {}),
+            };
+            let event = json::object! {
+                name: name, cat: category, ph: "X", ts: interval.start, dur: interval.end - interval.start,
+                pid: PIPELINE_PID, tid: interval.stage, args: args,
+            };
+            events.push(event).unwrap();
+        }
+
+        let state = self.state.lock().unwrap();
+        let mut devices: Vec<&str> = vec![];
+        for event in &state.devices {
+            if !devices.contains(&event.device.as_str()) {
+                devices.push(&event.device);
+                events.push(metadata(DEVICES_PID, devices.len() - 1, "thread_name", &event.device)).unwrap();
+            }
+            let tid = devices.iter().position(|device| *device == event.device).unwrap();
+            let event = json::object! {
+                name: event.name.as_str(), cat: "device", ph: "X", ts: event.cycle, dur: 1, pid: DEVICES_PID, tid: tid,
+                args: json::object! { address: format!("0x{:08x}", event.address) },
+            };
+            events.push(event).unwrap();
+        }
+        for (cycle, trap) in &state.traps {
+            let event = json::object! { name: trap.to_string(), cat: "trap", ph: "i", s: "g", ts: *cycle, pid: PIPELINE_PID, tid: 0 };
+            events.push(event).unwrap();
+        }
+        json::object! { traceEvents: events, displayTimeUnit: "ns" }
+    }
+
+    pub fn write_json(&self, mut output: impl Write) -> std::io::Result<()> {
+        self.json().write(&mut output)
+    }
+
+    fn record_device(&self, core: &RiscCore, device: String, name: &str, address: Address) {
+        let cycle = core.clock_cycle.load(Ordering::SeqCst);
+        self.state.lock().unwrap().devices.push(DeviceEvent { cycle, device, name: name.to_string(), address });
+    }
+}
+
+impl SimulationObserver for ChromeTrace {
+    fn on_trap(&self, core: &RiscCore, trap: &Trap) {
+        let cycle = core.clock_cycle.load(Ordering::SeqCst);
+        self.state.lock().unwrap().traps.push((cycle, *trap));
+    }
+
+    fn on_cache_miss(&self, core: &RiscCore, address: Address, instruction: bool) {
+        let cache = if instruction { "fetch buffer" } else { "data cache" };
+        self.record_device(core, cache.to_string(), "miss", address);
+    }
+
+    /// the accesses served by the L1 memories of the core are not device activity
+    fn on_mem_access(&self, core: &RiscCore, request: &MemoryRequest, _response: &MemoryResponse) {
+        if core.in_l1(request.data_address) {
+            return;
+        }
+        let Some(device) = core.mmu.read().unwrap().device_at(request.data_address) else {
+            return;
+        };
+        let device = match device.instance {
+            0 => format!("{:?}", device.memory_type),
+            instance => format!("{:?}{instance}", device.memory_type),
+        };
+        let name = match request.request_type {
+            MemoryRequestType::READ => "read",
+            MemoryRequestType::WRITE => "write",
+        };
+        self.record_device(core, device, name, request.data_address);
+    }
+}
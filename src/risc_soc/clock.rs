@@ -0,0 +1,106 @@
+use std::time::Instant;
+
+/// number of flip-flops of a synchronizer, the usual two-flop design
+pub const DEFAULT_SYNCHRONIZER_STAGES: u64 = 2;
+
+/// clock derived from the core clock with a rational ratio, like the output of a PLL or of a clock divider
+/// the clock of the domain runs at `multiplier / divisor` times the frequency of the core clock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClockDomain {
+    pub name: String,
+    multiplier: u64,
+    divisor: u64,
+}
+
+impl ClockDomain {
+    pub fn new(name: &str, multiplier: u64, divisor: u64) -> Self {
+        assert!(multiplier > 0 && divisor > 0);
+        Self { name: name.to_string(), multiplier, divisor }
+    }
+
+    /// domain of the core itself, devices in it tick once per core cycle and are accessed without crossing
+    pub fn core() -> Self {
+        Self::new("core", 1, 1)
+    }
+
+    /// slower clock obtained by dividing the core clock (ex. a peripheral bus at a quarter of the core frequency)
+    pub fn divided(name: &str, divisor: u64) -> Self {
+        Self::new(name, 1, divisor)
+    }
+
+    pub fn is_core(&self) -> bool {
+        self.multiplier == self.divisor
+    }
+
+    /// rising edges of the domain clock since reset, after the given number of core cycles
+    pub fn edges_at(&self, core_cycles: u64) -> u64 {
+        (core_cycles as u128 * self.multiplier as u128 / self.divisor as u128) as u64
+    }
+
+    /// core cycles to wait, from the given core cycle, until the domain clock had `edges` more rising edges
+    pub fn core_cycles_for(&self, core_cycles: u64, edges: u64) -> u64 {
+        let target = (self.edges_at(core_cycles) + edges) as u128;
+        let end = (target * self.divisor as u128).div_ceil(self.multiplier as u128) as u64;
+        end.saturating_sub(core_cycles)
+    }
+
+    /// frequency of the domain for a core clock period given in nanoseconds
+    pub fn frequency_hz(&self, core_clock_period: u128) -> f64 {
+        1e9 / core_clock_period as f64 * self.multiplier as f64 / self.divisor as f64
+    }
+}
+
+/// clock following the wall-clock time of the host instead of the core clock (ex. a timer during an interactive session)
+/// `scale` slows it down or speeds it up, so firmware waiting on it behaves like on a real MCU whatever the simulation speed
+#[derive(Debug, Clone)]
+pub struct HostClock {
+    /// rising edges per second of host time, after scaling
+    rate: f64,
+    start: Instant,
+}
+
+impl HostClock {
+    pub fn new(frequency_hz: u64, scale: f64) -> Self {
+        assert!(frequency_hz > 0 && scale > 0.0);
+        Self { rate: frequency_hz as f64 * scale, start: Instant::now() }
+    }
+
+    /// rising edges since the clock was created
+    pub fn edges(&self) -> u64 {
+        (self.start.elapsed().as_secs_f64() * self.rate) as u64
+    }
+}
+
+/// flip-flops clocked by the destination domain that a signal goes through when it crosses from another domain
+/// a value driven by the source is only seen by the destination after as many destination edges as there are stages
+#[derive(Debug, Clone)]
+pub struct Synchronizer<T> {
+    flops: Vec<T>,
+}
+
+impl<T: Copy + Default> Synchronizer<T> {
+    pub fn new(stages: usize) -> Self {
+        assert!(stages > 0);
+        Self { flops: vec![T::default(); stages] }
+    }
+
+    /// rising edge of the destination clock, sampling the value driven by the source domain
+    pub fn tick(&mut self, input: T) {
+        self.flops.rotate_right(1);
+        self.flops[0] = input;
+    }
+
+    /// value seen by the destination domain
+    pub fn output(&self) -> T {
+        *self.flops.last().unwrap()
+    }
+}
+
+/// cost of the accesses of the core to devices in other clock domains
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClockCrossingStats {
+    /// accesses to a device outside of the core domain
+    pub crossings: u64,
+    /// core cycles spent in synchronizers: the request waits for the device clock and the response for the core clock
+    pub synchronizer_cycles: u64,
+}
@@ -0,0 +1,253 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryManagementUnit, MemoryRequest, MemoryRequestType, MemoryResponse,
+    MemoryResponseType,
+};
+use std::sync::{Arc, Mutex};
+
+/// state of a cache line in the MESI protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MesiState {
+    /// the only copy, newer than memory
+    Modified,
+    /// the only copy, same as memory
+    Exclusive,
+    /// one of several copies, same as memory
+    Shared,
+    Invalid,
+}
+
+/// counters of a single cache connected to the bus
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoherenceStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// misses on lines this cache held before another cache invalidated them
+    pub coherence_misses: u64,
+    /// lines of this cache invalidated by writes of other caches
+    pub invalidations: u64,
+    /// modified lines written back to memory, on eviction or when snooped by another cache
+    pub writebacks: u64,
+}
+
+#[derive(Debug, Clone)]
+struct CacheLine {
+    /// address of the first byte of the line, kept after an invalidation to tell coherence misses apart
+    line_address: Address,
+    state: MesiState,
+    data: Vec<u8>,
+}
+
+const NO_LINE: Address = Address::MAX;
+
+/// direct mapped caches of all the harts and the bus they snoop
+/// the whole bus is locked for a transaction, so transactions of different caches never interleave
+struct SnoopingBus {
+    line_size: usize,
+    num_lines: usize,
+    caches: Vec<Vec<CacheLine>>,
+    stats: Vec<CoherenceStats>,
+    /// BusRd, BusRdX and BusUpgr transactions seen on the bus
+    transactions: u64,
+}
+
+/// shared snooping bus keeping the L1 data caches of the harts coherent with the MESI protocol
+#[derive(Clone)]
+pub struct CoherenceBus(Arc<Mutex<SnoopingBus>>);
+
+impl CoherenceBus {
+    /// line size must be a multiple of a double word, so memory is filled and written back with aligned accesses
+    pub fn new(line_size: usize, num_lines: usize) -> Self {
+        assert!(line_size >= 8 && line_size.is_power_of_two());
+        assert!(num_lines > 0);
+        Self(Arc::new(Mutex::new(SnoopingBus { line_size, num_lines, caches: vec![], stats: vec![], transactions: 0 })))
+    }
+
+    /// add a new cache to the bus, serving the given range of addresses
+    pub fn connect(&self, start_address: Address, end_address: Address) -> CoherentCache {
+        assert!(end_address > start_address);
+        let mut bus = self.0.lock().unwrap();
+        let line = CacheLine { line_address: NO_LINE, state: MesiState::Invalid, data: vec![0u8; bus.line_size] };
+        let lines = vec![line; bus.num_lines];
+        bus.caches.push(lines);
+        bus.stats.push(CoherenceStats::default());
+        CoherentCache { bus: self.clone(), id: bus.caches.len() - 1, start_address, end_address }
+    }
+
+    pub fn stats(&self, cache_id: usize) -> CoherenceStats {
+        self.0.lock().unwrap().stats[cache_id]
+    }
+
+    pub fn transactions(&self) -> u64 {
+        self.0.lock().unwrap().transactions
+    }
+
+    /// state of the line holding the address in the given cache
+    pub fn state(&self, cache_id: usize, address: Address) -> MesiState {
+        let bus = self.0.lock().unwrap();
+        let (line_address, index) = bus.locate(address);
+        let line = &bus.caches[cache_id][index];
+        if line.line_address == line_address { line.state } else { MesiState::Invalid }
+    }
+}
+
+impl SnoopingBus {
+    fn locate(&self, address: Address) -> (Address, usize) {
+        let line_address = address & !(self.line_size as Address - 1);
+        let index = (line_address / self.line_size as Address) as usize % self.num_lines;
+        (line_address, index)
+    }
+
+    fn write_back(line: &CacheLine, mmu: &mut MemoryManagementUnit) {
+        for (offset, bytes) in line.data.chunks(8).enumerate() {
+            mmu.process_memory_request(MemoryRequest::write(line.line_address + (offset * 8) as Address, bytes));
+        }
+    }
+
+    /// drop the line currently held at the index, writing it back if it was modified
+    fn evict(&mut self, cache_id: usize, index: usize, mmu: &mut MemoryManagementUnit) {
+        let line = &mut self.caches[cache_id][index];
+        if line.state == MesiState::Modified {
+            Self::write_back(line, mmu);
+            self.stats[cache_id].writebacks += 1;
+        }
+        line.state = MesiState::Invalid;
+        line.line_address = NO_LINE;
+    }
+
+    /// broadcast a BusRd (or a BusRdX when `exclusive`) to the other caches
+    /// modified copies are written back first, then demoted to shared or invalidated; returns true if another copy remains
+    fn snoop(&mut self, cache_id: usize, line_address: Address, index: usize, exclusive: bool, mmu: &mut MemoryManagementUnit) -> bool {
+        self.transactions += 1;
+        let mut shared = false;
+        for other in 0..self.caches.len() {
+            let line = &mut self.caches[other][index];
+            if other == cache_id || line.line_address != line_address || line.state == MesiState::Invalid {
+                continue;
+            }
+            if line.state == MesiState::Modified {
+                Self::write_back(line, mmu);
+                self.stats[other].writebacks += 1;
+            }
+            if exclusive {
+                line.state = MesiState::Invalid;
+                self.stats[other].invalidations += 1;
+            } else {
+                line.state = MesiState::Shared;
+                shared = true;
+            }
+        }
+        shared
+    }
+
+    /// bring the line holding the address into the cache, answering with the failed memory response if it cannot be read
+    fn fill(&mut self, cache_id: usize, line_address: Address, index: usize, exclusive: bool, mmu: &mut MemoryManagementUnit) -> Result<(), MemoryResponse> {
+        let line = &self.caches[cache_id][index];
+        if line.line_address == line_address {
+            // the line is still here, but was invalidated by another cache
+            self.stats[cache_id].coherence_misses += 1;
+        } else {
+            self.evict(cache_id, index, mmu);
+        }
+        self.stats[cache_id].misses += 1;
+        let shared = self.snoop(cache_id, line_address, index, exclusive, mmu);
+        let mut data = vec![0u8; self.line_size];
+        for (offset, bytes) in data.chunks_mut(8).enumerate() {
+            let response = mmu.process_memory_request(MemoryRequest::read_u64(line_address + (offset * 8) as Address));
+            if response.status != MemoryResponseType::Valid && response.status != MemoryResponseType::CacheHit {
+                return Err(response);
+            }
+            bytes.copy_from_slice(&response.data[..8]);
+        }
+        let line = &mut self.caches[cache_id][index];
+        line.line_address = line_address;
+        line.data = data;
+        line.state = match (exclusive, shared) {
+            (true, _) => MesiState::Modified,
+            (false, true) => MesiState::Shared,
+            (false, false) => MesiState::Exclusive,
+        };
+        Ok(())
+    }
+}
+
+/// port of a hart on the coherence bus, caching the accesses to the shared memory range it serves
+pub struct CoherentCache {
+    bus: CoherenceBus,
+    id: usize,
+    start_address: Address,
+    end_address: Address,
+}
+
+impl CoherentCache {
+    pub fn holds(&self, address: Address) -> bool {
+        address >= self.start_address && address < self.end_address
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn stats(&self) -> CoherenceStats {
+        self.bus.stats(self.id)
+    }
+
+    /// serve a load or store, the caller must hold the MMU so the bus transaction and the memory accesses are atomic
+    /// accesses never cross a line, since the core splits misaligned accesses into aligned ones
+    pub fn access(&self, mmu: &mut MemoryManagementUnit, request: MemoryRequest) -> MemoryResponse {
+        let mut bus = self.bus.0.lock().unwrap();
+        let (line_address, index) = bus.locate(request.data_address);
+        let offset = (request.data_address - line_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > bus.line_size {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::UnalignedAddress };
+        }
+        let line = &bus.caches[self.id][index];
+        let state = if line.line_address == line_address { line.state } else { MesiState::Invalid };
+
+        if request.request_type == MemoryRequestType::READ {
+            if state == MesiState::Invalid {
+                if let Err(response) = bus.fill(self.id, line_address, index, false, mmu) {
+                    return response;
+                }
+            } else {
+                bus.stats[self.id].hits += 1;
+            }
+            let line = &bus.caches[self.id][index];
+            return MemoryResponse {
+                data: MemoryData::new(&line.data[offset..offset + size]),
+                status: MemoryResponseType::CacheHit,
+            };
+        }
+
+        match state {
+            MesiState::Modified | MesiState::Exclusive => bus.stats[self.id].hits += 1,
+            MesiState::Shared => {
+                // BusUpgr: the data is already here, the other copies only have to be invalidated
+                bus.stats[self.id].hits += 1;
+                bus.snoop(self.id, line_address, index, true, mmu);
+            }
+            MesiState::Invalid => {
+                if let Err(response) = bus.fill(self.id, line_address, index, true, mmu) {
+                    return response;
+                }
+            }
+        }
+        let line = &mut bus.caches[self.id][index];
+        line.state = MesiState::Modified;
+        line.data[offset..offset + size].copy_from_slice(&request.data.unwrap()[..size]);
+        // the store does not reach the MMU, but it must still break the LR reservations of the other harts
+        mmu.invalidate_reservations(request.data_address, size as Address);
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::CacheHit }
+    }
+
+    /// latest value of a byte, from any cache holding a valid copy of its line
+    pub fn peek(&self, address: Address) -> Option<u8> {
+        let bus = self.bus.0.lock().unwrap();
+        let (line_address, index) = bus.locate(address);
+        bus.caches
+            .iter()
+            .map(|lines| &lines[index])
+            .find(|line| line.line_address == line_address && line.state != MesiState::Invalid)
+            .map(|line| line.data[(address - line_address) as usize])
+    }
+}
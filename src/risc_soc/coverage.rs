@@ -0,0 +1,130 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::symbols::SymbolTable;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+const OP_BRANCH: u32 = 0b1100011;
+const OP_JAL: u32 = 0b1101111;
+const OP_JALR: u32 = 0b1100111;
+
+/// covered instructions of a function, out of the instructions its symbol spans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub address: Address,
+    pub covered: usize,
+    pub instructions: usize,
+}
+
+#[derive(Debug, Default)]
+struct CoverageState {
+    /// retired instructions and how many times they retired
+    executed: BTreeMap<Address, u64>,
+    /// transfers between basic blocks, from the control flow instruction to the next instruction that retired
+    edges: BTreeMap<(Address, Address), u64>,
+    last: Option<(Address, u32)>,
+}
+
+/// Coverage of the guest code, collected from the instructions that retire
+/// A basic block ends with a branch or a jump, or where the next instruction to retire is not the sequential one (ex. a trap)
+/// so the edges tell the taken and not taken directions of every branch apart
+/// Registered on a core with `RiscCore::enable_coverage`
+#[derive(Debug, Default)]
+pub struct Coverage {
+    state: Mutex<CoverageState>,
+}
+
+impl Coverage {
+    pub fn executed(&self) -> BTreeMap<Address, u64> {
+        self.state.lock().unwrap().executed.clone()
+    }
+
+    pub fn edges(&self) -> BTreeMap<(Address, Address), u64> {
+        self.state.lock().unwrap().edges.clone()
+    }
+
+    pub fn is_covered(&self, address: Address) -> bool {
+        self.state.lock().unwrap().executed.contains_key(&address)
+    }
+
+    /// executed addresses one per line, ready to be piped to `addr2line -e <program.elf>`
+    pub fn addresses(&self) -> String {
+        let state = self.state.lock().unwrap();
+        state.executed.keys().map(|address| format!("0x{address:X}\n")).collect()
+    }
+
+    /// `from to count` lines, one for every edge taken
+    pub fn edge_report(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut report = String::new();
+        for ((from, to), count) in &state.edges {
+            writeln!(report, "0x{from:X} 0x{to:X} {count}").unwrap();
+        }
+        report
+    }
+
+    /// coverage of every symbol with a known size, assuming 4-byte instructions
+    pub fn functions(&self, symbols: &SymbolTable) -> Vec<FunctionCoverage> {
+        let state = self.state.lock().unwrap();
+        symbols
+            .iter()
+            .filter(|symbol| symbol.size > 0)
+            .map(|symbol| FunctionCoverage {
+                name: symbol.name.clone(),
+                address: symbol.address,
+                covered: state.executed.range(symbol.address..symbol.address + symbol.size).count(),
+                instructions: symbol.size.div_ceil(4) as usize,
+            })
+            .collect()
+    }
+
+    /// per-function summary in the lcov tracefile format, addresses stand in for the line numbers of the missing debug info
+    pub fn lcov(&self, symbols: &SymbolTable, source: &str) -> String {
+        let state = self.state.lock().unwrap();
+        let mut report = format!("TN:\nSF:{source}\n");
+        let functions: Vec<_> = symbols.iter().filter(|symbol| symbol.size > 0).collect();
+        for symbol in &functions {
+            writeln!(report, "FN:{},{}", symbol.address, symbol.name).unwrap();
+        }
+        for symbol in &functions {
+            let calls = state.executed.get(&symbol.address).copied().unwrap_or(0);
+            writeln!(report, "FNDA:{calls},{}", symbol.name).unwrap();
+        }
+        let hit = functions.iter().filter(|symbol| state.executed.contains_key(&symbol.address)).count();
+        writeln!(report, "FNF:{}\nFNH:{hit}", functions.len()).unwrap();
+        // the instructions of the functions that never retired are reported with a zero count
+        let mut lines = state.executed.clone();
+        for symbol in &functions {
+            for address in (symbol.address..symbol.address + symbol.size).step_by(4) {
+                lines.entry(address).or_insert(0);
+            }
+        }
+        for (address, count) in &lines {
+            writeln!(report, "DA:{address},{count}").unwrap();
+        }
+        writeln!(report, "LF:{}\nLH:{}\nend_of_record", lines.len(), state.executed.len()).unwrap();
+        report
+    }
+
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = CoverageState::default();
+    }
+}
+
+impl SimulationObserver for Coverage {
+    fn on_instruction_retired(&self, _core: &RiscCore, pc: RiscWord, instruction: u32) {
+        let pc = pc as Address;
+        let mut state = self.state.lock().unwrap();
+        *state.executed.entry(pc).or_default() += 1;
+        if let Some((last_pc, last_instruction)) = state.last {
+            let control_flow = [OP_BRANCH, OP_JAL, OP_JALR].contains(&(last_instruction & 0x7F));
+            if control_flow || pc != last_pc + 4 {
+                *state.edges.entry((last_pc, pc)).or_default() += 1;
+            }
+        }
+        state.last = Some((pc, instruction));
+    }
+}
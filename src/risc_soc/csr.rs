@@ -0,0 +1,195 @@
+use crate::risc_soc::risc_soc::{RiscWord, XLEN};
+use crate::risc_soc::trigger::{CSR_TDATA1, CSR_TDATA2, CSR_TDATA3, CSR_TINFO, CSR_TSELECT, TriggerModule};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// addresses of the machine information registers, as defined by the privileged spec
+pub const CSR_MVENDORID: u16 = 0xF11;
+pub const CSR_MARCHID: u16 = 0xF12;
+pub const CSR_MIMPID: u16 = 0xF13;
+pub const CSR_MHARTID: u16 = 0xF14;
+pub const CSR_MISA: u16 = 0x301;
+pub const CSR_MIP: u16 = 0x344;
+/// Debug Mode registers of the Debug spec, only accessible while the hart is halted in Debug Mode
+pub const CSR_DCSR: u16 = 0x7B0;
+pub const CSR_DPC: u16 = 0x7B1;
+pub const CSR_DSCRATCH0: u16 = 0x7B2;
+pub const CSR_DSCRATCH1: u16 = 0x7B3;
+
+/// fields of dcsr: external debug support of version 1.0, EBREAK in M-mode entering Debug Mode, single stepping
+/// the hart always runs in M-mode, which prv reports
+const DCSR_XDEBUGVER: RiscWord = 4 << 28;
+pub const DCSR_EBREAKM: RiscWord = 1 << 15;
+const DCSR_CAUSE_SHIFT: u32 = 6;
+pub const DCSR_STEP: RiscWord = 1 << 2;
+const DCSR_PRV_M: RiscWord = 0b11;
+
+/// pending interrupt bits of mip driven by the CLINT
+pub const MIP_MSIP: u64 = 1 << 3;
+pub const MIP_MTIP: u64 = 1 << 7;
+/// pending interrupt bit of mip driven by the interrupt lines of the devices
+pub const MIP_MEIP: u64 = 1 << 11;
+
+/// interrupt pending lines of a hart, set and cleared by the interrupt controllers and read through mip
+pub type InterruptLines = Arc<AtomicU64>;
+
+/// identification of a hart, exposed to guest code through the machine information CSRs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineInfo {
+    /// JEDEC manufacturer id, 0 for non-commercial implementations
+    pub vendor_id: u32,
+    pub arch_id: RiscWord,
+    pub impl_id: RiscWord,
+    pub hart_id: RiscWord,
+    /// single letter extensions implemented by the hart (ex. "IMAC"), followed by the multi-letter ones separated by underscores
+    /// as in ISA strings (ex. "IMAB_Zicond")
+    pub extensions: String,
+}
+
+impl Default for MachineInfo {
+    fn default() -> Self {
+        Self { vendor_id: 0, arch_id: 0, impl_id: 0, hart_id: 0, extensions: "I".to_string() }
+    }
+}
+
+impl MachineInfo {
+    pub fn new(hart_id: RiscWord, extensions: &str) -> Self {
+        Self { hart_id, extensions: extensions.to_string(), ..Default::default() }
+    }
+
+    /// MXL in the two most significant bits, followed by one bit per extension letter starting from A
+    pub fn misa(&self) -> RiscWord {
+        let mxl: RiscWord = if XLEN == 64 { 2 } else { 1 };
+        let extensions = self
+            .single_letter_extensions()
+            .chars()
+            .filter(|c| c.is_ascii_alphabetic())
+            .fold(0, |bits: RiscWord, c| bits | 1 << (c.to_ascii_uppercase() as u8 - b'A'));
+        mxl << (XLEN - 2) | extensions
+    }
+
+    fn single_letter_extensions(&self) -> &str {
+        self.extensions.split('_').next().unwrap_or_default()
+    }
+
+    /// a single letter (ex. "M") or a multi-letter extension (ex. "Zicond"), B implies Zba, Zbb and Zbs
+    pub fn has_extension(&self, name: &str) -> bool {
+        let has_letter = |letter: char| self.single_letter_extensions().chars().any(|c| c.eq_ignore_ascii_case(&letter));
+        match name.to_ascii_lowercase().as_str() {
+            letter if letter.len() == 1 => has_letter(letter.chars().next().unwrap()),
+            "zba" | "zbb" | "zbs" if has_letter('B') => true,
+            _ => self.extensions.split('_').skip(1).any(|extension| extension.eq_ignore_ascii_case(name)),
+        }
+    }
+}
+
+/// why the hart entered Debug Mode, as reported in the cause field of dcsr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCause {
+    Ebreak = 1,
+    Trigger = 2,
+    HaltRequest = 3,
+    Step = 4,
+    ResetHaltRequest = 5,
+}
+
+/// state of the hart for the Debug spec, the hart never executes in Debug Mode so the program cannot reach it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DebugRegisters {
+    /// halted in Debug Mode, the debug CSRs are only accessible meanwhile
+    pub halted: bool,
+    pub dcsr: RiscWord,
+    /// address of the next instruction to execute when the hart resumes
+    pub dpc: RiscWord,
+    pub dscratch: [RiscWord; 2],
+}
+
+impl Default for DebugRegisters {
+    fn default() -> Self {
+        Self { halted: false, dcsr: DCSR_XDEBUGVER | DCSR_PRV_M, dpc: 0, dscratch: [0; 2] }
+    }
+}
+
+impl DebugRegisters {
+    pub fn cause(&self) -> Option<DebugCause> {
+        match (self.dcsr >> DCSR_CAUSE_SHIFT) & 0b111 {
+            1 => Some(DebugCause::Ebreak),
+            2 => Some(DebugCause::Trigger),
+            3 => Some(DebugCause::HaltRequest),
+            4 => Some(DebugCause::Step),
+            5 => Some(DebugCause::ResetHaltRequest),
+            _ => None,
+        }
+    }
+
+    pub fn enter(&mut self, pc: RiscWord, cause: DebugCause) {
+        self.halted = true;
+        self.dpc = pc;
+        self.dcsr = self.dcsr & !(0b111 << DCSR_CAUSE_SHIFT) | (cause as RiscWord) << DCSR_CAUSE_SHIFT;
+    }
+}
+
+/// control and status registers of a hart
+#[derive(Debug, Clone, Default)]
+pub struct ControlStatusRegisters {
+    pub info: MachineInfo,
+    pub mip: InterruptLines,
+    pub debug: DebugRegisters,
+    pub triggers: TriggerModule,
+}
+
+impl ControlStatusRegisters {
+    pub fn new(info: MachineInfo) -> Self {
+        Self { info, mip: InterruptLines::default(), debug: DebugRegisters::default(), triggers: TriggerModule::default() }
+    }
+
+    /// `None` if the CSR is not implemented, which makes the access an illegal instruction
+    pub fn read(&self, csr: u16) -> Option<RiscWord> {
+        match csr {
+            CSR_MVENDORID => Some(self.info.vendor_id as RiscWord),
+            CSR_MARCHID => Some(self.info.arch_id),
+            CSR_MIMPID => Some(self.info.impl_id),
+            CSR_MHARTID => Some(self.info.hart_id),
+            CSR_MISA => Some(self.info.misa()),
+            CSR_MIP => Some(self.mip.load(Ordering::SeqCst) as RiscWord),
+            CSR_DCSR if self.debug.halted => Some(self.debug.dcsr),
+            CSR_DPC if self.debug.halted => Some(self.debug.dpc),
+            CSR_DSCRATCH0 if self.debug.halted => Some(self.debug.dscratch[0]),
+            CSR_DSCRATCH1 if self.debug.halted => Some(self.debug.dscratch[1]),
+            CSR_TSELECT | CSR_TDATA1 | CSR_TDATA2 | CSR_TDATA3 | CSR_TINFO => self.triggers.read(csr),
+            _ => None,
+        }
+    }
+
+    /// the two most significant address bits set mark a read-only CSR, writing it is an illegal instruction
+    /// misa is WARL and the extensions cannot be changed at runtime, so writes to it are ignored
+    /// the machine level bits of mip are only changed by the interrupt controllers, so writes to it are ignored as well
+    /// only ebreakm and step of dcsr are writable, the other fields are fixed or report the state of the hart
+    pub fn write(&mut self, csr: u16, value: RiscWord) -> Option<()> {
+        if csr >> 10 == 0b11 {
+            return None;
+        }
+        match csr {
+            CSR_MISA | CSR_MIP => Some(()),
+            CSR_DCSR if self.debug.halted => {
+                let writable = DCSR_EBREAKM | DCSR_STEP;
+                self.debug.dcsr = self.debug.dcsr & !writable | value & writable;
+                Some(())
+            }
+            CSR_DPC if self.debug.halted => {
+                self.debug.dpc = value;
+                Some(())
+            }
+            CSR_DSCRATCH0 if self.debug.halted => {
+                self.debug.dscratch[0] = value;
+                Some(())
+            }
+            CSR_DSCRATCH1 if self.debug.halted => {
+                self.debug.dscratch[1] = value;
+                Some(())
+            }
+            CSR_TSELECT | CSR_TDATA1 | CSR_TDATA2 | CSR_TDATA3 | CSR_TINFO => self.triggers.write(csr, value, self.debug.halted),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use crate::risc_soc::isa_model::{IsaModel, MicroOp, MicroOpResult, Operands};
+use crate::risc_soc::risc_soc::RiscCore;
+use crate::risc_soc::sim_error::SimErrorKind;
+use std::sync::{Arc, Mutex};
+
+/// major opcodes the RISC-V spec leaves to custom instructions, which no standard extension will ever use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomOpcode {
+    Custom0 = 0b0001011,
+    Custom1 = 0b0101011,
+}
+
+impl CustomOpcode {
+    pub fn of(instruction: u32) -> Option<Self> {
+        match (instruction & 0x7F) as u8 {
+            opcode if opcode == Self::Custom0 as u8 => Some(Self::Custom0),
+            opcode if opcode == Self::Custom1 as u8 => Some(Self::Custom1),
+            _ => None,
+        }
+    }
+}
+
+/// micro-op of an R-type instruction writing rd from rs1 and rs2, the format of most accelerator instructions
+pub fn r_type(instruction: u32) -> MicroOp {
+    MicroOp {
+        opcode: (instruction & 0x7F) as u8,
+        rd: ((instruction >> 7) & 0x1F) as u8,
+        func3: ((instruction >> 12) & 0x7) as u8,
+        rs1: ((instruction >> 15) & 0x1F) as u8,
+        rs2: ((instruction >> 20) & 0x1F) as u8,
+        func7: (instruction >> 25) as u8,
+        reg_write: true,
+        uses_rs1: true,
+        uses_rs2: true,
+        ..Default::default()
+    }
+}
+
+type CustomDecode = Box<dyn Fn(u32) -> Option<MicroOp> + Send + Sync>;
+type CustomExecute<S> = Box<dyn Fn(&MicroOp, Operands, &mut S, &RiscCore) -> MicroOpResult + Send + Sync>;
+
+/// instructions of an X extension, with the architectural state they add to the core (ex. the accumulators of an accelerator)
+/// the state is back to its default value after a reset of the core
+pub struct XExtension<S> {
+    pub name: String,
+    decode: CustomDecode,
+    execute: CustomExecute<S>,
+    state: Arc<Mutex<S>>,
+}
+
+impl<S: Default + Send + 'static> XExtension<S> {
+    /// `decode` returns `None` for the encodings of the opcode space the extension does not define
+    /// `execute` is only called once the instruction leaves EX, so it can update the state of the extension
+    pub fn new(
+        name: &str,
+        decode: impl Fn(u32) -> Option<MicroOp> + Send + Sync + 'static,
+        execute: impl Fn(&MicroOp, Operands, &mut S, &RiscCore) -> MicroOpResult + Send + Sync + 'static,
+    ) -> Self {
+        Self { name: name.to_string(), decode: Box::new(decode), execute: Box::new(execute), state: Arc::default() }
+    }
+
+    /// shared with the extension, to inspect or initialize its state from the host
+    pub fn state(&self) -> Arc<Mutex<S>> {
+        self.state.clone()
+    }
+}
+
+/// object safe part of an extension, whatever its state
+trait CustomInstructions: Send + Sync {
+    fn decode(&self, instruction: u32) -> Option<MicroOp>;
+    fn execute(&self, op: &MicroOp, operands: Operands, core: &RiscCore) -> MicroOpResult;
+    fn reset(&self);
+}
+
+impl<S: Default + Send + 'static> CustomInstructions for XExtension<S> {
+    fn decode(&self, instruction: u32) -> Option<MicroOp> {
+        (self.decode)(instruction)
+    }
+
+    fn execute(&self, op: &MicroOp, operands: Operands, core: &RiscCore) -> MicroOpResult {
+        (self.execute)(op, operands, &mut self.state.lock().unwrap(), core)
+    }
+
+    fn reset(&self) {
+        *self.state.lock().unwrap() = S::default();
+    }
+}
+
+/// ISA model adding X extensions on the custom opcodes of a base ISA, the other instructions are left to the base
+pub struct CustomExtensions {
+    base: Arc<dyn IsaModel>,
+    extensions: Vec<(CustomOpcode, Box<dyn CustomInstructions>)>,
+}
+
+impl CustomExtensions {
+    pub fn new(base: impl IsaModel + 'static) -> Self {
+        Self { base: Arc::new(base), extensions: vec![] }
+    }
+
+    /// the extension decodes and executes every instruction of the opcode space, which can only be claimed once
+    pub fn claim<S: Default + Send + 'static>(mut self, opcode: CustomOpcode, extension: XExtension<S>) -> Self {
+        assert!(self.extension(opcode as u8).is_none(), "Opcode space {opcode:?} is already claimed by another extension!");
+        tracing::info!("X extension {} claims opcode space {opcode:?}", extension.name);
+        self.extensions.push((opcode, Box::new(extension)));
+        self
+    }
+
+    fn extension(&self, opcode: u8) -> Option<&dyn CustomInstructions> {
+        self.extensions.iter().find(|(claimed, _)| *claimed as u8 == opcode).map(|(_, extension)| extension.as_ref())
+    }
+}
+
+impl IsaModel for CustomExtensions {
+    fn decode(&self, instruction: u32) -> Result<Vec<MicroOp>, SimErrorKind> {
+        let Some(opcode) = CustomOpcode::of(instruction) else {
+            return self.base.decode(instruction);
+        };
+        let Some(extension) = self.extension(opcode as u8) else {
+            return self.base.decode(instruction);
+        };
+        // the opcode routes the micro-op back to the extension in EX
+        let op = extension.decode(instruction).ok_or(SimErrorKind::UnsupportedInstruction(instruction))?;
+        Ok(vec![MicroOp { opcode: opcode as u8, ..op }])
+    }
+
+    fn execute(&self, op: &MicroOp, operands: Operands, core: &RiscCore) -> MicroOpResult {
+        match self.extension(op.opcode) {
+            // the result of a held instruction is dropped, it is executed again once MEM releases the pipeline
+            Some(_) if operands.hold => MicroOpResult { target: operands.pc, ..Default::default() },
+            Some(extension) => extension.execute(op, operands, core),
+            None => self.base.execute(op, operands, core),
+        }
+    }
+
+    fn reset(&self) {
+        for (_, extension) in &self.extensions {
+            extension.reset();
+        }
+        self.base.reset();
+    }
+}
@@ -0,0 +1,208 @@
+use crate::risc_soc::disasm::disassemble;
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::run_control::{RunControl, StopReason};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// data memory accesses kept for the dashboard, the oldest ones are dropped
+const RECENT_ACCESSES: usize = 16;
+/// upper bound of the clock cycles run between two refreshes of the screen
+const MAX_CYCLES_PER_FRAME: u64 = 1 << 16;
+
+const HELP: &str = "s: step  r: run/pause  +/-: cycles per frame  q: quit";
+
+struct Access {
+    request_type: MemoryRequestType,
+    address: Address,
+    /// bytes written, or read back from memory
+    data: Vec<u8>,
+}
+
+/// events of the simulation shown next to the architectural state of the core
+#[derive(Default)]
+struct DashboardEvents {
+    retired: AtomicU64,
+    last_retired: Mutex<Option<(RiscWord, u32)>>,
+    accesses: Mutex<VecDeque<Access>>,
+    trap: Mutex<Option<Trap>>,
+}
+
+impl SimulationObserver for DashboardEvents {
+    fn on_instruction_retired(&self, _core: &RiscCore, pc: RiscWord, instruction: u32) {
+        self.retired.fetch_add(1, Ordering::Relaxed);
+        *self.last_retired.lock().unwrap() = Some((pc, instruction));
+    }
+
+    fn on_trap(&self, _core: &RiscCore, trap: &Trap) {
+        *self.trap.lock().unwrap() = Some(*trap);
+    }
+
+    fn on_mem_access(&self, _core: &RiscCore, request: &MemoryRequest, response: &MemoryResponse) {
+        let size = request.data_size as usize;
+        let data = match (request.request_type, &request.data) {
+            (MemoryRequestType::WRITE, Some(data)) => data[..size].to_vec(),
+            _ => response.data.iter().take(size).copied().collect(),
+        };
+        let mut accesses = self.accesses.lock().unwrap();
+        if accesses.len() == RECENT_ACCESSES {
+            accesses.pop_front();
+        }
+        accesses.push_back(Access { request_type: request.request_type, address: request.data_address, data });
+    }
+}
+
+/// Terminal front end showing the pipeline stages, the register file, the recent data accesses and the statistics of a core
+/// The screen is refreshed at the end of every step, or after every batch of clock cycles while running
+/// Meant as a teaching aid, the console output of the program (ex. the UART) shares the terminal and is best kept quiet
+pub struct Dashboard<'a> {
+    core: &'a mut RiscCore,
+    events: Arc<DashboardEvents>,
+    running: bool,
+    cycles_per_frame: u64,
+    last_stop: Option<StopReason>,
+}
+
+impl<'a> Dashboard<'a> {
+    pub fn new(core: &'a mut RiscCore) -> Self {
+        let events = Arc::new(DashboardEvents::default());
+        core.add_observer(events.clone());
+        Self { core, events, running: false, cycles_per_frame: 1, last_stop: None }
+    }
+
+    /// take over the terminal until the user quits
+    pub fn run(&mut self) -> std::io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            // while running the keys are only polled between two batches of clock cycles
+            let timeout = if self.running { Duration::ZERO } else { Duration::from_millis(100) };
+            let key = match event::poll(timeout)? {
+                true => match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => Some(key.code),
+                    _ => None,
+                },
+                false => None,
+            };
+            match key {
+                Some(KeyCode::Char('q') | KeyCode::Esc) => return Ok(()),
+                Some(KeyCode::Char('s')) => {
+                    self.running = false;
+                    self.advance(1);
+                }
+                Some(KeyCode::Char('r' | ' ')) => self.running = !self.running,
+                Some(KeyCode::Char('+')) => self.cycles_per_frame = (self.cycles_per_frame * 2).min(MAX_CYCLES_PER_FRAME),
+                Some(KeyCode::Char('-')) => self.cycles_per_frame = (self.cycles_per_frame / 2).max(1),
+                _ => {}
+            }
+            if self.running {
+                self.advance(self.cycles_per_frame);
+            }
+        }
+    }
+
+    /// run on the calling thread, so the refresh rate does not depend on the scheduling of the stage threads
+    pub fn advance(&mut self, cycles: u64) {
+        let reason = self.core.run_sequential_with(RunControl::cycles(cycles));
+        if reason != StopReason::CycleLimit {
+            self.running = false;
+        }
+        self.last_stop = Some(reason);
+    }
+
+    /// render the whole dashboard into a frame, ex. of a terminal with a test backend
+    pub fn draw(&self, frame: &mut Frame) {
+        let [header, body, footer] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, registers] = Layout::horizontal([Constraint::Min(40), Constraint::Length(44)]).areas(body);
+        let [stages, accesses] = Layout::vertical([Constraint::Length(self.core.stages.len() as u16 + 2), Constraint::Min(0)]).areas(left);
+
+        frame.render_widget(Paragraph::new(self.statistics()).block(Block::bordered().title("Statistics")), header);
+        frame.render_widget(Paragraph::new(self.stage_lines()).block(Block::bordered().title("Pipeline")), stages);
+        frame.render_widget(Paragraph::new(self.access_lines()).block(Block::bordered().title("Memory accesses")), accesses);
+        frame.render_widget(Paragraph::new(self.register_lines()).block(Block::bordered().title("Registers")), registers);
+        frame.render_widget(Paragraph::new(HELP), footer);
+    }
+
+    fn statistics(&self) -> Line<'static> {
+        let cycles = self.core.stages.first().map_or(0, |stage| stage.lock().unwrap().clock_cycle);
+        let retired = self.events.retired.load(Ordering::Relaxed);
+        let ipc = if cycles == 0 { 0.0 } else { retired as f64 / cycles as f64 };
+        let mut text = format!("cycle {cycles}  retired {retired}  IPC {ipc:.2}  pc 0x{:X}", self.core.get_pc());
+        if let Some((pc, instruction)) = *self.events.last_retired.lock().unwrap() {
+            text += &format!("  last 0x{pc:X} {}", disassemble(instruction));
+        }
+        if let Some(stats) = self.core.store_buffer_stats() {
+            text += &format!("  stores {} (combined {})", stats.stores, stats.combined);
+        }
+        if let Some(stats) = self.core.prefetch_stats() {
+            text += &format!("  prefetch coverage {:.0}%", stats.coverage() * 100.0);
+        }
+        if let Some(trap) = *self.events.trap.lock().unwrap() {
+            text += &format!("  trap: {trap}");
+        } else if let Some(reason) = self.last_stop.as_ref().filter(|reason| **reason != StopReason::CycleLimit) {
+            text += &format!("  stopped: {reason:?}");
+        }
+        Line::from(text)
+    }
+
+    /// instruction processed by every stage during the last clock cycle
+    fn stage_lines(&self) -> Vec<Line<'static>> {
+        self.core
+            .stages
+            .iter()
+            .map(|stage| {
+                let stage = stage.lock().unwrap();
+                let instruction = stage.instruction.0;
+                let text = if instruction == 0x0 { "-".to_string() } else { disassemble(instruction) };
+                Line::from(format!("{:>4}  {instruction:08X}  {text}", stage.name))
+            })
+            .collect()
+    }
+
+    fn access_lines(&self) -> Vec<Line<'static>> {
+        self.events
+            .accesses
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .map(|access| {
+                let kind = match access.request_type {
+                    MemoryRequestType::READ => "R",
+                    MemoryRequestType::WRITE => "W",
+                };
+                let data: Vec<String> = access.data.iter().map(|byte| format!("{byte:02X}")).collect();
+                Line::from(format!("{kind} 0x{:08X}  {}", access.address, data.join(" ")))
+            })
+            .collect()
+    }
+
+    /// two columns of registers, x0..x15 next to x16..x31
+    fn register_lines(&self) -> Vec<Line<'static>> {
+        let registers: Vec<String> = self
+            .core
+            .registers
+            .iter()
+            .enumerate()
+            .map(|(index, (name, value))| format!("{:>8} {value:0width$X}", format!("x{index}({name})"), width = 2 * size_of::<RiscWord>()))
+            .collect();
+        let half = registers.len() / 2;
+        (0..half).map(|row| Line::from(format!("{}  {}", registers[row], registers[row + half]))).collect()
+    }
+}
@@ -0,0 +1,213 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord, XLEN, zero_extend_word};
+
+/// addresses of the Debug Module registers on the Debug Module Interface
+pub const DMI_DATA0: u8 = 0x04;
+pub const DMI_DATA1: u8 = 0x05;
+pub const DMI_DMCONTROL: u8 = 0x10;
+pub const DMI_DMSTATUS: u8 = 0x11;
+pub const DMI_HARTINFO: u8 = 0x12;
+pub const DMI_ABSTRACTCS: u8 = 0x16;
+pub const DMI_COMMAND: u8 = 0x17;
+pub const DMI_SBCS: u8 = 0x38;
+pub const DMI_SBADDRESS0: u8 = 0x39;
+pub const DMI_SBDATA0: u8 = 0x3C;
+
+pub const DMCONTROL_HALTREQ: u32 = 1 << 31;
+pub const DMCONTROL_RESUMEREQ: u32 = 1 << 30;
+pub const DMCONTROL_ACKHAVERESET: u32 = 1 << 28;
+pub const DMCONTROL_NDMRESET: u32 = 1 << 1;
+pub const DMCONTROL_DMACTIVE: u32 = 1 << 0;
+
+pub const DMSTATUS_ALLRESUMEACK: u32 = 1 << 17;
+pub const DMSTATUS_ANYRESUMEACK: u32 = 1 << 16;
+pub const DMSTATUS_ALLRUNNING: u32 = 1 << 11;
+pub const DMSTATUS_ANYRUNNING: u32 = 1 << 10;
+pub const DMSTATUS_ALLHALTED: u32 = 1 << 9;
+pub const DMSTATUS_ANYHALTED: u32 = 1 << 8;
+pub const DMSTATUS_AUTHENTICATED: u32 = 1 << 7;
+/// the Debug Module conforms to version 0.13 of the spec, the one supported by most debuggers
+const DMSTATUS_VERSION: u32 = 2;
+
+/// fields of the Access Register abstract command
+const COMMAND_TRANSFER: u32 = 1 << 17;
+const COMMAND_WRITE: u32 = 1 << 16;
+const COMMAND_POSTEXEC: u32 = 1 << 18;
+const REGNO_GPR: u32 = 0x1000;
+
+/// error of the last abstract command, reported in abstractcs until the debugger clears it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommandError {
+    #[default]
+    None = 0,
+    NotSupported = 2,
+    Exception = 3,
+    HaltResume = 4,
+}
+
+/// Debug Module driving one hart through its Debug Module Interface, as a JTAG or GDB front end would
+/// the registers are read and written between runs of the core: a halt request takes effect during the next run,
+/// while abstract commands and resume requests are performed right away on the halted hart
+/// only the MCU pipeline implements Debug Mode, with no program buffer: registers and CSRs are accessed by abstract commands,
+/// memory by the system bus access registers
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DebugModule {
+    dmcontrol: u32,
+    data: [u32; 2],
+    cmderr: CommandError,
+    resume_ack: bool,
+    sbcs: u32,
+    sbaddress: Address,
+    sbdata: u32,
+}
+
+/// sbcs fields: version 1 of system bus access, 32-bit accesses only and the auto increment and read on address/data modes
+const SBCS_VERSION: u32 = 1 << 29;
+const SBCS_READONADDR: u32 = 1 << 20;
+const SBCS_ACCESS_32: u32 = 2 << 17;
+const SBCS_AUTOINCREMENT: u32 = 1 << 16;
+const SBCS_READONDATA: u32 = 1 << 15;
+const SBCS_ERROR_SHIFT: u32 = 12;
+const SBCS_ERROR_BAD_ADDRESS: u32 = 2;
+const SBCS_SUPPORTED_ACCESS: u32 = 1 << 2;
+
+impl DebugModule {
+    pub fn dmi_read(&mut self, core: &RiscCore, address: u8) -> u32 {
+        match address {
+            DMI_DATA0 => self.data[0],
+            DMI_DATA1 => self.data[1],
+            DMI_DMCONTROL => self.dmcontrol,
+            DMI_DMSTATUS => {
+                let state = if core.in_debug_mode() {
+                    DMSTATUS_ANYHALTED | DMSTATUS_ALLHALTED
+                } else {
+                    DMSTATUS_ANYRUNNING | DMSTATUS_ALLRUNNING
+                };
+                let resume_ack = if self.resume_ack { DMSTATUS_ANYRESUMEACK | DMSTATUS_ALLRESUMEACK } else { 0 };
+                DMSTATUS_VERSION | DMSTATUS_AUTHENTICATED | state | resume_ack
+            }
+            DMI_HARTINFO => 0,
+            // two data registers hold the XLEN-bit values, no program buffer and never busy
+            DMI_ABSTRACTCS => 2 | (self.cmderr as u32) << 8,
+            DMI_SBCS => {
+                let asize = (XLEN as u32) << 5;
+                SBCS_VERSION | self.sbcs | asize | SBCS_SUPPORTED_ACCESS
+            }
+            DMI_SBADDRESS0 => self.sbaddress as u32,
+            DMI_SBDATA0 => {
+                let value = self.sbdata;
+                if self.sbcs & SBCS_READONDATA != 0 {
+                    self.system_bus_read(core);
+                }
+                value
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn dmi_write(&mut self, core: &RiscCore, address: u8, value: u32) {
+        match address {
+            DMI_DATA0 => self.data[0] = value,
+            DMI_DATA1 => self.data[1] = value,
+            DMI_DMCONTROL => self.write_dmcontrol(core, value),
+            // cmderr is cleared by writing ones to it
+            DMI_ABSTRACTCS if (value >> 8) & 0b111 != 0 => self.cmderr = CommandError::None,
+            DMI_COMMAND => self.execute_command(core, value),
+            DMI_SBCS => {
+                let writable = SBCS_READONADDR | SBCS_ACCESS_32 | SBCS_AUTOINCREMENT | SBCS_READONDATA;
+                let error = (self.sbcs >> SBCS_ERROR_SHIFT) & 0b111 & !(value >> SBCS_ERROR_SHIFT);
+                self.sbcs = value & writable | error << SBCS_ERROR_SHIFT;
+            }
+            DMI_SBADDRESS0 => {
+                self.sbaddress = value as Address;
+                if self.sbcs & SBCS_READONADDR != 0 {
+                    self.system_bus_read(core);
+                }
+            }
+            DMI_SBDATA0 => {
+                self.sbdata = value;
+                if core.poke_memory(self.sbaddress, &value.to_le_bytes()).is_none() {
+                    self.sbcs |= SBCS_ERROR_BAD_ADDRESS << SBCS_ERROR_SHIFT;
+                } else if self.sbcs & SBCS_AUTOINCREMENT != 0 {
+                    self.sbaddress += 4;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write_dmcontrol(&mut self, core: &RiscCore, value: u32) {
+        // the requests and acknowledgements are not kept, they only act when written
+        self.dmcontrol = value & (DMCONTROL_DMACTIVE | DMCONTROL_NDMRESET);
+        if value & DMCONTROL_DMACTIVE == 0 {
+            // the Debug Module is reset while inactive
+            *self = Self::default();
+            return;
+        }
+        if value & DMCONTROL_HALTREQ != 0 {
+            core.request_halt();
+        } else if value & DMCONTROL_RESUMEREQ != 0 {
+            self.resume_ack = core.resume_from_debug_mode();
+        }
+    }
+
+    /// only the Access Register command is supported, on the registers, the PC (through dpc) and the CSRs of the halted hart
+    fn execute_command(&mut self, core: &RiscCore, command: u32) {
+        if self.cmderr != CommandError::None {
+            return;
+        }
+        let cmdtype = command >> 24;
+        let size = (command >> 20) & 0b111;
+        let regno = command & 0xFFFF;
+        // 32-bit accesses, and 64-bit ones on RV64
+        let size_supported = size == 2 || size == 3 && XLEN == 64;
+        if cmdtype != 0 || command & COMMAND_POSTEXEC != 0 || command & COMMAND_TRANSFER != 0 && !size_supported {
+            self.cmderr = CommandError::NotSupported;
+            return;
+        }
+        if !core.in_debug_mode() {
+            self.cmderr = CommandError::HaltResume;
+            return;
+        }
+        if command & COMMAND_TRANSFER == 0 {
+            return;
+        }
+        let write = command & COMMAND_WRITE != 0;
+        let value = if size == 3 { self.data[0] as u64 | (self.data[1] as u64) << 32 } else { self.data[0] as u64 };
+        let result = match regno {
+            REGNO_GPR..=0x101F => {
+                let index = (regno - REGNO_GPR) as usize;
+                if write {
+                    core.write_reg(index, value as RiscWord);
+                    Some(value as RiscWord)
+                } else {
+                    Some(core.read_regs(index, 0).0)
+                }
+            }
+            0x0000..=0x0FFF if write => core.write_csr(regno as u16, value as RiscWord).map(|_| value as RiscWord),
+            0x0000..=0x0FFF => core.read_csr(regno as u16),
+            _ => None,
+        };
+        match result {
+            Some(value) if !write => {
+                let value = zero_extend_word(value);
+                self.data[0] = value as u32;
+                self.data[1] = (value >> 32) as u32;
+            }
+            Some(_) => {}
+            None => self.cmderr = CommandError::Exception,
+        }
+    }
+
+    fn system_bus_read(&mut self, core: &RiscCore) {
+        match core.peek_memory(self.sbaddress, 4) {
+            Some(bytes) => {
+                self.sbdata = u32::from_le_bytes(bytes.try_into().unwrap());
+                if self.sbcs & SBCS_AUTOINCREMENT != 0 {
+                    self.sbaddress += 4;
+                }
+            }
+            None => self.sbcs |= SBCS_ERROR_BAD_ADDRESS << SBCS_ERROR_SHIFT,
+        }
+    }
+}
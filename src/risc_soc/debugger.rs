@@ -0,0 +1,184 @@
+use crate::risc_soc::disasm::instruction_length;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::run_control::StopReason;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+/// Interactive debugger on top of the run control of `RiscCore` (`run_for_cycles` and `run_until`)
+/// Each `step` advances the whole pipeline by one clock cycle, while breakpoints are checked against the PC of the fetch stage
+pub struct Debugger<'a> {
+    core: &'a mut RiscCore,
+    breakpoints: BTreeSet<Address>,
+}
+
+const HELP: &str = "commands:
+  step [n]          run n clock cycles (default 1)
+  break <addr|sym>  stop when the PC reaches the address
+  delete <addr|sym> remove a breakpoint
+  continue          run until a breakpoint or an EBREAK is reached
+  regs              print the PC and the register file
+  mem <addr> <len>  dump memory
+  disas <addr> [n]  disassemble n instructions (default 1)
+  speed <hz|free>   throttle the runs to a clock frequency, or let them run free
+  quit";
+
+impl<'a> Debugger<'a> {
+    pub fn new(core: &'a mut RiscCore) -> Self {
+        // debug mode prints the instruction processed by every stage at each clock cycle
+        core.enable_debug(true);
+        Self { core, breakpoints: BTreeSet::new() }
+    }
+
+    /// read commands until `quit` or the end of the input
+    pub fn repl(&mut self, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        write!(output, "(rdb) ")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let mut args = line.split_whitespace();
+            let Some(command) = args.next() else {
+                write!(output, "(rdb) ")?;
+                output.flush()?;
+                continue;
+            };
+            let args: Vec<&str> = args.collect();
+            match command {
+                "q" | "quit" | "exit" => break,
+                _ => {
+                    if let Err(message) = self.execute(command, &args, &mut output) {
+                        writeln!(output, "{message}")?;
+                    }
+                }
+            }
+            write!(output, "(rdb) ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, command: &str, args: &[&str], output: &mut impl Write) -> Result<(), String> {
+        let io_error = |e: std::io::Error| e.to_string();
+        match command {
+            "s" | "step" => {
+                let cycles = match args.first() {
+                    Some(n) => n.parse::<u64>().map_err(|_| format!("invalid cycle count: {n}"))?,
+                    None => 1,
+                };
+                self.core.run_for_cycles(cycles);
+                writeln!(output, "pc = {}", self.format_pc()).map_err(io_error)?;
+            }
+            "b" | "break" => {
+                let address = self.parse_address(args.first().copied())?;
+                self.breakpoints.insert(address);
+                writeln!(output, "breakpoint at 0x{address:X}").map_err(io_error)?;
+            }
+            "d" | "delete" => {
+                let address = self.parse_address(args.first().copied())?;
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!("no breakpoint at 0x{address:X}"));
+                }
+            }
+            "c" | "continue" => {
+                // without breakpoints only an EBREAK of the program or the end of the simulation stop the run
+                // the current breakpoint is always left, as the condition is only checked at the end of a clock cycle
+                // a hart halted in Debug Mode resumes from dpc
+                self.core.resume_from_debug_mode();
+                let breakpoints = &self.breakpoints;
+                match self.core.run_until(|core| breakpoints.contains(&(core.get_pc() as Address))) {
+                    StopReason::Condition => writeln!(output, "breakpoint hit, pc = {}", self.format_pc()).map_err(io_error)?,
+                    StopReason::Breakpoint => writeln!(output, "ebreak hit, pc = {}", self.format_pc()).map_err(io_error)?,
+                    StopReason::DebugHalt => {
+                        let debug = self.core.csrs.read().unwrap().debug;
+                        writeln!(output, "halted in Debug Mode ({:?}), dpc = 0x{:X}", debug.cause(), debug.dpc).map_err(io_error)?
+                    }
+                    reason => writeln!(output, "stopped ({reason:?}), pc = {}", self.format_pc()).map_err(io_error)?,
+                }
+            }
+            "r" | "regs" => {
+                writeln!(output, "pc={}", self.format_pc()).map_err(io_error)?;
+                write!(output, "{}", self.core.registers).map_err(io_error)?;
+            }
+            "x" | "mem" => {
+                let address = self.parse_address(args.first().copied())?;
+                let len = match args.get(1) {
+                    Some(len) => parse_number(len).ok_or(format!("invalid length: {len}"))? as usize,
+                    None => 4,
+                };
+                let bytes = self
+                    .core
+                    .peek_memory(address, len)
+                    .ok_or(format!("0x{address:X}..0x{:X} is not mapped", address + len as Address))?;
+                for (i, line) in bytes.chunks(16).enumerate() {
+                    let hex: Vec<String> = line.iter().map(|b| format!("{b:02X}")).collect();
+                    writeln!(output, "{:08X}: {}", address + (i * 16) as Address, hex.join(" ")).map_err(io_error)?;
+                }
+            }
+            "disas" => {
+                let address = self.parse_address(args.first().copied())?;
+                let count = match args.get(1) {
+                    Some(n) => parse_number(n).ok_or(format!("invalid count: {n}"))?,
+                    None => 1,
+                };
+                let end = address + count * 4;
+                let instructions = self
+                    .core
+                    .disassemble(address, end)
+                    .ok_or(format!("0x{address:X}..0x{end:X} is not mapped"))?;
+                // compressed instructions take 2 bytes, so the range may hold more than n of them
+                for (current, instruction, asm) in instructions.into_iter().take(count as usize) {
+                    let location = self
+                        .core
+                        .lookup_symbol(current)
+                        .map(|symbol| format!(" <{symbol}>"))
+                        .unwrap_or_default();
+                    let encoding = match instruction_length(instruction as u16) {
+                        2 => format!("{instruction:04X}    "),
+                        _ => format!("{instruction:08X}"),
+                    };
+                    writeln!(output, "{current:08X}{location}: {encoding}  {asm}").map_err(io_error)?;
+                }
+            }
+            "speed" => {
+                match args.first().copied() {
+                    Some("free") => self.core.run_free(),
+                    Some(hz) => match hz.parse::<u64>() {
+                        Ok(hz) if hz > 0 && hz <= 1_000_000_000 => self.core.set_speed(hz),
+                        _ => return Err(format!("invalid clock frequency: {hz}")),
+                    },
+                    None => {}
+                }
+                match self.core.speed() {
+                    Some(hz) => writeln!(output, "clock = {hz} Hz").map_err(io_error)?,
+                    None => writeln!(output, "running free").map_err(io_error)?,
+                }
+            }
+            "h" | "help" => writeln!(output, "{HELP}").map_err(io_error)?,
+            _ => return Err(format!("unknown command: {command}, type help for a list of commands")),
+        }
+        Ok(())
+    }
+
+    /// addresses can be given in hex (0x prefix), decimal or as the name of an ELF symbol
+    fn parse_address(&self, arg: Option<&str>) -> Result<Address, String> {
+        let arg = arg.ok_or("missing address".to_string())?;
+        parse_number(arg)
+            .or_else(|| self.core.symbol_address(arg))
+            .ok_or(format!("unknown address or symbol: {arg}"))
+    }
+
+    fn format_pc(&self) -> String {
+        let pc: RiscWord = self.core.get_pc();
+        match self.core.lookup_symbol(pc as Address) {
+            Some(symbol) => format!("0x{pc:X} <{symbol}>"),
+            None => format!("0x{pc:X}"),
+        }
+    }
+}
+
+fn parse_number(arg: &str) -> Option<u64> {
+    match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(&hex.replace('_', ""), 16).ok(),
+        None => arg.parse().ok(),
+    }
+}
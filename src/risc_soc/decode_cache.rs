@@ -0,0 +1,86 @@
+use crate::risc_soc::isa_model::MicroOp;
+use crate::risc_soc::memory_management_unit::Address;
+use std::collections::{HashMap, HashSet};
+
+/// granularity at which stores invalidate the decoded instructions
+pub const CODE_PAGE_SIZE: Address = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeCacheStats {
+    /// instructions served from the cache without being decoded again
+    pub hits: u64,
+    pub misses: u64,
+    /// code pages dropped because a store wrote to them, FENCE.I and resets not included
+    pub invalidated_pages: u64,
+}
+
+impl DecodeCacheStats {
+    /// share of the decoded instructions served from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// micro-ops of the instructions already decoded, keyed by their PC so tight loops are decoded only once
+/// the instruction word is kept along, a PC holding another instruction (ex. after loading a new program) is decoded again
+/// the whole cache is dropped by FENCE.I, and the stores of the core drop the code pages they write to
+#[derive(Default)]
+pub struct DecodeCache {
+    entries: HashMap<Address, (u32, Vec<MicroOp>)>,
+    /// pages holding at least one decoded instruction, so stores to data pages stay cheap
+    pages: HashSet<Address>,
+    pub stats: DecodeCacheStats,
+}
+
+impl DecodeCache {
+    /// micro-ops of the instruction at pc, decoded with `decode` only when they are not cached yet
+    /// errors are not cached, the instruction fails the same way every time it is decoded
+    pub fn decode<E>(
+        &mut self,
+        pc: Address,
+        instruction: u32,
+        decode: impl FnOnce(u32) -> Result<Vec<MicroOp>, E>,
+    ) -> Result<Vec<MicroOp>, E> {
+        if let Some((cached, ops)) = self.entries.get(&pc)
+            && *cached == instruction
+        {
+            self.stats.hits += 1;
+            return Ok(ops.clone());
+        }
+        self.stats.misses += 1;
+        let ops = decode(instruction)?;
+        self.pages.insert(pc / CODE_PAGE_SIZE);
+        self.entries.insert(pc, (instruction, ops.clone()));
+        Ok(ops)
+    }
+
+    /// drop the instructions of the pages overlapping [address, address + len)
+    pub fn invalidate_range(&mut self, address: Address, len: usize) {
+        if len == 0 || self.pages.is_empty() {
+            return;
+        }
+        let first = address / CODE_PAGE_SIZE;
+        let last = (address + len as Address - 1) / CODE_PAGE_SIZE;
+        for page in first..=last {
+            if self.pages.remove(&page) {
+                self.stats.invalidated_pages += 1;
+                self.entries.retain(|pc, _| *pc / CODE_PAGE_SIZE != page);
+            }
+        }
+    }
+
+    /// drop every decoded instruction, the statistics keep counting
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.pages.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
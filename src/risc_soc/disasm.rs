@@ -0,0 +1,377 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::ABI_NAMES;
+use crate::risc_soc::symbols::SymbolTable;
+
+/// bytes taken by the instruction starting with the given half word, 2 for the compressed (C) instructions
+pub fn instruction_length(low_half: u16) -> usize {
+    if low_half & 0x3 == 0x3 { 4 } else { 2 }
+}
+
+/// text of an instruction in the objdump syntax, branch and jump targets are shown as offsets from the PC
+pub fn disassemble(instruction: u32) -> String {
+    Disassembler { pc: None, symbols: None }.instruction(instruction)
+}
+
+/// text of the instruction at the given PC, branch and jump targets are resolved and named after the closest symbol
+pub fn disassemble_at(instruction: u32, pc: Address, symbols: Option<&SymbolTable>) -> String {
+    Disassembler { pc: Some(pc), symbols }.instruction(instruction)
+}
+
+/// address, encoding and text of every instruction of a block of code starting at the given address
+/// compressed instructions take 2 bytes and are returned as their 16-bit encoding
+pub fn disassemble_block(start_address: Address, code: &[u8], symbols: Option<&SymbolTable>) -> Vec<(Address, u32, String)> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset + 2 <= code.len() {
+        let low_half = u16::from_le_bytes([code[offset], code[offset + 1]]);
+        let length = instruction_length(low_half);
+        if offset + length > code.len() {
+            break;
+        }
+        let instruction = match length {
+            2 => low_half as u32,
+            _ => u32::from_le_bytes([code[offset], code[offset + 1], code[offset + 2], code[offset + 3]]),
+        };
+        let address = start_address + offset as Address;
+        instructions.push((address, instruction, disassemble_at(instruction, address, symbols)));
+        offset += length;
+    }
+    instructions
+}
+
+fn reg(index: u32) -> &'static str {
+    ABI_NAMES[(index & 0x1F) as usize]
+}
+
+/// registers x8-x15 addressed by the 3-bit fields of the compressed instructions
+fn creg(index: u32) -> &'static str {
+    ABI_NAMES[(8 + (index & 0x7)) as usize]
+}
+
+/// names of the CSRs shown instead of their number, also accepted by the assembler
+pub const CSR_NAMES: &[(u32, &str)] = &[
+    (0x001, "fflags"),
+    (0x002, "frm"),
+    (0x003, "fcsr"),
+    (0x100, "sstatus"),
+    (0x104, "sie"),
+    (0x105, "stvec"),
+    (0x140, "sscratch"),
+    (0x141, "sepc"),
+    (0x142, "scause"),
+    (0x143, "stval"),
+    (0x144, "sip"),
+    (0x180, "satp"),
+    (0x300, "mstatus"),
+    (0x301, "misa"),
+    (0x302, "medeleg"),
+    (0x303, "mideleg"),
+    (0x304, "mie"),
+    (0x305, "mtvec"),
+    (0x340, "mscratch"),
+    (0x341, "mepc"),
+    (0x342, "mcause"),
+    (0x343, "mtval"),
+    (0x344, "mip"),
+    (0xB00, "mcycle"),
+    (0xB02, "minstret"),
+    (0xC00, "cycle"),
+    (0xC01, "time"),
+    (0xC02, "instret"),
+    (0xC80, "cycleh"),
+    (0xC81, "timeh"),
+    (0xC82, "instreth"),
+    (0xF11, "mvendorid"),
+    (0xF12, "marchid"),
+    (0xF13, "mimpid"),
+    (0xF14, "mhartid"),
+];
+
+fn csr_name(csr: u32) -> String {
+    match CSR_NAMES.iter().find(|&&(number, _)| number == csr) {
+        Some((_, name)) => name.to_string(),
+        None => format!("0x{csr:X}"),
+    }
+}
+
+/// sign extend the lowest `bits` bits of a value
+fn sext(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    ((value << shift) as i32) >> shift
+}
+
+struct Disassembler<'a> {
+    pc: Option<Address>,
+    symbols: Option<&'a SymbolTable>,
+}
+
+impl Disassembler<'_> {
+    fn target(&self, offset: i32) -> String {
+        let Some(pc) = self.pc else {
+            return format!("pc{offset:+}");
+        };
+        let target = pc.wrapping_add(offset as i64 as Address);
+        match self.symbols.and_then(|symbols| symbols.format_address(target)) {
+            Some(symbol) => format!("0x{target:X} <{symbol}>"),
+            None => format!("0x{target:X}"),
+        }
+    }
+
+    fn instruction(&self, instruction: u32) -> String {
+        // the pipelines use an all-zero word as bubble
+        if instruction == 0x0 {
+            return "nop".to_string();
+        }
+        let text = if instruction & 0x3 == 0x3 {
+            self.standard(instruction)
+        } else {
+            self.compressed(instruction as u16 as u32)
+        };
+        text.unwrap_or_else(|| "unknown".to_string())
+    }
+
+    fn standard(&self, instruction: u32) -> Option<String> {
+        let opcode = instruction & 0x7F;
+        let rd = (instruction >> 7) & 0x1F;
+        let funct3 = (instruction >> 12) & 0x7;
+        let rs1 = (instruction >> 15) & 0x1F;
+        let rs2 = (instruction >> 20) & 0x1F;
+        let funct7 = instruction >> 25;
+        let imm_i = sext(instruction >> 20, 12);
+        let imm_s = sext((funct7 << 5) | rd, 12);
+        let imm_b = sext(
+            ((instruction >> 31) << 12)
+                | (((instruction >> 7) & 0x1) << 11)
+                | (((instruction >> 25) & 0x3F) << 5)
+                | (((instruction >> 8) & 0xF) << 1),
+            13,
+        );
+        let imm_j = sext(
+            ((instruction >> 31) << 20)
+                | (((instruction >> 12) & 0xFF) << 12)
+                | (((instruction >> 20) & 0x1) << 11)
+                | (((instruction >> 21) & 0x3FF) << 1),
+            21,
+        );
+        let upper = instruction >> 12;
+
+        let text = match opcode {
+            0x37 => format!("lui {}, 0x{upper:X}", reg(rd)),
+            0x17 => format!("auipc {}, 0x{upper:X}", reg(rd)),
+            0x6F if rd == 0 => format!("j {}", self.target(imm_j)),
+            0x6F => format!("jal {}, {}", reg(rd), self.target(imm_j)),
+            0x67 if funct3 == 0 => match (rd, rs1, imm_i) {
+                (0, 1, 0) => "ret".to_string(),
+                (0, _, 0) => format!("jr {}", reg(rs1)),
+                _ => format!("jalr {}, {imm_i}({})", reg(rd), reg(rs1)),
+            },
+            0x63 => {
+                let mnemonic = ["beq", "bne", "", "", "blt", "bge", "bltu", "bgeu"][funct3 as usize];
+                if mnemonic.is_empty() {
+                    return None;
+                }
+                format!("{mnemonic} {}, {}, {}", reg(rs1), reg(rs2), self.target(imm_b))
+            }
+            0x03 => {
+                let mnemonic = ["lb", "lh", "lw", "ld", "lbu", "lhu", "lwu", ""][funct3 as usize];
+                if mnemonic.is_empty() {
+                    return None;
+                }
+                format!("{mnemonic} {}, {imm_i}({})", reg(rd), reg(rs1))
+            }
+            0x23 => {
+                let mnemonic = ["sb", "sh", "sw", "sd"].get(funct3 as usize)?;
+                format!("{mnemonic} {}, {imm_s}({})", reg(rs2), reg(rs1))
+            }
+            0x13 => match funct3 {
+                0x0 if instruction == 0x0000_0013 => "nop".to_string(),
+                0x0 if rs1 == 0 => format!("li {}, {imm_i}", reg(rd)),
+                0x0 if imm_i == 0 => format!("mv {}, {}", reg(rd), reg(rs1)),
+                0x1 if funct7 >> 1 == 0 => format!("slli {}, {}, {}", reg(rd), reg(rs1), (instruction >> 20) & 0x3F),
+                0x5 if funct7 >> 1 == 0x00 => format!("srli {}, {}, {}", reg(rd), reg(rs1), (instruction >> 20) & 0x3F),
+                0x5 if funct7 >> 1 == 0x10 => format!("srai {}, {}, {}", reg(rd), reg(rs1), (instruction >> 20) & 0x3F),
+                0x1 | 0x5 => return None,
+                _ => {
+                    let mnemonic = ["addi", "", "slti", "sltiu", "xori", "", "ori", "andi"][funct3 as usize];
+                    format!("{mnemonic} {}, {}, {imm_i}", reg(rd), reg(rs1))
+                }
+            },
+            0x1B => match (funct3, funct7) {
+                (0x0, _) => format!("addiw {}, {}, {imm_i}", reg(rd), reg(rs1)),
+                (0x1, 0x00) => format!("slliw {}, {}, {rs2}", reg(rd), reg(rs1)),
+                (0x5, 0x00) => format!("srliw {}, {}, {rs2}", reg(rd), reg(rs1)),
+                (0x5, 0x20) => format!("sraiw {}, {}, {rs2}", reg(rd), reg(rs1)),
+                _ => return None,
+            },
+            0x33 | 0x3B => {
+                let word = opcode == 0x3B;
+                let mnemonic = match (funct7, funct3) {
+                    (0x00, 0x0) => "add",
+                    (0x20, 0x0) => "sub",
+                    (0x00, 0x1) => "sll",
+                    (0x00, 0x2) if !word => "slt",
+                    (0x00, 0x3) if !word => "sltu",
+                    (0x00, 0x4) if !word => "xor",
+                    (0x00, 0x5) => "srl",
+                    (0x20, 0x5) => "sra",
+                    (0x00, 0x6) if !word => "or",
+                    (0x00, 0x7) if !word => "and",
+                    (0x01, 0x0) => "mul",
+                    (0x01, 0x1) if !word => "mulh",
+                    (0x01, 0x2) if !word => "mulhsu",
+                    (0x01, 0x3) if !word => "mulhu",
+                    (0x01, 0x4) => "div",
+                    (0x01, 0x5) => "divu",
+                    (0x01, 0x6) => "rem",
+                    (0x01, 0x7) => "remu",
+                    _ => return None,
+                };
+                let suffix = if word { "w" } else { "" };
+                format!("{mnemonic}{suffix} {}, {}, {}", reg(rd), reg(rs1), reg(rs2))
+            }
+            0x0F => match funct3 {
+                0x0 => "fence".to_string(),
+                0x1 => "fence.i".to_string(),
+                _ => return None,
+            },
+            0x73 => match funct3 {
+                0x0 => match instruction >> 20 {
+                    _ if rd != 0 || rs1 != 0 => return None,
+                    0x000 => "ecall".to_string(),
+                    0x001 => "ebreak".to_string(),
+                    0x102 => "sret".to_string(),
+                    0x302 => "mret".to_string(),
+                    0x105 => "wfi".to_string(),
+                    _ => return None,
+                },
+                0x4 => return None,
+                _ => {
+                    let mnemonic = ["", "csrrw", "csrrs", "csrrc", "", "csrrwi", "csrrsi", "csrrci"][funct3 as usize];
+                    let csr = csr_name(instruction >> 20);
+                    if funct3 >= 0x5 {
+                        format!("{mnemonic} {}, {csr}, {rs1}", reg(rd))
+                    } else {
+                        format!("{mnemonic} {}, {csr}, {}", reg(rd), reg(rs1))
+                    }
+                }
+            },
+            0x2F => {
+                let width = match funct3 {
+                    0x2 => "w",
+                    0x3 => "d",
+                    _ => return None,
+                };
+                let ordering = match (instruction >> 25) & 0x3 {
+                    0x0 => "",
+                    0x1 => ".rl",
+                    0x2 => ".aq",
+                    _ => ".aqrl",
+                };
+                match instruction >> 27 {
+                    0x02 if rs2 == 0 => format!("lr.{width}{ordering} {}, ({})", reg(rd), reg(rs1)),
+                    funct5 => {
+                        let mnemonic = match funct5 {
+                            0x03 => "sc",
+                            0x01 => "amoswap",
+                            0x00 => "amoadd",
+                            0x04 => "amoxor",
+                            0x0C => "amoand",
+                            0x08 => "amoor",
+                            0x10 => "amomin",
+                            0x14 => "amomax",
+                            0x18 => "amominu",
+                            0x1C => "amomaxu",
+                            _ => return None,
+                        };
+                        format!("{mnemonic}.{width}{ordering} {}, {}, ({})", reg(rd), reg(rs2), reg(rs1))
+                    }
+                }
+            }
+            _ => return None,
+        };
+        Some(text)
+    }
+
+    /// RV32C, the encodings that are only defined on RV64 (ex. c.ld) or need the F/D extensions are unknown
+    fn compressed(&self, instruction: u32) -> Option<String> {
+        let quadrant = instruction & 0x3;
+        let funct3 = (instruction >> 13) & 0x7;
+        let bit = |position: u32| (instruction >> position) & 0x1;
+        let bits = |high: u32, low: u32| (instruction >> low) & ((1 << (high - low + 1)) - 1);
+        // full register fields of quadrant 2 and of c.addi/c.li/c.lui
+        let rd = bits(11, 7);
+        let rs2 = bits(6, 2);
+        // compressed register fields
+        let rd_c = bits(4, 2);
+        let rs1_c = bits(9, 7);
+        // 6-bit immediate of c.addi, c.li, c.andi and the shifts
+        let imm6 = (bit(12) << 5) | bits(6, 2);
+        // offsets of the word loads and stores
+        let offset_lw = (bit(5) << 6) | (bits(12, 10) << 3) | (bit(6) << 2);
+        let offset_j = sext(
+            (bit(12) << 11)
+                | (bit(11) << 4)
+                | (bits(10, 9) << 8)
+                | (bit(8) << 10)
+                | (bit(7) << 6)
+                | (bit(6) << 7)
+                | (bits(5, 3) << 1)
+                | (bit(2) << 5),
+            12,
+        );
+        let offset_b = sext((bit(12) << 8) | (bits(11, 10) << 3) | (bits(6, 5) << 6) | (bits(4, 3) << 1) | (bit(2) << 5), 9);
+
+        let text = match (quadrant, funct3) {
+            (0x0, 0x0) => {
+                let imm = (bits(10, 7) << 6) | (bits(12, 11) << 4) | (bit(5) << 3) | (bit(6) << 2);
+                if imm == 0 {
+                    return None;
+                }
+                format!("c.addi4spn {}, sp, {imm}", creg(rd_c))
+            }
+            (0x0, 0x2) => format!("c.lw {}, {offset_lw}({})", creg(rd_c), creg(rs1_c)),
+            (0x0, 0x6) => format!("c.sw {}, {offset_lw}({})", creg(rd_c), creg(rs1_c)),
+            (0x1, 0x0) if rd == 0 => "c.nop".to_string(),
+            (0x1, 0x0) => format!("c.addi {}, {}", reg(rd), sext(imm6, 6)),
+            (0x1, 0x1) => format!("c.jal {}", self.target(offset_j)),
+            (0x1, 0x2) => format!("c.li {}, {}", reg(rd), sext(imm6, 6)),
+            (0x1, 0x3) if rd == 2 => {
+                let imm = (bit(12) << 9) | (bits(4, 3) << 7) | (bit(5) << 6) | (bit(2) << 5) | (bit(6) << 4);
+                format!("c.addi16sp sp, {}", sext(imm, 10))
+            }
+            (0x1, 0x3) if imm6 != 0 => format!("c.lui {}, 0x{:X}", reg(rd), sext(imm6, 6) as u32 & 0xF_FFFF),
+            (0x1, 0x4) => match bits(11, 10) {
+                0x0 => format!("c.srli {}, {imm6}", creg(rs1_c)),
+                0x1 => format!("c.srai {}, {imm6}", creg(rs1_c)),
+                0x2 => format!("c.andi {}, {}", creg(rs1_c), sext(imm6, 6)),
+                _ if bit(12) == 0 => {
+                    let mnemonic = ["c.sub", "c.xor", "c.or", "c.and"][bits(6, 5) as usize];
+                    format!("{mnemonic} {}, {}", creg(rs1_c), creg(rd_c))
+                }
+                _ => return None,
+            },
+            (0x1, 0x5) => format!("c.j {}", self.target(offset_j)),
+            (0x1, 0x6) => format!("c.beqz {}, {}", creg(rs1_c), self.target(offset_b)),
+            (0x1, 0x7) => format!("c.bnez {}, {}", creg(rs1_c), self.target(offset_b)),
+            (0x2, 0x0) => format!("c.slli {}, {imm6}", reg(rd)),
+            (0x2, 0x2) if rd != 0 => {
+                let offset = (bits(3, 2) << 6) | (bit(12) << 5) | (bits(6, 4) << 2);
+                format!("c.lwsp {}, {offset}(sp)", reg(rd))
+            }
+            (0x2, 0x4) => match (bit(12), rd, rs2) {
+                (0, 0, _) => return None,
+                (0, _, 0) => format!("c.jr {}", reg(rd)),
+                (0, _, _) => format!("c.mv {}, {}", reg(rd), reg(rs2)),
+                (1, 0, 0) => "c.ebreak".to_string(),
+                (1, _, 0) => format!("c.jalr {}", reg(rd)),
+                _ => format!("c.add {}, {}", reg(rd), reg(rs2)),
+            },
+            (0x2, 0x6) => {
+                let offset = (bits(8, 7) << 6) | (bits(12, 9) << 2);
+                format!("c.swsp {}, {offset}(sp)", reg(rs2))
+            }
+            _ => return None,
+        };
+        Some(text)
+    }
+}
@@ -0,0 +1,266 @@
+//! Flattened Device Tree generation for the memory map configured on a `RiscCore`
+//! The blob follows the devicetree specification (version 17) and can be handed to firmware or a kernel in a1 at reset
+
+use crate::risc_soc::memory_management_unit::{Address, MemoryDeviceType};
+use crate::risc_soc::risc_soc::RiscCore;
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_END: u32 = 0x9;
+const FDT_HEADER_SIZE: usize = 40;
+
+/// register of the RISC-V calling convention used to pass the DTB address at boot
+pub const DTB_ADDRESS_REG: usize = 11; //a1
+pub const HART_ID_REG: usize = 10; //a0
+
+const CPU_INTC_PHANDLE: u32 = 1;
+const PLIC_PHANDLE: u32 = 2;
+
+/// same timebase as the QEMU virt machine
+pub const TIMEBASE_FREQUENCY: u32 = 10_000_000;
+
+#[derive(Default)]
+pub struct FdtBuilder {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+}
+
+impl FdtBuilder {
+    pub fn begin_node(&mut self, name: &str) {
+        self.structure.extend_from_slice(&FDT_BEGIN_NODE.to_be_bytes());
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.align();
+    }
+
+    pub fn end_node(&mut self) {
+        self.structure.extend_from_slice(&FDT_END_NODE.to_be_bytes());
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) {
+        let name_offset = self.string_offset(name);
+        self.structure.extend_from_slice(&FDT_PROP.to_be_bytes());
+        self.structure.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.structure.extend_from_slice(&name_offset.to_be_bytes());
+        self.structure.extend_from_slice(value);
+        self.align();
+    }
+
+    pub fn property_empty(&mut self, name: &str) {
+        self.property(name, &[]);
+    }
+
+    pub fn property_u32(&mut self, name: &str, value: u32) {
+        self.property(name, &value.to_be_bytes());
+    }
+
+    pub fn property_cells(&mut self, name: &str, cells: &[u32]) {
+        let bytes: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+        self.property(name, &bytes);
+    }
+
+    pub fn property_string(&mut self, name: &str, value: &str) {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        self.property(name, &bytes);
+    }
+
+    /// reg property for a parent node with #address-cells = <2> and #size-cells = <2>
+    pub fn property_reg(&mut self, start: Address, end: Address) {
+        let size = end - start;
+        self.property_cells(
+            "reg",
+            &[(start >> 32) as u32, start as u32, (size >> 32) as u32, size as u32],
+        );
+    }
+
+    fn string_offset(&mut self, name: &str) -> u32 {
+        // reuse names already present in the strings block
+        let needle: Vec<u8> = name.bytes().chain(std::iter::once(0)).collect();
+        let mut offset = 0;
+        for string in self.strings.split_inclusive(|byte| *byte == 0) {
+            if string == needle.as_slice() {
+                return offset as u32;
+            }
+            offset += string.len();
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(&needle);
+        offset
+    }
+
+    fn align(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+
+    /// assemble header, memory reservation map, structure and strings blocks
+    pub fn finish(mut self) -> Vec<u8> {
+        self.structure.extend_from_slice(&FDT_END.to_be_bytes());
+        // empty memory reservation map: a single terminating entry
+        let reserve_map = [0u8; 16];
+        let off_mem_rsvmap = FDT_HEADER_SIZE;
+        let off_dt_struct = off_mem_rsvmap + reserve_map.len();
+        let off_dt_strings = off_dt_struct + self.structure.len();
+        let total_size = off_dt_strings + self.strings.len();
+
+        let mut blob = Vec::with_capacity(total_size);
+        for field in [
+            FDT_MAGIC,
+            total_size as u32,
+            off_dt_struct as u32,
+            off_dt_strings as u32,
+            off_mem_rsvmap as u32,
+            17, //version
+            16, //last compatible version
+            0,  //boot cpu id
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ] {
+            blob.extend_from_slice(&field.to_be_bytes());
+        }
+        blob.extend_from_slice(&reserve_map);
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+        blob
+    }
+}
+
+/// describe the CPU, the memories and the devices mapped in the MMU of the given core
+pub fn generate_dtb(core: &RiscCore, isa: &str) -> Vec<u8> {
+    let mmu = core.mmu.read().unwrap();
+    let memory_map = mmu.memory_map();
+    let has_plic = memory_map.iter().any(|(memory_type, _, _)| *memory_type == MemoryDeviceType::PLIC);
+
+    let mut fdt = FdtBuilder::default();
+    fdt.begin_node("");
+    fdt.property_u32("#address-cells", 2);
+    fdt.property_u32("#size-cells", 2);
+    fdt.property_string("compatible", "riscv-on-rust");
+    fdt.property_string("model", "riscv-on-rust,soc");
+
+    let console = memory_map.iter().find(|(memory_type, _, _)| {
+        *memory_type == MemoryDeviceType::UART1 || *memory_type == MemoryDeviceType::UART0 || *memory_type == MemoryDeviceType::UART2
+    });
+    fdt.begin_node("chosen");
+    if let Some((_, start, _)) = console {
+        fdt.property_string("stdout-path", &format!("/soc/serial@{:x}", start));
+    }
+    fdt.end_node();
+
+    fdt.begin_node("cpus");
+    fdt.property_u32("#address-cells", 1);
+    fdt.property_u32("#size-cells", 0);
+    fdt.property_u32("timebase-frequency", TIMEBASE_FREQUENCY);
+    fdt.begin_node("cpu@0");
+    fdt.property_string("device_type", "cpu");
+    fdt.property_u32("reg", 0);
+    fdt.property_string("status", "okay");
+    fdt.property_string("compatible", "riscv");
+    fdt.property_string("riscv,isa", isa);
+    fdt.property_string("mmu-type", "riscv,none");
+    fdt.begin_node("interrupt-controller");
+    fdt.property_u32("#interrupt-cells", 1);
+    fdt.property_empty("interrupt-controller");
+    fdt.property_string("compatible", "riscv,cpu-intc");
+    fdt.property_u32("phandle", CPU_INTC_PHANDLE);
+    fdt.end_node();
+    fdt.end_node();
+    fdt.end_node();
+
+    // memories: L1 memories used as the main memory of an MCU, and the RAM mapped in the MMU
+    // the boot ROM and the flash are not usable as RAM by an OS, they are described as devices under /soc
+    let mut memories = vec![];
+    for cache in [&core.icache, &core.dcache].into_iter().flatten() {
+        memories.push(cache.read().unwrap().start_end_addresses());
+    }
+    for (memory_type, start, end) in &memory_map {
+        if *memory_type == MemoryDeviceType::DRAM {
+            memories.push((*start, *end));
+        }
+    }
+    for (start, end) in memories {
+        fdt.begin_node(&format!("memory@{:x}", start));
+        fdt.property_string("device_type", "memory");
+        fdt.property_reg(start, end);
+        fdt.end_node();
+    }
+
+    fdt.begin_node("soc");
+    fdt.property_u32("#address-cells", 2);
+    fdt.property_u32("#size-cells", 2);
+    fdt.property_string("compatible", "simple-bus");
+    fdt.property_empty("ranges");
+    for (memory_type, start, end) in &memory_map {
+        let (name, compatible) = match memory_type {
+            MemoryDeviceType::UART0 => ("serial", "xlnx,xps-uartlite-1.00.a"),
+            MemoryDeviceType::UART1 => ("serial", "ns16550a"),
+            MemoryDeviceType::UART2 => ("serial", "sifive,uart0"),
+            MemoryDeviceType::CLINT => ("clint", "riscv,clint0"),
+            MemoryDeviceType::PLIC => ("plic", "riscv,plic0"),
+            MemoryDeviceType::VIRTIO0 => ("virtio_mmio", "virtio,mmio"),
+            MemoryDeviceType::GPIO0 => ("gpio", "riscv-on-rust,gpio"),
+            MemoryDeviceType::SPI0 => ("spi", "riscv-on-rust,spi"),
+            MemoryDeviceType::I2C0 => ("i2c", "opencores,i2c-ocores"),
+            MemoryDeviceType::DMA0 => ("dma", "riscv-on-rust,dma"),
+            MemoryDeviceType::NET0 => ("ethernet", "riscv-on-rust,nic"),
+            MemoryDeviceType::RTC => ("rtc", "google,goldfish-rtc"),
+            MemoryDeviceType::WATCHDOG => ("watchdog", "riscv-on-rust,watchdog"),
+            MemoryDeviceType::TIMER0 => ("timer", "riscv-on-rust,timer"),
+            MemoryDeviceType::FRAMEBUFFER0 => ("framebuffer", "simple-framebuffer"),
+            MemoryDeviceType::MROM => ("rom", "riscv-on-rust,boot-rom"),
+            MemoryDeviceType::FLASH => ("flash", "cfi-flash"),
+            _ => continue,
+        };
+        fdt.begin_node(&format!("{name}@{:x}", start));
+        fdt.property_string("compatible", compatible);
+        fdt.property_reg(*start, *end);
+        match memory_type {
+            MemoryDeviceType::UART1 => {
+                fdt.property_u32("clock-frequency", 0x0038_4000);
+            }
+            MemoryDeviceType::FLASH => {
+                fdt.property_u32("bank-width", 4);
+            }
+            MemoryDeviceType::CLINT => {
+                // machine software and machine timer interrupts
+                fdt.property_cells("interrupts-extended", &[CPU_INTC_PHANDLE, 3, CPU_INTC_PHANDLE, 7]);
+            }
+            MemoryDeviceType::PLIC => {
+                fdt.property_u32("#interrupt-cells", 1);
+                fdt.property_empty("interrupt-controller");
+                fdt.property_u32("riscv,ndev", 31);
+                // machine external interrupt
+                fdt.property_cells("interrupts-extended", &[CPU_INTC_PHANDLE, 11]);
+                fdt.property_u32("phandle", PLIC_PHANDLE);
+            }
+            _ => {}
+        }
+        if let Some(id) = mmu.device_at(*start) {
+            mmu.devicetree_properties(id, &mut fdt);
+        }
+        if let (true, Some(irq)) = (has_plic, memory_type.interrupt_source()) {
+            fdt.property_u32("interrupt-parent", PLIC_PHANDLE);
+            fdt.property_u32("interrupts", irq);
+        }
+        fdt.end_node();
+    }
+    fdt.end_node();
+
+    fdt.end_node();
+    fdt.finish()
+}
+
+/// place the DTB at the given address and pass it to the boot code as done by real boot flows:
+/// a0 holds the hart id and a1 the address of the DTB
+pub fn install_dtb(core: &mut RiscCore, address: Address, isa: &str) -> usize {
+    let dtb = generate_dtb(core, isa);
+    core.init_memory(address, &dtb);
+    core.write_reg(HART_ID_REG, 0);
+    core.write_reg(DTB_ADDRESS_REG, address as crate::risc_soc::risc_soc::RiscWord);
+    tracing::info!("Installed DTB of {} bytes @{:X}", dtb.len(), address);
+    dtb.len()
+}
@@ -0,0 +1,214 @@
+use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest, MemoryResponse};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use std::sync::Mutex;
+
+/// instructions with their own energy weight, the fetch of the instruction is included in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstructionClass {
+    Alu,
+    Multiply,
+    Divide,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    Atomic,
+    /// CSR accesses, fences, environment calls and breakpoints
+    System,
+}
+
+pub const INSTRUCTION_CLASSES: usize = 9;
+
+impl InstructionClass {
+    pub const ALL: [InstructionClass; INSTRUCTION_CLASSES] = [
+        InstructionClass::Alu,
+        InstructionClass::Multiply,
+        InstructionClass::Divide,
+        InstructionClass::Load,
+        InstructionClass::Store,
+        InstructionClass::Branch,
+        InstructionClass::Jump,
+        InstructionClass::Atomic,
+        InstructionClass::System,
+    ];
+
+    pub fn of(instruction: u32) -> Self {
+        let funct3 = (instruction >> 12) & 0x7;
+        let funct7 = instruction >> 25;
+        match instruction & 0x7F {
+            // OP and OP-32 with the M extension
+            0b0110011 | 0b0111011 if funct7 == 0x1 => {
+                if funct3 < 0x4 { InstructionClass::Multiply } else { InstructionClass::Divide }
+            }
+            // scalar and vector loads and stores
+            0b0000011 | 0b0000111 => InstructionClass::Load,
+            0b0100011 | 0b0100111 => InstructionClass::Store,
+            0b1100011 => InstructionClass::Branch,
+            0b1101111 | 0b1100111 => InstructionClass::Jump,
+            0b0101111 => InstructionClass::Atomic,
+            0b1110011 | 0b0001111 => InstructionClass::System,
+            _ => InstructionClass::Alu,
+        }
+    }
+
+    /// key of the class in the energy configuration
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstructionClass::Alu => "alu",
+            InstructionClass::Multiply => "multiply",
+            InstructionClass::Divide => "divide",
+            InstructionClass::Load => "load",
+            InstructionClass::Store => "store",
+            InstructionClass::Branch => "branch",
+            InstructionClass::Jump => "jump",
+            InstructionClass::Atomic => "atomic",
+            InstructionClass::System => "system",
+        }
+    }
+}
+
+/// energy of every activity in picojoules, only meant to compare program variants on the same configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyWeights {
+    /// per retired instruction, indexed by `InstructionClass`
+    pub instructions: [f64; INSTRUCTION_CLASSES],
+    /// per data access served by the L1 memories
+    pub cache_access: f64,
+    pub dram_access: f64,
+    /// per data access to any other device (ex. flash, I/O registers)
+    pub device_access: f64,
+    /// static energy of the core per clock cycle
+    pub cycle: f64,
+}
+
+impl Default for EnergyWeights {
+    /// rough figures of a small embedded core
+    fn default() -> Self {
+        Self {
+            instructions: [4.0, 12.0, 30.0, 5.0, 5.0, 4.5, 4.5, 8.0, 6.0],
+            cache_access: 10.0,
+            dram_access: 150.0,
+            device_access: 25.0,
+            cycle: 1.0,
+        }
+    }
+}
+
+impl EnergyWeights {
+    /// read the weights from a JSON object, the missing ones keep their default value:
+    /// `{ "instructions": { "alu": 4, "load": 5 }, "cache_access": 10, "dram_access": 150, "device_access": 25, "cycle": 1 }`
+    pub fn from_json(config: &str) -> Result<Self, String> {
+        let config = json::parse(config).map_err(|e| e.to_string())?;
+        if !config.is_object() {
+            return Err("the energy configuration must be a JSON object".to_string());
+        }
+        let number = |value: &json::JsonValue, key: &str, default: f64| match value {
+            json::JsonValue::Null => Ok(default),
+            value => value.as_f64().ok_or(format!("{key} must be a number")),
+        };
+        let mut weights = Self::default();
+        for (key, value) in config["instructions"].entries() {
+            let class = InstructionClass::ALL
+                .iter()
+                .position(|class| class.name() == key)
+                .ok_or(format!("unknown instruction class: {key}"))?;
+            weights.instructions[class] = number(value, key, 0.0)?;
+        }
+        weights.cache_access = number(&config["cache_access"], "cache_access", weights.cache_access)?;
+        weights.dram_access = number(&config["dram_access"], "dram_access", weights.dram_access)?;
+        weights.device_access = number(&config["device_access"], "device_access", weights.device_access)?;
+        weights.cycle = number(&config["cycle"], "cycle", weights.cycle)?;
+        Ok(weights)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let config = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::from_json(&config)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EnergyStats {
+    /// retired instructions, indexed by `InstructionClass`
+    pub instructions: [u64; INSTRUCTION_CLASSES],
+    pub cache_accesses: u64,
+    pub dram_accesses: u64,
+    pub device_accesses: u64,
+    pub cycles: u64,
+    /// picojoules spent by the retired instructions
+    pub instruction_energy: f64,
+    /// picojoules spent by the data accesses
+    pub memory_energy: f64,
+    /// picojoules spent by the clock cycles, whatever the core did in them
+    pub static_energy: f64,
+}
+
+impl EnergyStats {
+    /// picojoules
+    pub fn total(&self) -> f64 {
+        self.instruction_energy + self.memory_energy + self.static_energy
+    }
+
+    pub fn retired(&self) -> u64 {
+        self.instructions.iter().sum()
+    }
+}
+
+/// Activity-based energy estimation, accumulating the weight of every retired instruction, data access and clock cycle
+/// Registered on a core with `RiscCore::enable_energy_model`
+#[derive(Debug)]
+pub struct EnergyModel {
+    pub weights: EnergyWeights,
+    stats: Mutex<EnergyStats>,
+}
+
+impl EnergyModel {
+    pub fn new(weights: EnergyWeights) -> Self {
+        Self { weights, stats: Mutex::new(EnergyStats::default()) }
+    }
+
+    pub fn stats(&self) -> EnergyStats {
+        *self.stats.lock().unwrap()
+    }
+
+    pub fn clear(&self) {
+        *self.stats.lock().unwrap() = EnergyStats::default();
+    }
+}
+
+impl SimulationObserver for EnergyModel {
+    fn on_cycle(&self, _core: &RiscCore, _clock_cycle: u64) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.cycles += 1;
+        stats.static_energy += self.weights.cycle;
+    }
+
+    fn on_instruction_retired(&self, _core: &RiscCore, _pc: RiscWord, instruction: u32) {
+        let class = InstructionClass::of(instruction) as usize;
+        let mut stats = self.stats.lock().unwrap();
+        stats.instructions[class] += 1;
+        stats.instruction_energy += self.weights.instructions[class];
+    }
+
+    fn on_mem_access(&self, core: &RiscCore, request: &MemoryRequest, _response: &MemoryResponse) {
+        let address = request.data_address;
+        let device = core.mmu.read().unwrap().device_at(address).map(|device| device.memory_type);
+        let cached = core.in_l1(address) || device.is_some_and(|memory_type| memory_type <= MemoryDeviceType::LLCACHE);
+        let mut stats = self.stats.lock().unwrap();
+        match device {
+            _ if cached => {
+                stats.cache_accesses += 1;
+                stats.memory_energy += self.weights.cache_access;
+            }
+            Some(MemoryDeviceType::DRAM) => {
+                stats.dram_accesses += 1;
+                stats.memory_energy += self.weights.dram_access;
+            }
+            _ => {
+                stats.device_accesses += 1;
+                stats.memory_energy += self.weights.device_access;
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse, MemoryResponseType};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const OP_BRANCH: u32 = 0b1100011;
+const OP_JAL: u32 = 0b1101111;
+const OP_JALR: u32 = 0b1100111;
+
+struct EventLogState {
+    output: Box<dyn Write + Send>,
+    /// first write that failed, the following events are dropped
+    error: Option<std::io::Error>,
+    /// last retired control flow instruction, its branch event is written once the next instruction retires
+    branch: Option<RiscWord>,
+}
+
+/// Machine-readable stream of the events of a simulation, one JSON object per line, registered with `RiscCore::enable_event_log`
+/// every object has the clock cycle of the event and its kind, the other fields depend on the kind:
+/// - `retire`: `pc`, `instruction`
+/// - `branch`: `pc`, `target` and `taken`, logged when the instruction at the target retires
+/// - `trap`: `exception`, `cause`, `tval`, `pc`
+/// - `cache_miss`: `cache` (`instruction` or `data`), `address`
+/// - `mmio`: `pc`, `device`, `address`, `size`, `write`, `data` (null if the access failed)
+///
+/// addresses and data are JSON numbers, ex. to be loaded in a notebook with `pandas.read_json(path, lines=True)`
+pub struct EventLog {
+    state: Mutex<EventLogState>,
+    events: AtomicU64,
+}
+
+impl EventLog {
+    pub fn new(output: impl Write + Send + 'static) -> Self {
+        Self { state: Mutex::new(EventLogState { output: Box::new(output), error: None, branch: None }), events: AtomicU64::new(0) }
+    }
+
+    /// events written so far
+    pub fn events(&self) -> u64 {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    /// flush the output, returning the first error met while writing the events
+    pub fn finish(&self) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match state.error.take() {
+            Some(error) => Err(error),
+            None => state.output.flush(),
+        }
+    }
+
+    fn log(&self, core: &RiscCore, kind: &str, mut event: json::JsonValue) {
+        event.insert("cycle", core.clock_cycle.load(Ordering::SeqCst)).unwrap();
+        event.insert("event", kind).unwrap();
+        let mut state = self.state.lock().unwrap();
+        if state.error.is_some() {
+            return;
+        }
+        match writeln!(state.output, "{}", event.dump()) {
+            Ok(()) => _ = self.events.fetch_add(1, Ordering::Relaxed),
+            Err(error) => state.error = Some(error),
+        }
+    }
+}
+
+impl SimulationObserver for EventLog {
+    fn on_instruction_retired(&self, core: &RiscCore, pc: RiscWord, instruction: u32) {
+        let previous = {
+            let mut state = self.state.lock().unwrap();
+            let control_flow = instruction & 0x3 == 0x3 && [OP_BRANCH, OP_JAL, OP_JALR].contains(&(instruction & 0x7F));
+            std::mem::replace(&mut state.branch, control_flow.then_some(pc))
+        };
+        if let Some(branch_pc) = previous {
+            let taken = pc != branch_pc.wrapping_add(4);
+            self.log(core, "branch", json::object! { pc: branch_pc, target: pc, taken: taken });
+        }
+        self.log(core, "retire", json::object! { pc: pc, instruction: instruction });
+    }
+
+    fn on_trap(&self, core: &RiscCore, trap: &Trap) {
+        let exception = format!("{:?}", trap.exception);
+        self.log(core, "trap", json::object! { exception: exception, cause: trap.exception as u8, tval: trap.tval, pc: trap.pc });
+    }
+
+    fn on_cache_miss(&self, core: &RiscCore, address: Address, instruction: bool) {
+        let cache = if instruction { "instruction" } else { "data" };
+        self.log(core, "cache_miss", json::object! { cache: cache, address: address });
+    }
+
+    fn on_mem_access(&self, core: &RiscCore, request: &MemoryRequest, response: &MemoryResponse) {
+        let device = {
+            let mmu = core.mmu.read().unwrap();
+            if mmu.attributes(request.data_address).cacheable {
+                return;
+            }
+            match mmu.device_at(request.data_address) {
+                Some(device) if device.instance == 0 => format!("{:?}", device.memory_type),
+                Some(device) => format!("{:?}{}", device.memory_type, device.instance),
+                None => return,
+            }
+        };
+        let write = request.request_type == MemoryRequestType::WRITE;
+        let size = request.data_size as usize;
+        let completed = matches!(response.status, MemoryResponseType::CacheHit | MemoryResponseType::Valid);
+        let bytes = if write { request.data.as_deref() } else { Some(&response.data[..]) };
+        let data = match bytes.filter(|bytes| completed && bytes.len() >= size) {
+            Some(bytes) => {
+                let mut value = [0u8; 8];
+                value[..size].copy_from_slice(&bytes[..size]);
+                json::JsonValue::from(u64::from_le_bytes(value))
+            }
+            None => json::JsonValue::Null,
+        };
+        let pc = core.data_access_pc.load(Ordering::SeqCst);
+        self.log(core, "mmio", json::object! { pc: pc, device: device, address: request.data_address, size: size, write: write, data: data });
+    }
+}
@@ -0,0 +1,29 @@
+use crate::risc_soc::pipeline_stage::ClockCycle;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// clock cycles at which a part of the SoC needs the core to be simulated again (ex. the end of a transfer started by a
+/// stage), used by the event-driven runs to skip the cycles in which the core only waits for an interrupt
+#[derive(Debug, Default)]
+pub struct EventQueue(BinaryHeap<Reverse<ClockCycle>>);
+
+impl EventQueue {
+    pub fn post(&mut self, clock_cycle: ClockCycle) {
+        self.0.push(Reverse(clock_cycle));
+    }
+
+    /// earliest event after the given clock cycle, the events up to it are dropped as they already happened
+    pub fn next_after(&mut self, clock_cycle: ClockCycle) -> Option<ClockCycle> {
+        while let Some(Reverse(next)) = self.0.peek() {
+            if *next > clock_cycle {
+                return Some(*next);
+            }
+            self.0.pop();
+        }
+        None
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
@@ -0,0 +1,58 @@
+use crate::risc_soc::memory_management_unit::Address;
+use std::fmt::Display;
+
+/// synchronous exceptions, numbered as the exception codes of the mcause register in the privileged spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    InstructionAddressMisaligned = 0,
+    InstructionAccessFault = 1,
+    IllegalInstruction = 2,
+    Breakpoint = 3,
+    LoadAddressMisaligned = 4,
+    LoadAccessFault = 5,
+    StoreAddressMisaligned = 6,
+    StoreAccessFault = 7,
+    EnvironmentCallFromMMode = 11,
+}
+
+/// an exception raised by one of the pipeline stages, together with the value mtval would receive
+/// (ex. the faulting address for memory accesses) and the address of the instruction raising it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trap {
+    pub exception: Exception,
+    pub tval: Address,
+    pub pc: Address,
+}
+
+impl Exception {
+    pub fn from_cause(cause: u64) -> Option<Self> {
+        match cause {
+            0 => Some(Exception::InstructionAddressMisaligned),
+            1 => Some(Exception::InstructionAccessFault),
+            2 => Some(Exception::IllegalInstruction),
+            3 => Some(Exception::Breakpoint),
+            4 => Some(Exception::LoadAddressMisaligned),
+            5 => Some(Exception::LoadAccessFault),
+            6 => Some(Exception::StoreAddressMisaligned),
+            7 => Some(Exception::StoreAccessFault),
+            11 => Some(Exception::EnvironmentCallFromMMode),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} (cause {}) tval=0x{:X} pc=0x{:X}", self.exception, self.exception as u8, self.tval, self.pc)
+    }
+}
+
+/// how loads and stores that are not naturally aligned are handled by a core
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MisalignedAccessPolicy {
+    /// raise a load/store address misaligned exception
+    Trap,
+    /// split the access into aligned accesses, as if the memories supported misaligned accesses
+    #[default]
+    Split,
+}
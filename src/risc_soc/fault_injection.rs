@@ -0,0 +1,252 @@
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::pipeline_stage::PipelineStage;
+use crate::risc_soc::risc_soc::{ExitStatus, RiscCore, RiscWord, XLEN};
+use crate::risc_soc::run_control::{RunControl, StopReason};
+use std::ops::Range;
+use std::sync::Mutex;
+
+/// storage element in which a fault flips a bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultTarget {
+    /// integer register, flips in x0 have no effect
+    Register { index: usize, bit: u32 },
+    /// byte of a memory, ex. a line of the L1 memories or the DRAM, rewritten through the data port
+    Memory { address: Address, bit: u32 },
+    /// output register of a pipeline stage, taken modulo its width so random bits hit any stage
+    Wire { stage: usize, bit: usize },
+}
+
+/// single event upset: the bit of the target flips at the end of the clock cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub cycle: u64,
+    pub target: FaultTarget,
+}
+
+/// xorshift64*, so campaigns are reproducible from their seed without another dependency
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 of the seed, so neighbouring seeds give unrelated sequences, and the state must never be zero
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((state ^ (state >> 31)).max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// value in [range.start, range.end)
+    pub fn range(&mut self, range: Range<u64>) -> u64 {
+        assert!(range.end > range.start);
+        range.start + self.next_u64() % (range.end - range.start)
+    }
+}
+
+/// `count` faults spread over the cycles, each in a register, a byte of one of the memory ranges or the output of one of the stages
+pub fn random_faults(seed: u64, count: usize, cycles: Range<u64>, memory: &[(Address, usize)], stages: usize) -> Vec<Fault> {
+    let mut rng = Rng::new(seed);
+    let kinds = 1 + (!memory.is_empty()) as u64 + (stages > 0) as u64;
+    let mut faults: Vec<_> = (0..count)
+        .map(|_| {
+            let cycle = rng.range(cycles.clone());
+            let target = match rng.range(0..kinds) {
+                0 => FaultTarget::Register { index: rng.range(1..32) as usize, bit: rng.range(0..XLEN as u64) as u32 },
+                1 if !memory.is_empty() => {
+                    let (start, len) = memory[rng.range(0..memory.len() as u64) as usize];
+                    FaultTarget::Memory { address: start + rng.range(0..len as u64), bit: rng.range(0..8) as u32 }
+                }
+                _ => FaultTarget::Wire { stage: rng.range(0..stages as u64) as usize, bit: rng.next_u64() as usize },
+            };
+            Fault { cycle, target }
+        })
+        .collect();
+    faults.sort_by_key(|fault| fault.cycle);
+    faults
+}
+
+/// Flips the bits of the scheduled faults while the core runs, registered with `RiscCore::enable_fault_injection`
+/// registers and memories are flipped once the cycle ended, pipeline registers when the stages latch their output
+#[derive(Debug, Default)]
+pub struct FaultInjector {
+    pending: Mutex<Vec<Fault>>,
+    applied: Mutex<Vec<Fault>>,
+}
+
+impl FaultInjector {
+    pub fn new(faults: Vec<Fault>) -> Self {
+        Self { pending: Mutex::new(faults), applied: Mutex::new(vec![]) }
+    }
+
+    pub fn schedule(&self, fault: Fault) {
+        self.pending.lock().unwrap().push(fault);
+    }
+
+    /// faults injected so far, in the order they were injected, faults on unmapped memory are never injected
+    pub fn applied(&self) -> Vec<Fault> {
+        self.applied.lock().unwrap().clone()
+    }
+
+    fn take_due(&self, clock_cycle: u64, due: impl Fn(&FaultTarget) -> bool) -> Vec<Fault> {
+        let mut pending = self.pending.lock().unwrap();
+        let (taken, kept) = pending.drain(..).partition(|fault| fault.cycle == clock_cycle && due(&fault.target));
+        *pending = kept;
+        taken
+    }
+
+    /// called by the run loops once the stage latched its output for the cycle
+    pub fn flip_wires(&self, stage: &mut PipelineStage) {
+        let faults = self.take_due(stage.clock_cycle, |target| matches!(target, FaultTarget::Wire { stage: index, .. } if *index == stage.index));
+        for fault in faults {
+            let FaultTarget::Wire { bit, .. } = fault.target else { unreachable!() };
+            let width = stage.data_out.0.len() * 8;
+            if width > 0 {
+                stage.data_out.0[bit % width / 8] ^= 1 << (bit % 8);
+                self.applied.lock().unwrap().push(fault);
+            }
+        }
+    }
+}
+
+impl SimulationObserver for FaultInjector {
+    fn on_cycle(&self, core: &RiscCore, clock_cycle: u64) {
+        for fault in self.take_due(clock_cycle, |target| !matches!(target, FaultTarget::Wire { .. })) {
+            let injected = match fault.target {
+                FaultTarget::Register { index, bit } => {
+                    let (value, _) = core.read_regs(index, 0);
+                    core.write_reg(index, value ^ (1 as RiscWord) << (bit % XLEN as u32));
+                    true
+                }
+                FaultTarget::Memory { address, bit } => match core.peek_memory(address, 1) {
+                    Some(byte) => core.poke_memory(address, &[byte[0] ^ (1 << (bit % 8))]).is_some(),
+                    None => false,
+                },
+                FaultTarget::Wire { .. } => unreachable!(),
+            };
+            if injected {
+                self.applied.lock().unwrap().push(fault);
+            }
+        }
+    }
+}
+
+/// effect of the faults on the run, compared with the run without them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultEffect {
+    /// same end of the run, same registers and memory
+    Masked,
+    /// same end of the run, but other values in the registers or the compared memory
+    SilentDataCorruption,
+    /// the faulty run trapped, while the run without faults did not
+    Crash(Trap),
+    /// the faulty run used up the cycle budget, while the run without faults ended before
+    Hang,
+    /// both runs ended, with another exit status or trap
+    OtherOutcome,
+}
+
+/// divergence of a faulty run from the run without faults
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultReport {
+    pub effect: FaultEffect,
+    pub applied: Vec<Fault>,
+    /// registers holding another value at the end of the run: index, expected and faulty value
+    pub registers: Vec<(usize, RiscWord, RiscWord)>,
+    /// bytes of the compared memory holding another value: address, expected and faulty value
+    pub memory: Vec<(Address, u8, u8)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RunOutcome {
+    trap: Option<Trap>,
+    exit: Option<ExitStatus>,
+    cycle_limit: bool,
+    /// clock cycles run until the end of the run
+    cycles: u64,
+    registers: Vec<RiscWord>,
+    memory: Vec<Vec<u8>>,
+}
+
+/// Soft-error campaign on a program: every run builds a fresh core with `build` (ex. `init_core` followed by loading the program),
+/// a golden run without faults gives the expected end state, and each faulty run is classified by how it diverges from it
+pub struct FaultCampaign {
+    build: Box<dyn Fn() -> RiscCore>,
+    pub cycle_budget: u64,
+    /// memory ranges compared at the end of the runs, ex. the output buffers of the program
+    pub compared_memory: Vec<(Address, usize)>,
+    golden: Option<RunOutcome>,
+}
+
+impl FaultCampaign {
+    pub fn new(build: impl Fn() -> RiscCore + 'static, cycle_budget: u64, compared_memory: Vec<(Address, usize)>) -> Self {
+        Self { build: Box::new(build), cycle_budget, compared_memory, golden: None }
+    }
+
+    fn run_core(&self, faults: Vec<Fault>) -> (RunOutcome, Vec<Fault>) {
+        let mut core = (self.build)();
+        let injector = core.enable_fault_injection(faults);
+        let start = core.stages[0].lock().unwrap().clock_cycle;
+        let reason = core.run_sequential_with(RunControl::cycles(self.cycle_budget));
+        let outcome = RunOutcome {
+            trap: core.pending_trap(),
+            exit: core.exit_status(),
+            cycle_limit: reason == StopReason::CycleLimit,
+            cycles: core.stages[0].lock().unwrap().clock_cycle - start,
+            registers: (0..32).map(|index| core.read_regs(index, 0).0).collect(),
+            memory: self.compared_memory.iter().map(|&(start, len)| core.peek_memory(start, len).unwrap_or_default()).collect(),
+        };
+        (outcome, injector.applied())
+    }
+
+    /// run the program with the faults and compare its end with the golden run
+    pub fn run(&mut self, faults: Vec<Fault>) -> FaultReport {
+        self.golden();
+        let golden = self.golden.as_ref().unwrap();
+        let (faulty, applied) = self.run_core(faults);
+        let registers: Vec<_> = (0..32)
+            .filter(|&index| golden.registers[index] != faulty.registers[index])
+            .map(|index| (index, golden.registers[index], faulty.registers[index]))
+            .collect();
+        let mut memory = vec![];
+        for (range, (expected, actual)) in self.compared_memory.iter().zip(golden.memory.iter().zip(&faulty.memory)) {
+            for (offset, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                if expected != actual {
+                    memory.push((range.0 + offset as Address, *expected, *actual));
+                }
+            }
+        }
+        let effect = match faulty.trap {
+            Some(trap) if golden.trap.is_none() => FaultEffect::Crash(trap),
+            _ if faulty.cycle_limit && !golden.cycle_limit => FaultEffect::Hang,
+            _ if (faulty.trap, faulty.exit) != (golden.trap, golden.exit) => FaultEffect::OtherOutcome,
+            _ if registers.is_empty() && memory.is_empty() => FaultEffect::Masked,
+            _ => FaultEffect::SilentDataCorruption,
+        };
+        FaultReport { effect, applied, registers, memory }
+    }
+
+    /// the golden run is only run once
+    fn golden(&mut self) -> &RunOutcome {
+        if self.golden.is_none() {
+            self.golden = Some(self.run_core(vec![]).0);
+        }
+        self.golden.as_ref().unwrap()
+    }
+
+    /// `runs` runs with a single random fault each, drawn from the seed over the cycles of the golden run
+    pub fn run_random(&mut self, seed: u64, runs: usize) -> Vec<FaultReport> {
+        let cycles = self.golden().cycles.max(1);
+        let stages = (self.build)().stages.len();
+        let faults = random_faults(seed, runs, 0..cycles, &self.compared_memory, stages);
+        faults.into_iter().map(|fault| self.run(vec![fault])).collect()
+    }
+}
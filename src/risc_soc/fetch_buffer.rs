@@ -0,0 +1,105 @@
+use crate::risc_soc::memory_management_unit::Address;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrefetchStats {
+    /// fetches of a line that was neither held nor being prefetched (ex. the target of a taken branch)
+    pub misses: u64,
+    /// lines requested ahead of the fetch by the sequential prefetcher
+    pub prefetches: u64,
+    /// prefetched lines the fetch actually reached
+    pub useful_prefetches: u64,
+    /// clock cycles the fetch waited for a line
+    pub stall_cycles: u64,
+}
+
+impl PrefetchStats {
+    /// share of the prefetched lines that were used
+    pub fn accuracy(&self) -> f64 {
+        if self.prefetches == 0 { 0.0 } else { self.useful_prefetches as f64 / self.prefetches as f64 }
+    }
+
+    /// share of the lines reached by the fetch that were prefetched instead of missing
+    pub fn coverage(&self) -> f64 {
+        let lines = self.useful_prefetches + self.misses;
+        if lines == 0 { 0.0 } else { self.useful_prefetches as f64 / lines as f64 }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FetchLine {
+    address: Address,
+    /// clock cycles before the line arrives from memory
+    ready_in: u64,
+    prefetched: bool,
+    /// the fetch already reached this line
+    used: bool,
+}
+
+/// timing model of the fetch queue in front of the instruction memory, the instructions themselves are still read from it
+/// the queue holds the line being fetched followed by the next sequential lines, requested one after the other
+/// a fetch outside the queue (ex. a taken branch) drops it and waits for the whole latency of the line
+pub struct FetchBuffer {
+    line_size: usize,
+    /// lines held by the queue, the one being fetched included
+    depth: usize,
+    /// clock cycles taken to bring a line from memory
+    latency: u64,
+    lines: VecDeque<FetchLine>,
+    pub stats: PrefetchStats,
+}
+
+impl FetchBuffer {
+    pub fn new(line_size: usize, depth: usize, latency: u64) -> Self {
+        assert!(line_size.is_power_of_two() && line_size >= 4);
+        assert!(depth > 0);
+        Self { line_size, depth, latency, lines: VecDeque::with_capacity(depth), stats: PrefetchStats::default() }
+    }
+
+    /// drop the lines in flight, the statistics keep counting
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    /// clock cycle of the fetch at the given PC, false if its line did not arrive yet
+    pub fn fetch(&mut self, pc: Address) -> bool {
+        for line in self.lines.iter_mut() {
+            line.ready_in = line.ready_in.saturating_sub(1);
+        }
+        let address = pc & !(self.line_size as Address - 1);
+        match self.lines.iter().position(|line| line.address == address) {
+            // the lines before it were passed by the fetch
+            Some(index) => {
+                self.lines.drain(..index);
+            }
+            None => {
+                self.stats.misses += 1;
+                self.lines.clear();
+                self.lines.push_back(FetchLine { address, ready_in: self.latency, prefetched: false, used: true });
+            }
+        }
+        let line = self.lines.front_mut().unwrap();
+        if line.prefetched && !line.used {
+            self.stats.useful_prefetches += 1;
+        }
+        line.used = true;
+        let ready = line.ready_in == 0;
+
+        // the next lines are requested as soon as there is room for them
+        while self.lines.len() < self.depth {
+            let last = *self.lines.back().unwrap();
+            self.lines.push_back(FetchLine {
+                address: last.address + self.line_size as Address,
+                ready_in: last.ready_in + self.latency,
+                prefetched: true,
+                used: false,
+            });
+            self.stats.prefetches += 1;
+        }
+
+        if !ready {
+            self.stats.stall_cycles += 1;
+        }
+        ready
+    }
+}
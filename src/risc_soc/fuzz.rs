@@ -0,0 +1,67 @@
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{ExitStatus, RiscCore, RiscWord};
+use crate::risc_soc::run_control::{RunControl, StopReason};
+
+/// registers receiving the length and the address of the input when the run starts
+const REG_A0: usize = 10;
+const REG_A1: usize = 11;
+
+/// how the program handled a fuzz input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FuzzOutcome {
+    /// the program requested the end of the simulation (ex. through the exit ECALL or the test finisher)
+    Exited(ExitStatus),
+    /// the program raised an exception, reported as a crash to the fuzzer
+    Crashed(Trap),
+    /// the cycle budget was used up, ex. because the input made the program loop forever
+    CycleLimit,
+}
+
+impl FuzzOutcome {
+    pub fn is_crash(&self) -> bool {
+        matches!(self, FuzzOutcome::Crashed(_))
+    }
+}
+
+/// Deterministic entry point for coverage-guided fuzzers (libFuzzer, AFL) targeting the firmware running on a core
+/// Every input runs on a core freshly built by `build` (ex. `init_core` followed by loading the program), so no state
+/// leaks from one input to the next. The input is copied to `input_address`, its length is passed in a0 and its address in a1,
+/// and the run goes on the calling thread until the program exits, traps or uses up the cycle budget
+pub struct FuzzHarness {
+    build: Box<dyn Fn() -> RiscCore>,
+    pub input_address: Address,
+    /// longer inputs are truncated, so they fit in the memory reserved for them by the program
+    pub max_input_len: usize,
+    pub cycle_budget: u64,
+}
+
+impl FuzzHarness {
+    pub fn new(build: impl Fn() -> RiscCore + 'static, input_address: Address, max_input_len: usize, cycle_budget: u64) -> Self {
+        Self { build: Box::new(build), input_address, max_input_len, cycle_budget }
+    }
+
+    /// ex. from a libFuzzer target: `if harness.run_with_input(data).is_crash() { panic!() }`
+    pub fn run_with_input(&self, input: &[u8]) -> FuzzOutcome {
+        let (outcome, _) = self.run_core_with_input(input);
+        outcome
+    }
+
+    /// same as `run_with_input`, also returning the core to inspect its state (ex. the coverage collected by its observers)
+    pub fn run_core_with_input(&self, input: &[u8]) -> (FuzzOutcome, RiscCore) {
+        let mut core = (self.build)();
+        let input = &input[..input.len().min(self.max_input_len)];
+        core.init_memory(self.input_address, input);
+        core.write_reg(REG_A0, input.len() as RiscWord);
+        core.write_reg(REG_A1, self.input_address as RiscWord);
+
+        let reason = core.run_sequential_with(RunControl::cycles(self.cycle_budget));
+        let outcome = match (core.pending_trap(), core.exit_status()) {
+            (Some(trap), _) => FuzzOutcome::Crashed(trap),
+            (None, Some(status)) => FuzzOutcome::Exited(status),
+            _ if reason == StopReason::CycleLimit => FuzzOutcome::CycleLimit,
+            _ => panic!("A fuzz run stopped for an unexpected reason: {reason:?}"),
+        };
+        (outcome, core)
+    }
+}
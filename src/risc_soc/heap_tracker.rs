@@ -0,0 +1,243 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord, zero_extend_word};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+const REG_RA: usize = 1;
+const REG_A0: usize = 10;
+const OP_JALR: u32 = 0b1100111;
+
+/// allocation function of the C library followed by `HeapTracker`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapFunction {
+    Malloc,
+    Calloc,
+    Realloc,
+    Free,
+}
+
+/// entry point of an allocation function, `first_argument` is the argument register holding its first C argument,
+/// a1 for the reentrant functions of newlib which take the reent structure first (ex. `_malloc_r(reent, size)`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapHook {
+    pub function: HeapFunction,
+    pub address: Address,
+    pub first_argument: usize,
+}
+
+/// block of the heap still allocated, with the call site of the allocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    pub address: Address,
+    pub size: u64,
+    /// `0x80000010 function+0x8` notation of the traces
+    pub site: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// successful calls of malloc, calloc and realloc
+    pub allocations: u64,
+    /// calls of free with an allocated block, and blocks released by realloc
+    pub frees: u64,
+    /// allocations returning NULL
+    pub failed_allocations: u64,
+    /// calls of free with a pointer that is not allocated, ex. a double free
+    pub invalid_frees: u64,
+    /// bytes allocated by the program right now and at most, as requested, without the overhead of the allocator
+    pub current_bytes: u64,
+    pub peak_bytes: u64,
+}
+
+/// call of a hooked function waiting for its return to the caller
+#[derive(Debug)]
+struct PendingCall {
+    function: HeapFunction,
+    arguments: [RiscWord; 2],
+    return_address: RiscWord,
+}
+
+#[derive(Debug, Default)]
+struct HeapState {
+    /// a0..a2 and ra before the instruction retiring last, the arguments of a function are read when its first instruction retires
+    registers: [RiscWord; 4],
+    pending: Option<PendingCall>,
+    live: BTreeMap<Address, Allocation>,
+    stats: HeapStats,
+}
+
+/// Heap profiler of the guest, following the calls of its allocation functions, registered with `RiscCore::enable_heap_tracker`
+/// The functions are found from the symbols of newlib (`HeapTracker::newlib`) or given by address, ex. for a custom allocator,
+/// and the calls they make to each other (ex. realloc calling malloc) are not counted. At the end of the simulation, `report`
+/// gives the allocations, the peak usage of the heap and the blocks never freed
+#[derive(Debug, Default)]
+pub struct HeapTracker {
+    hooks: Vec<HeapHook>,
+    state: Mutex<HeapState>,
+}
+
+impl HeapTracker {
+    /// function taking its arguments from a0 as in the C calling convention
+    pub fn with_hook(self, function: HeapFunction, address: Address) -> Self {
+        self.with_argument_hook(function, address, REG_A0)
+    }
+
+    /// reentrant function of newlib, taking the reent structure in a0 and its arguments from a1
+    pub fn with_reentrant_hook(self, function: HeapFunction, address: Address) -> Self {
+        self.with_argument_hook(function, address, REG_A0 + 1)
+    }
+
+    fn with_argument_hook(mut self, function: HeapFunction, address: Address, first_argument: usize) -> Self {
+        assert!(self.hooks.iter().all(|hook| hook.address != address), "Two heap functions hooked at 0x{address:X}");
+        self.hooks.push(HeapHook { function, address, first_argument });
+        self
+    }
+
+    /// hooks on the allocation functions of newlib in the symbols of the loaded ELF, the reentrant ones (`_malloc_r`...)
+    /// when present since malloc and the other functions call them, so the calls of the library itself are seen too
+    pub fn newlib(core: &RiscCore) -> Result<Self, String> {
+        let functions = [
+            (HeapFunction::Malloc, "malloc"),
+            (HeapFunction::Calloc, "calloc"),
+            (HeapFunction::Realloc, "realloc"),
+            (HeapFunction::Free, "free"),
+        ];
+        let mut tracker = Self::default();
+        let reentrant = core.symbol_address("_malloc_r").is_some();
+        for (function, name) in functions {
+            tracker = match (reentrant, core.symbol_address(&format!("_{name}_r")), core.symbol_address(name)) {
+                (true, Some(address), _) => tracker.with_reentrant_hook(function, address),
+                (false, _, Some(address)) => tracker.with_hook(function, address),
+                _ => tracker,
+            };
+        }
+        if !tracker.hooks.iter().any(|hook| hook.function == HeapFunction::Malloc) {
+            return Err("no malloc or _malloc_r in the symbols of the program".to_string());
+        }
+        Ok(tracker)
+    }
+
+    pub fn hooks(&self) -> &[HeapHook] {
+        &self.hooks
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        self.state.lock().unwrap().stats
+    }
+
+    /// blocks allocated and not freed yet, by address, which are the leaks of the program once it finished
+    pub fn live_allocations(&self) -> Vec<Allocation> {
+        self.state.lock().unwrap().live.values().cloned().collect()
+    }
+
+    /// summary of the heap usage followed by the blocks that were never freed
+    pub fn report(&self) -> String {
+        let stats = self.stats();
+        let leaks = self.live_allocations();
+        let mut report = format!(
+            "Heap: {} allocations ({} failed), {} frees ({} invalid), peak {} bytes, {} bytes in {} blocks never freed\n",
+            stats.allocations,
+            stats.failed_allocations,
+            stats.frees,
+            stats.invalid_frees,
+            stats.peak_bytes,
+            stats.current_bytes,
+            leaks.len()
+        );
+        for leak in leaks {
+            report += &format!("  {} bytes @{:X} allocated from {}\n", leak.size, leak.address, leak.site);
+        }
+        report
+    }
+
+    fn call(&self, core: &RiscCore, state: &mut HeapState, call: PendingCall, result: RiscWord) {
+        let [first, second] = call.arguments;
+        let site = call.return_address.wrapping_sub(4);
+        let site = match core.lookup_symbol(site as Address) {
+            Some(symbol) => format!("0x{site:08X} {symbol}"),
+            None => format!("0x{site:08X}"),
+        };
+        match call.function {
+            HeapFunction::Malloc => state.allocate(result, first, site),
+            HeapFunction::Calloc => state.allocate(result, first.wrapping_mul(second), site),
+            // a failed realloc keeps the old block, a realloc to 0 bytes may free it and return NULL
+            HeapFunction::Realloc => {
+                if first != 0 && (result != 0 || second == 0) {
+                    state.free(first, &site);
+                }
+                if result != 0 || second != 0 {
+                    state.allocate(result, second, site);
+                }
+            }
+            HeapFunction::Free => {
+                if first != 0 {
+                    state.free(first, &site);
+                }
+            }
+        }
+    }
+}
+
+impl HeapState {
+    fn allocate(&mut self, address: RiscWord, size: RiscWord, site: String) {
+        if address == 0 {
+            self.stats.failed_allocations += 1;
+            return;
+        }
+        self.stats.allocations += 1;
+        self.stats.current_bytes += zero_extend_word(size);
+        self.stats.peak_bytes = self.stats.peak_bytes.max(self.stats.current_bytes);
+        self.live.insert(address as Address, Allocation { address: address as Address, size: zero_extend_word(size), site });
+    }
+
+    fn free(&mut self, address: RiscWord, site: &str) {
+        match self.live.remove(&(address as Address)) {
+            Some(allocation) => {
+                self.stats.frees += 1;
+                self.stats.current_bytes -= allocation.size;
+            }
+            None => {
+                self.stats.invalid_frees += 1;
+                tracing::warn!("Free of 0x{address:X} from {site}, which is not an allocated block");
+            }
+        }
+    }
+}
+
+/// target of a return (a JALR linking no register, ex. `ret`), from the registers as they are when it retires
+fn return_target(core: &RiscCore, instruction: u32) -> Option<RiscWord> {
+    let rd = (instruction >> 7) & 0x1F;
+    if instruction & 0x7F != OP_JALR || rd != 0 {
+        return None;
+    }
+    let rs1 = ((instruction >> 15) & 0x1F) as usize;
+    let offset = (instruction.cast_signed() >> 20) as RiscWord;
+    Some(core.registers.read_regs(rs1, 0).0.wrapping_add(offset) & !1)
+}
+
+impl SimulationObserver for HeapTracker {
+    fn on_instruction_retired(&self, core: &RiscCore, pc: RiscWord, instruction: u32) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.is_none()
+            && let Some(hook) = self.hooks.iter().find(|hook| hook.address == pc as Address)
+        {
+            let index = hook.first_argument - REG_A0;
+            let arguments = [state.registers[index], state.registers[index + 1]];
+            // ra of the call, also when the function was reached by a tail call of a wrapper (ex. malloc jumping to _malloc_r)
+            let return_address = state.registers[3];
+            state.pending = Some(PendingCall { function: hook.function, arguments, return_address });
+        }
+        // the result is taken when the jump back to the caller retires, before the caller may overwrite a0
+        // which may be the first instruction of the function, ex. a free doing nothing
+        let returned = state.pending.as_ref().is_some_and(|call| return_target(core, instruction) == Some(call.return_address));
+        if returned {
+            let call = state.pending.take().unwrap();
+            let result = core.registers.read_regs(REG_A0, 0).0;
+            self.call(core, &mut state, call, result);
+        }
+        let (a0, a1) = core.registers.read_regs(REG_A0, REG_A0 + 1);
+        let (a2, ra) = core.registers.read_regs(REG_A0 + 2, REG_RA);
+        state.registers = [a0, a1, a2, ra];
+    }
+}
@@ -0,0 +1,129 @@
+//! Parsers for the text based image formats produced by objcopy and most flashing tools
+//! Both return the data records as (address, bytes) chunks and the start address record if there is one
+
+use crate::risc_soc::load_error::LoadError;
+use crate::risc_soc::memory_management_unit::Address;
+
+pub struct ParsedImage {
+    pub chunks: Vec<(Address, Vec<u8>)>,
+    pub entry: Option<Address>,
+}
+
+fn format_error(line: usize, reason: &str) -> LoadError {
+    LoadError::Format { line, reason: reason.to_string() }
+}
+
+fn parse_hex_bytes(line: &str, line_number: usize) -> Result<Vec<u8>, LoadError> {
+    if !line.len().is_multiple_of(2) || !line.is_ascii() {
+        return Err(format_error(line_number, "odd number of hex digits"));
+    }
+    (0..line.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&line[i..i + 2], 16)
+                .map_err(|_| format_error(line_number, "invalid hex digits"))
+        })
+        .collect()
+}
+
+/// Intel HEX: `:LLAAAATT<data>CC` with 16-bit addresses extended by segment (02) and linear (04) address records
+pub fn parse_intel_hex(content: &str) -> Result<ParsedImage, LoadError> {
+    let mut chunks = vec![];
+    let mut entry = None;
+    let mut base: Address = 0;
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| format_error(line_number, "record does not start with ':'"))?;
+        let bytes = parse_hex_bytes(record, line_number)?;
+        if bytes.len() < 5 {
+            return Err(format_error(line_number, "record is too short"));
+        }
+        let checksum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if checksum != 0 {
+            return Err(format_error(line_number, "wrong checksum"));
+        }
+
+        let length = bytes[0] as usize;
+        if bytes.len() != length + 5 {
+            return Err(format_error(line_number, "wrong record length"));
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as Address;
+        let data = &bytes[4..4 + length];
+        let expected_length = match bytes[3] {
+            0x02 | 0x04 => 2,
+            0x03 | 0x05 => 4,
+            _ => 0,
+        };
+        if data.len() < expected_length {
+            return Err(format_error(line_number, "address record is too short"));
+        }
+        match bytes[3] {
+            0x00 => chunks.push((base + offset, data.to_vec())),
+            0x01 => break,
+            0x02 => base = (u16::from_be_bytes([data[0], data[1]]) as Address) << 4,
+            0x03 => {
+                // CS:IP start address
+                let segment = u16::from_be_bytes([data[0], data[1]]) as Address;
+                let ip = u16::from_be_bytes([data[2], data[3]]) as Address;
+                entry = Some((segment << 4) + ip);
+            }
+            0x04 => base = (u16::from_be_bytes([data[0], data[1]]) as Address) << 16,
+            0x05 => entry = Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as Address),
+            _ => return Err(format_error(line_number, "unknown record type")),
+        }
+    }
+    Ok(ParsedImage { chunks, entry })
+}
+
+/// Motorola S-record: `S<type><count><address><data><checksum>`, S1/S2/S3 carry data and S9/S8/S7 the start address
+pub fn parse_srec(content: &str) -> Result<ParsedImage, LoadError> {
+    let mut chunks = vec![];
+    let mut entry = None;
+    for (index, line) in content.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() < 4 || !line.starts_with('S') || !line.is_ascii() {
+            return Err(format_error(line_number, "record does not start with 'S'"));
+        }
+        let record_type = line.as_bytes()[1];
+        let bytes = parse_hex_bytes(&line[2..], line_number)?;
+        let count = bytes[0] as usize;
+        if bytes.len() != count + 1 {
+            return Err(format_error(line_number, "wrong record length"));
+        }
+        let checksum = bytes[..count].iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        if !checksum != bytes[count] {
+            return Err(format_error(line_number, "wrong checksum"));
+        }
+
+        let address_size = match record_type {
+            b'0' | b'1' | b'5' | b'9' => 2,
+            b'2' | b'6' | b'8' => 3,
+            b'3' | b'7' => 4,
+            _ => return Err(format_error(line_number, "unknown record type")),
+        };
+        if count < address_size + 1 {
+            return Err(format_error(line_number, "record is too short"));
+        }
+        let address = bytes[1..1 + address_size]
+            .iter()
+            .fold(0 as Address, |address, byte| (address << 8) | *byte as Address);
+        let data = &bytes[1 + address_size..count];
+        match record_type {
+            b'1' | b'2' | b'3' => chunks.push((address, data.to_vec())),
+            b'7' | b'8' | b'9' => entry = Some(address),
+            // header and record count records carry no memory content
+            _ => {}
+        }
+    }
+    Ok(ParsedImage { chunks, entry })
+}
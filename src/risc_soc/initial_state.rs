@@ -0,0 +1,135 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord, register_index};
+
+/// value preloaded into a register, either a number or the address of a symbol of the loaded ELF (ex. `__global_pointer$`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitialValue {
+    Value(RiscWord),
+    Symbol(String),
+}
+
+/// Registers and memory written before the first instruction runs, in place of a crt0 setting up the stack and global pointer
+/// or of a loader passing arguments in a0/a1. Set with `RiscCore::set_initial_state`, the registers are written again
+/// by every reset of the core since the program expects them at its entry point
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InitialState {
+    /// register index and value, written in order
+    pub registers: Vec<(usize, InitialValue)>,
+    /// start address and content of the memory ranges, written in order after the registers
+    pub memory: Vec<(Address, Vec<u8>)>,
+}
+
+impl InitialState {
+    /// register given by its ABI or architectural name, ex. "sp" or "x2"
+    pub fn with_reg(self, name: &str, value: RiscWord) -> Self {
+        self.with_value(name, InitialValue::Value(value))
+    }
+
+    /// register holding the address of a symbol, resolved when the state is applied so the ELF can be loaded afterwards
+    pub fn with_reg_symbol(self, name: &str, symbol: &str) -> Self {
+        self.with_value(name, InitialValue::Symbol(symbol.to_string()))
+    }
+
+    /// stack pointer of the program, the stack grows down from this address
+    pub fn with_stack(self, top: RiscWord) -> Self {
+        self.with_reg("sp", top)
+    }
+
+    pub fn with_memory(mut self, address: Address, data: &[u8]) -> Self {
+        self.memory.push((address, data.to_vec()));
+        self
+    }
+
+    fn with_value(mut self, name: &str, value: InitialValue) -> Self {
+        let index = register_index(name).unwrap_or_else(|| panic!("Unknown register name {name}"));
+        assert!(index != 0, "zero cannot be preloaded");
+        self.registers.retain(|(register, _)| *register != index);
+        self.registers.push((index, value));
+        self
+    }
+
+    /// read the state from a JSON object, numbers may be given as strings to write them in hex, ex.
+    /// `{ "registers": { "sp": "0x8002_0000", "gp": "__global_pointer$", "a0": 2 }, "memory": [ { "address": "0x8001_0000", "data": [1, 2] } ] }`
+    /// a memory range holds either the bytes of `data` or the content of the binary `file`, relative to the working directory
+    pub fn from_json(config: &str) -> Result<Self, String> {
+        let config = json::parse(config).map_err(|e| e.to_string())?;
+        if !config.is_object() {
+            return Err("the initial state must be a JSON object".to_string());
+        }
+        let mut state = Self::default();
+        if let Some((key, _)) = config.entries().find(|(key, _)| !["registers", "memory"].contains(key)) {
+            return Err(format!("unknown field: {key}"));
+        }
+        if !config["registers"].is_null() && !config["registers"].is_object() {
+            return Err("registers must be an object mapping the register names to their value".to_string());
+        }
+        for (name, value) in config["registers"].entries() {
+            let index = register_index(name).filter(|index| *index != 0).ok_or(format!("unknown register: {name}"))?;
+            let value = match parse_number(value) {
+                Some(value) => InitialValue::Value(value as RiscWord),
+                None => match value.as_str() {
+                    Some(symbol) if !symbol.is_empty() => InitialValue::Symbol(symbol.to_string()),
+                    _ => return Err(format!("registers.{name} must be a number or a symbol")),
+                },
+            };
+            state.registers.retain(|(register, _)| *register != index);
+            state.registers.push((index, value));
+        }
+        if !config["memory"].is_null() && !config["memory"].is_array() {
+            return Err("memory must be an array of ranges".to_string());
+        }
+        for (index, range) in config["memory"].members().enumerate() {
+            let address = parse_number(&range["address"]).ok_or(format!("memory[{index}].address must be a number"))?;
+            let data = match (&range["data"], range["file"].as_str()) {
+                (json::JsonValue::Array(bytes), None) => bytes
+                    .iter()
+                    .map(|byte| byte.as_u8())
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or(format!("memory[{index}].data must be an array of bytes"))?,
+                (json::JsonValue::Null, Some(path)) => std::fs::read(path).map_err(|e| format!("{path}: {e}"))?,
+                _ => return Err(format!("memory[{index}] must have either data or file")),
+            };
+            state.memory.push((address as Address, data));
+        }
+        Ok(state)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let config = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::from_json(&config)
+    }
+
+    /// write the registers then the memory ranges, as seen by the program (through the caches and store buffer)
+    pub fn apply(&self, core: &RiscCore) -> Result<(), String> {
+        self.apply_registers(core)?;
+        for (address, data) in &self.memory {
+            if core.poke_memory(*address, data).is_none() {
+                return Err(format!("cannot write {} bytes at 0x{address:X}, the range is not fully mapped or writable", data.len()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn apply_registers(&self, core: &RiscCore) -> Result<(), String> {
+        for (index, value) in &self.registers {
+            let value = match value {
+                InitialValue::Value(value) => *value,
+                InitialValue::Symbol(symbol) => core.symbol_address(symbol).ok_or(format!("unknown symbol: {symbol}"))? as RiscWord,
+            };
+            core.registers.write_reg(*index, value);
+        }
+        Ok(())
+    }
+}
+
+/// JSON number (negative ones in two's complement), or string holding a decimal or 0x prefixed hex number with optional underscores
+fn parse_number(value: &json::JsonValue) -> Option<u64> {
+    if let Some(number) = value.as_u64().or(value.as_i64().map(|number| number as u64)) {
+        return Some(number);
+    }
+    let text = value.as_str()?.replace('_', "");
+    match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
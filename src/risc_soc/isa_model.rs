@@ -0,0 +1,62 @@
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::sim_error::SimErrorKind;
+
+/// operation carried by the pipeline for an instruction, as produced by the decoder of an ISA model
+/// the fields follow the RISC-V formats the pipeline registers are laid out for, other ISAs map their encodings onto them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MicroOp {
+    /// only interpreted by the `execute` of the model, the stages just carry it along
+    pub opcode: u8,
+    pub func3: u8,
+    pub func7: u8,
+    pub rd: u8,
+    pub rs1: u8,
+    pub rs2: u8,
+    /// sign extended immediate
+    pub imm: RiscWord,
+    /// the result is written to rd
+    pub reg_write: bool,
+    /// memory side operation performed by MEM, one of the `MEM_*` constants of the MCU decoder
+    pub mem_op: u8,
+    /// the fetch is redirected to the target of the micro-op once it reaches MEM, if it takes the jump
+    pub branch_or_jump: bool,
+    /// source registers actually read, for the hazard detection of ID
+    pub uses_rs1: bool,
+    pub uses_rs2: bool,
+}
+
+/// values a micro-op executes with, once forwarded from the later stages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Operands {
+    pub rs1: RiscWord,
+    pub rs2: RiscWord,
+    pub pc: RiscWord,
+    /// set while MEM holds the pipeline, the micro-op is executed again later so it must not read or write the CSRs yet
+    pub hold: bool,
+}
+
+/// outcome of a micro-op in EX, passed down to MEM
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MicroOpResult {
+    /// written to rd, or the address of a memory access
+    pub value: RiscWord,
+    /// address the fetch restarts from when the jump is taken
+    pub target: RiscWord,
+    pub take_jump: bool,
+    /// raised once the micro-op reaches MEM, where it can no longer be flushed by an older branch
+    pub exception: Option<Exception>,
+}
+
+/// decode and execute logic of an instruction set, so the pipeline, caches, MMU and tracing of a core can run
+/// another ISA or a custom RISC-V extension without copying the stage functions
+pub trait IsaModel: Send + Sync {
+    /// micro-ops of an instruction in program order, or why the model cannot run it
+    fn decode(&self, instruction: u32) -> Result<Vec<MicroOp>, SimErrorKind>;
+
+    /// result of a micro-op, the CSRs and the rest of the architectural state are reached through the core
+    fn execute(&self, op: &MicroOp, operands: Operands, core: &RiscCore) -> MicroOpResult;
+
+    /// bring the architectural state the model keeps outside of the core back to its power-on value, called on a reset of the core
+    fn reset(&self) {}
+}
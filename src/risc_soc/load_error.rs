@@ -0,0 +1,64 @@
+use crate::risc_soc::memory_management_unit::Address;
+use std::fmt::Display;
+
+/// what decides the parts of an ELF copied to memory by `RiscCore::load_binary`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ElfLoadMode {
+    /// the sections named like code, data or bss (.text, .data, .sdata, .rodata, .bss, .sbss), at their address
+    #[default]
+    Sections,
+    /// the PT_LOAD program headers at their physical address, as a debugger or boot loader does: every section the
+    /// linker script placed in a segment is loaded (ex. .init, .tohost) and the memory past the file size is zeroed
+    Segments,
+}
+
+/// reasons for which a program image could not be loaded into the memories of a core
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(object::read::Error),
+    /// malformed text image (Intel HEX, S-record), with the offending line
+    Format { line: usize, reason: String },
+    /// e_machine of the ELF is not EM_RISCV
+    NotRiscV(u16),
+    /// ELF class does not match the XLEN of the core
+    WrongClass { elf_bits: usize, core_bits: usize },
+    /// the cores of this SoC only run little-endian programs
+    BigEndian,
+    /// a section does not fit in the memory it should be loaded to
+    OutOfRange { name: String, address: Address, size: usize },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "could not read image: {e}"),
+            LoadError::Parse(e) => write!(f, "could not parse ELF: {e}"),
+            LoadError::Format { line, reason } => write!(f, "line {line}: {reason}"),
+            LoadError::NotRiscV(machine) => write!(f, "ELF machine type {machine} is not RISC-V"),
+            LoadError::WrongClass { elf_bits, core_bits } => {
+                write!(f, "ELF{elf_bits} image cannot run on an RV{core_bits} core")
+            }
+            LoadError::BigEndian => write!(f, "big-endian images are not supported"),
+            LoadError::OutOfRange { name, address, size } => write!(
+                f,
+                "section {name} @{:X} of {size} bytes does not fit in the target memory",
+                address
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<object::read::Error> for LoadError {
+    fn from(e: object::read::Error) -> Self {
+        LoadError::Parse(e)
+    }
+}
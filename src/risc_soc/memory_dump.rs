@@ -0,0 +1,112 @@
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse, MemoryResponseType};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::RiscCore;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// bytes shown on each line of a dump
+pub const DUMP_LINE_BYTES: usize = 16;
+/// first line of the access log
+pub const ACCESS_LOG_HEADER: &str = "cycle,pc,address,size,access,data";
+
+/// access of the data port as recorded by the access log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub cycle: u64,
+    /// instruction performing the access, buffered stores keep the PC of the last store merged into their entry
+    pub pc: Address,
+    pub address: Address,
+    pub size: usize,
+    pub write: bool,
+    /// value read or written, None if the access failed
+    pub data: Option<u64>,
+}
+
+impl MemoryAccess {
+    /// line of the access in the CSV log, values are in hexadecimal and the data as wide as the access
+    pub fn csv(&self) -> String {
+        let data = self.data.map(|data| format!("0x{data:0width$x}", width = 2 * self.size)).unwrap_or_default();
+        let access = if self.write { "W" } else { "R" };
+        format!("{},0x{:08x},0x{:08x},{},{access},{data}", self.cycle, self.pc, self.address, self.size)
+    }
+}
+
+/// every access of the data port in the order it reached memory, registered with `RiscCore::enable_access_log`
+#[derive(Debug, Default)]
+pub struct MemoryAccessLog {
+    accesses: Mutex<Vec<MemoryAccess>>,
+}
+
+impl MemoryAccessLog {
+    pub fn accesses(&self) -> Vec<MemoryAccess> {
+        self.accesses.lock().unwrap().clone()
+    }
+
+    pub fn csv(&self) -> String {
+        let mut csv = format!("{ACCESS_LOG_HEADER}\n");
+        for access in self.accesses.lock().unwrap().iter() {
+            csv.push_str(&access.csv());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn write_csv(&self, mut output: impl Write) -> std::io::Result<()> {
+        output.write_all(self.csv().as_bytes())
+    }
+
+    pub fn clear(&self) {
+        self.accesses.lock().unwrap().clear();
+    }
+}
+
+impl SimulationObserver for MemoryAccessLog {
+    fn on_mem_access(&self, core: &RiscCore, request: &MemoryRequest, response: &MemoryResponse) {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        let size = request.data_size as usize;
+        let completed = matches!(response.status, MemoryResponseType::CacheHit | MemoryResponseType::Valid);
+        let bytes = if write { request.data.as_deref() } else { Some(&response.data[..]) };
+        let data = bytes.filter(|bytes| completed && bytes.len() >= size).map(|bytes| {
+            let mut value = [0u8; 8];
+            value[..size].copy_from_slice(&bytes[..size]);
+            u64::from_le_bytes(value)
+        });
+        self.accesses.lock().unwrap().push(MemoryAccess {
+            cycle: core.clock_cycle.load(std::sync::atomic::Ordering::SeqCst),
+            pc: core.data_access_pc.load(std::sync::atomic::Ordering::SeqCst),
+            address: request.data_address,
+            size,
+            write,
+            data,
+        });
+    }
+}
+
+/// hex dump of the given (start, length) ranges as the program currently sees them, one line of 16 bytes per address
+/// the output only depends on the content of memory, so the dumps of two runs can be compared with diff
+/// bytes not mapped to a memory are shown as `--`, ranges are dumped in the given order
+pub fn hex_dump(core: &RiscCore, ranges: &[(Address, usize)]) -> String {
+    let mut dump = String::new();
+    for &(start, len) in ranges {
+        writeln!(dump, "# 0x{start:08x}..0x{:08x}", start + len as Address).unwrap();
+        for line in (0..len).step_by(DUMP_LINE_BYTES) {
+            let address = start + line as Address;
+            let count = DUMP_LINE_BYTES.min(len - line);
+            let bytes: Vec<Option<u8>> = match core.peek_memory(address, count) {
+                Some(bytes) => bytes.into_iter().map(Some).collect(),
+                None => (0..count).map(|byte| core.peek_memory(address + byte as Address, 1).map(|byte| byte[0])).collect(),
+            };
+            let hex: Vec<String> = bytes.iter().map(|byte| byte.map_or("--".to_string(), |byte| format!("{byte:02x}"))).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|byte| match byte {
+                    Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+            writeln!(dump, "{address:08x}: {:<width$} |{ascii}|", hex.join(" "), width = 3 * DUMP_LINE_BYTES - 1).unwrap();
+        }
+    }
+    dump
+}
@@ -0,0 +1,73 @@
+use crate::risc_soc::call_stack::CallStack;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::sim_error::SimErrorKind;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+
+/// bytes guarded on each side of a stack or heap, enough for the frame of a function spilling a few registers
+pub const DEFAULT_GUARD_SIZE: Address = 64;
+
+/// range [start, end) the program must never access
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuardRegion {
+    pub name: String,
+    pub start: Address,
+    pub end: Address,
+}
+
+/// Guard regions around the stack and heap of the program, registered with `RiscCore::enable_memory_guard`
+/// a load or store touching one of them stops the run with a `GuardViolation` error, giving the PC of the access and the
+/// backtrace of the guest, ex. for a stack overflow into the data of the firmware which would otherwise go unnoticed
+#[derive(Debug, Default)]
+pub struct MemoryGuard {
+    regions: Vec<GuardRegion>,
+    call_stack: Mutex<CallStack>,
+}
+
+impl MemoryGuard {
+    pub fn with_region(mut self, name: &str, start: Address, end: Address) -> Self {
+        assert!(start < end, "The guard region {name} is empty");
+        self.regions.push(GuardRegion { name: name.to_string(), start, end });
+        self
+    }
+
+    /// stack of `size` bytes growing down from `top`, guarded by `guard_size` bytes below it (overflow) and above it (underflow)
+    pub fn with_stack(self, top: Address, size: Address, guard_size: Address) -> Self {
+        let bottom = top - size;
+        self.with_region("stack overflow", bottom - guard_size, bottom).with_region("stack underflow", top, top + guard_size)
+    }
+
+    /// heap from `start` to `end`, guarded by `guard_size` bytes on each side
+    pub fn with_heap(self, start: Address, end: Address, guard_size: Address) -> Self {
+        self.with_region("heap underflow", start - guard_size, start).with_region("heap overflow", end, end + guard_size)
+    }
+
+    pub fn regions(&self) -> &[GuardRegion] {
+        &self.regions
+    }
+}
+
+impl SimulationObserver for MemoryGuard {
+    fn on_instruction_retired(&self, _core: &RiscCore, pc: RiscWord, instruction: u32) {
+        self.call_stack.lock().unwrap().retire(pc, instruction);
+    }
+
+    fn on_mem_access(&self, core: &RiscCore, request: &MemoryRequest, _response: &MemoryResponse) {
+        let start = request.data_address;
+        let end = start + request.data_size as Address;
+        let Some(region) = self.regions.iter().find(|region| start < region.end && region.start < end) else {
+            return;
+        };
+        let pc = core.data_access_pc.load(Ordering::SeqCst) as RiscWord;
+        let backtrace = self.call_stack.lock().unwrap().backtrace(core, pc);
+        let kind = SimErrorKind::GuardViolation {
+            region: region.name.clone(),
+            address: start,
+            write: request.request_type == MemoryRequestType::WRITE,
+            backtrace,
+        };
+        core.raise_sim_error(kind, Some(pc));
+    }
+}
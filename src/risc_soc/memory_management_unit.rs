@@ -1,6 +1,12 @@
 use ahash::AHashMap;
-use crate::risc_soc::risc_soc::WordSize;
+use crate::risc_soc::clock::{ClockCrossingStats, ClockDomain, DEFAULT_SYNCHRONIZER_STAGES, HostClock};
+use crate::risc_soc::csr::{InterruptLines, MIP_MEIP};
+use crate::risc_soc::dtb::FdtBuilder;
+use crate::risc_soc::risc_soc::{RiscWord, WordSize};
 use std::{fmt::Debug};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 pub type Address = u64;
 
@@ -21,6 +27,8 @@ pub enum MemoryResponseType {
     NotReadable,
     NotExecutable,
     WrongMemoryMap,
+    /// the request itself is malformed (ex. a store without data), reported by the core as an error of the model
+    BadRequest(String),
 }
 
 /// Some generic memory types, such as cache, DRAM, UART, and a generic IOMMU which can handle other IOs
@@ -36,26 +44,345 @@ pub enum MemoryDeviceType {
     DRAM,
     FLASH, 
     UART0,
+    UART1,
+    UART2,
+    GPIO0,
+    SPI0,
+    I2C0,
+    VIRTIO0,
+    FRAMEBUFFER0,
+    DMA0,
+    NET0,
+    RTC,
+    WATCHDOG,
+    TIMER0,
+    CLINT,
+    PLIC,
     DEBUG,
     IOMMU //reference to other IO units
 }
 
-/// TODO: add methods for converting u8/u16/u32 etc to data vec for memory request
+impl MemoryDeviceType {
+    /// source of the interrupt line of the device on the PLIC, following the numbering of the QEMU virt machine
+    /// the UART Lite has no counterpart there and takes a free source, so it can be told apart from the 16550
+    pub fn interrupt_source(&self) -> Option<u32> {
+        match self {
+            MemoryDeviceType::VIRTIO0 => Some(1),
+            MemoryDeviceType::UART1 => Some(10),
+            MemoryDeviceType::UART2 => Some(12),
+            MemoryDeviceType::UART0 => Some(13),
+            MemoryDeviceType::GPIO0 => Some(3),
+            MemoryDeviceType::DMA0 => Some(4),
+            MemoryDeviceType::NET0 => Some(5),
+            MemoryDeviceType::RTC => Some(11),
+            MemoryDeviceType::WATCHDOG => Some(6),
+            MemoryDeviceType::TIMER0 => Some(7),
+            _ => None,
+        }
+    }
+}
+
+/// device mapped in the MMU: its type, and the order in which it was added among the devices of the same type
+/// a type converts to its first instance, so SoCs with a single device of each type can keep using the types
+#[derive(Debug, Eq, Hash, PartialEq, PartialOrd, Clone, Copy)]
+pub struct DeviceId {
+    pub memory_type: MemoryDeviceType,
+    pub instance: usize,
+}
+
+impl DeviceId {
+    pub fn new(memory_type: MemoryDeviceType, instance: usize) -> Self {
+        Self { memory_type, instance }
+    }
+}
+
+impl From<MemoryDeviceType> for DeviceId {
+    fn from(memory_type: MemoryDeviceType) -> Self {
+        Self::new(memory_type, 0)
+    }
+}
+
+/// data accesses of the cores to a device, counted by the MMU, the accesses served by the private L1 memories never reach it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeviceAccessStats {
+    pub reads: u64,
+    pub writes: u64,
+    /// bytes read and written
+    pub bytes: u64,
+    /// clock cycles the cores waited on the device, as reported by its access latency
+    pub latency_cycles: u64,
+}
+
+/// lookups of the device holding an address, a hit is served by the cache of the last ranges used without searching the memory map
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LookupStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl DeviceAccessStats {
+    pub fn accesses(&self) -> u64 {
+        self.reads + self.writes
+    }
+
+    /// clock cycles waited per access
+    pub fn average_latency(&self) -> f64 {
+        if self.accesses() == 0 { 0.0 } else { self.latency_cycles as f64 / self.accesses() as f64 }
+    }
+
+    fn add(&mut self, other: &DeviceAccessStats) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.bytes += other.bytes;
+        self.latency_cycles += other.latency_cycles;
+    }
+}
+
+/// accesses allowed on a device registered in the MMU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const R: Self = Self { read: true, write: false, execute: false };
+    pub const RW: Self = Self { read: true, write: true, execute: false };
+    pub const RX: Self = Self { read: true, write: false, execute: true };
+    pub const RWX: Self = Self { read: true, write: true, execute: true };
+
+    /// status of a denied access, None if the access is allowed
+    /// fetches need execute permission only, as the instruction is never seen by a load
+    pub fn check(&self, request_type: MemoryRequestType, fetch: bool) -> Option<MemoryResponseType> {
+        match request_type {
+            MemoryRequestType::READ if fetch && !self.execute => Some(MemoryResponseType::NotExecutable),
+            MemoryRequestType::READ if !fetch && !self.read => Some(MemoryResponseType::NotReadable),
+            MemoryRequestType::WRITE if !self.write => Some(MemoryResponseType::NotWrittable),
+            _ => None,
+        }
+    }
+}
+
+/// byte a debugger could not read or write, a write is checked over its whole range first so nothing was written
+#[derive(Debug, PartialEq)]
+pub struct MemoryAccessError {
+    pub address: Address,
+    pub status: MemoryResponseType,
+}
+
+impl std::fmt::Display for MemoryAccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot access memory at 0x{:X}: {:?}", self.address, self.status)
+    }
+}
+
+impl std::error::Error for MemoryAccessError {}
+
+/// physical memory attributes (PMA) of a region, as in the privileged spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAttributes {
+    pub permissions: Permissions,
+    /// accesses may be served by the caches, otherwise they always reach the device
+    pub cacheable: bool,
+    /// accesses have no side effects, so they can be repeated or split (unlike a read of a UART FIFO)
+    pub idempotent: bool,
+    /// AMOs and LR/SC are supported
+    pub atomic: bool,
+}
+
+impl MemoryAttributes {
+    /// main memory: RAM, ROM and flash
+    pub const MEMORY: Self = Self { permissions: Permissions::RWX, cacheable: true, idempotent: true, atomic: true };
+    /// registers of I/O devices
+    pub const IO: Self = Self { permissions: Permissions::RW, cacheable: false, idempotent: false, atomic: false };
+
+    pub fn with_permissions(self, permissions: Permissions) -> Self {
+        Self { permissions, ..self }
+    }
+}
+
+/// bytes carried by a memory request or response, at most a double word
+/// they are stored inline so no allocation is made for every access of the core
+#[derive(Clone, Copy, Default)]
+pub struct MemoryData {
+    bytes: [u8; WordSize::DOUBLE as usize],
+    len: usize,
+}
+
+impl MemoryData {
+    pub fn new(data: &[u8]) -> Self {
+        let mut memory_data = Self::zeroed(data.len());
+        memory_data.copy_from_slice(data);
+        memory_data
+    }
+
+    pub fn zeroed(len: usize) -> Self {
+        assert!(len <= WordSize::DOUBLE as usize, "memory accesses are at most a double word");
+        Self { bytes: [0u8; WordSize::DOUBLE as usize], len }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    /// same as `Vec::resize`, used by registers which accept writes narrower than their width
+    pub fn resize(&mut self, len: usize, value: u8) {
+        assert!(len <= WordSize::DOUBLE as usize, "memory accesses are at most a double word");
+        if len > self.len {
+            self.bytes[self.len..len].fill(value);
+        }
+        self.len = len;
+    }
+}
+
+impl Deref for MemoryData {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.bytes[..self.len]
+    }
+}
+
+impl DerefMut for MemoryData {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.bytes[..self.len]
+    }
+}
+
+/// only the bytes up to the length are compared, the ones left behind by `truncate` are not part of the data
+impl PartialEq for MemoryData {
+    fn eq(&self, other: &Self) -> bool {
+        self.deref() == other.deref()
+    }
+}
+
+impl Eq for MemoryData {}
+
+impl Debug for MemoryData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.deref().fmt(f)
+    }
+}
+
 #[derive(Clone,Debug)]
 pub struct MemoryRequest {
     pub request_type: MemoryRequestType,
     pub data_address: Address,
     pub data_size: WordSize,
-    pub data: Option<Vec<u8>>,
+    pub data: Option<MemoryData>,
+}
+
+/// typed constructors, values are laid out in memory as little-endian as required by RISC-V
+impl MemoryRequest {
+    pub fn read(address: Address, data_size: WordSize) -> Self {
+        Self { request_type: MemoryRequestType::READ, data_address: address, data_size, data: None }
+    }
+
+    pub fn read_u8(address: Address) -> Self {
+        Self::read(address, WordSize::BYTE)
+    }
+
+    pub fn read_u16(address: Address) -> Self {
+        Self::read(address, WordSize::HALF)
+    }
+
+    pub fn read_u32(address: Address) -> Self {
+        Self::read(address, WordSize::WORD)
+    }
+
+    pub fn read_u64(address: Address) -> Self {
+        Self::read(address, WordSize::DOUBLE)
+    }
+
+    pub fn write(address: Address, data: &[u8]) -> Self {
+        let data_size = match data.len() {
+            1 => WordSize::BYTE,
+            2 => WordSize::HALF,
+            4 => WordSize::WORD,
+            8 => WordSize::DOUBLE,
+            len => panic!("Cannot write {len} bytes in a single memory request!"),
+        };
+        Self {
+            request_type: MemoryRequestType::WRITE,
+            data_address: address,
+            data_size,
+            data: Some(MemoryData::new(data)),
+        }
+    }
+
+    pub fn write_u8(address: Address, value: u8) -> Self {
+        Self::write(address, &[value])
+    }
+
+    pub fn write_u16(address: Address, value: u16) -> Self {
+        Self::write(address, &value.to_le_bytes())
+    }
+
+    pub fn write_u32(address: Address, value: u32) -> Self {
+        Self::write(address, &value.to_le_bytes())
+    }
+
+    pub fn write_u64(address: Address, value: u64) -> Self {
+        Self::write(address, &value.to_le_bytes())
+    }
+
+    /// stores must carry at least the bytes of their size
+    pub fn check(&self) -> Result<(), String> {
+        if self.request_type == MemoryRequestType::READ {
+            return Ok(());
+        }
+        match &self.data {
+            Some(data) if data.len() >= self.data_size as usize => Ok(()),
+            Some(data) => Err(format!("store of {} bytes carries {} bytes of data", self.data_size as usize, data.len())),
+            None => Err("store without data".to_string()),
+        }
+    }
 }
 
-/// TODO: add methods for converting byte array back to u8/u16/u32 etc for processor
 #[derive(Debug)]
 pub struct MemoryResponse {
-    pub data: Vec<u8>,
+    pub data: MemoryData,
     pub status: MemoryResponseType
 }
 
+/// typed views of the returned bytes, the response must hold at least the bytes of the requested type
+impl MemoryResponse {
+    pub fn bad_request(reason: String) -> Self {
+        Self { data: MemoryData::default(), status: MemoryResponseType::BadRequest(reason) }
+    }
+
+    pub fn as_u8(&self) -> u8 {
+        assert!(!self.data.is_empty(), "memory response holds no data: {:?}", self.status);
+        self.data[0]
+    }
+
+    pub fn as_u16(&self) -> u16 {
+        u16::from_le_bytes(self.bytes())
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        u32::from_le_bytes(self.bytes())
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        u64::from_le_bytes(self.bytes())
+    }
+
+    fn bytes<const N: usize>(&self) -> [u8; N] {
+        assert!(
+            self.data.len() >= N,
+            "memory response holds {} bytes, but {N} were expected: {:?}",
+            self.data.len(),
+            self.status
+        );
+        self.data[..N].try_into().unwrap()
+    }
+}
+
+/// memory port used by devices that access memory on their own, such as DMA engines or virtio devices
+/// it must not route back into the MMU that holds the device, as the MMU is locked while serving the device
+pub type BusMaster = Arc<dyn Fn(MemoryRequest) -> MemoryResponse + Send + Sync>;
+
 pub trait MemoryDevice {
 
     /// minumum amount of info required for a new memory device
@@ -84,6 +411,54 @@ pub trait MemoryDevice {
     /// helper function to debug various aspects of the memory
     fn debug(&self, start_address: Address, end_address: Address) -> std::fmt::Result;
 
+    /// core clock cycles a request keeps the pipeline waiting on top of the cycle of the stage issuing it
+    /// asked once per access by the cores modeling memory timing, so the device can update its own state (ex. open DRAM rows)
+    fn access_latency(&mut self, _request: &MemoryRequest) -> u64 {
+        0
+    }
+
+    /// advance the device by the given number of rising edges of its own clock, called by the MMU at the end of every core cycle
+    /// devices not placed in a clock domain run on the core clock, so timers, FIFOs and DMA progress without being accessed
+    fn tick(&mut self, _edges: u64) {}
+
+    /// whether the device accesses the other devices at the end of this cycle, ex. a DMA engine running a transfer
+    fn wants_bus(&self) -> bool {
+        false
+    }
+
+    /// accesses of the device to the other devices, called by the MMU after ticking them all while `wants_bus` holds
+    /// the requests go through the MMU like the ones of the cores, so they are checked and counted the same way
+    fn master_bus(&mut self, _bus: &mut dyn FnMut(MemoryRequest) -> MemoryResponse) {}
+
+    /// level of the interrupt line of the device, sampled by the MMU after ticking the devices
+    fn pending_irq(&self) -> bool {
+        false
+    }
+
+    /// levels of the interrupt lines of the other devices, bit N for the source N, given by the MMU after sampling them
+    /// only interrupt controllers (ex. the PLIC) use them
+    fn interrupt_sources(&mut self, _levels: u64) {}
+
+    /// rising edges of its own clock after which the device changes its state or interrupt line by itself (ex. a timer reaching its compare value)
+    /// `None` when nothing happens until it is accessed, devices acting in `tick` must report it for the event-driven runs to wake up in time
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+
+    /// bring the registers of the device back to their power-on value, memory arrays are only zeroed when `clear_memory` is set
+    /// non-volatile memories (ex. flash) keep their content either way
+    fn reset(&mut self, _clear_memory: bool) {}
+
+    /// name of the register accessed by the request and the value read or written, ex. "THR <= 0x48 'H'", for the traces
+    /// called after the access was performed, devices usually describe it from their `register_map`
+    fn describe_access(&self, _request: &MemoryRequest, _value: u64) -> Option<String> {
+        None
+    }
+
+    /// properties of the node describing the device in the generated devicetree, besides its compatible, reg and interrupts
+    /// ex. the resolution and pixel format of a framebuffer
+    fn devicetree_properties(&self, _fdt: &mut FdtBuilder) {}
+
 }
 
 
@@ -92,24 +467,218 @@ pub trait MemoryDevice {
 /// For example it can be used to decide if memory requests should be forwarded to cache, RAM or an IO
 /// A process function can be passed to it, where it processes a memory request, and it can return a memory response to the CPU
 pub struct MemoryManagementUnit {
-    memmap: AHashMap<MemoryDeviceType, Box<dyn MemoryDevice + Send + Sync>>,
+    memmap: AHashMap<DeviceId, Box<dyn MemoryDevice + Send + Sync>>,
     process_fn: fn(&mut Self, MemoryRequest) -> MemoryResponse,
+    /// address ranges of the mapped devices and of their aliases sorted by start address, so a device is found with a binary search
+    ranges: Vec<MappedRange>,
+    /// extra windows on devices already mapped, kept apart so the ranges can be rebuilt when a device is added
+    aliases: Vec<MappedRange>,
+    /// last ranges hit, one entry per device, as the cores usually alternate between a few of them (ex. DRAM and a UART)
+    lookup_cache: LookupCache,
+    /// devices registered without explicit permissions allow every access
+    permissions: AHashMap<DeviceId, Permissions>,
+    /// regions with explicit attributes, the others get the attributes of the kind of device mapped there
+    pma: Vec<(Address, Address, MemoryAttributes)>,
+    /// addresses reserved by LR, keyed by the id of the hart holding the reservation
+    reservations: AHashMap<RiscWord, Address>,
+    /// devices clocked on their own, with the number of edges of their clock they were already ticked for
+    clock_domains: AHashMap<DeviceId, (ClockDomain, u64)>,
+    /// devices following the time of the host, with the number of edges of their clock they were already ticked for
+    host_clocks: AHashMap<DeviceId, (HostClock, u64)>,
+    /// core cycles elapsed, as last reported by `tick`
+    core_cycle: u64,
+    crossing_stats: ClockCrossingStats,
+    access_stats: AHashMap<DeviceId, DeviceAccessStats>,
+    /// pending interrupts of the harts receiving the interrupt lines of the devices on MEIP
+    external_interrupts: Vec<InterruptLines>,
     // TODO: add TLB
-    // TODO: add another structure to cache/hold the mapping between an address and the memory device
 }
 
+/// address range of a device in the memory map
+#[derive(Debug, Clone, Copy)]
+struct MappedRange {
+    start: Address,
+    end: Address,
+    device: DeviceId,
+    /// device address seen at `start`, the offset in the window is masked first for mirrored regions
+    device_start: Address,
+    mask: Address,
+}
+
+impl MappedRange {
+    fn contains(&self, address: Address) -> bool {
+        address >= self.start && address < self.end
+    }
+
+    fn device_address(&self, address: Address) -> Address {
+        self.device_start + ((address - self.start) & self.mask)
+    }
+}
+
+const NO_HIT: usize = usize::MAX;
+/// devices whose last hit range is remembered by the MMU
+const LOOKUP_CACHE_ENTRIES: usize = 4;
+
+/// indices in `ranges` of the last hits, replaced in round robin order, atomic so the lookups only need a shared MMU
+struct LookupCache {
+    entries: [AtomicUsize; LOOKUP_CACHE_ENTRIES],
+    next: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for LookupCache {
+    fn default() -> Self {
+        Self {
+            entries: std::array::from_fn(|_| AtomicUsize::new(NO_HIT)),
+            next: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LookupCache {
+    /// forget the hits, the indices are stale once the ranges are rebuilt
+    fn invalidate(&self) {
+        for entry in &self.entries {
+            entry.store(NO_HIT, Ordering::Relaxed);
+        }
+    }
+
+    fn insert(&self, index: usize) {
+        let slot = self.next.fetch_add(1, Ordering::Relaxed) % LOOKUP_CACHE_ENTRIES;
+        self.entries[slot].store(index, Ordering::Relaxed);
+    }
+}
+
+/// size of the naturally aligned block covered by an LR reservation
+pub const RESERVATION_GRANULE: Address = 8;
+
 impl MemoryManagementUnit {
     pub fn new(
-        memmap: AHashMap<MemoryDeviceType, Box<dyn MemoryDevice + Send + Sync>>,
+        memmap: AHashMap<DeviceId, Box<dyn MemoryDevice + Send + Sync>>,
         process_fn: fn(&mut Self, MemoryRequest) -> MemoryResponse, 
     ) -> Self {
-        Self { memmap, process_fn}
+        let mut mmu = Self {
+            memmap,
+            process_fn,
+            ranges: vec![],
+            aliases: vec![],
+            lookup_cache: LookupCache::default(),
+            permissions: AHashMap::default(),
+            pma: vec![],
+            reservations: AHashMap::default(),
+            clock_domains: AHashMap::default(),
+            host_clocks: AHashMap::default(),
+            core_cycle: 0,
+            crossing_stats: ClockCrossingStats::default(),
+            access_stats: AHashMap::default(),
+            external_interrupts: vec![],
+        };
+        mmu.rebuild_ranges();
+        mmu
     }
 
-    pub fn add_memory_device(&mut self, memory_device: Box<dyn MemoryDevice + Send + Sync>) {
-        
-        if self.memmap.contains_key(&memory_device.get_memory_type()) {
-            panic!("There is already a device of this type defined in the MMU!");
+    /// cache memories are not mapped to a specific memory range, so only the other devices are indexed
+    fn rebuild_ranges(&mut self) {
+        self.ranges = self
+            .memmap
+            .iter()
+            .filter(|(id, _)| id.memory_type > MemoryDeviceType::LLCACHE)
+            .map(|(id, device)| {
+                let (start, end) = device.start_end_addresses();
+                MappedRange { start, end, device: *id, device_start: start, mask: Address::MAX }
+            })
+            .chain(self.aliases.iter().copied())
+            .collect();
+        self.ranges.sort_by_key(|range| range.start);
+        self.lookup_cache.invalidate();
+    }
+
+    /// mapped range holding the given address, first checking the ranges of the last hits and then searching the sorted ranges
+    /// an address outside of every range is a miss, only the searches finding a range are cached
+    fn range_at(&self, address: Address) -> Option<&MappedRange> {
+        let cache = &self.lookup_cache;
+        let cached = cache.entries.iter().map(|entry| entry.load(Ordering::Relaxed));
+        if let Some(range) = cached.filter_map(|index| self.ranges.get(index)).find(|range| range.contains(address)) {
+            cache.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(range);
+        }
+        cache.misses.fetch_add(1, Ordering::Relaxed);
+        let index = self.ranges.partition_point(|range| range.start <= address);
+        if index > 0 && address < self.ranges[index - 1].end {
+            cache.insert(index - 1);
+            return Some(&self.ranges[index - 1]);
+        }
+        None
+    }
+
+    /// device holding the given address, directly or through one of its aliases
+    /// caches in the MMU are only checked when no mapped device holds the address
+    pub fn device_at(&self, address: Address) -> Option<DeviceId> {
+        if let Some(range) = self.range_at(address) {
+            return Some(range.device);
+        }
+        self.memmap
+            .iter()
+            .filter(|(id, _)| id.memory_type <= MemoryDeviceType::LLCACHE)
+            .find(|(_, device)| {
+                let (start, end) = device.start_end_addresses();
+                address >= start && address < end
+            })
+            .map(|(id, _)| *id)
+    }
+
+    /// address at which the device sees an access, only different from the given one inside an alias
+    pub fn device_address(&self, address: Address) -> Address {
+        self.range_at(address).map_or(address, |range| range.device_address(address))
+    }
+
+    /// map [start_address, end_address) onto the device too, starting from its first address (ex. an uncached alias of DRAM)
+    pub fn add_alias(&mut self, device: impl Into<DeviceId>, start_address: Address, end_address: Address) {
+        let device = device.into();
+        let (device_start, device_end) = self.device(device).start_end_addresses();
+        assert!(end_address - start_address <= device_end - device_start, "The alias is larger than the device!");
+        self.add_window(MappedRange { start: start_address, end: end_address, device, device_start, mask: Address::MAX });
+    }
+
+    /// map [start_address, end_address) onto the device, repeating its first `mask + 1` bytes over the whole window
+    /// ex. a 4 KiB device mirrored every 4 KiB in a 64 KiB window uses the mask 0xFFF, as with partially decoded address lines
+    pub fn add_mirror(&mut self, device: impl Into<DeviceId>, start_address: Address, end_address: Address, mask: Address) {
+        let device = device.into();
+        let (device_start, device_end) = self.device(device).start_end_addresses();
+        assert!((mask + 1).is_power_of_two() && mask < device_end - device_start, "The mirror mask must select a power of two sized part of the device!");
+        self.add_window(MappedRange { start: start_address, end: end_address, device, device_start, mask });
+    }
+
+    fn add_window(&mut self, window: MappedRange) {
+        assert!(window.end > window.start);
+        if self.ranges.iter().any(|range| window.start < range.end && window.end > range.start) {
+            panic!("This alias overlaps with other memory ranges already defined in the MMU!")
+        }
+        self.aliases.push(window);
+        self.rebuild_ranges();
+    }
+
+    /// properties of the mapped device in the generated devicetree, see `MemoryDevice::devicetree_properties`
+    pub fn devicetree_properties(&self, id: DeviceId, fdt: &mut FdtBuilder) {
+        self.device(id).devicetree_properties(fdt);
+    }
+
+    fn device(&self, id: DeviceId) -> &(dyn MemoryDevice + Send + Sync) {
+        match self.memmap.get(&id) {
+            Some(device) => device.as_ref(),
+            None => panic!("There is no device {id:?} in the MMU!"),
+        }
+    }
+
+    /// devices of the same type (ex. several timers) are told apart by the instance of the returned id, in order of addition
+    pub fn add_memory_device(&mut self, memory_device: Box<dyn MemoryDevice + Send + Sync>) -> DeviceId {
+        let memory_type = memory_device.get_memory_type();
+        let instance = self.memmap.keys().filter(|id| id.memory_type == memory_type).count();
+        if memory_type <= MemoryDeviceType::LLCACHE && instance > 0 {
+            panic!("There is already a cache of this type defined in the MMU!");
         }
 
         if memory_device.get_memory_type() < MemoryDeviceType::L2CACHE {
@@ -119,38 +688,395 @@ impl MemoryManagementUnit {
         if memory_device.get_memory_type() > MemoryDeviceType::LLCACHE {
             //cache memories are not mapped to a specific memory range, they just cache a specific range
             for mem in &self.memmap {
-                if *mem.0 > MemoryDeviceType::LLCACHE && 
-                    memory_device.start_end_addresses().0 >= mem.1.start_end_addresses().0 &&
-                    memory_device.start_end_addresses().0 <= mem.1.start_end_addresses().1 {
+                // end addresses are exclusive, so devices can be mapped back to back
+                if mem.0.memory_type > MemoryDeviceType::LLCACHE && 
+                    memory_device.start_end_addresses().0 < mem.1.start_end_addresses().1 &&
+                    mem.1.start_end_addresses().0 < memory_device.start_end_addresses().1 {
                         panic!("This memory device overlaps with other memory ranges already defined in the MMU!")
                 } 
             }
         }
         
-        self.memmap.insert(memory_device.get_memory_type(), memory_device);
+        let (start, end) = memory_device.start_end_addresses();
+        if self.aliases.iter().any(|alias| start < alias.end && end > alias.start) {
+            panic!("This memory device overlaps with an alias already defined in the MMU!")
+        }
+        let id = DeviceId::new(memory_type, instance);
+        self.memmap.insert(id, memory_device);
+        self.rebuild_ranges();
+        id
+    }
+
+    /// same as `add_memory_device`, restricting the accesses allowed on the device (ex. RX for a ROM, RW for MMIO)
+    pub fn add_memory_device_with_permissions(
+        &mut self,
+        memory_device: Box<dyn MemoryDevice + Send + Sync>,
+        permissions: Permissions,
+    ) -> DeviceId {
+        let id = self.add_memory_device(memory_device);
+        self.permissions.insert(id, permissions);
+        id
+    }
+
+    /// swap a mapped device for another one of the same type at the same addresses, ex. a UART bound to another terminal
+    /// the permissions, clock domain and aliases of the device are kept
+    pub fn replace_memory_device(&mut self, device: impl Into<DeviceId>, memory_device: Box<dyn MemoryDevice + Send + Sync>) {
+        let device = device.into();
+        let Some(current) = self.memmap.get(&device) else {
+            panic!("There is no {device:?} to replace in the MMU!");
+        };
+        assert_eq!(current.get_memory_type(), memory_device.get_memory_type());
+        assert_eq!(current.start_end_addresses(), memory_device.start_end_addresses());
+        self.memmap.insert(device, memory_device);
+    }
+
+    pub fn permissions(&self, device: impl Into<DeviceId>) -> Permissions {
+        self.permissions.get(&device.into()).copied().unwrap_or(Permissions::RWX)
+    }
+
+    /// describe [start_address, end_address) with its own attributes instead of the ones of the device mapped there
+    pub fn add_pma_region(&mut self, start_address: Address, end_address: Address, attributes: MemoryAttributes) {
+        assert!(end_address > start_address);
+        if self.pma.iter().any(|&(start, end, _)| start_address < end && end_address > start) {
+            panic!("This region overlaps with other regions already described in the PMA table!")
+        }
+        self.pma.push((start_address, end_address, attributes));
+    }
+
+    /// explicit attributes of the region, otherwise main memory for the memories and the addresses the MMU does not map
+    /// (ex. the L1 memories of an MCU) and I/O for all the other devices, with the permissions the device was added with
+    pub fn attributes(&self, address: Address) -> MemoryAttributes {
+        if let Some(&(_, _, attributes)) = self.pma.iter().find(|&&(start, end, _)| address >= start && address < end) {
+            return attributes;
+        }
+        let Some(device) = self.device_at(address) else {
+            return MemoryAttributes::MEMORY;
+        };
+        let attributes = match device.memory_type {
+            MemoryDeviceType::L2CACHE
+            | MemoryDeviceType::LLCACHE
+            | MemoryDeviceType::MROM
+            | MemoryDeviceType::DRAM
+            | MemoryDeviceType::FLASH => MemoryAttributes::MEMORY,
+            _ => MemoryAttributes::IO,
+        };
+        attributes.with_permissions(self.permissions(device))
+    }
+
+    fn denied_access(&self, request: &MemoryRequest, fetch: bool) -> Option<MemoryResponse> {
+        let status = self.attributes(request.data_address).permissions.check(request.request_type, fetch)?;
+        Some(MemoryResponse { data: MemoryData::default(), status })
+    }
+
+    /// clock a device on its own, it is then ticked for every edge of the domain clock
+    /// accesses of the core to a device outside of the core domain go through synchronizers in both directions
+    pub fn set_clock_domain(&mut self, device: impl Into<DeviceId>, domain: ClockDomain) {
+        let device = device.into();
+        assert!(self.memmap.contains_key(&device), "There is no device {device:?} in the MMU!");
+        let edges = domain.edges_at(self.core_cycle);
+        self.clock_domains.insert(device, (domain, edges));
+    }
+
+    /// tick a device with the time of the host, it is still only ticked at the end of a core cycle and ignores its clock domain
+    /// accesses to it are not synchronized, as it stands for a timer of the real platform rather than another clock of the simulation
+    pub fn set_host_clock(&mut self, device: impl Into<DeviceId>, clock: HostClock) {
+        let device = device.into();
+        assert!(self.memmap.contains_key(&device), "There is no device {device:?} in the MMU!");
+        self.host_clocks.insert(device, (clock, 0));
+    }
+
+    pub fn clock_domain(&self, device: impl Into<DeviceId>) -> Option<&ClockDomain> {
+        self.clock_domains.get(&device.into()).map(|(domain, _)| domain)
+    }
+
+    /// end of a core clock cycle: tick the devices for the edges their clock had in the meantime, then sample their interrupt lines
+    /// harts sharing the MMU all report the same cycle, so a device is only ticked once per edge
+    pub fn tick(&mut self, core_cycle: u64) {
+        let core_edges = (core_cycle + 1).saturating_sub(self.core_cycle);
+        if core_edges == 0 {
+            return;
+        }
+        self.core_cycle += core_edges;
+        for (id, device) in self.memmap.iter_mut() {
+            if let Some((clock, ticked_edges)) = self.host_clocks.get_mut(id) {
+                let edges = clock.edges();
+                if edges > *ticked_edges {
+                    device.tick(edges - *ticked_edges);
+                    *ticked_edges = edges;
+                }
+                continue;
+            }
+            match self.clock_domains.get_mut(id) {
+                Some((domain, ticked_edges)) => {
+                    let edges = domain.edges_at(self.core_cycle);
+                    if edges > *ticked_edges {
+                        device.tick(edges - *ticked_edges);
+                        *ticked_edges = edges;
+                    }
+                }
+                None => device.tick(core_edges),
+            }
+        }
+
+        // the bus masters take their turn once every device was ticked, out of the map while they access the others
+        let masters: Vec<DeviceId> = self.memmap.iter().filter(|(_, device)| device.wants_bus()).map(|(id, _)| *id).collect();
+        for id in masters {
+            let mut master = self.memmap.remove(&id).unwrap();
+            master.master_bus(&mut |request| {
+                if self.device_at(request.data_address) == Some(id) {
+                    return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+                }
+                self.process_memory_request(request)
+            });
+            self.memmap.insert(id, master);
+        }
+
+        // a PLIC routes the lines to the harts by itself, without one every line raises MEIP
+        let plic = DeviceId::from(MemoryDeviceType::PLIC);
+        if self.memmap.contains_key(&plic) {
+            let levels = self
+                .memmap
+                .iter()
+                .filter(|(_, device)| device.pending_irq())
+                .filter_map(|(id, _)| id.memory_type.interrupt_source())
+                .fold(0u64, |levels, source| levels | 1 << source);
+            self.memmap.get_mut(&plic).unwrap().interrupt_sources(levels);
+        }
+        let pending = self.memmap.values().any(|device| device.pending_irq());
+        for interrupt_lines in &self.external_interrupts {
+            if pending {
+                interrupt_lines.fetch_or(MIP_MEIP, Ordering::SeqCst);
+            } else {
+                interrupt_lines.fetch_and(!MIP_MEIP, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// reset every device and drop the LR reservations of all harts, devices keep their clock domain
+    /// host clocks restart from the reset, so a timer following the host time counts again from its power-on value
+    pub fn reset(&mut self, clear_memory: bool) {
+        for device in self.memmap.values_mut() {
+            device.reset(clear_memory);
+        }
+        self.reservations.clear();
+        // the cores count their cycles from the reset again, and so do the clock domains
+        self.core_cycle = 0;
+        for (_, ticked_edges) in self.clock_domains.values_mut() {
+            *ticked_edges = 0;
+        }
+        for (clock, ticked_edges) in self.host_clocks.values_mut() {
+            *ticked_edges = clock.edges();
+        }
+        for interrupt_lines in &self.external_interrupts {
+            interrupt_lines.fetch_and(!MIP_MEIP, Ordering::SeqCst);
+        }
+    }
+
+    /// raise MEIP of the hart while any device has its interrupt line asserted, as a PLIC routing every source to it
+    pub fn connect_external_interrupts(&mut self, interrupt_lines: InterruptLines) {
+        self.external_interrupts.push(interrupt_lines);
+    }
+
+    /// core cycles after the last ticked one until the first device event, devices following the host time may change at any cycle
+    pub fn next_event(&self) -> Option<u64> {
+        self.memmap
+            .iter()
+            .filter_map(|(id, device)| {
+                if self.host_clocks.contains_key(id) {
+                    return Some(1);
+                }
+                let edges = device.next_event()?;
+                match self.clock_domains.get(id) {
+                    Some((domain, _)) => Some(domain.core_cycles_for(self.core_cycle, edges).max(1)),
+                    None => Some(edges.max(1)),
+                }
+            })
+            .min()
+    }
+
+    /// devices asserting their interrupt line, in order of address
+    pub fn pending_irqs(&self) -> Vec<DeviceId> {
+        let mut pending: Vec<_> = self.memmap.iter().filter(|(_, device)| device.pending_irq()).collect();
+        pending.sort_by_key(|(_, device)| device.start_end_addresses().0);
+        pending.into_iter().map(|(id, _)| *id).collect()
+    }
+
+    pub fn crossing_stats(&self) -> ClockCrossingStats {
+        self.crossing_stats
+    }
+
+    pub fn lookup_stats(&self) -> LookupStats {
+        LookupStats {
+            hits: self.lookup_cache.hits.load(Ordering::Relaxed),
+            misses: self.lookup_cache.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// data accesses of every device reached by the cores, in order of address
+    pub fn access_stats(&self) -> Vec<(DeviceId, DeviceAccessStats)> {
+        let mut stats: Vec<_> = self.access_stats.iter().map(|(id, stats)| (*id, *stats)).collect();
+        stats.sort_by_key(|(id, _)| self.memmap.get(id).map(|device| device.start_end_addresses().0));
+        stats
+    }
+
+    /// data accesses of the cores to all the devices
+    pub fn total_access_stats(&self) -> DeviceAccessStats {
+        let mut total = DeviceAccessStats::default();
+        self.access_stats.values().for_each(|stats| total.add(stats));
+        total
+    }
+
+    pub fn clear_access_stats(&mut self) {
+        self.access_stats.clear();
+    }
+
+    /// the request waits for the synchronizer of the device clock, then the response for the one of the core clock
+    fn record_crossing(&mut self, address: Address) {
+        let Some(device) = self.device_at(address) else {
+            return;
+        };
+        if let Some((domain, _)) = self.clock_domains.get(&device).filter(|(domain, _)| !domain.is_core()) {
+            let request_cycles = domain.core_cycles_for(self.core_cycle, DEFAULT_SYNCHRONIZER_STAGES);
+            self.crossing_stats.crossings += 1;
+            self.crossing_stats.synchronizer_cycles += request_cycles + DEFAULT_SYNCHRONIZER_STAGES;
+        }
+    }
+
+    /// address ranges of all the devices mapped in the MMU, sorted by start address
+    pub fn memory_map(&self) -> Vec<(MemoryDeviceType, Address, Address)> {
+        let mut map: Vec<_> = self
+            .memmap
+            .iter()
+            .map(|(id, device)| {
+                let (start, end) = device.start_end_addresses();
+                (id.memory_type, start, end)
+            })
+            .collect();
+        map.sort_by_key(|(_, start, _)| *start);
+        map
     }
 
     pub fn init_section_into_memory(&mut self, address: Address, data: &[u8]) {
-        for device in &mut self.memmap{
-            let (start_address, end_address) = device.1.start_end_addresses();
-            if address >= start_address && address < end_address {
-                assert!(address + data.len() as Address <= end_address);   
-                device.1.init_mem(address, data); 
+        if let Some(device_id) = self.device_at(address) {
+            let address = self.device_address(address);
+            let device = self.memmap.get_mut(&device_id).unwrap();
+            assert!(address + data.len() as Address <= device.start_end_addresses().1);
+            device.init_mem(address, data);
+        }
+    }
+
+    /// read through the read only path of the device holding the address, so no device state is changed (ex. a UART FIFO)
+    pub fn peek(&self, mut request: MemoryRequest) -> MemoryResponse {
+        match self.device_at(request.data_address) {
+            Some(device_id) => {
+                request.data_address = self.device_address(request.data_address);
+                self.memmap.get(&device_id).unwrap().read_request(request)
             }
-        }   
+            None => MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress },
+        }
     }
 
     /// the main logic of the MemoryManagementUnit  whould be handled in its process_fn, includinf thigs such as address translation
-    pub fn process_memory_request(&mut self, memory_request: MemoryRequest) -> MemoryResponse {
+    /// loads and stores are checked against the read and write permissions of the target device
+    /// accesses to an alias reach the process_fn at the address of the device, so reservations also match across aliases
+    /// stores break the LR reservations of all harts on the written block
+    /// the accesses reaching a device are counted in its access statistics
+    /// malformed requests are answered with `BadRequest` without reaching the devices
+    pub fn process_memory_request(&mut self, mut memory_request: MemoryRequest) -> MemoryResponse {
+        if let Err(reason) = memory_request.check() {
+            return MemoryResponse::bad_request(reason);
+        }
+        if let Some(response) = self.denied_access(&memory_request, false) {
+            return response;
+        }
+        if let Some(device) = self.device_at(memory_request.data_address) {
+            let stats = self.access_stats.entry(device).or_default();
+            match memory_request.request_type {
+                MemoryRequestType::READ => stats.reads += 1,
+                MemoryRequestType::WRITE => stats.writes += 1,
+            }
+            stats.bytes += memory_request.data_size as u64;
+        }
+        memory_request.data_address = self.device_address(memory_request.data_address);
+        if memory_request.request_type == MemoryRequestType::WRITE && !self.reservations.is_empty() {
+            self.invalidate_reservations(memory_request.data_address, memory_request.data_size as Address);
+        }
+        if !self.clock_domains.is_empty() {
+            self.record_crossing(memory_request.data_address);
+        }
+        (self.process_fn)(self, memory_request)
+    }
+
+    /// access to a device register named after the device and the register, ex. "UART0.THR <= 0x48 'H'"
+    /// None for failed accesses and for the devices without a description of the register
+    pub fn describe_access(&self, request: &MemoryRequest, response: &MemoryResponse) -> Option<String> {
+        if response.status != MemoryResponseType::Valid && response.status != MemoryResponseType::CacheHit {
+            return None;
+        }
+        let device_id = self.device_at(request.data_address)?;
+        let data = match request.request_type {
+            MemoryRequestType::WRITE => request.data.as_deref()?,
+            MemoryRequestType::READ => &response.data[..],
+        };
+        let mut value = [0u8; 8];
+        let len = data.len().min(8);
+        value[..len].copy_from_slice(&data[..len]);
+        let request = MemoryRequest { data_address: self.device_address(request.data_address), ..request.clone() };
+        let description = self.memmap.get(&device_id)?.describe_access(&request, u64::from_le_bytes(value))?;
+        Some(match device_id.instance {
+            0 => format!("{:?}.{description}", device_id.memory_type),
+            instance => format!("{:?}[{instance}].{description}", device_id.memory_type),
+        })
+    }
+
+    /// latency of the device serving the request, unmapped addresses fault without waiting
+    pub fn access_latency(&mut self, request: &MemoryRequest) -> u64 {
+        let Some(device_id) = self.device_at(request.data_address) else {
+            return 0;
+        };
+        let request = MemoryRequest { data_address: self.device_address(request.data_address), ..request.clone() };
+        let latency = self.memmap.get_mut(&device_id).unwrap().access_latency(&request);
+        self.access_stats.entry(device_id).or_default().latency_cycles += latency;
+        latency
+    }
+
+    /// LR: register a reservation for the hart, replacing the one it held before
+    pub fn reserve(&mut self, hart_id: RiscWord, address: Address) {
+        let address = self.device_address(address);
+        self.reservations.insert(hart_id, address & !(RESERVATION_GRANULE - 1));
+    }
+
+    /// SC: true if the hart still holds a reservation on the address, the reservation is consumed either way
+    pub fn take_reservation(&mut self, hart_id: RiscWord, address: Address) -> bool {
+        let address = self.device_address(address);
+        self.reservations.remove(&hart_id) == Some(address & !(RESERVATION_GRANULE - 1))
+    }
+
+    /// also used for stores that complete in a cache and never reach the MMU
+    pub fn invalidate_reservations(&mut self, address: Address, size: Address) {
+        let first = address & !(RESERVATION_GRANULE - 1);
+        let last = (address + size - 1) & !(RESERVATION_GRANULE - 1);
+        self.reservations.retain(|_, reserved| *reserved < first || *reserved > last);
+    }
+
+    /// instruction fetches are checked against the execute permission of the target device
+    pub fn process_fetch_request(&mut self, mut memory_request: MemoryRequest) -> MemoryResponse {
+        if let Some(response) = self.denied_access(&memory_request, true) {
+            return response;
+        }
+        memory_request.data_address = self.device_address(memory_request.data_address);
+        if !self.clock_domains.is_empty() {
+            self.record_crossing(memory_request.data_address);
+        }
         (self.process_fn)(self, memory_request)
     }
 
 }
 
 impl Debug for MemoryManagementUnit {
-    fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for mem in &self.memmap {
-            println!("{:?}: ({:X} -> {:X}) ", mem.0, mem.1.start_end_addresses().0, mem.1.start_end_addresses().1);
+            writeln!(f, "{:?}: ({:X} -> {:X}) ", mem.0, mem.1.start_end_addresses().0, mem.1.start_end_addresses().1)?;
         }
         Ok(())
     }
@@ -164,14 +1090,23 @@ impl Default for MemoryManagementUnit {
             memmap: AHashMap::default(),
             process_fn: |_self, _request| {
                 assert!(!_self.memmap.is_empty());
-                for device in &mut _self.memmap {
-                    let (start_address, end_address) = device.1.start_end_addresses();
-                    if _request.data_address >= start_address && _request.data_address < end_address {
-                        return device.1.send_data_request(_request);
-                    }
+                match _self.device_at(_request.data_address) {
+                    Some(device_id) => _self.memmap.get_mut(&device_id).unwrap().send_data_request(_request),
+                    None => MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress },
                 }
-                MemoryResponse { data: vec![], status: MemoryResponseType::InvalidAddress }
-            }
+            },
+            ranges: vec![],
+            aliases: vec![],
+            lookup_cache: LookupCache::default(),
+            permissions: AHashMap::default(),
+            pma: vec![],
+            reservations: AHashMap::default(),
+            clock_domains: AHashMap::default(),
+            host_clocks: AHashMap::default(),
+            core_cycle: 0,
+            crossing_stats: ClockCrossingStats::default(),
+            access_stats: AHashMap::default(),
+            external_interrupts: vec![],
         }
     }
 }
\ No newline at end of file
@@ -1,7 +1,53 @@
 pub mod pipeline_stage;
 pub mod cache;
-mod instruction_asm;
+pub mod disasm;
+pub mod asm;
 mod cdb;
 pub mod memory_management_unit;
+pub mod register_map;
 pub mod wire;
+#[allow(clippy::module_inception)]
 pub mod risc_soc;
+pub mod dtb;
+pub mod image_formats;
+pub mod symbols;
+pub mod load_error;
+pub mod sim_error;
+pub mod debugger;
+pub mod exception;
+pub mod csr;
+pub mod debug_module;
+pub mod trigger;
+pub mod vector;
+pub mod soc;
+pub mod coherence;
+pub mod pipeline_diagram;
+pub mod vcd;
+pub mod clock;
+pub mod run_control;
+pub mod event_queue;
+pub mod semihosting;
+pub mod store_buffer;
+pub mod fetch_buffer;
+pub mod decode_cache;
+pub mod memory_dump;
+pub mod initial_state;
+pub mod event_log;
+pub mod chrome_trace;
+pub mod stage_harness;
+pub mod observer;
+pub mod profiler;
+pub mod call_stack;
+pub mod memory_guard;
+pub mod shadow_memory;
+pub mod heap_tracker;
+pub mod coverage;
+pub mod fuzz;
+pub mod fault_injection;
+pub mod energy;
+pub mod timing;
+pub mod isa_model;
+pub mod custom_extension;
+pub mod benchmark;
+#[cfg(feature = "tui")]
+pub mod dashboard;
@@ -0,0 +1,30 @@
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryResponse};
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::sim_error::SimError;
+
+/// hooks into the events of a simulation (ex. for a GUI, a profiler or a fuzzer), registered with `RiscCore::add_observer`
+/// every event is ignored unless overridden, and observers are called from the stage threads of `run`, so they keep their state behind locks or atomics
+pub trait SimulationObserver: Send + Sync {
+    /// end of a clock cycle, once every stage and device was updated
+    fn on_cycle(&self, _core: &RiscCore, _clock_cycle: u64) {}
+
+    /// an instruction updated the architectural state and left the pipeline, in program order
+    fn on_instruction_retired(&self, _core: &RiscCore, _pc: RiscWord, _instruction: u32) {}
+
+    /// an exception stopped the core, only the first one of a clock cycle is reported
+    fn on_trap(&self, _core: &RiscCore, _trap: &Trap) {}
+
+    /// an error of the model stopped the core, only the first one is reported
+    fn on_sim_error(&self, _core: &RiscCore, _error: &SimError) {}
+
+    /// a line missed in a cache, the fetch buffer for instructions or the coherent cache for data
+    /// the bus is still locked by the access, so observers must not access memory from it
+    fn on_cache_miss(&self, _core: &RiscCore, _address: Address, _instruction: bool) {}
+
+    /// access of the data port to memory, buffered stores are reported when they are written
+    fn on_mem_access(&self, _core: &RiscCore, _request: &MemoryRequest, _response: &MemoryResponse) {}
+
+    /// memory written outside of the program, by a loader (ex. the sections of an ELF) or a debugger
+    fn on_mem_init(&self, _core: &RiscCore, _address: Address, _len: usize) {}
+}
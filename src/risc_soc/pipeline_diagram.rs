@@ -0,0 +1,160 @@
+use crate::risc_soc::disasm::disassemble_at;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::pipeline_stage::ClockCycle;
+use crate::risc_soc::risc_soc::RiscWord;
+use std::fmt::Display;
+
+/// what a stage did during a clock cycle, sampled after the control signals of the cycle are known
+#[derive(Debug, Clone)]
+pub struct StageSample {
+    pub clock_cycle: ClockCycle,
+    pub stage_index: usize,
+    pub letter: char,
+    pub reset: bool,
+    /// the stage wrote its output register, i.e. it was neither disabled nor stalled by the next stage
+    pub enabled: bool,
+    /// instruction, PC and number of younger instructions in the packet produced by the first stage
+    pub fetched: Option<(u32, RiscWord, usize)>,
+}
+
+/// an instruction fetched by the first stage and the stages it went through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiagramRow {
+    pub pc: RiscWord,
+    pub instruction: u32,
+    /// younger instructions fetched in the same packet
+    pub bundle: usize,
+    /// stage letter for every cycle the instruction was processed, a stalled instruction is processed again by the same stage
+    pub stages: Vec<(ClockCycle, char)>,
+    /// cycle in which the instruction was dropped from the pipeline by a stage reset
+    pub flushed: Option<ClockCycle>,
+}
+
+/// per-cycle occupancy of the pipeline stages, rendered as a table of instructions against clock cycles
+/// the framework only sees the pipeline registers, so an instruction is followed from the output of a stage
+/// to the input of the next one, kept in place while a stage is disabled and dropped when a stage is reset
+#[derive(Debug, Default)]
+pub struct PipelineDiagram {
+    samples: Vec<StageSample>,
+}
+
+/// letter shown for a stage, following the classic F/D/E/M/W notation for the usual stage names
+pub fn stage_letter(name: &str) -> char {
+    match name {
+        "IF" => 'F',
+        "ID" => 'D',
+        "WB" => 'W',
+        _ => name.chars().next().unwrap_or('?').to_ascii_uppercase(),
+    }
+}
+
+impl PipelineDiagram {
+    pub fn record(&mut self, sample: StageSample) {
+        self.samples.push(sample);
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// follow the instructions through the pipeline registers, in fetch order
+    pub fn rows(&self) -> Vec<DiagramRow> {
+        let mut samples = self.samples.clone();
+        // stage threads record their samples concurrently
+        samples.sort_by_key(|sample| (sample.clock_cycle, sample.stage_index));
+        let num_stages = samples.iter().map(|sample| sample.stage_index + 1).max().unwrap_or(0);
+
+        let mut rows: Vec<DiagramRow> = vec![];
+        // row of the instruction held in the output register of each stage
+        let mut outputs: Vec<Option<usize>> = vec![None; num_stages];
+        // instruction fetched while the first stage was disabled, fetched again in the next cycle
+        let mut refetch: Option<usize> = None;
+        for cycle in samples.chunk_by(|a, b| a.clock_cycle == b.clock_cycle) {
+            let mut next_outputs = outputs.clone();
+            let mut processed = vec![None; num_stages];
+            let mut next_refetch = None;
+            for sample in cycle {
+                let row = if sample.stage_index == 0 {
+                    match sample.fetched {
+                        Some((instruction, pc, bundle)) if instruction != 0x0 => match refetch {
+                            Some(row) if rows[row].pc == pc => Some(row),
+                            _ => {
+                                rows.push(DiagramRow { pc, instruction, bundle, stages: vec![], flushed: None });
+                                Some(rows.len() - 1)
+                            }
+                        },
+                        _ => None,
+                    }
+                } else {
+                    outputs[sample.stage_index - 1]
+                };
+                if let Some(row) = row {
+                    rows[row].stages.push((sample.clock_cycle, sample.letter));
+                }
+                processed[sample.stage_index] = row;
+                if sample.reset {
+                    next_outputs[sample.stage_index] = None;
+                } else if sample.enabled {
+                    next_outputs[sample.stage_index] = row;
+                } else if sample.stage_index == 0 {
+                    next_refetch = row;
+                }
+            }
+            // an instruction dropped by a reset that no pipeline register holds anymore was flushed
+            for sample in cycle.iter().filter(|sample| sample.reset) {
+                if let Some(row) = processed[sample.stage_index]
+                    && !next_outputs.contains(&Some(row))
+                    && next_refetch != Some(row)
+                {
+                    rows[row].flushed = Some(sample.clock_cycle);
+                }
+            }
+            outputs = next_outputs;
+            refetch = next_refetch;
+        }
+        rows
+    }
+}
+
+/// one line per instruction and one column per clock cycle, lowercase letters mark the cycle an instruction was flushed in
+impl Display for PipelineDiagram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = self.rows();
+        let Some(last_cycle) = self.samples.iter().map(|sample| sample.clock_cycle).max() else {
+            return Ok(());
+        };
+        let first_cycle = self.samples.iter().map(|sample| sample.clock_cycle).min().unwrap();
+        let width = last_cycle.to_string().len() + 1;
+        let labels: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let bundle = if row.bundle > 0 { format!(" (+{})", row.bundle) } else { String::new() };
+                format!("{:08X} {}{}", row.pc, disassemble_at(row.instruction, row.pc as Address, None), bundle)
+            })
+            .collect();
+        let label_width = labels.iter().map(String::len).max().unwrap_or(0).max("cycle".len());
+
+        write!(f, "{:label_width$}", "cycle")?;
+        for cycle in first_cycle..=last_cycle {
+            write!(f, "{cycle:>width$}")?;
+        }
+        writeln!(f)?;
+        for (row, label) in rows.iter().zip(labels) {
+            write!(f, "{label:label_width$}")?;
+            let mut stages = row.stages.iter().peekable();
+            for cycle in first_cycle..=last_cycle {
+                // a stage reprocessing the same instruction in a cycle only shows the latest stage
+                let mut letter = ' ';
+                while let Some((_, stage)) = stages.next_if(|(stage_cycle, _)| *stage_cycle == cycle) {
+                    letter = *stage;
+                }
+                if row.flushed == Some(cycle) {
+                    letter = letter.to_ascii_lowercase();
+                }
+                write!(f, "{letter:>width$}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
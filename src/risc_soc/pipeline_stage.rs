@@ -1,16 +1,10 @@
-use crate::risc_soc::risc_soc::RiscCore;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord, XLEN_BYTES};
 use crossbeam_channel::{Receiver, Sender};
 
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct PipelineData(pub Vec<u8>);
 
-impl Default for PipelineData{
-    fn default() -> Self {
-        Self(vec![])
-    }
-}
-
 impl PipelineData {
 
     pub fn is_empty(&self) -> bool {
@@ -27,7 +21,7 @@ impl PipelineData {
         assert!(address + 2 <= self.0.len());
         let mut value: u16 = 0x0;
         for i in 0..2 {
-            value |= (self.0[address + i] as u16) << i*8;
+            value |= (self.0[address + i] as u16) << (i * 8);
         }
         value
     }
@@ -36,7 +30,7 @@ impl PipelineData {
         assert!(address + 4 <= self.0.len());
         let mut value: u32 = 0x0;
         for i in 0..4 {
-            value |= (self.0[address + i] as u32) << i*8;
+            value |= (self.0[address + i] as u32) << (i * 8);
         }
         value
     }
@@ -45,7 +39,17 @@ impl PipelineData {
         assert!(address + 8 <= self.0.len());
         let mut value: u64 = 0x0;
         for i in 0..8 {
-            value |= (self.0[address + i] as u64) << i*8;
+            value |= (self.0[address + i] as u64) << (i * 8);
+        }
+        value
+    }
+
+    /// read a value of the core register width (XLEN)
+    pub fn get_word(&self, address: usize) -> RiscWord {
+        assert!(address + XLEN_BYTES <= self.0.len());
+        let mut value: RiscWord = 0x0;
+        for i in 0..XLEN_BYTES {
+            value |= (self.0[address + i] as RiscWord) << (i * 8);
         }
         value
     }
@@ -57,8 +61,33 @@ impl PipelineData {
     pub fn size(&self) -> usize {
         self.0.len()
     }
+
+    /// pipeline register holding a fetch packet, one slot per instruction fetched in the same cycle, oldest first
+    pub fn fetch_packet(slots: &[(u32, RiscWord)]) -> Self {
+        let mut data = Vec::with_capacity(slots.len() * FETCH_SLOT_SIZE);
+        for (instruction, pc) in slots {
+            data.extend_from_slice(&instruction.to_le_bytes());
+            data.extend_from_slice(&pc.to_le_bytes());
+        }
+        Self(data)
+    }
+
+    pub fn fetch_slots(&self) -> usize {
+        self.0.len() / FETCH_SLOT_SIZE
+    }
+
+    /// instruction and PC held in a slot of a fetch packet
+    pub fn fetch_slot(&self, slot: usize) -> (u32, RiscWord) {
+        let offset = slot * FETCH_SLOT_SIZE;
+        (self.get_u32(offset), self.get_word(offset + 4))
+    }
 }
 
+/// widest fetch packet supported by the framework
+pub const MAX_ISSUE_WIDTH: usize = 4;
+/// each slot of a fetch packet holds an instruction followed by its PC, a single slot is the IF/ID register of a scalar pipeline
+pub const FETCH_SLOT_SIZE: usize = 4 + XLEN_BYTES;
+
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Instruction(pub u32);
 
@@ -67,7 +96,12 @@ pub type ClockCycle = u64;
 #[derive(Debug, Default)]
 pub struct PipelinePayload {
     pub instruction: Instruction,
+    /// younger instructions fetched in the same cycle as `instruction`, only used by superscalar pipelines
+    pub bundle: Vec<Instruction>,
     pub data: PipelineData,
+    /// the output register of the sender holds an instruction the receiver did not consume yet
+    /// otherwise `data` is the bubble of the sender, ex. after a flush or while the sender is stalled
+    pub valid: bool,
 }
 
 
@@ -79,10 +113,17 @@ pub struct PipelineStage {
     pub size_out: usize,
     /// current clock_cycle and instruction in this stage
     pub instruction: Instruction,
+    /// younger instructions travelling with `instruction` through a superscalar pipeline
+    pub bundle: Vec<Instruction>,
     pub clock_cycle: ClockCycle,
     /// current data it consumed and produced during a clock cycle
     pub data_in: PipelineData,
     pub data_out: PipelineData,
+    /// value of the output register after a reset or a flush of the stage, all zero unless set with `with_bubble`
+    pub bubble: PipelineData,
+    /// the stage consumed its input in the last clock cycle, so it latches the next one from the previous stage
+    /// a stage which is not ready keeps its input and processes it again
+    pub input_consumed: bool,
     /// input and output to previous and next stages
     /// stages like fetch and commit may not have any previous or next stage
     /// so we define the input and output channels as optional
@@ -93,6 +134,17 @@ pub struct PipelineStage {
     pub debug: bool,
 }
 
+impl PipelineStage {
+    /// output of the stage when it holds no instruction, ex. a NOP whose valid bit is cleared
+    /// the following stages then see a harmless instruction after a flush, instead of decoding a zeroed register
+    pub fn with_bubble(mut self, bubble: PipelineData) -> Self {
+        assert_eq!(bubble.size(), self.size_out, "The bubble of stage {} does not match the size of its output", self.name);
+        self.data_out = bubble.clone();
+        self.bubble = bubble;
+        self
+    }
+}
+
 pub trait PipelineStageInterface {
     type F: Fn(&PipelineData, &RiscCore) -> PipelineData + Send + 'static;
 
@@ -135,11 +187,14 @@ impl PipelineStageInterface for PipelineStage {
             process_fn,
             debug: false,
             instruction: Instruction(0x0),
+            bundle: vec![],
             clock_cycle: 0,
             input_channel,
             output_channel,
             data_in: PipelineData(vec![0u8; size_in]),
             data_out: PipelineData(vec![0u8; size_out]),
+            bubble: PipelineData(vec![0u8; size_out]),
+            input_consumed: true,
         }
     }
 
@@ -0,0 +1,147 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Mutex;
+
+const OP_JAL: u32 = 0b1101111;
+const OP_JALR: u32 = 0b1100111;
+/// ra and t0, the link registers of the calling convention
+const LINK_REGISTERS: [u32; 2] = [1, 5];
+/// frame of the instructions outside every symbol
+const UNKNOWN_FUNCTION: &str = "[unknown]";
+
+/// cost attributed to a function or to a call stack
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileCounts {
+    /// instructions retired
+    pub instructions: u64,
+    /// clock cycles in which one of the instructions retired, together with the stall cycles before it
+    pub cycles: u64,
+    /// clock cycles in which nothing retired, attributed to the instruction retiring after them
+    pub stall_cycles: u64,
+}
+
+impl ProfileCounts {
+    fn add(&mut self, other: &ProfileCounts) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+        self.stall_cycles += other.stall_cycles;
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProfileState {
+    /// functions of the guest from the outermost one, rebuilt from the calls and returns that retired
+    stack: Vec<String>,
+    /// the last retired instruction was a call, the next one opens a new frame
+    calling: bool,
+    /// the last retired instruction was a return, the next one is back in the caller
+    returning: bool,
+    /// clock cycles since the last one in which an instruction retired
+    stall_cycles: u64,
+    retired_this_cycle: bool,
+    /// counts of every call stack, keyed by its frames joined with ';'
+    stacks: HashMap<String, ProfileCounts>,
+}
+
+/// Exact profiler of the guest, attributing every retired instruction and every clock cycle to the function it belongs to
+/// Functions are found through the symbols of the loaded ELF, and the call stack is followed through the calls (JAL/JALR
+/// linking ra or t0) and returns (JALR to ra or t0) that retire, a jump to another function replaces the innermost frame
+/// Registered on a core with `RiscCore::enable_profiler`, the result can be rendered with any flamegraph tool from `folded`
+#[derive(Debug, Default)]
+pub struct Profiler {
+    state: Mutex<ProfileState>,
+}
+
+impl Profiler {
+    /// counts of every call stack in the folded format of flamegraph tools, one `outer;inner cycles` line per stack
+    pub fn folded(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut stacks: Vec<_> = state.stacks.iter().collect();
+        stacks.sort_by(|a, b| a.0.cmp(b.0));
+        stacks.iter().map(|(stack, counts)| format!("{stack} {}\n", counts.cycles)).collect()
+    }
+
+    pub fn write_folded(&self, mut output: impl Write) -> std::io::Result<()> {
+        output.write_all(self.folded().as_bytes())
+    }
+
+    /// self cost of every function, excluding the functions it called, from the most to the least expensive
+    pub fn functions(&self) -> Vec<(String, ProfileCounts)> {
+        let state = self.state.lock().unwrap();
+        let mut functions: HashMap<&str, ProfileCounts> = HashMap::new();
+        for (stack, counts) in &state.stacks {
+            let function = stack.rsplit(';').next().unwrap();
+            functions.entry(function).or_default().add(counts);
+        }
+        let mut functions: Vec<_> = functions.into_iter().map(|(name, counts)| (name.to_string(), counts)).collect();
+        functions.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then_with(|| a.0.cmp(&b.0)));
+        functions
+    }
+
+    /// total cost of the given call stack (ex. "main;compute"), the functions it called included
+    pub fn inclusive(&self, stack: &str) -> ProfileCounts {
+        let state = self.state.lock().unwrap();
+        let mut total = ProfileCounts::default();
+        for (current, counts) in &state.stacks {
+            if current == stack || current.strip_prefix(stack).is_some_and(|callees| callees.starts_with(';')) {
+                total.add(counts);
+            }
+        }
+        total
+    }
+
+    pub fn clear(&self) {
+        *self.state.lock().unwrap() = ProfileState::default();
+    }
+}
+
+impl SimulationObserver for Profiler {
+    fn on_cycle(&self, _core: &RiscCore, _clock_cycle: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !state.retired_this_cycle {
+            state.stall_cycles += 1;
+        }
+        state.retired_this_cycle = false;
+    }
+
+    fn on_instruction_retired(&self, core: &RiscCore, pc: RiscWord, instruction: u32) {
+        let function = match core.symbols.lookup(pc as Address) {
+            Some((symbol, _)) => symbol.name.clone(),
+            None => UNKNOWN_FUNCTION.to_string(),
+        };
+        let mut state = self.state.lock().unwrap();
+        if std::mem::take(&mut state.returning) {
+            state.stack.pop();
+        }
+        if std::mem::take(&mut state.calling) {
+            state.stack.push(function);
+        } else {
+            match state.stack.last_mut() {
+                Some(top) if *top != function => *top = function,
+                Some(_) => {}
+                None => state.stack.push(function),
+            }
+        }
+
+        let mut counts = ProfileCounts { instructions: 1, ..Default::default() };
+        if !state.retired_this_cycle {
+            counts.stall_cycles = std::mem::take(&mut state.stall_cycles);
+            counts.cycles = 1 + counts.stall_cycles;
+            state.retired_this_cycle = true;
+        }
+        let stack = state.stack.join(";");
+        state.stacks.entry(stack).or_default().add(&counts);
+
+        let opcode = instruction & 0x7F;
+        let rd = (instruction >> 7) & 0x1F;
+        let rs1 = (instruction >> 15) & 0x1F;
+        if (opcode == OP_JAL || opcode == OP_JALR) && LINK_REGISTERS.contains(&rd) {
+            state.calling = true;
+        } else if opcode == OP_JALR && rd == 0 && LINK_REGISTERS.contains(&rs1) {
+            state.returning = true;
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::risc_soc::memory_management_unit::Address;
+
+/// bit field of a device register, shown by name in the traces of the accesses to the register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterField {
+    pub name: &'static str,
+    pub lsb: u32,
+    pub width: u32,
+}
+
+impl RegisterField {
+    pub const fn bit(name: &'static str, bit: u32) -> Self {
+        Self { name, lsb: bit, width: 1 }
+    }
+
+    pub const fn bits(name: &'static str, lsb: u32, width: u32) -> Self {
+        Self { name, lsb, width }
+    }
+
+    fn value(&self, register: u64) -> u64 {
+        (register >> self.lsb) & ((1u64 << self.width) - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterAccess {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+}
+
+impl RegisterAccess {
+    fn allows(self, write: bool) -> bool {
+        match self {
+            RegisterAccess::ReadOnly => !write,
+            RegisterAccess::WriteOnly => write,
+            RegisterAccess::ReadWrite => true,
+        }
+    }
+}
+
+/// register of a device as listed in its data sheet, at an offset from the start of the device
+/// registers sharing an offset (ex. RBR and THR of a 16550) are told apart by the direction of the access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Register {
+    pub name: &'static str,
+    pub offset: Address,
+    /// bytes covered by the register, the halves of a 64 bit register accessed by 32 bit cores are described with its name
+    pub size: Address,
+    pub access: RegisterAccess,
+    pub fields: &'static [RegisterField],
+    /// the value is a character (ex. the data register of a UART), shown along the number when it is printable
+    pub character: bool,
+}
+
+impl Register {
+    pub const fn new(name: &'static str, offset: Address, access: RegisterAccess) -> Self {
+        Self { name, offset, size: 4, access, fields: &[], character: false }
+    }
+
+    pub const fn with_size(mut self, size: Address) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub const fn with_fields(mut self, fields: &'static [RegisterField]) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    pub const fn character(mut self) -> Self {
+        self.character = true;
+        self
+    }
+}
+
+/// access to the register of the map holding the offset, ex. "THR <= 0x48 'H'" or "LSR => 0x60 {THRE|TEMT}"
+/// the fields set in the value are listed, single bits by name and wider fields with their value
+/// None if no register of the map holds the offset for this direction
+pub fn describe_access(registers: &[Register], offset: Address, write: bool, value: u64) -> Option<String> {
+    let register = registers
+        .iter()
+        .find(|register| register.access.allows(write) && (register.offset..register.offset + register.size).contains(&offset))?;
+    let mut description = register.name.to_string();
+    if offset != register.offset {
+        description.push_str(&format!("+{}", offset - register.offset));
+    }
+    description.push_str(&format!(" {} 0x{value:x}", if write { "<=" } else { "=>" }));
+    if register.character {
+        let character = value as u8;
+        if value <= 0xFF && (character.is_ascii_graphic() || character == b' ') {
+            description.push_str(&format!(" '{}'", character as char));
+        }
+    }
+    // the fields are only meaningful when the access covers the whole register
+    let fields: Vec<String> = register
+        .fields
+        .iter()
+        .filter(|_| offset == register.offset)
+        .filter(|field| field.value(value) != 0)
+        .map(|field| if field.width == 1 { field.name.to_string() } else { format!("{}={}", field.name, field.value(value)) })
+        .collect();
+    if !fields.is_empty() {
+        description.push_str(&format!(" {{{}}}", fields.join("|")));
+    }
+    Some(description)
+}
@@ -1,22 +1,95 @@
 use super::pipeline_stage::*;
 use crate::risc_soc::cache::Cache;
 use crate::risc_soc::cdb::CommonDataBus;
+use crate::risc_soc::coherence::CoherentCache;
+use crate::risc_soc::asm::{self, AsmError, Assembly};
+use crate::risc_soc::disasm;
+use crate::risc_soc::csr::{ControlStatusRegisters, DCSR_EBREAKM, DCSR_STEP, DebugCause, DebugRegisters, InterruptLines, MachineInfo};
+use crate::risc_soc::event_queue::EventQueue;
+use crate::risc_soc::exception::{Exception, MisalignedAccessPolicy, Trap};
+use crate::risc_soc::image_formats;
+use crate::risc_soc::load_error::{ElfLoadMode, LoadError};
+use crate::risc_soc::pipeline_diagram::{PipelineDiagram, StageSample, stage_letter};
+use crate::risc_soc::vcd::WaveformRecorder;
+use crate::risc_soc::run_control::{RunControl, StopReason};
+use crate::risc_soc::semihosting::{self, Semihosting};
+use crate::risc_soc::sim_error::{SimError, SimErrorKind};
+use crate::risc_soc::store_buffer::{StoreBuffer, StoreBufferStats};
+use crate::risc_soc::fetch_buffer::{FetchBuffer, PrefetchStats};
+use crate::risc_soc::decode_cache::{DecodeCache, DecodeCacheStats};
+use crate::risc_soc::isa_model::MicroOp;
+use crate::risc_soc::memory_dump::{self, MemoryAccessLog};
+use crate::risc_soc::event_log::EventLog;
+use crate::risc_soc::chrome_trace::ChromeTrace;
+use crate::risc_soc::initial_state::InitialState;
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::profiler::Profiler;
+use crate::risc_soc::memory_guard::MemoryGuard;
+use crate::risc_soc::call_stack::Backtraces;
+use crate::risc_soc::shadow_memory::ShadowMemory;
+use crate::risc_soc::heap_tracker::HeapTracker;
+use crate::risc_soc::fault_injection::{Fault, FaultInjector};
+use crate::risc_soc::coverage::Coverage;
+use crate::risc_soc::energy::{EnergyModel, EnergyWeights};
+use crate::risc_soc::symbols::{Symbol, SymbolTable};
+use crate::risc_soc::trigger::TriggerAction;
+use crate::risc_soc::vector::VectorRegisters;
 use crate::risc_soc::memory_management_unit::{
-    Address, MemoryDeviceType, MemoryManagementUnit, MemoryRequest,
-    MemoryResponse, MemoryResponseType,
+    Address, BusMaster, MemoryAccessError, MemoryData, MemoryDeviceType, MemoryManagementUnit, MemoryRequest,
+    MemoryRequestType, MemoryResponse, MemoryResponseType,
 };
-use object::read::elf::{FileHeader, SectionHeader};
+use object::read::elf::{FileHeader, ProgramHeader, SectionHeader, Sym};
 use object::{Endianness, elf};
+use std::any::Any;
 use std::fmt::Debug;
 use std::fs;
-use std::io::Read;
 use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicBool, AtomicU64};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 /// type used to represent data inside the RiscCore (defaulted to u32 for RV32)
-/// can be overwritten to u64 if RV64 is intended for implementation
+/// the `rv64` feature switches it to u64 so RV64 cores can be implemented
+#[cfg(not(feature = "rv64"))]
 pub type RiscWord = u32;
+#[cfg(feature = "rv64")]
+pub type RiscWord = u64;
+
+/// signed counterpart of `RiscWord`, used for signed comparisons and arithmetic shifts
+#[cfg(not(feature = "rv64"))]
+pub type RiscSignedWord = i32;
+#[cfg(feature = "rv64")]
+pub type RiscSignedWord = i64;
+
+/// atomic counterpart of `RiscWord`, holding the registers and the PC shared by the stage threads
+#[cfg(not(feature = "rv64"))]
+pub type AtomicRiscWord = std::sync::atomic::AtomicU32;
+#[cfg(feature = "rv64")]
+pub type AtomicRiscWord = std::sync::atomic::AtomicU64;
+
+/// register width in bits and bytes
+pub const XLEN: usize = RiscWord::BITS as usize;
+pub const XLEN_BYTES: usize = XLEN / 8;
+
+/// widen a word to the 64 bit values of the host (ex. file offsets and counters), the word is returned as is on RV64
+#[cfg(not(feature = "rv64"))]
+pub const fn zero_extend_word(word: RiscWord) -> u64 {
+    word as u64
+}
+#[cfg(feature = "rv64")]
+pub const fn zero_extend_word(word: RiscWord) -> u64 {
+    word
+}
+
+/// signed counterpart of `zero_extend_word`
+#[cfg(not(feature = "rv64"))]
+pub const fn sign_extend_word(word: RiscWord) -> i64 {
+    word as RiscSignedWord as i64
+}
+#[cfg(feature = "rv64")]
+pub const fn sign_extend_word(word: RiscWord) -> i64 {
+    word as i64
+}
 
 /// sizes of the supported words in bytes
 #[derive(Debug, Clone, Copy)]
@@ -27,10 +100,76 @@ pub enum WordSize {
     DOUBLE = 8,
 }
 
+/// index of the class (32 or 64 bits) in the identification bytes at the start of an ELF file
+pub const EI_CLASS: usize = 4;
+
+/// address the program counter starts from unless another reset vector is configured
+pub const DEFAULT_RESET_VECTOR: RiscWord = 0x8000_0000;
+
+/// result of a simulation ended by the program itself (ex. through a test finisher device)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Pass,
+    Fail(u16),
+    Reset,
+}
+
+/// shared between a core and the devices allowed to end its simulation
+#[derive(Debug, Clone, Default)]
+pub struct ExitSignal(Arc<Mutex<Option<ExitStatus>>>);
+
+impl ExitSignal {
+    /// only the first exit request is kept
+    pub fn exit(&self, status: ExitStatus) {
+        self.0.lock().unwrap().get_or_insert(status);
+    }
+
+    pub fn status(&self) -> Option<ExitStatus> {
+        *self.0.lock().unwrap()
+    }
+
+    /// forget the exit request, once the core was reset
+    pub fn clear(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+}
+
 /// should usually represent main control signals such as a reset and enable
 type PipelineControlSignals = Vec<AtomicBool>;
 const RESET_SIGNAL:usize = 0x0;
 const ENABLE_SIGNAL: usize= 0x1;
+/// the stage consumes its input at the end of the clock cycle, set by the stage itself
+const READY_SIGNAL: usize = 0x2;
+/// the output register of the stage holds an instruction not yet consumed by the next stage, maintained by the run loops
+const VALID_SIGNAL: usize = 0x3;
+
+/// how the pipeline registers around a stage change on a clock edge, given the valid/ready handshake with its neighbours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Handshake {
+    /// the output register takes the output of the stage (or its bubble on a reset)
+    write: bool,
+    /// the output register holds an instruction for the next stage after the edge
+    valid: bool,
+    /// the input register is free to latch the next instruction of the previous stage
+    consumed: bool,
+}
+
+/// registers holding the number and the first argument of an environment call
+const REG_A0: usize = 10;
+const REG_A7: usize = 17;
+/// exit system call of the Linux ABI, also used by the `_exit` of newlib's bare-metal runtime
+const SYS_EXIT: RiscWord = 93;
+/// encoding of WFI, the core sleeps after retiring it until an interrupt is pending
+const WFI_INSTRUCTION: u32 = 0x1050_0073;
+
+/// services the ECALLs of a core instead of trapping, ex. to emulate the system calls of an OS on the host
+pub trait EnvironmentCallHandler: Send + Sync {
+    /// called once every instruction older than the ECALL completed, returns the value to write to a0 (if any)
+    fn environment_call(&self, core: &RiscCore) -> Option<RiscWord>;
+}
+
+/// resets a part of the microarchitecture the core does not know about (ex. the reorder buffer of an out-of-order core)
+pub type ResetHook = Box<dyn Fn(&RiscCore) + Send + Sync>;
 
 pub struct RiscCore {
     pub debug: bool,
@@ -38,11 +177,79 @@ pub struct RiscCore {
     pub icache: Option<Arc<RwLock<Box<dyn Cache + Send + Sync>>>>,
     pub dcache: Option<Arc<RwLock<Box<dyn Cache + Send + Sync>>>>,
     pub registers: Registers,
-    pub program_counter: AtomicU64,
+    pub program_counter: AtomicRiscWord,
+    pub reset_vector: RiscWord,
     pub mmu: Arc<RwLock<MemoryManagementUnit>>,
     pub clock_period: Option<u128>, //nanoseconds
     pub cdb: CommonDataBus,
-    pub pipeline_control_signals: Vec<PipelineControlSignals>
+    pub pipeline_control_signals: Vec<PipelineControlSignals>,
+    pub symbols: SymbolTable,
+    pub misaligned_policy: MisalignedAccessPolicy,
+    /// parts of the ELF images copied to memory by `load_binary`
+    pub elf_load_mode: ElfLoadMode,
+    /// reset vector kept when loading an ELF image, instead of moving it to the entry point of the image
+    pub entry_override: Option<RiscWord>,
+    /// exception raised during the current clock cycle, execution stops at the end of the cycle
+    pub trap: Mutex<Option<Trap>>,
+    /// error of the model raised during the current clock cycle, execution stops at the end of the cycle
+    pub sim_error: Mutex<Option<SimError>>,
+    /// clock cycle being run, counted like the clock cycles of the stages
+    pub clock_cycle: AtomicU64,
+    /// set by the program when it wants to end the simulation, execution stops at the end of the cycle
+    pub exit_signal: ExitSignal,
+    pub csrs: RwLock<ControlStatusRegisters>,
+    /// register file of the vector extension, vector instructions are illegal without it
+    pub vector: Option<RwLock<VectorRegisters>>,
+    /// data cache kept coherent with the other harts, serving the accesses to shared memory that miss in the L1 memories
+    pub coherent_cache: Option<CoherentCache>,
+    /// state of the microarchitecture that does not fit in the pipeline registers (ex. the reorder buffer of an out-of-order core)
+    /// stage functions are plain functions, so this is where they keep what must survive between clock cycles
+    pub microarchitecture: Option<Box<dyn Any + Send + Sync>>,
+    /// instructions fetched per clock cycle, the PC advances by a whole fetch packet when the first stage is enabled
+    pub issue_width: usize,
+    /// occupancy of the pipeline stages at every clock cycle, only recorded when enabled
+    /// the stages lock it at every clock cycle, so it is left empty rather than holding an empty recorder
+    pub pipeline_diagram: Option<Mutex<PipelineDiagram>>,
+    /// pipeline registers and CDB wires sampled at every clock cycle, only recorded when enabled
+    pub waveform: Option<Mutex<WaveformRecorder>>,
+    /// wall-clock time after which a run is abandoned, unless the run sets its own timeout
+    pub run_timeout: Option<Duration>,
+    /// services the ECALLs of the program, without it they raise an environment call exception
+    pub environment_call_handler: Option<Arc<dyn EnvironmentCallHandler>>,
+    /// services the semihosting calls of the program, without it every EBREAK raises a breakpoint exception
+    pub semihosting: Option<Arc<Semihosting>>,
+    /// set by an EBREAK of the program in debug mode, the run stops at the end of the cycle instead of trapping
+    pub software_breakpoint: AtomicBool,
+    /// stores retired by MEM but not yet written to the data memory, stores go straight to memory without it
+    pub store_buffer: Option<Mutex<StoreBuffer>>,
+    /// fetch queue and sequential prefetcher of the instruction memory, fetches never wait without it
+    pub fetch_buffer: Option<Mutex<FetchBuffer>>,
+    /// micro-ops of the instructions already decoded by the first stages, see `decode_cached`
+    pub decode_cache: Mutex<DecodeCache>,
+    /// set by the first stage when it could not fetch during the current clock cycle, the PC is then not advanced
+    pub fetch_hold: AtomicBool,
+    /// PC of the instruction whose data accesses are being performed, set by the stage accessing memory for the observers
+    pub data_access_pc: AtomicU64,
+    /// accesses of the data port recorded since `enable_access_log`, written next to the dumps of `dump_memory`
+    pub access_log: Option<Arc<MemoryAccessLog>>,
+    /// stage intervals and device activity recorded since `enable_chrome_trace`
+    pub chrome_trace: Option<Arc<ChromeTrace>>,
+    /// registers and memory preloaded by `set_initial_state`, the registers are written again by every reset
+    pub initial_state: Option<InitialState>,
+    /// set when a WFI retires during the current clock cycle, the event-driven runs then skip the cycles until the next event
+    pub waiting_for_interrupt: AtomicBool,
+    /// clock cycles at which the stages or the SoC model need the core to be simulated again, see `post_event`
+    pub events: Mutex<EventQueue>,
+    /// set by the debugger to halt the hart in Debug Mode, the pipeline enters it in front of the next instruction to issue
+    pub halt_request: AtomicBool,
+    /// set once the instruction of a single step was issued, the hart then halts again in front of the next one
+    pub debug_step_issued: AtomicBool,
+    /// flips the bits of the scheduled faults, the run loops give it the pipeline registers as they latch them
+    pub fault_injector: Option<Arc<FaultInjector>>,
+    /// notified of the events of the simulation, in the order they were added
+    pub observers: Vec<Arc<dyn SimulationObserver>>,
+    /// bring the state kept outside of the pipeline registers back to its power-on value, called by `reset`
+    pub reset_hooks: Vec<ResetHook>,
 }
 
 impl RiscCore {
@@ -56,79 +263,1124 @@ impl RiscCore {
             icache: None,
             dcache: None,
             registers: Registers::default(),
-            program_counter: AtomicU64::new(0x8000_0000),
+            program_counter: AtomicRiscWord::new(DEFAULT_RESET_VECTOR),
+            reset_vector: DEFAULT_RESET_VECTOR,
             mmu: Arc::new(RwLock::new(MemoryManagementUnit::default())),
             cdb,
             clock_period,
             debug,
-            pipeline_control_signals
+            pipeline_control_signals,
+            symbols: SymbolTable::default(),
+            misaligned_policy: MisalignedAccessPolicy::default(),
+            elf_load_mode: ElfLoadMode::default(),
+            entry_override: None,
+            trap: Mutex::new(None),
+            sim_error: Mutex::new(None),
+            clock_cycle: AtomicU64::new(0),
+            exit_signal: ExitSignal::default(),
+            csrs: RwLock::new(ControlStatusRegisters::default()),
+            vector: None,
+            coherent_cache: None,
+            microarchitecture: None,
+            issue_width: 1,
+            pipeline_diagram: None,
+            waveform: None,
+            run_timeout: None,
+            environment_call_handler: None,
+            semihosting: None,
+            software_breakpoint: AtomicBool::new(false),
+            store_buffer: None,
+            fetch_buffer: None,
+            decode_cache: Mutex::new(DecodeCache::default()),
+            fetch_hold: AtomicBool::new(false),
+            data_access_pc: AtomicU64::new(0),
+            access_log: None,
+            chrome_trace: None,
+            initial_state: None,
+            waiting_for_interrupt: AtomicBool::new(false),
+            events: Mutex::new(EventQueue::default()),
+            halt_request: AtomicBool::new(false),
+            debug_step_issued: AtomicBool::new(false),
+            fault_injector: None,
+            observers: vec![],
+            reset_hooks: vec![],
+        }
+    }
+
+    pub fn enable_debug(&mut self, debug: bool){
+        self.debug = debug;
+        for cdb_lane in &mut self.cdb.bus {
+            for wire in cdb_lane.1 {
+                wire.enable_debug(debug);
+            }
+        }
+        for pipeline_stage in &self.stages{
+            let mut lock = pipeline_stage.lock().unwrap();
+            lock.enable_debug(debug);
+        }
+    }
+
+    pub fn add_l1_cache(
+        &mut self,
+        icache: Box<dyn Cache + Send + Sync>,
+        dcache: Box<dyn Cache + Send + Sync>,
+    ) -> &mut Self {
+        self.icache = Some(Arc::new(RwLock::new(icache)));
+        self.dcache = Some(Arc::new(RwLock::new(dcache)));
+        self
+    }
+
+    pub fn set_microarchitecture<T: Any + Send + Sync>(&mut self, state: T) {
+        self.microarchitecture = Some(Box::new(state));
+    }
+
+    /// panics if the core was not configured with a microarchitecture state of the requested type
+    pub fn microarchitecture<T: Any>(&self) -> &T {
+        self.microarchitecture
+            .as_ref()
+            .and_then(|state| state.downcast_ref::<T>())
+            .expect("The core was not configured with this microarchitecture state!")
+    }
+
+    /// configure a superscalar front end, only pipelines whose stages handle fetch packets (ex. the out-of-order core) support more than one
+    pub fn set_issue_width(&mut self, issue_width: usize) {
+        assert!((1..=MAX_ISSUE_WIDTH).contains(&issue_width));
+        self.issue_width = issue_width;
+    }
+
+    pub fn add_mmu(&mut self, mmu: MemoryManagementUnit) {
+        self.mmu = Arc::new(RwLock::new(mmu));
+    }
+
+    pub fn set_clock_period(&mut self, nanosecs: u128) {
+        self.clock_period = Some(nanosecs);
+    }
+
+    /// throttle the threaded runs to the given clock frequency, every stage sleeps for the rest of each clock period
+    /// takes effect from the next run, the sequential runs are never throttled
+    pub fn set_speed(&mut self, hz: u64) {
+        assert!(hz > 0 && hz <= 1_000_000_000, "The clock period is counted in whole nanoseconds!");
+        self.clock_period = Some(1_000_000_000 / hz as u128);
+    }
+
+    /// let the threaded runs go as fast as the host allows, ex. for CI
+    pub fn run_free(&mut self) {
+        self.clock_period = None;
+    }
+
+    /// clock frequency the threaded runs are throttled to, None when running free
+    pub fn speed(&self) -> Option<u64> {
+        self.clock_period.map(|period| (1_000_000_000 / period.max(1)) as u64)
+    }
+
+    /// configure where execution starts after reset (ex. a boot ROM) and move the PC there
+    pub fn set_reset_vector(&mut self, address: RiscWord) {
+        self.reset_vector = address;
+        self.set_pc(address);
+    }
+
+    pub fn icache_request(&self, request: MemoryRequest) -> MemoryResponse {
+        if let Some(icache) = &self.icache {
+            let cache_response = icache.write().unwrap().send_data_request(request.clone());
+            if cache_response.status == MemoryResponseType::CacheHit {
+                cache_response
+            } else if let Some(response) = Self::sibling_cache_request(&self.dcache, request.clone()) {
+                // code copied into data memory (ex. by a bootloader) can be executed from there
+                response
+            } else {
+                self.mmu.write().unwrap().process_fetch_request(request)
+            }
+        } else {
+            panic!("An L1Cache request was made, but there is no L1Cache configured on this core!")
+        }
+    }
+
+    /// identification reported by mvendorid, marchid, mimpid, mhartid and misa
+    pub fn set_machine_info(&mut self, info: MachineInfo) {
+        self.csrs.write().unwrap().info = info;
+    }
+
+    /// pending interrupt lines of this hart, to be connected to the interrupt controllers
+    pub fn interrupt_lines(&self) -> InterruptLines {
+        self.csrs.read().unwrap().mip.clone()
+    }
+
+    pub fn read_csr(&self, csr: u16) -> Option<RiscWord> {
+        let vector = self.vector.as_ref().and_then(|vector| vector.read().unwrap().read_csr(csr));
+        vector.or_else(|| self.csrs.read().unwrap().read(csr))
+    }
+
+    pub fn write_csr(&self, csr: u16, value: RiscWord) -> Option<()> {
+        let vector = self.vector.as_ref().and_then(|vector| vector.write().unwrap().write_csr(csr, value));
+        vector.or_else(|| self.csrs.write().unwrap().write(csr, value))
+    }
+
+    /// add a vector register file of `vlen` bits per register and list Zve32x in the machine information
+    pub fn enable_vector(&mut self, vlen: usize) {
+        self.vector = Some(RwLock::new(VectorRegisters::new(vlen)));
+        let info = &mut self.csrs.get_mut().unwrap().info;
+        if !info.has_extension("Zve32x") {
+            info.extensions.push_str("_Zve32x");
+        }
+    }
+
+    pub fn set_misaligned_policy(&mut self, policy: MisalignedAccessPolicy) {
+        self.misaligned_policy = policy;
+    }
+
+    pub fn set_elf_load_mode(&mut self, mode: ElfLoadMode) {
+        self.elf_load_mode = mode;
+    }
+
+    /// start from `entry` whatever the entry point of the ELF images loaded next, None to follow their entry point again
+    /// the PC moves there right away
+    pub fn set_entry_override(&mut self, entry: Option<RiscWord>) {
+        self.entry_override = entry;
+        if let Some(entry) = entry {
+            self.set_reset_vector(entry);
+        }
+    }
+
+    /// record an exception, only the first one raised is kept until it is taken
+    /// the instruction raising it is the one whose data accesses are being performed, see `data_access_pc`
+    pub fn raise_exception(&self, exception: Exception, tval: Address) {
+        self.raise_exception_at(exception, tval, self.data_access_pc.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    /// record an exception raised by the instruction at `pc`, for the stages not accessing memory (ex. the ECALLs performed in WB)
+    pub fn raise_exception_at(&self, exception: Exception, tval: Address, pc: Address) {
+        let trap = Trap { exception, tval, pc };
+        if self.debug {
+            println!("Exception raised: {trap}");
+        } else {
+            tracing::warn!("Exception raised: {trap}");
+        }
+        let mut pending = self.trap.lock().unwrap();
+        if pending.is_none() {
+            *pending = Some(trap);
+            drop(pending);
+            for observer in &self.observers {
+                observer.on_trap(self, &trap);
+            }
+        }
+    }
+
+    pub fn add_observer(&mut self, observer: Arc<dyn SimulationObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// attribute the retired instructions and the clock cycles of the next runs to the functions of the program
+    pub fn enable_profiler(&mut self) -> Arc<Profiler> {
+        let profiler = Arc::new(Profiler::default());
+        self.add_observer(profiler.clone());
+        profiler
+    }
+
+    /// follow the calls and returns of the program, to log its backtrace when an exception or an error of the model stops it
+    pub fn enable_backtraces(&mut self) -> Arc<Backtraces> {
+        let backtraces = Arc::new(Backtraces::default());
+        self.add_observer(backtraces.clone());
+        backtraces
+    }
+
+    /// stop the next runs with a `GuardViolation` error once a load or store touches one of the guard regions
+    pub fn enable_memory_guard(&mut self, guard: MemoryGuard) -> Arc<MemoryGuard> {
+        let guard = Arc::new(guard);
+        self.add_observer(guard.clone());
+        guard
+    }
+
+    /// warn about the loads of the next runs reading bytes of the shadowed ranges that were never initialized
+    /// registered before loading the program, so that its image counts as initialized
+    pub fn enable_shadow_memory(&mut self, shadow: ShadowMemory) -> Arc<ShadowMemory> {
+        let shadow = Arc::new(shadow);
+        self.add_observer(shadow.clone());
+        shadow
+    }
+
+    /// follow the allocations of the program during the next runs, ex. with the hooks of `HeapTracker::newlib`
+    pub fn enable_heap_tracker(&mut self, tracker: HeapTracker) -> Arc<HeapTracker> {
+        let tracker = Arc::new(tracker);
+        self.add_observer(tracker.clone());
+        tracker
+    }
+
+    /// flip the bits of the faults at their clock cycle during the next runs, the cycles are counted like the ones of the stages
+    pub fn enable_fault_injection(&mut self, faults: Vec<Fault>) -> Arc<FaultInjector> {
+        let injector = Arc::new(FaultInjector::new(faults));
+        self.add_observer(injector.clone());
+        self.fault_injector = Some(injector.clone());
+        injector
+    }
+
+    /// record the instructions and the basic block edges of the program that retire during the next runs
+    pub fn enable_coverage(&mut self) -> Arc<Coverage> {
+        let coverage = Arc::new(Coverage::default());
+        self.add_observer(coverage.clone());
+        coverage
+    }
+
+    /// estimate the energy spent by the next runs from the activity of the core
+    pub fn enable_energy_model(&mut self, weights: EnergyWeights) -> Arc<EnergyModel> {
+        let model = Arc::new(EnergyModel::new(weights));
+        self.add_observer(model.clone());
+        model
+    }
+
+    /// record every access of the data port during the next runs, with its clock cycle and the PC of its instruction
+    pub fn enable_access_log(&mut self) -> Arc<MemoryAccessLog> {
+        let log = Arc::new(MemoryAccessLog::default());
+        self.add_observer(log.clone());
+        self.access_log = Some(log.clone());
+        log
+    }
+
+    /// write the events of the next runs to output as JSON lines, see `EventLog`
+    pub fn enable_event_log(&mut self, output: impl std::io::Write + Send + 'static) -> Arc<EventLog> {
+        let log = Arc::new(EventLog::new(output));
+        self.add_observer(log.clone());
+        log
+    }
+
+    /// record the busy, stall and flush intervals of the stages and the device accesses of the next runs, see `ChromeTrace`
+    /// the stages must already be added, as their names are used to label the threads of the trace
+    pub fn enable_chrome_trace(&mut self) -> Arc<ChromeTrace> {
+        let stage_names = self.stages.iter().map(|stage| stage.lock().unwrap().name.clone()).collect();
+        let trace = Arc::new(ChromeTrace::new(stage_names));
+        self.add_observer(trace.clone());
+        self.chrome_trace = Some(trace.clone());
+        trace
+    }
+
+    /// preload the registers and memory of the program as described by `state`, ex. the stack pointer of an ELF without a crt0
+    /// symbols are resolved against the loaded ELF, so this is called after loading the program
+    pub fn set_initial_state(&mut self, state: InitialState) -> Result<(), String> {
+        state.apply(self)?;
+        self.initial_state = Some(state);
+        Ok(())
+    }
+
+    /// write a hex dump of the given (start, length) ranges to path, see `memory_dump::hex_dump`
+    /// with the access log enabled, the accesses recorded so far are also written as CSV to path with a `.csv` suffix
+    pub fn dump_memory(&self, path: &str, ranges: &[(Address, usize)]) -> std::io::Result<()> {
+        fs::write(path, memory_dump::hex_dump(self, ranges))?;
+        if let Some(log) = &self.access_log {
+            log.write_csv(std::io::BufWriter::new(fs::File::create(format!("{path}.csv"))?))?;
+        }
+        Ok(())
+    }
+
+    /// called by the last stage of the pipeline for every instruction it retires
+    pub fn retire_instruction(&self, pc: RiscWord, instruction: u32) {
+        if instruction == WFI_INSTRUCTION {
+            self.waiting_for_interrupt.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+        for observer in &self.observers {
+            observer.on_instruction_retired(self, pc, instruction);
+        }
+    }
+
+    fn observe_access(&self, request: &MemoryRequest, response: &MemoryResponse) {
+        for observer in &self.observers {
+            observer.on_mem_access(self, request, response);
+        }
+    }
+
+    fn observe_init(&self, address: Address, len: usize) {
+        for observer in &self.observers {
+            observer.on_mem_init(self, address, len);
+        }
+    }
+
+    pub fn set_environment_call_handler(&mut self, handler: Arc<dyn EnvironmentCallHandler>) {
+        self.environment_call_handler = Some(handler);
+    }
+
+    /// perform the ECALL at `pc`, returning the value to write to a0
+    /// without a handler the exit call of newlib's crt0 (a7 = 93) ends the simulation with the code in a0, anything else traps
+    pub fn environment_call(&self, pc: RiscWord) -> Option<RiscWord> {
+        match &self.environment_call_handler {
+            Some(handler) => handler.environment_call(self),
+            None if self.read_regs(REG_A7, 0).0 == SYS_EXIT => {
+                let code = self.read_regs(REG_A0, 0).0;
+                self.exit_signal.exit(if code == 0 { ExitStatus::Pass } else { ExitStatus::Fail(code as u16) });
+                None
+            }
+            None => {
+                self.raise_exception_at(Exception::EnvironmentCallFromMMode, 0, pc as Address);
+                None
+            }
+        }
+    }
+
+    /// let the program request services of the host through semihosting, the returned state holds its console output
+    pub fn enable_semihosting(&mut self, command_line: &str) -> Arc<Semihosting> {
+        let semihosting = Arc::new(Semihosting::new(command_line));
+        self.semihosting = Some(semihosting.clone());
+        semihosting
+    }
+
+    /// perform the EBREAK at `pc`, returning the value to write to a0 if it was a semihosting call
+    /// in debug mode it stops the run as a debugger breakpoint would, and execution resumes after it
+    /// with ebreakm set in dcsr it enters Debug Mode instead, with dpc pointing to the EBREAK
+    pub fn breakpoint(&self, pc: RiscWord) -> Option<RiscWord> {
+        match &self.semihosting {
+            Some(semihosting) if semihosting::is_semihosting_call(self, pc as Address) => Some(semihosting.call(self)),
+            _ if self.csrs.read().unwrap().debug.dcsr & DCSR_EBREAKM != 0 => {
+                self.enter_debug_mode(pc, DebugCause::Ebreak);
+                None
+            }
+            _ if self.debug => {
+                println!("EBREAK at 0x{pc:X}");
+                self.software_breakpoint.store(true, std::sync::atomic::Ordering::SeqCst);
+                None
+            }
+            _ => {
+                self.raise_exception_at(Exception::Breakpoint, pc as Address, pc as Address);
+                None
+            }
+        }
+    }
+
+    /// true if an EBREAK stopped the run since the last call
+    pub fn take_software_breakpoint(&self) -> bool {
+        self.software_breakpoint.swap(false, std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// ask the hart to halt in Debug Mode, as done by haltreq of the Debug Module
+    pub fn request_halt(&self) {
+        self.halt_request.store(true, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn in_debug_mode(&self) -> bool {
+        self.csrs.read().unwrap().debug.halted
+    }
+
+    /// why the pipeline has to halt in front of the next instruction to issue, if it has to
+    pub fn pending_debug_halt(&self) -> Option<DebugCause> {
+        if self.halt_request.load(std::sync::atomic::Ordering::SeqCst) {
+            Some(DebugCause::HaltRequest)
+        } else if self.debug_step_issued.load(std::sync::atomic::Ordering::SeqCst) {
+            Some(DebugCause::Step)
+        } else {
+            None
+        }
+    }
+
+    /// called by the pipeline when it issues an instruction, so a single step halts in front of the next one
+    pub fn debug_instruction_issued(&self) {
+        if self.csrs.read().unwrap().debug.dcsr & DCSR_STEP != 0 {
+            self.debug_step_issued.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    /// halt in Debug Mode with `pc` as the next instruction to execute, the instructions before it must have left the pipeline
+    /// buffered stores are written to memory so the debugger sees them, the run stops at the end of the cycle
+    pub fn enter_debug_mode(&self, pc: RiscWord, cause: DebugCause) {
+        self.halt_request.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.debug_step_issued.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.drain_store_buffer(None);
+        self.csrs.write().unwrap().debug.enter(pc, cause);
+        if self.debug {
+            println!("Entered Debug Mode at 0x{pc:X} ({cause:?})");
+        }
+    }
+
+    /// leave Debug Mode, dropping the instructions the pipeline fetched meanwhile and restarting from dpc
+    /// returns false if the hart was not halted, must not be called during a run
+    pub fn resume_from_debug_mode(&self) -> bool {
+        let dpc = {
+            let mut csrs = self.csrs.write().unwrap();
+            if !csrs.debug.halted {
+                return false;
+            }
+            csrs.debug.halted = false;
+            csrs.debug.dpc
+        };
+        self.flush_pipeline();
+        self.set_pc(dpc);
+        true
+    }
+
+    /// fire the triggers matching one of the `accesses` (MCONTROL_EXECUTE, MCONTROL_LOAD, MCONTROL_STORE) on `address`,
+    /// the pipeline performs the returned action in place of the instruction, triggers never fire in Debug Mode
+    pub fn check_triggers(&self, accesses: RiscWord, address: RiscWord) -> Option<TriggerAction> {
+        let mut csrs = self.csrs.write().unwrap();
+        if csrs.debug.halted {
+            return None;
+        }
+        csrs.triggers.fire(accesses, address)
+    }
+
+    pub fn pending_trap(&self) -> Option<Trap> {
+        *self.trap.lock().unwrap()
+    }
+
+    /// record an error of the model instead of panicking, only the first one raised is kept
+    /// `pc` is the address of the instruction being processed, if the error is tied to one
+    pub fn raise_sim_error(&self, kind: SimErrorKind, pc: Option<RiscWord>) {
+        let error = SimError { kind, pc, clock_cycle: self.clock_cycle.load(std::sync::atomic::Ordering::SeqCst) };
+        if self.debug {
+            println!("Simulation error: {error}");
+        } else {
+            tracing::error!("Simulation error: {error}");
+        }
+        let mut pending = self.sim_error.lock().unwrap();
+        if pending.is_none() {
+            *pending = Some(error.clone());
+            drop(pending);
+            for observer in &self.observers {
+                observer.on_sim_error(self, &error);
+            }
+        }
+    }
+
+    pub fn sim_error(&self) -> Option<SimError> {
+        self.sim_error.lock().unwrap().clone()
+    }
+
+    pub fn add_reset_hook(&mut self, hook: impl Fn(&RiscCore) + Send + Sync + 'static) {
+        self.reset_hooks.push(Box::new(hook));
+    }
+
+    /// reset the whole SoC without building it again: registers, PC, pipeline registers, CSRs, CDB wires, pending traps, errors and
+    /// exit requests, buffers in front of the memories and the registers of every device behind the MMU
+    /// memories keep their content (a warm reset) unless `clear_memory` is set, the clock cycle counters keep counting
+    /// the program has to be loaded again after clearing the memory, its symbols are kept
+    pub fn reset(&mut self, clear_memory: bool) {
+        self.registers = Registers::default();
+        self.set_pc(self.reset_vector);
+        self.flush_pipeline();
+        self.waiting_for_interrupt.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.data_access_pc.store(0, std::sync::atomic::Ordering::SeqCst);
+        self.events.lock().unwrap().clear();
+        self.software_breakpoint.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.halt_request.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.debug_step_issued.store(false, std::sync::atomic::Ordering::SeqCst);
+        self.csrs.write().unwrap().debug = DebugRegisters::default();
+        if let Some(vector) = &self.vector {
+            vector.write().unwrap().reset();
+        }
+        *self.trap.lock().unwrap() = None;
+        *self.sim_error.lock().unwrap() = None;
+        self.exit_signal.clear();
+        if let Some(buffer) = &self.store_buffer {
+            buffer.lock().unwrap().clear();
+        }
+        if let Some(buffer) = &self.fetch_buffer {
+            buffer.lock().unwrap().clear();
+        }
+        self.decode_cache.lock().unwrap().clear();
+        for cache in [&self.icache, &self.dcache].into_iter().flatten() {
+            let mut cache = cache.write().unwrap();
+            cache.invalidate();
+            cache.reset(clear_memory);
+        }
+        // the interrupt lines are levels, the devices raise them again on their next tick if they still have to
+        self.interrupt_lines().store(0, std::sync::atomic::Ordering::SeqCst);
+        self.mmu.write().unwrap().reset(clear_memory);
+        // the symbols are kept by a reset, so the state that applied before still applies
+        if let Some(state) = &self.initial_state {
+            let applied = if clear_memory { state.apply(self) } else { state.apply_registers(self) };
+            applied.unwrap_or_else(|e| panic!("Failed to apply the initial state after a reset: {e}"));
+        }
+        for hook in &self.reset_hooks {
+            hook(self);
+        }
+    }
+
+    /// drop every instruction in flight, leaving the pipeline registers, handshake signals and CDB wires as after a reset
+    pub fn flush_pipeline(&self) {
+        let mut previous_bubble = PipelineData::default();
+        for stage in &self.stages {
+            let mut stage = stage.lock().unwrap();
+            stage.instruction = Instruction(0x0);
+            stage.bundle.clear();
+            stage.data_in = if previous_bubble.size() == stage.size_in {
+                previous_bubble
+            } else {
+                PipelineData(vec![0u8; stage.size_in])
+            };
+            stage.data_out = stage.bubble.clone();
+            stage.input_consumed = true;
+            previous_bubble = stage.bubble.clone();
+            // cycle budgets of the next runs count from the reset
+            stage.clock_cycle = 0;
+            // payloads sent on the last cycle of the previous run would be latched by the next one
+            if let Some(input_channel) = &stage.input_channel {
+                while input_channel.try_recv().is_ok() {}
+            }
+            self.cdb.clear(stage.index);
+        }
+        for stage_index in 0..self.pipeline_control_signals.len() {
+            self.reset_stage(stage_index, false);
+            self.enable_stage(stage_index, true);
+            self.set_stage_ready(stage_index, true);
+            self.set_output_valid(stage_index, false);
+        }
+        self.cdb.resume();
+        self.fetch_hold.store(false, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// warm reset requested by the program (ex. through the reset code of the test finisher), execution restarts from the
+    /// reset vector with the memories kept, as after a watchdog or software reset of an MCU
+    /// returns false if the last run did not stop on a reset request
+    pub fn reboot(&mut self) -> bool {
+        if self.exit_status() != Some(ExitStatus::Reset) {
+            return false;
+        }
+        self.reset(false);
+        true
+    }
+
+    pub fn take_trap(&self) -> Option<Trap> {
+        self.trap.lock().unwrap().take()
+    }
+
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_signal.status()
+    }
+
+    /// true once the simulation has to stop, because of an exception, an error of the model or because the program requested it
+    pub fn halted(&self) -> bool {
+        self.pending_trap().is_some() || self.exit_status().is_some() || self.sim_error.lock().unwrap().is_some()
+    }
+
+    /// model the fetch of whole lines of `line_size` bytes taking `latency` clock cycles each
+    /// the line being fetched and the next `depth - 1` sequential lines are held in a fetch queue
+    pub fn enable_prefetcher(&mut self, line_size: usize, depth: usize, latency: u64) {
+        self.fetch_buffer = Some(Mutex::new(FetchBuffer::new(line_size, depth, latency)));
+    }
+
+    pub fn prefetch_stats(&self) -> Option<PrefetchStats> {
+        self.fetch_buffer.as_ref().map(|buffer| buffer.lock().unwrap().stats)
+    }
+
+    /// micro-ops of the instruction at pc, decoded by `decode` only the first time the instruction is seen there
+    pub fn decode_cached<E>(
+        &self,
+        pc: RiscWord,
+        instruction: u32,
+        decode: impl FnOnce(u32) -> Result<Vec<MicroOp>, E>,
+    ) -> Result<Vec<MicroOp>, E> {
+        self.decode_cache.lock().unwrap().decode(pc as Address, instruction, decode)
+    }
+
+    pub fn decode_cache_stats(&self) -> DecodeCacheStats {
+        self.decode_cache.lock().unwrap().stats
+    }
+
+    /// instructions decoded from [address, address + len) are stale, ex. after a store or a load of the memory
+    fn invalidate_decoded(&self, address: Address, len: usize) {
+        self.decode_cache.lock().unwrap().invalidate_range(address, len);
+    }
+
+    /// false if the line of the PC did not arrive yet, the first stage then fetches a bubble and the PC is held
+    pub fn fetch_ready(&self, pc: RiscWord) -> bool {
+        let Some(buffer) = &self.fetch_buffer else {
+            return true;
+        };
+        let mut buffer = buffer.lock().unwrap();
+        let misses = buffer.stats.misses;
+        let ready = buffer.fetch(pc as Address);
+        let missed = buffer.stats.misses != misses;
+        drop(buffer);
+        if missed {
+            for observer in &self.observers {
+                observer.on_cache_miss(self, pc as Address, true);
+            }
+        }
+        if !ready {
+            self.fetch_hold.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+        ready
+    }
+
+    /// instruction port of the pipeline, returning the exception of a fetch that cannot complete
+    /// (misaligned PC, unmapped or not executable memory) instead of raising it, as the fetch may be on a wrong path
+    pub fn fetch_request(&self, request: MemoryRequest) -> Result<MemoryResponse, Exception> {
+        if !request.data_address.is_multiple_of(request.data_size as Address) {
+            return Err(Exception::InstructionAddressMisaligned);
+        }
+        let response = self.icache_request(request);
+        match response.status {
+            MemoryResponseType::CacheHit | MemoryResponseType::Valid => Ok(response),
+            _ => Err(Exception::InstructionAccessFault),
+        }
+    }
+
+    /// load/store port of the pipeline, applying the misaligned access policy of the core before going to the data memory
+    /// a misaligned access under the `Trap` policy raises the exception and is answered with `UnalignedAddress`
+    /// accesses denied by the permissions of a device raise a load/store access fault
+    pub fn data_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let address = request.data_address;
+        let observed = (!self.observers.is_empty()).then(|| request.clone());
+        let response = self.aligned_data_request(request);
+        if let Some(request) = observed {
+            self.observe_access(&request, &response);
+        }
+        match &response.status {
+            MemoryResponseType::NotReadable => self.raise_exception(Exception::LoadAccessFault, address),
+            MemoryResponseType::NotWrittable => self.raise_exception(Exception::StoreAccessFault, address),
+            MemoryResponseType::BadRequest(reason) => {
+                self.raise_sim_error(SimErrorKind::BadMemoryRequest { address, reason: reason.clone() }, None)
+            }
+            _ => {}
+        }
+        response
+    }
+
+    fn aligned_data_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let size = request.data_size as Address;
+        if request.data_address.is_multiple_of(size) {
+            return self.dcache_request(request);
+        }
+        match self.misaligned_policy {
+            MisalignedAccessPolicy::Trap => {
+                let exception = match request.request_type {
+                    MemoryRequestType::READ => Exception::LoadAddressMisaligned,
+                    MemoryRequestType::WRITE => Exception::StoreAddressMisaligned,
+                };
+                self.raise_exception(exception, request.data_address);
+                MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::UnalignedAddress }
+            }
+            MisalignedAccessPolicy::Split if self.mmu.read().unwrap().attributes(request.data_address).idempotent => {
+                self.split_request(request)
+            }
+            // each piece would have its own side effects on the registers of a device, so the access faults instead
+            MisalignedAccessPolicy::Split => {
+                let status = match request.request_type {
+                    MemoryRequestType::READ => MemoryResponseType::NotReadable,
+                    MemoryRequestType::WRITE => MemoryResponseType::NotWrittable,
+                };
+                MemoryResponse { data: MemoryData::default(), status }
+            }
+        }
+    }
+
+    /// perform a misaligned access as a sequence of the largest naturally aligned accesses covering it
+    /// line sizes are multiples of the word size, so none of the pieces crosses a cache line
+    fn split_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let size = request.data_size as usize;
+        let mut data = MemoryData::zeroed(size);
+        let mut offset = 0;
+        while offset < size {
+            let address = request.data_address + offset as Address;
+            let piece_size = [WordSize::DOUBLE, WordSize::WORD, WordSize::HALF, WordSize::BYTE]
+                .into_iter()
+                .find(|piece| *piece as usize <= size - offset && address.is_multiple_of(*piece as Address))
+                .unwrap();
+            let piece_len = piece_size as usize;
+            let response = self.dcache_request(MemoryRequest {
+                request_type: request.request_type,
+                data_address: address,
+                data_size: piece_size,
+                data: request.data.map(|bytes| MemoryData::new(&bytes[offset..offset + piece_len])),
+            });
+            if response.status != MemoryResponseType::CacheHit && response.status != MemoryResponseType::Valid {
+                return response;
+            }
+            if request.request_type == MemoryRequestType::READ {
+                data[offset..offset + piece_len].copy_from_slice(&response.data[..piece_len]);
+            }
+            offset += piece_len;
+        }
+        if request.request_type == MemoryRequestType::WRITE {
+            data = MemoryData::default();
+        }
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    /// id of the hart (mhartid), also identifying its LR reservations in the shared MMU
+    pub fn hart_id(&self) -> RiscWord {
+        self.csrs.read().unwrap().info.hart_id
+    }
+
+    /// AMO: atomic read-modify-write of an aligned word or double word, answered with the previous value
+    /// the MMU stays locked during the whole access, like a locked bus transaction, so the harts sharing it cannot interleave
+    pub fn atomic_request(&self, address: Address, size: WordSize, op: impl FnOnce(u64) -> u64) -> MemoryResponse {
+        if let Some(response) = self.misaligned_atomic(address, size, Exception::StoreAddressMisaligned) {
+            return response;
+        }
+        let mut mmu = self.mmu.write().unwrap();
+        if let Some(response) = self.non_atomic_region(&mmu, address, Exception::StoreAccessFault) {
+            return response;
+        }
+        let read = MemoryRequest::read(address, size);
+        let response = self.bus_locked_request(&mut mmu, read.clone());
+        if !Self::completed(&response) {
+            self.raise_exception(Exception::StoreAccessFault, address);
+            return response;
+        }
+        let mut old_value = [0u8; 8];
+        old_value[..size as usize].copy_from_slice(&response.data[..size as usize]);
+        let new_value = op(u64::from_le_bytes(old_value)).to_le_bytes();
+        let write = MemoryRequest::write(address, &new_value[..size as usize]);
+        let write_response = self.bus_locked_request(&mut mmu, write.clone());
+        if !Self::completed(&write_response) {
+            self.raise_exception(Exception::StoreAccessFault, address);
+            return write_response;
+        }
+        // observers may access the memory themselves, so they are notified once the bus is released
+        drop(mmu);
+        self.observe_access(&read, &response);
+        self.observe_access(&write, &write_response);
+        response
+    }
+
+    /// LR: load a word or double word and reserve the block holding it for this hart
+    pub fn load_reserved(&self, address: Address, size: WordSize) -> MemoryResponse {
+        if let Some(response) = self.misaligned_atomic(address, size, Exception::LoadAddressMisaligned) {
+            return response;
+        }
+        let mut mmu = self.mmu.write().unwrap();
+        if let Some(response) = self.non_atomic_region(&mmu, address, Exception::LoadAccessFault) {
+            return response;
+        }
+        let read = MemoryRequest::read(address, size);
+        let response = self.bus_locked_request(&mut mmu, read.clone());
+        if Self::completed(&response) {
+            mmu.reserve(self.hart_id(), address);
+            drop(mmu);
+            self.observe_access(&read, &response);
+        } else {
+            self.raise_exception(Exception::LoadAccessFault, address);
+        }
+        response
+    }
+
+    /// SC: store only if this hart still holds the reservation made by its last LR, returns whether the store was performed
+    pub fn store_conditional(&self, address: Address, data: &[u8]) -> bool {
+        let size = MemoryRequest::write(address, data).data_size;
+        if self.misaligned_atomic(address, size, Exception::StoreAddressMisaligned).is_some() {
+            return false;
+        }
+        let mut mmu = self.mmu.write().unwrap();
+        if self.non_atomic_region(&mmu, address, Exception::StoreAccessFault).is_some() {
+            return false;
+        }
+        if !mmu.take_reservation(self.hart_id(), address) {
+            return false;
+        }
+        let write = MemoryRequest::write(address, data);
+        let response = self.bus_locked_request(&mut mmu, write.clone());
+        if !Self::completed(&response) {
+            self.raise_exception(Exception::StoreAccessFault, address);
+            return false;
+        }
+        drop(mmu);
+        self.observe_access(&write, &response);
+        true
+    }
+
+    /// atomics are never split, whatever the misaligned access policy of the core
+    fn misaligned_atomic(&self, address: Address, size: WordSize, exception: Exception) -> Option<MemoryResponse> {
+        if address.is_multiple_of(size as Address) {
+            return None;
+        }
+        self.raise_exception(exception, address);
+        Some(MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::UnalignedAddress })
+    }
+
+    /// atomics on a region without atomic support (ex. I/O registers) raise an access fault
+    fn non_atomic_region(&self, mmu: &MemoryManagementUnit, address: Address, exception: Exception) -> Option<MemoryResponse> {
+        if mmu.attributes(address).atomic {
+            return None;
+        }
+        self.raise_exception(exception, address);
+        let status = match exception {
+            Exception::LoadAccessFault => MemoryResponseType::NotReadable,
+            _ => MemoryResponseType::NotWrittable,
+        };
+        Some(MemoryResponse { data: MemoryData::default(), status })
+    }
+
+    /// access to a region that is not cacheable, traced with the name of the device register when the device describes it
+    fn mmio_request(&self, mmu: &mut MemoryManagementUnit, request: MemoryRequest) -> MemoryResponse {
+        let traced = (self.debug || tracing::enabled!(tracing::Level::INFO)).then(|| request.clone());
+        let response = mmu.process_memory_request(request);
+        if let Some(description) = traced.and_then(|request| mmu.describe_access(&request, &response)) {
+            let clock_cycle = self.clock_cycle.load(std::sync::atomic::Ordering::SeqCst);
+            if self.debug {
+                println!("MMIO @ClockCycle {clock_cycle} -> {description}");
+            } else {
+                tracing::info!("MMIO @ClockCycle {clock_cycle} -> {description}");
+            }
+        }
+        response
+    }
+
+    /// data access performed while the caller holds the MMU lock, the private L1 memories are checked first
+    fn bus_locked_request(&self, mmu: &mut MemoryManagementUnit, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::WRITE {
+            self.invalidate_decoded(request.data_address, request.data_size as usize);
+        }
+        if !mmu.attributes(request.data_address).cacheable {
+            return self.mmio_request(mmu, request);
+        }
+        for cache in [&self.dcache, &self.icache] {
+            if let Some(response) = Self::sibling_cache_request(cache, request.clone()) {
+                return response;
+            }
+        }
+        self.shared_memory_request(mmu, request)
+    }
+
+    /// accesses going past the L1 memories, through the coherent cache if it holds the address and the region is cacheable
+    fn shared_memory_request(&self, mmu: &mut MemoryManagementUnit, request: MemoryRequest) -> MemoryResponse {
+        match &self.coherent_cache {
+            Some(cache) if cache.holds(request.data_address) && mmu.attributes(request.data_address).cacheable => {
+                let misses = (!self.observers.is_empty()).then(|| cache.stats().misses);
+                let address = request.data_address;
+                let response = cache.access(mmu, request);
+                if misses.is_some_and(|misses| cache.stats().misses != misses) {
+                    for observer in &self.observers {
+                        observer.on_cache_miss(self, address, false);
+                    }
+                }
+                response
+            }
+            _ => mmu.process_memory_request(request),
+        }
+    }
+
+    /// clock cycles a data access waits on top of the cycle of the stage issuing it
+    /// the L1 memories answer within the cycle, only the devices behind the MMU add their latency
+    pub fn data_access_latency(&self, request: &MemoryRequest) -> u64 {
+        if self.in_l1(request.data_address) { 0 } else { self.mmu.write().unwrap().access_latency(request) }
+    }
+
+    /// buffer the stores of MEM in front of the data memory, combining the stores to the same block
+    pub fn enable_store_buffer(&mut self, depth: usize) {
+        self.store_buffer = Some(Mutex::new(StoreBuffer::new(depth)));
+    }
+
+    pub fn store_buffer_stats(&self) -> Option<StoreBufferStats> {
+        self.store_buffer.as_ref().map(|buffer| buffer.lock().unwrap().stats)
+    }
+
+    /// statistics of the run so far, one line per model enabled on the core and one per device reached by the data accesses
+    /// the devices with the most accesses come first, to find the MMIO registers polled by the firmware
+    pub fn statistics_report(&self) -> String {
+        let cycles = self.stages.first().map_or(0, |stage| stage.lock().unwrap().clock_cycle);
+        let mut report = format!("clock cycles: {cycles}\n");
+        if let Some(stats) = self.store_buffer_stats() {
+            report += &format!("store buffer: {} stores, {} combined, {} full stalls\n", stats.stores, stats.combined, stats.full_stalls);
+        }
+        if let Some(stats) = self.prefetch_stats() {
+            report += &format!("prefetcher: {} misses, coverage {:.0}%, accuracy {:.0}%\n", stats.misses, stats.coverage() * 100.0, stats.accuracy() * 100.0);
+        }
+        let decode_cache = self.decode_cache_stats();
+        if decode_cache.hits + decode_cache.misses > 0 {
+            report += &format!("decode cache: hit rate {:.0}%\n", decode_cache.hit_rate() * 100.0);
+        }
+        let mmu = self.mmu.read().unwrap();
+        let crossings = mmu.crossing_stats();
+        if crossings.crossings > 0 {
+            report += &format!("clock crossings: {} accesses, {} synchronizer cycles\n", crossings.crossings, crossings.synchronizer_cycles);
+        }
+        let mut devices = mmu.access_stats();
+        devices.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.accesses()));
+        report += &format!("{:<16}{:>12}{:>12}{:>14}{:>14}\n", "memory accesses", "reads", "writes", "bytes", "avg latency");
+        let totals = std::iter::once(("total".to_string(), mmu.total_access_stats()));
+        let rows = devices.into_iter().map(|(id, stats)| match id.instance {
+            0 => (format!("{:?}", id.memory_type), stats),
+            instance => (format!("{:?}[{instance}]", id.memory_type), stats),
+        });
+        for (name, stats) in rows.chain(totals) {
+            report += &format!("{name:<16}{:>12}{:>12}{:>14}{:>14.2}\n", stats.reads, stats.writes, stats.bytes, stats.average_latency());
+        }
+        report
+    }
+
+    /// store of MEM, returning the clock cycles it waits on top of the cycle of the stage
+    /// stores that might fault or have side effects (misaligned, denied, unmapped or to I/O) are not buffered
+    /// they wait for the buffer to drain and are performed in place, so their exceptions stay precise
+    pub fn buffered_store(&self, request: MemoryRequest) -> u64 {
+        let Some(buffer) = &self.store_buffer else {
+            let latency = self.data_access_latency(&request);
+            self.data_request(request);
+            return latency;
+        };
+        let address = request.data_address;
+        let size = request.data_size as Address;
+        let bufferable = address.is_multiple_of(size) && {
+            let mmu = self.mmu.read().unwrap();
+            let attributes = mmu.attributes(address);
+            attributes.cacheable
+                && attributes.permissions.write
+                && (self.in_l1(address) || mmu.device_at(address).is_some())
+        };
+        if !bufferable {
+            let latency = self.drain_store_buffer(None) + self.data_access_latency(&request);
+            self.data_request(request);
+            return latency;
         }
+        let data = request.data.expect("A store was buffered without data");
+        let mut latency = 0;
+        let pc = self.data_access_pc.load(std::sync::atomic::Ordering::SeqCst);
+        if !buffer.lock().unwrap().push(address, &data[..size as usize], pc) {
+            buffer.lock().unwrap().stats.full_stalls += 1;
+            latency = self.drain_store_buffer(Some(1));
+            assert!(buffer.lock().unwrap().push(address, &data[..size as usize], pc));
+        }
+        latency
     }
 
-    pub fn enable_debug(&mut self, debug: bool){
-        self.debug = debug;
-        for cdb_lane in &mut self.cdb.bus {
-            for wire in cdb_lane.1 {
-                wire.enable_debug(debug);
+    /// write the given number of the oldest entries (all of them if None) to the data memory
+    /// returns the clock cycles waited for the port and for the writes
+    pub fn drain_store_buffer(&self, entries: Option<usize>) -> u64 {
+        let Some(buffer) = &self.store_buffer else {
+            return 0;
+        };
+        let mut latency = std::mem::take(&mut buffer.lock().unwrap().busy);
+        for _ in 0..entries.unwrap_or(usize::MAX) {
+            let mut buffer = buffer.lock().unwrap();
+            let pc = buffer.oldest_pc();
+            let Some(requests) = buffer.pop() else {
+                break;
+            };
+            drop(buffer);
+            for request in requests {
+                latency += self.data_access_latency(&request);
+                self.buffer_write(request, pc.unwrap());
             }
         }
-        for pipeline_stage in &self.stages{
-            let mut lock = pipeline_stage.lock().unwrap();
-            lock.enable_debug(debug);
+        latency
+    }
+
+    /// drain the older stores overlapping a load, so the load reads them from memory
+    pub fn drain_overlapping_stores(&self, address: Address, len: usize) -> u64 {
+        let Some(buffer) = &self.store_buffer else {
+            return 0;
+        };
+        let pending = buffer.lock().unwrap().pending_before(address, len);
+        if pending == 0 {
+            return 0;
         }
+        buffer.lock().unwrap().stats.load_drains += 1;
+        self.drain_store_buffer(Some(pending))
     }
 
-    pub fn add_l1_cache(
-        &mut self,
-        icache: Box<dyn Cache + Send + Sync>,
-        dcache: Box<dyn Cache + Send + Sync>,
-    ) -> &mut Self {
-        self.icache = Some(Arc::new(RwLock::new(icache)));
-        self.dcache = Some(Arc::new(RwLock::new(dcache)));
-        self
+    /// clock cycle of MEM not holding for a slow access, the buffer writes its oldest entry while the data port is free
+    pub fn store_buffer_cycle(&self, port_free: bool) {
+        let Some(buffer) = &self.store_buffer else {
+            return;
+        };
+        let mut buffer = buffer.lock().unwrap();
+        if buffer.busy > 0 {
+            buffer.busy -= 1;
+            return;
+        }
+        if !port_free {
+            return;
+        }
+        let pc = buffer.oldest_pc();
+        let Some(requests) = buffer.pop() else {
+            return;
+        };
+        drop(buffer);
+        let mut latency = 0;
+        for request in requests {
+            latency += self.data_access_latency(&request);
+            self.buffer_write(request, pc.unwrap());
+        }
+        self.store_buffer.as_ref().unwrap().lock().unwrap().busy = latency;
     }
 
-    pub fn add_mmu(&mut self, mmu: MemoryManagementUnit) {
-        self.mmu = Arc::new(RwLock::new(mmu));
+    /// write of a store buffer entry, the checks of `data_request` were done when the store was buffered
+    /// the observers see the PC of the store, not the one of the instruction in MEM while the entry drains
+    fn buffer_write(&self, request: MemoryRequest, pc: Address) {
+        let observed = (!self.observers.is_empty()).then(|| request.clone());
+        let response = self.dcache_request(request);
+        if let Some(request) = observed {
+            let current = self.data_access_pc.swap(pc, std::sync::atomic::Ordering::SeqCst);
+            self.observe_access(&request, &response);
+            self.data_access_pc.store(current, std::sync::atomic::Ordering::SeqCst);
+        }
     }
 
-    pub fn set_clock_period(&mut self, nanosecs: u128) {
-        self.clock_period = Some(nanosecs);
+    /// true if the address is held by one of the L1 memories of the core
+    pub(crate) fn in_l1(&self, address: Address) -> bool {
+        [&self.dcache, &self.icache].into_iter().flatten().any(|cache| {
+            let (start, end) = cache.read().unwrap().start_end_addresses();
+            address >= start && address < end
+        })
     }
 
-    pub fn icache_request(&self, request: MemoryRequest) -> MemoryResponse {
-        if self.icache.is_some() {
-            let cache_response = self
-                .icache.as_ref()
-                .unwrap()
-                .write()
-                .unwrap()
-                .send_data_request(request.clone());
+    fn completed(response: &MemoryResponse) -> bool {
+        response.status == MemoryResponseType::CacheHit || response.status == MemoryResponseType::Valid
+    }
+
+    /// accesses to regions that are not cacheable (ex. MMIO) bypass the caches and go straight to the MMU
+    pub fn dcache_request(&self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::WRITE {
+            self.invalidate_decoded(request.data_address, request.data_size as usize);
+        }
+        if !self.mmu.read().unwrap().attributes(request.data_address).cacheable {
+            return self.mmio_request(&mut self.mmu.write().unwrap(), request);
+        }
+        if let Some(dcache) = &self.dcache {
+            let cache_response = dcache.write().unwrap().send_data_request(request.clone());
             if cache_response.status == MemoryResponseType::CacheHit {
                 cache_response
+            } else if let Some(response) = Self::sibling_cache_request(&self.icache, request.clone()) {
+                // loads and stores may target instruction memory, ex. for self-modifying code
+                response
             } else {
-                self.mmu.write().unwrap().process_memory_request(request)
+                self.shared_memory_request(&mut self.mmu.write().unwrap(), request)
             }
         } else {
             panic!("An L1Cache request was made, but there is no L1Cache configured on this core!")
         }
     }
 
-    pub fn dcache_request(&self, request: MemoryRequest) -> MemoryResponse {
-        if self.dcache.is_some() {
-            let cache_response = self
-                .dcache.as_ref()
-                .unwrap()
-                .write()
-                .unwrap()
-                .send_data_request(request.clone());
-            if cache_response.status == MemoryResponseType::CacheHit {
-                cache_response
-            } else {
-                self.mmu.write().unwrap().process_memory_request(request)
-            }
+    /// when L1 caches act as the actual memory of the core (ex. an MCU) the instruction and data memories hold disjoint ranges
+    /// a request that misses in one of them is passed to the other one and only answered if it hits there
+    fn sibling_cache_request(
+        cache: &Option<Arc<RwLock<Box<dyn Cache + Send + Sync>>>>,
+        request: MemoryRequest,
+    ) -> Option<MemoryResponse> {
+        let cache = cache.as_ref()?;
+        let response = cache.write().unwrap().send_data_request(request);
+        if response.status == MemoryResponseType::CacheHit {
+            Some(response)
         } else {
-            panic!("An L1Cache request was made, but there is no L1Cache configured on this core!")
+            None
+        }
+    }
+
+    /// memory port for devices acting as bus masters, served by the L1 data memory of the core
+    pub fn data_bus_master(&self) -> BusMaster {
+        let dcache = self
+            .dcache
+            .clone()
+            .expect("A bus master was requested, but there is no L1Cache configured on this core!");
+        Arc::new(move |request| dcache.write().unwrap().send_data_request(request))
+    }
+
+    /// memory port for bus masters running on their own thread (ex. a DMA engine)
+    /// requests follow the same path as the core data accesses, so they contend with the core for the memories and the MMU
+    pub fn system_bus_master(&self) -> BusMaster {
+        let dcache = self.dcache.clone();
+        let icache = self.icache.clone();
+        let mmu = self.mmu.clone();
+        Arc::new(move |request: MemoryRequest| {
+            for cache in [&dcache, &icache] {
+                if let Some(response) = Self::sibling_cache_request(cache, request.clone()) {
+                    return response;
+                }
+            }
+            mmu.write().unwrap().process_memory_request(request)
+        })
+    }
+
+    /// FENCE.I: make all previous stores visible to the following instruction fetches
+    /// stale instructions already in the pipeline must be flushed by the caller
+    pub fn fence_i(&self) {
+        self.decode_cache.lock().unwrap().clear();
+        if let Some(icache) = &self.icache {
+            icache.write().unwrap().invalidate();
         }
     }
 
@@ -139,10 +1391,20 @@ impl RiscCore {
             panic!("Trying to add more stages then configured for current core!");
         }
         stage.enable_debug(self.debug);
+        // until the previous stage produces its first output the stage sees it holding a bubble
+        if let Some(previous) = self.stages.last() {
+            let previous = previous.lock().unwrap();
+            if previous.size_out == stage.size_in {
+                stage.data_in = previous.bubble.clone();
+            }
+        }
         self.stages.push(Arc::new(Mutex::new(stage)));
-        let mut control_signals = vec![];
-        control_signals.push(AtomicBool::new(false)); //reset
-        control_signals.push(AtomicBool::new(true)); //enable
+        let control_signals = vec![
+            AtomicBool::new(false), //reset
+            AtomicBool::new(true),  //enable
+            AtomicBool::new(true),  //ready
+            AtomicBool::new(false), //valid
+        ];
         self.pipeline_control_signals.push(control_signals);
         self
     }
@@ -167,26 +1429,102 @@ impl RiscCore {
         stage_control_signals[ENABLE_SIGNAL].load(std::sync::atomic::Ordering::SeqCst)
     }
 
-    /// load a binary file containing the code to be executed
-    pub fn load_binary(&mut self, elf_path: &str, memory_device: MemoryDeviceType) {
-        let data = fs::read(elf_path).expect("Could not read provided elf file path");
-        let elf =
-            elf::FileHeader32::<object::Endianness>::parse(&*data).expect("Failed to parse elf");
+    /// a stage which is not ready keeps its input for the next clock cycle, ex. while a multi-cycle operation completes
+    /// its output register is still written, and the previous stages stall as long as their output is not consumed
+    pub fn set_stage_ready(&self, stage_index: usize, ready_value: bool) {
+        let stage_control_signals = &self.pipeline_control_signals[stage_index];
+        stage_control_signals[READY_SIGNAL].store(ready_value, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    pub fn is_stage_ready(&self, stage_index: usize) -> bool {
+        let stage_control_signals = &self.pipeline_control_signals[stage_index];
+        stage_control_signals[READY_SIGNAL].load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// the output register of the stage holds an instruction the next stage did not consume yet
+    pub fn is_output_valid(&self, stage_index: usize) -> bool {
+        let stage_control_signals = &self.pipeline_control_signals[stage_index];
+        stage_control_signals[VALID_SIGNAL].load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn set_output_valid(&self, stage_index: usize, valid_value: bool) {
+        let stage_control_signals = &self.pipeline_control_signals[stage_index];
+        stage_control_signals[VALID_SIGNAL].store(valid_value, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    /// resolve the handshake from the last stage back to the given one, as a stage can only overwrite its output register
+    /// once the next stage consumes it:
+    /// - a reset stage writes its bubble and consumes its input if it is ready, whatever happens downstream (a flush)
+    /// - a disabled stage, or one whose output is not consumed, keeps its output and its input (a stall)
+    /// - otherwise the stage writes its output, and consumes its input if it is ready
+    fn handshake(&self, stage_index: usize) -> Handshake {
+        let last_index = self.pipeline_control_signals.len() - 1;
+        let mut handshake = Handshake { write: true, valid: false, consumed: true };
+        let mut downstream_consumed = true;
+        for index in (stage_index..=last_index).rev() {
+            let valid = index != last_index && self.is_output_valid(index);
+            let ready = self.is_stage_ready(index);
+            handshake = if self.is_stage_reset(index) {
+                Handshake { write: true, valid: false, consumed: ready }
+            } else if !self.is_stage_enabled(index) || !downstream_consumed {
+                Handshake { write: false, valid: valid && !downstream_consumed, consumed: false }
+            } else {
+                Handshake { write: true, valid: index != last_index, consumed: ready }
+            };
+            downstream_consumed = handshake.consumed;
+        }
+        handshake
+    }
+
+    /// load a binary file containing the code to be executed, the reset vector moves to its entry point
+    /// both ELF32 and ELF64 headers are parsed, but the class must match the XLEN of the core
+    pub fn load_binary(&mut self, elf_path: &str, memory_device: MemoryDeviceType) -> Result<(), LoadError> {
+        let data = fs::read(elf_path)?;
+        self.load_elf_bytes(&data, memory_device)
+    }
+
+    /// same as `load_binary`, for an ELF image already in memory (ex. embedded with include_bytes!)
+    pub fn load_elf_bytes(&mut self, data: &[u8], memory_device: MemoryDeviceType) -> Result<(), LoadError> {
+        let core_bits = XLEN;
+        match data.get(EI_CLASS).copied() {
+            Some(elf::ELFCLASS64) if core_bits == 64 => {
+                self.load_elf_image::<elf::FileHeader64<Endianness>>(data, memory_device)
+            }
+            Some(elf::ELFCLASS32) if core_bits == 32 => {
+                self.load_elf_image::<elf::FileHeader32<Endianness>>(data, memory_device)
+            }
+            Some(elf::ELFCLASS64) => Err(LoadError::WrongClass { elf_bits: 64, core_bits }),
+            Some(elf::ELFCLASS32) => Err(LoadError::WrongClass { elf_bits: 32, core_bits }),
+            // let the parser report what is wrong with the header
+            _ => self.load_elf_image::<elf::FileHeader32<Endianness>>(data, memory_device),
+        }
+    }
 
-        let endian = elf.endian().expect("Failed to parse endianess");
-        assert!(endian == Endianness::Little);
+    fn load_elf_image<Elf: FileHeader<Endian = Endianness>>(
+        &mut self,
+        data: &[u8],
+        memory_device: MemoryDeviceType,
+    ) -> Result<(), LoadError> {
+        let elf = Elf::parse(data)?;
+        let endian = elf.endian()?;
+        if endian == Endianness::Big {
+            return Err(LoadError::BigEndian);
+        }
+        let machine = elf.e_machine(endian);
+        if machine != elf::EM_RISCV {
+            return Err(LoadError::NotRiscV(machine));
+        }
 
         //read sections
-        let sections = elf
-            .sections(endian, &*data)
-            .expect("Failed to parse sections of elf file");
-        let section_headers = sections.iter().filter(|x| {
-            let mut name: String = Default::default();
+        let sections = elf.sections(endian, data)?;
+        let section_name = |section: &Elf::SectionHeader| -> String {
             sections
-                .section_name(endian, x)
-                .unwrap()
-                .read_to_string(&mut name)
-                .unwrap();
+                .section_name(endian, section)
+                .map(|name| String::from_utf8_lossy(name).to_string())
+                .unwrap_or_default()
+        };
+        let section_headers = sections.iter().filter(|x| {
+            let name = section_name(x);
             name.contains(".text")
                 || name.contains(".data")
                 || name.contains(".sdata")
@@ -194,106 +1532,452 @@ impl RiscCore {
                 || name.contains(".bss")
                 || name.contains(".sbss")
         });
-        for section in section_headers {
-            let mut name: String = Default::default();
-            sections
-                .section_name(endian, section)
-                .unwrap()
-                .read_to_string(&mut name)
-                .unwrap();
-            let data = section
-                .data(endian, &*data)
-                .expect("Failed to read section data");
-            let address = section.sh_addr.get(endian) as Address;
-            //let size = section.sh_size.get(endian);
-            //println!("{name} @{:X}:{:X}", address, size);
-            //println!("Data: {:?}", data);
+        // keep the symbols of the program for traces and address lookups
+        self.symbols.clear();
+        if let Ok(symtab) = sections.symbols(endian, data, elf::SHT_SYMTAB) {
+            for symbol in symtab.symbols() {
+                let symbol_type = symbol.st_type();
+                if symbol_type != elf::STT_FUNC && symbol_type != elf::STT_OBJECT && symbol_type != elf::STT_NOTYPE {
+                    continue;
+                }
+                let Ok(name) = symbol.name(endian, symtab.strings()) else {
+                    continue;
+                };
+                if name.is_empty() || symbol.st_shndx(endian) == elf::SHN_UNDEF {
+                    continue;
+                }
+                self.symbols.insert(Symbol {
+                    name: String::from_utf8_lossy(name).to_string(),
+                    address: symbol.st_value(endian).into(),
+                    size: symbol.st_size(endian).into(),
+                });
+            }
+        }
+
+        // (name, address, content, executable) of the parts of the image to copy
+        let mut chunks: Vec<(String, Address, Vec<u8>, bool)> = vec![];
+        match self.elf_load_mode {
+            ElfLoadMode::Sections => {
+                for section in section_headers {
+                    let name = section_name(section);
+                    let address: Address = section.sh_addr(endian).into();
+                    // .bss and .sbss take no room in the file, their memory is zeroed
+                    let content = if section.sh_type(endian) == elf::SHT_NOBITS {
+                        vec![0u8; section.sh_size(endian).into() as usize]
+                    } else {
+                        section.data(endian, data)?.to_vec()
+                    };
+                    let executable = name.contains(".text");
+                    chunks.push((name, address, content, executable));
+                }
+            }
+            ElfLoadMode::Segments => {
+                for segment in elf.program_headers(endian, data)? {
+                    if segment.p_type(endian) != elf::PT_LOAD {
+                        continue;
+                    }
+                    let offset: Address = segment.p_offset(endian).into();
+                    let name = format!("segment @0x{offset:X}");
+                    let mut content = segment
+                        .data(endian, data)
+                        .map_err(|()| std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("{name} is past the end of the file")))?
+                        .to_vec();
+                    let memory_size: Address = segment.p_memsz(endian).into();
+                    content.resize(content.len().max(memory_size as usize), 0);
+                    let executable = segment.p_flags(endian) & elf::PF_X != 0;
+                    chunks.push((name, segment.p_paddr(endian).into(), content, executable));
+                }
+            }
+        }
+
+        for (name, address, section_data, executable) in chunks {
+            let out_of_range = || LoadError::OutOfRange {
+                name: name.clone(),
+                address,
+                size: section_data.len(),
+            };
 
             if memory_device < MemoryDeviceType::L2CACHE {
                 // in the case where we are using cache memories as the only level of memory
                 // we split the sections as .text in icache and everything else in dcache
-                assert!(self.icache.is_some() && self.dcache.is_some());
-                let mut icache = self.icache.as_ref().unwrap().write().unwrap();
-                let mut dcache = self.dcache.as_ref().unwrap().write().unwrap();
-                if name.contains(".text") {
-                    let (start, end) = icache.start_end_addresses();
-                    let cache_size = icache.size();
-                    assert!(
-                        address >= start
-                            && address < end
-                            && (address - start) as usize + data.len() < cache_size
-                    );
-                    icache.init_mem(address - start, data);
+                let (Some(icache), Some(dcache)) = (&self.icache, &self.dcache) else {
+                    return Err(out_of_range());
+                };
+                let mut memory = if executable {
+                    icache.write().unwrap()
                 } else {
-                    let (start, end) = dcache.start_end_addresses();
-                    let cache_size = dcache.size();
-                    assert!(
-                        address >= start
-                            && address < end
-                            && (address - start) as usize + data.len() < cache_size
-                    );
-                    dcache.init_mem(address - start, data);
+                    dcache.write().unwrap()
+                };
+                let (start, end) = memory.start_end_addresses();
+                if address < start || address >= end || (address - start) as usize + section_data.len() > memory.size() {
+                    return Err(out_of_range());
                 }
-
+                memory.init_mem(address - start, &section_data);
             } else {
                 //map to the selected memory device (ex. DRAM)
                 // here, usually all sections will be mapped in same memory region
                 let mut mmu = self.mmu.write().unwrap();
-                mmu.init_section_into_memory(address as Address, data);
+                mmu.init_section_into_memory(address, &section_data);
+            }
+            self.observe_init(address, section_data.len());
+        }
+        // execution starts from the entry point of the program, unless the core was told to start elsewhere (ex. a boot ROM)
+        let entry: u64 = elf.e_entry(endian).into();
+        self.set_reset_vector(self.entry_override.unwrap_or(entry as RiscWord));
+        Ok(())
+    }
+
+    /// load a raw binary image (ex. a flash dump or objcopy -O binary output) starting at `base_address`
+    pub fn load_raw(&mut self, path: &str, base_address: Address) -> Result<(), LoadError> {
+        let data = fs::read(path)?;
+        self.init_memory(base_address, &data);
+        Ok(())
+    }
+
+    /// assemble a program written inline (ex. by a test) at `base_address`, its labels become symbols of the core
+    pub fn load_assembly(&mut self, source: &str, base_address: Address) -> Result<Assembly, AsmError> {
+        let assembly = asm::assemble(source, base_address)?;
+        self.init_memory(base_address, &assembly.bytes());
+        for (name, &address) in &assembly.labels {
+            self.symbols.insert(Symbol { name: name.clone(), address, size: 0 });
+        }
+        Ok(assembly)
+    }
+
+    /// load an Intel HEX image, returning its start address record if present
+    pub fn load_intel_hex(&mut self, path: &str) -> Result<Option<Address>, LoadError> {
+        let content = fs::read_to_string(path)?;
+        let image = image_formats::parse_intel_hex(&content)?;
+        for (address, data) in &image.chunks {
+            self.init_memory(*address, data);
+        }
+        Ok(image.entry)
+    }
+
+    /// load a Motorola S-record image, returning its start address record if present
+    pub fn load_srec(&mut self, path: &str) -> Result<Option<Address>, LoadError> {
+        let content = fs::read_to_string(path)?;
+        let image = image_formats::parse_srec(&content)?;
+        for (address, data) in &image.chunks {
+            self.init_memory(*address, data);
+        }
+        Ok(image.entry)
+    }
+
+    /// write data directly into whatever memory holds the given address, L1 memories first and then the MMU devices
+    pub fn init_memory(&mut self, address: Address, data: &[u8]) {
+        self.invalidate_decoded(address, data.len());
+        self.observe_init(address, data.len());
+        for cache in [&self.dcache, &self.icache].into_iter().flatten() {
+            let mut cache = cache.write().unwrap();
+            let (start, end) = cache.start_end_addresses();
+            if address >= start && address + data.len() as Address <= end {
+                cache.init_mem(address - start, data);
+                return;
+            }
+        }
+        self.mmu.write().unwrap().init_section_into_memory(address, data);
+    }
+
+    /// address, encoding and assembly of the instructions in [start_address, end_address), read without side effects
+    /// returns None if any of the bytes is not mapped to a memory
+    pub fn disassemble(&self, start_address: Address, end_address: Address) -> Option<Vec<(Address, u32, String)>> {
+        assert!(end_address >= start_address);
+        let code = self.peek_memory(start_address, (end_address - start_address) as usize)?;
+        Some(disasm::disassemble_block(start_address, &code, Some(&self.symbols)))
+    }
+
+    /// read memory byte by byte without side effects, used by debuggers and tests
+    /// returns None if any of the bytes is not mapped to a memory
+    pub fn peek_memory(&self, address: Address, len: usize) -> Option<Vec<u8>> {
+        self.read_mem(address, len).ok()
+    }
+
+    /// write memory byte by byte as the stores of the core would, so cached and shared copies stay coherent
+    /// no exception is raised, returns None if any of the bytes is not mapped or not writable
+    /// unlike `write_mem` the registers of the devices are reached as well, ex. by the system bus access of the debug module
+    pub fn poke_memory(&self, address: Address, data: &[u8]) -> Option<()> {
+        self.store_bytes(address, data).ok()
+    }
+
+    /// memory as currently seen by the program, including the lines not yet written back by the caches and the buffered stores
+    /// devices are read through their read only path, so a read has no side effect (ex. on a UART FIFO)
+    pub fn read_mem(&self, address: Address, len: usize) -> Result<Vec<u8>, MemoryAccessError> {
+        let mut bytes = Vec::with_capacity(len);
+        for current_address in address..address + len as Address {
+            let request = MemoryRequest::read(current_address, WordSize::BYTE);
+            let mut response = None;
+            for cache in [&self.icache, &self.dcache].into_iter().flatten() {
+                let cache = cache.read().unwrap();
+                let (start, end) = cache.start_end_addresses();
+                if current_address >= start && current_address < end {
+                    response = Some(cache.read_request(request.clone()));
+                    break;
+                }
+            }
+            // a modified line of the coherent caches is newer than the memory behind it
+            if response.is_none() {
+                let cached = self.coherent_cache.as_ref().and_then(|cache| cache.peek(current_address));
+                if let Some(byte) = cached {
+                    bytes.push(byte);
+                    continue;
+                }
+            }
+            let response = response.unwrap_or_else(|| self.mmu.read().unwrap().peek(request));
+            match response.data.first() {
+                Some(byte) if response.status == MemoryResponseType::CacheHit || response.status == MemoryResponseType::Valid => {
+                    bytes.push(*byte);
+                }
+                _ => return Err(MemoryAccessError { address: current_address, status: response.status }),
+            }
+        }
+        // stores still waiting in the store buffer are newer than the memory
+        if let Some(buffer) = &self.store_buffer {
+            buffer.lock().unwrap().forward(address, &mut bytes);
+        }
+        Ok(bytes)
+    }
+
+    /// write memory byte by byte as the stores of the core would, so cached and shared copies stay coherent
+    /// the whole range is checked first, and only memories without side effects (RAM, ROM, flash) writable by the core are accepted,
+    /// so a failed write leaves the memory untouched and never reaches the registers of a device
+    pub fn write_mem(&self, address: Address, data: &[u8]) -> Result<(), MemoryAccessError> {
+        {
+            let mmu = self.mmu.read().unwrap();
+            for current_address in address..address + data.len() as Address {
+                if self.in_l1(current_address) {
+                    continue;
+                }
+                let attributes = mmu.attributes(current_address);
+                let status = if mmu.device_at(current_address).is_none() {
+                    Some(MemoryResponseType::InvalidAddress)
+                } else if !attributes.idempotent {
+                    Some(MemoryResponseType::NotWrittable)
+                } else {
+                    attributes.permissions.check(MemoryRequestType::WRITE, false)
+                };
+                if let Some(status) = status {
+                    return Err(MemoryAccessError { address: current_address, status });
+                }
+            }
+        }
+        self.store_bytes(address, data)
+    }
+
+    fn store_bytes(&self, address: Address, data: &[u8]) -> Result<(), MemoryAccessError> {
+        // the write must not be overwritten by older stores still in the store buffer
+        self.drain_store_buffer(None);
+        for (offset, byte) in data.iter().enumerate() {
+            let current_address = address + offset as Address;
+            let response = self.dcache_request(MemoryRequest::write_u8(current_address, *byte));
+            if response.status != MemoryResponseType::CacheHit && response.status != MemoryResponseType::Valid {
+                return Err(MemoryAccessError { address: current_address, status: response.status });
             }
         }
+        self.observe_init(address, data.len());
+        Ok(())
+    }
+
+    /// `function+offset` name of an address, based on the symbols of the loaded ELF
+    pub fn lookup_symbol(&self, address: Address) -> Option<String> {
+        self.symbols.format_address(address)
+    }
+
+    pub fn symbol_address(&self, name: &str) -> Option<Address> {
+        self.symbols.address_of(name)
     }
 
+    /// relaxed for the same reason as the registers, the PC is only updated by the first stage after the second clock boundary
     pub fn get_pc(&self) -> RiscWord {
         self.program_counter
-            .load(std::sync::atomic::Ordering::SeqCst) as RiscWord
+            .load(std::sync::atomic::Ordering::Relaxed)
     }
 
     pub fn set_pc(&self, pc: RiscWord) {
         self.program_counter
-            .store(pc as u64, std::sync::atomic::Ordering::SeqCst);
+            .store(pc, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// start (or stop) recording which instruction every stage processes at each clock cycle
+    pub fn record_pipeline_diagram(&mut self, enable: bool) {
+        self.pipeline_diagram = enable.then(Mutex::default);
+    }
+
+    /// table of the recorded instructions against clock cycles, with the letter of the stage processing them
+    pub fn pipeline_diagram(&self) -> Option<String> {
+        self.pipeline_diagram.as_ref().map(|diagram| diagram.lock().unwrap().to_string())
+    }
+
+    /// start (or stop) sampling the pipeline registers and the CDB wires at each clock cycle
+    /// the stages must already be added, as their names are used to label the signals
+    pub fn record_waveform(&mut self, enable: bool) {
+        let stage_names = self.stages.iter().map(|stage| stage.lock().unwrap().name.clone()).collect();
+        self.waveform = enable.then(|| Mutex::new(WaveformRecorder::new(stage_names)));
+    }
+
+    /// write the recorded waveform as a VCD file, using the clock period of the core (if any) as time base
+    pub fn write_vcd(&self, path: &str) -> std::io::Result<()> {
+        let Some(waveform) = &self.waveform else {
+            return Err(std::io::Error::other("no waveform was recorded on this core"));
+        };
+        let waveform = waveform.lock().unwrap();
+        let file = std::io::BufWriter::new(fs::File::create(path)?);
+        waveform.write_vcd(file, self.clock_period.unwrap_or(2))
+    }
+
+    /// sample the output register of a stage and the wires it drives, at the end of the clock cycle
+    #[inline]
+    fn sample_waveform(&self, stage: &PipelineStage) {
+        if let Some(waveform) = &self.waveform {
+            let mut waveform = waveform.lock().unwrap();
+            waveform.sample_stage(stage.clock_cycle, stage.index, stage.instruction.0, &stage.data_out);
+            for to in 0..self.stages.len() {
+                waveform.sample_wire(stage.clock_cycle, stage.index, to, self.cdb.signal(stage.index, to));
+            }
+        }
+    }
+
+    /// end of a clock cycle for the devices clocked in their own domain
+    #[inline]
+    fn tick_devices(&self, clock_cycle: u64) {
+        self.mmu.write().unwrap().tick(clock_cycle);
+    }
+
+    #[inline]
+    fn end_cycle(&self, clock_cycle: u64) {
+        self.clock_cycle.store(clock_cycle + 1, std::sync::atomic::Ordering::SeqCst);
+        for observer in &self.observers {
+            observer.on_cycle(self, clock_cycle);
+        }
+    }
+
+    /// ask the event-driven runs to simulate the given clock cycle even if the core sleeps in a WFI (ex. the end of a transfer of a stage)
+    pub fn post_event(&self, clock_cycle: ClockCycle) {
+        self.events.lock().unwrap().post(clock_cycle);
+    }
+
+    /// skip the clock cycles following `clock_cycle` when the core retired a WFI in it and no interrupt is pending,
+    /// up to the cycle before the next posted event or the cycle after the next device event, so the core sees the interrupt it raises
+    /// the devices are ticked over all the skipped cycles at once and the observers still see every one of them
+    /// returns the number of skipped cycles, at most `max_cycles`, nothing is skipped without a known event
+    fn skip_idle_cycles(&self, clock_cycle: ClockCycle, max_cycles: u64) -> u64 {
+        if !self.waiting_for_interrupt.swap(false, std::sync::atomic::Ordering::SeqCst)
+            || self.interrupt_lines().load(std::sync::atomic::Ordering::SeqCst) != 0
+        {
+            return 0;
+        }
+        let device_event = self.mmu.read().unwrap().next_event().map(|cycles| clock_cycle + cycles + 1);
+        let posted_event = self.events.lock().unwrap().next_after(clock_cycle);
+        let Some(next_cycle) = device_event.into_iter().chain(posted_event).min() else {
+            return 0;
+        };
+        let skipped = (next_cycle - clock_cycle - 1).min(max_cycles);
+        if skipped == 0 {
+            return 0;
+        }
+        self.tick_devices(clock_cycle + skipped);
+        if self.observers.is_empty() {
+            self.clock_cycle.store(clock_cycle + skipped + 1, std::sync::atomic::Ordering::SeqCst);
+        } else {
+            for cycle in clock_cycle + 1..=clock_cycle + skipped {
+                self.end_cycle(cycle);
+            }
+        }
+        skipped
+    }
+
+    #[inline]
+    fn sample_stage(&self, stage: &PipelineStage, data_output: &PipelineData, reset: bool, enabled: bool) {
+        let fetched = || {
+            (stage.index == 0x0 && data_output.fetch_slots() > 0).then(|| {
+                let (instruction, pc) = data_output.fetch_slot(0);
+                (instruction, pc, data_output.fetch_slots() - 1)
+            })
+        };
+        if let Some(trace) = &self.chrome_trace {
+            // the first stage only knows the instruction it fetched once its output is latched
+            let instruction = if stage.index == 0x0 { fetched().map_or(0x0, |(instruction, _, _)| instruction) } else { stage.instruction.0 };
+            trace.sample_stage(stage.clock_cycle, stage.index, instruction, reset, enabled);
+        }
+        if let Some(diagram) = &self.pipeline_diagram {
+            let fetched = fetched();
+            diagram.lock().unwrap().record(StageSample {
+                clock_cycle: stage.clock_cycle,
+                stage_index: stage.index,
+                letter: stage_letter(&stage.name),
+                reset,
+                enabled,
+                fetched,
+            });
+        }
     }
 
     #[inline]
     fn trace_asm_instr(&self, stage: &mut PipelineStage, print_asm: bool, disassmble: bool) {
-        use crate::risc_soc::instruction_asm::rv32_asm;
         if print_asm {
             // handle the print/log of the current instruction
             let mut instr_bin = stage.instruction.0;
+            let mut location = String::new();
+            let mut instr_pc = None;
             if stage.index == 0x0 && !stage.data_out.is_empty() {
                 //special case for first stage in pipeline
                 instr_bin = stage.data_out.get_u32(0x0);
                 stage.instruction = Instruction(instr_bin);
+                // the fetch stage places the PC of the instruction right after it
+                if stage.data_out.size() >= 4 + XLEN_BYTES {
+                    let pc = stage.data_out.get_word(0x4) as Address;
+                    instr_pc = Some(pc);
+                    if let Some(symbol) = self.lookup_symbol(pc) {
+                        location = format!(" <{symbol}>");
+                    }
+                }
+                // a superscalar fetch stage produces a whole fetch packet
+                stage.bundle = (1..stage.data_out.fetch_slots())
+                    .map(|slot| Instruction(stage.data_out.fetch_slot(slot).0))
+                    .collect();
+            }
+            // the younger instructions of a bundle are listed after the first one
+            for instruction in &stage.bundle {
+                if disassmble {
+                    location.push_str(&format!(" + {}(0x{:X})", disasm::disassemble(instruction.0), instruction.0));
+                } else {
+                    location.push_str(&format!(" + 0x{:X}", instruction.0));
+                }
             }
 
             if disassmble {
-                let asm_instr = rv32_asm(instr_bin);
+                let asm_instr = match instr_pc {
+                    Some(pc) => disasm::disassemble_at(instr_bin, pc, Some(&self.symbols)),
+                    None => disasm::disassemble(instr_bin),
+                };
                 if self.debug {
                     println!(
-                        "Pipeline Stage {} @ClockCycle {} -> Instruction:{}(0x{:X})",
-                        stage.name, stage.clock_cycle, asm_instr, stage.instruction.0
+                        "Pipeline Stage {} @ClockCycle {} -> Instruction:{}(0x{:X}){}",
+                        stage.name, stage.clock_cycle, asm_instr, stage.instruction.0, location
                     );
                 } else {
                     tracing::info!(
-                        "Pipeline Stage {} @ClockCycle {} -> Instruction:{}(0x{:X})",
+                        "Pipeline Stage {} @ClockCycle {} -> Instruction:{}(0x{:X}){}",
                         stage.name,
                         stage.clock_cycle,
                         asm_instr,
-                        stage.instruction.0
+                        stage.instruction.0,
+                        location
                     );
                 }
             } else {
                 if self.debug {
                     println!(
-                        "Pipeline Stage {} @ClockCycle {} -> Instruction: 0x{:X}",
-                        stage.name, stage.clock_cycle, stage.instruction.0
+                        "Pipeline Stage {} @ClockCycle {} -> Instruction: 0x{:X}{}",
+                        stage.name, stage.clock_cycle, stage.instruction.0, location
                     );
                 } else {
                     tracing::info!(
-                        "Pipeline Stage {} @ClockCycle {} -> Instruction: 0x{:X}",
+                        "Pipeline Stage {} @ClockCycle {} -> Instruction: 0x{:X}{}",
                         stage.name,
                         stage.clock_cycle,
-                        stage.instruction.0
+                        stage.instruction.0,
+                        location
                     );
                 }
             }
@@ -302,31 +1986,109 @@ impl RiscCore {
 
     /// start execution of loaded program
     /// if running in debug mode it will run a single instruction through all pipeline stages and the run function must be called for each new instruction
-    pub fn run(&mut self, num_clock_cycles: Option<u64>) {
+    /// run until the given clock cycle (compared with the clock cycle counter of the stages), a trap or an exit request
+    /// in debug mode a single clock cycle is run per call
+    pub fn run(&mut self, num_clock_cycles: Option<u64>) -> StopReason {
+        let control = RunControl { last_cycle: num_clock_cycles, step: self.debug, timeout: self.run_timeout, ..Default::default() };
+        self.run_with(control)
+    }
+
+    /// run the given number of clock cycles, counted from the current one
+    pub fn run_for_cycles(&mut self, cycles: u64) -> StopReason {
+        self.run_with(RunControl::cycles(cycles).with_timeout(self.run_timeout))
+    }
+
+    /// run until the next instruction to fetch is at the given address
+    pub fn run_until_pc(&mut self, pc: RiscWord) -> StopReason {
+        self.run_with(RunControl::until_pc(pc).with_timeout(self.run_timeout))
+    }
+
+    /// run until the condition holds at the end of a clock cycle, the condition must not lock the pipeline stages
+    pub fn run_until(&mut self, condition: impl Fn(&RiscCore) -> bool + Send + Sync) -> StopReason {
+        self.run_with(RunControl::until(condition).with_timeout(self.run_timeout))
+    }
+
+    /// wall-clock time after which the runs started without an explicit timeout are abandoned
+    pub fn set_run_timeout(&mut self, timeout: Option<Duration>) {
+        self.run_timeout = timeout;
+    }
+
+    /// evaluate the function of the stage, a panic in it is reported as an error of the model stopping the run at the end of the cycle
+    /// the CDB is aborted, so the stages waiting for a wire the panicking stage did not assign still reach the end of the cycle
+    /// locks held by the stage function are poisoned by the panic, so the core has to be built again before running it
+    fn process_stage(&self, stage: &PipelineStage) -> PipelineData {
+        let process_fn = stage.process_fn;
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| process_fn(&stage.data_in, self))) {
+            Ok(data_output) => data_output,
+            Err(payload) => {
+                let message = match payload.downcast_ref::<&str>() {
+                    Some(message) => message.to_string(),
+                    None => payload.downcast_ref::<String>().cloned().unwrap_or_default(),
+                };
+                let kind = SimErrorKind::StagePanic { stage: stage.name.clone(), instruction: stage.instruction.0, message };
+                self.raise_sim_error(kind, None);
+                self.cdb.abort();
+                stage.bubble.clone()
+            }
+        }
+    }
+
+    /// wakes up the stages blocked on a wire once the timeout elapsed, until the returned sender is dropped
+    fn spawn_watchdog<'scope, 'env: 'scope>(
+        &'env self,
+        s: &'scope std::thread::Scope<'scope, 'env>,
+        timeout: Option<Duration>,
+    ) -> crossbeam_channel::Sender<()> {
+        let (done_sender, done_receiver) = crossbeam_channel::bounded::<()>(0);
+        self.cdb.resume();
+        if let Some(timeout) = timeout {
+            s.spawn(move || {
+                if let Err(crossbeam_channel::RecvTimeoutError::Timeout) = done_receiver.recv_timeout(timeout) {
+                    tracing::warn!("Simulation did not stop within {timeout:?}, aborting it");
+                    self.cdb.abort();
+                }
+            });
+        }
+        done_sender
+    }
+
+    /// run one thread per pipeline stage, synchronized on the clock edges, until one of the conditions of `control` is met
+    pub fn run_with(&mut self, control: RunControl) -> StopReason {
         //start execution of all stages
         use std::thread::sleep;
-        use std::time::Instant;
         use std::sync::Barrier;
 
+        if control.cycles == Some(0) {
+            return StopReason::CycleLimit;
+        }
         let barrier = Barrier::new(self.stages.len());
+        let stop: Mutex<Option<StopReason>> = Mutex::new(None);
+        let start = Instant::now();
         std::thread::scope(|s| {
-                        
+            let watchdog = self.spawn_watchdog(s, control.timeout);
             for arc_stage in &self.stages {
+                let watchdog = watchdog.clone();
                 s.spawn(|| {
+                    // the watchdog stops once every stage thread is done
+                    let _watchdog = watchdog;
                     let clock_period = self.clock_period;
                     let mut stage = arc_stage.lock().unwrap();
+                    let mut cycles_run = 0;
                     loop {
                         
                         self.cdb.clear(stage.index); //clear all wires of current stage before new clock edge so that we can react to a change
                         barrier.wait(); //clock boundary
-                        let pipeline_payload;
                         
-                        // read from previous pipeline stage if available
+                        // read from previous pipeline stage if available, the payload is only latched once the last input was consumed
+                        // a payload is sent every cycle, so an empty channel means that the previous stage did not run yet
                         if stage.input_channel.is_some() {
                             match stage.input_channel.as_ref().unwrap().try_recv() {
                                 Ok(data_input) => {
-                                    stage.instruction = data_input.instruction;
-                                    stage.data_in = data_input.data;
+                                    if stage.input_consumed {
+                                        stage.instruction = data_input.instruction;
+                                        stage.bundle = data_input.bundle;
+                                        stage.data_in = data_input.data;
+                                    }
                                 },
 
                                 Err(e) => {
@@ -341,36 +2103,47 @@ impl RiscCore {
                             }
                         } else {
                             stage.instruction = Instruction(0x0);
+                            stage.bundle.clear();
                             stage.data_in = PipelineData(vec![]); 
                         };
     
-                        let period_start = Instant::now();
-                        let data_output = (stage.process_fn)(&stage.data_in, self);            
-                        let elapsed_period = period_start.elapsed();
+                        // running free, the delay of the stage is neither measured nor logged
+                        let period_start = clock_period.map(|_| Instant::now());
+                        let data_output = self.process_stage(&stage);
+                        let elapsed_period = period_start.map(|start| start.elapsed().as_nanos());
                         
                         barrier.wait(); //clock boundary
                         
-                        //chech if a reset or a stall was asserted 
+                        //chech if a reset or a stall was asserted, the valid flags of the stages only change after the last clock boundary
                         let reset = self.is_stage_reset(stage.index);
-                        let enabled = self.is_stage_enabled(stage.index);
+                        let handshake = self.handshake(stage.index);
+                        let fetch_held = stage.index == 0x0 && self.fetch_hold.swap(false, std::sync::atomic::Ordering::SeqCst);
+                        self.sample_stage(&stage, &data_output, reset, handshake.write);
                         if reset {
-                            // reset the output of the current pipeline stage
-                            stage.data_out = PipelineData(vec![0u8; stage.size_out]);
+                            // flush the output of the current pipeline stage
+                            stage.data_out = stage.bubble.clone();
                             stage.instruction = Instruction(0x0);
-                        } else if enabled {
+                            stage.bundle.clear();
+                        } else if handshake.write {
                             //update output of pipeline stage if no stall was asserted
                             stage.data_out = data_output;
-                            if stage.index == 0x0 {
-                                self.set_pc(self.get_pc() + 4);
+                            if stage.index == 0x0 && !fetch_held {
+                                self.set_pc(self.get_pc().wrapping_add(4 * self.issue_width as RiscWord));
                             }
                         } 
 
+                        if let Some(injector) = &self.fault_injector {
+                            injector.flip_wires(&mut stage);
+                        }
                         self.trace_asm_instr(&mut stage, true, true);
+                        self.sample_waveform(&stage);
+                        if stage.index == 0x0 {
+                            self.tick_devices(stage.clock_cycle);
+                            self.end_cycle(stage.clock_cycle);
+                        }
 
-                        let period = elapsed_period.as_nanos();
-                        tracing::info!("Stage {} delay time: {} ns", stage.name, period);
-                        if clock_period.is_some() {
-                            let clock_period = clock_period.unwrap();
+                        if let (Some(clock_period), Some(period)) = (clock_period, elapsed_period) {
+                            tracing::info!("Stage {} delay time: {} ns", stage.name, period);
                             if period < clock_period {
                                 //complete remainder of clock period
                                 sleep(std::time::Duration::from_nanos((clock_period - period) as u64));
@@ -384,36 +2157,157 @@ impl RiscCore {
                             }
                         }
 
-                        pipeline_payload = PipelinePayload {
-                            instruction: stage.instruction,
-                            data: stage.data_out.clone(),
+                        // a consumed output register is seen as a bubble, so it is not processed twice by the next stage
+                        let pipeline_payload = if handshake.valid {
+                            PipelinePayload {
+                                instruction: stage.instruction,
+                                bundle: stage.bundle.clone(),
+                                data: stage.data_out.clone(),
+                                valid: true,
+                            }
+                        } else {
+                            PipelinePayload { data: stage.bubble.clone(), ..Default::default() }
                         };
 
-                        if num_clock_cycles.is_some() && stage.clock_cycle == num_clock_cycles.unwrap() {
-                            break;
+                        //send to next pipeline stage if available, even on the last cycle of the run so that the next run resumes from it
+                        if let Some(ref pipline_output) = stage.output_channel
+                            && let Err(e) = pipline_output.send(pipeline_payload)
+                        {
+                            tracing::info!("{e}");
+                            return;
                         }
-                                
-                        //send to next pipeline stage if available
-                        match stage.output_channel {
-                            Some(ref pipline_output) => match pipline_output.send(pipeline_payload) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    tracing::info!("{e}");
-                                    return;
-                                }
-                            },
-                            None => {}
+
+                        // the first stage decides for all of them, after its own updates of the PC and of the devices
+                        cycles_run += 1;
+                        if stage.index == 0x0 {
+                            *stop.lock().unwrap() = control.stop_reason(self, stage.clock_cycle, cycles_run, start);
                         }
-                        
+                        barrier.wait();
+                        self.set_output_valid(stage.index, handshake.valid);
+                        stage.input_consumed = handshake.consumed;
                         stage.clock_cycle += 1;
-                        if self.debug {
+                        if stop.lock().unwrap().is_some() {
                             break;
                         }
-
                     }
                 });
             }
         });
+        stop.into_inner().unwrap().unwrap_or(StopReason::Halted)
+    }
+
+    /// alternative to `run` which evaluates all pipeline stages on the calling thread, one clock cycle at a time
+    /// stages are evaluated from the last one to the first one, so the wires of the CDB (which only go towards earlier stages)
+    /// are always assigned before being pulled, and every stage still consumes the output its predecessor produced in the previous cycle
+    /// the result does not depend on thread scheduling and no clock period is enforced, which makes it suited for tests and CI
+    pub fn run_sequential(&mut self, num_clock_cycles: Option<u64>) -> StopReason {
+        let control = RunControl { last_cycle: num_clock_cycles, step: self.debug, timeout: self.run_timeout, ..Default::default() };
+        self.run_sequential_with(control)
+    }
+
+    /// same as `run_sequential`, but the cycles in which the core sleeps in a WFI are skipped up to the next event
+    pub fn run_event_driven(&mut self, num_clock_cycles: Option<u64>) -> StopReason {
+        let control = RunControl {
+            last_cycle: num_clock_cycles,
+            step: self.debug,
+            timeout: self.run_timeout,
+            event_driven: true,
+            ..Default::default()
+        };
+        self.run_sequential_with(control)
+    }
+
+    pub fn run_sequential_with(&mut self, control: RunControl) -> StopReason {
+        if control.cycles == Some(0) {
+            return StopReason::CycleLimit;
+        }
+        let core: &RiscCore = self;
+        let start = Instant::now();
+        // a WFI retired by an earlier run is not waited for anymore
+        core.waiting_for_interrupt.store(false, std::sync::atomic::Ordering::SeqCst);
+        std::thread::scope(|s| {
+            let _watchdog = core.spawn_watchdog(s, control.timeout);
+            let mut stages: Vec<_> = core.stages.iter().map(|stage| stage.lock().unwrap()).collect();
+            let mut cycles_run = 0;
+            loop {
+                for stage in &stages {
+                    core.cdb.clear(stage.index);
+                }
+
+                // latch the pipeline registers from the previous cycle, in the stages which consumed their last input
+                for index in (0..stages.len()).rev() {
+                    if index == 0 {
+                        stages[index].instruction = Instruction(0x0);
+                        stages[index].bundle.clear();
+                        stages[index].data_in = PipelineData(vec![]);
+                    } else if stages[index].input_consumed {
+                        let previous = &stages[index - 1];
+                        let (instruction, bundle, data) = if core.is_output_valid(index - 1) {
+                            (previous.instruction, previous.bundle.clone(), previous.data_out.clone())
+                        } else {
+                            (Instruction(0x0), vec![], previous.bubble.clone())
+                        };
+                        stages[index].instruction = instruction;
+                        stages[index].bundle = bundle;
+                        stages[index].data_in = data;
+                    }
+                }
+
+                let mut outputs = vec![PipelineData::default(); stages.len()];
+                for index in (0..stages.len()).rev() {
+                    let stage = &stages[index];
+                    outputs[index] = core.process_stage(stage);
+                }
+
+                // same as the second clock boundary of `run`: control signals are only sampled after every stage was evaluated
+                // and the handshakes are all resolved before any valid flag changes
+                let handshakes: Vec<_> = stages.iter().map(|stage| core.handshake(stage.index)).collect();
+                for ((stage, data_output), handshake) in stages.iter_mut().zip(outputs).zip(handshakes) {
+                    let reset = core.is_stage_reset(stage.index);
+                    let fetch_held = stage.index == 0x0 && core.fetch_hold.swap(false, std::sync::atomic::Ordering::SeqCst);
+                    core.sample_stage(stage, &data_output, reset, handshake.write);
+                    core.set_output_valid(stage.index, handshake.valid);
+                    stage.input_consumed = handshake.consumed;
+                    if reset {
+                        stage.data_out = stage.bubble.clone();
+                        stage.instruction = Instruction(0x0);
+                        stage.bundle.clear();
+                    } else if handshake.write {
+                        stage.data_out = data_output;
+                        if stage.index == 0x0 && !fetch_held {
+                            core.set_pc(core.get_pc().wrapping_add(4 * core.issue_width as RiscWord));
+                        }
+                    }
+                    if let Some(injector) = &core.fault_injector {
+                        injector.flip_wires(stage);
+                    }
+                    core.trace_asm_instr(stage, true, true);
+                    core.sample_waveform(stage);
+                }
+                core.tick_devices(stages[0].clock_cycle);
+                core.end_cycle(stages[0].clock_cycle);
+
+                cycles_run += 1;
+                let stop = control.stop_reason(core, stages[0].clock_cycle, cycles_run, start);
+                // the run still stops on the cycle given by its cycle limits
+                let skipped = if stop.is_none() && control.event_driven {
+                    let max_cycles = [
+                        control.last_cycle.map(|last_cycle| last_cycle.saturating_sub(stages[0].clock_cycle + 1)),
+                        control.cycles.map(|cycles| cycles.saturating_sub(cycles_run + 1)),
+                    ];
+                    core.skip_idle_cycles(stages[0].clock_cycle, max_cycles.into_iter().flatten().min().unwrap_or(u64::MAX))
+                } else {
+                    0
+                };
+                cycles_run += skipped;
+                for stage in stages.iter_mut() {
+                    stage.clock_cycle += 1 + skipped;
+                }
+                if let Some(reason) = stop {
+                    return reason;
+                }
+            }
+        })
     }
 
 }
@@ -431,16 +2325,37 @@ impl DerefMut for RiscCore {
     }
 }
 
+/// names of the integer registers in the standard calling convention, indexed by register number
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// number of a register given either its ABI name (ex. "a0", or "fp" for s0) or its architectural name (ex. "x10")
+pub fn register_index(name: &str) -> Option<usize> {
+    if name == "fp" {
+        return Some(8);
+    }
+    if let Some(index) = ABI_NAMES.iter().position(|abi_name| *abi_name == name) {
+        return Some(index);
+    }
+    let index: usize = name.strip_prefix('x')?.parse().ok()?;
+    (index < 32).then_some(index)
+}
+
+/// integer register file of a hart
+/// the stage threads only exchange values through the clock boundaries (barriers) and the wires of the CDB (mutexes), which
+/// already order their accesses, so the registers are relaxed atomics: on most hosts they compile to plain loads and stores
 #[derive(Debug, Default)]
-pub struct Registers([AtomicU64; 32]);
+pub struct Registers([AtomicRiscWord; 32]);
 
 impl Registers {
     pub fn read_regs(&self, rs1_address: usize, rs2_address: usize) -> (RiscWord, RiscWord) {
         assert!(rs1_address < 32);
         assert!(rs2_address < 32);
         (
-            self.0[rs1_address].load(std::sync::atomic::Ordering::SeqCst) as RiscWord, 
-            self.0[rs2_address].load(std::sync::atomic::Ordering::SeqCst) as RiscWord
+            self.0[rs1_address].load(std::sync::atomic::Ordering::Relaxed), 
+            self.0[rs2_address].load(std::sync::atomic::Ordering::Relaxed)
         )
     }
 
@@ -448,16 +2363,36 @@ impl Registers {
         assert!(rd_address < 32);
         if rd_address > 0 {
             //should never overwrite x0
-            self.0[rd_address].store(rd as u64, std::sync::atomic::Ordering::SeqCst);
+            self.0[rd_address].store(rd, std::sync::atomic::Ordering::Relaxed);
         }
     }
+
+    /// read a register by its ABI or architectural name, ex. "a0" or "x10"
+    pub fn read_reg_by_name(&self, name: &str) -> RiscWord {
+        let index = register_index(name).unwrap_or_else(|| panic!("Unknown register name {name}"));
+        self.read_regs(index, 0).0
+    }
+
+    /// write a register by its ABI or architectural name, writes to zero are ignored as for x0
+    pub fn write_reg_by_name(&self, name: &str, value: RiscWord) {
+        let index = register_index(name).unwrap_or_else(|| panic!("Unknown register name {name}"));
+        self.write_reg(index, value);
+    }
+
+    /// (ABI name, value) of every register, from x0 to x31
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, RiscWord)> + '_ {
+        ABI_NAMES
+            .iter()
+            .zip(self.0.iter())
+            .map(|(name, register)| (*name, register.load(std::sync::atomic::Ordering::Relaxed)))
+    }
 }
 
 use std::fmt::Display;
 impl Display for Registers {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for i in 0..self.0.len() {
-            write!(f, "x{i}={:X}\n", (self.0[i].load(std::sync::atomic::Ordering::SeqCst) as RiscWord).cast_signed())?;
+        for (i, (name, value)) in self.iter().enumerate() {
+            writeln!(f, "x{i}({name})={:X}", value.cast_signed())?;
         }
         Ok(())
     }    
@@ -0,0 +1,91 @@
+use crate::risc_soc::pipeline_stage::ClockCycle;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::risc_soc::sim_error::SimError;
+use std::time::{Duration, Instant};
+
+/// why a run of the core returned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    /// the program trapped or requested the end of the simulation
+    Halted,
+    /// the cycle budget of the run was used up
+    CycleLimit,
+    /// the stop condition of the run was met
+    Condition,
+    /// the program reached an EBREAK in debug mode
+    Breakpoint,
+    /// the hart halted in Debug Mode, see the cause field of dcsr, the run does not go on until it is resumed
+    DebugHalt,
+    /// the wall-clock timeout elapsed, ex. because the simulation was deadlocked
+    Timeout,
+    /// a single clock cycle was run, as done in debug mode
+    Step,
+    /// the model of the SoC could not go on (ex. an instruction it does not implement), the core is left as it was in that cycle
+    Error(SimError),
+}
+
+/// predicate on the state of the core at the end of a clock cycle
+pub type StopCondition<'a> = Box<dyn Fn(&RiscCore) -> bool + Send + Sync + 'a>;
+
+/// when a run of the core stops, traps and exit requests of the program always stop it
+/// conditions are checked at the end of every clock cycle, once all the stages updated their pipeline registers,
+/// so every stage stops after the same cycle and a later run resumes exactly where this one stopped
+#[derive(Default)]
+pub struct RunControl<'a> {
+    /// last clock cycle to run, compared with the clock cycle counter of the stages which keeps counting across runs
+    pub last_cycle: Option<ClockCycle>,
+    /// clock cycles to run, counted from the start of the run
+    pub cycles: Option<u64>,
+    /// checked on the first stage thread, so it must not lock the pipeline stages
+    pub until: Option<StopCondition<'a>>,
+    /// wall-clock time after which the run is abandoned, stages blocked on a wire are woken up to reach the end of their cycle
+    pub timeout: Option<Duration>,
+    /// stop after a single clock cycle
+    pub step: bool,
+    /// skip the clock cycles in which the core sleeps in a WFI up to the next event of the devices or the stages, only honored by the sequential runs
+    /// the stop condition is not checked on the skipped cycles, as the core does not change during them
+    pub event_driven: bool,
+}
+
+impl<'a> RunControl<'a> {
+    pub fn cycles(cycles: u64) -> Self {
+        Self { cycles: Some(cycles), ..Default::default() }
+    }
+
+    /// stop once the next instruction to fetch is at the given address
+    pub fn until_pc(pc: RiscWord) -> Self {
+        Self::until(move |core| core.get_pc() == pc)
+    }
+
+    pub fn until(condition: impl Fn(&RiscCore) -> bool + Send + Sync + 'a) -> Self {
+        Self { until: Some(Box::new(condition)), ..Default::default() }
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// `cycles_run` counts the current cycle
+    pub(crate) fn stop_reason(&self, core: &RiscCore, clock_cycle: ClockCycle, cycles_run: u64, start: Instant) -> Option<StopReason> {
+        if let Some(error) = core.sim_error() {
+            Some(StopReason::Error(error))
+        } else if core.halted() {
+            Some(StopReason::Halted)
+        } else if core.take_software_breakpoint() {
+            Some(StopReason::Breakpoint)
+        } else if core.in_debug_mode() {
+            Some(StopReason::DebugHalt)
+        } else if self.until.as_ref().is_some_and(|until| until(core)) {
+            Some(StopReason::Condition)
+        } else if self.last_cycle.is_some_and(|last_cycle| clock_cycle >= last_cycle) || self.cycles == Some(cycles_run) {
+            Some(StopReason::CycleLimit)
+        } else if self.timeout.is_some_and(|timeout| start.elapsed() >= timeout) || core.cdb.is_aborted() {
+            Some(StopReason::Timeout)
+        } else if self.step {
+            Some(StopReason::Step)
+        } else {
+            None
+        }
+    }
+}
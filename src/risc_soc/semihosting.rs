@@ -0,0 +1,291 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{ExitStatus, RiscCore, RiscSignedWord, RiscWord, XLEN_BYTES, zero_extend_word};
+use ahash::AHashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// instructions surrounding the EBREAK of a semihosting call, both are NOPs writing to x0
+pub const SEMIHOSTING_ENTRY: u32 = 0x01f01013; // slli x0, x0, 0x1f
+pub const SEMIHOSTING_EXIT: u32 = 0x40705013; // srai x0, x0, 7
+
+// operation numbers passed in a0, the parameter (or the address of a block of parameters) is passed in a1
+pub const SYS_OPEN: RiscWord = 0x01;
+pub const SYS_CLOSE: RiscWord = 0x02;
+pub const SYS_WRITEC: RiscWord = 0x03;
+pub const SYS_WRITE0: RiscWord = 0x04;
+pub const SYS_WRITE: RiscWord = 0x05;
+pub const SYS_READ: RiscWord = 0x06;
+pub const SYS_READC: RiscWord = 0x07;
+pub const SYS_ISERROR: RiscWord = 0x08;
+pub const SYS_ISTTY: RiscWord = 0x09;
+pub const SYS_SEEK: RiscWord = 0x0A;
+pub const SYS_FLEN: RiscWord = 0x0C;
+pub const SYS_REMOVE: RiscWord = 0x0E;
+pub const SYS_CLOCK: RiscWord = 0x10;
+pub const SYS_TIME: RiscWord = 0x11;
+pub const SYS_ERRNO: RiscWord = 0x13;
+pub const SYS_GET_CMDLINE: RiscWord = 0x15;
+pub const SYS_HEAPINFO: RiscWord = 0x16;
+pub const SYS_EXIT: RiscWord = 0x18;
+pub const SYS_EXIT_EXTENDED: RiscWord = 0x20;
+
+/// reason of SYS_EXIT for a program returning from main
+const ADP_STOPPED_APPLICATION_EXIT: RiscWord = 0x20026;
+/// special file name of the console, opened for reading it is stdin, for writing stdout and for appending stderr
+const CONSOLE_NAME: &str = ":tt";
+/// failure value of most operations, as a signed word
+const FAILURE: RiscWord = RiscWord::MAX;
+
+const REG_A0: usize = 10;
+const REG_A1: usize = 11;
+
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+struct Host {
+    handles: AHashMap<RiscWord, Handle>,
+    /// error number of the last failed operation, returned by SYS_ERRNO
+    errno: RiscWord,
+    command_line: String,
+    /// everything written to the console
+    console: Vec<u8>,
+    start: Instant,
+}
+
+/// ARM-style semihosting as specified for RISC-V: an EBREAK between the SEMIHOSTING_ENTRY and SEMIHOSTING_EXIT NOPs
+/// asks the host for a service, so programs built with `--specs=semihost.specs` can print and access host files without drivers
+/// the console is the one of the simulator, files are opened on the host relative to its working directory
+pub struct Semihosting {
+    host: Mutex<Host>,
+}
+
+/// the EBREAK at `pc` is surrounded by the semihosting sequence
+pub fn is_semihosting_call(core: &RiscCore, pc: Address) -> bool {
+    let instruction = |address: Address| core.peek_memory(address, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+    pc >= 4 && instruction(pc - 4) == Some(SEMIHOSTING_ENTRY) && instruction(pc + 4) == Some(SEMIHOSTING_EXIT)
+}
+
+impl Semihosting {
+    pub fn new(command_line: &str) -> Self {
+        Self {
+            host: Mutex::new(Host {
+                handles: AHashMap::new(),
+                errno: 0,
+                command_line: command_line.to_string(),
+                console: vec![],
+                start: Instant::now(),
+            }),
+        }
+    }
+
+    /// what the program wrote to the console so far
+    pub fn console_output(&self) -> String {
+        String::from_utf8_lossy(&self.host.lock().unwrap().console).to_string()
+    }
+
+    /// perform the operation requested in a0, returning the value to write to a0
+    pub fn call(&self, core: &RiscCore) -> RiscWord {
+        let (operation, parameter) = core.read_regs(REG_A0, REG_A1);
+        let mut host = self.host.lock().unwrap();
+        // most operations take a block of XLEN words
+        let field = |index: usize| -> Option<RiscWord> {
+            let bytes = core.peek_memory(parameter as Address + (index * XLEN_BYTES) as Address, XLEN_BYTES)?;
+            Some(RiscWord::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let result = match operation {
+            SYS_OPEN => (|| {
+                let name = core.peek_memory(field(0)? as Address, field(2)? as usize)?;
+                Some(host.open(&String::from_utf8_lossy(&name), field(1)?))
+            })(),
+            SYS_CLOSE => field(0).map(|handle| if host.handles.remove(&handle).is_some() { 0 } else { FAILURE }),
+            SYS_WRITEC => core.peek_memory(parameter as Address, 1).map(|byte| {
+                host.console(&byte);
+                0
+            }),
+            SYS_WRITE0 => read_c_string(core, parameter as Address).map(|string| {
+                host.console(&string);
+                0
+            }),
+            // the number of bytes that were not written
+            SYS_WRITE => (|| {
+                let length = field(2)?;
+                let bytes = core.peek_memory(field(1)? as Address, length as usize)?;
+                Some(length - host.write(field(0)?, &bytes))
+            })(),
+            // the number of bytes that were not read
+            SYS_READ => (|| {
+                let length = field(2)?;
+                let bytes = host.read(field(0)?, length as usize);
+                core.poke_memory(field(1)? as Address, &bytes)?;
+                Some(length - bytes.len() as RiscWord)
+            })(),
+            SYS_READC => {
+                let mut byte = [0u8];
+                Some(if std::io::stdin().read_exact(&mut byte).is_ok() { byte[0] as RiscWord } else { FAILURE })
+            }
+            SYS_ISERROR => field(0).map(|status| ((status as RiscSignedWord) < 0) as RiscWord),
+            SYS_ISTTY => field(0).map(|handle| matches!(host.handles.get(&handle), Some(Handle::Stdin | Handle::Stdout | Handle::Stderr)) as RiscWord),
+            SYS_SEEK => (|| Some(host.seek(field(0)?, field(1)?)))(),
+            SYS_FLEN => field(0).map(|handle| host.length(handle)),
+            SYS_REMOVE => (|| {
+                let name = core.peek_memory(field(0)? as Address, field(1)? as usize)?;
+                Some(host.status(std::fs::remove_file(String::from_utf8_lossy(&name).to_string())))
+            })(),
+            // centiseconds since the start of the simulation
+            SYS_CLOCK => Some((host.start.elapsed().as_millis() / 10) as RiscWord),
+            SYS_TIME => Some(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0) as RiscWord),
+            SYS_ERRNO => Some(host.errno),
+            SYS_GET_CMDLINE => (|| {
+                let mut command_line = host.command_line.as_bytes().to_vec();
+                command_line.push(0);
+                if command_line.len() > field(1)? as usize {
+                    return Some(FAILURE);
+                }
+                core.poke_memory(field(0)? as Address, &command_line)?;
+                Some(0)
+            })(),
+            // no heap or stack is imposed on the program, the C runtime then uses the symbols of its linker script
+            SYS_HEAPINFO => field(0).and_then(|block| core.poke_memory(block as Address, &[0; 4 * XLEN_BYTES])).map(|()| 0),
+            SYS_EXIT => {
+                // RV32 passes the reason itself, RV64 the address of a (reason, exit code) block
+                let (reason, code) = if XLEN_BYTES == 4 { (parameter, 0) } else { (field(0).unwrap_or(0), field(1).unwrap_or(0)) };
+                core.exit_signal.exit(exit_status(reason, code));
+                Some(0)
+            }
+            SYS_EXIT_EXTENDED => {
+                core.exit_signal.exit(exit_status(field(0).unwrap_or(0), field(1).unwrap_or(0)));
+                Some(0)
+            }
+            _ => {
+                tracing::warn!("Unsupported semihosting operation {operation:#X}");
+                None
+            }
+        };
+        // the parameters of the operation could not be accessed
+        result.unwrap_or(FAILURE)
+    }
+}
+
+fn exit_status(reason: RiscWord, code: RiscWord) -> ExitStatus {
+    match (reason, code) {
+        (ADP_STOPPED_APPLICATION_EXIT, 0) => ExitStatus::Pass,
+        (ADP_STOPPED_APPLICATION_EXIT, code) => ExitStatus::Fail(code as u16),
+        _ => ExitStatus::Fail(1),
+    }
+}
+
+impl Host {
+    fn insert(&mut self, handle: Handle) -> RiscWord {
+        let number = (1..).find(|number| !self.handles.contains_key(number)).unwrap();
+        self.handles.insert(number, handle);
+        number
+    }
+
+    /// modes follow the fopen strings: r, rb, r+, r+b, w, wb, w+, w+b, a, ab, a+, a+b
+    fn open(&mut self, name: &str, mode: RiscWord) -> RiscWord {
+        if name == CONSOLE_NAME {
+            let handle = match mode {
+                0..=3 => Handle::Stdin,
+                4..=7 => Handle::Stdout,
+                _ => Handle::Stderr,
+            };
+            return self.insert(handle);
+        }
+        let mut options = OpenOptions::new();
+        match mode / 4 {
+            0 => options.read(true).write(mode & 0x2 != 0),
+            1 => options.write(true).create(true).truncate(true).read(mode & 0x2 != 0),
+            _ => options.append(true).create(true).read(mode & 0x2 != 0),
+        };
+        match options.open(name) {
+            Ok(file) => self.insert(Handle::File(file)),
+            Err(e) => {
+                self.errno = e.raw_os_error().unwrap_or(0) as RiscWord;
+                FAILURE
+            }
+        }
+    }
+
+    /// SYS_WRITEC and SYS_WRITE0 print on the console without opening it
+    fn console(&mut self, bytes: &[u8]) {
+        self.console.extend_from_slice(bytes);
+        let _ = std::io::stdout().write_all(bytes);
+    }
+
+    /// returns how many bytes were written
+    fn write(&mut self, handle: RiscWord, bytes: &[u8]) -> RiscWord {
+        let result = match self.handles.get_mut(&handle) {
+            Some(Handle::File(file)) => file.write_all(bytes),
+            Some(Handle::Stdout) => {
+                self.console.extend_from_slice(bytes);
+                std::io::stdout().write_all(bytes)
+            }
+            Some(Handle::Stderr) => {
+                self.console.extend_from_slice(bytes);
+                std::io::stderr().write_all(bytes)
+            }
+            _ => return 0,
+        };
+        match self.status(result) {
+            0 => bytes.len() as RiscWord,
+            _ => 0,
+        }
+    }
+
+    fn read(&mut self, handle: RiscWord, length: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; length];
+        let read = match self.handles.get_mut(&handle) {
+            Some(Handle::File(file)) => file.read(&mut bytes),
+            Some(Handle::Stdin) => std::io::stdin().read(&mut bytes),
+            _ => Ok(0),
+        };
+        bytes.truncate(read.unwrap_or(0));
+        bytes
+    }
+
+    fn seek(&mut self, handle: RiscWord, position: RiscWord) -> RiscWord {
+        match self.handles.get_mut(&handle) {
+            Some(Handle::File(file)) => {
+                let result = file.seek(SeekFrom::Start(zero_extend_word(position))).map(|_| ());
+                self.status(result)
+            }
+            _ => FAILURE,
+        }
+    }
+
+    fn length(&mut self, handle: RiscWord) -> RiscWord {
+        match self.handles.get(&handle) {
+            Some(Handle::File(file)) => file.metadata().map(|metadata| metadata.len() as RiscWord).unwrap_or(FAILURE),
+            _ => FAILURE,
+        }
+    }
+
+    /// 0 on success, otherwise the failure value with errno set
+    fn status(&mut self, result: std::io::Result<()>) -> RiscWord {
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                self.errno = e.raw_os_error().unwrap_or(0) as RiscWord;
+                FAILURE
+            }
+        }
+    }
+}
+
+fn read_c_string(core: &RiscCore, mut address: Address) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    loop {
+        let byte = core.peek_memory(address, 1)?[0];
+        if byte == 0 {
+            return Some(bytes);
+        }
+        bytes.push(byte);
+        address += 1;
+    }
+}
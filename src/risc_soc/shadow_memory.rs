@@ -0,0 +1,121 @@
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse, MemoryResponseType};
+use crate::risc_soc::observer::SimulationObserver;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+
+/// load of the program reading bytes it never wrote, the first one of each instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UninitializedRead {
+    pub pc: RiscWord,
+    pub address: Address,
+    pub size: usize,
+    /// addresses of the bytes of the load that were never initialized
+    pub bytes: Vec<Address>,
+    /// loads of this instruction that read uninitialized bytes, including this first one
+    pub count: u64,
+}
+
+/// initialization state of the bytes of [start, end), one bit per byte
+#[derive(Debug)]
+struct ShadowRange {
+    start: Address,
+    end: Address,
+    initialized: Vec<u64>,
+}
+
+impl ShadowRange {
+    fn index(&self, address: Address) -> Option<usize> {
+        (address >= self.start && address < self.end).then(|| (address - self.start) as usize)
+    }
+}
+
+/// Shadow memory keeping whether every byte of its ranges (ex. the DRAM and data memory) was initialized, registered with
+/// `RiscCore::enable_shadow_memory`, like a lightweight MSan for the firmware. The bytes are initialized by the stores of
+/// the program and by the loaders (ELF sections including .bss, raw images, initial state, debugger writes), and a load
+/// reading any other byte of the ranges logs a warning, once per instruction. Writes of devices (ex. DMA) are not seen,
+/// their buffers are marked with `mark_initialized`. Bytes outside the ranges are considered initialized
+#[derive(Debug, Default)]
+pub struct ShadowMemory {
+    ranges: Mutex<Vec<ShadowRange>>,
+    reads: Mutex<Vec<UninitializedRead>>,
+}
+
+impl ShadowMemory {
+    /// track the bytes of [start, end), uninitialized until written
+    pub fn with_range(self, start: Address, end: Address) -> Self {
+        assert!(start < end, "The shadow range 0x{start:X}..0x{end:X} is empty");
+        let size = (end - start) as usize;
+        self.ranges.lock().unwrap().push(ShadowRange { start, end, initialized: vec![0; size.div_ceil(64)] });
+        self
+    }
+
+    pub fn mark_initialized(&self, address: Address, len: usize) {
+        let mut ranges = self.ranges.lock().unwrap();
+        for byte in address..address + len as Address {
+            for range in ranges.iter_mut() {
+                if let Some(index) = range.index(byte) {
+                    range.initialized[index / 64] |= 1 << (index % 64);
+                }
+            }
+        }
+    }
+
+    /// addresses of the bytes of [address, address + len) that were never initialized
+    pub fn uninitialized(&self, address: Address, len: usize) -> Vec<Address> {
+        let ranges = self.ranges.lock().unwrap();
+        (address..address + len as Address)
+            .filter(|byte| {
+                ranges.iter().any(|range| match range.index(*byte) {
+                    Some(index) => range.initialized[index / 64] & (1 << (index % 64)) == 0,
+                    None => false,
+                })
+            })
+            .collect()
+    }
+
+    /// loads of uninitialized bytes, one per instruction in the order they were first seen
+    pub fn reads(&self) -> Vec<UninitializedRead> {
+        self.reads.lock().unwrap().clone()
+    }
+
+    fn report(&self, core: &RiscCore, read: UninitializedRead) {
+        let mut reads = self.reads.lock().unwrap();
+        if let Some(previous) = reads.iter_mut().find(|previous| previous.pc == read.pc) {
+            previous.count += 1;
+            return;
+        }
+        let location = core.lookup_symbol(read.pc as Address).unwrap_or_default();
+        tracing::warn!(
+            "Load of {} bytes @{:X} by the instruction @{:X} {location} reads {} uninitialized bytes",
+            read.size,
+            read.address,
+            read.pc,
+            read.bytes.len()
+        );
+        reads.push(read);
+    }
+}
+
+impl SimulationObserver for ShadowMemory {
+    fn on_mem_init(&self, _core: &RiscCore, address: Address, len: usize) {
+        self.mark_initialized(address, len);
+    }
+
+    fn on_mem_access(&self, core: &RiscCore, request: &MemoryRequest, response: &MemoryResponse) {
+        if response.status != MemoryResponseType::CacheHit && response.status != MemoryResponseType::Valid {
+            return;
+        }
+        let size = request.data_size as usize;
+        if request.request_type == MemoryRequestType::WRITE {
+            self.mark_initialized(request.data_address, size);
+            return;
+        }
+        let bytes = self.uninitialized(request.data_address, size);
+        if bytes.is_empty() {
+            return;
+        }
+        let pc = core.data_access_pc.load(Ordering::SeqCst) as RiscWord;
+        self.report(core, UninitializedRead { pc, address: request.data_address, size, bytes, count: 1 });
+    }
+}
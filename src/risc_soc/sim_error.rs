@@ -0,0 +1,59 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::pipeline_stage::ClockCycle;
+use crate::risc_soc::risc_soc::RiscWord;
+use std::fmt::Display;
+
+/// reasons for which the model of the SoC cannot go on, as opposed to the exceptions of the program which are taken as traps
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimErrorKind {
+    /// a stage has no model for this instruction (ex. an extension the core does not implement)
+    UnsupportedInstruction(u32),
+    /// a stage has no model for this operation of an instruction it otherwise handles (ex. an unknown AMO)
+    UnsupportedOperation { instruction: u32, reason: String },
+    /// a cache or device was sent a request it cannot make sense of (ex. a store without data)
+    BadMemoryRequest { address: Address, reason: String },
+    /// the function of a stage panicked, with the instruction it was processing
+    StagePanic { stage: String, instruction: u32, message: String },
+    /// a load or store touched a region of `MemoryGuard`, with the backtrace of the guest from the PC of the access
+    GuardViolation { region: String, address: Address, write: bool, backtrace: Vec<String> },
+}
+
+impl Display for SimErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SimErrorKind::UnsupportedInstruction(instruction) => write!(f, "unsupported instruction 0x{instruction:08X}"),
+            SimErrorKind::UnsupportedOperation { instruction, reason } => {
+                write!(f, "unsupported operation in instruction 0x{instruction:08X}: {reason}")
+            }
+            SimErrorKind::BadMemoryRequest { address, reason } => write!(f, "bad memory request @{address:X}: {reason}"),
+            SimErrorKind::StagePanic { stage, instruction, message } => {
+                write!(f, "stage {stage} panicked on instruction 0x{instruction:08X}: {message}")
+            }
+            SimErrorKind::GuardViolation { region, address, write, backtrace } => {
+                let access = if *write { "store" } else { "load" };
+                write!(f, "{access} @{address:X} in the {region} guard, backtrace: {}", backtrace.join(" <- "))
+            }
+        }
+    }
+}
+
+/// error stopping a run of the core, with the state of the core when it was raised
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimError {
+    pub kind: SimErrorKind,
+    /// address of the instruction being processed, unknown for the accesses not tied to one (ex. a store buffer drain)
+    pub pc: Option<RiscWord>,
+    pub clock_cycle: ClockCycle,
+}
+
+impl Display for SimError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in clock cycle {}", self.kind, self.clock_cycle)?;
+        if let Some(pc) = self.pc {
+            write!(f, ", pc = 0x{pc:X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for SimError {}
@@ -0,0 +1,61 @@
+use crate::risc_soc::coherence::CoherenceBus;
+use crate::risc_soc::memory_management_unit::{Address, MemoryManagementUnit};
+use crate::risc_soc::risc_soc::{ExitSignal, ExitStatus, RiscCore, RiscWord};
+use std::sync::{Arc, RwLock};
+
+/// multiple harts sharing the same MMU and memory devices
+/// each hart keeps its own pipeline, registers and L1 memories, while the devices of the MMU (ex. DRAM, CLINT) are shared
+/// atomic accesses lock the shared MMU, which keeps LR/SC and AMOs on shared memory coherent between harts
+pub struct RiscSoc {
+    pub harts: Vec<RiscCore>,
+    pub mmu: Arc<RwLock<MemoryManagementUnit>>,
+    /// a single exit request (ex. through a test finisher) ends the simulation of all the harts
+    pub exit_signal: ExitSignal,
+}
+
+impl RiscSoc {
+    pub fn new(mmu: MemoryManagementUnit) -> Self {
+        Self { harts: vec![], mmu: Arc::new(RwLock::new(mmu)), exit_signal: ExitSignal::default() }
+    }
+
+    /// connect a hart to the shared MMU, its mhartid is its index in the SoC
+    pub fn add_hart(&mut self, mut hart: RiscCore) -> &mut Self {
+        hart.mmu = self.mmu.clone();
+        hart.exit_signal = self.exit_signal.clone();
+        hart.csrs.write().unwrap().info.hart_id = self.harts.len() as RiscWord;
+        self.harts.push(hart);
+        self
+    }
+
+    /// give every hart a MESI data cache for the shared memory range, all snooping the same bus
+    /// harts added afterwards access the range without caching it
+    pub fn add_coherent_caches(&mut self, line_size: usize, num_lines: usize, start_address: Address, end_address: Address) -> CoherenceBus {
+        let bus = CoherenceBus::new(line_size, num_lines);
+        for hart in self.harts.iter_mut() {
+            hart.coherent_cache = Some(bus.connect(start_address, end_address));
+        }
+        bus
+    }
+
+    pub fn hart(&self, hart_id: usize) -> &RiscCore {
+        &self.harts[hart_id]
+    }
+
+    pub fn hart_mut(&mut self, hart_id: usize) -> &mut RiscCore {
+        &mut self.harts[hart_id]
+    }
+
+    /// run all the harts in parallel, each one on its own set of stage threads
+    /// every hart stops by itself after the given number of clock cycles, on a trap or when the program requests it
+    pub fn run(&mut self, num_clock_cycles: Option<u64>) {
+        std::thread::scope(|s| {
+            for hart in self.harts.iter_mut() {
+                s.spawn(move || hart.run(num_clock_cycles));
+            }
+        });
+    }
+
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_signal.status()
+    }
+}
@@ -0,0 +1,129 @@
+use crate::risc_soc::exception::Trap;
+use crate::risc_soc::pipeline_stage::PipelineData;
+use crate::risc_soc::risc_soc::RiscCore;
+use std::time::Duration;
+
+/// control signals of a stage as left by the stage under test, ex. a stall of the previous stages or a flush
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageControl {
+    pub reset: bool,
+    pub enabled: bool,
+    pub ready: bool,
+}
+
+/// what a single run of the stage function produced
+#[derive(Debug, Clone)]
+pub struct StageRun {
+    /// value for the output register of the stage
+    pub output: PipelineData,
+    /// wires assigned by the stage, with the index of the stage they go to
+    pub assigned: Vec<(usize, PipelineData)>,
+    /// control signals of every stage after the run
+    pub controls: Vec<StageControl>,
+    pub trap: Option<Trap>,
+}
+
+impl StageRun {
+    /// value assigned by the stage to the wire going to the given stage
+    pub fn wire(&self, to: usize) -> Option<&PipelineData> {
+        self.assigned.iter().find(|(index, _)| *index == to).map(|(_, data)| data)
+    }
+}
+
+/// Runs one stage function of a core on its own, for table-driven unit tests of the stages
+/// The other stages do not run: the wires the stage reads carry the canned values given with `drive`, and the wires it assigns
+/// are captured in the returned `StageRun`. The core (ex. built by `init_core`) still provides the registers, memories and CSRs
+/// A stage waiting on a wire that is not driven would block forever, so the harness gives up after `timeout` and names the wires
+pub struct StageHarness {
+    pub core: RiscCore,
+    pub timeout: Duration,
+    driven: Vec<(usize, usize, PipelineData)>,
+}
+
+impl StageHarness {
+    pub fn new(core: RiscCore) -> Self {
+        Self { core, timeout: Duration::from_secs(1), driven: vec![] }
+    }
+
+    /// canned value of the wire going from a stage to another one, kept for all the following runs
+    pub fn drive(&mut self, from: usize, to: usize, data: PipelineData) -> &mut Self {
+        self.driven.retain(|(driven_from, driven_to, _)| (*driven_from, *driven_to) != (from, to));
+        self.driven.push((from, to, data));
+        self
+    }
+
+    /// canned value of the wire declared on the CDB with the given name
+    pub fn drive_signal(&mut self, name: &str, data: PipelineData) -> &mut Self {
+        let Some((from, to)) = self.core.cdb.find(name) else {
+            panic!("No wire is declared as {name} on the CDB");
+        };
+        self.drive(from, to, data)
+    }
+
+    /// drive all zeros on the wires coming back to the stage from the later stages that are not driven yet, as if they held bubbles
+    /// undeclared wires get a double word
+    pub fn drive_idle(&mut self, stage: usize) -> &mut Self {
+        for from in stage + 1..self.core.stages.len() {
+            if !self.driven.iter().any(|(driven_from, driven_to, _)| (*driven_from, *driven_to) == (from, stage)) {
+                let width = self.core.cdb.signal(from, stage).width.unwrap_or(64);
+                self.driven.push((from, stage, PipelineData(vec![0x0; width / 8])));
+            }
+        }
+        self
+    }
+
+    pub fn clear_driven(&mut self) {
+        self.driven.clear();
+    }
+
+    /// run the stage function once on the given input, starting from a clean clock cycle:
+    /// every wire is cleared then the canned values are assigned, and the control signals of the stages are released
+    pub fn run(&self, stage: usize, process_fn: fn(&PipelineData, &RiscCore) -> PipelineData, input: &PipelineData) -> StageRun {
+        let core = &self.core;
+        let num_stages = core.stages.len();
+        for index in 0..num_stages {
+            core.cdb.clear(index);
+            core.reset_stage(index, false);
+            core.enable_stage(index, true);
+            core.set_stage_ready(index, true);
+        }
+        for (from, to, data) in &self.driven {
+            core.cdb.assign(*from, *to, data.clone());
+        }
+
+        let output = std::thread::scope(|scope| {
+            let (sender, receiver) = crossbeam_channel::bounded(1);
+            let handle = scope.spawn(move || {
+                let output = process_fn(input, core);
+                let _ = sender.send(());
+                output
+            });
+            // a panicking stage drops the sender, only a stage still running timed out
+            if receiver.recv_timeout(self.timeout) == Err(crossbeam_channel::RecvTimeoutError::Timeout) {
+                // release the stage and report the wires it may be waiting for
+                core.cdb.abort();
+                let _ = handle.join();
+                core.cdb.resume();
+                let undriven: Vec<_> = (stage + 1..num_stages)
+                    .filter(|from| core.cdb.peek(*from, stage).is_none())
+                    .map(|from| core.cdb.signal(from, stage).name.clone())
+                    .collect();
+                panic!("Stage {stage} did not complete within {:?}, it may wait on the wires that are not driven: {}", self.timeout, undriven.join(", "));
+            }
+            match handle.join() {
+                Ok(output) => output,
+                Err(payload) => std::panic::resume_unwind(payload),
+            }
+        });
+
+        let assigned = (0..num_stages).filter_map(|to| core.cdb.peek(stage, to).map(|data| (to, data))).collect();
+        let controls = (0..num_stages)
+            .map(|index| StageControl {
+                reset: core.is_stage_reset(index),
+                enabled: core.is_stage_enabled(index),
+                ready: core.is_stage_ready(index),
+            })
+            .collect();
+        StageRun { output, assigned, controls, trap: core.pending_trap() }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryRequest, MemoryRequestType};
+use crate::risc_soc::risc_soc::WordSize;
+use std::collections::VecDeque;
+
+/// bytes covered by an entry, stores to the same block are combined into one entry
+pub const STORE_BUFFER_BLOCK: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StoreBufferStats {
+    /// stores accepted by the buffer
+    pub stores: u64,
+    /// stores merged into the youngest entry instead of taking a new one
+    pub combined: u64,
+    /// entries written to memory
+    pub drained: u64,
+    /// stores that found the buffer full and waited for its oldest entry to drain
+    pub full_stalls: u64,
+    /// loads that waited for older stores to the same bytes to drain
+    pub load_drains: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StoreBufferEntry {
+    block: Address,
+    bytes: [u8; STORE_BUFFER_BLOCK],
+    /// bytes of the block written by the stores of the entry
+    mask: u8,
+    /// store merged last into the entry
+    pc: Address,
+}
+
+impl StoreBufferEntry {
+    fn overlaps(&self, address: Address, len: usize) -> bool {
+        (0..STORE_BUFFER_BLOCK)
+            .filter(|byte| self.mask & (1 << byte) != 0)
+            .any(|byte| (address..address + len as Address).contains(&(self.block + byte as Address)))
+    }
+}
+
+/// stores waiting between the MEM stage and the data memory, drained in program order
+/// only the youngest entry takes further stores to its block (write-combining), so the stores still reach memory in order
+pub struct StoreBuffer {
+    depth: usize,
+    entries: VecDeque<StoreBufferEntry>,
+    pub stats: StoreBufferStats,
+    /// clock cycles the data port is still busy writing the last drained entry
+    pub busy: u64,
+}
+
+impl StoreBuffer {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth > 0);
+        Self { depth, entries: VecDeque::with_capacity(depth), stats: StoreBufferStats::default(), busy: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.len() == self.depth
+    }
+
+    /// drop the pending stores without writing them, as a reset of the core does
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.busy = 0;
+    }
+
+    /// false if the store needs a new entry and the buffer is full, aligned stores never cross a block
+    pub fn push(&mut self, address: Address, data: &[u8], pc: Address) -> bool {
+        let block = address & !(STORE_BUFFER_BLOCK as Address - 1);
+        let offset = (address - block) as usize;
+        assert!(offset + data.len() <= STORE_BUFFER_BLOCK);
+        let combine = self.entries.back().is_some_and(|entry| entry.block == block);
+        if !combine {
+            if self.is_full() {
+                return false;
+            }
+            self.entries.push_back(StoreBufferEntry { block, bytes: [0; STORE_BUFFER_BLOCK], mask: 0, pc });
+        }
+        let entry = self.entries.back_mut().unwrap();
+        entry.bytes[offset..offset + data.len()].copy_from_slice(data);
+        entry.mask |= (((1u16 << data.len()) - 1) << offset) as u8;
+        entry.pc = pc;
+        self.stats.stores += 1;
+        if combine {
+            self.stats.combined += 1;
+        }
+        true
+    }
+
+    /// PC of the last store merged into the oldest entry, the one `pop` writes next
+    pub fn oldest_pc(&self) -> Option<Address> {
+        self.entries.front().map(|entry| entry.pc)
+    }
+
+    /// oldest entry, as the largest naturally aligned writes covering its bytes
+    /// a word store reaches memory as a single word write, which matters for devices reacting to the written value
+    pub fn pop(&mut self) -> Option<Vec<MemoryRequest>> {
+        let entry = self.entries.pop_front()?;
+        self.stats.drained += 1;
+        let mut requests = vec![];
+        let mut offset = 0;
+        while offset < STORE_BUFFER_BLOCK {
+            if entry.mask & (1 << offset) == 0 {
+                offset += 1;
+                continue;
+            }
+            let size = [WordSize::DOUBLE, WordSize::WORD, WordSize::HALF, WordSize::BYTE]
+                .into_iter()
+                .find(|size| {
+                    let len = *size as usize;
+                    let run = (((1u16 << len) - 1) << offset) as u8;
+                    offset % len == 0 && offset + len <= STORE_BUFFER_BLOCK && entry.mask & run == run
+                })
+                .unwrap();
+            let len = size as usize;
+            requests.push(MemoryRequest {
+                request_type: MemoryRequestType::WRITE,
+                data_address: entry.block + offset as Address,
+                data_size: size,
+                data: Some(MemoryData::new(&entry.bytes[offset..offset + len])),
+            });
+            offset += len;
+        }
+        Some(requests)
+    }
+
+    /// number of the oldest entries to drain before the bytes can be read from memory
+    pub fn pending_before(&self, address: Address, len: usize) -> usize {
+        self.entries.iter().rposition(|entry| entry.overlaps(address, len)).map_or(0, |index| index + 1)
+    }
+
+    /// replace the bytes read from memory at the given address with the ones still waiting in the buffer
+    pub fn forward(&self, address: Address, bytes: &mut [u8]) {
+        for entry in &self.entries {
+            for (index, byte) in bytes.iter_mut().enumerate() {
+                let offset = address + index as Address;
+                if offset >= entry.block && offset < entry.block + STORE_BUFFER_BLOCK as Address {
+                    let position = (offset - entry.block) as usize;
+                    if entry.mask & (1 << position) != 0 {
+                        *byte = entry.bytes[position];
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::risc_soc::memory_management_unit::Address;
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: Address,
+    pub size: u64,
+}
+
+/// symbols read from the loaded ELF, used to annotate traces and to locate special addresses (ex. tohost)
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    /// sorted by address
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn insert(&mut self, symbol: Symbol) {
+        let index = self.symbols.partition_point(|s| s.address <= symbol.address);
+        self.symbols.insert(index, symbol);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.symbols.clear();
+    }
+
+    /// symbols in address order
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    /// closest symbol at or below the address, together with the offset from its start
+    /// symbols with a known size are only matched inside their range
+    pub fn lookup(&self, address: Address) -> Option<(&Symbol, Address)> {
+        let index = self.symbols.partition_point(|s| s.address <= address);
+        let symbol = self.symbols[..index].iter().rev().find(|s| s.size == 0 || address < s.address + s.size)?;
+        Some((symbol, address - symbol.address))
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<Address> {
+        self.symbols.iter().find(|s| s.name == name).map(|s| s.address)
+    }
+
+    /// `function+0xoffset` notation used in traces
+    pub fn format_address(&self, address: Address) -> Option<String> {
+        let (symbol, offset) = self.lookup(address)?;
+        if offset == 0 {
+            Some(symbol.name.clone())
+        } else {
+            Some(format!("{}+0x{:X}", symbol.name, offset))
+        }
+    }
+}
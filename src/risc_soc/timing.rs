@@ -0,0 +1,79 @@
+use crate::risc_soc::energy::{INSTRUCTION_CLASSES, InstructionClass};
+use crate::risc_soc::risc_soc::XLEN;
+
+/// timing of the instructions of a class in the execute stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassTiming {
+    /// clock cycles from the start of an instruction until an instruction using its result can start
+    pub latency: u64,
+    /// clock cycles before the next instruction can start after one of this class (1 when the unit is pipelined)
+    pub throughput: u64,
+}
+
+impl ClassTiming {
+    pub fn new(latency: u64, throughput: u64) -> Self {
+        assert!(latency > 0 && throughput > 0, "An instruction takes at least one clock cycle");
+        Self { latency, throughput }
+    }
+}
+
+/// Latency and throughput of every instruction class, consumed by the pipeline when it schedules the instructions
+/// The functional model stays the same, so a table matching a commercial core approximates its cycle counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimingModel {
+    /// indexed by `InstructionClass`
+    pub classes: [ClassTiming; INSTRUCTION_CLASSES],
+}
+
+impl Default for TimingModel {
+    /// single cycle ALU, a 3 cycle multiplier and an iterative divider producing one bit per cycle
+    /// loads and atomics have the latency of the load-use stall of the 5-stage pipeline
+    fn default() -> Self {
+        let single = ClassTiming::new(1, 1);
+        let mut classes = [single; INSTRUCTION_CLASSES];
+        classes[InstructionClass::Multiply as usize] = ClassTiming::new(3, 3);
+        classes[InstructionClass::Divide as usize] = ClassTiming::new(XLEN as u64, XLEN as u64);
+        classes[InstructionClass::Load as usize] = ClassTiming::new(2, 1);
+        classes[InstructionClass::Atomic as usize] = ClassTiming::new(2, 1);
+        Self { classes }
+    }
+}
+
+impl TimingModel {
+    pub fn of(&self, class: InstructionClass) -> ClassTiming {
+        self.classes[class as usize]
+    }
+
+    pub fn with_timing(mut self, class: InstructionClass, latency: u64, throughput: u64) -> Self {
+        self.classes[class as usize] = ClassTiming::new(latency, throughput);
+        self
+    }
+
+    /// read the table from a JSON object, the missing classes and fields keep their default value:
+    /// `{ "multiply": { "latency": 4, "throughput": 1 }, "divide": { "latency": 20, "throughput": 20 } }`
+    pub fn from_json(config: &str) -> Result<Self, String> {
+        let config = json::parse(config).map_err(|e| e.to_string())?;
+        if !config.is_object() {
+            return Err("the timing configuration must be a JSON object".to_string());
+        }
+        let mut model = Self::default();
+        for (key, value) in config.entries() {
+            let class = InstructionClass::ALL
+                .iter()
+                .position(|class| class.name() == key)
+                .ok_or(format!("unknown instruction class: {key}"))?;
+            let cycles = |field: &str, default: u64| match &value[field] {
+                json::JsonValue::Null => Ok(default),
+                cycles => cycles.as_u64().filter(|cycles| *cycles > 0).ok_or(format!("{key}.{field} must be a positive integer")),
+            };
+            let timing = model.classes[class];
+            model.classes[class] = ClassTiming::new(cycles("latency", timing.latency)?, cycles("throughput", timing.throughput)?);
+        }
+        Ok(model)
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let config = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        Self::from_json(&config)
+    }
+}
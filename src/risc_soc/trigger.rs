@@ -0,0 +1,145 @@
+use crate::risc_soc::risc_soc::{RiscWord, XLEN};
+
+/// CSRs of the trigger module of the Debug spec, tdata1 and tdata2 are the registers of the trigger selected by tselect
+pub const CSR_TSELECT: u16 = 0x7A0;
+pub const CSR_TDATA1: u16 = 0x7A1;
+pub const CSR_TDATA2: u16 = 0x7A2;
+pub const CSR_TDATA3: u16 = 0x7A3;
+pub const CSR_TINFO: u16 = 0x7A4;
+
+/// number of triggers of each hart
+pub const NUM_TRIGGERS: usize = 4;
+
+/// fields of tdata1 for address match triggers (mcontrol, type 2)
+/// the triggers always fire before the access or the execution, on single addresses and with no chaining
+const MCONTROL_TYPE: RiscWord = 2 << (XLEN - 4);
+pub const MCONTROL_DMODE: RiscWord = 1 << (XLEN - 5);
+pub const MCONTROL_HIT: RiscWord = 1 << 20;
+const MCONTROL_ACTION_SHIFT: u32 = 12;
+const MCONTROL_MATCH_SHIFT: u32 = 7;
+pub const MCONTROL_M: RiscWord = 1 << 6;
+pub const MCONTROL_EXECUTE: RiscWord = 1 << 2;
+pub const MCONTROL_STORE: RiscWord = 1 << 1;
+pub const MCONTROL_LOAD: RiscWord = 1 << 0;
+
+/// comparisons of the address with tdata2 selected by the match field
+const MATCH_EQUAL: RiscWord = 0;
+const MATCH_GREATER_OR_EQUAL: RiscWord = 2;
+const MATCH_LESS_THAN: RiscWord = 3;
+
+/// what a trigger does when it fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerAction {
+    /// raise a breakpoint exception, with mtval holding the matched address
+    Breakpoint,
+    /// enter Debug Mode, only for the triggers reserved to the debugger (dmode set)
+    DebugMode,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trigger {
+    pub tdata1: RiscWord,
+    pub tdata2: RiscWord,
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Self { tdata1: MCONTROL_TYPE, tdata2: 0 }
+    }
+}
+
+impl Trigger {
+    fn matches(&self, accesses: RiscWord, address: RiscWord) -> bool {
+        if self.tdata1 & MCONTROL_M == 0 || self.tdata1 & accesses == 0 {
+            return false;
+        }
+        match (self.tdata1 >> MCONTROL_MATCH_SHIFT) & 0b1111 {
+            MATCH_GREATER_OR_EQUAL => address >= self.tdata2,
+            MATCH_LESS_THAN => address < self.tdata2,
+            _ => address == self.tdata2,
+        }
+    }
+
+    fn action(&self) -> TriggerAction {
+        if (self.tdata1 >> MCONTROL_ACTION_SHIFT) & 0b1111 == 1 {
+            TriggerAction::DebugMode
+        } else {
+            TriggerAction::Breakpoint
+        }
+    }
+}
+
+/// hardware breakpoints on the address of the executed instructions or of the loads and stores
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TriggerModule {
+    pub select: usize,
+    pub triggers: [Trigger; NUM_TRIGGERS],
+}
+
+impl TriggerModule {
+    pub fn read(&self, csr: u16) -> Option<RiscWord> {
+        let trigger = &self.triggers[self.select];
+        match csr {
+            CSR_TSELECT => Some(self.select as RiscWord),
+            CSR_TDATA1 => Some(trigger.tdata1),
+            CSR_TDATA2 => Some(trigger.tdata2),
+            CSR_TDATA3 => Some(0),
+            // only address match triggers are supported
+            CSR_TINFO => Some(1 << 2),
+            _ => None,
+        }
+    }
+
+    /// tselect keeps its value when written with a trigger that does not exist, which is how debuggers count them
+    /// the triggers reserved to the debugger (dmode set) and their dmode bit can only be written in Debug Mode,
+    /// entering Debug Mode is a reserved action for the other triggers, which raise breakpoint exceptions instead
+    pub fn write(&mut self, csr: u16, value: RiscWord, debug_mode: bool) -> Option<()> {
+        if csr == CSR_TSELECT {
+            if (value as usize) < NUM_TRIGGERS {
+                self.select = value as usize;
+            }
+            return Some(());
+        }
+        let trigger = &mut self.triggers[self.select];
+        if trigger.tdata1 & MCONTROL_DMODE != 0 && !debug_mode {
+            return match csr {
+                CSR_TDATA1 | CSR_TDATA2 | CSR_TDATA3 => Some(()),
+                _ => None,
+            };
+        }
+        match csr {
+            CSR_TDATA1 => {
+                let dmode = if debug_mode { value & MCONTROL_DMODE } else { 0 };
+                let action = match (value >> MCONTROL_ACTION_SHIFT) & 0b1111 {
+                    1 if dmode != 0 => 1,
+                    _ => 0,
+                };
+                let matching = match (value >> MCONTROL_MATCH_SHIFT) & 0b1111 {
+                    matching @ (MATCH_GREATER_OR_EQUAL | MATCH_LESS_THAN) => matching,
+                    _ => MATCH_EQUAL,
+                };
+                let flags = MCONTROL_HIT | MCONTROL_M | MCONTROL_EXECUTE | MCONTROL_STORE | MCONTROL_LOAD;
+                trigger.tdata1 = MCONTROL_TYPE
+                    | dmode
+                    | action << MCONTROL_ACTION_SHIFT
+                    | matching << MCONTROL_MATCH_SHIFT
+                    | value & flags;
+                Some(())
+            }
+            CSR_TDATA2 => {
+                trigger.tdata2 = value;
+                Some(())
+            }
+            CSR_TDATA3 | CSR_TINFO => Some(()),
+            _ => None,
+        }
+    }
+
+    /// the action of the first trigger matching one of the `accesses` (MCONTROL_EXECUTE, MCONTROL_LOAD, MCONTROL_STORE)
+    /// on `address`, its hit bit is set
+    pub fn fire(&mut self, accesses: RiscWord, address: RiscWord) -> Option<TriggerAction> {
+        let trigger = self.triggers.iter_mut().find(|trigger| trigger.matches(accesses, address))?;
+        trigger.tdata1 |= MCONTROL_HIT;
+        Some(trigger.action())
+    }
+}
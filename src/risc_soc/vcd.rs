@@ -0,0 +1,148 @@
+use crate::risc_soc::pipeline_stage::{ClockCycle, PipelineData};
+use crate::risc_soc::wire::Signal;
+use std::io::Write;
+
+/// values of a signal at the end of every sampled clock cycle, None while it is undefined (ex. a wire not assigned in a cycle)
+struct Trace {
+    scope: String,
+    name: String,
+    values: Vec<(ClockCycle, Option<Vec<u8>>)>,
+}
+
+/// samples the pipeline registers and the CDB wires of a core every clock cycle and writes them as a VCD waveform,
+/// which can be opened with GTKWave or any other waveform viewer
+/// values are kept in memory until the file is written, the width of each signal is the widest value it carried
+pub struct WaveformRecorder {
+    stage_names: Vec<String>,
+    signals: Vec<Trace>,
+}
+
+impl WaveformRecorder {
+    /// the names of the stages are used as the scopes of their signals
+    pub fn new(stage_names: Vec<String>) -> Self {
+        Self { stage_names, signals: vec![] }
+    }
+
+    fn signal(&mut self, scope: &str, name: &str) -> &mut Trace {
+        let index = match self.signals.iter().position(|signal| signal.scope == scope && signal.name == name) {
+            Some(index) => index,
+            None => {
+                self.signals.push(Trace { scope: scope.to_string(), name: name.to_string(), values: vec![] });
+                self.signals.len() - 1
+            }
+        };
+        &mut self.signals[index]
+    }
+
+    pub fn sample(&mut self, clock_cycle: ClockCycle, scope: &str, name: &str, value: Option<&PipelineData>) {
+        let value = value.map(|data| data.0.clone());
+        self.signal(scope, name).values.push((clock_cycle, value));
+    }
+
+    pub fn sample_stage(&mut self, clock_cycle: ClockCycle, stage_index: usize, instruction: u32, data_out: &PipelineData) {
+        let scope = self.stage_names[stage_index].clone();
+        self.sample(clock_cycle, &scope, "instruction", Some(&PipelineData(instruction.to_le_bytes().to_vec())));
+        self.sample(clock_cycle, &scope, "data_out", Some(data_out));
+    }
+
+    /// wires are only recorded once they are assigned for the first time, until then they are left out of the waveform
+    /// undeclared wires are named after the stages they connect
+    pub fn sample_wire(&mut self, clock_cycle: ClockCycle, from: usize, to: usize, signal: &Signal) {
+        let name = match signal.width {
+            Some(_) => signal.name.clone(),
+            None => format!("{}_to_{}", self.stage_names[from], self.stage_names[to]),
+        };
+        let value = signal.peek();
+        let known = self.signals.iter().any(|signal| signal.scope == "cdb" && signal.name == name);
+        if known || value.is_some() {
+            self.sample(clock_cycle, "cdb", &name, value.as_ref());
+        }
+    }
+
+    /// write the waveform, `clock_period` (in ns) sets the time between two rising edges of the clock
+    pub fn write_vcd<W: Write>(&self, mut out: W, clock_period: u128) -> std::io::Result<()> {
+        // the clock falls in the middle of the period, so a period needs at least two time units
+        let period = clock_period.max(2);
+        writeln!(out, "$version riscv-on-rust $end")?;
+        writeln!(out, "$timescale 1ns $end")?;
+        writeln!(out, "$scope module core $end")?;
+        writeln!(out, "$var wire 1 {} clk $end", identifier(0))?;
+        let widths: Vec<usize> = self
+            .signals
+            .iter()
+            .map(|signal| signal.values.iter().filter_map(|(_, value)| value.as_ref()).map(Vec::len).max().unwrap_or(1).max(1) * 8)
+            .collect();
+        let mut scopes: Vec<&str> = vec![];
+        for signal in &self.signals {
+            if !scopes.contains(&signal.scope.as_str()) {
+                scopes.push(&signal.scope);
+            }
+        }
+        for scope in scopes {
+            writeln!(out, "$scope module {scope} $end")?;
+            for (index, signal) in self.signals.iter().enumerate().filter(|(_, signal)| signal.scope == scope) {
+                writeln!(out, "$var wire {} {} {} $end", widths[index], identifier(index + 1), signal.name)?;
+            }
+            writeln!(out, "$upscope $end")?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+
+        let first_cycle = self.signals.iter().flat_map(|signal| signal.values.first()).map(|(cycle, _)| *cycle).min();
+        let last_cycle = self.signals.iter().flat_map(|signal| signal.values.last()).map(|(cycle, _)| *cycle).max();
+        let (Some(first_cycle), Some(last_cycle)) = (first_cycle, last_cycle) else {
+            return Ok(());
+        };
+        let mut positions = vec![0usize; self.signals.len()];
+        let mut current: Vec<Option<Option<&Vec<u8>>>> = vec![None; self.signals.len()];
+        for cycle in first_cycle..=last_cycle {
+            writeln!(out, "#{}", (cycle - first_cycle) as u128 * period)?;
+            writeln!(out, "1{}", identifier(0))?;
+            for (index, signal) in self.signals.iter().enumerate() {
+                // a signal not sampled in a cycle (ex. a wire before its first assignment) is undefined
+                let value = match signal.values.get(positions[index]) {
+                    Some((sample_cycle, value)) if *sample_cycle == cycle => {
+                        positions[index] += 1;
+                        value.as_ref()
+                    }
+                    _ => None,
+                };
+                // only changes are dumped
+                if current[index] != Some(value) {
+                    current[index] = Some(value);
+                    writeln!(out, "b{} {}", binary(value, widths[index]), identifier(index + 1))?;
+                }
+            }
+            writeln!(out, "#{}", (cycle - first_cycle) as u128 * period + period / 2)?;
+            writeln!(out, "0{}", identifier(0))?;
+        }
+        Ok(())
+    }
+}
+
+/// short identifier code of a signal, made of the printable ASCII characters
+fn identifier(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+/// little endian bytes as a binary vector, most significant bit first
+fn binary(value: Option<&Vec<u8>>, width: usize) -> String {
+    match value {
+        Some(bytes) => (0..width)
+            .rev()
+            .map(|bit| {
+                let byte = bytes.get(bit / 8).copied().unwrap_or(0);
+                if byte >> (bit % 8) & 0x1 == 0x1 { '1' } else { '0' }
+            })
+            .collect(),
+        None => "x".to_string(),
+    }
+}
@@ -0,0 +1,149 @@
+use crate::risc_soc::risc_soc::{RiscWord, XLEN};
+
+/// CSRs of the vector extensions, vl, vtype and vlenb are read-only and only changed by the vsetvl instructions
+pub const CSR_VSTART: u16 = 0x008;
+pub const CSR_VXSAT: u16 = 0x009;
+pub const CSR_VXRM: u16 = 0x00A;
+pub const CSR_VCSR: u16 = 0x00F;
+pub const CSR_VL: u16 = 0xC20;
+pub const CSR_VTYPE: u16 = 0xC21;
+pub const CSR_VLENB: u16 = 0xC22;
+
+/// set in vtype when the program requested a configuration the hart does not support, vector instructions are then illegal
+pub const VTYPE_VILL: RiscWord = 1 << (XLEN - 1);
+/// fields of vtype: vma, vta, vsew and vlmul, the other bits are reserved
+const VTYPE_MASK: RiscWord = 0xFF;
+
+/// widest element of Zve32x, in bits
+pub const ELEN: usize = 32;
+
+/// vector register file and vector CSRs of a hart implementing Zve32x
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VectorRegisters {
+    /// bits of each of the 32 registers
+    vlen: usize,
+    /// registers stored one after the other, so the registers of a group are contiguous
+    data: Vec<u8>,
+    pub vl: RiscWord,
+    pub vtype: RiscWord,
+    pub vstart: RiscWord,
+    pub vxsat: RiscWord,
+    pub vxrm: RiscWord,
+}
+
+impl VectorRegisters {
+    pub fn new(vlen: usize) -> Self {
+        assert!(vlen.is_power_of_two() && (ELEN..=65536).contains(&vlen), "VLEN must be a power of two between {ELEN} and 65536 bits");
+        Self { vlen, data: vec![0; 32 * vlen / 8], vl: 0, vtype: VTYPE_VILL, vstart: 0, vxsat: 0, vxrm: 0 }
+    }
+
+    pub fn vlen(&self) -> usize {
+        self.vlen
+    }
+
+    pub fn vlenb(&self) -> usize {
+        self.vlen / 8
+    }
+
+    /// bytes of the selected element width
+    pub fn sew(&self) -> usize {
+        1 << ((self.vtype >> 3) & 0b111)
+    }
+
+    /// registers per group for LMUL >= 1, 1 for the fractional LMULs
+    pub fn group_registers(&self) -> usize {
+        match self.vtype & 0b111 {
+            lmul @ 0..=3 => 1 << lmul,
+            _ => 1,
+        }
+    }
+
+    /// maximum number of elements of a group with the given vtype, None if the hart does not support it
+    pub fn vlmax(&self, vtype: RiscWord) -> Option<usize> {
+        let sew = 8 << ((vtype >> 3) & 0b111);
+        if vtype & !VTYPE_MASK != 0 || sew > ELEN {
+            return None;
+        }
+        // fractional LMULs still have to hold an element of ELEN bits at the widest SEW they allow
+        let (numerator, denominator) = match vtype & 0b111 {
+            lmul @ 0..=3 => (1 << lmul, 1),
+            0b111 => (1, 2),
+            0b110 => (1, 4),
+            0b101 => (1, 8),
+            _ => return None,
+        };
+        if sew * denominator > ELEN * numerator {
+            return None;
+        }
+        Some(self.vlen * numerator / denominator / sew).filter(|vlmax| *vlmax > 0)
+    }
+
+    /// perform a vsetvl with the requested application vector length (None to keep vl), returning the new vl
+    pub fn set_vtype(&mut self, vtype: RiscWord, avl: Option<RiscWord>) -> RiscWord {
+        match self.vlmax(vtype) {
+            Some(vlmax) => {
+                self.vtype = vtype;
+                self.vl = avl.unwrap_or(self.vl).min(vlmax as RiscWord);
+            }
+            None => {
+                self.vtype = VTYPE_VILL;
+                self.vl = 0;
+            }
+        }
+        self.vstart = 0;
+        self.vl
+    }
+
+    /// element `index` of `size` bytes of the group starting at `register`, zero extended
+    pub fn element(&self, register: usize, index: usize, size: usize) -> u64 {
+        let start = register * self.vlenb() + index * size;
+        self.data[start..start + size].iter().rev().fold(0, |value, byte| value << 8 | *byte as u64)
+    }
+
+    pub fn set_element(&mut self, register: usize, index: usize, size: usize, value: u64) {
+        let start = register * self.vlenb() + index * size;
+        self.data[start..start + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    /// true if the `size` bytes elements up to `count` of the group starting at `register` are in the register file
+    pub fn fits(&self, register: usize, count: usize, size: usize) -> bool {
+        register * self.vlenb() + count * size <= self.data.len()
+    }
+
+    /// mask bit of element `index`, held in v0
+    pub fn mask(&self, index: usize) -> bool {
+        (self.data[index / 8] >> (index % 8)) & 0x1 == 0x1
+    }
+
+    pub fn read_csr(&self, csr: u16) -> Option<RiscWord> {
+        match csr {
+            CSR_VSTART => Some(self.vstart),
+            CSR_VXSAT => Some(self.vxsat),
+            CSR_VXRM => Some(self.vxrm),
+            CSR_VCSR => Some(self.vxrm << 1 | self.vxsat),
+            CSR_VL => Some(self.vl),
+            CSR_VTYPE => Some(self.vtype),
+            CSR_VLENB => Some(self.vlenb() as RiscWord),
+            _ => None,
+        }
+    }
+
+    /// None for the CSRs that are not vector CSRs and for the read-only ones
+    pub fn write_csr(&mut self, csr: u16, value: RiscWord) -> Option<()> {
+        match csr {
+            CSR_VSTART => self.vstart = value % (self.vlen as RiscWord),
+            CSR_VXSAT => self.vxsat = value & 0b1,
+            CSR_VXRM => self.vxrm = value & 0b11,
+            CSR_VCSR => {
+                self.vxsat = value & 0b1;
+                self.vxrm = (value >> 1) & 0b11;
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.vlen);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::risc_soc::pipeline_stage::PipelineData;
 use std::{
-    sync::{Arc, Condvar, Mutex},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Condvar, Mutex},
     time::Duration,
 };
 use std::hash::{BuildHasher, Hasher, RandomState};
@@ -9,13 +9,15 @@ use std::hash::{BuildHasher, Hasher, RandomState};
 /// In order to react to it we are using the CondVar sync mechanism in Rust
 /// If there is any kind of data that arrived until the specified `critical_path` delay, then we can read it
 /// The `critical_path` delay should usually be within the clock cycle of the cpu, thus modeling the behaviour of metastability if the setup and hold up times are violated
-
+///
 /// we are reusing Pipeline data here for olding the actual bits and bytes that we want to "wire"
 pub struct Wire {
     /// We make use of Option as a Valid assertion for our wire data
     data: Arc<(Mutex<Option<PipelineData>>, Condvar)>,
     critical_path: Option<u128>,
     debug: bool,
+    /// set when the simulation is abandoned, readers then stop waiting for the wire to be assigned
+    aborted: Arc<AtomicBool>,
 }
 
 impl Wire {
@@ -24,17 +26,29 @@ impl Wire {
             critical_path,
             data: Arc::new((Mutex::new(None), Condvar::new())),
             debug,
+            aborted: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// share the abort flag of the bus the wire belongs to
+    pub fn with_abort_flag(mut self, aborted: Arc<AtomicBool>) -> Self {
+        self.aborted = aborted;
+        self
+    }
+
+    /// wake up the stages waiting for the wire, the abort flag must be set beforehand
+    pub fn wake_up(&self) {
+        let (lock, cvar) = &*self.data;
+        let _wire = lock.lock().unwrap();
+        cvar.notify_all();
+    }
+
     pub fn enable_debug(&mut self, debug: bool) {
         self.debug = debug;
     }
 
     pub fn clear(&self) {
-        let pair = self.data.clone();
-        let (lock, cvar) = &*pair;
-        let mut wire = lock.lock().unwrap();
+        let mut wire = self.data.0.lock().unwrap();
         *wire = None;
     }
 
@@ -46,14 +60,19 @@ impl Wire {
         cvar.notify_all();
     }
 
+    /// current value of the wire without waiting for it to be assigned, used to sample it at the end of a clock cycle
+    pub fn peek(&self) -> Option<PipelineData> {
+        self.data.0.lock().unwrap().clone()
+    }
+
     pub fn read(&self) -> PipelineData {
         let pair = self.data.clone();
         let (lock, cvar) = &*pair;
         let wire = lock.lock().unwrap();
 
-        if self.critical_path.is_some() {
+        if let Some(critical_path) = self.critical_path {
             let result = cvar
-                .wait_timeout_while(wire, Duration::from_nanos(self.critical_path.unwrap() as u64), |data|{
+                .wait_timeout_while(wire, Duration::from_nanos(critical_path as u64), |data|{
                     data.is_none()
                 })
                 .unwrap();
@@ -67,25 +86,96 @@ impl Wire {
                 let bytes: Vec<u8> = (0..256)
                 .map(|_| (RandomState::new().build_hasher().finish() % 255) as u8)
                 .collect();
-                return PipelineData(bytes)
+                PipelineData(bytes)
             } else {
                 if self.debug {
                     println!("Combinational logic delay was within the defined critical path");
                 } else {
                     tracing::info!("Combinational logic delay was within the defined critical path");
                 }
-                return result.0.as_ref().unwrap().clone()
+                result.0.as_ref().unwrap().clone()
             }
         } else {
             let result = cvar.wait_while(wire, |data|{
-                data.is_none()
+                data.is_none() && !self.aborted.load(Ordering::SeqCst)
             }).unwrap();
+            if result.is_none() {
+                // the simulation was abandoned while waiting, the value does not matter anymore
+                return PipelineData(vec![0u8; 256]);
+            }
             let data = result.as_ref().unwrap();
             //should never get empty data as this models ideal behaviour
             assert!(!data.is_empty());
-            return data.clone();
+            data.clone()
+        }
+    }
+
+}
+
+
+/// named wire with a declared width, like a `wire [width-1:0] name;` net in Verilog
+/// assigning data of another width is a bug of the stage driving it, so it is caught when the data is assigned
+pub struct Signal {
+    pub name: String,
+    /// width in bits, None while the signal is not declared and accepts data of any size
+    pub width: Option<usize>,
+    wire: Wire,
+}
+
+impl Signal {
+    pub fn new(name: String, wire: Wire) -> Self {
+        Self { name, width: None, wire }
+    }
+
+    pub fn declare(&mut self, name: &str, width: usize) {
+        assert!(width > 0 && width.is_multiple_of(8), "Signal {name} must be a whole number of bytes wide, got {width} bits");
+        self.name = name.to_string();
+        self.width = Some(width);
+    }
+
+    pub fn assign(&self, data: PipelineData) {
+        if let Some(width) = self.width {
+            assert!(
+                data.size() * 8 == width,
+                "Signal {} is declared with {} bits but was assigned {} bits",
+                self.name,
+                width,
+                data.size() * 8
+            );
         }
+        self.wire.assign(data);
+    }
+}
+
+impl std::ops::Deref for Signal {
+    type Target = Wire;
+    fn deref(&self) -> &Self::Target {
+        &self.wire
     }
+}
 
+impl std::ops::DerefMut for Signal {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.wire
+    }
 }
 
+impl std::fmt::Debug for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.width {
+            Some(width) => write!(f, "{}[{}:0] = ", self.name, width - 1)?,
+            None => write!(f, "{} = ", self.name)?,
+        }
+        match self.peek() {
+            // most significant byte first, as the value would be shown by a simulator
+            Some(data) => {
+                write!(f, "0x")?;
+                for byte in data.0.iter().rev() {
+                    write!(f, "{byte:02X}")?;
+                }
+                Ok(())
+            }
+            None => write!(f, "x"),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::risc_soc::isa_model::MicroOp;
+use crate::risc_soc::risc_soc::{RiscSignedWord, RiscWord, XLEN};
+use crate::rv32i_baremetal::decode::{OP_ALU, OP_ALUI, OP_ALUI_W, OP_ALU_W, sign_extend};
+
+/// bit index and rotation amounts only use the lower log2(XLEN) bits of the operand
+const SHAMT_MASK: RiscWord = (XLEN - 1) as RiscWord;
+const SHAMT_W_MASK: u32 = 0b11111;
+/// the lowest bit of FUNCT7 is the 6th bit of the shift amount of the immediate forms on RV64
+const FUNCT_6_MASK: u8 = 0b1111110;
+
+// FUNCT7 values of the register forms under OP and OP-32, the immediate forms of the single-bit and rotate instructions share them
+const FUNCT_7_SHADD: u8 = 0b0010000;
+const FUNCT_7_NEGATED: u8 = 0b0100000;
+const FUNCT_7_MINMAX: u8 = 0b0000101;
+const FUNCT_7_ROTATE: u8 = 0b0110000;
+/// zext.h is the pack instruction of the Zbkb extension with rs2 = x0, add.uw uses the same FUNCT7 under OP-32
+const FUNCT_7_ZEXT: u8 = 0b0000100;
+const FUNCT_7_BCLR: u8 = 0b0100100;
+const FUNCT_7_BINV: u8 = 0b0110100;
+const FUNCT_7_BSET: u8 = 0b0010100;
+const FUNCT_7_CZERO: u8 = 0b0000111;
+
+// 12 bit immediates of the unary instructions under OP-IMM and OP-IMM-32
+const IMM_CLZ: RiscWord = 0x600;
+const IMM_CTZ: RiscWord = 0x601;
+const IMM_CPOP: RiscWord = 0x602;
+const IMM_SEXT_B: RiscWord = 0x604;
+const IMM_SEXT_H: RiscWord = 0x605;
+const IMM_ORC_B: RiscWord = 0x287;
+const IMM_REV8: RiscWord = if XLEN == 64 { 0x6B8 } else { 0x698 };
+
+/// extension and result of a Zba, Zbb, Zbs or Zicond instruction, `None` for any other instruction
+/// the instruction is only legal when the hart implements the extension, which EX checks
+pub fn bit_manipulation(op: &MicroOp, rs1: RiscWord, rs2: RiscWord) -> Option<(&'static str, RiscWord)> {
+    let (func3, func7) = (op.func3, op.func7);
+    let bit = |index: RiscWord| -> RiscWord { 1 << (index & SHAMT_MASK) };
+    let imm: RiscWord = op.imm & 0xFFF;
+    let result = match op.opcode {
+        OP_ALU => match (func7, func3) {
+            (FUNCT_7_SHADD, 0b010) => ("Zba", (rs1 << 1).wrapping_add(rs2)),
+            (FUNCT_7_SHADD, 0b100) => ("Zba", (rs1 << 2).wrapping_add(rs2)),
+            (FUNCT_7_SHADD, 0b110) => ("Zba", (rs1 << 3).wrapping_add(rs2)),
+            (FUNCT_7_NEGATED, 0b111) => ("Zbb", rs1 & !rs2),
+            (FUNCT_7_NEGATED, 0b110) => ("Zbb", rs1 | !rs2),
+            (FUNCT_7_NEGATED, 0b100) => ("Zbb", !(rs1 ^ rs2)),
+            (FUNCT_7_MINMAX, 0b100) => ("Zbb", (rs1 as RiscSignedWord).min(rs2 as RiscSignedWord) as RiscWord),
+            (FUNCT_7_MINMAX, 0b101) => ("Zbb", rs1.min(rs2)),
+            (FUNCT_7_MINMAX, 0b110) => ("Zbb", (rs1 as RiscSignedWord).max(rs2 as RiscSignedWord) as RiscWord),
+            (FUNCT_7_MINMAX, 0b111) => ("Zbb", rs1.max(rs2)),
+            (FUNCT_7_ROTATE, 0b001) => ("Zbb", rs1.rotate_left(shift_amount(rs2))),
+            (FUNCT_7_ROTATE, 0b101) => ("Zbb", rs1.rotate_right(shift_amount(rs2))),
+            (FUNCT_7_ZEXT, 0b100) if XLEN == 32 && op.rs2 == 0 => ("Zbb", rs1 & 0xFFFF),
+            (FUNCT_7_BCLR, 0b001) => ("Zbs", rs1 & !bit(rs2)),
+            (FUNCT_7_BCLR, 0b101) => ("Zbs", (rs1 >> (rs2 & SHAMT_MASK)) & 0x1),
+            (FUNCT_7_BINV, 0b001) => ("Zbs", rs1 ^ bit(rs2)),
+            (FUNCT_7_BSET, 0b001) => ("Zbs", rs1 | bit(rs2)),
+            (FUNCT_7_CZERO, 0b101) => ("Zicond", if rs2 == 0 { 0 } else { rs1 }),
+            (FUNCT_7_CZERO, 0b111) => ("Zicond", if rs2 != 0 { 0 } else { rs1 }),
+            _ => return None,
+        },
+        OP_ALUI => match (func3, imm) {
+            (0b001, IMM_CLZ) => ("Zbb", rs1.leading_zeros() as RiscWord),
+            (0b001, IMM_CTZ) => ("Zbb", rs1.trailing_zeros() as RiscWord),
+            (0b001, IMM_CPOP) => ("Zbb", rs1.count_ones() as RiscWord),
+            (0b001, IMM_SEXT_B) => ("Zbb", rs1 as i8 as RiscSignedWord as RiscWord),
+            (0b001, IMM_SEXT_H) => ("Zbb", rs1 as i16 as RiscSignedWord as RiscWord),
+            (0b101, IMM_ORC_B) => ("Zbb", or_combine_bytes(rs1)),
+            (0b101, IMM_REV8) => ("Zbb", rs1.swap_bytes()),
+            _ => match (func3, func7 & FUNCT_6_MASK) {
+                (0b101, FUNCT_7_ROTATE) => ("Zbb", rs1.rotate_right(shift_amount(op.imm))),
+                (0b001, FUNCT_7_BCLR) => ("Zbs", rs1 & !bit(op.imm)),
+                (0b101, FUNCT_7_BCLR) => ("Zbs", (rs1 >> (op.imm & SHAMT_MASK)) & 0x1),
+                (0b001, FUNCT_7_BINV) => ("Zbs", rs1 ^ bit(op.imm)),
+                (0b001, FUNCT_7_BSET) => ("Zbs", rs1 | bit(op.imm)),
+                _ => return None,
+            },
+        },
+        // RV64 only: the unsigned word forms zero extend the lower 32 bits of rs1, the other results are sign extended
+        OP_ALU_W if XLEN == 64 => {
+            let (rs1_w, rs2_w) = (low_word(rs1), low_word(rs2));
+            match (func7, func3) {
+                (FUNCT_7_ZEXT, 0b000) => ("Zba", (rs1_w as RiscWord).wrapping_add(rs2)),
+                (FUNCT_7_SHADD, 0b010) => ("Zba", ((rs1_w as RiscWord) << 1).wrapping_add(rs2)),
+                (FUNCT_7_SHADD, 0b100) => ("Zba", ((rs1_w as RiscWord) << 2).wrapping_add(rs2)),
+                (FUNCT_7_SHADD, 0b110) => ("Zba", ((rs1_w as RiscWord) << 3).wrapping_add(rs2)),
+                (FUNCT_7_ZEXT, 0b100) if op.rs2 == 0 => ("Zbb", rs1 & 0xFFFF),
+                (FUNCT_7_ROTATE, 0b001) => ("Zbb", sign_extend(rs1_w.rotate_left(rs2_w & SHAMT_W_MASK))),
+                (FUNCT_7_ROTATE, 0b101) => ("Zbb", sign_extend(rs1_w.rotate_right(rs2_w & SHAMT_W_MASK))),
+                _ => return None,
+            }
+        }
+        OP_ALUI_W if XLEN == 64 => {
+            let rs1_w = low_word(rs1);
+            match (func3, imm) {
+                (0b001, IMM_CLZ) => ("Zbb", rs1_w.leading_zeros() as RiscWord),
+                (0b001, IMM_CTZ) => ("Zbb", rs1_w.trailing_zeros() as RiscWord),
+                (0b001, IMM_CPOP) => ("Zbb", rs1_w.count_ones() as RiscWord),
+                _ => match (func3, func7 & FUNCT_6_MASK) {
+                    (0b001, FUNCT_7_ZEXT) => ("Zba", (rs1_w as RiscWord) << (op.imm & SHAMT_MASK)),
+                    (0b101, FUNCT_7_ROTATE) if func7 == FUNCT_7_ROTATE => {
+                        ("Zbb", sign_extend(rs1_w.rotate_right(low_word(op.imm) & SHAMT_W_MASK)))
+                    }
+                    _ => return None,
+                },
+            }
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// rotation amount of the register and immediate forms
+fn shift_amount(value: RiscWord) -> u32 {
+    let amount: RiscWord = value & SHAMT_MASK;
+    amount as u32
+}
+
+/// lower 32 bits of an operand, the only ones read by the word forms of RV64
+fn low_word(value: RiscWord) -> u32 {
+    let value: RiscWord = value;
+    value as u32
+}
+
+/// orc.b: every byte becomes all ones if any of its bits is set, and zero otherwise
+fn or_combine_bytes(value: RiscWord) -> RiscWord {
+    (0..XLEN / 8).fold(0, |result, byte| {
+        let mask = (0xFF as RiscWord) << (8 * byte);
+        if value & mask != 0 { result | mask } else { result }
+    })
+}
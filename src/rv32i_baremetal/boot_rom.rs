@@ -0,0 +1,129 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::csr::CSR_MHARTID;
+use crate::risc_soc::risc_soc::XLEN;
+use crate::rv32i_baremetal::decode::{FUNC3_CSRRS, OP_ALUI, OP_JALR, OP_LUI, OP_SYSTEM};
+
+/// registers used by the boot stub
+const REG_A0: u32 = 10;
+const REG_A1: u32 = 11;
+const REG_T0: u32 = 5;
+
+fn lui(rd: u32, imm: u32) -> u32 {
+    (imm & 0xFFFF_F000) | (rd << 7) | OP_LUI as u32
+}
+
+fn addi(rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xFFF) << 20) | (rs1 << 15) | (rd << 7) | OP_ALUI as u32
+}
+
+/// slli (func3 0b001) or srli (func3 0b101) by a 6-bit amount
+fn shift_immediate(func3: u32, rd: u32, rs1: u32, shamt: u32) -> u32 {
+    ((shamt & 0x3F) << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | OP_ALUI as u32
+}
+
+fn jalr(rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xFFF) << 20) | (rs1 << 15) | (rd << 7) | OP_JALR as u32
+}
+
+/// csrr rd, csr (csrrs with x0 as source)
+fn csrr(rd: u32, csr: u16) -> u32 {
+    ((csr as u32) << 20) | ((FUNC3_CSRRS as u32) << 12) | (rd << 7) | OP_SYSTEM as u32
+}
+
+/// lui + addi pair loading a full 32-bit constant, the upper part is rounded so the sign extended addi lands on value
+/// on RV64 lui sign extends, so addresses in the upper 2GiB are zero extended again with a shift pair
+fn load_immediate(rd: u32, value: u32) -> Vec<u32> {
+    let upper = value.wrapping_add(0x800) & 0xFFFF_F000;
+    let lower = value.wrapping_sub(upper) as i32;
+    let mut code = vec![lui(rd, upper), addi(rd, rd, lower)];
+    if XLEN == 64 && value & 0x8000_0000 != 0 {
+        code.push(shift_immediate(0b001, rd, rd, 32));
+        code.push(shift_immediate(0b101, rd, rd, 32));
+    }
+    code
+}
+
+/// reset code executed from the boot ROM, as in the QEMU virt machine:
+/// a0 = hart id, a1 = DTB address, then jump to the entry point of the next boot stage
+pub fn boot_stub(dtb_address: u32, entry: u32) -> Vec<u8> {
+    let mut code = vec![csrr(REG_A0, CSR_MHARTID)];
+    code.extend_from_slice(&load_immediate(REG_A1, dtb_address));
+    code.extend_from_slice(&load_immediate(REG_T0, entry));
+    code.push(jalr(0, REG_T0, 0));
+    code.iter().flat_map(|instruction| instruction.to_le_bytes()).collect()
+}
+
+/// read only memory holding the code executed right after reset
+pub struct BootRom {
+    start_address: Address,
+    end_address: Address,
+    data: Vec<u8>,
+}
+
+impl MemoryDevice for BootRom {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::MROM);
+        Self {
+            start_address,
+            end_address,
+            data: vec![0u8; (end_address - start_address) as usize],
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::WRITE {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+        }
+        self.read_request(request)
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.data.len() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        MemoryResponse {
+            data: MemoryData::new(&self.data[offset..offset + size]),
+            status: MemoryResponseType::Valid,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::MROM
+    }
+
+    fn init_mem(&mut self, address: Address, data: &[u8]) {
+        let offset = (address - self.start_address) as usize;
+        assert!(offset + data.len() <= self.data.len());
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn debug(&self, start_address: Address, end_address: Address) -> std::fmt::Result {
+        assert!(start_address >= self.start_address && end_address <= self.end_address);
+        println!("\nMemory {:?}: {{", MemoryDeviceType::MROM);
+        let start = (start_address - self.start_address) as usize;
+        let end = (end_address - self.start_address) as usize;
+        for (line, words) in self.data[start..end].chunks(16).enumerate() {
+            print!("{:X}: ", start_address as usize + line * 16);
+            for byte in words {
+                print!("{:02X}", byte);
+            }
+            println!();
+        }
+        println!("}}");
+        Ok(())
+    }
+}
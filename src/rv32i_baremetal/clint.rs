@@ -0,0 +1,185 @@
+use crate::risc_soc::csr::{InterruptLines, MIP_MSIP, MIP_MTIP};
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use std::sync::atomic::Ordering;
+
+/// register offsets, same layout as the SiFive CLINT used by the QEMU virt machine
+/// one 32-bit msip register and one 64-bit mtimecmp register per hart, followed by the shared mtime register
+pub const CLINT_MSIP: Address = 0x0;
+pub const CLINT_MTIMECMP: Address = 0x4000;
+pub const CLINT_MTIME: Address = 0xBFF8;
+
+/// core local interruptor: software interrupts between harts (IPIs) and the machine timer
+/// writing 1 to the msip register of a hart raises MSIP in its mip CSR, writing 0 clears it
+pub struct Clint {
+    start_address: Address,
+    end_address: Address,
+    harts: Vec<InterruptLines>,
+    mtimecmp: Vec<u64>,
+    mtime: u64,
+}
+
+impl Clint {
+    /// the hart id is the position of the hart in the order it was connected
+    pub fn connect_hart(&mut self, interrupt_lines: InterruptLines) {
+        self.harts.push(interrupt_lines);
+        self.mtimecmp.push(u64::MAX);
+    }
+
+    /// register holding the address and the offset of the access inside it
+    fn register(&self, offset: Address) -> Option<(Register, usize)> {
+        let num_harts = self.harts.len() as Address;
+        if offset < CLINT_MSIP + 4 * num_harts {
+            Some((Register::Msip((offset / 4) as usize), (offset % 4) as usize))
+        } else if (CLINT_MTIMECMP..CLINT_MTIMECMP + 8 * num_harts).contains(&offset) {
+            let offset = offset - CLINT_MTIMECMP;
+            Some((Register::MtimeCmp((offset / 8) as usize), (offset % 8) as usize))
+        } else if (CLINT_MTIME..CLINT_MTIME + 8).contains(&offset) {
+            Some((Register::Mtime, (offset - CLINT_MTIME) as usize))
+        } else {
+            None
+        }
+    }
+
+    fn value(&self, register: Register) -> u64 {
+        match register {
+            Register::Msip(hart) => (self.harts[hart].load(Ordering::SeqCst) & MIP_MSIP != 0) as u64,
+            Register::MtimeCmp(hart) => self.mtimecmp[hart],
+            Register::Mtime => self.mtime,
+        }
+    }
+
+    fn update_timer_interrupts(&self) {
+        for (hart, interrupt_lines) in self.harts.iter().enumerate() {
+            if self.mtime >= self.mtimecmp[hart] {
+                interrupt_lines.fetch_or(MIP_MTIP, Ordering::SeqCst);
+            } else {
+                interrupt_lines.fetch_and(!MIP_MTIP, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Register {
+    Msip(usize),
+    MtimeCmp(usize),
+    Mtime,
+}
+
+impl MemoryDevice for Clint {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::CLINT);
+        Self {
+            start_address,
+            end_address,
+            harts: vec![],
+            mtimecmp: vec![],
+            mtime: 0,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        let Some((register, offset)) = self.register(request.data_address - self.start_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        };
+        assert!(request.data.is_some());
+        let data = request.data.unwrap();
+        // narrower writes only replace the written bytes of the register
+        let mut bytes = self.value(register).to_le_bytes();
+        let end = (offset + data.len()).min(8);
+        bytes[offset..end].copy_from_slice(&data[..end - offset]);
+        let value = u64::from_le_bytes(bytes);
+        match register {
+            Register::Msip(hart) => {
+                if value & 0x1 != 0 {
+                    self.harts[hart].fetch_or(MIP_MSIP, Ordering::SeqCst);
+                } else {
+                    self.harts[hart].fetch_and(!MIP_MSIP, Ordering::SeqCst);
+                }
+            }
+            Register::MtimeCmp(hart) => self.mtimecmp[hart] = value,
+            Register::Mtime => self.mtime = value,
+        }
+        self.update_timer_interrupts();
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let Some((register, offset)) = self.register(request.data_address - self.start_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        };
+        let bytes = self.value(register).to_le_bytes();
+        let size = request.data_size as usize;
+        let mut data = MemoryData::zeroed(size);
+        let end = (offset + size).min(8);
+        data[..end - offset].copy_from_slice(&bytes[offset..end]);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::CLINT
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!("\nMemory {:?}: {{", MemoryDeviceType::CLINT);
+        for hart in 0..self.harts.len() {
+            println!(
+                "hart {hart}: msip={} mtimecmp={:X}",
+                self.value(Register::Msip(hart)),
+                self.mtimecmp[hart]
+            );
+        }
+        println!("mtime={:X}", self.mtime);
+        println!("}}");
+        Ok(())
+    }
+
+    /// mtime counts the edges of the clock of the timer, which is usually slower than the core clock
+    fn tick(&mut self, edges: u64) {
+        self.mtime = self.mtime.wrapping_add(edges);
+        self.update_timer_interrupts();
+    }
+
+    /// edges until mtime reaches the closest compare value still ahead of it
+    fn next_event(&self) -> Option<u64> {
+        self.mtimecmp.iter().filter(|mtimecmp| **mtimecmp > self.mtime).map(|mtimecmp| mtimecmp - self.mtime).min()
+    }
+
+    fn reset(&mut self, _clear_memory: bool) {
+        self.mtime = 0;
+        self.mtimecmp.iter_mut().for_each(|mtimecmp| *mtimecmp = u64::MAX);
+        for interrupt_lines in &self.harts {
+            interrupt_lines.fetch_and(!(MIP_MSIP | MIP_MTIP), Ordering::SeqCst);
+        }
+    }
+
+    /// the registers are named after the hart they belong to, ex. "MTIMECMP[1]"
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let (register, offset) = self.register(request.data_address - self.start_address)?;
+        let name = match register {
+            Register::Msip(hart) => format!("MSIP[{hart}]"),
+            Register::MtimeCmp(hart) => format!("MTIMECMP[{hart}]"),
+            Register::Mtime => "MTIME".to_string(),
+        };
+        let offset = if offset == 0 { String::new() } else { format!("+{offset}") };
+        let direction = if request.request_type == MemoryRequestType::WRITE { "<=" } else { "=>" };
+        Some(format!("{name}{offset} {direction} 0x{value:x}"))
+    }
+}
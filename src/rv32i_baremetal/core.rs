@@ -1,5 +1,6 @@
+use std::sync::{Arc, Mutex, RwLock};
 use crossbeam_channel::bounded;
-use crate::{risc_soc::{cache::Cache, memory_management_unit::{Address, MemoryDevice, MemoryDeviceType}, pipeline_stage::{PipelineStage, PipelineStageInterface}, risc_soc::RiscCore}, rv32i_baremetal::{decode, execute, fetch, mcu_cache::MCUCache, memory, uart::UART, writeback}};
+use crate::{risc_soc::{cache::Cache, csr::{InterruptLines, MachineInfo}, isa_model::IsaModel, load_error::LoadError, sim_error::SimErrorKind, memory_management_unit::{Address, MemoryDevice, MemoryDeviceType, MemoryManagementUnit, Permissions}, pipeline_stage::{PipelineStage, PipelineStageInterface}, risc_soc::{ExitSignal, RiscCore, RiscWord, XLEN, XLEN_BYTES}, soc::RiscSoc, timing::TimingModel}, rv32i_baremetal::{boot_rom::{BootRom, boot_stub}, clint::Clint, decode, isa::RiscvIsa, dram::Dram, execute::{self, ExecuteWait}, flash::Flash, fetch, mcu_cache::MCUCache, memory::{self, MemoryWait}, network::NetworkBackend, nic::Nic, plic::Plic, rtc::Rtc, serial::SerialConnection, sifive_uart::SifiveUart, test_finisher::TestFinisher, uart::UART, uart16550::Uart16550, watchdog::Watchdog, writeback}};
 
 pub const IF_STAGE: usize = 0x0;
 pub const ID_STAGE: usize = 0x1;
@@ -7,12 +8,241 @@ pub const EX_STAGE: usize = 0x2;
 pub const MEM_STAGE: usize = 0x3;
 pub const WB_STAGE: usize = 0x4;
 
+/// boot ROM location and size, same as the MROM of the QEMU virt machine
+pub const BOOT_ROM_ADDRESS: Address = 0x1000;
+pub const BOOT_ROM_SIZE: Address = 0x1000;
+
+/// AXI UART Lite of the MicroBlaze systems, and 16550 at the location of the QEMU virt machine
+pub const UART_ADDRESS: Address = 0x4060_0000;
+pub const UART16550_ADDRESS: Address = 0x1000_0000;
+pub const UART_SIZE: Address = 0x100;
+
+/// NIC location, next to the 16550 in the range of the virtio devices of the QEMU virt machine
+pub const NIC_ADDRESS: Address = 0x1000_2000;
+pub const NIC_SIZE: Address = 0x100;
+
+/// test finisher location, same as the SiFive test device of the QEMU virt machine
+pub const TEST_FINISHER_ADDRESS: Address = 0x10_0000;
+pub const TEST_FINISHER_SIZE: Address = 0x1000;
+
+/// Goldfish RTC location and size, same as in the QEMU virt machine
+pub const RTC_ADDRESS: Address = 0x10_1000;
+pub const RTC_SIZE: Address = 0x1000;
+
+/// watchdog location, right after the RTC
+pub const WATCHDOG_ADDRESS: Address = 0x10_2000;
+pub const WATCHDOG_SIZE: Address = 0x1000;
+
+/// CLINT location and size, same as in the QEMU virt machine
+pub const CLINT_ADDRESS: Address = 0x200_0000;
+pub const CLINT_SIZE: Address = 0x1_0000;
+/// frequency of mtime when it follows the host time, same timebase as in the QEMU virt machine
+pub const CLINT_TIMEBASE_HZ: u64 = 10_000_000;
+
+/// PLIC location and size, same as in the QEMU virt machine and the FE310
+pub const PLIC_ADDRESS: Address = 0xC00_0000;
+pub const PLIC_SIZE: Address = 0x400_0000;
+
+/// memory shared by all the harts, placed right after the L1 memories private to each core
+pub const DRAM_ADDRESS: Address = 0x8100_0000;
+pub const DRAM_SIZE: Address = 0x10_0000;
+
+/// NOR flash executed in place, at the location of the first pflash bank of the QEMU virt machine
+pub const FLASH_ADDRESS: Address = 0x2000_0000;
+pub const FLASH_SIZE: Address = 0x10_0000;
+
+/// L1 memories private to each hart, instruction memory first and data memory right after it
+pub const L1_ADDRESS: Address = 0x8000_0000;
+
+/// memory map of an SoC: where the memories and devices of the platform are mapped, `None` leaves a device out
+/// the presets follow real boards, so firmware linked for them runs without changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardConfig {
+    pub name: &'static str,
+    /// start of the L1 memories of each hart, which take the first 128KB from there
+    pub l1_address: Address,
+    /// start and size of the memory shared by the harts
+    pub dram: (Address, Address),
+    /// start and size of the NOR flash executed in place
+    pub flash: Option<(Address, Address)>,
+    pub uartlite: Option<Address>,
+    pub uart16550: Option<Address>,
+    pub sifive_uart: Option<Address>,
+    pub test_finisher: Option<Address>,
+    pub rtc: Option<Address>,
+    pub watchdog: Option<Address>,
+    pub clint: Address,
+    /// without a PLIC every interrupt line of the devices raises MEIP of the first hart
+    pub plic: Option<Address>,
+    /// address of the first instruction after a reset
+    pub reset_vector: Address,
+    /// serial port used as the console of the board
+    pub console: MemoryDeviceType,
+}
+
+impl BoardConfig {
+    /// memory map of this SoC, as used by the examples and tests of the repository
+    pub const DEFAULT: BoardConfig = BoardConfig {
+        name: "default",
+        l1_address: L1_ADDRESS,
+        dram: (DRAM_ADDRESS, DRAM_SIZE),
+        flash: Some((FLASH_ADDRESS, FLASH_SIZE)),
+        uartlite: Some(UART_ADDRESS),
+        uart16550: Some(UART16550_ADDRESS),
+        sifive_uart: None,
+        test_finisher: Some(TEST_FINISHER_ADDRESS),
+        rtc: Some(RTC_ADDRESS),
+        watchdog: Some(WATCHDOG_ADDRESS),
+        clint: CLINT_ADDRESS,
+        plic: None,
+        reset_vector: L1_ADDRESS,
+        console: MemoryDeviceType::UART0,
+    };
+
+    /// QEMU virt machine: 128MB of DRAM at 0x8000_0000 where the firmware is linked, 16550 console, test device, RTC, CLINT and PLIC
+    /// the L1 memories are placed in the hole at 0x0800_0000, so every program address goes to the devices of the board
+    pub const QEMU_VIRT: BoardConfig = BoardConfig {
+        name: "qemu-virt",
+        l1_address: 0x0800_0000,
+        dram: (0x8000_0000, 0x800_0000),
+        flash: Some((FLASH_ADDRESS, FLASH_SIZE)),
+        uartlite: None,
+        uart16550: Some(UART16550_ADDRESS),
+        sifive_uart: None,
+        test_finisher: Some(TEST_FINISHER_ADDRESS),
+        rtc: Some(RTC_ADDRESS),
+        watchdog: None,
+        clint: CLINT_ADDRESS,
+        plic: Some(PLIC_ADDRESS),
+        reset_vector: 0x8000_0000,
+        console: MemoryDeviceType::UART1,
+    };
+
+    /// SiFive FE310-G002 of the HiFive1 Rev B: 16KB DTIM at 0x8000_0000, 4MB SPI flash at 0x2000_0000 with the user program
+    /// at 0x2001_0000 after the bootloader, SiFive UART0, CLINT and PLIC, the L1 memories take the place of the ITIM
+    /// the clock (PRCI) and pin (GPIO) controllers are not modeled, firmware configuring them gets an access fault
+    pub const SIFIVE_FE310: BoardConfig = BoardConfig {
+        name: "fe310",
+        l1_address: 0x0800_0000,
+        dram: (0x8000_0000, 0x4000),
+        flash: Some((FLASH_ADDRESS, 0x40_0000)),
+        uartlite: None,
+        uart16550: None,
+        sifive_uart: Some(0x1001_3000),
+        test_finisher: None,
+        rtc: None,
+        watchdog: None,
+        clint: CLINT_ADDRESS,
+        plic: Some(PLIC_ADDRESS),
+        reset_vector: 0x2001_0000,
+        console: MemoryDeviceType::UART2,
+    };
+
+    /// preset with the given name, ex. "qemu-virt" or "fe310"
+    pub fn preset(name: &str) -> Option<BoardConfig> {
+        [Self::DEFAULT, Self::QEMU_VIRT, Self::SIFIVE_FE310].into_iter().find(|board| board.name == name)
+    }
+}
+
+/// single core MCU, the devices of the platform are mapped in the MMU of the core
 pub fn init_core(clock_period: Option<u128>) -> RiscCore {
+    init_board(&BoardConfig::DEFAULT, clock_period)
+}
+
+/// single core MCU with the memory map of the given board, starting from its reset vector
+pub fn init_board(board: &BoardConfig, clock_period: Option<u128>) -> RiscCore {
+    let mut rv32i_core = init_hart_at(board.l1_address, clock_period);
+    {
+        let mut mmu = rv32i_core.mmu.write().unwrap();
+        add_board_devices(&mut mmu, board, rv32i_core.exit_signal.clone(), &[rv32i_core.interrupt_lines()]);
+    }
+    rv32i_core.set_reset_vector(board.reset_vector as RiscWord);
+    rv32i_core
+}
+
+/// SMP system with `num_harts` MCU cores sharing the devices of the platform, the mhartid of each hart is its index
+pub fn init_soc(num_harts: usize, clock_period: Option<u128>) -> RiscSoc {
+    init_board_soc(&BoardConfig::DEFAULT, num_harts, clock_period)
+}
+
+/// SMP system with the memory map of the given board, all the harts start from its reset vector
+pub fn init_board_soc(board: &BoardConfig, num_harts: usize, clock_period: Option<u128>) -> RiscSoc {
+    let mut soc = RiscSoc::new(MemoryManagementUnit::default());
+    for _ in 0..num_harts {
+        let mut hart = init_hart_at(board.l1_address, clock_period);
+        hart.set_reset_vector(board.reset_vector as RiscWord);
+        soc.add_hart(hart);
+    }
+    let interrupt_lines: Vec<_> = soc.harts.iter().map(|hart| hart.interrupt_lines()).collect();
+    add_board_devices(&mut soc.mmu.write().unwrap(), board, soc.exit_signal.clone(), &interrupt_lines);
+    soc
+}
+
+/// state kept by the stages of the MCU between clock cycles
+pub struct McuState {
+    /// access of MEM waiting for a slow memory
+    pub memory_wait: Mutex<MemoryWait>,
+    /// operation of EX running in a multi-cycle functional unit
+    pub execute_wait: Mutex<ExecuteWait>,
+    /// address and error of the instructions ID failed to decode, raised by MEM if they are not flushed before
+    pub decode_errors: Mutex<Vec<(RiscWord, SimErrorKind)>>,
+    /// latency and throughput of the instructions in EX, kept across resets
+    /// read by EX at every clock cycle and only written between runs
+    pub timing: RwLock<TimingModel>,
+    /// instruction set decoded by ID and executed by EX, kept across resets
+    pub isa: RwLock<Arc<dyn IsaModel>>,
+}
+
+impl Default for McuState {
+    fn default() -> Self {
+        Self {
+            memory_wait: Mutex::default(),
+            execute_wait: Mutex::default(),
+            decode_errors: Mutex::default(),
+            timing: RwLock::default(),
+            isa: RwLock::new(Arc::new(RiscvIsa)),
+        }
+    }
+}
+
+impl McuState {
+    pub fn isa_model(&self) -> Arc<dyn IsaModel> {
+        self.isa.read().unwrap().clone()
+    }
+}
+
+/// timing table used by EX of an MCU core to schedule the instructions
+pub fn set_timing_model(core: &RiscCore, timing: TimingModel) {
+    *core.microarchitecture::<McuState>().timing.write().unwrap() = timing;
+}
+
+/// instruction set run by the pipeline of an MCU core, ex. RISC-V with a custom extension
+pub fn set_isa_model(core: &RiscCore, isa: impl IsaModel + 'static) {
+    *core.microarchitecture::<McuState>().isa.write().unwrap() = Arc::new(isa);
+    // the micro-ops decoded by the previous model are meaningless to the new one
+    core.decode_cache.lock().unwrap().clear();
+}
+
+/// pipeline and private L1 memories of a core, without any device in its MMU
+pub fn init_hart(clock_period: Option<u128>) -> RiscCore {
+    init_hart_at(L1_ADDRESS, clock_period)
+}
+
+/// same as `init_hart`, with the L1 memories starting at `start_address`
+pub fn init_hart_at(start_address: Address, clock_period: Option<u128>) -> RiscCore {
     let mut rv32i_core = RiscCore::new(5, clock_period, false); //1us clock period
-    let start_address = 0x8000_0000;
     let icache = MCUCache::new_with_lines(MemoryDeviceType::L1ICACHE, 64, 1024, start_address);
     let dcache = MCUCache::new_with_lines(MemoryDeviceType::L1DCACHE, 64, 1024, start_address + icache.size() as Address); 
     rv32i_core.add_l1_cache(Box::new(icache), Box::new(dcache));
+    // MEM holds the pipeline while an access to a slow memory completes, and EX while a multiplication or division completes
+    rv32i_core.set_microarchitecture(McuState::default());
+    rv32i_core.add_reset_hook(|core| {
+        let state = core.microarchitecture::<McuState>();
+        *state.memory_wait.lock().unwrap() = MemoryWait::default();
+        *state.execute_wait.lock().unwrap() = ExecuteWait::default();
+        state.decode_errors.lock().unwrap().clear();
+        state.isa_model().reset();
+    });
     
     // add stages and connections between them
     // bound channels to one entry to mimic the behaviour of a single pipeline reg
@@ -20,76 +250,4446 @@ pub fn init_core(clock_period: Option<u128>) -> RiscCore {
     let (id_ex_sender, id_ex_receiver) = bounded(1);
     let (ex_mem_sender, ex_mem_receiver) = bounded(1);
     let (mem_wb_sender, mem_wb_receiver) = bounded(1);
-    let if_stage = PipelineStage::new("IF".to_string(), IF_STAGE, 0usize, 8usize, fetch::rv32_mcu_fetch_stage, None, Some(if_id_sender));
-    let id_stage = PipelineStage::new("ID".to_string(), ID_STAGE,  8usize, 25usize, decode::rv32_mcu_decode_stage, Some(if_id_receiver), Some(id_ex_sender));
-    let ex_stage= PipelineStage::new("EX".to_string(), EX_STAGE,  25usize, 18usize, execute::rv32_mcu_execute_stage, Some(id_ex_receiver), Some(ex_mem_sender));
-    let mem_stage= PipelineStage::new("MEM".to_string(), MEM_STAGE,  18usize, 11usize, memory::rv32_mcu_mem_stage, Some(ex_mem_receiver), Some(mem_wb_sender));
-    let wb_stage= PipelineStage::new("WB".to_string(), WB_STAGE,  11usize, 0usize, writeback::rv32_mcu_commit_stage, Some(mem_wb_receiver), None);
+    // pipeline register sizes depend on the register width of the core
+    let if_id_size = 6 + XLEN_BYTES;
+    let id_ex_size = decode::ID_EX_SIZE;
+    let ex_mem_size = 11 + 4 * XLEN_BYTES;
+    let mem_wb_size = 7 + 3 * XLEN_BYTES;
+    let if_stage = PipelineStage::new("IF".to_string(), IF_STAGE, 0usize, if_id_size, fetch::rv32_mcu_fetch_stage, None, Some(if_id_sender));
+    // flushed stages hold a NOP, the EX/MEM and MEM/WB bubbles are all zero: no register write, memory operation or jump
+    let id_stage = PipelineStage::new("ID".to_string(), ID_STAGE,  if_id_size, id_ex_size, decode::rv32_mcu_decode_stage, Some(if_id_receiver), Some(id_ex_sender))
+        .with_bubble(decode::id_ex_bubble());
+    let ex_stage= PipelineStage::new("EX".to_string(), EX_STAGE,  id_ex_size, ex_mem_size, execute::rv32_mcu_execute_stage, Some(id_ex_receiver), Some(ex_mem_sender));
+    let mem_stage= PipelineStage::new("MEM".to_string(), MEM_STAGE,  ex_mem_size, mem_wb_size, memory::rv32_mcu_mem_stage, Some(ex_mem_receiver), Some(mem_wb_sender));
+    let wb_stage= PipelineStage::new("WB".to_string(), WB_STAGE,  mem_wb_size, 0usize, writeback::rv32_mcu_commit_stage, Some(mem_wb_receiver), None);
     rv32i_core.add_stage(if_stage);
     rv32i_core.add_stage(id_stage);
     rv32i_core.add_stage(ex_stage);
     rv32i_core.add_stage(mem_stage);
     rv32i_core.add_stage(wb_stage);
-    tracing::info!("Configured RV32I core with {} stages", rv32i_core.stages.len());
 
-    { 
-        let mut  mmu= rv32i_core.mmu.write().unwrap();
-        let uart_device = UART::new(MemoryDeviceType::UART0, 0x4060_0000, 0x4060_0100);
-        mmu.add_memory_device(Box::new(uart_device));
-    }
-    
+    // wires going back to earlier stages, for hazard detection, branches and forwarding
+    let forward_size = 8 * (2 + XLEN_BYTES);
+    rv32i_core.cdb.declare(EX_STAGE, ID_STAGE, "ex_load_hazard", 56);
+    rv32i_core.cdb.declare(MEM_STAGE, IF_STAGE, "mem_branch_to_if", forward_size);
+    rv32i_core.cdb.declare(MEM_STAGE, ID_STAGE, "mem_branch_to_id", forward_size + 8);
+    rv32i_core.cdb.declare(MEM_STAGE, EX_STAGE, "mem_forward", forward_size + 16);
+    rv32i_core.cdb.declare(WB_STAGE, ID_STAGE, "wb_forward_to_id", forward_size);
+    rv32i_core.cdb.declare(WB_STAGE, EX_STAGE, "wb_forward_to_ex", forward_size);
+    rv32i_core.set_machine_info(MachineInfo::new(0, "IMAB_Zicond"));
+    tracing::info!("Configured RV{}IMAB_Zicond core with {} stages", XLEN, rv32i_core.stages.len());
     rv32i_core
 }
 
-pub fn load_elf(core: &mut RiscCore, path: &str) {
-    core.load_binary(path, MemoryDeviceType::L1ICACHE);
+/// serial ports, test finisher, RTC, watchdog, CLINT and shared DRAM, the CLINT raises the interrupts of the given harts in order of their id
+pub fn add_platform_devices(mmu: &mut MemoryManagementUnit, exit_signal: ExitSignal, harts: &[InterruptLines]) {
+    add_board_devices(mmu, &BoardConfig::DEFAULT, exit_signal, harts);
+}
+
+/// devices and shared memories of the given board, the CLINT and PLIC raise the interrupts of the given harts in order of their id
+pub fn add_board_devices(mmu: &mut MemoryManagementUnit, board: &BoardConfig, exit_signal: ExitSignal, harts: &[InterruptLines]) {
+    if let Some(address) = board.uartlite {
+        let uart_device = UART::new(MemoryDeviceType::UART0, address, address + UART_SIZE);
+        mmu.add_memory_device_with_permissions(Box::new(uart_device), Permissions::RW);
+    }
+    if let Some(address) = board.uart16550 {
+        let uart16550_device = Uart16550::new(MemoryDeviceType::UART1, address, address + UART_SIZE);
+        mmu.add_memory_device_with_permissions(Box::new(uart16550_device), Permissions::RW);
+    }
+    if let Some(address) = board.sifive_uart {
+        let sifive_uart = SifiveUart::new(MemoryDeviceType::UART2, address, address + UART_SIZE);
+        mmu.add_memory_device_with_permissions(Box::new(sifive_uart), Permissions::RW);
+    }
+    if let Some(address) = board.test_finisher {
+        let finisher = TestFinisher::new(MemoryDeviceType::DEBUG, address, address + TEST_FINISHER_SIZE)
+            .with_exit_signal(exit_signal.clone());
+        mmu.add_memory_device_with_permissions(Box::new(finisher), Permissions::RW);
+    }
+    // OS ports read the wall clock from the RTC during boot
+    if let Some(address) = board.rtc {
+        let rtc = Rtc::new(MemoryDeviceType::RTC, address, address + RTC_SIZE);
+        mmu.add_memory_device_with_permissions(Box::new(rtc), Permissions::RW);
+    }
+    if let Some(address) = board.watchdog {
+        let watchdog = Watchdog::new(MemoryDeviceType::WATCHDOG, address, address + WATCHDOG_SIZE)
+            .with_exit_signal(exit_signal);
+        mmu.add_memory_device_with_permissions(Box::new(watchdog), Permissions::RW);
+    }
+    let mut clint = Clint::new(MemoryDeviceType::CLINT, board.clint, board.clint + CLINT_SIZE);
+    for interrupt_lines in harts {
+        clint.connect_hart(interrupt_lines.clone());
+    }
+    mmu.add_memory_device_with_permissions(Box::new(clint), Permissions::RW);
+    let (dram_address, dram_size) = board.dram;
+    let dram = Dram::new(MemoryDeviceType::DRAM, dram_address, dram_address + dram_size);
+    mmu.add_memory_device_with_permissions(Box::new(dram), Permissions::RWX);
+    if let Some((flash_address, flash_size)) = board.flash {
+        let flash = Flash::new(MemoryDeviceType::FLASH, flash_address, flash_address + flash_size);
+        mmu.add_memory_device_with_permissions(Box::new(flash), Permissions::RWX);
+    }
+    match board.plic {
+        Some(address) => {
+            let mut plic = Plic::new(MemoryDeviceType::PLIC, address, address + PLIC_SIZE);
+            for interrupt_lines in harts {
+                plic.connect_hart(interrupt_lines.clone());
+            }
+            mmu.add_memory_device_with_permissions(Box::new(plic), Permissions::RW);
+        }
+        // without a PLIC, the interrupt lines of the devices go to the first hart
+        None => {
+            if let Some(interrupt_lines) = harts.first() {
+                mmu.connect_external_interrupts(interrupt_lines.clone());
+            }
+        }
+    }
+}
+
+/// connect a serial port of the platform (UART0, UART1 or UART2) to a terminal in place of stdin and stdout
+/// the port is replaced by a new one at the same location, so it should be attached before the program uses it
+pub fn attach_serial(core: &RiscCore, port: MemoryDeviceType, connection: SerialConnection) {
+    let mut mmu = core.mmu.write().unwrap();
+    let Some((_, start, end)) = mmu.memory_map().into_iter().find(|(memory_type, _, _)| *memory_type == port) else {
+        panic!("{port:?} is not mapped on this board");
+    };
+    match port {
+        MemoryDeviceType::UART0 => {
+            let mut uart = UART::new(MemoryDeviceType::UART0, start, end);
+            uart.attach(connection);
+            mmu.replace_memory_device(port, Box::new(uart));
+        }
+        MemoryDeviceType::UART1 => {
+            let mut uart = Uart16550::new(MemoryDeviceType::UART1, start, end);
+            uart.attach(connection);
+            mmu.replace_memory_device(port, Box::new(uart));
+        }
+        MemoryDeviceType::UART2 => {
+            let mut uart = SifiveUart::new(MemoryDeviceType::UART2, start, end);
+            uart.attach(connection);
+            mmu.replace_memory_device(port, Box::new(uart));
+        }
+        _ => panic!("{port:?} is not a serial port of the platform"),
+    }
+}
+
+/// map the NIC of the platform bridged to a host network backend, its rings are served through the system bus of the core
+pub fn add_network_device(core: &RiscCore, backend: Box<dyn NetworkBackend>) {
+    let mut nic = Nic::new(MemoryDeviceType::NET0, NIC_ADDRESS, NIC_ADDRESS + NIC_SIZE);
+    nic.attach_backend(backend);
+    nic.set_bus_master(core.system_bus_master());
+    core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(nic), Permissions::RW);
+}
+
+/// map a boot ROM with a reset stub that passes the hart id and DTB address to `entry`, and start execution from it
+/// the programs loaded afterwards keep starting from the boot ROM
+pub fn add_boot_rom(core: &mut RiscCore, dtb_address: Address, entry: Address) {
+    let mut rom = BootRom::new(MemoryDeviceType::MROM, BOOT_ROM_ADDRESS, BOOT_ROM_ADDRESS + BOOT_ROM_SIZE);
+    rom.init_mem(BOOT_ROM_ADDRESS, &boot_stub(dtb_address as u32, entry as u32));
+    core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(rom), Permissions::RX);
+    core.set_entry_override(Some(BOOT_ROM_ADDRESS as RiscWord));
+}
+
+pub fn load_elf(core: &mut RiscCore, path: &str) -> Result<(), LoadError> {
+    core.load_binary(path, MemoryDeviceType::L1ICACHE)
+}
+
+/// load an ELF image held in memory, ex. a test fixture embedded in the test binary
+pub fn load_bytes(core: &mut RiscCore, image: &[u8]) -> Result<(), LoadError> {
+    core.load_elf_bytes(image, MemoryDeviceType::L1ICACHE)
 }
 
 
 #[cfg(test)]
 mod tests {
-    // TODO: refactor tests to properly check results in registers/memory
-     #[test]
-    fn test_add() {
-        let mut rv32i_core = super::init_core(None);
-        rv32i_core.enable_debug(true);
-        super::load_elf(&mut rv32i_core, "./isa_tests/add.elf");
-        for _i in 0..12{
-            rv32i_core.run(None);
+    use crate::risc_soc::risc_soc::RiscWord;
+    #[cfg(not(feature = "rv64"))]
+    use crate::rv32i_baremetal::isa_test::IsaTest;
+    use crate::rv32i_baremetal::isa_test::isa_test_image;
+    use std::collections::HashMap;
+
+    /// place the instructions at the start of the instruction memory and start executing them from there
+    fn load_program(core: &mut crate::risc_soc::risc_soc::RiscCore, program: &[u32]) {
+        let program: Vec<u8> = program.iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        core.init_memory(0x8000_0000, &program);
+        core.set_reset_vector(0x8000_0000);
+    }
+
+    /// paths of the nodes and values of the properties of a device tree blob, checking the layout of its header
+    fn parse_dtb(dtb: &[u8]) -> (Vec<String>, HashMap<String, Vec<u8>>) {
+        // header: magic, total size, then the blocks one after the other
+        let cell = |offset: usize| u32::from_be_bytes(dtb[offset..offset + 4].try_into().unwrap()) as usize;
+        let (off_dt_struct, off_dt_strings, off_mem_rsvmap) = (cell(8), cell(12), cell(16));
+        assert_eq!((cell(0), cell(4)), (0xD00D_FEED, dtb.len()));
+        assert_eq!((off_mem_rsvmap, off_dt_struct), (40, 56));
+        assert_eq!(off_dt_struct + cell(36), off_dt_strings);
+        assert_eq!(off_dt_strings + cell(32), dtb.len());
+
+        // nodes and properties from the structure block
+        let string = |offset: usize| {
+            let end = offset + dtb[offset..].iter().position(|byte| *byte == 0).unwrap();
+            String::from_utf8(dtb[offset..end].to_vec()).unwrap()
+        };
+        let (mut path, mut nodes, mut properties) = (vec![], vec![], HashMap::new());
+        let mut offset = off_dt_struct;
+        loop {
+            offset += 4;
+            match cell(offset - 4) {
+                0x1 => {
+                    let name = string(offset);
+                    offset = (offset + name.len() + 1).next_multiple_of(4);
+                    path.push(name);
+                    nodes.push(path.join("/"));
+                }
+                0x2 => {
+                    path.pop();
+                }
+                0x3 => {
+                    let (len, name) = (cell(offset), string(off_dt_strings + cell(offset + 4)));
+                    properties.insert(format!("{}/{}", path.join("/"), name), dtb[offset + 8..offset + 8 + len].to_vec());
+                    offset = (offset + 8 + len).next_multiple_of(4);
+                }
+                0x9 => break,
+                token => panic!("unexpected token {token:X} at offset {offset:X}"),
+            }
         }
-        println!("{}", rv32i_core.registers);
+        assert!(path.is_empty());
+        (nodes, properties)
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_add() {
+        IsaTest::new("add.elf")
+            .max_cycles(50)
+            .expect_reg("x1", 1)
+            .expect_reg("x2", 2)
+            .expect_reg("x3", 3)
+            .expect_reg("x4", -1i32 as RiscWord)
+            .expect_reg("x5", -5i32 as RiscWord)
+            .expect_reg("x6", -2i32 as RiscWord)
+            .expect_reg("x7", 3)
+            .run();
     }
 
     #[test]
+    #[cfg(not(feature = "rv64"))]
     fn test_branch() {
+        // the program loops forever, but the beq is always taken so the instruction after it never writes x13
+        let rv32i_core = IsaTest::new("branch.elf").max_cycles(50).expect_reg("x13", 0).run();
+        assert!(rv32i_core.read_reg_by_name("x10") >= 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_jump() {
+        IsaTest::new("jump_and_return.elf")
+            .max_cycles(50)
+            .expect_reg("a0", 5)
+            .expect_reg("a1", 2)
+            .expect_reg("a2", 3)
+            .expect_reg("a4", 3)
+            .expect_reg("a5", 6)
+            .expect_reg("a6", 0)
+            .run();
+    }
+
+    #[test]
+    fn test_self_modifying_code() {
+        // the store replaces an instruction already fetched, FENCE.I flushes it so the new one runs
+        let mut rv32i_core = super::init_hart(None);
+        let program = rv32i_core
+            .load_assembly(
+                "
+                    la t0, patch
+                    li t1, 0x02A00513       # li a0, 42
+                    sw t1, 0(t0)
+                    fence.i
+                patch:
+                    li a0, 1
+                    fence
+                    addi a0, a0, 1
+                done: j done
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_sequential(Some(100));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 43);
+        assert_eq!(rv32i_core.read_mem(program.labels["patch"], 4).unwrap(), 0x02A00513u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_uart_receive_interrupt() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::uart::*;
+        use std::sync::atomic::Ordering;
+        use std::time::{Duration, Instant};
+
+        fn access(uart: &mut UART, offset: Address, data: Option<u8>) -> u8 {
+            let request_type = if data.is_some() { MemoryRequestType::WRITE } else { MemoryRequestType::READ };
+            let request = MemoryRequest { request_type, data_address: 0x4060_0000 + offset, data_size: WordSize::BYTE, data: data.map(|byte| MemoryData::new(&[byte])) };
+            uart.send_data_request(request).data.first().copied().unwrap_or(0)
+        }
+        let mut uart = UART::new(MemoryDeviceType::UART0, 0x4060_0000, 0x4060_0100).with_input(std::io::Cursor::new(b"ok".to_vec()));
+        let interrupt_line = uart.interrupt_line();
+
+        // enabling the interrupt starts the receiver, which raises the line once a byte arrived
+        access(&mut uart, UART_CONTROL, Some(CONTROL_ENABLE_INTR));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while access(&mut uart, UART_STATUS, None) & STATUS_RX_VALID == 0 {
+            assert!(Instant::now() < deadline, "the UART did not receive in time");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_ne!(access(&mut uart, UART_STATUS, None) & STATUS_INTR_ENABLED, 0);
+        assert!(interrupt_line.load(Ordering::SeqCst));
+
+        let mut received = vec![];
+        while received.len() < 2 {
+            if access(&mut uart, UART_STATUS, None) & STATUS_RX_VALID != 0 {
+                received.push(access(&mut uart, UART_RX_FIFO, None));
+            }
+            assert!(Instant::now() < deadline, "the UART did not receive in time");
+        }
+        assert_eq!(received, b"ok");
+        // draining the RX FIFO lowers the line again
+        assert_eq!(access(&mut uart, UART_STATUS, None) & STATUS_RX_VALID, 0);
+        assert!(!interrupt_line.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_uart16550_registers() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::uart16550::*;
+        use std::sync::atomic::Ordering;
+
+        let mut uart = Uart16550::new(MemoryDeviceType::UART1, 0x1000_0000, 0x1000_0100);
+        let interrupt_line = uart.interrupt_line();
+        let request = |request_type, register: Address, data: Option<MemoryData>| MemoryRequest {
+            request_type,
+            data_address: 0x1000_0000 + register,
+            data_size: WordSize::BYTE,
+            data,
+        };
+        let write = |uart: &mut Uart16550, register, value| {
+            uart.send_data_request(request(MemoryRequestType::WRITE, register, Some(MemoryData::new(&[value]))));
+        };
+        let read = |uart: &mut Uart16550, register| uart.send_data_request(request(MemoryRequestType::READ, register, None)).data[0];
+
+        // with DLAB set the first two registers hold the divisor latch instead of THR and IER
+        write(&mut uart, LCR, LCR_DLAB | 0x03);
+        write(&mut uart, RBR_THR_DLL, 0x36);
+        write(&mut uart, IER_DLM, 0x01);
+        assert_eq!(read(&mut uart, RBR_THR_DLL), 0x36);
+        assert_eq!(read(&mut uart, IER_DLM), 0x01);
+        write(&mut uart, LCR, 0x03);
+        assert_eq!(read(&mut uart, IER_DLM), 0);
+        assert_eq!(read(&mut uart, LCR), 0x03);
+
+        write(&mut uart, SCR, 0x5A);
+        assert_eq!(read(&mut uart, SCR), 0x5A);
+        write(&mut uart, IIR_FCR, FCR_ENABLE_FIFO);
+        assert_eq!(read(&mut uart, IIR_FCR), IIR_FIFO_ENABLED | IIR_NO_INTERRUPT);
+
+        // enabling the THR empty interrupt with an idle transmitter raises it, reading IIR acknowledges it
+        write(&mut uart, IER_DLM, IER_THR_EMPTY);
+        assert!(interrupt_line.load(Ordering::SeqCst));
+        assert_eq!(read(&mut uart, IIR_FCR), IIR_FIFO_ENABLED | IIR_THR_EMPTY);
+        assert!(!interrupt_line.load(Ordering::SeqCst));
+        assert_eq!(read(&mut uart, IIR_FCR), IIR_FIFO_ENABLED | IIR_NO_INTERRUPT);
+        assert_eq!(read(&mut uart, LSR) & (LSR_THR_EMPTY | LSR_TX_EMPTY), LSR_THR_EMPTY | LSR_TX_EMPTY);
+    }
+
+    #[test]
+    fn test_gpio_host_pins() {
+        use crate::risc_soc::memory_management_unit::{MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::gpio::*;
+        use std::sync::atomic::Ordering;
+        use std::sync::{Arc, Mutex};
+
         let mut rv32i_core = super::init_core(None);
-        rv32i_core.enable_debug(true);
-        super::load_elf(&mut rv32i_core, "./isa_tests/branch.elf");
-        for _i in 0..20{
-            rv32i_core.run(None);
+        let mut gpio = GPIO::new(MemoryDeviceType::GPIO0, 0x4000_0000, 0x4000_0100);
+        let toggles = Arc::new(Mutex::new(vec![]));
+        let recorded = toggles.clone();
+        gpio.on_output_change(Box::new(move |pin, level| recorded.lock().unwrap().push((pin, level))));
+        let buttons = gpio.inputs();
+        let interrupt_line = gpio.interrupt_line();
+        rv32i_core.mmu.write().unwrap().add_memory_device(Box::new(gpio));
+
+        // blink the LED on pin 0, then enable the interrupt of the button on pin 1
+        let program: Vec<u8> = [
+            0x400002B7u32, // lui t0, 0x40000
+            0x00100313,    // li t1, 1
+            0x0062A223,    // sw t1, 4(t0)
+            0x0062A023,    // sw t1, 0(t0)
+            0x0002A023,    // sw zero, 0(t0)
+            0x00200313,    // li t1, 2
+            0x0062A623,    // sw t1, 12(t0)
+            0x0000006F,    // j .
+        ]
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect();
+        rv32i_core.icache.as_ref().unwrap().write().unwrap().init_mem(0, &program);
+        rv32i_core.run(Some(100));
+        assert_eq!(*toggles.lock().unwrap(), [(0, true), (0, false)]);
+        assert!(!interrupt_line.load(Ordering::SeqCst));
+
+        let access = |register, data: Option<u32>| {
+            let request_type = if data.is_some() { MemoryRequestType::WRITE } else { MemoryRequestType::READ };
+            let data = data.map(|value| MemoryData::new(&value.to_le_bytes()));
+            let request = MemoryRequest { request_type, data_address: 0x4000_0000 + register, data_size: WordSize::WORD, data };
+            let response = rv32i_core.mmu.write().unwrap().process_memory_request(request);
+            u32::from_le_bytes(response.data[..].try_into().unwrap_or([0; 4]))
+        };
+        // an enabled edge raises the line right away, until the guest acknowledges it
+        buttons.set_pin(1, true);
+        assert!(interrupt_line.load(Ordering::SeqCst));
+        assert_eq!(access(GPIO_INPUT, None), 0b10);
+        assert_eq!(access(GPIO_IRQ_STATUS, None), 0b10);
+        access(GPIO_IRQ_STATUS, Some(0b10));
+        assert!(!interrupt_line.load(Ordering::SeqCst));
+        buttons.set_pin(1, false);
+        assert!(interrupt_line.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_spi_nor_flash() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType, MemoryResponse};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::spi::*;
+        use crate::rv32i_baremetal::spi_flash::*;
+
+        let mut spi = SpiController::new(MemoryDeviceType::SPI0, 0, 0x100);
+        let mut flash = SpiNorFlash::new(2 * FLASH_SECTOR_SIZE);
+        flash.init_mem(FLASH_SECTOR_SIZE, b"boot");
+        assert_eq!(spi.attach_slave(Box::new(flash)), 0);
+        let request = |register: Address, data: Option<u32>| MemoryRequest {
+            request_type: if data.is_some() { MemoryRequestType::WRITE } else { MemoryRequestType::READ },
+            data_address: register,
+            data_size: WordSize::WORD,
+            data: data.map(|value| MemoryData::new(&value.to_le_bytes())),
+        };
+        let value = |response: MemoryResponse| u32::from_le_bytes(response.data[..4].try_into().unwrap());
+        // one command framed by the chip select, returns the bytes shifted in
+        let command = |spi: &mut SpiController, bytes: &[u8]| -> Vec<u8> {
+            spi.send_data_request(request(SPI_CS, Some(0)));
+            let miso = bytes
+                .iter()
+                .map(|byte| {
+                    spi.send_data_request(request(SPI_TXDATA, Some(*byte as u32)));
+                    assert_eq!(value(spi.read_request(request(SPI_STATUS, None))), SPI_STATUS_RX_VALID as u32);
+                    value(spi.send_data_request(request(SPI_RXDATA, None))) as u8
+                })
+                .collect();
+            spi.send_data_request(request(SPI_CS, Some(SPI_CS_NONE)));
+            miso
+        };
+
+        assert_eq!(command(&mut spi, &[CMD_READ_JEDEC_ID, 0, 0, 0])[1..], [0xEF, 0x40, 0x18]);
+        assert_eq!(command(&mut spi, &[CMD_READ, 0x00, 0x10, 0x00, 0, 0, 0, 0])[4..], *b"boot");
+
+        // programming needs a write enable first, and only clears bits
+        command(&mut spi, &[CMD_PAGE_PROGRAM, 0x00, 0x01, 0x00, 0x55]);
+        assert_eq!(command(&mut spi, &[CMD_READ, 0x00, 0x01, 0x00, 0])[4], 0xFF);
+        command(&mut spi, &[CMD_WRITE_ENABLE]);
+        assert_eq!(command(&mut spi, &[CMD_READ_STATUS, 0])[1], STATUS_WRITE_ENABLED);
+        command(&mut spi, &[CMD_PAGE_PROGRAM, 0x00, 0x01, 0x00, 0x55]);
+        assert_eq!(command(&mut spi, &[CMD_READ_STATUS, 0])[1], 0);
+        command(&mut spi, &[CMD_WRITE_ENABLE]);
+        command(&mut spi, &[CMD_PAGE_PROGRAM, 0x00, 0x01, 0x00, 0x0F]);
+        assert_eq!(command(&mut spi, &[CMD_READ, 0x00, 0x01, 0x00, 0])[4], 0x05);
+
+        // erasing the first sector leaves the second one alone
+        command(&mut spi, &[CMD_WRITE_ENABLE]);
+        command(&mut spi, &[CMD_SECTOR_ERASE, 0x00, 0x00, 0x80]);
+        assert_eq!(command(&mut spi, &[CMD_READ, 0x00, 0x01, 0x00, 0])[4], 0xFF);
+        assert_eq!(command(&mut spi, &[CMD_READ, 0x00, 0x10, 0x00, 0])[4], b'b');
+
+        // without a selected slave MISO floats high
+        spi.send_data_request(request(SPI_TXDATA, Some(CMD_READ_JEDEC_ID as u32)));
+        assert_eq!(value(spi.send_data_request(request(SPI_RXDATA, None))), 0xFF);
+    }
+
+    #[test]
+    fn test_i2c_devices() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::i2c::*;
+        use crate::rv32i_baremetal::i2c_devices::{I2cEeprom, I2cTemperatureSensor, LM75_TEMPERATURE};
+        use std::sync::atomic::Ordering;
+
+        let mut i2c = I2cController::new(MemoryDeviceType::I2C0, 0, 0x100);
+        i2c.attach_device(Box::new(I2cEeprom::new(0x50, 16, 8)));
+        let sensor = I2cTemperatureSensor::new(0x48, 25_500);
+        let temperature = sensor.temperature();
+        i2c.attach_device(Box::new(sensor));
+        let request = |register: Address, data: Option<u8>| MemoryRequest {
+            request_type: if data.is_some() { MemoryRequestType::WRITE } else { MemoryRequestType::READ },
+            data_address: register,
+            data_size: WordSize::BYTE,
+            data: data.map(|value| MemoryData::new(&[value])),
+        };
+        // one byte phase of a transaction, returns the status
+        let phase = |i2c: &mut I2cController, byte: u8, command: u8| {
+            i2c.send_data_request(request(I2C_TXRX, Some(byte)));
+            i2c.send_data_request(request(I2C_COMMAND_STATUS, Some(command)));
+            i2c.send_data_request(request(I2C_COMMAND_STATUS, None)).data[0]
+        };
+        let receive = |i2c: &mut I2cController, command: u8| {
+            i2c.send_data_request(request(I2C_COMMAND_STATUS, Some(command)));
+            i2c.send_data_request(request(I2C_TXRX, None)).data[0]
+        };
+
+        // a page write wraps around inside the page of the EEPROM
+        assert_eq!(phase(&mut i2c, 0x50 << 1, CMD_START | CMD_WRITE), STATUS_BUSY | STATUS_INTERRUPT);
+        phase(&mut i2c, 6, CMD_WRITE);
+        for byte in b"xy" {
+            phase(&mut i2c, *byte, CMD_WRITE);
         }
-        println!("{}", rv32i_core.registers);
+        assert_eq!(phase(&mut i2c, b'z', CMD_WRITE | CMD_STOP), STATUS_INTERRUPT);
+        // random read: set the word address, then a repeated START for reading
+        phase(&mut i2c, 0x50 << 1, CMD_START | CMD_WRITE);
+        phase(&mut i2c, 6, CMD_WRITE);
+        phase(&mut i2c, (0x50 << 1) | 1, CMD_START | CMD_WRITE);
+        let bytes = [receive(&mut i2c, CMD_READ), receive(&mut i2c, CMD_READ), receive(&mut i2c, CMD_READ | CMD_NACK | CMD_STOP)];
+        assert_eq!(bytes, [b'x', b'y', 0xFF]);
+        phase(&mut i2c, 0x50 << 1, CMD_START | CMD_WRITE);
+        phase(&mut i2c, 0, CMD_WRITE);
+        phase(&mut i2c, (0x50 << 1) | 1, CMD_START | CMD_WRITE);
+        assert_eq!(receive(&mut i2c, CMD_READ | CMD_NACK | CMD_STOP), b'z');
+
+        // the LM75 reports the host temperature in 0.5C steps, left aligned
+        let read_temperature = |i2c: &mut I2cController| {
+            phase(i2c, 0x48 << 1, CMD_START | CMD_WRITE);
+            phase(i2c, LM75_TEMPERATURE, CMD_WRITE);
+            phase(i2c, (0x48 << 1) | 1, CMD_START | CMD_WRITE);
+            [receive(i2c, CMD_READ), receive(i2c, CMD_READ | CMD_NACK | CMD_STOP)]
+        };
+        assert_eq!(read_temperature(&mut i2c), [0x19, 0x80]);
+        temperature.store(-5_000, Ordering::SeqCst);
+        assert_eq!(read_temperature(&mut i2c), [0xFB, 0x00]);
+        // the temperature register cannot be written
+        phase(&mut i2c, 0x48 << 1, CMD_START | CMD_WRITE);
+        phase(&mut i2c, LM75_TEMPERATURE, CMD_WRITE);
+        assert_ne!(phase(&mut i2c, 0, CMD_WRITE | CMD_STOP) & STATUS_NO_ACK, 0);
+
+        // nobody answers an unused address
+        assert_ne!(phase(&mut i2c, 0x20 << 1, CMD_START | CMD_WRITE | CMD_STOP) & STATUS_NO_ACK, 0);
     }
 
     #[test]
-    fn test_jump() {
+    fn test_virtio_blk() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType, MemoryResponse, MemoryResponseType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::virtio_blk::*;
+        use std::cell::RefCell;
+        use std::sync::atomic::Ordering;
+        use std::sync::{Arc, Mutex};
+
+        let path = std::env::temp_dir().join("riscv_on_rust_test_disk.img");
+        let mut image = vec![0u8; 2 * VIRTIO_BLK_SECTOR_SIZE as usize];
+        image[VIRTIO_BLK_SECTOR_SIZE as usize..].fill(0xA5);
+        std::fs::write(&path, &image).unwrap();
+
+        let base: Address = 0x1000_8000;
+        let mut virtio = VirtioBlk::new(MemoryDeviceType::VIRTIO0, base, base + 0x200);
+        virtio.attach_disk(path.to_str().unwrap()).unwrap();
+        // the rings and buffers live in a guest memory served byte by byte to the bus master port
+        let memory_base: Address = 0x8001_0000;
+        let memory = Arc::new(Mutex::new(vec![0u8; 0x1000]));
+        let guest = memory.clone();
+        virtio.set_bus_master(Arc::new(move |request: MemoryRequest| {
+            let mut guest = guest.lock().unwrap();
+            let index = (request.data_address - memory_base) as usize;
+            let data = match request.data {
+                Some(data) => {
+                    guest[index] = data[0];
+                    MemoryData::default()
+                }
+                None => MemoryData::new(&[guest[index]]),
+            };
+            MemoryResponse { data, status: MemoryResponseType::CacheHit }
+        }));
+        let interrupt = virtio.interrupt_line();
+
+        let request = |offset: Address, data: Option<u32>| MemoryRequest {
+            request_type: if data.is_some() { MemoryRequestType::WRITE } else { MemoryRequestType::READ },
+            data_address: base + offset,
+            data_size: WordSize::WORD,
+            data: data.map(|value| MemoryData::new(&value.to_le_bytes())),
+        };
+        let virtio = RefCell::new(virtio);
+        let read = |offset: Address| {
+            let response = virtio.borrow_mut().send_data_request(request(offset, None));
+            u32::from_le_bytes(response.data[..4].try_into().unwrap())
+        };
+        let write = |offset: Address, value: u32| {
+            virtio.borrow_mut().send_data_request(request(offset, Some(value)));
+        };
+        let store = |address: Address, bytes: &[u8]| {
+            let index = (address - memory_base) as usize;
+            memory.lock().unwrap()[index..index + bytes.len()].copy_from_slice(bytes);
+        };
+        let load = |address: Address, len: usize| -> Vec<u8> {
+            let index = (address - memory_base) as usize;
+            memory.lock().unwrap()[index..index + len].to_vec()
+        };
+        assert_eq!(read(VIRTIO_MMIO_MAGIC_VALUE), 0x7472_6976);
+        assert_eq!(read(VIRTIO_MMIO_DEVICE_ID), 2);
+        assert_eq!(read(VIRTIO_MMIO_CONFIG), 2);
+
+        let (desc, avail, used) = (0x8001_0000, 0x8001_0100, 0x8001_0200);
+        let (header, buffer, status) = (0x8001_0300, 0x8001_0400, 0x8001_0800);
+        write(VIRTIO_MMIO_QUEUE_NUM, 4);
+        write(VIRTIO_MMIO_QUEUE_DESC_LOW, desc as u32);
+        write(VIRTIO_MMIO_QUEUE_DRIVER_LOW, avail as u32);
+        write(VIRTIO_MMIO_QUEUE_DEVICE_LOW, used as u32);
+        write(VIRTIO_MMIO_QUEUE_READY, 1);
+
+        let descriptor = |address: Address, len: u32, flags: u16, next: u16| {
+            let mut bytes = address.to_le_bytes().to_vec();
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(&flags.to_le_bytes());
+            bytes.extend_from_slice(&next.to_le_bytes());
+            bytes
+        };
+        // header, data and status descriptors of one request, published as the available entry `index`
+        let submit = |request_type: u32, sector: u64, data_flags: u16, index: u16| {
+            let mut request = request_type.to_le_bytes().to_vec();
+            request.extend_from_slice(&[0; 4]);
+            request.extend_from_slice(&sector.to_le_bytes());
+            store(header, &request);
+            let chain = [
+                descriptor(header, 16, VIRTQ_DESC_F_NEXT, 1),
+                descriptor(buffer, VIRTIO_BLK_SECTOR_SIZE as u32, VIRTQ_DESC_F_NEXT | data_flags, 2),
+                descriptor(status, 1, VIRTQ_DESC_F_WRITE, 0),
+            ];
+            store(desc, &chain.concat());
+            store(avail + 4 + 2 * (index as Address % 4), &0u16.to_le_bytes());
+            store(avail + 2, &(index + 1).to_le_bytes());
+            write(VIRTIO_MMIO_QUEUE_NOTIFY, 0);
+        };
+
+        // read the second sector into the guest buffer
+        store(status, &[0xFF]);
+        submit(VIRTIO_BLK_T_IN, 1, VIRTQ_DESC_F_WRITE, 0);
+        assert_eq!(load(status, 1), [VIRTIO_BLK_S_OK]);
+        assert!(load(buffer, VIRTIO_BLK_SECTOR_SIZE as usize).iter().all(|byte| *byte == 0xA5));
+        assert_eq!(load(used + 2, 2), 1u16.to_le_bytes());
+        assert_eq!(load(used + 4, 8), [0, 0, 0, 0, 1, 2, 0, 0]);
+        assert_eq!(read(VIRTIO_MMIO_INTERRUPT_STATUS), 1);
+        assert!(interrupt.load(Ordering::SeqCst));
+        write(VIRTIO_MMIO_INTERRUPT_ACK, 1);
+        assert!(!interrupt.load(Ordering::SeqCst));
+
+        // write the guest buffer to the first sector of the image
+        store(buffer, &[0x5A; VIRTIO_BLK_SECTOR_SIZE as usize]);
+        submit(VIRTIO_BLK_T_OUT, 0, 0, 1);
+        assert_eq!(load(status, 1), [VIRTIO_BLK_S_OK]);
+        assert_eq!(load(used + 2, 2), 2u16.to_le_bytes());
+        let image = std::fs::read(&path).unwrap();
+        assert!(image[..VIRTIO_BLK_SECTOR_SIZE as usize].iter().all(|byte| *byte == 0x5A));
+        assert!(image[VIRTIO_BLK_SECTOR_SIZE as usize..].iter().all(|byte| *byte == 0xA5));
+
+        // sectors past the end of the image fail
+        submit(VIRTIO_BLK_T_IN, 2, VIRTQ_DESC_F_WRITE, 2);
+        assert_eq!(load(status, 1), [VIRTIO_BLK_S_IOERR]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_framebuffer_drawing() {
+        use crate::risc_soc::memory_management_unit::{MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::rv32i_baremetal::framebuffer::{Framebuffer, PixelFormat};
+
         let mut rv32i_core = super::init_core(None);
-        rv32i_core.enable_debug(true);
-        super::load_elf(&mut rv32i_core, "./isa_tests/jump_and_return.elf");
-        for _i in 0..20{
-            rv32i_core.run(None);
+        let framebuffer = Framebuffer::new(MemoryDeviceType::FRAMEBUFFER0, 0x3000_0000, 0x3000_0100).with_mode(4, 2, PixelFormat::RGB565);
+        let view = framebuffer.view();
+        rv32i_core.mmu.write().unwrap().add_memory_device(Box::new(framebuffer));
+
+        // red, green and blue pixels at the corners of the 4x2 frame
+        let program: Vec<u8> = [
+            0x300002B7u32, // lui t0, 0x30000
+            0x00010337,    // lui t1, 0x10
+            0x80030313,    // addi t1, t1, -2048
+            0x00629023,    // sh t1, 0(t0)
+            0x7E000313,    // li t1, 0x07E0
+            0x00629323,    // sh t1, 6(t0)
+            0x01F00313,    // li t1, 0x001F
+            0x00629723,    // sh t1, 14(t0)
+            0x0000006F,    // j .
+        ]
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect();
+        rv32i_core.icache.as_ref().unwrap().write().unwrap().init_mem(0, &program);
+        rv32i_core.run(Some(100));
+        assert_eq!(view.to_xrgb(), [0xFF0000, 0, 0, 0x00FF00, 0, 0, 0, 0x0000FF]);
+
+        // the other formats convert to the same host pixels
+        let framebuffer = Framebuffer::new(MemoryDeviceType::FRAMEBUFFER0, 0, 0x100);
+        let mut gray = framebuffer.with_mode(2, 1, PixelFormat::GRAY8);
+        gray.send_data_request(MemoryRequest {
+            request_type: MemoryRequestType::WRITE,
+            data_address: 0,
+            data_size: WordSize::HALF,
+            data: Some(MemoryData::new(&0x80FFu16.to_le_bytes())),
+        });
+        assert_eq!(gray.view().to_xrgb(), [0xFFFFFF, 0x808080]);
+        let mut xrgb = Framebuffer::new(MemoryDeviceType::FRAMEBUFFER0, 0, 0x100).with_mode(1, 1, PixelFormat::XRGB8888);
+        xrgb.send_data_request(MemoryRequest {
+            request_type: MemoryRequestType::WRITE,
+            data_address: 0,
+            data_size: WordSize::WORD,
+            data: Some(MemoryData::new(&0xFF12_3456u32.to_le_bytes())),
+        });
+        assert_eq!(xrgb.view().to_xrgb(), [0x12_3456]);
+    }
+
+    #[test]
+    fn test_boot_rom() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryData, MemoryRequest, MemoryRequestType, MemoryResponseType};
+        use crate::risc_soc::risc_soc::{RiscWord, WordSize};
+
+        let mut rv32i_core = super::init_core(None);
+        let dtb_address: Address = 0x8700_0000;
+        super::add_boot_rom(&mut rv32i_core, dtb_address, 0x8000_0000);
+        assert_eq!(rv32i_core.get_pc(), super::BOOT_ROM_ADDRESS as RiscWord);
+        let program: Vec<u8> = [
+            0x00050413u32, // mv s0, a0
+            0x00058493,    // mv s1, a1
+            0x00100913,    // li s2, 1
+            0x0000006F,    // j .
+        ]
+        .iter()
+        .flat_map(|instruction| instruction.to_le_bytes())
+        .collect();
+        rv32i_core.init_memory(0x8000_0000, &program);
+        rv32i_core.run(Some(100));
+
+        // the stub passed the hart id and the DTB address before jumping to the program
+        assert_eq!(rv32i_core.read_regs(18, 0).0, 1);
+        assert_eq!(rv32i_core.read_regs(8, 9), (0, dtb_address as RiscWord));
+
+        // the ROM cannot be written
+        let request = |request_type: MemoryRequestType, data: Option<MemoryData>| MemoryRequest {
+            request_type,
+            data_address: super::BOOT_ROM_ADDRESS,
+            data_size: WordSize::WORD,
+            data,
+        };
+        let mut mmu = rv32i_core.mmu.write().unwrap();
+        let stub = mmu.process_memory_request(request(MemoryRequestType::READ, None)).data;
+        let response = mmu.process_memory_request(request(MemoryRequestType::WRITE, Some(MemoryData::zeroed(4))));
+        assert_eq!(response.status, MemoryResponseType::NotWrittable);
+        assert_eq!(mmu.process_memory_request(request(MemoryRequestType::READ, None)).data, stub);
+    }
+
+    #[test]
+    fn test_image_formats() {
+        use crate::risc_soc::load_error::LoadError;
+        use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType};
+        use crate::risc_soc::risc_soc::{RiscCore, WordSize};
+
+        let mut rv32i_core = super::init_core(None);
+        let read_mem = |core: &RiscCore, address: Address, len: usize| -> Vec<u8> {
+            (0..len as Address)
+                .map(|offset| {
+                    let request = MemoryRequest {
+                        request_type: MemoryRequestType::READ,
+                        data_address: address + offset,
+                        data_size: WordSize::BYTE,
+                        data: None,
+                    };
+                    core.dcache_request(request).data[0]
+                })
+                .collect()
+        };
+        let dir = std::env::temp_dir();
+        let to_hex = |bytes: &[u8]| bytes.iter().map(|byte| format!("{byte:02X}")).collect::<String>();
+        let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let intel_record = |record_type: u8, offset: u16, data: &[u8]| {
+            let mut bytes = vec![data.len() as u8];
+            bytes.extend_from_slice(&offset.to_be_bytes());
+            bytes.push(record_type);
+            bytes.extend_from_slice(data);
+            bytes.push(sum(&bytes).wrapping_neg());
+            format!(":{}\n", to_hex(&bytes))
+        };
+        let srec_record = |record_type: char, address: u32, data: &[u8]| {
+            let mut bytes = vec![(4 + data.len() + 1) as u8];
+            bytes.extend_from_slice(&address.to_be_bytes());
+            bytes.extend_from_slice(data);
+            bytes.push(!sum(&bytes));
+            format!("S{record_type}{}\n", to_hex(&bytes))
+        };
+
+        // a raw image is copied as is from the base address
+        let raw = dir.join("riscv_on_rust_test.bin");
+        std::fs::write(&raw, [1, 2, 3, 4, 5]).unwrap();
+        rv32i_core.load_raw(raw.to_str().unwrap(), 0x8001_0000).unwrap();
+        assert_eq!(read_mem(&rv32i_core, 0x8001_0000, 5), [1, 2, 3, 4, 5]);
+
+        // Intel HEX data records are placed after the extended linear address
+        let hex = [
+            intel_record(0x04, 0, &[0x80, 0x01]),
+            intel_record(0x00, 0x0100, b"intel"),
+            intel_record(0x05, 0, &[0x80, 0x00, 0x00, 0x00]),
+            intel_record(0x01, 0, &[]),
+        ]
+        .concat();
+        let path = dir.join("riscv_on_rust_test.hex");
+        std::fs::write(&path, &hex).unwrap();
+        assert_eq!(rv32i_core.load_intel_hex(path.to_str().unwrap()).unwrap(), Some(0x8000_0000));
+        assert_eq!(read_mem(&rv32i_core, 0x8001_0100, 5), b"intel");
+
+        // S-records with 32-bit addresses, the header record carries no content
+        let srec = ["S00600004844521B\n".to_string(), srec_record('3', 0x8001_0200, b"srec"), srec_record('7', 0x8000_0004, &[])].concat();
+        let path = dir.join("riscv_on_rust_test.srec");
+        std::fs::write(&path, &srec).unwrap();
+        assert_eq!(rv32i_core.load_srec(path.to_str().unwrap()).unwrap(), Some(0x8000_0004));
+        assert_eq!(read_mem(&rv32i_core, 0x8001_0200, 4), b"srec");
+
+        // a damaged record is reported with its line
+        std::fs::write(&path, srec.replace("73726563", "73726564")).unwrap();
+        let error = rv32i_core.load_srec(path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(error, LoadError::Format { line: 2, .. }), "{error}");
+        for path in [raw, dir.join("riscv_on_rust_test.hex"), path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_elf_symbols() {
+        use crate::rv32i_baremetal::isa_test::isa_test_image;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
         }
-        println!("{}", rv32i_core.registers);
+
+        let mut rv32i_core = super::init_core(None);
+        super::load_bytes(&mut rv32i_core, isa_test_image("jump_and_return.elf")).unwrap();
+        // symbols of the ELF symbol table, with the offset inside the closest one
+        assert_eq!(rv32i_core.symbol_address("_func"), Some(0x8000_0018));
+        assert_eq!(rv32i_core.lookup_symbol(0x8000_0018).as_deref(), Some("_func"));
+        assert_eq!(rv32i_core.lookup_symbol(0x8000_001C).as_deref(), Some("_func+0x4"));
+        assert_eq!(rv32i_core.disassemble(0x8000_000C, 0x8000_0010).unwrap()[0].2, "jal ra, 0x80000018 <_func>");
+
+        // the trace shows where each fetched instruction comes from
+        let output = Output::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer(move || writer.clone()).finish();
+        tracing::subscriber::with_default(subscriber, || rv32i_core.run_sequential(Some(20)));
+        let trace = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        assert!(trace.contains("Instruction:addi a0, a0, 1(0x150513) <_start>"), "{trace}");
+        assert!(trace.contains("Instruction:add a4, a0, a1(0xB50733) <_func>"));
+        assert!(trace.contains("Instruction:slli a5, a2, 1(0x161793) <_func+0x4>"));
     }
 
     #[test]
+    #[cfg(not(feature = "rv64"))]
     fn test_memory() {
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+
+        let rv32i_core = IsaTest::new("memory.elf").max_cycles(50).expect_mem(0x8001_0000, b"helloworld!").run();
+        // pokes are seen by the program as its own stores, here through the L1 data memory and in DRAM
+        rv32i_core.write_mem(0x8001_0005, b"WO").unwrap();
+        assert_eq!(rv32i_core.read_mem(0x8001_0000, 11).unwrap(), b"helloWOrld!");
+        rv32i_core.write_mem(super::DRAM_ADDRESS, &[0xAB, 0xCD]).unwrap();
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u16(super::DRAM_ADDRESS)).as_u16(), 0xCDAB);
+        assert_eq!(rv32i_core.peek_memory(0x10, 1), None);
+    }
+
+    #[test]
+    fn test_debugger_repl() {
+        use crate::risc_soc::debugger::Debugger;
+
+        let mut rv32i_core = super::init_core(None);
+        let program = rv32i_core
+            .load_assembly(
+                "
+                main:
+                    la t1, data
+                    li t0, 0x2A
+                    sw t0, 0(t1)
+                    jal func
+                done:
+                    j done
+                func:
+                    addi a0, t0, 1
+                    ebreak
+                data:
+                    .word 0
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        let (func, data) = (program.labels["func"], program.labels["data"]);
+
+        let script = "break func\ncontinue\ncontinue\nmem data 4\ndisas func 2\ndelete func\ndelete func\nspeed 0\nbogus\n\nquit\nstep\n";
+        let mut output = vec![];
+        Debugger::new(&mut rv32i_core).repl(script.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let expected = [
+            format!("breakpoint at 0x{func:X}"),
+            format!("breakpoint hit, pc = 0x{func:X} <func>"),
+            "ebreak hit".to_string(),
+            format!("{data:08X}: 2A 00 00 00"),
+            format!("{func:08X} <func>: 00128513  addi a0, t0, 1"),
+            format!("no breakpoint at 0x{func:X}"),
+            "invalid clock frequency: 0".to_string(),
+            "unknown command: bogus".to_string(),
+        ];
+        // the answers come in the order of the commands, and nothing runs after quit
+        let mut rest = output.as_str();
+        for line in &expected {
+            let index = rest.find(line.as_str()).unwrap_or_else(|| panic!("{line:?} missing in {output}"));
+            rest = &rest[index + line.len()..];
+        }
+        assert!(!rest.contains("pc = "));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 0x2B);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_sequential_matches_threaded() {
+        let mut threaded_core = super::init_core(None);
+        super::load_bytes(&mut threaded_core, isa_test_image("branch.elf")).unwrap();
+        threaded_core.run(Some(20));
+
+        let mut sequential_core = super::init_core(None);
+        super::load_bytes(&mut sequential_core, isa_test_image("branch.elf")).unwrap();
+        sequential_core.run_sequential(Some(20));
+
+        assert_eq!(threaded_core.registers.to_string(), sequential_core.registers.to_string());
+        assert_eq!(threaded_core.get_pc(), sequential_core.get_pc());
+    }
+
+    #[test]
+    fn test_memory_data() {
+        use crate::risc_soc::memory_management_unit::MemoryData;
+
+        // the bytes of a double word are carried inline, with the length of the access
+        assert!(std::mem::size_of::<MemoryData>() <= 16);
+        let mut data = MemoryData::new(&[1, 2, 3, 4]);
+        assert_eq!((data.len(), &data[..]), (4, &[1u8, 2, 3, 4][..]));
+        data[1] = 0x20;
+        data.truncate(2);
+        assert_eq!(&data[..], [1, 0x20]);
+        // bytes left past the length do not matter, and are not brought back by growing it again
+        assert_eq!(data, MemoryData::new(&[1, 0x20]));
+        data.resize(4, 0);
+        assert_eq!(data, MemoryData::new(&[1, 0x20, 0, 0]));
+        assert_eq!(MemoryData::zeroed(8), MemoryData::new(&[0; 8]));
+        assert_eq!(format!("{:?}", MemoryData::new(&[5, 6])), "[5, 6]");
+
+        assert!(std::panic::catch_unwind(|| MemoryData::new(&[0; 9])).is_err());
+        assert!(std::panic::catch_unwind(|| MemoryData::zeroed(4).resize(16, 0)).is_err());
+    }
+
+    #[test]
+    fn test_typed_memory_accesses() {
+        use crate::risc_soc::memory_management_unit::{MemoryData, MemoryRequest, MemoryRequestType, MemoryResponse, MemoryResponseType};
+        use crate::risc_soc::risc_soc::WordSize;
+
+        // stores are laid out in little-endian, with the size of the value
+        let store = MemoryRequest::write_u32(0x100, 0x1122_3344);
+        assert_eq!((store.request_type, store.data_address, store.data_size as usize), (MemoryRequestType::WRITE, 0x100, 4));
+        assert_eq!(&store.data.unwrap()[..], [0x44, 0x33, 0x22, 0x11]);
+        assert_eq!(MemoryRequest::write_u64(0, 1).data_size as usize, 8);
+        assert_eq!(MemoryRequest::read_u16(0x102).data_size as usize, 2);
+        assert!(MemoryRequest::read_u8(0).data.is_none());
+        assert!(std::panic::catch_unwind(|| MemoryRequest::write(0, &[0; 3])).is_err());
+
+        // a store must carry the bytes of its size
+        assert_eq!(MemoryRequest::write_u8(0, 1).check(), Ok(()));
+        let mut short = MemoryRequest::write_u16(0, 1);
+        short.data_size = WordSize::WORD;
+        assert_eq!(short.check(), Err("store of 4 bytes carries 2 bytes of data".to_string()));
+        short.data = None;
+        assert_eq!(short.check(), Err("store without data".to_string()));
+
+        // loads read the value back from the same layout
+        let response = MemoryResponse { data: MemoryData::new(&0x8877_6655_4433_2211u64.to_le_bytes()), status: MemoryResponseType::Valid };
+        assert_eq!(response.as_u8(), 0x11);
+        assert_eq!(response.as_u16(), 0x2211);
+        assert_eq!(response.as_u32(), 0x4433_2211);
+        assert_eq!(response.as_u64(), 0x8877_6655_4433_2211);
+        let narrow = MemoryResponse { data: MemoryData::new(&[1, 2]), status: MemoryResponseType::Valid };
+        assert!(std::panic::catch_unwind(|| narrow.as_u32()).is_err());
+        let empty = MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        assert!(std::panic::catch_unwind(|| empty.as_u8()).is_err());
+    }
+
+    #[test]
+    fn test_misaligned_access_policy() {
+        use crate::risc_soc::exception::{Exception, MisalignedAccessPolicy};
+        use crate::risc_soc::memory_management_unit::{MemoryRequest, MemoryResponseType};
+
+        // word crossing the boundary between two lines of the data memory
+        let address = 0x8001_003E;
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.data_request(MemoryRequest::write_u32(address, 0xDEAD_BEEF));
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(address)).as_u32(), 0xDEAD_BEEF);
+        assert!(rv32i_core.pending_trap().is_none());
+
+        rv32i_core.set_misaligned_policy(MisalignedAccessPolicy::Trap);
+        let response = rv32i_core.data_request(MemoryRequest::read_u32(address));
+        assert_eq!(response.status, MemoryResponseType::UnalignedAddress);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!(trap.exception, Exception::LoadAddressMisaligned);
+        assert_eq!(trap.tval, address);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_access_permissions() {
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, MemoryRequest, Permissions};
+        use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+        use crate::rv32i_baremetal::framebuffer::Framebuffer;
+
+        let window = 0x3000_0000;
+        // a boot ROM that can be read and executed, and a framebuffer that can only be written
+        let run = |program: &[u32]| -> RiscCore {
+            let mut rv32i_core = super::init_core(None);
+            super::add_boot_rom(&mut rv32i_core, 0, 0x8000_0000);
+            let framebuffer = Framebuffer::new(MemoryDeviceType::FRAMEBUFFER0, window, window + 0x1000);
+            let write_only = Permissions { read: false, write: true, execute: false };
+            rv32i_core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(framebuffer), write_only);
+            let program: Vec<u8> = program.iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+            rv32i_core.init_memory(0x8000_0000, &program);
+            rv32i_core.set_reset_vector(0x8000_0000);
+            rv32i_core.run_sequential(Some(50));
+            rv32i_core
+        };
+
+        // the ROM is read, but the store to it faults
+        let rv32i_core = run(&[
+            0x000012B7, // lui t0, 0x1
+            0x0002A503, // lw a0, 0(t0)
+            0x00A2A023, // sw a0, 0(t0)
+            0x0000006F, // j .
+        ]);
+        let stub = rv32i_core.mmu.read().unwrap().peek(MemoryRequest::read_u32(super::BOOT_ROM_ADDRESS)).as_u32();
+        assert_eq!(rv32i_core.read_regs(10, 0).0, stub as i32 as RiscWord);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.tval), (Exception::StoreAccessFault, super::BOOT_ROM_ADDRESS));
+
+        // the registers of a device cannot be executed
+        let rv32i_core = run(&[
+            0x406002B7, // lui t0, 0x40600
+            0x00028067, // jr t0
+        ]);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.tval), (Exception::InstructionAccessFault, 0x4060_0000));
+
+        // nor a write only memory read back
+        let rv32i_core = run(&[
+            0x300002B7, // lui t0, 0x30000
+            0x00700513, // li a0, 7
+            0x00A2A023, // sw a0, 0(t0)
+            0x0002A583, // lw a1, 0(t0)
+            0x0000006F, // j .
+        ]);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.tval), (Exception::LoadAccessFault, window));
+        assert_eq!(rv32i_core.read_regs(11, 0).0, 0);
+    }
+
+    #[test]
+    fn test_access_stats() {
+        use crate::risc_soc::memory_management_unit::{DeviceAccessStats, MemoryDeviceType, MemoryRequest};
+
+        let rv32i_core = super::init_core(None);
+        rv32i_core.data_request(MemoryRequest::write_u32(super::DRAM_ADDRESS, 7));
+        rv32i_core.data_request(MemoryRequest::write_u8(super::DRAM_ADDRESS + 4, 1));
+        let read = MemoryRequest::read_u32(super::DRAM_ADDRESS);
+        rv32i_core.data_request(read.clone());
+        let latency = rv32i_core.data_access_latency(&read);
+        for _ in 0..3 {
+            rv32i_core.data_request(MemoryRequest::read_u32(super::RTC_ADDRESS));
+        }
+        // the accesses to the L1 memories do not reach the MMU
+        rv32i_core.data_request(MemoryRequest::read_u32(super::L1_ADDRESS));
+
+        let mmu = rv32i_core.mmu.read().unwrap();
+        let dram = DeviceAccessStats { reads: 1, writes: 2, bytes: 9, latency_cycles: latency };
+        let rtc = DeviceAccessStats { reads: 3, writes: 0, bytes: 12, latency_cycles: 0 };
+        let stats: Vec<_> = mmu.access_stats().into_iter().map(|(id, stats)| (id.memory_type, stats)).collect();
+        assert_eq!(stats, [(MemoryDeviceType::RTC, rtc), (MemoryDeviceType::DRAM, dram)]);
+        assert_eq!(mmu.total_access_stats(), DeviceAccessStats { reads: 4, writes: 2, bytes: 21, latency_cycles: latency });
+        drop(mmu);
+
+        // the busiest devices are listed first in the report
+        let report = rv32i_core.statistics_report();
+        let rows: Vec<_> = report.lines().skip_while(|line| !line.starts_with("memory accesses")).skip(1).collect();
+        assert!(rows[0].starts_with("RTC") && rows[1].starts_with("DRAM") && rows[2].starts_with("total"));
+        rv32i_core.mmu.write().unwrap().clear_access_stats();
+        assert!(rv32i_core.mmu.read().unwrap().access_stats().is_empty());
+    }
+
+    #[test]
+    fn test_event_log() {
+        use crate::risc_soc::risc_soc::ExitStatus;
+        use crate::risc_soc::run_control::RunControl;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core
+            .load_assembly(
+                "
+                main:
+                    li t0, 2
+                loop:
+                    addi t0, t0, -1
+                    bnez t0, loop
+                    li a0, 0x101000
+                    lw a1, 0(a0)
+                    li a2, 0x100000
+                    li a3, 0x5555
+                    sw a3, 0(a2)
+                done: j done
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.enable_prefetcher(16, 2, 1);
+        let output = Output::default();
+        let log = rv32i_core.enable_event_log(output.clone());
+        rv32i_core.run_sequential_with(RunControl::cycles(200));
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Pass));
+        log.finish().unwrap();
+
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<_> = text.lines().map(|line| json::parse(line).unwrap()).collect();
+        assert_eq!(events.len() as u64, log.events());
+        let of_kind = |kind: &str| events.iter().filter(|event| event["event"] == kind).collect::<Vec<_>>();
+        // li t0, 2 / addi / bnez taken / addi / bnez not taken
+        let retired = of_kind("retire");
+        assert_eq!(retired[0]["pc"], 0x8000_0000u64);
+        assert_eq!(retired[0]["instruction"], 0x00200293u32);
+        let branches = of_kind("branch");
+        assert_eq!(branches.len(), 2);
+        assert_eq!((branches[0]["pc"].as_u64(), branches[0]["target"].as_u64()), (Some(0x8000_0008), Some(0x8000_0004)));
+        assert_eq!(branches[0]["taken"], true);
+        assert_eq!(branches[1]["taken"], false);
+        // the RTC read and the test finisher write, the events are in the order of the clock cycles
+        let mmio = of_kind("mmio");
+        assert_eq!(mmio.len(), 2);
+        assert_eq!((mmio[0]["device"].as_str(), mmio[0]["address"].as_u64(), mmio[0]["write"].as_bool()), (Some("RTC"), Some(super::RTC_ADDRESS), Some(false)));
+        assert_eq!((mmio[1]["address"].as_u64(), mmio[1]["data"].as_u64(), mmio[1]["size"].as_u64()), (Some(super::TEST_FINISHER_ADDRESS), Some(0x5555), Some(4)));
+        assert!(of_kind("cache_miss").iter().all(|event| event["cache"] == "instruction"));
+        assert_eq!(of_kind("cache_miss")[0]["address"], 0x8000_0000u64);
+        assert!(events.windows(2).all(|pair| pair[0]["cycle"].as_u64() <= pair[1]["cycle"].as_u64()));
+        assert!(of_kind("trap").is_empty());
+
+        // traps are logged with their cause
+        let output = Output::default();
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.load_assembly("main: nop\nebreak\ndone: j done", 0x8000_0000).unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.enable_event_log(output.clone());
+        rv32i_core.run_sequential_with(RunControl::cycles(20));
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let trap = text.lines().map(|line| json::parse(line).unwrap()).find(|event| event["event"] == "trap").unwrap();
+        assert_eq!((trap["exception"].as_str(), trap["cause"].as_u8(), trap["tval"].as_u64()), (Some("Breakpoint"), Some(3), Some(0x8000_0004)));
+    }
+
+    /// ELF32 executable without section headers, each segment is (address, content, memory size, flags)
+    #[cfg(not(feature = "rv64"))]
+    fn segments_image(entry: u32, segments: &[(u32, &[u8], u32, u32)]) -> Vec<u8> {
+        let mut elf = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        elf.extend_from_slice(&2u16.to_le_bytes());
+        elf.extend_from_slice(&243u16.to_le_bytes());
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        for word in [entry, 52, 0, 0] {
+            elf.extend_from_slice(&word.to_le_bytes());
+        }
+        for half in [52u16, 32, segments.len() as u16, 40, 0, 0] {
+            elf.extend_from_slice(&half.to_le_bytes());
+        }
+        // the contents follow the program headers, in the same order
+        let mut offset = 52 + 32 * segments.len() as u32;
+        for (address, content, memory_size, flags) in segments {
+            for word in [1, offset, *address, *address, content.len() as u32, *memory_size, *flags, 4] {
+                elf.extend_from_slice(&word.to_le_bytes());
+            }
+            offset += content.len() as u32;
+        }
+        for (_, content, _, _) in segments {
+            elf.extend_from_slice(content);
+        }
+        elf
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_load_segments() {
+        use crate::risc_soc::load_error::{ElfLoadMode, LoadError};
+        use crate::risc_soc::run_control::RunControl;
+
+        // the code (R+X), then data (R+W) whose last word is only in memory, as for a .bss
+        // lui t0, 0x80010; lw a0, 0(t0); lw a1, 4(t0); j .
+        let code: Vec<u8> = [0x800102B7u32, 0x0002A503, 0x0042A583, 0x0000006F].iter().flat_map(|word| word.to_le_bytes()).collect();
+        let elf = segments_image(0x8000_0000, &[(0x8000_0000, &code, 16, 5), (0x8001_0000, &42u32.to_le_bytes(), 8, 6)]);
+
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.write_mem(0x8001_0004, &[0xFF; 4]).unwrap();
+        rv32i_core.set_elf_load_mode(ElfLoadMode::Segments);
+        super::load_bytes(&mut rv32i_core, &elf).unwrap();
+        rv32i_core.run_sequential_with(RunControl::cycles(20));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 42);
+        assert_eq!(rv32i_core.read_reg_by_name("a1"), 0);
+
+        // the sections of the ISA tests are still found by name, or through their segments
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.set_elf_load_mode(ElfLoadMode::Segments);
+        super::load_bytes(&mut rv32i_core, isa_test_image("add.elf")).unwrap();
+        let mut sections_core = super::init_core(None);
+        super::load_bytes(&mut sections_core, isa_test_image("add.elf")).unwrap();
+        assert_eq!(rv32i_core.read_mem(0x8000_0000, 0x40).unwrap(), sections_core.read_mem(0x8000_0000, 0x40).unwrap());
+
+        // a segment that does not fit in the L1 memories is reported
+        let elf = segments_image(0x8000_0000, &[(0x8001_0000, &[0; 4], 0x2_0000, 6)]);
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.set_elf_load_mode(ElfLoadMode::Segments);
+        let error = super::load_bytes(&mut rv32i_core, &elf).unwrap_err();
+        assert!(matches!(error, LoadError::OutOfRange { address: 0x8001_0000, size: 0x2_0000, .. }), "{error}");
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_elf_entry() {
+        use crate::risc_soc::load_error::ElfLoadMode;
+        use crate::risc_soc::run_control::RunControl;
+
+        // the entry point is past a word of data at the start of the image: li a0, 7; j .
+        let code: Vec<u8> = [0xDEADBEEFu32, 0x00700513, 0x0000006F].iter().flat_map(|word| word.to_le_bytes()).collect();
+        let elf = segments_image(0x8000_0104, &[(0x8000_0100, &code, 12, 5)]);
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.set_elf_load_mode(ElfLoadMode::Segments);
+        super::load_bytes(&mut rv32i_core, &elf).unwrap();
+        assert_eq!((rv32i_core.get_pc(), rv32i_core.reset_vector), (0x8000_0104, 0x8000_0104));
+        rv32i_core.run_sequential_with(RunControl::cycles(20));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 7);
+        // a reset goes back to the entry point
+        rv32i_core.reset(false);
+        assert_eq!(rv32i_core.get_pc(), 0x8000_0104);
+
+        // an override is kept by the next loads, until it is removed
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.set_entry_override(Some(0x8000_0100));
+        super::load_bytes(&mut rv32i_core, &elf).unwrap();
+        assert_eq!(rv32i_core.get_pc(), 0x8000_0100);
+        rv32i_core.set_entry_override(None);
+        super::load_bytes(&mut rv32i_core, &elf).unwrap();
+        assert_eq!(rv32i_core.get_pc(), 0x8000_0104);
+    }
+
+    #[test]
+    fn test_initial_state() {
+        use crate::risc_soc::initial_state::{InitialState, InitialValue};
+        use crate::risc_soc::run_control::RunControl;
+
+        // no crt0: the stack pointer, the global pointer, the arguments and the data they point to are preloaded
+        let program = "
+            main:
+                lw t0, 0(a1)
+                addi sp, sp, -16
+                sw t0, 4(sp)
+                add a2, a0, t0
+                mv a3, gp
+            done:
+                j done
+        ";
+        let state = InitialState::default()
+            .with_stack(0x8001_1000)
+            .with_reg_symbol("gp", "done")
+            .with_reg("a0", 2)
+            .with_reg("a1", 0x8001_0000)
+            .with_memory(0x8001_0000, &40u32.to_le_bytes());
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.load_assembly(program, 0x8000_0000).unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.set_initial_state(state.clone()).unwrap();
+        rv32i_core.run_sequential_with(RunControl::cycles(30));
+        let registers = ["sp", "a2", "a3"].map(|register| rv32i_core.read_reg_by_name(register));
+        assert_eq!(registers, [0x8001_0FF0, 42, 0x8000_0014]);
+        assert_eq!(rv32i_core.read_mem(0x8001_0FF4, 4).unwrap(), 40u32.to_le_bytes());
+
+        // a reset writes the registers again, the memory keeps what the program wrote
+        rv32i_core.write_mem(0x8001_0000, &7u32.to_le_bytes()).unwrap();
+        rv32i_core.reset(false);
+        assert_eq!(rv32i_core.read_reg_by_name("sp"), 0x8001_1000);
+        rv32i_core.run_sequential_with(RunControl::cycles(30));
+        assert_eq!(rv32i_core.read_reg_by_name("a2"), 9);
+
+        // the same state from a configuration file, with symbols resolved only once applied
+        let config = r#"{ "registers": { "sp": "0x8001_1000", "gp": "done", "a0": 2, "x11": 2147549184 },
+                         "memory": [ { "address": "0x80010000", "data": [40, 0, 0, 0] } ] }"#;
+        assert_eq!(InitialState::from_json(config), Ok(state));
         let mut rv32i_core = super::init_core(None);
-        //rv32i_core.enable_debug(true);
-        super::load_elf(&mut rv32i_core, "./isa_tests/memory.elf");
-        //for _i in 0..50{
-            rv32i_core.run(Some(50));
-        //}
-        rv32i_core.dcache.unwrap().read().unwrap().debug(0x8001_0000, 0x8001_0010).unwrap();
+        assert_eq!(rv32i_core.set_initial_state(InitialState::from_json(config).unwrap()), Err("unknown symbol: done".to_string()));
+        assert_eq!(InitialState::from_json(r#"{ "registers": { "a0": -1 } }"#).unwrap().registers, [(10, InitialValue::Value(RiscWord::MAX))]);
+        assert_eq!(InitialState::from_json(r#"{ "registers": { "zero": 1 } }"#), Err("unknown register: zero".to_string()));
+        assert_eq!(InitialState::from_json(r#"{ "memory": [ { "address": 16 } ] }"#), Err("memory[0] must have either data or file".to_string()));
+        assert!(rv32i_core.set_initial_state(InitialState::default().with_memory(0x10, &[1])).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_chrome_trace() {
+        use crate::risc_soc::chrome_trace::StageActivity;
+        use crate::risc_soc::risc_soc::ExitStatus;
+        use crate::risc_soc::run_control::RunControl;
+
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core
+            .load_assembly(
+                "
+                main:
+                    li t0, 2
+                    li a0, 0x81000000
+                loop:
+                    lw a1, 0(a0)
+                    addi a1, a1, 1
+                    sw a1, 0(a0)
+                    addi t0, t0, -1
+                    bnez t0, loop
+                    li a2, 0x100000
+                    li a3, 0x5555
+                    sw a3, 0(a2)
+                done: j done
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        let trace = rv32i_core.enable_chrome_trace();
+        rv32i_core.run_sequential_with(RunControl::cycles(100));
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Pass));
+
+        let intervals = trace.intervals();
+        assert_eq!((intervals[0].stage, intervals[0].activity, intervals[0].start, intervals[0].end), (0, StageActivity::Busy(0x00200293), 0, 1));
+        // the intervals of a stage never overlap, the load-use hazard stalls the fetch and the taken branch flushes the stages behind EX
+        assert!(intervals.windows(2).all(|pair| pair[0].stage != pair[1].stage || pair[0].end <= pair[1].start));
+        assert!(intervals.iter().any(|interval| interval.stage == 0 && interval.activity == StageActivity::Stalled));
+        assert!(intervals.iter().any(|interval| interval.stage == 2 && interval.activity == StageActivity::Flushed));
+        let devices: Vec<_> = trace.device_events().into_iter().map(|event| (event.device, event.name)).collect();
+        let dram = |name: &str| ("DRAM".to_string(), name.to_string());
+        assert_eq!(devices[..4], [dram("read"), dram("write"), dram("read"), dram("write")]);
+        assert_eq!(devices.len(), 5);
+
+        let mut output = vec![];
+        trace.write_json(&mut output).unwrap();
+        let json = json::parse(std::str::from_utf8(&output).unwrap()).unwrap();
+        let events: Vec<_> = json["traceEvents"].members().collect();
+        let threads: Vec<_> = events.iter().filter(|event| event["name"] == "thread_name" && event["pid"] == 0).map(|event| event["args"]["name"].to_string()).collect();
+        assert_eq!(threads, ["IF", "ID", "EX", "MEM", "WB"]);
+        let stage_events = events.iter().filter(|event| event["ph"] == "X" && event["pid"] == 0).count();
+        assert_eq!(stage_events, intervals.len());
+        assert!(events.iter().any(|event| event["name"] == "li t0, 2" && event["ts"] == 0 && event["dur"] == 1));
+        trace.clear();
+        assert!(trace.intervals().is_empty() && trace.device_events().is_empty());
+    }
+
+    #[test]
+    fn test_finisher_exit() {
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+        use crate::risc_soc::risc_soc::ExitStatus;
+
+        let rv32i_core = super::init_core(None);
+        // unknown values are ignored
+        rv32i_core.data_request(MemoryRequest::write_u32(super::TEST_FINISHER_ADDRESS, 0x1234));
+        assert!(!rv32i_core.halted());
+        rv32i_core.data_request(MemoryRequest::write_u32(super::TEST_FINISHER_ADDRESS, (3 << 16) | 0x3333));
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Fail(3)));
+        // the first exit request wins
+        rv32i_core.data_request(MemoryRequest::write_u32(super::TEST_FINISHER_ADDRESS, 0x5555));
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Fail(3)));
+        assert!(rv32i_core.halted());
+    }
+
+    #[test]
+    fn test_machine_info_csrs() {
+        use crate::risc_soc::csr::{CSR_MARCHID, CSR_MHARTID, CSR_MISA, CSR_MVENDORID, MachineInfo};
+        use crate::risc_soc::risc_soc::{RiscWord, XLEN};
+
+        let mut rv32i_core = super::init_core(None);
+        let mxl: RiscWord = (if XLEN == 64 { 2 } else { 1 }) << (XLEN - 2);
+        assert_eq!(rv32i_core.read_csr(CSR_MISA), Some(mxl | 1 << 0 | 1 << 1 | 1 << 8 | 1 << 12));
+        assert_eq!(rv32i_core.read_csr(CSR_MVENDORID), Some(0));
+
+        rv32i_core.set_machine_info(MachineInfo { hart_id: 3, arch_id: 0x2A, ..MachineInfo::new(0, "IMAC") });
+        assert_eq!(rv32i_core.read_csr(CSR_MISA), Some(mxl | 1 << 0 | 1 << 2 | 1 << 8 | 1 << 12));
+        assert_eq!(rv32i_core.read_csr(CSR_MHARTID), Some(3));
+        assert_eq!(rv32i_core.read_csr(CSR_MARCHID), Some(0x2A));
+        // machine information registers are read-only, misa ignores writes
+        assert!(rv32i_core.write_csr(CSR_MHARTID, 0).is_none());
+        assert!(rv32i_core.write_csr(CSR_MISA, 0).is_some());
+        assert_eq!(rv32i_core.read_csr(CSR_MHARTID), Some(3));
+    }
+
+    #[test]
+    fn test_smp_ipi_and_atomics() {
+        use crate::risc_soc::csr::{CSR_MHARTID, CSR_MIP, MIP_MSIP};
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+        use crate::risc_soc::risc_soc::{RiscWord, WordSize};
+
+        let soc = super::init_soc(2, None);
+        let (hart0, hart1) = (soc.hart(0), soc.hart(1));
+        assert_eq!(hart1.read_csr(CSR_MHARTID), Some(1));
+
+        // hart 0 wakes up hart 1 through its msip register
+        hart0.data_request(MemoryRequest::write_u32(super::CLINT_ADDRESS + 4, 1));
+        assert_eq!(hart1.read_csr(CSR_MIP).unwrap() & MIP_MSIP as RiscWord, MIP_MSIP as RiscWord);
+        assert_eq!(hart0.read_csr(CSR_MIP), Some(0));
+
+        // AMOs on the shared DRAM are seen by the other hart
+        let lock = super::DRAM_ADDRESS;
+        let old = hart0.atomic_request(lock, WordSize::WORD, |_| 1);
+        assert_eq!(old.as_u32(), 0);
+        assert_eq!(hart1.atomic_request(lock, WordSize::WORD, |_| 1).as_u32(), 1);
+
+        // a store from another hart breaks the reservation
+        hart0.load_reserved(lock, WordSize::WORD);
+        hart1.data_request(MemoryRequest::write_u32(lock, 0));
+        assert!(!hart0.store_conditional(lock, &1u32.to_le_bytes()));
+        hart0.load_reserved(lock, WordSize::WORD);
+        assert!(hart0.store_conditional(lock, &2u32.to_le_bytes()));
+        assert_eq!(hart1.data_request(MemoryRequest::read_u32(lock)).as_u32(), 2);
+        assert!(hart0.pending_trap().is_none() && hart1.pending_trap().is_none());
+    }
+
+    #[test]
+    fn test_mesi_coherence() {
+        use crate::risc_soc::coherence::MesiState;
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+
+        let mut soc = super::init_soc(2, None);
+        let bus = soc.add_coherent_caches(64, 16, super::DRAM_ADDRESS, super::DRAM_ADDRESS + super::DRAM_SIZE);
+        let (hart0, hart1) = (soc.hart(0), soc.hart(1));
+        let address = super::DRAM_ADDRESS + 0x40;
+
+        hart0.data_request(MemoryRequest::write_u32(address, 0x1234));
+        assert_eq!(bus.state(0, address), MesiState::Modified);
+        // the modified copy is written back and both caches end up sharing the line
+        assert_eq!(hart1.data_request(MemoryRequest::read_u32(address)).as_u32(), 0x1234);
+        assert_eq!(bus.state(0, address), MesiState::Shared);
+        assert_eq!(bus.state(1, address), MesiState::Shared);
+        assert_eq!(bus.stats(0).writebacks, 1);
+
+        // upgrade on write invalidates the other copy, so the next read of hart 0 is a coherence miss
+        hart1.data_request(MemoryRequest::write_u32(address, 0x5678));
+        assert_eq!(bus.state(0, address), MesiState::Invalid);
+        assert_eq!(bus.stats(0).invalidations, 1);
+        assert_eq!(hart0.data_request(MemoryRequest::read_u32(address)).as_u32(), 0x5678);
+        assert_eq!(bus.stats(0).coherence_misses, 1);
+        assert_eq!(hart0.peek_memory(address, 1), Some(vec![0x78]));
+    }
+
+    #[test]
+    fn test_load_invalid_elf() {
+        let mut rv32i_core = super::init_core(None);
+        // assembly sources are not ELF images and must be rejected without panicking
+        assert!(super::load_bytes(&mut rv32i_core, include_bytes!("../../isa_tests/add.s")).is_err());
+        // neither are empty or truncated images
+        assert!(super::load_bytes(&mut rv32i_core, &[]).is_err());
+        assert!(super::load_bytes(&mut rv32i_core, &isa_test_image("add.elf")[..16]).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_pipeline_diagram() {
+        let mut rv32i_core = super::init_core(None);
+        super::load_bytes(&mut rv32i_core, isa_test_image("branch.elf")).unwrap();
+        let entry = rv32i_core.get_pc();
+        rv32i_core.record_pipeline_diagram(true);
+        rv32i_core.run_sequential(Some(12));
+
+        let rows = rv32i_core.pipeline_diagram.as_ref().unwrap().lock().unwrap().rows();
+        assert_eq!(rows[0].pc, entry);
+        assert_eq!(rows[0].stages, vec![(0, 'F'), (1, 'D'), (2, 'E'), (3, 'M'), (4, 'W')]);
+        assert_eq!(rows[1].pc, entry + 4);
+        assert_eq!(rows[1].stages[0], (1, 'F'));
+        let diagram = rv32i_core.pipeline_diagram().unwrap();
+        println!("{diagram}");
+        assert!(diagram.starts_with("cycle"));
+        assert_eq!(diagram.lines().count(), rows.len() + 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_vcd_waveform() {
+        let mut rv32i_core = super::init_core(None);
+        super::load_bytes(&mut rv32i_core, isa_test_image("add.elf")).unwrap();
+        rv32i_core.record_waveform(true);
+        rv32i_core.run_sequential(Some(8));
+
+        let path = std::env::temp_dir().join("riscv_on_rust_test.vcd");
+        rv32i_core.write_vcd(path.to_str().unwrap()).unwrap();
+        let vcd = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(vcd.contains("$scope module IF $end"));
+        assert!(vcd.contains("$var wire 32 \" instruction $end"));
+        // wires between stages are driven from the first cycle, ex. the forwarding from MEM to EX
+        assert!(vcd.contains("$scope module cdb $end"));
+        assert!(vcd.contains(" mem_forward $end"));
+        assert!(vcd.contains("$enddefinitions $end"));
+        // one rising and one falling clock edge per cycle
+        assert_eq!(vcd.lines().filter(|line| *line == "1!").count(), 9);
+        assert_eq!(vcd.lines().filter(|line| *line == "0!").count(), 9);
+    }
+
+    #[test]
+    fn test_stage_harness() {
+        use crate::risc_soc::pipeline_stage::PipelineData;
+        use crate::risc_soc::risc_soc::XLEN_BYTES;
+        use crate::risc_soc::stage_harness::StageHarness;
+        use crate::rv32i_baremetal::{decode, execute};
+
+        let mut harness = StageHarness::new(super::init_core(None));
+        harness.drive_idle(super::ID_STAGE).drive_idle(super::EX_STAGE);
+        let pc: RiscWord = 0x8000_0100;
+        // decode the instruction with rs1 = x1 and rs2 = x2, then execute it: result, branch taken and target
+        let run_instruction = |harness: &StageHarness, instruction: u32, rs1: RiscWord, rs2: RiscWord| {
+            harness.core.write_reg(1, rs1);
+            harness.core.write_reg(2, rs2);
+            let mut if_id = instruction.to_le_bytes().to_vec();
+            if_id.extend_from_slice(&pc.to_le_bytes());
+            if_id.extend_from_slice(&[0x0, 0x0]);
+            let id_ex = harness.run(super::ID_STAGE, decode::rv32_mcu_decode_stage, &PipelineData(if_id)).output;
+            // multi-cycle operations leave EX busy until their result is released
+            let mut run = harness.run(super::EX_STAGE, execute::rv32_mcu_execute_stage, &id_ex);
+            while !run.controls[super::EX_STAGE].ready {
+                run = harness.run(super::EX_STAGE, execute::rv32_mcu_execute_stage, &id_ex);
+            }
+            // EX tells ID that it holds an instruction
+            assert_eq!(run.wire(super::ID_STAGE).unwrap().get_u8(0x6), 0x1);
+            let ex_mem = run.output;
+            (ex_mem.get_word(0x4), ex_mem.get_u8(0x5 + 2 * XLEN_BYTES), ex_mem.get_word(0x6 + 2 * XLEN_BYTES))
+        };
+        let cases: [(u32, RiscWord, RiscWord, RiscWord, u8, RiscWord); 7] = [
+            // add x3, x1, x2
+            (0x002081b3, 5, 7, 12, 0, pc),
+            // sub x3, x1, x2
+            (0x402081b3, 5, 7, (-2i32) as RiscWord, 0, pc),
+            // slt x3, x1, x2
+            (0x0020a1b3, (-1i32) as RiscWord, 1, 1, 0, pc),
+            // mul x3, x1, x2
+            (0x022081b3, 6, 7, 42, 0, pc),
+            // divu x3, x1, x2 by zero gives all ones
+            (0x0220d1b3, 7, 0, RiscWord::MAX, 0, pc),
+            // beq x1, x2, 8, the target is computed even when the branch is not taken
+            (0x00208463, 3, 3, 0, 1, pc + 8),
+            (0x00208463, 3, 4, 0, 0, pc + 8),
+        ];
+        for (instruction, rs1, rs2, result, taken, target) in cases {
+            let (alu_out, take_jump, next_pc) = run_instruction(&harness, instruction, rs1, rs2);
+            assert_eq!(take_jump, taken, "0x{instruction:08x}");
+            assert_eq!(next_pc, target, "0x{instruction:08x}");
+            if instruction & 0x7F != 0b1100011 {
+                assert_eq!(alu_out, result, "0x{instruction:08x}");
+            }
+        }
+
+        // the value forwarded by MEM replaces the one read from the register file
+        let mut forward = vec![0x1, 0x1];
+        forward.extend_from_slice(&(100 as RiscWord).to_le_bytes());
+        forward.extend_from_slice(&[0x0, 0x0]);
+        harness.drive_signal("mem_forward", PipelineData(forward));
+        assert_eq!(run_instruction(&harness, 0x002081b3, 5, 7).0, 107);
+    }
+
+    #[test]
+    #[should_panic(expected = "it may wait on the wires that are not driven: mem_forward, wb_forward_to_ex")]
+    fn test_stage_harness_undriven() {
+        use crate::risc_soc::stage_harness::StageHarness;
+        use crate::rv32i_baremetal::decode;
+
+        let mut harness = StageHarness::new(super::init_core(None));
+        harness.timeout = std::time::Duration::from_millis(50);
+        harness.run(super::EX_STAGE, crate::rv32i_baremetal::execute::rv32_mcu_execute_stage, &decode::id_ex_bubble());
+    }
+
+    #[test]
+    #[should_panic(expected = "Signal ex_load_hazard is declared with 56 bits but was assigned 24 bits")]
+    fn test_signal_width_checked() {
+        use crate::risc_soc::pipeline_stage::PipelineData;
+
+        let rv32i_core = super::init_core(None);
+        assert_eq!(rv32i_core.cdb.find("mem_forward"), Some((super::MEM_STAGE, super::EX_STAGE)));
+        assert_eq!(format!("{:?}", rv32i_core.cdb.signal(super::EX_STAGE, super::ID_STAGE)), "ex_load_hazard[55:0] = x");
+        rv32i_core.cdb.assign(super::EX_STAGE, super::ID_STAGE, PipelineData(vec![0x3, 0x5, 0x0, 0x0, 0x0, 0x0, 0x1]));
+        assert_eq!(format!("{:?}", rv32i_core.cdb.signal(super::EX_STAGE, super::ID_STAGE)), "ex_load_hazard[55:0] = 0x01000000000503");
+        rv32i_core.cdb.assign(super::EX_STAGE, super::ID_STAGE, PipelineData(vec![0x0; 3]));
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_clock_domains() {
+        use crate::risc_soc::clock::{ClockDomain, Synchronizer};
+        use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::clint::CLINT_MTIME;
+
+        let rtc = ClockDomain::divided("rtc", 4);
+        assert_eq!(rtc.edges_at(9), 2);
+        // from core cycle 9 the next two edges of the divided clock are at cycles 12 and 16
+        assert_eq!(rtc.core_cycles_for(9, 2), 7);
+        let mut synchronizer = Synchronizer::<bool>::new(2);
+        synchronizer.tick(true);
+        assert!(!synchronizer.output());
+        synchronizer.tick(true);
+        assert!(synchronizer.output());
+
+        let mut rv32i_core = super::init_core(None);
+        super::load_bytes(&mut rv32i_core, isa_test_image("add.elf")).unwrap();
+        rv32i_core.mmu.write().unwrap().set_clock_domain(MemoryDeviceType::CLINT, rtc);
+        rv32i_core.run_sequential(Some(15));
+        // the timer only counts the 4 edges of its clock in the 16 core cycles
+        let mtime = rv32i_core.data_request(MemoryRequest::read_u32(super::CLINT_ADDRESS + CLINT_MTIME));
+        assert_eq!(mtime.as_u32(), 4);
+        let stats = rv32i_core.mmu.read().unwrap().crossing_stats();
+        assert_eq!(stats.crossings, 1);
+        assert_eq!(stats.synchronizer_cycles, 8 + 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_host_clock() {
+        use crate::risc_soc::clock::HostClock;
+        use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::clint::CLINT_MTIME;
+        use std::time::Duration;
+
+        let mut rv32i_core = super::init_core(None);
+        super::load_bytes(&mut rv32i_core, isa_test_image("add.elf")).unwrap();
+        // a tenth of the timebase, so mtime counts 1 per microsecond of host time
+        rv32i_core.mmu.write().unwrap().set_host_clock(MemoryDeviceType::CLINT, HostClock::new(super::CLINT_TIMEBASE_HZ, 0.1));
+        std::thread::sleep(Duration::from_millis(5));
+        rv32i_core.run_sequential(Some(2));
+        // mtime follows the time spent by the host rather than the 3 core cycles
+        let mtime = rv32i_core.data_request(MemoryRequest::read_u32(super::CLINT_ADDRESS + CLINT_MTIME));
+        assert!(mtime.as_u32() >= 5000);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_run_control() {
+        use crate::risc_soc::pipeline_stage::{PipelineData, PipelineStage, PipelineStageInterface};
+        use crate::risc_soc::risc_soc::RiscCore;
+        use crate::risc_soc::run_control::StopReason;
+        use std::time::Duration;
+
+        let mut rv32i_core = super::init_core(None);
+        super::load_bytes(&mut rv32i_core, isa_test_image("add.elf")).unwrap();
+        rv32i_core.set_run_timeout(Some(Duration::from_secs(10)));
+        let entry = rv32i_core.get_pc();
+        assert_eq!(rv32i_core.run_for_cycles(0), StopReason::CycleLimit);
+        // budgets are counted from the start of each run
+        assert_eq!(rv32i_core.run_for_cycles(3), StopReason::CycleLimit);
+        assert_eq!(rv32i_core.run_for_cycles(3), StopReason::CycleLimit);
+        assert_eq!(rv32i_core.stages[0].lock().unwrap().clock_cycle, 6);
+        assert_eq!(rv32i_core.get_pc(), entry + 24);
+        assert_eq!(rv32i_core.run_until_pc(entry + 28), StopReason::Condition);
+        assert_eq!(rv32i_core.get_pc(), entry + 28);
+        // sub x7, x6, x5 is the last instruction of the program
+        assert_eq!(rv32i_core.run_until(|core| core.read_regs(7, 0).0 == 3), StopReason::Condition);
+
+        // a stage waiting for a wire nobody drives never reaches the end of its clock cycle without the timeout
+        fn stuck_stage(_data_in: &PipelineData, core: &RiscCore) -> PipelineData {
+            core.cdb.pull(0, 0)
+        }
+        let mut stuck_core = RiscCore::new(1, None, false);
+        stuck_core.add_stage(PipelineStage::new("IF".to_string(), 0, 0, 0, stuck_stage, None, None));
+        stuck_core.set_run_timeout(Some(Duration::from_millis(50)));
+        assert_eq!(stuck_core.run_for_cycles(1000), StopReason::Timeout);
+        assert_eq!(stuck_core.run_sequential(None), StopReason::Timeout);
+    }
+
+    #[test]
+    fn test_speed_throttling() {
+        use std::time::{Duration, Instant};
+
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x00150513, 0xFFDFF06F]); // addi a0, a0, 1; j -4
+        assert_eq!(rv32i_core.speed(), None);
+        // 20 cycles of 1 ms each
+        rv32i_core.set_speed(1_000);
+        assert_eq!(rv32i_core.speed(), Some(1_000));
+        let start = Instant::now();
+        rv32i_core.run_for_cycles(20);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        rv32i_core.run_free();
+        assert_eq!(rv32i_core.speed(), None);
+        rv32i_core.run_for_cycles(20);
+        assert_eq!(rv32i_core.stages[0].lock().unwrap().clock_cycle, 40);
+    }
+
+    #[test]
+    #[cfg(feature = "rv64")]
+    fn test_rv64_words() {
+        // the fixtures and the programs of the other tests compute the addresses above 2GiB with lui, sign extended here
+        let mut rv64_core = super::init_core(None);
+        load_program(&mut rv64_core, &[
+            0x00010297,    // auipc t0, 0x10
+            0xfff00313,    // li t1, -1
+            0x0062a023,    // sw t1, 0(t0)
+            0x0002a503,    // lw a0, 0(t0)
+            0x0002e583,    // lwu a1, 0(t0)
+            0x0062b423,    // sd t1, 8(t0)
+            0x0082b603,    // ld a2, 8(t0)
+            0x0015869b,    // addiw a3, a1, 1
+            0x00100713,    // li a4, 1
+            0x02871713,    // slli a4, a4, 40
+            0x800007b7,    // lui a5, 0x80000
+            0x0000006f,    // j 0
+        ]);
+        rv64_core.run_sequential(Some(50));
+
+        assert_eq!(rv64_core.read_reg_by_name("t0"), 0x8001_0000);
+        assert_eq!(rv64_core.read_reg_by_name("a0"), u64::MAX);
+        assert_eq!(rv64_core.read_reg_by_name("a1"), 0xFFFF_FFFF);
+        assert_eq!(rv64_core.read_reg_by_name("a2"), u64::MAX);
+        assert_eq!(rv64_core.peek_memory(0x8001_0008, 8), Some(vec![0xFF; 8]));
+        // the 32 bit sum wraps to 0 before being sign extended
+        assert_eq!(rv64_core.read_reg_by_name("a3"), 0);
+        assert_eq!(rv64_core.read_reg_by_name("a4"), 1 << 40);
+        assert_eq!(rv64_core.read_reg_by_name("a5"), 0xFFFF_FFFF_8000_0000);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_semihosting() {
+        use crate::risc_soc::exception::Exception;
+
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[
+            0x800105b7,    // lui a1, 0x80010
+            0x00400513,    // li a0, 4 (SYS_WRITE0)
+            0x01f01013,    // slli x0, x0, 0x1f
+            0x00100073,    // ebreak
+            0x40705013,    // srai x0, x0, 7
+            0x00050413,    // mv s0, a0
+            0x00100073,    // ebreak, without the semihosting sequence
+            0x0000006f,    // j 0
+        ]);
+        rv32i_core.init_memory(0x8001_0000, b"hi\n\0");
+        let semihosting = rv32i_core.enable_semihosting("semihosting");
+        rv32i_core.run_sequential(Some(50));
+
+        assert_eq!(semihosting.console_output(), "hi\n");
+        assert_eq!(rv32i_core.read_reg_by_name("s0"), 0);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!(trap.exception, Exception::Breakpoint);
+        assert_eq!(trap.tval, 0x8000_0018);
+    }
+
+    #[test]
+    fn test_register_names() {
+        use crate::risc_soc::risc_soc::register_index;
+
+        let rv32i_core = super::init_core(None);
+        assert_eq!(register_index("fp"), Some(8));
+        assert_eq!(register_index("x31"), Some(31));
+        assert_eq!(register_index("x32"), None);
+        rv32i_core.write_reg_by_name("sp", 0x8001_0400);
+        rv32i_core.write_reg_by_name("x10", 7);
+        rv32i_core.write_reg_by_name("zero", 1);
+        assert_eq!(rv32i_core.read_reg_by_name("x2"), 0x8001_0400);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 7);
+        assert_eq!(rv32i_core.read_reg_by_name("zero"), 0);
+        let registers: Vec<_> = rv32i_core.registers.iter().collect();
+        assert_eq!(registers[10], ("a0", 7));
+        assert_eq!(registers[31].0, "t6");
+        assert!(rv32i_core.registers.to_string().contains("x10(a0)=7\n"));
+    }
+
+    #[test]
+    fn test_ecall_ebreak() {
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::risc_soc::ExitStatus;
+        use crate::risc_soc::run_control::StopReason;
+
+        // exit sequence of newlib's crt0: li a0, 0; li a7, 93; ecall
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x00000513, 0x05d00893, 0x00000073, 0x0000006f]);
+        assert_eq!(rv32i_core.run_for_cycles(50), StopReason::Halted);
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Pass));
+
+        // any other ecall traps: li a7, 1; ecall
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x00100893, 0x00000073, 0x0000006f]);
+        assert_eq!(rv32i_core.run_for_cycles(50), StopReason::Halted);
+        assert_eq!(rv32i_core.take_trap().unwrap().exception, Exception::EnvironmentCallFromMMode);
+
+        // in debug mode ebreak stops the run and execution resumes after it: ebreak; li a0, 5; j 0
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.enable_debug(true);
+        load_program(&mut rv32i_core, &[0x00100073, 0x00500513, 0x0000006f]);
+        assert_eq!(rv32i_core.run_for_cycles(50), StopReason::Breakpoint);
+        assert!(rv32i_core.pending_trap().is_none());
+        assert_eq!(rv32i_core.run_for_cycles(20), StopReason::CycleLimit);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 5);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_fetch_faults() {
+        use crate::risc_soc::exception::Exception;
+
+        // li t0, 0x10; jr t0, into memory that is not mapped
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x01000293, 0x00028067]);
+        rv32i_core.run_for_cycles(50);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!(trap.exception, Exception::InstructionAccessFault);
+        assert_eq!(trap.tval, 0x10);
+
+        // li t0, 0x80000002; jr t0
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x800002b7, 0x00228293, 0x00028067]);
+        rv32i_core.run_for_cycles(50);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!(trap.exception, Exception::InstructionAddressMisaligned);
+        assert_eq!(trap.tval, 0x8000_0002);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_dram_latency() {
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, Permissions};
+        use crate::rv32i_baremetal::dram::{Dram, DramTiming};
+
+        // lui t0, 0x81000; li t2, 7; sw t2, 0(t0); lw t1, 0(t0); addi a0, t1, 1; j .
+        let cycles_to_finish = |timing: DramTiming| {
+            let mut rv32i_core = super::init_hart(None);
+            let dram = Dram::new(MemoryDeviceType::DRAM, super::DRAM_ADDRESS, super::DRAM_ADDRESS + super::DRAM_SIZE).with_timing(timing);
+            rv32i_core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(dram), Permissions::RWX);
+            load_program(&mut rv32i_core, &[0x810002b7, 0x00700393, 0x0072a023, 0x0002a303, 0x00130513, 0x0000006f]);
+            rv32i_core.run_until(|core| core.read_reg_by_name("a0") == 8);
+            assert_eq!(rv32i_core.read_reg_by_name("a0"), 8);
+            rv32i_core.stages[0].lock().unwrap().clock_cycle
+        };
+
+        let ideal = cycles_to_finish(DramTiming::default());
+        let timing = DramTiming { cas_latency: 3, ras_to_cas_delay: 4, precharge: 5, ..DramTiming::default() };
+        // the store opens the row (tRCD + tCAS), the load then hits the open row (tCAS)
+        assert_eq!(cycles_to_finish(timing), ideal + 4 + 3 + 3);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_store_buffer() {
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, Permissions};
+        use crate::risc_soc::store_buffer::StoreBufferStats;
+        use crate::rv32i_baremetal::dram::{Dram, DramTiming};
+
+        // lui t0, 0x81000; li t2, 7; sw t2, 0(t0); sw t2, 4(t0); sw t2, 8(t0); sw t2, 12(t0); lw t1, 4(t0); addi a0, t1, 1; j .
+        let program = [
+            0x810002b7, 0x00700393, 0x0072a023, 0x0072a223, 0x0072a423, 0x0072a623, 0x0042a303, 0x00130513, 0x0000006f,
+        ];
+        let run = |timing: DramTiming, store_buffer: bool| {
+            let mut rv32i_core = super::init_hart(None);
+            let dram = Dram::new(MemoryDeviceType::DRAM, super::DRAM_ADDRESS, super::DRAM_ADDRESS + super::DRAM_SIZE).with_timing(timing);
+            rv32i_core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(dram), Permissions::RWX);
+            if store_buffer {
+                rv32i_core.enable_store_buffer(2);
+            }
+            load_program(&mut rv32i_core, &program);
+            rv32i_core.run_until(|core| core.read_reg_by_name("a0") == 8);
+            assert_eq!(rv32i_core.read_reg_by_name("a0"), 8);
+            assert_eq!(rv32i_core.read_mem(super::DRAM_ADDRESS, 16).unwrap(), [7, 0, 0, 0].repeat(4));
+            let cycles = rv32i_core.stages[0].lock().unwrap().clock_cycle;
+            (cycles, rv32i_core.store_buffer_stats())
+        };
+
+        let (ideal, _) = run(DramTiming::default(), false);
+        let timing = DramTiming { cas_latency: 3, ras_to_cas_delay: 4, precharge: 5, ..DramTiming::default() };
+        // every store waits for the DRAM, the first one opening the row
+        assert_eq!(run(timing, false), (ideal + 7 + 3 * 4, None));
+
+        // the stores are combined two by two and retire at once, the load only waits for the entry holding its bytes
+        let (cycles, stats) = run(timing, true);
+        assert_eq!(cycles, ideal + 7 + 3);
+        let stats = stats.unwrap();
+        assert_eq!(stats, StoreBufferStats { stores: 4, combined: 2, drained: stats.drained, full_stalls: 0, load_drains: 1 });
+        assert!(stats.drained >= 1);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        // li a0, 1; nop; j .
+        let mut rv32i_core = super::init_hart(None);
+        load_program(&mut rv32i_core, &[0x00100513, 0x00000013, 0x0000006f]);
+        let instructions = rv32i_core.disassemble(0x8000_0000, 0x8000_000C).unwrap();
+        let encodings: Vec<_> = instructions.iter().map(|&(address, instruction, _)| (address, instruction)).collect();
+        assert_eq!(encodings, [(0x8000_0000, 0x00100513), (0x8000_0004, 0x00000013), (0x8000_0008, 0x0000006f)]);
+        let text: Vec<_> = instructions.iter().map(|(_, _, text)| text.as_str()).collect();
+        assert_eq!(text, ["li a0, 1", "nop", "j 0x80000008"]);
+        // the decoder is built once, so a large range is cheap to disassemble
+        // the zeroed memory after the program is read as 16-bit compressed encodings
+        assert_eq!(rv32i_core.disassemble(0x8000_0000, 0x8001_0000).unwrap().len(), 3 + (0x1_0000 - 12) / 2);
+        assert!(rv32i_core.disassemble(0x7FFF_FFF8, 0x8000_0008).is_none());
+
+        use crate::risc_soc::disasm::{disassemble, disassemble_at, disassemble_block};
+        use crate::risc_soc::symbols::{Symbol, SymbolTable};
+        assert_eq!(disassemble(0xffc42503), "lw a0, -4(s0)");
+        assert_eq!(disassemble(0x02b50533), "mul a0, a0, a1");
+        assert_eq!(disassemble(0x0cb5272f), "amoswap.w.aq a4, a1, (a0)");
+        assert_eq!(disassemble(0x34102573), "csrrs a0, mepc, zero");
+        assert_eq!(disassemble(0xfe0292e3), "bne t0, zero, pc-28");
+        let mut symbols = SymbolTable::default();
+        symbols.insert(Symbol { name: "loop".to_string(), address: 0x8000_0010, size: 0 });
+        assert_eq!(disassemble_at(0xfe0292e3, 0x8000_002C, Some(&symbols)), "bne t0, zero, 0x80000010 <loop>");
+        // c.li a0, 5; c.addi a0, -1; addi a0, a0, 1
+        let code = [0x15, 0x45, 0x7D, 0x15, 0x13, 0x05, 0x15, 0x00];
+        let text: Vec<_> = disassemble_block(0x100, &code, None).into_iter().map(|(address, _, text)| (address, text)).collect();
+        assert_eq!(text, [(0x100, "c.li a0, 5".to_string()), (0x102, "c.addi a0, -1".to_string()), (0x104, "addi a0, a0, 1".to_string())]);
+    }
+
+    #[test]
+    fn test_assembler() {
+        use crate::risc_soc::asm::{AsmError, assemble};
+        use crate::risc_soc::disasm::disassemble;
+        use crate::risc_soc::memory_management_unit::Address;
+
+        let mut rv32i_core = super::init_hart(None);
+        let program = rv32i_core
+            .load_assembly(
+                "
+                    li a0, 0
+                    li t0, 10
+                loop:                       # a0 = 10 + 9 + ... + 1
+                    add a0, a0, t0
+                    addi t0, t0, -1
+                    bnez t0, loop
+                    call double
+                    la t1, result
+                    sw a0, 0(t1)
+                done: j done
+                double:
+                    slli a0, a0, 1
+                    ret
+                result: .word 0
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_for_cycles(200);
+        let result = program.labels["result"];
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 110);
+        assert_eq!(rv32i_core.read_mem(result, 4).unwrap(), 110u32.to_le_bytes());
+        assert_eq!(rv32i_core.symbol_address("double"), Some(program.labels["double"]));
+        assert_eq!(program.words.len() as Address, (result + 4 - 0x8000_0000) / 4);
+
+        // the disassembler prints back the text of the assembled instructions
+        let lines = [
+            "lui a0, 0x81000",
+            "lw a0, -4(s0)",
+            "sw t2, 12(t0)",
+            "srai a0, a0, 3",
+            "mul a0, a0, a1",
+            "amoswap.w.aq a4, a1, (a0)",
+            "lr.w t0, (a0)",
+            "csrrs a0, mepc, zero",
+            "ecall",
+        ];
+        let words = assemble(&lines.join("\n"), 0).unwrap().words;
+        let text: Vec<_> = words.into_iter().map(disassemble).collect();
+        assert_eq!(text, lines);
+        assert_eq!(assemble("li a0, 0x12345678", 0).unwrap().words, [0x12345537, 0x67850513]);
+
+        assert_eq!(assemble("nop\naddi a0, a0, 5000", 0).unwrap_err().line, 2);
+        assert_eq!(
+            assemble("j missing", 0).unwrap_err(),
+            AsmError { line: 1, reason: "undefined label: missing".to_string() }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "jit")]
+    fn test_jit_matches_pipeline() {
+        use crate::risc_soc::risc_soc::RiscWord;
+        use crate::rv32i_baremetal::jit::JitEngine;
+
+        let source = "
+                la s0, data
+                li t0, 8
+            fill:                       # data[i] = (t0 + 3) * t0 - 0x80
+                addi t1, t0, 3
+                mul t1, t1, t0
+                addi t1, t1, -128
+                sw t1, 0(s0)
+                addi s0, s0, 4
+                addi t0, t0, -1
+                bnez t0, fill
+                la s0, data
+                li t0, 8
+                li a0, 0
+            sum:
+                lb t2, 0(s0)
+                lhu t3, 2(s0)
+                call accumulate
+                addi s0, s0, 4
+                addi t0, t0, -1
+                bnez t0, sum
+                la t1, data
+                sw a0, 0(t1)
+            done: j done
+            accumulate:
+                add a0, a0, t2
+                xor a0, a0, t3
+                sra a1, a0, t0
+                sltu a2, a0, a1
+                ret
+            data: .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+            ";
+        let mut pipeline_core = super::init_hart(None);
+        let program = pipeline_core.load_assembly(source, 0x8000_0000).unwrap();
+        pipeline_core.set_reset_vector(0x8000_0000);
+        pipeline_core.run_for_cycles(1000);
+
+        let mut jit_core = super::init_hart(None);
+        jit_core.load_assembly(source, 0x8000_0000).unwrap();
+        jit_core.set_reset_vector(0x8000_0000);
+        let mut jit = JitEngine::new(2);
+        assert_eq!(jit.run(&jit_core, 500).unwrap(), 500);
+        // the loops were translated, the code before them interpreted
+        assert!(jit.translated_instructions > 0 && jit.interpreted_instructions > 0);
+
+        let data = program.labels["data"];
+        let done: RiscWord = program.labels["done"] as RiscWord;
+        assert_eq!(jit_core.get_pc(), done);
+        for index in 1..32 {
+            assert_eq!(jit_core.read_regs(index, 0).0, pipeline_core.read_regs(index, 0).0, "x{index}");
+        }
+        assert_eq!(jit_core.peek_memory(data, 32), pipeline_core.peek_memory(data, 32));
+        assert_ne!(pipeline_core.read_reg_by_name("a0"), 0);
+    }
+
+    #[test]
+    fn test_debugger_memory_access() {
+        use crate::risc_soc::memory_management_unit::{MemoryAccessError, MemoryResponseType};
+
+        let mut rv32i_core = super::init_core(None);
+        super::add_boot_rom(&mut rv32i_core, 0, super::DRAM_ADDRESS);
+        let end = super::DRAM_ADDRESS + super::DRAM_SIZE;
+        rv32i_core.write_mem(end - 4, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(rv32i_core.read_mem(end - 4, 4), Ok(vec![1, 2, 3, 4]));
+
+        // a write running past the end of the DRAM is refused before any byte is written
+        assert_eq!(
+            rv32i_core.write_mem(end - 2, &[0xAA; 4]),
+            Err(MemoryAccessError { address: end, status: MemoryResponseType::InvalidAddress })
+        );
+        assert_eq!(rv32i_core.read_mem(end - 4, 4), Ok(vec![1, 2, 3, 4]));
+        assert_eq!(
+            rv32i_core.read_mem(end - 2, 4),
+            Err(MemoryAccessError { address: end, status: MemoryResponseType::InvalidAddress })
+        );
+
+        // the registers of a device are never written, so the test finisher does not stop the simulation
+        let finisher = super::TEST_FINISHER_ADDRESS;
+        assert_eq!(
+            rv32i_core.write_mem(finisher, &0x5555u32.to_le_bytes()),
+            Err(MemoryAccessError { address: finisher, status: MemoryResponseType::NotWrittable })
+        );
+        assert_eq!(rv32i_core.exit_status(), None);
+        // nor is a memory the core cannot write
+        let rom = super::BOOT_ROM_ADDRESS;
+        let stub = rv32i_core.read_mem(rom, 4).unwrap();
+        assert_eq!(
+            rv32i_core.write_mem(rom, &[0; 4]),
+            Err(MemoryAccessError { address: rom, status: MemoryResponseType::NotWrittable })
+        );
+        assert_eq!(rv32i_core.read_mem(rom, 4).unwrap(), stub);
+    }
+
+    #[test]
+    fn test_observer() {
+        use crate::risc_soc::exception::{Exception, Trap};
+        use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType, MemoryResponse};
+        use crate::risc_soc::observer::SimulationObserver;
+        use crate::risc_soc::risc_soc::RiscCore;
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct Recorder {
+            cycles: Mutex<Vec<u64>>,
+            retired: Mutex<Vec<(RiscWord, u32)>>,
+            traps: Mutex<Vec<Trap>>,
+            accesses: Mutex<Vec<(MemoryRequestType, Address)>>,
+        }
+
+        impl SimulationObserver for Recorder {
+            fn on_cycle(&self, _core: &RiscCore, clock_cycle: u64) {
+                self.cycles.lock().unwrap().push(clock_cycle);
+            }
+            fn on_instruction_retired(&self, _core: &RiscCore, pc: RiscWord, instruction: u32) {
+                self.retired.lock().unwrap().push((pc, instruction));
+            }
+            fn on_trap(&self, _core: &RiscCore, trap: &Trap) {
+                self.traps.lock().unwrap().push(*trap);
+            }
+            fn on_mem_access(&self, _core: &RiscCore, request: &MemoryRequest, _response: &MemoryResponse) {
+                self.accesses.lock().unwrap().push((request.request_type, request.data_address));
+            }
+        }
+
+        let mut rv32i_core = super::init_hart(None);
+        let recorder = Arc::new(Recorder::default());
+        rv32i_core.add_observer(recorder.clone());
+        let program = rv32i_core
+            .load_assembly(
+                "
+                    la t0, data
+                    li a0, 5
+                    sw a0, 0(t0)
+                    lw a1, 0(t0)
+                    li a7, 1
+                    ecall               # traps without a handler
+                    nop                 # keeps the data out of the instructions fetched after the ecall
+                    nop
+                    nop
+                    nop
+                data: .word 0
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_for_cycles(100);
+
+        let cycles = recorder.cycles.lock().unwrap();
+        assert!(!cycles.is_empty() && cycles.len() < 100);
+        assert!(cycles.windows(2).all(|pair| pair[1] == pair[0] + 1));
+
+        // the ecall trapped, so it did not retire
+        let retired = recorder.retired.lock().unwrap();
+        let expected: Vec<_> = (0..6).map(|index| (0x8000_0000 + 4 * index as RiscWord, program.words[index])).collect();
+        assert_eq!(*retired, expected);
+
+        let data = program.labels["data"];
+        assert_eq!(*recorder.accesses.lock().unwrap(), [(MemoryRequestType::WRITE, data), (MemoryRequestType::READ, data)]);
+        let traps = recorder.traps.lock().unwrap();
+        assert_eq!(traps.len(), 1);
+        assert_eq!(traps[0].exception, Exception::EnvironmentCallFromMMode);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_fault_injection() {
+        use crate::risc_soc::fault_injection::{random_faults, Fault, FaultCampaign, FaultEffect, FaultTarget};
+        use crate::risc_soc::risc_soc::ExitStatus;
+
+        // a0 = 4 * 3 stored at the start of the DRAM, then the test finisher ends the run
+        let build = || {
+            let mut rv32i_core = super::init_core(None);
+            rv32i_core
+                .load_assembly(
+                    "
+                    main:
+                        li a0, 0
+                        li t0, 4
+                    loop:
+                        addi a0, a0, 3
+                        addi t0, t0, -1
+                        bnez t0, loop
+                        li a1, 0x81000000
+                        sw a0, 0(a1)
+                        li a2, 0x100000
+                        li a3, 0x5555
+                        sw a3, 0(a2)
+                    done: j done
+                    ",
+                    0x8000_0000,
+                )
+                .unwrap();
+            rv32i_core.set_reset_vector(0x8000_0000);
+            rv32i_core
+        };
+        let mut golden = build();
+        golden.run_for_cycles(200);
+        assert_eq!(golden.exit_status(), Some(ExitStatus::Pass));
+        assert_eq!(golden.read_mem(super::DRAM_ADDRESS, 4).unwrap(), [12, 0, 0, 0]);
+
+        let mut campaign = FaultCampaign::new(build, 200, vec![(super::DRAM_ADDRESS, 16)]);
+        // a register the program never reads keeps the flipped bit
+        let fault = Fault { cycle: 3, target: FaultTarget::Register { index: 31, bit: 4 } };
+        let report = campaign.run(vec![fault]);
+        assert_eq!((report.effect, report.applied, report.registers, report.memory), (FaultEffect::SilentDataCorruption, vec![fault], vec![(31, 0, 16)], vec![]));
+        // the program overwrites the flipped byte
+        let report = campaign.run(vec![Fault { cycle: 1, target: FaultTarget::Memory { address: super::DRAM_ADDRESS, bit: 7 } }]);
+        assert_eq!(report.effect, FaultEffect::Masked);
+        // while it stays in the memory the program does not write
+        let report = campaign.run(vec![Fault { cycle: 1, target: FaultTarget::Memory { address: super::DRAM_ADDRESS + 8, bit: 7 } }]);
+        assert_eq!((report.effect, report.memory), (FaultEffect::SilentDataCorruption, vec![(super::DRAM_ADDRESS + 8, 0, 0x80)]));
+        // the loop counter goes negative and the loop no longer ends within the budget
+        let report = campaign.run(vec![Fault { cycle: 8, target: FaultTarget::Register { index: 5, bit: 31 } }]);
+        assert_eq!(report.effect, FaultEffect::Hang);
+        // faults on unmapped memory are not injected
+        let report = campaign.run(vec![Fault { cycle: 1, target: FaultTarget::Memory { address: 0x4000_0000, bit: 0 } }]);
+        assert_eq!((report.effect, report.applied), (FaultEffect::Masked, vec![]));
+
+        // the campaigns are reproducible from their seed
+        assert_eq!(random_faults(7, 20, 0..200, &[(super::DRAM_ADDRESS, 16)], 5), random_faults(7, 20, 0..200, &[(super::DRAM_ADDRESS, 16)], 5));
+        let reports = campaign.run_random(7, 20);
+        assert_eq!(reports, campaign.run_random(7, 20));
+        assert!(reports.iter().any(|report| matches!(report.applied[..], [Fault { target: FaultTarget::Wire { .. }, .. }])));
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_torture() {
+        use crate::rv32i_baremetal::torture::{TortureInstruction, TortureTest};
+
+        for seed in 0..8 {
+            let test = TortureTest::generate(seed, 200);
+            assert_eq!(test.check(), Ok(()), "seed {seed}");
+        }
+        // the sequences are reproducible from their seed
+        assert_eq!(TortureTest::generate(3, 50), TortureTest::generate(3, 50));
+        assert_ne!(TortureTest::generate(3, 50), TortureTest::generate(4, 50));
+
+        // shrinking keeps the smallest body for which the sequence still fails, here any body with a MUL
+        let is_mul = |instruction: &TortureInstruction| matches!(instruction, TortureInstruction::Plain(word) if word & 0xFE00_707F == 0x0200_0033);
+        let test = TortureTest::generate(5, 100);
+        assert!(test.body.iter().any(is_mul));
+        let smallest = test.shrink(|test| test.body.iter().any(is_mul));
+        assert_eq!(smallest.body.len(), 1);
+        assert!(is_mul(&smallest.body[0]));
+        assert_eq!(smallest.check(), Ok(()));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_pipeline_data_round_trip(
+            prefix in proptest::collection::vec(proptest::num::u8::ANY, 0..16),
+            value in proptest::num::u64::ANY,
+            suffix in proptest::collection::vec(proptest::num::u8::ANY, 0..16),
+            slots in proptest::collection::vec((proptest::num::u32::ANY, proptest::num::u64::ANY), 1..=crate::risc_soc::pipeline_stage::MAX_ISSUE_WIDTH),
+        ) {
+            use crate::risc_soc::pipeline_stage::PipelineData;
+            use crate::risc_soc::risc_soc::XLEN_BYTES;
+
+            // values pushed after any other field are read back whole at their offset, whatever surrounds them
+            let offset = prefix.len();
+            let mut data = PipelineData(prefix);
+            data.push_bytes(value.to_le_bytes().to_vec());
+            data.push_bytes(suffix.clone());
+            proptest::prop_assert_eq!(data.size(), offset + 8 + suffix.len());
+            proptest::prop_assert_eq!(data.get_u8(offset), value as u8);
+            proptest::prop_assert_eq!(data.get_u16(offset), value as u16);
+            proptest::prop_assert_eq!(data.get_u32(offset), value as u32);
+            proptest::prop_assert_eq!(data.get_u64(offset), value);
+            proptest::prop_assert_eq!(data.get_word(offset), value as RiscWord);
+            proptest::prop_assert_eq!(data.get_u32(offset + 4), (value >> 32) as u32);
+            proptest::prop_assert_eq!(&data.0[offset + 8..], &suffix[..]);
+            if XLEN_BYTES == 4 {
+                proptest::prop_assert_eq!(data.get_word(offset + 4), (value >> 32) as RiscWord);
+            }
+
+            let slots: Vec<(u32, RiscWord)> = slots.into_iter().map(|(instruction, pc)| (instruction, pc as RiscWord)).collect();
+            let packet = PipelineData::fetch_packet(&slots);
+            proptest::prop_assert_eq!(packet.fetch_slots(), slots.len());
+            for (index, slot) in slots.iter().enumerate() {
+                proptest::prop_assert_eq!(packet.fetch_slot(index), *slot);
+            }
+        }
+
+        #[test]
+        fn test_decode_immediate(fields in proptest::num::u32::ANY, format in 0..9usize) {
+            use crate::risc_soc::risc_soc::{RiscSignedWord, XLEN};
+            use crate::rv32i_baremetal::decode::*;
+
+            // the immediate of every format straight from the bit positions of the specification, sign extended from its top bit
+            let bits = |high: u32, low: u32| (fields >> low) & ((1u64 << (high - low + 1)) - 1) as u32;
+            let sign_extend = |value: u32, width: u32| ((value as i64) << (64 - width) >> (64 - width)) as RiscSignedWord as RiscWord;
+            let opcodes = [OP_ALUI, OP_LOAD, OP_JALR, OP_STORE, OP_BRANCH, OP_JAL, OP_LUI, OP_AUIPC, OP_ALU];
+            let opcode = opcodes[format];
+            let expected = match opcode {
+                OP_ALUI | OP_LOAD | OP_JALR => sign_extend(bits(31, 20), 12),
+                OP_STORE => sign_extend(bits(31, 25) << 5 | bits(11, 7), 12),
+                OP_BRANCH => sign_extend(bits(31, 31) << 12 | bits(7, 7) << 11 | bits(30, 25) << 5 | bits(11, 8) << 1, 13),
+                OP_JAL => sign_extend(bits(31, 31) << 20 | bits(19, 12) << 12 | bits(20, 20) << 11 | bits(30, 21) << 1, 21),
+                // the 20 upper bits, sign extended on RV64
+                OP_LUI | OP_AUIPC => sign_extend(bits(31, 12) << 12, 32),
+                _ => 0,
+            };
+            let instruction = fields & !OPCODE_MASK | opcode as u32;
+            let op = decode_instruction(instruction).unwrap();
+            proptest::prop_assert_eq!(op.imm, expected, "instruction 0x{:08x}", instruction);
+            if XLEN == 64 {
+                let op = decode_instruction(fields & !OPCODE_MASK | OP_ALUI_W as u32).unwrap();
+                proptest::prop_assert_eq!(op.imm, sign_extend(bits(31, 20), 12));
+            }
+        }
+    }
+
+    #[test]
+    fn test_profiler() {
+        let mut rv32i_core = super::init_hart(None);
+        let profiler = rv32i_core.enable_profiler();
+        rv32i_core
+            .load_assembly(
+                "
+                main:
+                    li a0, 0
+                    call work
+                    call work
+                done: j done
+                work:
+                    addi a0, a0, 1
+                    ret
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_for_cycles(60);
+
+        // the jump to done leaves main without a call, so it replaces its frame
+        assert_eq!(profiler.inclusive("main").instructions, 7);
+        assert_eq!(profiler.inclusive("main;work").instructions, 4);
+        let functions = profiler.functions();
+        let work = functions.iter().find(|(name, _)| name == "work").unwrap().1;
+        assert_eq!(work.instructions, 4);
+        // the pipeline is flushed by the taken calls, so their targets wait before retiring
+        assert!(work.stall_cycles > 0 && work.cycles == work.instructions + work.stall_cycles);
+        assert!(functions.iter().map(|(_, counts)| counts.cycles).sum::<u64>() <= 60);
+
+        let folded = profiler.folded();
+        assert!(folded.lines().any(|line| line == format!("main;work {}", work.cycles)));
+        assert!(folded.lines().any(|line| line.starts_with("done ")));
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_memory_guard() {
+        use crate::risc_soc::memory_guard::MemoryGuard;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::risc_soc::sim_error::SimErrorKind;
+
+        // recursion 2 levels deeper than the 64 bytes of stack allow, each frame takes 16 bytes
+        let program = |depth: u32| {
+            format!(
+                "
+                main:
+                    li sp, 0x80011000
+                    li a0, {depth}
+                    call recurse
+                done: j done
+                recurse:
+                    addi sp, sp, -16
+                    sw ra, 12(sp)
+                    beqz a0, base
+                    addi a0, a0, -1
+                    call recurse
+                base:
+                    lw ra, 12(sp)
+                    addi sp, sp, 16
+                    ret
+                "
+            )
+        };
+        let run = |depth: u32| {
+            let mut rv32i_core = super::init_hart(None);
+            rv32i_core.load_assembly(&program(depth), 0x8000_0000).unwrap();
+            rv32i_core.set_reset_vector(0x8000_0000);
+            rv32i_core.enable_memory_guard(MemoryGuard::default().with_stack(0x8001_1000, 64, 16).with_heap(0x8001_0000, 0x8001_0800, 16));
+            rv32i_core.run_sequential_with(RunControl::cycles(500))
+        };
+        assert_eq!(run(3), StopReason::CycleLimit);
+        let StopReason::Error(error) = run(5) else {
+            panic!("the stack overflow was not detected");
+        };
+        // the fifth frame is in the guard, the access is its save of ra
+        let SimErrorKind::GuardViolation { region, address, write, backtrace } = &error.kind else {
+            panic!("{error}");
+        };
+        assert_eq!((region.as_str(), *address, *write, error.pc), ("stack overflow", 0x8001_0FBC, true, Some(0x8000_0014)));
+        assert_eq!(backtrace[0], "0x80000014 recurse+0x4");
+        assert_eq!(backtrace[1..5], ["0x80000020 recurse+0x10"; 4]);
+        assert_eq!(backtrace[5..], ["0x80000008 main+0x8"]);
+        assert!(error.to_string().starts_with("store @80010FBC in the stack overflow guard, backtrace: 0x80000014 recurse+0x4 <- "));
+    }
+
+    #[test]
+    fn test_backtraces() {
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::run_control::RunControl;
+
+        let mut rv32i_core = super::init_hart(None);
+        rv32i_core
+            .load_assembly(
+                "
+                main:
+                    call outer
+                done: j done
+                outer:
+                    mv s0, ra
+                    call inner
+                    mv ra, s0
+                    ret
+                inner:
+                    nop
+                    ebreak
+                    ret
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        let backtraces = rv32i_core.enable_backtraces();
+        rv32i_core.run_sequential_with(RunControl::cycles(50));
+
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.pc), (Exception::Breakpoint, 0x8000_001C));
+        assert!(trap.to_string().ends_with("pc=0x8000001C"));
+        assert_eq!(backtraces.last().unwrap(), ["0x8000001C inner+0x4", "0x8000000C outer+0x4", "0x80000000 main"]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_shadow_memory() {
+        use crate::risc_soc::run_control::RunControl;
+        use crate::risc_soc::shadow_memory::ShadowMemory;
+
+        let mut rv32i_core = super::init_hart(None);
+        let shadow = rv32i_core.enable_shadow_memory(ShadowMemory::default().with_range(0x8001_0000, 0x8002_0000));
+        rv32i_core
+            .load_assembly(
+                "
+                main:
+                    li sp, 0x80011000
+                    addi sp, sp, -16
+                    sw zero, 0(sp)
+                    lw a0, 0(sp)
+                    lw a1, 4(sp)
+                    li t0, 3
+                loop:
+                    lh a2, 10(sp)
+                    addi t0, t0, -1
+                    bnez t0, loop
+                    li a3, 0x80010000
+                    lw a4, 0x100(a3)
+                done: j done
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        // data of the image, only half of the word read by the program
+        rv32i_core.init_memory(0x8001_0100, &[1, 2]);
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_sequential_with(RunControl::cycles(100));
+
+        let reads: Vec<_> = shadow.reads().into_iter().map(|read| (read.pc, read.address, read.bytes, read.count)).collect();
+        assert_eq!(reads, [
+            (0x8000_0010, 0x8001_0FF4, vec![0x8001_0FF4, 0x8001_0FF5, 0x8001_0FF6, 0x8001_0FF7], 1),
+            (0x8000_0018, 0x8001_0FFA, vec![0x8001_0FFA, 0x8001_0FFB], 3),
+            (0x8000_0028, 0x8001_0100, vec![0x8001_0102, 0x8001_0103], 1),
+        ]);
+        assert!(shadow.uninitialized(0x8001_0FF0, 4).is_empty());
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_heap_tracker() {
+        use crate::risc_soc::heap_tracker::{HeapFunction, HeapTracker};
+        use crate::risc_soc::run_control::RunControl;
+
+        // bump allocator behind wrappers tail calling the reentrant functions, as in newlib
+        let mut rv32i_core = super::init_hart(None);
+        rv32i_core
+            .load_assembly(
+                "
+                main:
+                    li s11, 0x80010100
+                    li a0, 16
+                    call malloc
+                    mv s0, a0
+                    li a0, 32
+                    call malloc
+                    mv a0, s0               # the result is overwritten by the first instruction after the return
+                    call free
+                    mv a0, s0
+                    call free
+                done: j done
+                malloc:
+                    mv a1, a0
+                    li a0, 0
+                    j _malloc_r
+                _malloc_r:
+                    mv a0, s11
+                    add s11, s11, a1
+                    ret
+                free:
+                    mv a1, a0
+                    li a0, 0
+                    j _free_r
+                _free_r:
+                    ret
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        let tracker = HeapTracker::newlib(&rv32i_core).unwrap();
+        let hooks: Vec<_> = tracker.hooks().iter().map(|hook| (hook.function, hook.first_argument)).collect();
+        assert_eq!(hooks, [(HeapFunction::Malloc, 11), (HeapFunction::Free, 11)]);
+        let tracker = rv32i_core.enable_heap_tracker(tracker);
+        rv32i_core.run_sequential_with(RunControl::cycles(200));
+
+        let stats = tracker.stats();
+        assert_eq!((stats.allocations, stats.frees, stats.invalid_frees, stats.failed_allocations), (2, 1, 1, 0));
+        assert_eq!((stats.current_bytes, stats.peak_bytes), (32, 48));
+        let leaks = tracker.live_allocations();
+        assert_eq!((leaks[0].address, leaks[0].size, leaks[0].site.as_str()), (0x8001_0110, 32, "0x80000018 main+0x18"));
+        assert!(tracker.report().ends_with("\n  32 bytes @80010110 allocated from 0x80000018 main+0x18\n"));
+    }
+
+    #[test]
+    fn test_coverage() {
+        use crate::risc_soc::symbols::Symbol;
+
+        let mut rv32i_core = super::init_hart(None);
+        let coverage = rv32i_core.enable_coverage();
+        rv32i_core
+            .load_assembly(
+                "
+                    li t0, 3
+                loop:
+                    addi t0, t0, -1
+                    bnez t0, loop
+                    j done
+                skipped:
+                    nop
+                done: j done
+                ",
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_for_cycles(40);
+
+        assert!(coverage.is_covered(0x8000_000C) && !coverage.is_covered(0x8000_0010));
+        assert_eq!(coverage.executed()[&0x8000_0004], 3);
+        // both directions of the branch were taken
+        let edges = coverage.edges();
+        assert_eq!(edges[&(0x8000_0008, 0x8000_0004)], 2);
+        assert_eq!(edges[&(0x8000_0008, 0x8000_000C)], 1);
+        assert_eq!(edges[&(0x8000_000C, 0x8000_0014)], 1);
+        assert!(!edges.contains_key(&(0x8000_0000, 0x8000_0004)));
+        assert!(coverage.addresses().starts_with("0x80000000\n0x80000004\n"));
+
+        rv32i_core.symbols.insert(Symbol { name: "program".to_string(), address: 0x8000_0000, size: 0x18 });
+        let functions = coverage.functions(&rv32i_core.symbols);
+        let program = functions.iter().find(|function| function.name == "program").unwrap();
+        assert_eq!((program.covered, program.instructions), (5, 6));
+        let lcov = coverage.lcov(&rv32i_core.symbols, "program.elf");
+        assert!(lcov.contains("DA:2147483664,0\n") && lcov.contains("LF:6\nLH:5\n"));
+    }
+
+    #[test]
+    fn test_fuzz_harness() {
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::fuzz::{FuzzHarness, FuzzOutcome};
+        use crate::risc_soc::risc_soc::ExitStatus;
+
+        // a parser crashing on inputs starting with "B!", and looping forever on inputs starting with "L"
+        let harness = FuzzHarness::new(
+            || {
+                let mut rv32i_core = super::init_hart(None);
+                rv32i_core
+                    .load_assembly(
+                        "
+                            beqz a0, pass
+                            lbu t2, 0(a1)
+                            li t1, 76           # 'L'
+                        hang:
+                            beq t2, t1, hang
+                            li t1, 2
+                            blt a0, t1, pass
+                            li t1, 66           # 'B'
+                            bne t2, t1, pass
+                            lbu t2, 1(a1)
+                            li t1, 33           # '!'
+                            bne t2, t1, pass
+                            ebreak
+                        pass:
+                            li a0, 0
+                            li a7, 93
+                            ecall
+                        ",
+                        0x8000_0000,
+                    )
+                    .unwrap();
+                rv32i_core.set_reset_vector(0x8000_0000);
+                rv32i_core
+            },
+            0x8000_1000,
+            64,
+            500,
+        );
+
+        assert_eq!(harness.run_with_input(b""), FuzzOutcome::Exited(ExitStatus::Pass));
+        assert_eq!(harness.run_with_input(b"B?"), FuzzOutcome::Exited(ExitStatus::Pass));
+        assert_eq!(harness.run_with_input(b"Loop"), FuzzOutcome::CycleLimit);
+        let outcome = harness.run_with_input(b"B!");
+        assert!(outcome.is_crash());
+        assert!(matches!(outcome, FuzzOutcome::Crashed(trap) if trap.exception == Exception::Breakpoint));
+        // every input starts from the same state, whatever ran before
+        assert_eq!(harness.run_with_input(b"B?"), FuzzOutcome::Exited(ExitStatus::Pass));
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_energy_model() {
+        use crate::risc_soc::asm::assemble;
+        use crate::risc_soc::energy::{EnergyWeights, InstructionClass, INSTRUCTION_CLASSES};
+
+        let weights = EnergyWeights::from_json(r#"{ "instructions": { "alu": 2, "store": 3 }, "dram_access": 200 }"#).unwrap();
+        let defaults = EnergyWeights::default();
+        assert_eq!(weights.instructions[InstructionClass::Alu as usize], 2.0);
+        assert_eq!(weights.instructions[InstructionClass::Load as usize], defaults.instructions[InstructionClass::Load as usize]);
+        assert_eq!((weights.dram_access, weights.cache_access), (200.0, defaults.cache_access));
+        assert!(EnergyWeights::from_json(r#"{ "instructions": { "float": 1 } }"#).is_err());
+        assert!(EnergyWeights::from_json(r#"{ "cycle": "fast" }"#).is_err());
+        assert!(EnergyWeights::from_json("[1, 2]").is_err());
+
+        let program = "mul a0, a1, a2\ndivu a0, a1, a2\nl: beq a0, a1, l\nret\namoadd.w a0, a1, (a2)\ncsrr a0, mstatus\nlui a0, 1";
+        let classes: Vec<_> = assemble(program, 0).unwrap().words.into_iter().map(InstructionClass::of).collect();
+        assert_eq!(
+            classes,
+            [
+                InstructionClass::Multiply,
+                InstructionClass::Divide,
+                InstructionClass::Branch,
+                InstructionClass::Jump,
+                InstructionClass::Atomic,
+                InstructionClass::System,
+                InstructionClass::Alu,
+            ]
+        );
+
+        let mut rv32i_core = super::init_core(None);
+        let model = rv32i_core.enable_energy_model(weights.clone());
+        rv32i_core
+            .load_assembly(
+                &format!(
+                    "
+                        li a0, 42
+                        la t0, value
+                        sw a0, 0(t0)
+                        lw a1, 0(t0)
+                        li t1, 0x{:X}
+                        sw a0, 0(t1)
+                    done: j done
+                        nop
+                        nop
+                        nop
+                    value: .word 0
+                    ",
+                    super::DRAM_ADDRESS
+                ),
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        rv32i_core.run_for_cycles(60);
+
+        let stats = model.stats();
+        assert_eq!(stats.instructions[InstructionClass::Store as usize], 2);
+        assert_eq!(stats.instructions[InstructionClass::Load as usize], 1);
+        assert_eq!((stats.cache_accesses, stats.dram_accesses, stats.device_accesses), (2, 1, 0));
+        assert_eq!(stats.cycles, 60);
+        assert_eq!(stats.memory_energy, 2.0 * weights.cache_access + weights.dram_access);
+        assert_eq!(stats.static_energy, 60.0 * weights.cycle);
+        let instruction_energy: f64 = (0..INSTRUCTION_CLASSES).map(|class| stats.instructions[class] as f64 * weights.instructions[class]).sum();
+        assert_eq!(stats.instruction_energy, instruction_energy);
+        assert_eq!(stats.total(), stats.instruction_energy + stats.memory_energy + stats.static_energy);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_reset() {
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+        use crate::risc_soc::risc_soc::ExitStatus;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::rv32i_baremetal::clint::CLINT_MTIME;
+
+        // count the boots in DRAM, which a warm reset keeps, and request a reset until the second boot
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core
+            .load_assembly(
+                &format!(
+                    "
+                        li t0, 0x{:X}
+                        lw a0, 0(t0)
+                        addi a0, a0, 1
+                        sw a0, 0(t0)
+                        li t1, 0x{:X}
+                        li t2, 2
+                        blt a0, t2, reboot
+                        li t3, 0x5555
+                        sw t3, 0(t1)
+                    done: j done
+                    reboot:
+                        li t3, 0x7777
+                        sw t3, 0(t1)
+                        j done
+                    ",
+                    super::DRAM_ADDRESS,
+                    super::TEST_FINISHER_ADDRESS
+                ),
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        assert!(!rv32i_core.reboot());
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(100)), StopReason::Halted);
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Reset));
+        assert!(rv32i_core.reboot());
+        assert_eq!((rv32i_core.get_pc(), rv32i_core.read_reg_by_name("a0"), rv32i_core.exit_status()), (0x8000_0000, 0, None));
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(100)), StopReason::Halted);
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Pass));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 2);
+        assert!(!rv32i_core.reboot());
+
+        // a cold reset also clears the memories and the registers of the devices
+        rv32i_core.reset(true);
+        assert_eq!(rv32i_core.exit_status(), None);
+        assert_eq!(rv32i_core.read_mem(0x8000_0000, 4).unwrap(), [0; 4]);
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(super::DRAM_ADDRESS)).as_u32(), 0);
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(super::CLINT_ADDRESS + CLINT_MTIME)).as_u32(), 0);
+    }
+
+    #[test]
+    fn test_stage_bubbles() {
+        use crate::risc_soc::observer::SimulationObserver;
+        use crate::risc_soc::risc_soc::RiscCore;
+        use crate::rv32i_baremetal::decode::{id_ex_bubble, NOP};
+        use std::sync::{Arc, Mutex};
+
+        #[derive(Default)]
+        struct RetiredInstructions(Mutex<Vec<u32>>);
+
+        impl SimulationObserver for RetiredInstructions {
+            fn on_instruction_retired(&self, _core: &RiscCore, _pc: RiscWord, instruction: u32) {
+                self.0.lock().unwrap().push(instruction);
+            }
+        }
+
+        let mut rv32i_core = super::init_core(None);
+        let bubble = id_ex_bubble();
+        let registers = |core: &RiscCore, stage: usize| {
+            let stage = core.stages[stage].lock().unwrap();
+            (stage.data_in.0.clone(), stage.data_out.0.clone())
+        };
+        // EX sees the bubble of ID before ID produced anything
+        assert_eq!(registers(&rv32i_core, super::ID_STAGE).1, bubble.0);
+        assert_eq!(registers(&rv32i_core, super::EX_STAGE).0, bubble.0);
+
+        // beq x0, x0, 8; addi a0, x0, 1; addi a1, x0, 2; j .
+        load_program(&mut rv32i_core, &[0x00000463, 0x00100513, 0x00200593, 0x0000006F]);
+        let observer = Arc::new(RetiredInstructions::default());
+        rv32i_core.add_observer(observer.clone());
+        rv32i_core.run_sequential(Some(20));
+        // the instruction in the shadow of the branch was flushed into a NOP that never retires
+        assert_eq!((rv32i_core.read_reg_by_name("a0"), rv32i_core.read_reg_by_name("a1")), (0, 2));
+        let retired = observer.0.lock().unwrap().clone();
+        assert!(!retired.contains(&NOP));
+        assert_eq!(&retired[..2], [0x00000463, 0x00200593]);
+
+        rv32i_core.reset(false);
+        assert_eq!(registers(&rv32i_core, super::ID_STAGE).1, bubble.0);
+        assert_eq!(registers(&rv32i_core, super::EX_STAGE).0, bubble.0);
+    }
+
+    #[test]
+    fn test_stage_handshake() {
+        use crate::risc_soc::pipeline_stage::{PipelineData, PipelineStage, PipelineStageInterface};
+        use crate::risc_soc::risc_soc::RiscCore;
+        use crossbeam_channel::bounded;
+        use std::sync::Mutex;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static BUSY_CYCLES: AtomicU64 = AtomicU64::new(0);
+        static RECEIVED: Mutex<Vec<u32>> = Mutex::new(vec![]);
+
+        fn source_stage(_data_in: &PipelineData, core: &RiscCore) -> PipelineData {
+            let pc: RiscWord = core.get_pc();
+            PipelineData((pc as u32 / 4).to_le_bytes().to_vec())
+        }
+        // spends three cycles on every input, sending bubbles meanwhile
+        fn slow_stage(data_in: &PipelineData, core: &RiscCore) -> PipelineData {
+            let done = BUSY_CYCLES.fetch_add(1, Ordering::SeqCst) + 1 == 3;
+            if done {
+                BUSY_CYCLES.store(0, Ordering::SeqCst);
+            }
+            core.set_stage_ready(1, done);
+            if done { data_in.clone() } else { PipelineData(vec![0u8; 4]) }
+        }
+        fn sink_stage(data_in: &PipelineData, _core: &RiscCore) -> PipelineData {
+            let value = data_in.get_u32(0);
+            if value != 0 {
+                RECEIVED.lock().unwrap().push(value);
+            }
+            PipelineData(vec![])
+        }
+        let build = || {
+            let mut core = RiscCore::new(3, None, false);
+            core.set_pc(0x1000);
+            let (source_sender, slow_receiver) = bounded(1);
+            let (slow_sender, sink_receiver) = bounded(1);
+            core.add_stage(PipelineStage::new("SRC".to_string(), 0, 0, 4, source_stage, None, Some(source_sender)));
+            core.add_stage(PipelineStage::new("SLOW".to_string(), 1, 4, 4, slow_stage, Some(slow_receiver), Some(slow_sender)));
+            core.add_stage(PipelineStage::new("SINK".to_string(), 2, 4, 0, sink_stage, Some(sink_receiver), None));
+            BUSY_CYCLES.store(0, Ordering::SeqCst);
+            RECEIVED.lock().unwrap().clear();
+            core
+        };
+
+        // every value reaches the sink exactly once and in order, the source stalls until the slow stage takes its output
+        let expected: Vec<u32> = (0x400..0x408).collect();
+        let mut core = build();
+        core.run_sequential(Some(29));
+        assert_eq!(*RECEIVED.lock().unwrap(), expected);
+        assert_eq!(core.get_pc(), 0x1000 + 10 * 4);
+
+        let mut core = build();
+        core.run_for_cycles(30);
+        assert_eq!(*RECEIVED.lock().unwrap(), expected);
+        assert_eq!(core.get_pc(), 0x1000 + 10 * 4);
+    }
+
+    #[test]
+    fn test_stage_panic() {
+        use crate::risc_soc::pipeline_stage::{PipelineData, PipelineStage, PipelineStageInterface};
+        use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+        use crate::risc_soc::run_control::StopReason;
+        use crate::risc_soc::sim_error::SimErrorKind;
+        use crossbeam_channel::bounded;
+
+        // the source waits every cycle for a wire assigned by the sink, which panics before assigning it
+        fn source_stage(_data_in: &PipelineData, core: &RiscCore) -> PipelineData {
+            core.cdb.pull(1, 0);
+            let pc: RiscWord = core.get_pc();
+            PipelineData((pc as u32 / 4).to_le_bytes().to_vec())
+        }
+        fn sink_stage(data_in: &PipelineData, core: &RiscCore) -> PipelineData {
+            let value = data_in.get_u32(0);
+            assert!(value != 0x403, "value 0x{value:X}");
+            core.cdb.assign(1, 0, PipelineData(vec![0x1]));
+            PipelineData(vec![])
+        }
+        let build = || {
+            let mut core = RiscCore::new(2, None, false);
+            core.set_pc(0x1000);
+            let (source_sender, sink_receiver) = bounded(1);
+            core.add_stage(PipelineStage::new("SRC".to_string(), 0, 0, 4, source_stage, None, Some(source_sender)));
+            core.add_stage(PipelineStage::new("SINK".to_string(), 1, 4, 0, sink_stage, Some(sink_receiver), None));
+            core
+        };
+
+        // 0x403 leaves the source in cycle 3 and reaches the sink in cycle 4, both runs stop at the end of that cycle
+        for threaded in [false, true] {
+            let mut core = build();
+            let reason = if threaded { core.run_for_cycles(100) } else { core.run_sequential(Some(100)) };
+            let StopReason::Error(error) = reason else {
+                panic!("the run should stop on the panic of the sink, not on {reason:?}");
+            };
+            let SimErrorKind::StagePanic { stage, message, .. } = error.kind else {
+                panic!("unexpected error: {}", error.kind);
+            };
+            assert_eq!(stage, "SINK");
+            assert_eq!(message, "value 0x403");
+            assert_eq!(error.clock_cycle, 4);
+            assert_eq!(core.stages[1].lock().unwrap().clock_cycle, 5);
+        }
+    }
+
+    #[test]
+    fn test_mul_div_latencies() {
+        use crate::risc_soc::energy::InstructionClass;
+        use crate::risc_soc::risc_soc::XLEN;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::risc_soc::timing::TimingModel;
+        use crate::rv32i_baremetal::execute::multiply_divide;
+
+        // division by zero and overflow do not trap
+        let min: RiscWord = 1 << (XLEN - 1);
+        assert_eq!(multiply_divide(0b100, 7, 0), RiscWord::MAX);
+        assert_eq!(multiply_divide(0b110, 7, 0), 7);
+        assert_eq!(multiply_divide(0b100, min, RiscWord::MAX), min);
+        assert_eq!(multiply_divide(0b110, min, RiscWord::MAX), 0);
+        assert_eq!(multiply_divide(0b001, RiscWord::MAX, RiscWord::MAX), 0);
+        assert_eq!(multiply_divide(0b010, RiscWord::MAX, 2), RiscWord::MAX);
+        assert_eq!(multiply_divide(0b011, RiscWord::MAX, RiscWord::MAX), RiscWord::MAX - 1);
+
+        // li a0, 7; li a1, 3; mul a2, a0, a1; div a3, a0, a1; rem a4, a0, a1; add a5, a3, a4; j .
+        let program = [0x00700513, 0x00300593, 0x02B50633, 0x02B546B3, 0x02B56733, 0x00E687B3, 0x0000006F];
+        let run = |timing: TimingModel| {
+            let mut rv32i_core = super::init_core(None);
+            super::set_timing_model(&rv32i_core, timing);
+            load_program(&mut rv32i_core, &program);
+            let control = RunControl { cycles: Some(500), ..RunControl::until(|core| core.read_reg_by_name("a5") == 3) };
+            let reason = rv32i_core.run_sequential_with(control);
+            assert_eq!(reason, StopReason::Condition);
+            assert_eq!(rv32i_core.read_reg_by_name("a2"), 21);
+            assert_eq!(rv32i_core.read_reg_by_name("a3"), 2);
+            assert_eq!(rv32i_core.read_reg_by_name("a4"), 1);
+            rv32i_core.stages[0].lock().unwrap().clock_cycle
+        };
+
+        // every cycle spent in EX beyond the first one stalls the instructions behind it
+        let single_cycle_timing = TimingModel::default()
+            .with_timing(InstructionClass::Multiply, 1, 1)
+            .with_timing(InstructionClass::Divide, 1, 1);
+        let single_cycle = run(single_cycle_timing);
+        assert_eq!(run(TimingModel::default()), single_cycle + 2 + 2 * (XLEN as u64 - 1));
+        // a pipelined divider starts rem right after div, the add waits for the result of rem
+        let pipelined = single_cycle_timing.with_timing(InstructionClass::Divide, 5, 1);
+        assert_eq!(run(pipelined), single_cycle + 4);
+        assert_eq!(TimingModel::from_json(r#"{ "divide": { "latency": 5 }, "multiply": { "throughput": 1, "latency": 1 } }"#), Ok(pipelined.with_timing(InstructionClass::Divide, 5, XLEN as u64)));
+        assert!(TimingModel::from_json(r#"{ "vector": { "latency": 5 } }"#).is_err());
+        assert!(TimingModel::from_json(r#"{ "alu": { "latency": 0 } }"#).is_err());
+    }
+
+    #[test]
+    fn test_event_driven() {
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+        use crate::risc_soc::observer::SimulationObserver;
+        use crate::risc_soc::risc_soc::RiscCore;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::rv32i_baremetal::clint::{CLINT_MTIME, CLINT_MTIMECMP};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        #[derive(Default)]
+        struct RetiredWfi(AtomicU64);
+
+        impl SimulationObserver for RetiredWfi {
+            fn on_instruction_retired(&self, _core: &RiscCore, _pc: RiscWord, instruction: u32) {
+                if instruction == 0x10500073 {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+
+        // sleep until the timer reaches 1000, then set a1
+        let program = format!(
+            "
+                li t0, 0x{:X}
+                li t1, 1000
+                sw t1, 0(t0)
+                sw zero, 4(t0)
+            sleep:
+                wfi
+                csrr a0, mip
+                andi a0, a0, 0x80
+                beqz a0, sleep
+                li a1, 1
+            done: j done
+            ",
+            super::CLINT_ADDRESS + CLINT_MTIMECMP
+        );
+        let mut wake_up = vec![];
+        for event_driven in [false, true] {
+            let mut rv32i_core = super::init_core(None);
+            rv32i_core.load_assembly(&program, 0x8000_0000).unwrap();
+            rv32i_core.set_reset_vector(0x8000_0000);
+            let observer = Arc::new(RetiredWfi::default());
+            rv32i_core.add_observer(observer.clone());
+            let control =
+                RunControl { cycles: Some(5000), event_driven, ..RunControl::until(|core| core.read_reg_by_name("a1") == 1) };
+            assert_eq!(rv32i_core.run_sequential_with(control), StopReason::Condition);
+            let mtime = rv32i_core.data_request(MemoryRequest::read_u32(super::CLINT_ADDRESS + CLINT_MTIME)).as_u32();
+            assert!(mtime >= 1000);
+            let wfi = observer.0.load(Ordering::SeqCst);
+            // lock-step runs the loop around the WFI until the timer fires, the event-driven run sleeps through it
+            assert!(if event_driven { wfi <= 3 } else { wfi > 50 });
+            wake_up.push(rv32i_core.stages[0].lock().unwrap().clock_cycle);
+        }
+        // the cycles are still counted while sleeping, the core wakes up at about the same cycle
+        assert!(wake_up[1] >= 1000 && wake_up[1].abs_diff(wake_up[0]) <= 20);
+
+        // the cycle limits of the run are kept while sleeping
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.load_assembly(&program, 0x8000_0000).unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        assert_eq!(rv32i_core.run_event_driven(Some(500)), StopReason::CycleLimit);
+        assert_eq!(rv32i_core.stages[0].lock().unwrap().clock_cycle, 501);
+        assert_eq!(rv32i_core.read_reg_by_name("a1"), 0);
+    }
+
+    #[test]
+    fn test_benchmark() {
+        use crate::risc_soc::benchmark::measure;
+
+        // addi a0, a0, 1; j -4
+        for threaded in [false, true] {
+            let mut rv32i_core = super::init_core(None);
+            load_program(&mut rv32i_core, &[0x00150513, 0xFFDFF06F]);
+            let speed = measure(&mut rv32i_core, 200, threaded);
+            assert_eq!(speed.cycles, 200);
+            // every jump flushes the instructions fetched after it
+            assert!(speed.instructions > 50 && speed.instructions < 200);
+            // a reset restarts the cycle counters of the stages, the next measure still counts its own cycles
+            rv32i_core.reset(false);
+            assert_eq!(measure(&mut rv32i_core, 100, threaded).cycles, 100);
+        }
+    }
+
+    #[test]
+    fn test_sim_error() {
+        use crate::risc_soc::memory_management_unit::{MemoryRequest, MemoryRequestType, MemoryResponseType};
+        use crate::risc_soc::risc_soc::WordSize;
+        use crate::risc_soc::run_control::StopReason;
+        use crate::risc_soc::sim_error::SimErrorKind;
+
+        // li a0, 7; flw f0, 0(a0) (no F extension on the MCU); j .
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x00700513, 0x00052007, 0x0000006F]);
+        let StopReason::Error(error) = rv32i_core.run_sequential(Some(100)) else {
+            panic!("the run should stop on the unsupported instruction");
+        };
+        assert_eq!(error.kind, SimErrorKind::UnsupportedInstruction(0x00052007));
+        assert_eq!(error.pc, Some(0x8000_0004));
+        assert_eq!(error.clock_cycle + 1, rv32i_core.stages[0].lock().unwrap().clock_cycle);
+        assert!(rv32i_core.halted());
+        rv32i_core.reset(false);
+        assert_eq!(rv32i_core.sim_error(), None);
+
+        // a store without data is answered by the MMU without reaching the device
+        let request = MemoryRequest { request_type: MemoryRequestType::WRITE, data_address: super::DRAM_ADDRESS, data_size: WordSize::WORD, data: None };
+        let response = rv32i_core.data_request(request);
+        assert_eq!(response.status, MemoryResponseType::BadRequest("store without data".to_string()));
+        let error = rv32i_core.sim_error().unwrap();
+        assert_eq!(error.kind, SimErrorKind::BadMemoryRequest { address: super::DRAM_ADDRESS, reason: "store without data".to_string() });
+        assert_eq!(error.pc, None);
+    }
+
+    #[test]
+    fn test_isa_model() {
+        use crate::risc_soc::isa_model::{IsaModel, MicroOp, MicroOpResult, Operands};
+        use crate::risc_soc::risc_soc::RiscCore;
+        use crate::risc_soc::run_control::StopReason;
+        use crate::risc_soc::sim_error::SimErrorKind;
+        use crate::rv32i_baremetal::isa::RiscvIsa;
+
+        const OP_CUSTOM_0: u8 = 0b0001011;
+
+        /// RISC-V with a population count on the custom-0 opcode: popc rd, rs1
+        struct PopCount;
+
+        impl IsaModel for PopCount {
+            fn decode(&self, instruction: u32) -> Result<Vec<MicroOp>, SimErrorKind> {
+                if instruction & 0x7F != OP_CUSTOM_0 as u32 {
+                    return RiscvIsa.decode(instruction);
+                }
+                let (rd, rs1) = ((instruction >> 7 & 0x1F) as u8, (instruction >> 15 & 0x1F) as u8);
+                Ok(vec![MicroOp { opcode: OP_CUSTOM_0, rd, rs1, reg_write: true, uses_rs1: true, ..Default::default() }])
+            }
+
+            fn execute(&self, op: &MicroOp, operands: Operands, core: &RiscCore) -> MicroOpResult {
+                if op.opcode != OP_CUSTOM_0 {
+                    return RiscvIsa.execute(op, operands, core);
+                }
+                MicroOpResult { value: operands.rs1.count_ones() as RiscWord, target: operands.pc, ..Default::default() }
+            }
+        }
+
+        // li a1, 0xff; popc a0, a1; addi a0, a0, 1; j .
+        let program = [0x0FF00593, 0x0005850B, 0x00150513, 0x0000006F];
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &program);
+        let StopReason::Error(error) = rv32i_core.run_sequential(Some(20)) else {
+            panic!("the base ISA has no custom instruction");
+        };
+        assert_eq!(error.kind, SimErrorKind::UnsupportedInstruction(0x0005850B));
+
+        // the same pipeline runs the extension, with the result forwarded to the next instruction
+        let mut rv32i_core = super::init_core(None);
+        super::set_isa_model(&rv32i_core, PopCount);
+        load_program(&mut rv32i_core, &program);
+        assert_eq!(rv32i_core.run_sequential(Some(20)), StopReason::CycleLimit);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 9);
+    }
+
+    #[test]
+    fn test_custom_extension() {
+        use crate::risc_soc::custom_extension::{CustomExtensions, CustomOpcode, XExtension, r_type};
+        use crate::risc_soc::isa_model::MicroOpResult;
+        use crate::risc_soc::run_control::StopReason;
+        use crate::risc_soc::sim_error::SimErrorKind;
+        use crate::rv32i_baremetal::isa::RiscvIsa;
+
+        // multiply-accumulate unit on custom-0: mac rd, rs1, rs2 adds rs1 * rs2 to its accumulator and writes it to rd
+        let mac = XExtension::new(
+            "mac",
+            |instruction| (instruction >> 12 & 0x7 == 0).then(|| r_type(instruction)),
+            |_op, operands, accumulator: &mut RiscWord, _core| {
+                *accumulator = accumulator.wrapping_add(operands.rs1.wrapping_mul(operands.rs2));
+                MicroOpResult { value: *accumulator, target: operands.pc, ..Default::default() }
+            },
+        );
+        let accumulator = mac.state();
+        let mut rv32i_core = super::init_core(None);
+        super::set_isa_model(&rv32i_core, CustomExtensions::new(RiscvIsa).claim(CustomOpcode::Custom0, mac));
+
+        // li a1, 3; li a2, 4; mac a0, a1, a2; mac a0, a1, a2; j .
+        load_program(&mut rv32i_core, &[0x00300593, 0x00400613, 0x00C5850B, 0x00C5850B, 0x0000006F]);
+        assert_eq!(rv32i_core.run_sequential(Some(20)), StopReason::CycleLimit);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 24);
+        assert_eq!(*accumulator.lock().unwrap(), 24);
+        rv32i_core.reset(false);
+        assert_eq!(*accumulator.lock().unwrap(), 0);
+
+        // encodings the extension does not define and unclaimed opcode spaces are still unsupported
+        for instruction in [0x00C5950B, 0x00C5852B] {
+            rv32i_core.reset(false);
+            load_program(&mut rv32i_core, &[instruction, 0x0000006F]);
+            let StopReason::Error(error) = rv32i_core.run_sequential(Some(20)) else {
+                panic!("the instruction is not defined by any extension");
+            };
+            assert_eq!(error.kind, SimErrorKind::UnsupportedInstruction(instruction));
+        }
+    }
+
+    #[test]
+    fn test_debug_mode() {
+        use crate::risc_soc::csr::{CSR_DCSR, CSR_DPC, DCSR_EBREAKM, DCSR_STEP, DebugCause};
+        use crate::risc_soc::debug_module::*;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+
+        // access register commands of 32 bits, with the transfer bit set
+        let read_command = |regno: u32| 2 << 20 | 1 << 17 | regno;
+        let write_command = |regno: u32| 2 << 20 | 1 << 17 | 1 << 16 | regno;
+        // loop: addi a0, a0, 1; j loop
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x00150513, 0xFFDFF06F]);
+        let mut dm = DebugModule::default();
+        dm.dmi_write(&rv32i_core, DMI_DMCONTROL, DMCONTROL_DMACTIVE);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(20)), StopReason::CycleLimit);
+        assert_ne!(dm.dmi_read(&rv32i_core, DMI_DMSTATUS) & DMSTATUS_ALLRUNNING, 0);
+        // the debug CSRs are not accessible while the hart runs, nor are its registers through abstract commands
+        assert_eq!(rv32i_core.read_csr(CSR_DCSR), None);
+        dm.dmi_write(&rv32i_core, DMI_COMMAND, read_command(0x100A));
+        assert_eq!(dm.dmi_read(&rv32i_core, DMI_ABSTRACTCS) >> 8 & 0b111, CommandError::HaltResume as u32);
+        dm.dmi_write(&rv32i_core, DMI_ABSTRACTCS, 0b111 << 8);
+
+        // the hart halts once the instructions before the one in ID completed, runs then stop right away until it resumes
+        dm.dmi_write(&rv32i_core, DMI_DMCONTROL, DMCONTROL_DMACTIVE | DMCONTROL_HALTREQ);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(20)), StopReason::DebugHalt);
+        assert_ne!(dm.dmi_read(&rv32i_core, DMI_DMSTATUS) & DMSTATUS_ALLHALTED, 0);
+        let debug = rv32i_core.csrs.read().unwrap().debug;
+        assert_eq!(debug.cause(), Some(DebugCause::HaltRequest));
+        assert!(debug.dpc == 0x8000_0000 || debug.dpc == 0x8000_0004);
+        let a0: RiscWord = rv32i_core.read_reg_by_name("a0");
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(20)), StopReason::DebugHalt);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), a0);
+        dm.dmi_write(&rv32i_core, DMI_COMMAND, read_command(0x100A));
+        assert_eq!(dm.dmi_read(&rv32i_core, DMI_DATA0), a0 as u32);
+        dm.dmi_write(&rv32i_core, DMI_COMMAND, read_command(CSR_DPC as u32));
+        let dpc: RiscWord = debug.dpc;
+        assert_eq!(dm.dmi_read(&rv32i_core, DMI_DATA0), dpc as u32);
+
+        // two single steps execute the addi exactly once
+        dm.dmi_write(&rv32i_core, DMI_DATA0, 100);
+        dm.dmi_write(&rv32i_core, DMI_COMMAND, write_command(0x100A));
+        let step: RiscWord = DCSR_STEP;
+        dm.dmi_write(&rv32i_core, DMI_DATA0, step as u32);
+        dm.dmi_write(&rv32i_core, DMI_COMMAND, write_command(CSR_DCSR as u32));
+        assert_eq!(dm.dmi_read(&rv32i_core, DMI_ABSTRACTCS) >> 8 & 0b111, CommandError::None as u32);
+        for _ in 0..2 {
+            dm.dmi_write(&rv32i_core, DMI_DMCONTROL, DMCONTROL_DMACTIVE | DMCONTROL_RESUMEREQ);
+            assert_ne!(dm.dmi_read(&rv32i_core, DMI_DMSTATUS) & DMSTATUS_ALLRESUMEACK, 0);
+            assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(20)), StopReason::DebugHalt);
+            assert_eq!(rv32i_core.csrs.read().unwrap().debug.cause(), Some(DebugCause::Step));
+        }
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 101);
+        assert_eq!(rv32i_core.csrs.read().unwrap().debug.dpc, debug.dpc);
+
+        // memory is reached through the system bus registers
+        dm.dmi_write(&rv32i_core, DMI_SBADDRESS0, super::DRAM_ADDRESS as u32);
+        dm.dmi_write(&rv32i_core, DMI_SBDATA0, 0xDEAD_BEEF);
+        assert_eq!(rv32i_core.read_mem(super::DRAM_ADDRESS, 4).unwrap(), 0xDEAD_BEEFu32.to_le_bytes());
+        dm.dmi_write(&rv32i_core, DMI_SBCS, 1 << 20);
+        dm.dmi_write(&rv32i_core, DMI_SBADDRESS0, 0x8000_0000);
+        assert_eq!(dm.dmi_read(&rv32i_core, DMI_SBDATA0), 0x00150513);
+
+        // without single stepping the hart runs on from dpc
+        dm.dmi_write(&rv32i_core, DMI_DATA0, 0);
+        dm.dmi_write(&rv32i_core, DMI_COMMAND, write_command(CSR_DCSR as u32));
+        dm.dmi_write(&rv32i_core, DMI_DMCONTROL, DMCONTROL_DMACTIVE | DMCONTROL_RESUMEREQ);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(20)), StopReason::CycleLimit);
+        assert!(rv32i_core.read_reg_by_name("a0") > 101);
+
+        // with ebreakm set an EBREAK enters Debug Mode instead of trapping, with dpc pointing to it
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x00150513, 0x00100073, 0x0000006F]);
+        rv32i_core.csrs.write().unwrap().debug.dcsr |= DCSR_EBREAKM;
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(20)), StopReason::DebugHalt);
+        let debug = rv32i_core.csrs.read().unwrap().debug;
+        assert_eq!((debug.cause(), debug.dpc), (Some(DebugCause::Ebreak), 0x8000_0004));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 1);
+        assert!(rv32i_core.pending_trap().is_none());
+    }
+
+    #[test]
+    fn test_triggers() {
+        use crate::risc_soc::csr::DebugCause;
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::risc_soc::trigger::*;
+
+        // nop; csrw tdata2, a1; li t0, 0x42; csrw tdata1, t0; li a0, 1; sw a0, 0(a1); lw a2, 0(a1); addi a0, a0, 1; j .
+        // with a1 holding the start of the DRAM
+        let program = [0x00000013, 0x7A259073, 0x04200293, 0x7A129073, 0x00100513, 0x00A5A023, 0x0005A603, 0x00150513, 0x0000006F];
+        // the program sets a breakpoint on stores to the start of the DRAM, the store traps before writing it
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &program);
+        rv32i_core.write_reg_by_name("a1", super::DRAM_ADDRESS as RiscWord);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(50)), StopReason::Halted);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.tval), (Exception::Breakpoint, super::DRAM_ADDRESS));
+        assert_eq!(rv32i_core.read_mem(super::DRAM_ADDRESS, 4).unwrap(), vec![0; 4]);
+        assert_ne!(rv32i_core.read_csr(CSR_TDATA1).unwrap() & MCONTROL_HIT, 0);
+        // tselect keeps its value when selecting a trigger that does not exist
+        rv32i_core.write_csr(CSR_TSELECT, NUM_TRIGGERS as RiscWord);
+        assert_eq!(rv32i_core.read_csr(CSR_TSELECT), Some(0));
+
+        // the debugger sets an execute trigger entering Debug Mode on the addi, which the program can no longer change
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &program);
+        rv32i_core.write_reg_by_name("a1", super::DRAM_ADDRESS as RiscWord);
+        rv32i_core.request_halt();
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(50)), StopReason::DebugHalt);
+        assert_eq!(rv32i_core.csrs.read().unwrap().debug.dpc, 0x8000_0000);
+        let tdata1 = MCONTROL_DMODE | 1 << 12 | MCONTROL_M | MCONTROL_EXECUTE;
+        assert_eq!(rv32i_core.write_csr(CSR_TDATA1, tdata1), Some(()));
+        assert_eq!(rv32i_core.write_csr(CSR_TDATA2, 0x8000_001C), Some(()));
+        assert!(rv32i_core.resume_from_debug_mode());
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(50)), StopReason::DebugHalt);
+        let debug = rv32i_core.csrs.read().unwrap().debug;
+        assert_eq!((debug.cause(), debug.dpc), (Some(DebugCause::Trigger), 0x8000_001C));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 1);
+        assert_eq!(rv32i_core.read_reg_by_name("a2"), 1);
+        assert_eq!(rv32i_core.read_csr(CSR_TDATA1), Some(2 << (super::XLEN - 4) | tdata1 | MCONTROL_HIT));
+        assert_eq!(rv32i_core.read_csr(CSR_TDATA2), Some(0x8000_001C));
+    }
+
+    #[test]
+    fn test_vector() {
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::risc_soc::vector::{CSR_VL, CSR_VLENB, CSR_VTYPE};
+
+        // with a1 holding the start of the DRAM: li a2, 3; addi a3, a1, 16; li a6, 100; vsetivli t0, 4, e32, m1; vle32.v v1, (a1);
+        // vadd.vi v2, v1, 5; vmul.vx v3, v1, a2; vse32.v v3, (a3); vmv.x.s a4, v2; csrr a5, vl; vsetvli t1, a6, e8, m1; j .
+        let program = [
+            0x00300613, 0x01058693, 0x06400813, 0xC10272D7, 0x0205E087, 0x0212B157, 0x961661D7, 0x0206E1A7, 0x42202757,
+            0xC20027F3, 0x00087357, 0x0000006F,
+        ];
+        let elements: Vec<u8> = [1u32, 2, 3, 4].iter().flat_map(|element| element.to_le_bytes()).collect();
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.enable_vector(128);
+        assert!(rv32i_core.csrs.read().unwrap().info.has_extension("Zve32x"));
+        load_program(&mut rv32i_core, &program);
+        rv32i_core.write_mem(super::DRAM_ADDRESS, &elements).unwrap();
+        rv32i_core.write_reg_by_name("a1", super::DRAM_ADDRESS as RiscWord);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(60)), StopReason::CycleLimit);
+        let products: Vec<u8> = [3u32, 6, 9, 12].iter().flat_map(|element| element.to_le_bytes()).collect();
+        assert_eq!(rv32i_core.read_mem(super::DRAM_ADDRESS + 16, 16).unwrap(), products);
+        let registers = ["t0", "a4", "a5", "t1"].map(|register| rv32i_core.read_reg_by_name(register));
+        // 16 elements of 8 bits fit in a register of 128 bits
+        assert_eq!(registers, [4, 6, 4, 16]);
+        let vector = rv32i_core.vector.as_ref().unwrap().read().unwrap();
+        assert_eq!((0..4).map(|index| vector.element(2, index, 4)).collect::<Vec<_>>(), vec![6, 7, 8, 9]);
+        drop(vector);
+        assert_eq!(rv32i_core.read_csr(CSR_VLENB), Some(16));
+        assert_eq!(rv32i_core.read_csr(CSR_VTYPE), Some(0));
+        // vl is only changed by the vsetvl instructions
+        assert_eq!(rv32i_core.write_csr(CSR_VL, 1), None);
+
+        // without a vector register file the first vector instruction is illegal
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &program);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(60)), StopReason::Halted);
+        assert_eq!(rv32i_core.take_trap().unwrap().exception, Exception::IllegalInstruction);
+        assert_eq!(rv32i_core.read_reg_by_name("t0"), 0);
+    }
+
+    #[test]
+    fn test_bit_manipulation() {
+        use crate::risc_soc::csr::MachineInfo;
+        use crate::risc_soc::exception::Exception;
+        use crate::risc_soc::isa_model::MicroOp;
+        use crate::risc_soc::risc_soc::XLEN;
+        use crate::risc_soc::run_control::StopReason;
+        use crate::rv32i_baremetal::bitmanip::bit_manipulation;
+        use crate::rv32i_baremetal::decode::decode_instruction;
+
+        let execute = |instruction: u32, rs1: RiscWord, rs2: RiscWord| {
+            let op: MicroOp = decode_instruction(instruction).unwrap();
+            bit_manipulation(&op, rs1, rs2)
+        };
+        // add, sub and sra share their FUNCT7 or FUNCT3 with bit manipulation instructions
+        assert_eq!(execute(0x00B50633, 1, 2), None);
+        assert_eq!(execute(0x40B50633, 1, 2), None);
+        assert_eq!(execute(0x40B55633, 1, 2), None);
+        // clz, ctz, rol, ror, rev8, orc.b, sext.b, bexti, binv and czero.nez
+        assert_eq!(execute(0x60051613, 0x0001_0000, 0), Some(("Zbb", XLEN as RiscWord - 17)));
+        assert_eq!(execute(0x60151613, 0, 0), Some(("Zbb", XLEN as RiscWord)));
+        assert_eq!(execute(0x60B51633, 1 << (XLEN - 1), 1), Some(("Zbb", 1)));
+        assert_eq!(execute(0x60B55633, 1, 1), Some(("Zbb", 1 << (XLEN - 1))));
+        if XLEN == 32 {
+            assert_eq!(execute(0x69855613, 0x1234_5678, 0), Some(("Zbb", 0x7856_3412)));
+        }
+        assert_eq!(execute(0x28755613, 0x0100_8000, 0), Some(("Zbb", 0xFF00_FF00)));
+        assert_eq!(execute(0x60451613, 0x80, 0), Some(("Zbb", RiscWord::MAX - 0x7F)));
+        assert_eq!(execute(0x48355613, 0b1000, 0), Some(("Zbs", 1)));
+        assert_eq!(execute(0x68B51633, 0b1000, 3), Some(("Zbs", 0)));
+        assert_eq!(execute(0x0EB57633, 5, 1), Some(("Zicond", 0)));
+
+        // li a0, 240; li a1, -3; sh2add a2, a0, a1; andn a3, a0, a1; cpop a4, a0; minu a5, a0, a1; bseti a6, a0, 0;
+        // czero.eqz a7, a0, zero; j .
+        let program = [0x0F000513, 0xFFD00593, 0x20B54633, 0x40B576B3, 0x60251713, 0x0AB557B3, 0x28051813, 0x0E0558B3, 0x0000006F];
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &program);
+        rv32i_core.write_reg_by_name("a7", 1);
+        assert_eq!(rv32i_core.run_sequential(Some(30)), StopReason::CycleLimit);
+        let registers = ["a2", "a3", "a4", "a5", "a6", "a7"].map(|register| rv32i_core.read_reg_by_name(register));
+        assert_eq!(registers, [957, 0, 4, 240, 241, 0]);
+
+        // without the B extension the first bit manipulation instruction is illegal
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.set_machine_info(MachineInfo::new(0, "IMA"));
+        load_program(&mut rv32i_core, &program);
+        assert_eq!(rv32i_core.run_sequential(Some(30)), StopReason::Halted);
+        assert_eq!(rv32i_core.take_trap().unwrap().exception, Exception::IllegalInstruction);
+        assert_eq!(rv32i_core.read_reg_by_name("a2"), 0);
+        assert!(MachineInfo::new(0, "IMAB_Zicond").has_extension("zbs"));
+        assert!(MachineInfo::new(0, "IM_Zba_Zicond").has_extension("Zicond"));
+        assert!(!MachineInfo::new(0, "IM_Zba_Zicond").has_extension("Zbb"));
+    }
+
+    #[test]
+    fn test_prefetcher() {
+        // li a0, 0; li t0, 3; nop; nop
+        // loop: addi a0, a0, 1; nop x 5; addi t0, t0, -1; bnez t0, loop
+        // j .
+        let nop = 0x00000013;
+        let program = [
+            0x00000513, 0x00300293, nop, nop, 0x00150513, nop, nop, nop, nop, nop, 0xfff28293, 0xfe0292e3, 0x0000006f,
+        ];
+        let run = |prefetcher: Option<(usize, u64)>| {
+            let mut rv32i_core = super::init_hart(None);
+            if let Some((depth, latency)) = prefetcher {
+                rv32i_core.enable_prefetcher(16, depth, latency);
+            }
+            load_program(&mut rv32i_core, &program);
+            rv32i_core.run_until(|core| core.read_reg_by_name("a0") == 3);
+            assert_eq!(rv32i_core.read_reg_by_name("a0"), 3);
+            let cycles = rv32i_core.stages[0].lock().unwrap().clock_cycle;
+            (cycles, rv32i_core.prefetch_stats())
+        };
+
+        let (ideal, _) = run(None);
+        assert_eq!(run(Some((2, 0))).0, ideal);
+
+        // without prefetching every new line waits for the whole latency
+        let (no_prefetch, stats) = run(Some((1, 4)));
+        let stats = stats.unwrap();
+        assert!(no_prefetch > ideal);
+        assert_eq!((stats.prefetches, stats.useful_prefetches, stats.coverage()), (0, 0, 0.0));
+
+        // the sequential lines arrive while the previous ones are executed, only the first line and the taken branches miss
+        let (prefetch, stats) = run(Some((2, 4)));
+        let stats = stats.unwrap();
+        assert!(prefetch > ideal && prefetch < no_prefetch);
+        assert_eq!(stats.misses, 3);
+        assert!(stats.useful_prefetches > 0 && stats.accuracy() > 0.0 && stats.coverage() > 0.0);
+        assert!(stats.stall_cycles >= 3 * 4);
+    }
+
+    #[test]
+    fn test_decode_cache() {
+        use crate::risc_soc::run_control::RunControl;
+
+        // li a0, 0; li t0, 3; loop: addi a0, a0, 1; addi t0, t0, -1; bnez t0, loop; j .
+        let program = [0x00000513, 0x00300293, 0x00150513, 0xfff28293, 0xfe029ce3, 0x0000006f];
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &program);
+        rv32i_core.run_sequential_with(RunControl::cycles(40));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 3);
+        // the loop and the final jump are decoded once, then served from the cache
+        let stats = rv32i_core.decode_cache_stats();
+        assert!(stats.hits > stats.misses && stats.hit_rate() > 0.5);
+        assert!(!rv32i_core.decode_cache.lock().unwrap().is_empty());
+
+        // stores to data pages keep the decoded instructions, stores to code pages drop them
+        rv32i_core.write_mem(super::DRAM_ADDRESS, &[0xAA; 4]).unwrap();
+        assert_eq!(rv32i_core.decode_cache_stats().invalidated_pages, 0);
+        // addi a0, a0, 16
+        rv32i_core.write_mem(0x8000_0008, &0x01050513u32.to_le_bytes()).unwrap();
+        assert_eq!(rv32i_core.decode_cache_stats().invalidated_pages, 1);
+        rv32i_core.reset(false);
+        rv32i_core.run_sequential_with(RunControl::cycles(40));
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 48);
+
+        rv32i_core.fence_i();
+        assert!(rv32i_core.decode_cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_memory_dump() {
+        use crate::risc_soc::memory_dump::{ACCESS_LOG_HEADER, hex_dump};
+        use crate::risc_soc::run_control::RunControl;
+
+        // with a1 holding the start of the DRAM: li a0, 0x41; sw a0, 0(a1); lw a2, 0(a1); j .
+        let program = [0x04100513, 0x00a5a023, 0x0005a603, 0x0000006f];
+        let mut rv32i_core = super::init_core(None);
+        let log = rv32i_core.enable_access_log();
+        load_program(&mut rv32i_core, &program);
+        rv32i_core.write_reg_by_name("a1", super::DRAM_ADDRESS as RiscWord);
+        rv32i_core.run_sequential_with(RunControl::cycles(20));
+        assert_eq!(rv32i_core.read_reg_by_name("a2"), 0x41);
+
+        let accesses: Vec<_> = log.accesses().iter().map(|access| (access.pc, access.address, access.write, access.data)).collect();
+        assert_eq!(accesses, vec![(0x8000_0004, super::DRAM_ADDRESS, true, Some(0x41)), (0x8000_0008, super::DRAM_ADDRESS, false, Some(0x41))]);
+        let csv = log.csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], ACCESS_LOG_HEADER);
+        assert!(lines[1].ends_with(",0x80000004,0x81000000,4,W,0x00000041"));
+        assert!(lines[2].ends_with(",0x80000008,0x81000000,4,R,0x00000041"));
+
+        // unmapped bytes are marked, the ASCII column only shows printable characters
+        let dump = hex_dump(&rv32i_core, &[(0x7FFF_FFFE, 4), (super::DRAM_ADDRESS, 4)]);
+        assert_eq!(
+            dump,
+            format!(
+                "# 0x7ffffffe..0x80000002\n7ffffffe: {:<47} |....|\n# 0x81000000..0x81000004\n81000000: {:<47} |A...|\n",
+                "-- -- 13 05", "41 00 00 00"
+            )
+        );
+        // the dump only depends on the content of memory
+        assert_eq!(hex_dump(&rv32i_core, &[(super::DRAM_ADDRESS, 4)]), hex_dump(&rv32i_core, &[(super::DRAM_ADDRESS, 4)]));
+
+        let path = std::env::temp_dir().join("riscv_on_rust_test.dump");
+        let path = path.to_str().unwrap();
+        rv32i_core.dump_memory(path, &[(super::DRAM_ADDRESS, 32)]).unwrap();
+        let written = std::fs::read_to_string(path).unwrap();
+        let written_csv = std::fs::read_to_string(format!("{path}.csv")).unwrap();
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(format!("{path}.csv")).unwrap();
+        assert_eq!(written.lines().count(), 3);
+        assert_eq!(written_csv, csv);
+    }
+
+    #[test]
+    fn test_describe_mmio_access() {
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+
+        let rv32i_core = super::init_core(None);
+        let describe = |request: MemoryRequest| {
+            let response = rv32i_core.data_request(request.clone());
+            rv32i_core.mmu.read().unwrap().describe_access(&request, &response)
+        };
+        // the UART Lite on UART0 and the 16550 on UART1
+        assert_eq!(describe(MemoryRequest::write_u32(0x4060_0004, 0x41)).unwrap(), "UART0.TX_FIFO <= 0x41 'A'");
+        assert_eq!(describe(MemoryRequest::write_u8(0x1000_0000, b'H')).unwrap(), "UART1.THR <= 0x48 'H'");
+        assert_eq!(describe(MemoryRequest::write_u8(0x1000_0003, 0x83)).unwrap(), "UART1.LCR <= 0x83 {WLS=3|DLAB}");
+        assert_eq!(describe(MemoryRequest::write_u8(0x1000_0000, 0x01)).unwrap(), "UART1.DLL <= 0x1");
+        assert_eq!(describe(MemoryRequest::write_u8(0x1000_0003, 0x03)).unwrap(), "UART1.LCR <= 0x3 {WLS=3}");
+        // reading LSR through the MMU would start the receiver, the read only path has no side effect
+        let mmu = rv32i_core.mmu.read().unwrap();
+        let lsr = MemoryRequest::read_u8(0x1000_0005);
+        assert_eq!(mmu.describe_access(&lsr, &mmu.peek(lsr.clone())).unwrap(), "UART1.LSR => 0x60 {THRE|TEMT}");
+        drop(mmu);
+        assert_eq!(
+            describe(MemoryRequest::write_u32(super::CLINT_ADDRESS + 0x4004, 0)).unwrap(),
+            "CLINT.MTIMECMP[0]+4 <= 0x0"
+        );
+        // memories have no registers to describe
+        assert!(describe(MemoryRequest::write_u32(super::DRAM_ADDRESS, 0)).is_none());
+    }
+
+    #[test]
+    fn test_serial_over_tcp() {
+        use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::serial::SerialConnection;
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, TcpStream};
+        use std::time::{Duration, Instant};
+
+        let rv32i_core = super::init_core(None);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || TcpStream::connect(address).unwrap());
+        let connection = SerialConnection::accept(&listener).unwrap();
+        let mut terminal = client.join().unwrap();
+        terminal.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        super::attach_serial(&rv32i_core, MemoryDeviceType::UART1, connection);
+
+        let lsr = || rv32i_core.data_request(MemoryRequest::read_u8(super::UART16550_ADDRESS + 5)).as_u8();
+        let wait_for = |condition: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !condition() {
+                assert!(Instant::now() < deadline, "the serial port did not get ready in time");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+
+        // the transmitter stays busy until the terminal took the byte
+        rv32i_core.data_request(MemoryRequest::write_u8(super::UART16550_ADDRESS, b'H'));
+        let mut byte = [0u8];
+        terminal.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'H');
+        wait_for(&|| lsr() & 0x60 == 0x60);
+
+        // more bytes than the RX FIFO holds are held back by the flow control instead of being dropped
+        let typed: Vec<u8> = (b'a'..=b'z').collect();
+        terminal.write_all(&typed).unwrap();
+        let mut received = vec![];
+        while received.len() < typed.len() {
+            wait_for(&|| lsr() & 0x01 != 0);
+            received.push(rv32i_core.data_request(MemoryRequest::read_u8(super::UART16550_ADDRESS)).as_u8());
+        }
+        assert_eq!(received, typed);
+    }
+
+    #[test]
+    fn test_network_device() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryRequest};
+        use crate::rv32i_baremetal::network::{ETHERTYPE_ARP, ETHERTYPE_IPV4, USER_GATEWAY, USER_GATEWAY_MAC, UserNetwork, internet_checksum};
+        use crate::rv32i_baremetal::nic::*;
+        use std::net::UdpSocket;
+        use std::time::{Duration, Instant};
+
+        let mut rv32i_core = super::init_core(None);
+        super::add_network_device(&rv32i_core, Box::new(UserNetwork::new()));
+        let host = UdpSocket::bind("127.0.0.1:0").unwrap();
+        host.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let host_port = host.local_addr().unwrap().port();
+
+        let guest_ip = [10, 0, 2, 15];
+        let mut arp = USER_GATEWAY_MAC.to_vec();
+        arp.extend_from_slice(&NIC_DEFAULT_MAC);
+        arp.extend_from_slice(&ETHERTYPE_ARP.to_be_bytes());
+        arp.extend_from_slice(&[0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+        arp.extend_from_slice(&NIC_DEFAULT_MAC);
+        arp.extend_from_slice(&guest_ip);
+        arp.extend_from_slice(&[0; 6]);
+        arp.extend_from_slice(&USER_GATEWAY.octets());
+
+        let mut ip = vec![0x45, 0, 0, 32, 0, 0, 0x40, 0, 64, 17, 0, 0];
+        ip.extend_from_slice(&guest_ip);
+        ip.extend_from_slice(&USER_GATEWAY.octets());
+        let checksum = internet_checksum(&ip);
+        ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+        let mut udp = USER_GATEWAY_MAC.to_vec();
+        udp.extend_from_slice(&NIC_DEFAULT_MAC);
+        udp.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        udp.extend_from_slice(&ip);
+        udp.extend_from_slice(&5000u16.to_be_bytes());
+        udp.extend_from_slice(&host_port.to_be_bytes());
+        udp.extend_from_slice(&[0, 12, 0, 0]);
+        udp.extend_from_slice(b"ping");
+
+        // rings of 4 descriptors, the frames to send and the receive buffers in the shared DRAM
+        let (tx_ring, rx_ring) = (super::DRAM_ADDRESS, super::DRAM_ADDRESS + 0x40);
+        let (arp_buffer, udp_buffer) = (super::DRAM_ADDRESS + 0x100, super::DRAM_ADDRESS + 0x200);
+        let rx_buffers = [super::DRAM_ADDRESS + 0x800, super::DRAM_ADDRESS + 0x1000];
+        let descriptor = |address: Address, len: u16| {
+            let mut bytes = (address as u32).to_le_bytes().to_vec();
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(&[0, 0]);
+            bytes
+        };
+        rv32i_core.init_memory(arp_buffer, &arp);
+        rv32i_core.init_memory(udp_buffer, &udp);
+        rv32i_core.init_memory(tx_ring, &[descriptor(arp_buffer, arp.len() as u16), descriptor(udp_buffer, udp.len() as u16)].concat());
+        rv32i_core.init_memory(rx_ring, &[descriptor(rx_buffers[0], 1514), descriptor(rx_buffers[1], 1514)].concat());
+
+        let read = |offset: Address| rv32i_core.data_request(MemoryRequest::read_u32(super::NIC_ADDRESS + offset)).as_u32();
+        let write = |offset: Address, value: u32| {
+            rv32i_core.data_request(MemoryRequest::write_u32(super::NIC_ADDRESS + offset, value));
+        };
+        assert_eq!(read(NIC_ID), NIC_MAGIC);
+        assert_ne!(read(NIC_STATUS) & NIC_STATUS_LINK_UP, 0);
+        write(NIC_TX_RING_BASE, tx_ring as u32);
+        write(NIC_TX_RING_SIZE, 4);
+        write(NIC_RX_RING_BASE, rx_ring as u32);
+        write(NIC_RX_RING_SIZE, 4);
+        write(NIC_RX_HEAD, 2);
+        write(NIC_CONTROL, NIC_CONTROL_RX_ENABLE | NIC_CONTROL_TX_ENABLE | NIC_CONTROL_RX_IRQ_ENABLE);
+        write(NIC_TX_HEAD, 2);
+
+        // the datagram sent to the gateway reaches the host loopback, the answer comes back to the guest port
+        let mut buffer = [0u8; 16];
+        let (len, guest) = host.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"ping");
+        host.send_to(b"pong", guest).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while read(NIC_RX_PACKETS) < 2 {
+            assert!(Instant::now() < deadline, "the NIC did not receive the replies in time");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(read(NIC_TX_PACKETS), 2);
+        assert_eq!(read(NIC_TX_TAIL), 2);
+        assert_eq!(read(NIC_RX_TAIL), 2);
+        assert_eq!(read(NIC_STATUS) & NIC_STATUS_RX_DONE, NIC_STATUS_RX_DONE);
+        assert!(rv32i_core.mmu.read().unwrap().pending_irqs().contains(&crate::risc_soc::memory_management_unit::MemoryDeviceType::NET0.into()));
+
+        let received = |index: usize| {
+            let completion = rv32i_core.data_request(MemoryRequest::read_u32(rx_ring + 8 * index as Address + 4)).as_u32();
+            assert_eq!(completion >> 16, NIC_DESCRIPTOR_DONE as u32);
+            (0..completion & 0xFFFF)
+                .map(|offset| rv32i_core.data_request(MemoryRequest::read_u8(rx_buffers[index] + offset as Address)).as_u8())
+                .collect::<Vec<u8>>()
+        };
+        let arp_reply = received(0);
+        assert_eq!(arp_reply[12..14], ETHERTYPE_ARP.to_be_bytes());
+        assert_eq!(arp_reply[20..22], [0, 2]);
+        assert_eq!(arp_reply[22..28], USER_GATEWAY_MAC);
+        assert_eq!(arp_reply[28..32], USER_GATEWAY.octets());
+        let udp_reply = received(1);
+        assert_eq!(udp_reply[0..6], NIC_DEFAULT_MAC);
+        assert_eq!(udp_reply[26..30], USER_GATEWAY.octets());
+        assert_eq!(udp_reply[30..34], guest_ip);
+        assert_eq!(internet_checksum(&udp_reply[14..34]), 0);
+        assert_eq!(udp_reply[34..36], host_port.to_be_bytes());
+        assert_eq!(udp_reply[36..38], 5000u16.to_be_bytes());
+        assert_eq!(&udp_reply[42..], b"pong");
+    }
+
+    #[test]
+    fn test_flash() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryDevice, MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::flash::{
+            FLASH_BLOCK_SIZE, FLASH_CMD_BLOCK_ERASE, FLASH_CMD_CLEAR_STATUS, FLASH_CMD_CONFIRM, FLASH_CMD_READ_ARRAY,
+            FLASH_CMD_WORD_PROGRAM, FLASH_STATUS_ERASE_ERROR, FLASH_STATUS_PROTECTED, FLASH_STATUS_READY, Flash,
+        };
+
+        // li a0, 1; j ., executed in place from the flash of the platform
+        // followed by NOPs so the erased words after it are not fetched
+        let mut rv32i_core = super::init_core(None);
+        let program: Vec<u8> = [0x00100513u32, 0x0000006f, 0x00000013, 0x00000013, 0x00000013, 0x00000013].iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        rv32i_core.init_memory(super::FLASH_ADDRESS, &program);
+        rv32i_core.set_reset_vector(super::FLASH_ADDRESS as RiscWord);
+        rv32i_core.run_for_cycles(20);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 1);
+
+        // the first block holds the bootloader and is write protected
+        let start = 0x2000_0000;
+        let mut flash = Flash::new(MemoryDeviceType::FLASH, start, start + 2 * FLASH_BLOCK_SIZE as Address)
+            .with_write_protect(start, start + FLASH_BLOCK_SIZE as Address)
+            .with_latency(10, 1000);
+        let image = start + FLASH_BLOCK_SIZE as Address;
+        let read = |flash: &Flash, address| flash.read_request(MemoryRequest::read_u32(address)).as_u32();
+
+        // programming clears bits, the flash then answers with its status until read array mode is restored
+        let program = MemoryRequest::write_u32(image, 0x1234_5678);
+        flash.send_data_request(MemoryRequest::write_u8(image, FLASH_CMD_WORD_PROGRAM));
+        assert_eq!(flash.access_latency(&program), 10);
+        flash.send_data_request(program);
+        assert_eq!(read(&flash, image), FLASH_STATUS_READY as u32);
+        flash.send_data_request(MemoryRequest::write_u8(image, FLASH_CMD_READ_ARRAY));
+        assert_eq!(read(&flash, image), 0x1234_5678);
+        flash.send_data_request(MemoryRequest::write_u8(image, FLASH_CMD_WORD_PROGRAM));
+        flash.send_data_request(MemoryRequest::write_u32(image, 0xFFFF_0000));
+        flash.send_data_request(MemoryRequest::write_u8(image, FLASH_CMD_READ_ARRAY));
+        assert_eq!(read(&flash, image), 0x1234_0000);
+
+        // block erase needs its confirmation
+        let confirm = MemoryRequest::write_u8(image + 4, FLASH_CMD_CONFIRM);
+        flash.send_data_request(MemoryRequest::write_u8(image + 4, FLASH_CMD_BLOCK_ERASE));
+        assert_eq!(flash.access_latency(&confirm), 1000);
+        flash.send_data_request(confirm);
+        flash.send_data_request(MemoryRequest::write_u8(image, FLASH_CMD_READ_ARRAY));
+        assert_eq!(read(&flash, image), 0xFFFF_FFFF);
+
+        // the protected block is left untouched and the failure is reported in the status register
+        flash.send_data_request(MemoryRequest::write_u8(start, FLASH_CMD_BLOCK_ERASE));
+        flash.send_data_request(MemoryRequest::write_u8(start, FLASH_CMD_CONFIRM));
+        assert_eq!(read(&flash, start), (FLASH_STATUS_READY | FLASH_STATUS_ERASE_ERROR | FLASH_STATUS_PROTECTED) as u32);
+        flash.send_data_request(MemoryRequest::write_u8(start, FLASH_CMD_CLEAR_STATUS));
+        assert_eq!(read(&flash, start), FLASH_STATUS_READY as u32);
+        flash.send_data_request(MemoryRequest::write_u8(start, FLASH_CMD_WORD_PROGRAM));
+        flash.send_data_request(MemoryRequest::write_u32(start, 0));
+        flash.send_data_request(MemoryRequest::write_u8(start, FLASH_CMD_READ_ARRAY));
+        assert_eq!(read(&flash, start), 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn test_device_tick() {
+        use crate::risc_soc::csr::{CSR_MIP, MIP_MEIP};
+        use crate::risc_soc::memory_management_unit::{
+            Address, DeviceId, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryResponse, MemoryResponseType,
+        };
+
+        /// timer raising its interrupt once it counted 8 edges of its clock, it is never accessed by the program
+        struct Countdown {
+            start_address: Address,
+            end_address: Address,
+            edges: u64,
+        }
+
+        impl MemoryDevice for Countdown {
+            fn new(_memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+                Self { start_address, end_address, edges: 0 }
+            }
+            fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+                self.read_request(request)
+            }
+            fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+                MemoryResponse { data: MemoryData::zeroed(request.data_size as usize), status: MemoryResponseType::Valid }
+            }
+            fn size(&self) -> usize {
+                (self.end_address - self.start_address) as usize
+            }
+            fn start_end_addresses(&self) -> (Address, Address) {
+                (self.start_address, self.end_address)
+            }
+            fn get_memory_type(&self) -> MemoryDeviceType {
+                MemoryDeviceType::IOMMU
+            }
+            fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+            fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+                Ok(())
+            }
+            fn tick(&mut self, edges: u64) {
+                self.edges += edges;
+            }
+            fn pending_irq(&self) -> bool {
+                self.edges >= 8
+            }
+        }
+
+        let mut rv32i_core = super::init_core(None);
+        load_program(&mut rv32i_core, &[0x0000006f]);
+        let countdown = Countdown::new(MemoryDeviceType::IOMMU, 0x3000_0000, 0x3000_1000);
+        rv32i_core.mmu.write().unwrap().add_memory_device(Box::new(countdown));
+        rv32i_core.run_for_cycles(7);
+        assert!(rv32i_core.mmu.read().unwrap().pending_irqs().is_empty());
+        assert_eq!(rv32i_core.read_csr(CSR_MIP).unwrap() & MIP_MEIP as RiscWord, 0);
+        rv32i_core.run_for_cycles(1);
+        assert_eq!(rv32i_core.mmu.read().unwrap().pending_irqs(), vec![DeviceId::from(MemoryDeviceType::IOMMU)]);
+        assert_eq!(rv32i_core.read_csr(CSR_MIP).unwrap() & MIP_MEIP as RiscWord, MIP_MEIP as RiscWord);
+    }
+
+    #[test]
+    fn test_rtc() {
+        use crate::risc_soc::memory_management_unit::{Address, MemoryDevice, MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::rtc::*;
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        // the RTC of the platform follows the host time, read low half first
+        let rv32i_core = super::init_core(None);
+        let low = rv32i_core.data_request(MemoryRequest::read_u32(super::RTC_ADDRESS + RTC_TIME_LOW)).as_u32() as u64;
+        let high = rv32i_core.data_request(MemoryRequest::read_u32(super::RTC_ADDRESS + RTC_TIME_HIGH)).as_u32() as u64;
+        let host = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64;
+        assert!(host - ((high << 32) | low) < 10_000_000_000);
+
+        let start = 1_700_000_000_000_000_000;
+        let mut rtc = Rtc::new(MemoryDeviceType::RTC, 0, 0x1000)
+            .with_time_source(RtcTimeSource::Simulated { start, ns_per_edge: 100 });
+        let read = |rtc: &Rtc, offset: Address| rtc.read_request(MemoryRequest::read_u32(offset)).as_u32() as u64;
+        let time = |rtc: &Rtc| {
+            let low = read(rtc, RTC_TIME_LOW);
+            (read(rtc, RTC_TIME_HIGH) << 32) | low
+        };
+        assert_eq!(time(&rtc), start);
+        rtc.tick(10);
+        assert_eq!(time(&rtc), start + 1000);
+
+        // the guest sets the time, which keeps running from there
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_TIME_HIGH, 1));
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_TIME_LOW, 0));
+        assert_eq!(time(&rtc), 1 << 32);
+        rtc.tick(1);
+        assert_eq!(time(&rtc), (1 << 32) + 100);
+
+        // alarm 500ns ahead
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_ALARM_HIGH, 1));
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_ALARM_LOW, 600));
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_IRQ_ENABLED, 1));
+        assert_eq!(read(&rtc, RTC_ALARM_STATUS), 1);
+        assert_eq!(rtc.next_event(), Some(5));
+        rtc.tick(4);
+        assert!(!rtc.pending_irq());
+        rtc.tick(1);
+        assert!(rtc.pending_irq());
+        assert_eq!(read(&rtc, RTC_ALARM_STATUS), 0);
+        assert_eq!(rtc.next_event(), None);
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_CLEAR_INTERRUPT, 1));
+        assert!(!rtc.pending_irq());
+
+        // a cancelled alarm never fires
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_ALARM_LOW, 1000));
+        rtc.send_data_request(MemoryRequest::write_u32(RTC_CLEAR_ALARM, 1));
+        rtc.tick(100);
+        assert!(!rtc.pending_irq());
+    }
+
+    #[test]
+    fn test_watchdog() {
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, MemoryRequest};
+        use crate::risc_soc::risc_soc::{ExitSignal, ExitStatus};
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::rv32i_baremetal::watchdog::*;
+
+        // firmware that never kicks the watchdog is reset, and finds out why after the reboot
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core
+            .load_assembly(
+                &format!(
+                    "
+                        li t0, 0x{:X}
+                        li t1, 20
+                        sw t1, 0(t0)
+                        li t1, 0x{:X}
+                        sw t1, 8(t0)
+                    done: j done
+                    ",
+                    super::WATCHDOG_ADDRESS,
+                    WDT_CONTROL_ENABLE | WDT_CONTROL_RESET_ENABLE
+                ),
+                0x8000_0000,
+            )
+            .unwrap();
+        rv32i_core.set_reset_vector(0x8000_0000);
+        assert_eq!(rv32i_core.run_sequential_with(RunControl::cycles(100)), StopReason::Halted);
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Reset));
+        assert!(rv32i_core.reboot());
+        let read = |offset| rv32i_core.data_request(MemoryRequest::read_u32(super::WATCHDOG_ADDRESS + offset)).as_u32();
+        assert_eq!(read(WDT_STATUS), WDT_STATUS_RESET_CAUSE);
+        assert_eq!(read(WDT_CONTROL), 0);
+
+        // with the interrupt enabled the first expiry only raises it, the second one resets the SoC
+        let exit_signal = ExitSignal::default();
+        let mut watchdog = Watchdog::new(MemoryDeviceType::WATCHDOG, 0, 0x1000).with_exit_signal(exit_signal.clone());
+        let write = |watchdog: &mut Watchdog, offset, value| {
+            watchdog.send_data_request(MemoryRequest::write_u32(offset, value));
+        };
+        write(&mut watchdog, WDT_LOAD, 10);
+        write(&mut watchdog, WDT_CONTROL, WDT_CONTROL_ENABLE | WDT_CONTROL_IRQ_ENABLE | WDT_CONTROL_RESET_ENABLE);
+        assert_eq!(watchdog.next_event(), Some(10));
+        watchdog.tick(9);
+        assert!(!watchdog.pending_irq());
+        watchdog.tick(1);
+        assert!(watchdog.pending_irq());
+        assert_eq!(watchdog.read_request(MemoryRequest::read_u32(WDT_VALUE)).as_u32(), 10);
+
+        // kicking with the wrong key does nothing, the right key acknowledges the interrupt and reloads the counter
+        watchdog.tick(5);
+        write(&mut watchdog, WDT_KICK, 0);
+        assert!(watchdog.pending_irq());
+        write(&mut watchdog, WDT_KICK, WDT_KICK_KEY);
+        assert!(!watchdog.pending_irq());
+        watchdog.tick(19);
+        assert!(watchdog.pending_irq());
+        assert_eq!(exit_signal.status(), None);
+        watchdog.tick(1);
+        assert_eq!(exit_signal.status(), Some(ExitStatus::Reset));
+        assert_eq!(watchdog.next_event(), None);
+    }
+
+    #[test]
+    fn test_timer() {
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::timer::*;
+        use std::sync::{Arc, Mutex};
+
+        let mut timer = Timer::new(MemoryDeviceType::TIMER0, 0, 0x100);
+        let toggles = Arc::new(Mutex::new(vec![]));
+        let recorded = toggles.clone();
+        timer.on_output_change(Box::new(move |channel, level| recorded.lock().unwrap().push((channel, level))));
+        let pins = timer.pins();
+        let write = |timer: &mut Timer, offset, value| {
+            timer.send_data_request(MemoryRequest::write_u32(offset, value));
+        };
+        let read = |timer: &Timer, offset| timer.read_request(MemoryRequest::read_u32(offset)).as_u32();
+        let channel = |channel: usize, register| TIMER_CHANNEL_BASE + TIMER_CHANNEL_STRIDE * channel as u64 + register;
+
+        // a step every 2 edges, 10 steps per period: PWM at 30% on channel 0, compare match at 5 on channel 1
+        write(&mut timer, TIMER_PRESCALER, 1);
+        write(&mut timer, TIMER_PERIOD, 9);
+        write(&mut timer, channel(0, TIMER_CHANNEL_MODE), TIMER_MODE_PWM);
+        write(&mut timer, channel(0, TIMER_CHANNEL_COMPARE), 3);
+        write(&mut timer, channel(1, TIMER_CHANNEL_MODE), TIMER_MODE_COMPARE);
+        write(&mut timer, channel(1, TIMER_CHANNEL_COMPARE), 5);
+        write(&mut timer, TIMER_IRQ_ENABLE, TIMER_STATUS_OVERFLOW);
+        write(&mut timer, TIMER_CONTROL, TIMER_CONTROL_ENABLE);
+        assert!(pins.output(0));
+        assert_eq!(timer.next_event(), Some(6));
+
+        timer.tick(5);
+        assert_eq!(read(&timer, TIMER_COUNTER), 2);
+        timer.tick(1);
+        assert!(!pins.output(0));
+        timer.tick(4);
+        assert_eq!(read(&timer, TIMER_STATUS), timer_status_channel(1));
+        assert!(!timer.pending_irq());
+        timer.tick(10);
+        assert_eq!(read(&timer, TIMER_COUNTER), 0);
+        assert_eq!(read(&timer, TIMER_STATUS), TIMER_STATUS_OVERFLOW | timer_status_channel(1));
+        assert!(timer.pending_irq());
+        assert!(pins.output(0));
+        write(&mut timer, TIMER_STATUS, TIMER_STATUS_OVERFLOW);
+        assert!(!timer.pending_irq());
+
+        // many periods at once, as skipped by an event-driven run
+        timer.tick(2 * 10 * 99);
+        assert_eq!(pins.duty_cycle(0), 0.3);
+        assert_eq!(toggles.lock().unwrap()[..3], [(0, true), (0, false), (0, true)]);
+        assert_eq!(toggles.lock().unwrap().len(), 2 * 100 + 1);
+
+        // capture the counter on an input edge
+        write(&mut timer, channel(2, TIMER_CHANNEL_MODE), TIMER_MODE_CAPTURE);
+        timer.tick(8);
+        pins.capture(2);
+        assert_eq!(timer.next_event(), Some(1));
+        timer.tick(1);
+        assert_eq!(read(&timer, channel(2, TIMER_CHANNEL_COMPARE)), 4);
+        assert_ne!(read(&timer, TIMER_STATUS) & timer_status_channel(2), 0);
+
+        // a one shot timer stops after its overflow
+        write(&mut timer, TIMER_COUNTER, 0);
+        write(&mut timer, TIMER_CONTROL, TIMER_CONTROL_ENABLE | TIMER_CONTROL_ONE_SHOT);
+        timer.tick(100);
+        assert_eq!(read(&timer, TIMER_CONTROL), TIMER_CONTROL_ONE_SHOT);
+        assert_eq!(read(&timer, TIMER_COUNTER), 0);
+        assert_eq!(timer.next_event(), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_board_presets() {
+        use crate::risc_soc::csr::MIP_MEIP;
+        use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest};
+        use crate::risc_soc::risc_soc::ExitStatus;
+        use crate::rv32i_baremetal::plic::*;
+        use crate::rv32i_baremetal::sifive_uart::*;
+        use std::sync::atomic::Ordering;
+        use super::BoardConfig;
+
+        assert_eq!(BoardConfig::preset("qemu-virt"), Some(BoardConfig::QEMU_VIRT));
+        assert_eq!(BoardConfig::preset("fe310"), Some(BoardConfig::SIFIVE_FE310));
+        assert_eq!(BoardConfig::preset("unknown"), None);
+
+        // firmware linked at the start of the DRAM of the QEMU virt machine, ending through the test device
+        let mut rv32i_core = super::init_board(&BoardConfig::QEMU_VIRT, None);
+        let memory_map = rv32i_core.mmu.read().unwrap().memory_map();
+        assert!(memory_map.contains(&(MemoryDeviceType::DRAM, 0x8000_0000, 0x8800_0000)));
+        assert!(memory_map.contains(&(MemoryDeviceType::UART1, 0x1000_0000, 0x1000_0100)));
+        assert!(memory_map.contains(&(MemoryDeviceType::PLIC, 0xC00_0000, 0x1000_0000)));
+        assert!(!memory_map.iter().any(|(memory_type, _, _)| *memory_type == MemoryDeviceType::UART0));
+        assert_eq!(rv32i_core.get_pc(), 0x8000_0000);
+        rv32i_core.load_assembly("
+            li t0, 0x80001000
+            li t1, 42
+            sw t1, 0(t0)
+            lw a0, 0(t0)
+            li t0, 0x100000
+            li t1, 0x5555
+            sw t1, 0(t0)
+        loop:
+            j loop
+        ", 0x8000_0000).unwrap();
+        rv32i_core.run_for_cycles(100);
+        assert_eq!(rv32i_core.read_reg_by_name("a0"), 42);
+        assert_eq!(rv32i_core.exit_status(), Some(ExitStatus::Pass));
+
+        // the THR empty interrupt of the 16550 reaches MEIP through the PLIC, source 10 as in QEMU
+        let rv32i_core = super::init_board(&BoardConfig::QEMU_VIRT, None);
+        let plic = super::PLIC_ADDRESS;
+        let meip = || rv32i_core.interrupt_lines().load(Ordering::SeqCst) & MIP_MEIP != 0;
+        let tick = |cycle| rv32i_core.mmu.write().unwrap().tick(cycle);
+        rv32i_core.data_request(MemoryRequest::write_u8(super::UART16550_ADDRESS + 1, 0x2));
+        tick(0);
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(plic + PLIC_PENDING)).as_u32(), 1 << 10);
+        assert!(!meip());
+        rv32i_core.data_request(MemoryRequest::write_u32(plic + PLIC_PRIORITY + 4 * 10, 1));
+        rv32i_core.data_request(MemoryRequest::write_u32(plic + PLIC_ENABLE, 1 << 10));
+        assert!(meip());
+        // a threshold at the priority of the source masks it
+        rv32i_core.data_request(MemoryRequest::write_u32(plic + PLIC_CONTEXT + PLIC_THRESHOLD, 1));
+        assert!(!meip());
+        rv32i_core.data_request(MemoryRequest::write_u32(plic + PLIC_CONTEXT + PLIC_THRESHOLD, 0));
+        // claiming takes the source out of the pending ones until it is completed
+        let claim = plic + PLIC_CONTEXT + PLIC_CLAIM_COMPLETE;
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(claim)).as_u32(), 10);
+        assert!(!meip());
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(claim)).as_u32(), 0);
+        tick(1);
+        assert!(!meip());
+        // the line of the UART is still high, so the source is pending again once completed
+        rv32i_core.data_request(MemoryRequest::write_u32(claim, 10));
+        assert!(meip());
+        rv32i_core.data_request(MemoryRequest::write_u8(super::UART16550_ADDRESS + 1, 0));
+        tick(2);
+        assert!(!meip());
+
+        // HiFive1 Rev B: the user program starts in flash after the bootloader, the console is the SiFive UART
+        let rv32i_core = super::init_board(&BoardConfig::SIFIVE_FE310, None);
+        let memory_map = rv32i_core.mmu.read().unwrap().memory_map();
+        assert!(memory_map.contains(&(MemoryDeviceType::DRAM, 0x8000_0000, 0x8000_4000)));
+        assert!(memory_map.contains(&(MemoryDeviceType::FLASH, 0x2000_0000, 0x2040_0000)));
+        assert!(memory_map.contains(&(MemoryDeviceType::UART2, 0x1001_3000, 0x1001_3100)));
+        assert_eq!(rv32i_core.get_pc(), 0x2001_0000);
+        let uart = 0x1001_3000;
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(uart + SIFIVE_UART_TXDATA)).as_u32(), 0);
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(uart + SIFIVE_UART_DIV)).as_u32(), SIFIVE_UART_DEFAULT_DIV);
+        // the transmit watermark is pending while the FIFO holds fewer bytes than TXCNT
+        rv32i_core.data_request(MemoryRequest::write_u32(uart + SIFIVE_UART_TXCTRL, (1 << 16) | SIFIVE_UART_ENABLE));
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(uart + SIFIVE_UART_IP)).as_u32(), SIFIVE_UART_TXWM);
+        rv32i_core.data_request(MemoryRequest::write_u32(uart + SIFIVE_UART_IE, SIFIVE_UART_TXWM));
+        rv32i_core.mmu.write().unwrap().tick(0);
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(plic + PLIC_PENDING)).as_u32(), 1 << 12);
+    }
+
+    #[test]
+    fn test_mmu_aliases() {
+        use crate::risc_soc::memory_management_unit::{DeviceId, MemoryDeviceType};
+
+        let rv32i_core = super::init_core(None);
+        {
+            let mut mmu = rv32i_core.mmu.write().unwrap();
+            // uncached alias of the whole DRAM, and its first 4 KiB mirrored over 64 KiB
+            mmu.add_alias(MemoryDeviceType::DRAM, 0x9100_0000, 0x9100_0000 + super::DRAM_SIZE);
+            mmu.add_mirror(MemoryDeviceType::DRAM, 0x6000_0000, 0x6001_0000, 0xFFF);
+            assert_eq!(mmu.device_at(0x9100_0010), Some(DeviceId::from(MemoryDeviceType::DRAM)));
+            assert_eq!(mmu.device_address(0x6000_3008), super::DRAM_ADDRESS + 0x8);
+        }
+
+        rv32i_core.write_mem(0x9100_0010, &[0xAA, 0xBB]).unwrap();
+        assert_eq!(rv32i_core.read_mem(super::DRAM_ADDRESS + 0x10, 2).unwrap(), vec![0xAA, 0xBB]);
+        rv32i_core.write_mem(super::DRAM_ADDRESS + 0x8, &[0x11]).unwrap();
+        assert_eq!(rv32i_core.read_mem(0x6000_3008, 1).unwrap(), vec![0x11]);
+        assert_eq!(rv32i_core.read_mem(0x6000_F008, 1).unwrap(), vec![0x11]);
+        assert_eq!(rv32i_core.read_mem(0x9100_0008, 1).unwrap(), vec![0x11]);
+    }
+
+    #[test]
+    fn test_device_lookup_cache() {
+        use crate::risc_soc::memory_management_unit::{DeviceId, LookupStats, MemoryDeviceType};
+
+        let rv32i_core = super::init_core(None);
+        let mmu = rv32i_core.mmu.read().unwrap();
+        let dram = Some(DeviceId::from(MemoryDeviceType::DRAM));
+        let uart = Some(DeviceId::from(MemoryDeviceType::UART1));
+        // the first lookup of a device searches the ranges
+        let stats = mmu.lookup_stats();
+        assert_eq!(mmu.device_at(super::UART16550_ADDRESS), uart);
+        assert_eq!(mmu.device_at(super::DRAM_ADDRESS), dram);
+        assert_eq!(mmu.lookup_stats(), LookupStats { hits: stats.hits, misses: stats.misses + 2 });
+        let stats = mmu.lookup_stats();
+
+        // each device keeps its entry, alternating between them only hits
+        for offset in 0..4 {
+            assert_eq!(mmu.device_at(super::DRAM_ADDRESS + offset * 4), dram);
+            assert_eq!(mmu.device_at(super::UART16550_ADDRESS + offset), uart);
+        }
+        assert_eq!(mmu.lookup_stats(), LookupStats { hits: stats.hits + 8, misses: stats.misses });
+        // unmapped addresses always search the ranges, and do not evict the cached devices
+        assert_eq!(mmu.device_at(0x7000_0000), None);
+        assert_eq!(mmu.device_at(0x7000_0000), None);
+        assert_eq!(mmu.device_at(super::DRAM_ADDRESS), dram);
+        assert_eq!(mmu.lookup_stats(), LookupStats { hits: stats.hits + 9, misses: stats.misses + 2 });
+    }
+
+    #[test]
+    fn test_device_instances() {
+        use crate::risc_soc::memory_management_unit::{DeviceId, MemoryDevice, MemoryDeviceType, Permissions};
+        use crate::rv32i_baremetal::dram::Dram;
+
+        // a second bank of DRAM next to the one of the platform, read only
+        let rv32i_core = super::init_core(None);
+        let bank = Dram::new(MemoryDeviceType::DRAM, 0x9000_0000, 0x9000_1000);
+        let id = rv32i_core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(bank), Permissions::R);
+        assert_eq!(id, DeviceId::new(MemoryDeviceType::DRAM, 1));
+        {
+            let mmu = rv32i_core.mmu.read().unwrap();
+            assert_eq!(mmu.device_at(super::DRAM_ADDRESS), Some(DeviceId::new(MemoryDeviceType::DRAM, 0)));
+            assert_eq!(mmu.device_at(0x9000_0004), Some(id));
+            assert_eq!(mmu.permissions(MemoryDeviceType::DRAM), Permissions::RWX);
+            assert_eq!(mmu.permissions(id), Permissions::R);
+        }
+
+        rv32i_core.write_mem(super::DRAM_ADDRESS, &[0x5A]).unwrap();
+        assert_eq!(rv32i_core.poke_memory(0x9000_0000, &[0x5A]), None);
+        assert_eq!(rv32i_core.read_mem(0x9000_0000, 1).unwrap(), vec![0x00]);
+        assert_eq!(rv32i_core.read_mem(super::DRAM_ADDRESS, 1).unwrap(), vec![0x5A]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_embedded_isa_tests() {
+        #[cfg(not(feature = "rv64"))]
+    use crate::rv32i_baremetal::isa_test::IsaTest;
+    use crate::rv32i_baremetal::isa_test::isa_test_image;
+
+        // the embedded images are the ones of the isa_tests directory, and load as the files do
+        for program in ["add.elf", "branch.elf", "jump_and_return.elf", "memory.elf"] {
+            let path = format!("{}/isa_tests/{program}", env!("CARGO_MANIFEST_DIR"));
+            assert_eq!(isa_test_image(program), std::fs::read(&path).unwrap(), "{program}");
+            let mut from_file = super::init_core(None);
+            super::load_elf(&mut from_file, &path).unwrap();
+            let mut embedded = super::init_core(None);
+            super::load_bytes(&mut embedded, isa_test_image(program)).unwrap();
+            assert_eq!(embedded.get_pc(), from_file.get_pc());
+            assert_eq!(embedded.read_mem(0x8000_0000, 0x100).unwrap(), from_file.read_mem(0x8000_0000, 0x100).unwrap());
+        }
+        assert!(std::panic::catch_unwind(|| isa_test_image("missing.elf")).is_err());
+
+        // a failing golden-state check lists every mismatch
+        let failure = std::panic::catch_unwind(|| {
+            IsaTest::new("add.elf").max_cycles(50).expect_reg("x3", 4).expect_mem(0x8001_0000, &[1]).run();
+        })
+        .unwrap_err();
+        let message = failure.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("add.elf stopped with"), "{message}");
+        assert!(message.contains("x3 = 0x3, expected 0x4") && message.contains("memory at 0x80010000 = Some([00]), expected [01]"), "{message}");
+    }
+
+    #[test]
+    #[cfg(all(feature = "tui", not(feature = "rv64")))]
+    fn test_dashboard() {
+        use crate::risc_soc::dashboard::Dashboard;
+        use ratatui::Terminal;
+        use ratatui::backend::TestBackend;
+
+        let mut rv32i_core = super::init_core(None);
+        // li a0, 0x2A; sw a0, 0(a1) with a1 pointing to the L1 data memory; j .
+        load_program(&mut rv32i_core, &[0x02a00513, 0x800105b7, 0x00a5a023, 0x0000006f]);
+        let mut dashboard = Dashboard::new(&mut rv32i_core);
+        dashboard.advance(20);
+        let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+
+        // the statistics, the stages, the store and the registers written by the program
+        assert!(screen.contains("cycle 20  retired "), "{screen}");
+        assert!(screen.contains("last 0x8000000C j pc+0"));
+        assert!(screen.contains("  WB  0000006F  j pc+0"));
+        assert!(screen.contains("W 0x80010000  2A 00 00 00"));
+        assert!(screen.contains("x10(a0) 0000002A"));
+        assert!(screen.contains("s: step  r: run/pause"));
+    }
+
+    #[test]
+    fn test_timing_model() {
+        use crate::risc_soc::energy::InstructionClass;
+        use crate::risc_soc::run_control::{RunControl, StopReason};
+        use crate::risc_soc::timing::TimingModel;
+
+        // auipc a1, 0x10; lw a0, 0(a1); addi a2, a0, 1; addi a3, a2, 1; addi a4, a3, 1; j .
+        let program = [0x00010597, 0x0005A503, 0x00150613, 0x00160693, 0x00168713, 0x0000006F];
+        let run = |timing: TimingModel| {
+            let mut rv32i_core = super::init_core(None);
+            super::set_timing_model(&rv32i_core, timing);
+            load_program(&mut rv32i_core, &program);
+            rv32i_core.write_mem(0x8001_0000, &[0x29, 0, 0, 0]).unwrap();
+            let control = RunControl { cycles: Some(500), ..RunControl::until(|core| core.read_reg_by_name("a4") == 0x2C) };
+            assert_eq!(rv32i_core.run_sequential_with(control), StopReason::Condition);
+            rv32i_core.stages[0].lock().unwrap().clock_cycle
+        };
+
+        // the same program takes longer on a core with slower loads or a multi-cycle ALU, with the same results
+        let default = run(TimingModel::default());
+        assert_eq!(run(TimingModel::default().with_timing(InstructionClass::Load, 4, 1)), default + 2);
+        assert_eq!(run(TimingModel::default().with_timing(InstructionClass::Alu, 2, 1)), default + 3);
+
+        // the table of a core is read from a configuration file
+        let path = std::env::temp_dir().join(format!("timing_model_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "load": { "latency": 4 } }"#).unwrap();
+        let timing = TimingModel::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(timing, Ok(TimingModel::default().with_timing(InstructionClass::Load, 4, 1)));
+        assert!(TimingModel::load(path.to_str().unwrap()).unwrap_err().starts_with(path.to_str().unwrap()));
+        assert!(TimingModel::from_json("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_benchmark_workload() {
+        use crate::risc_soc::benchmark::measure;
+
+        // the dot product loop of the CoreMark-like benchmark
+        // the words fetched past the last jump are decoded before it is taken, so the data is kept after a few nops
+        let program = "
+            start:
+                la a0, vector
+                li a1, 4
+                li a2, 0
+            dot:
+                lw t2, 0(a0)
+                lw t3, 4(a0)
+                mul t4, t2, t3
+                add a2, a2, t4
+                addi a0, a0, 8
+                addi a1, a1, -1
+                bnez a1, dot
+                mv s0, a2
+                j start
+                nop
+                nop
+            vector: .word 1
+                .word 2
+                .word 3
+                .word 4
+                .word 5
+                .word 6
+                .word 7
+                .word 8
+        ";
+        let build = || {
+            let mut rv32i_core = super::init_core(None);
+            rv32i_core.load_assembly(program, 0x8000_0000).unwrap();
+            rv32i_core.set_reset_vector(0x8000_0000);
+            rv32i_core
+        };
+        // the benchmarks count their throughput in retired instructions, which must not change between runs
+        for (threaded, cycles) in [(false, 2_000), (true, 500)] {
+            let mut rv32i_core = build();
+            let speed = measure(&mut rv32i_core, cycles, threaded);
+            assert_eq!(rv32i_core.read_reg_by_name("s0"), 2 + 3 * 4 + 5 * 6 + 7 * 8);
+            assert_eq!(speed.cycles, cycles);
+            assert!(speed.instructions > cycles / 4 && speed.instructions <= cycles);
+            assert_eq!(measure(&mut build(), cycles, threaded).instructions, speed.instructions);
+            assert!(speed.mips() > 0.0 && speed.cycles_per_second() > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_pma() {
+        use crate::risc_soc::exception::{Exception, MisalignedAccessPolicy};
+        use crate::risc_soc::memory_management_unit::{MemoryAttributes, MemoryRequest, MemoryResponseType, Permissions};
+        use crate::risc_soc::risc_soc::WordSize;
+
+        let uart = 0x1000_0000;
+        let mut rv32i_core = super::init_core(None);
+        {
+            let mmu = rv32i_core.mmu.read().unwrap();
+            assert_eq!(mmu.attributes(super::DRAM_ADDRESS), MemoryAttributes::MEMORY);
+            assert_eq!(mmu.attributes(0x8000_0000), MemoryAttributes::MEMORY);
+            assert_eq!(mmu.attributes(uart), MemoryAttributes::IO);
+        }
+
+        // no atomics on the registers of the UART
+        rv32i_core.atomic_request(uart, WordSize::WORD, |value| value);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.tval), (Exception::StoreAccessFault, uart));
+
+        // misaligned accesses are only split where reading twice has no side effect
+        rv32i_core.set_misaligned_policy(MisalignedAccessPolicy::Split);
+        let response = rv32i_core.data_request(MemoryRequest::write_u32(super::DRAM_ADDRESS + 1, 0x1234_5678));
+        assert_eq!(response.status, MemoryResponseType::Valid);
+        let response = rv32i_core.data_request(MemoryRequest::read_u16(uart + 1));
+        assert_eq!(response.status, MemoryResponseType::NotReadable);
+        let trap = rv32i_core.take_trap().unwrap();
+        assert_eq!((trap.exception, trap.tval), (Exception::LoadAccessFault, uart + 1));
+
+        // a read only window in DRAM, described by its own attributes
+        let window = super::DRAM_ADDRESS + 0x1000;
+        rv32i_core.mmu.write().unwrap().add_pma_region(window, window + 0x1000, MemoryAttributes::MEMORY.with_permissions(Permissions::R));
+        let response = rv32i_core.data_request(MemoryRequest::write_u32(window, 0x1234_5678));
+        assert_eq!(response.status, MemoryResponseType::NotWrittable);
+        assert_eq!(rv32i_core.take_trap().unwrap().exception, Exception::StoreAccessFault);
+    }
+
+    #[test]
+    fn test_init_register_devices() {
+        use crate::risc_soc::memory_management_unit::MemoryRequest;
+        // an image overlapping the registers of a device is ignored there, the rest of the image is still loaded
+        let mut rv32i_core = super::init_core(None);
+        rv32i_core.init_memory(super::UART16550_ADDRESS, &[0xAA; 8]);
+        rv32i_core.init_memory(super::UART_ADDRESS, &[0xAA; 16]);
+        rv32i_core.init_memory(super::DRAM_ADDRESS, &[0x55; 4]);
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(super::DRAM_ADDRESS)).as_u32(), 0x5555_5555);
+        // the registers keep their reset values, ex. an empty transmitter in LSR
+        let lsr = rv32i_core.data_request(MemoryRequest::read_u8(super::UART16550_ADDRESS + 5)).as_u8();
+        assert_ne!(lsr & 0x20, 0);
+    }
+
+    #[test]
+    fn test_uart16550_placement() {
+        use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest};
+        use super::BoardConfig;
+
+        // the 16550 is mapped where the board places it, or left out
+        let board = BoardConfig { uart16550: Some(0x1000_1000), ..BoardConfig::DEFAULT };
+        let rv32i_core = super::init_board(&board, None);
+        let memory_map = rv32i_core.mmu.read().unwrap().memory_map();
+        assert!(memory_map.contains(&(MemoryDeviceType::UART1, 0x1000_1000, 0x1000_1100)));
+        let lsr = rv32i_core.data_request(MemoryRequest::read_u8(0x1000_1000 + 5)).as_u8();
+        assert_ne!(lsr & 0x20, 0);
+
+        let board = BoardConfig { uart16550: None, ..BoardConfig::DEFAULT };
+        let rv32i_core = super::init_board(&board, None);
+        let memory_map = rv32i_core.mmu.read().unwrap().memory_map();
+        assert!(!memory_map.iter().any(|(memory_type, _, _)| *memory_type == MemoryDeviceType::UART1));
+        assert!(memory_map.contains(&(MemoryDeviceType::UART0, super::UART_ADDRESS, super::UART_ADDRESS + super::UART_SIZE)));
+    }
+
+    #[test]
+    fn test_dma_beats() {
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, MemoryRequest, Permissions};
+        use crate::rv32i_baremetal::dma::*;
+
+        let rv32i_core = super::init_core(None);
+        let dma_address = 0x1000_3000;
+        let dma = DmaController::new(MemoryDeviceType::DMA0, dma_address, dma_address + 0x100).with_burst(2);
+        let interrupt_line = dma.interrupt_line();
+        rv32i_core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(dma), Permissions::RW);
+        let (source, destination) = (super::DRAM_ADDRESS, super::DRAM_ADDRESS + 0x100);
+        for word in 0..4 {
+            rv32i_core.data_request(MemoryRequest::write_u32(source + 4 * word, 0x1111_1111 * (word as u32 + 1)));
+        }
+        let write = |offset, value| rv32i_core.data_request(MemoryRequest::write_u32(dma_address + offset, value));
+        let read = |offset| rv32i_core.data_request(MemoryRequest::read_u32(dma_address + offset)).as_u32();
+        let tick = |cycle| rv32i_core.mmu.write().unwrap().tick(cycle);
+
+        // one word per cycle with 2 accesses per cycle, the transfer only advances when the MMU ticks
+        write(DMA_SOURCE, source as u32);
+        write(DMA_DESTINATION, destination as u32);
+        write(DMA_LENGTH, 16);
+        write(DMA_CONTROL, DMA_CONTROL_START | DMA_CONTROL_IRQ_ENABLE);
+        assert_eq!((read(DMA_STATUS), read(DMA_TRANSFERRED)), (DMA_STATUS_BUSY, 0));
+        for cycle in 0..3 {
+            tick(cycle);
+            assert_eq!(read(DMA_TRANSFERRED), 4 * (cycle as u32 + 1));
+        }
+        assert_eq!(read(DMA_STATUS), DMA_STATUS_BUSY);
+        assert!(!interrupt_line.load(std::sync::atomic::Ordering::SeqCst));
+        tick(3);
+        assert_eq!((read(DMA_STATUS), read(DMA_TRANSFERRED)), (DMA_STATUS_DONE, 16));
+        assert!(interrupt_line.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(rv32i_core.data_request(MemoryRequest::read_u32(destination + 12)).as_u32(), 0x4444_4444);
+        write(DMA_STATUS, DMA_STATUS_DONE);
+        assert!(!interrupt_line.load(std::sync::atomic::Ordering::SeqCst));
+
+        // a transfer reaching an unmapped address stops with an error
+        write(DMA_SOURCE, 0x7000_0000);
+        write(DMA_CONTROL, DMA_CONTROL_START);
+        tick(4);
+        assert_eq!(read(DMA_STATUS), DMA_STATUS_DONE | DMA_STATUS_ERROR);
+        assert_eq!(read(DMA_TRANSFERRED), 0);
+    }
+
+    #[test]
+    fn test_dtb() {
+        use crate::risc_soc::dtb::generate_dtb;
+        use crate::risc_soc::memory_management_unit::{MemoryDevice, MemoryDeviceType, Permissions};
+        use crate::rv32i_baremetal::framebuffer::Framebuffer;
+
+        let mut rv32i_core = super::init_core(None);
+        super::add_boot_rom(&mut rv32i_core, super::DRAM_ADDRESS, super::L1_ADDRESS);
+        let framebuffer = Framebuffer::new(MemoryDeviceType::FRAMEBUFFER0, 0x3000_0000, 0x3002_5800);
+        rv32i_core.mmu.write().unwrap().add_memory_device_with_permissions(Box::new(framebuffer), Permissions::RW);
+        let dtb = generate_dtb(&rv32i_core, "rv32imac");
+
+        let (nodes, properties) = parse_dtb(&dtb);
+
+        // only RAM is described as memory, the boot ROM and the flash are devices of the SoC
+        for node in ["/memory@80000000", "/memory@81000000", "/soc/rom@1000", "/soc/flash@20000000", "/soc/serial@10000000"] {
+            assert!(nodes.contains(&node.to_string()), "missing {node}");
+        }
+        assert!(!nodes.iter().any(|node| node == "/memory@1000" || node == "/memory@20000000"));
+        assert_eq!(properties["/memory@81000000/device_type"], b"memory\0");
+        assert!(!properties.contains_key("/soc/flash@20000000/device_type"));
+        assert_eq!(properties["/chosen/stdout-path"], b"/soc/serial@10000000\0");
+
+        // the mode of the framebuffer, as in the simple-framebuffer binding
+        let framebuffer = "/soc/framebuffer@30000000";
+        assert_eq!(properties[&format!("{framebuffer}/width")], 320u32.to_be_bytes());
+        assert_eq!(properties[&format!("{framebuffer}/height")], 240u32.to_be_bytes());
+        assert_eq!(properties[&format!("{framebuffer}/stride")], 640u32.to_be_bytes());
+        assert_eq!(properties[&format!("{framebuffer}/format")], b"r5g6b5\0");
+    }
+
+    #[test]
+    fn test_uart_interrupt_sources() {
+        use crate::risc_soc::dtb::generate_dtb;
+        use crate::risc_soc::memory_management_unit::{MemoryDeviceType, MemoryRequest};
+        use crate::rv32i_baremetal::plic::*;
+        use crate::rv32i_baremetal::serial::SerialConnection;
+        use std::time::{Duration, Instant};
+        use super::BoardConfig;
+
+        // the default board with both UARTs, routing their lines through a PLIC
+        let board = BoardConfig { name: "default-plic", plic: Some(super::PLIC_ADDRESS), ..BoardConfig::DEFAULT };
+        let rv32i_core = super::init_board(&board, None);
+        let (_, properties) = parse_dtb(&generate_dtb(&rv32i_core, "rv32imac"));
+        assert_eq!(properties["/soc/serial@40600000/interrupts"], 13u32.to_be_bytes());
+        assert_eq!(properties["/soc/serial@10000000/interrupts"], 10u32.to_be_bytes());
+
+        let plic = super::PLIC_ADDRESS;
+        let read = |address| rv32i_core.data_request(MemoryRequest::read_u32(address)).as_u32();
+        let write = |address, value| rv32i_core.data_request(MemoryRequest::write_u32(address, value));
+        let tick = |cycle| rv32i_core.mmu.write().unwrap().tick(cycle);
+        let claim = plic + PLIC_CONTEXT + PLIC_CLAIM_COMPLETE;
+        for source in [10, 13] {
+            write(plic + PLIC_PRIORITY + 4 * source, 1);
+        }
+        write(plic + PLIC_ENABLE, 1 << 10 | 1 << 13);
+
+        // a byte received by the UART Lite only raises its own source
+        let connection = SerialConnection { input: Box::new(std::io::Cursor::new(b"x".to_vec())), output: Box::new(std::io::sink()) };
+        super::attach_serial(&rv32i_core, MemoryDeviceType::UART0, connection);
+        write(super::UART_ADDRESS + 0xC, 0x10);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while read(super::UART_ADDRESS + 0x8) & 0x1 == 0 {
+            assert!(Instant::now() < deadline, "the UART did not receive in time");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        tick(0);
+        assert_eq!(read(plic + PLIC_PENDING), 1 << 13);
+        assert_eq!(read(claim), 13);
+        // the THR empty interrupt of the 16550 is told apart by the handler
+        rv32i_core.data_request(MemoryRequest::write_u8(super::UART16550_ADDRESS + 1, 0x2));
+        tick(1);
+        assert_eq!(read(plic + PLIC_PENDING), 1 << 10);
+        assert_eq!(read(claim), 10);
+        assert_eq!(read(claim), 0);
+    }
+}
@@ -1,7 +1,11 @@
+use crate::risc_soc::csr::DebugCause;
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::isa_model::MicroOp;
 use crate::risc_soc::pipeline_stage::{PipelineData};
-use crate::risc_soc::risc_soc::{RiscCore};
-use crate::rv32i_baremetal::core::{EX_STAGE, ID_STAGE, IF_STAGE, WB_STAGE, MEM_STAGE};
-use std::u32;
+use crate::risc_soc::risc_soc::{RiscCore, RiscSignedWord, RiscWord, XLEN, XLEN_BYTES};
+use crate::risc_soc::sim_error::SimErrorKind;
+use crate::risc_soc::trigger::{MCONTROL_EXECUTE, TriggerAction};
+use crate::rv32i_baremetal::core::{EX_STAGE, ID_STAGE, WB_STAGE, MEM_STAGE, McuState};
 
 /// FUNC7 and FUNCT3 field lengths
 pub const FUNCT_7L: u8 = 7;
@@ -29,11 +33,93 @@ pub const OP_ALU: u8 = 0b0110011; // ALU Instructions (ADD, SUB, AND, OR, XOR, e
 pub const OP_ALUI: u8 = 0b0010011; // ALU Immediate Instructions (ADDI, ANDI, ORI, XORI, etc.)
 pub const OP_FENCE: u8 = 0b0001111; // Fence
 pub const OP_SYSTEM: u8 = 0b1110011; // System Instructions (ECALL, EBREAK, etc.)
+pub const OP_AMO: u8 = 0b0101111; // Atomic Instructions (LR, SC, AMOSWAP, AMOADD, etc.)
+// RV64 only: operations on the lower 32 bits with a sign extended result
+pub const OP_ALUI_W: u8 = 0b0011011; // ADDIW, SLLIW, SRLIW, SRAIW
+pub const OP_ALU_W: u8 = 0b0111011; // ADDW, SUBW, SLLW, SRLW, SRAW
+// vector extension: configuration and arithmetic, then the vector loads and stores sharing the floating point opcodes
+pub const OP_V: u8 = 0b1010111; // VSETVLI, VSETIVLI, VSETVL, VADD, VMUL, etc.
+pub const OP_LOAD_FP: u8 = 0b0000111; // VLE8, VLE16, VLE32
+pub const OP_STORE_FP: u8 = 0b0100111; // VSE8, VSE16, VSE32
 
-pub fn rv32_mcu_decode_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
-    // we set the instruction starting at address 0x0 in the received pipeline data
-    let instruction = pipeline_reg.get_u32(0x0);
-    let pc = pipeline_reg.get_u32(0x4);
+// FUNCT3 of the vsetvl instructions under OP_V, and FUNCT6 (upper bits of FUNCT7) of the moves between vector and scalar registers
+pub const FUNC3_VSETVL: u8 = 0b111;
+pub const FUNC3_OPMVV: u8 = 0b010;
+pub const FUNCT6_VWXUNARY0: u8 = 0b010000;
+
+// FUNCT3 value telling FENCE.I apart from FENCE under OP_FENCE
+pub const FUNC3_FENCE_I: u8 = 0b001;
+
+// FUNCT3 values of the Zicsr instructions under OP_SYSTEM, the immediate variants have the third bit set
+pub const FUNC3_CSRRW: u8 = 0b001;
+pub const FUNC3_CSRRS: u8 = 0b010;
+pub const FUNC3_CSRRC: u8 = 0b011;
+pub const FUNC3_CSR_IMM: u8 = 0b100;
+/// WFI is encoded under OP_SYSTEM with FUNCT3 0, it is allowed to complete as a NOP
+pub const FUNCT12_WFI: u32 = 0x105;
+/// ECALL is encoded under OP_SYSTEM with FUNCT3 0 and all the other fields 0
+pub const FUNCT12_ECALL: u32 = 0x000;
+/// EBREAK differs from ECALL only by the lowest bit of the FUNCT12 field
+pub const FUNCT12_EBREAK: u32 = 0x001;
+
+// FUNCT5 values (upper bits of FUNCT7) of the A extension under OP_AMO, the lower two bits are the aq/rl ordering flags
+pub const AMO_ADD: u8 = 0b00000;
+pub const AMO_SWAP: u8 = 0b00001;
+pub const AMO_LR: u8 = 0b00010;
+pub const AMO_SC: u8 = 0b00011;
+pub const AMO_XOR: u8 = 0b00100;
+pub const AMO_OR: u8 = 0b01000;
+pub const AMO_AND: u8 = 0b01100;
+pub const AMO_MIN: u8 = 0b10000;
+pub const AMO_MAX: u8 = 0b10100;
+pub const AMO_MINU: u8 = 0b11000;
+pub const AMO_MAXU: u8 = 0b11100;
+
+// memory side operations passed from ID down to the MEM stage
+pub const MEM_NONE: u8 = 0x0;
+pub const MEM_LOAD: u8 = 0x1;
+pub const MEM_FENCE: u8 = 0x2;
+pub const MEM_STORE: u8 = 0x3;
+pub const MEM_FENCE_I: u8 = 0x4;
+/// the instruction raises an exception once it reaches MEM, where it can no longer be flushed by an older branch
+/// the cause is carried in place of the ALU result and mtval in place of rs2
+pub const MEM_TRAP: u8 = 0x5;
+/// atomic memory operation, the FUNCT7 field is passed down to tell them apart
+pub const MEM_AMO: u8 = 0x6;
+/// environment call, performed in WB once every older instruction completed
+/// like fences it flushes the younger instructions, which are fetched again and see the value it writes to a0
+pub const MEM_ECALL: u8 = 0x7;
+/// breakpoint, performed in WB like environment calls as it may be a semihosting call returning a value in a0
+pub const MEM_EBREAK: u8 = 0x8;
+/// instruction the ISA model could not decode, the run stops with the error of the decoder once it reaches MEM
+/// so an instruction fetched on a path flushed by an older branch (ex. erased flash after a jump) does not stop it
+pub const MEM_SIM_ERROR: u8 = 0x9;
+/// vector instruction, performed in order by MEM on the vector registers, the scalar operand or base address is carried
+/// in place of the ALU result and the scalar result (if any) replaces it before being forwarded
+pub const MEM_VECTOR: u8 = 0xA;
+
+/// canonical NOP encoding (addi x0, x0, 0)
+pub const NOP: u32 = 0x0000_0013;
+
+/// size of the ID/EX pipeline register
+pub const ID_EX_SIZE: usize = 13 + 4 * XLEN_BYTES;
+
+/// ID/EX register holding no instruction: a NOP that writes no register, with a zero instruction word
+/// the instruction word carried towards WB is the valid bit of the pipeline, only non-zero words retire
+pub fn id_ex_bubble() -> PipelineData {
+    let mut bubble = vec![0u8; ID_EX_SIZE];
+    bubble[0x0] = OP_ALUI;
+    PipelineData(bubble)
+}
+
+/// immediates and W results are 32-bit values sign extended to XLEN
+#[inline]
+pub fn sign_extend(value: u32) -> RiscWord {
+    value as i32 as RiscSignedWord as RiscWord
+}
+
+/// fields of an RV32/RV64 IMA instruction as carried by the pipeline, the register fields are kept even for formats without them
+pub fn decode_instruction(instruction: u32) -> Result<MicroOp, SimErrorKind> {
     let opcode = (instruction & OPCODE_MASK) as u8;
 
     // get register indexes
@@ -44,17 +130,34 @@ pub fn rv32_mcu_decode_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
     let func3 = ((instruction >> (OPCODE_L + REG_L)) & FUNCT_3_MASK) as u8;
     let func7 = ((instruction >> (OPCODE_L + 3 * REG_L + FUNCT_3L)) & FUNCT_7_MASK) as u8;
 
-    let branch_or_jump: u8 = (opcode == OP_BRANCH || opcode == OP_JAL || opcode == OP_JALR) as u8;
+    let ecall = opcode == OP_SYSTEM && func3 == 0 && instruction >> (OPCODE_L + FUNCT_3L + 2 * REG_L) == FUNCT12_ECALL;
+    let ebreak = opcode == OP_SYSTEM && func3 == 0 && instruction >> (OPCODE_L + FUNCT_3L + 2 * REG_L) == FUNCT12_EBREAK;
+
+    // fences, environment calls and breakpoints are handled as an unconditional jump to the next instruction once they reach MEM
+    // this flushes everything fetched after them, so younger instructions are fetched again after they completed
+    let branch_or_jump = opcode == OP_BRANCH || opcode == OP_JAL || opcode == OP_JALR || opcode == OP_FENCE || ecall || ebreak;
 
     let reg_write = match opcode {
-        OP_ALUI | OP_LOAD | OP_JALR | OP_ALU | OP_LUI | OP_AUIPC | OP_JAL => 1u8,
-        _ => 0u8,
+        OP_ALUI | OP_LOAD | OP_JALR | OP_ALU | OP_LUI | OP_AUIPC | OP_JAL | OP_ALUI_W | OP_ALU_W => true,
+        OP_SYSTEM if func3 != 0 => true,
+        OP_AMO => true,
+        // vsetvl instructions write the new vl, vmv.x.s an element
+        OP_V => func3 == FUNC3_VSETVL || func3 == FUNC3_OPMVV && func7 >> 1 == FUNCT6_VWXUNARY0,
+        _ => false,
     };
 
-    let mem_read_write = match opcode {
-        OP_LOAD => 1u8,
-        OP_STORE => 3u8,
-        _ => 0u8,
+    let mem_op = match opcode {
+        OP_LOAD => MEM_LOAD,
+        OP_STORE => MEM_STORE,
+        OP_FENCE if func3 == FUNC3_FENCE_I => MEM_FENCE_I,
+        OP_FENCE => MEM_FENCE,
+        OP_AMO => MEM_AMO,
+        OP_SYSTEM if ecall => MEM_ECALL,
+        OP_SYSTEM if ebreak => MEM_EBREAK,
+        OP_V => MEM_VECTOR,
+        // the other widths are the scalar floating point loads and stores
+        OP_LOAD_FP | OP_STORE_FP if matches!(func3, 0b000 | 0b101 | 0b110 | 0b111) => MEM_VECTOR,
+        _ => MEM_NONE,
     };
 
     // compute immediate based on OPCODE
@@ -62,10 +165,13 @@ pub fn rv32_mcu_decode_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
         // I-type Instructions + Load
         // we convert instruction to i32 in order to use arithmetic right shift
         OP_ALUI | OP_LOAD | OP_JALR => {
-            (instruction as i32 >> (OPCODE_L + FUNCT_3L + 2 * REG_L)) as u32 & u32::MAX
+            (instruction as i32 >> (OPCODE_L + FUNCT_3L + 2 * REG_L)) as u32
+        }
+        OP_ALUI_W if XLEN == 64 => {
+            (instruction as i32 >> (OPCODE_L + FUNCT_3L + 2 * REG_L)) as u32
         }
         OP_STORE => {
-            ((instruction as i32 >> 25) << 7) as u32 | ((instruction >> OPCODE_L) & REG_MASK)
+            ((instruction as i32 >> 25) << 5) as u32 | ((instruction >> OPCODE_L) & REG_MASK)
         }
         OP_BRANCH => {
             let instr7 = (instruction >> 7 & 0x1) << 11;
@@ -82,18 +188,78 @@ pub fn rv32_mcu_decode_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
             instr31 | instr19_12 | instr20 | instr30_21
         }
         OP_AUIPC | OP_LUI => instruction & 0xFFFF_F000,
-        OP_ALU => 0u32,
-        0x0 => 0u32,
-        _ => panic!("Cannot decode this type of opcode: {opcode}"), //this MCU cannot execute SYSTEM/FENCE instr
+        // CSR address, EX only uses the lower 12 bits so the sign extension below does not matter
+        OP_SYSTEM if func3 != 0 => instruction >> (OPCODE_L + FUNCT_3L + 2 * REG_L),
+        OP_SYSTEM if instruction >> (OPCODE_L + FUNCT_3L + 2 * REG_L) == FUNCT12_WFI => 0u32,
+        OP_SYSTEM if ecall || ebreak => 0u32,
+        OP_ALU | OP_FENCE | OP_AMO => 0u32,
+        OP_V => 0u32,
+        OP_LOAD_FP | OP_STORE_FP if mem_op == MEM_VECTOR => 0u32,
+        OP_ALU_W if XLEN == 64 => 0u32,
+        _ => return Err(SimErrorKind::UnsupportedInstruction(instruction)),
     };
 
+    Ok(MicroOp {
+        opcode,
+        func3,
+        func7,
+        rd: rd_address,
+        rs1: rs1_address,
+        rs2: rs2_address,
+        imm: sign_extend(imm),
+        reg_write,
+        mem_op,
+        branch_or_jump,
+        uses_rs1: !matches!(opcode, OP_LUI | OP_AUIPC | OP_JAL),
+        // vsetvl is the only vector instruction reading a scalar register in its rs2 field
+        uses_rs2: matches!(opcode, OP_ALU | OP_ALU_W | OP_STORE | OP_AMO | OP_BRANCH)
+            || opcode == OP_V && func3 == FUNC3_VSETVL && func7 == 0b1000000,
+    })
+}
+
+pub fn rv32_mcu_decode_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
+    // we set the instruction starting at address 0x0 in the received pipeline data
+    let fetched = pipeline_reg.get_u32(0x0);
+    let pc = pipeline_reg.get_word(0x4);
+    let fetch_fault = pipeline_reg.get_u8(0x4 + XLEN_BYTES);
+    let fetch_cause = pipeline_reg.get_u8(0x5 + XLEN_BYTES);
+    // IF leaves its register empty when it fetched nothing, the hazard logic below still runs for the NOP decoded in its place
+    let bubble = fetched == 0x0 && fetch_fault == 0x0;
+    let instruction = if fetched == 0x0 { NOP } else { fetched };
+    let isa = rv32_core.microarchitecture::<McuState>().isa_model();
+    // the instruction goes on as a NOP carrying the error, raised by MEM unless a branch flushes it before
+    let decode_error = match rv32_core.decode_cached(pc, instruction, |instruction| isa.decode(instruction)) {
+        Ok(ops) if ops.len() == 1 => Ok(ops[0]),
+        Ok(_) => {
+            let reason = "the in-order pipeline issues a single micro-op per instruction".to_string();
+            Err(SimErrorKind::UnsupportedOperation { instruction, reason })
+        }
+        Err(kind) => Err(kind),
+    };
+    let (op, decode_failed) = match decode_error {
+        Ok(op) => (op, false),
+        Err(kind) if !bubble && fetch_fault == 0x0 => {
+            // only the instructions in EX and MEM can be older than this one, the errors of the flushed ones are dropped
+            let mut decode_errors = rv32_core.microarchitecture::<McuState>().decode_errors.lock().unwrap();
+            if decode_errors.last().is_none_or(|(error_pc, _)| *error_pc != pc) {
+                let stale = decode_errors.len().saturating_sub(2);
+                decode_errors.drain(..stale);
+                decode_errors.push((pc, kind));
+            }
+            (MicroOp::default(), true)
+        }
+        Err(_) => (MicroOp::default(), false),
+    };
+    let opcode = op.opcode;
+    let (rd_address, rs1_address, rs2_address) = (op.rd, op.rs1, op.rs2);
+
     //leave read of regs at the end
     //first check commit stage(4th in our case) and see if there is a register to commit first as it might be needed for one of the rs
     // wait for WB stage to get latest values for our registers
     let wb_data = rv32_core.cdb.pull(WB_STAGE, ID_STAGE);
     let wb_reg_write = wb_data.get_u8(0x0);
     let wb_rd_address = wb_data.get_u8(0x1) & REG_MASK as u8;
-    let wb_rd_value = wb_data.get_u32(0x2);
+    let wb_rd_value = wb_data.get_word(0x2);
     if wb_reg_write == 0x1 {
         rv32_core.write_reg(wb_rd_address as usize, wb_rd_value);
     }
@@ -104,39 +270,78 @@ pub fn rv32_mcu_decode_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
     let ex_data = rv32_core.cdb.pull(EX_STAGE, ID_STAGE);
     let ex_mem_read = ex_data.get_u8(0x0);
     let ex_rd = ex_data.get_u8(0x1);
+    // registers whose result is not usable yet by an instruction entering EX, as the timing model of EX sets them
+    let ex_pending = ex_data.get_u32(0x2);
+    let pending = (op.uses_rs1 && (ex_pending >> rs1_address) & 0x1 == 0x1) || (op.uses_rs2 && (ex_pending >> rs2_address) & 0x1 == 0x1);
     let mem_data = rv32_core.cdb.pull(MEM_STAGE, ID_STAGE);
     let mem_branch_or_jump = mem_data.get_u8(0x0);
     let mem_take_jump = mem_data.get_u8(0x1);
+    // Debug Mode is entered in front of the instruction in ID once EX and MEM are empty, so dpc is its address
+    // the instruction in WB completes in this cycle, as its register write was applied above
+    let ex_busy = ex_data.get_u8(0x6) == 0x1;
+    let mem_busy = mem_data.get_u8(0x2 + XLEN_BYTES) == 0x1;
+    // a trigger on the address of the instruction fires before it executes
+    let trigger = if bubble { None } else { rv32_core.check_triggers(MCONTROL_EXECUTE, pc) };
+    let debug_halt = match trigger {
+        Some(TriggerAction::DebugMode) => Some(DebugCause::Trigger),
+        _ => rv32_core.pending_debug_halt(),
+    };
+    let debug_mode = rv32_core.in_debug_mode();
+    // a load-use hazard keeps the instruction in ID, which sends a bubble to EX, and IF stalls until ID is ready again
+    // a slow memory access in MEM needs no handling here, as the stages before MEM stall while it is not ready
     if mem_branch_or_jump & mem_take_jump == 0x1 {
+        rv32_core.set_stage_ready(ID_STAGE, true);
         rv32_core.reset_stage(ID_STAGE, true);
         rv32_core.reset_stage(EX_STAGE, true);
-    } else if ex_mem_read == 0x1
+    } else if (debug_halt.is_some() || debug_mode) && !bubble {
+        // nothing is issued while a halt is pending or the hart is halted, the run stops at the end of the cycle it halts in
+        rv32_core.set_stage_ready(ID_STAGE, false);
+        rv32_core.reset_stage(ID_STAGE, true);
+        if let Some(cause) = debug_halt.filter(|_| !debug_mode && !ex_busy && !mem_busy) {
+            rv32_core.enter_debug_mode(pc, cause);
+        }
+    } else if (ex_mem_read == MEM_LOAD || ex_mem_read == MEM_AMO)
         && ex_rd != 0x0
         && (ex_rd == rs1_address
-            || ((opcode == OP_ALU || opcode == OP_STORE) && ex_rd == rs2_address)) {
-        rv32_core.enable_stage(IF_STAGE, false);
+            || ((opcode == OP_ALU || opcode == OP_STORE || opcode == OP_AMO || op.uses_rs2 && opcode == OP_V) && ex_rd == rs2_address))
+        || pending {
+        rv32_core.set_stage_ready(ID_STAGE, false);
         rv32_core.reset_stage(ID_STAGE, true);
     } else {
-        rv32_core.enable_stage(IF_STAGE, true);
+        rv32_core.set_stage_ready(ID_STAGE, true);
         rv32_core.reset_stage(ID_STAGE, false);
         rv32_core.reset_stage(EX_STAGE, false);
+        if !bubble {
+            rv32_core.debug_instruction_issued();
+        }
+    }
+
+    if bubble {
+        return id_ex_bubble();
     }
 
+    // a failed fetch raises its exception once it reaches MEM, unless an older branch flushes it before
+    // so does a breakpoint trigger, with the address of the instruction in mtval
+    let (reg_write, mem_read_write, func7, imm) = if fetch_fault == 0x1 {
+        (0u8, MEM_TRAP, fetch_cause, pc)
+    } else if trigger == Some(TriggerAction::Breakpoint) {
+        (0u8, MEM_TRAP, Exception::Breakpoint as u8, pc)
+    } else if decode_failed {
+        (0u8, MEM_SIM_ERROR, 0u8, 0)
+    } else {
+        (op.reg_write as u8, op.mem_op, op.func7, op.imm)
+    };
+
     //concatanate add data into the pipeline register for next stage
-    let mut pipeline_out = vec![];
-    pipeline_out.push(opcode);
-    pipeline_out.push(func3);
-    pipeline_out.push(func7);
-    pipeline_out.push(reg_write);
-    pipeline_out.push(mem_read_write);
-    pipeline_out.push(rd_address);
-    pipeline_out.push(branch_or_jump);
+    let mut pipeline_out = vec![opcode, op.func3, func7, reg_write, mem_read_write, rd_address, op.branch_or_jump as u8];
     pipeline_out.extend_from_slice(&imm.to_le_bytes());
     pipeline_out.extend_from_slice(&rs1.to_le_bytes());
     pipeline_out.extend_from_slice(&rs2.to_le_bytes());
     pipeline_out.extend_from_slice(&pc.to_le_bytes());
     pipeline_out.push(rs1_address);
     pipeline_out.push(rs2_address);
+    // carried until WB to report the retired instruction, zero for bubbles and failed fetches
+    pipeline_out.extend_from_slice(&fetched.to_le_bytes());
 
     PipelineData(pipeline_out)
 }
@@ -0,0 +1,276 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest,
+    MemoryRequestType, MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::risc_soc::WordSize;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// register offsets of the DMA engine
+pub const DMA_SOURCE: Address = 0x0;
+pub const DMA_DESTINATION: Address = 0x4;
+pub const DMA_LENGTH: Address = 0x8;
+pub const DMA_CONTROL: Address = 0xC;
+/// write 1 to clear the DONE and ERROR bits
+pub const DMA_STATUS: Address = 0x10;
+/// bytes already copied by the current or last transfer
+pub const DMA_TRANSFERRED: Address = 0x14;
+
+/// control register bits
+pub const DMA_CONTROL_START: u32 = 1 << 0;
+pub const DMA_CONTROL_IRQ_ENABLE: u32 = 1 << 1;
+
+/// status register bits
+pub const DMA_STATUS_BUSY: u32 = 1 << 0;
+pub const DMA_STATUS_DONE: u32 = 1 << 1;
+pub const DMA_STATUS_ERROR: u32 = 1 << 2;
+
+/// number of bus accesses the engine performs in each cycle of its clock, the rest of the bandwidth is left to the cores
+pub const DMA_DEFAULT_BURST: usize = 4;
+
+/// memory to memory copy engine mastering the system bus of the MMU
+/// each copied word is a read and a write through the MMU, at most `burst` accesses per cycle of the engine,
+/// so a running transfer takes a share of the bandwidth of the devices the cores also use, and finishes after a known number of cycles
+/// the L1 memories private to the cores are not on this bus
+pub struct DmaController {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    source: u32,
+    destination: u32,
+    length: u32,
+    burst: usize,
+    /// bus accesses the engine may still perform, earned by the cycles ticked since its last turn on the bus
+    budget: usize,
+    busy: bool,
+    done: bool,
+    error: bool,
+    transferred: u32,
+    irq_enable: bool,
+    interrupt_line: Arc<AtomicBool>,
+}
+
+impl DmaController {
+    pub fn with_burst(mut self, burst: usize) -> Self {
+        assert!(burst > 0);
+        self.burst = burst;
+        self
+    }
+
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.interrupt_line.clone()
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = 0;
+        if self.busy {
+            status |= DMA_STATUS_BUSY;
+        }
+        if self.done {
+            status |= DMA_STATUS_DONE;
+        }
+        if self.error {
+            status |= DMA_STATUS_ERROR;
+        }
+        status
+    }
+
+    fn start(&mut self) {
+        if self.busy {
+            tracing::warn!("DMA transfer started while another one is still running, ignoring it");
+            return;
+        }
+        self.busy = true;
+        self.done = false;
+        self.error = false;
+        self.transferred = 0;
+        // the first accesses are made in the cycle after the one starting the transfer
+        self.budget = 0;
+    }
+
+    fn finish(&mut self) {
+        self.busy = false;
+        self.done = true;
+        self.budget = 0;
+        if self.irq_enable {
+            self.interrupt_line.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn is_valid(status: &MemoryResponseType) -> bool {
+        *status == MemoryResponseType::Valid || *status == MemoryResponseType::CacheHit
+    }
+}
+
+impl MemoryDevice for DmaController {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::DMA0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            source: 0,
+            destination: 0,
+            length: 0,
+            burst: DMA_DEFAULT_BURST,
+            budget: 0,
+            busy: false,
+            done: false,
+            error: false,
+            transferred: 0,
+            irq_enable: false,
+            interrupt_line: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let busy = self.busy;
+        match request.data_address - self.start_address {
+            // transfer parameters are locked while the engine is running
+            DMA_SOURCE if !busy => self.source = value,
+            DMA_DESTINATION if !busy => self.destination = value,
+            DMA_LENGTH if !busy => self.length = value,
+            DMA_SOURCE | DMA_DESTINATION | DMA_LENGTH => {}
+            DMA_CONTROL => {
+                self.irq_enable = value & DMA_CONTROL_IRQ_ENABLE != 0;
+                if value & DMA_CONTROL_START != 0 {
+                    self.start();
+                }
+            }
+            DMA_STATUS => {
+                if value & DMA_STATUS_DONE != 0 {
+                    self.done = false;
+                    self.interrupt_line.store(false, Ordering::SeqCst);
+                }
+                if value & DMA_STATUS_ERROR != 0 {
+                    self.error = false;
+                }
+            }
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let value = match request.data_address - self.start_address {
+            DMA_SOURCE => self.source,
+            DMA_DESTINATION => self.destination,
+            DMA_LENGTH => self.length,
+            DMA_CONTROL => {
+                if self.irq_enable { DMA_CONTROL_IRQ_ENABLE } else { 0 }
+            }
+            DMA_STATUS => self.status(),
+            DMA_TRANSFERRED => self.transferred,
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+            }
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn tick(&mut self, edges: u64) {
+        if self.busy {
+            self.budget = self.budget.saturating_add((edges as usize).saturating_mul(self.burst));
+        }
+    }
+
+    fn wants_bus(&self) -> bool {
+        self.busy
+    }
+
+    fn master_bus(&mut self, bus: &mut dyn FnMut(MemoryRequest) -> MemoryResponse) {
+        let (source, destination, length) = (self.source as Address, self.destination as Address, self.length as Address);
+        // every copy is a read and a write, a single access left in the budget waits for the next cycle
+        while self.busy && (self.transferred as Address) < length && self.budget >= 2 {
+            let offset = self.transferred as Address;
+            // copy whole words when both sides are aligned, bytes otherwise
+            let data_size = if length - offset >= 4 && (source + offset).is_multiple_of(4) && (destination + offset).is_multiple_of(4) {
+                WordSize::WORD
+            } else {
+                WordSize::BYTE
+            };
+            self.budget -= 2;
+            let read = bus(MemoryRequest::read(source + offset, data_size));
+            let write = if Self::is_valid(&read.status) {
+                bus(MemoryRequest {
+                    request_type: MemoryRequestType::WRITE,
+                    data_address: destination + offset,
+                    data_size,
+                    data: Some(read.data),
+                })
+            } else {
+                read
+            };
+            if !Self::is_valid(&write.status) {
+                tracing::warn!("DMA transfer aborted at offset {:X}: {:?}", offset, write.status);
+                self.error = true;
+                self.finish();
+                return;
+            }
+            self.transferred += data_size as u32;
+        }
+        if self.busy && self.transferred as Address >= length {
+            self.finish();
+        }
+    }
+
+    fn pending_irq(&self) -> bool {
+        self.interrupt_line.load(Ordering::SeqCst)
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        if self.busy { Some(1) } else { None }
+    }
+
+    fn reset(&mut self, _clear_memory: bool) {
+        self.source = 0;
+        self.destination = 0;
+        self.length = 0;
+        self.budget = 0;
+        self.busy = false;
+        self.done = false;
+        self.error = false;
+        self.transferred = 0;
+        self.irq_enable = false;
+        self.interrupt_line.store(false, Ordering::SeqCst);
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nDMA {:?}: {{ SRC={:X} DST={:X} LEN={:X} STATUS={:X} transferred={:X} }}",
+            self.memory_type,
+            self.source,
+            self.destination,
+            self.length,
+            self.status(),
+            self.transferred
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+
+/// timing of the DRAM in core clock cycles, the memory is split in banks each keeping one row open in its row buffer
+/// consecutive rows are interleaved between the banks, so sequential accesses spread over all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DramTiming {
+    pub banks: usize,
+    /// bytes of a row
+    pub row_size: usize,
+    /// column access, the whole latency of an access to the open row of a bank (tCAS)
+    pub cas_latency: u64,
+    /// activation of a row in a bank without an open row (tRCD)
+    pub ras_to_cas_delay: u64,
+    /// closing the open row of a bank before activating another one (tRP)
+    pub precharge: u64,
+    /// close the row after every access, so every access pays the activation but never a precharge
+    pub closed_page: bool,
+}
+
+impl Default for DramTiming {
+    /// an ideal memory answering in the cycle of the access, as the L1 memories of the MCU
+    fn default() -> Self {
+        Self { banks: 1, row_size: 1024, cas_latency: 0, ras_to_cas_delay: 0, precharge: 0, closed_page: false }
+    }
+}
+
+impl DramTiming {
+    /// roughly a DDR3 device behind a core running at a few hundred MHz
+    pub fn typical() -> Self {
+        Self { banks: 8, row_size: 2048, cas_latency: 4, ras_to_cas_delay: 4, precharge: 4, closed_page: false }
+    }
+}
+
+/// main memory mapped in the MMU, shared by all the harts of a SoC
+/// unlike the L1 memories of the MCU it is not private to a core, so it is where harts exchange data (ex. spinlocks)
+pub struct Dram {
+    start_address: Address,
+    end_address: Address,
+    data: Vec<u8>,
+    timing: DramTiming,
+    /// row held in the row buffer of every bank
+    open_rows: Vec<Option<u64>>,
+}
+
+impl Dram {
+    pub fn with_timing(mut self, timing: DramTiming) -> Self {
+        assert!(timing.banks > 0 && timing.row_size > 0);
+        self.timing = timing;
+        self.open_rows = vec![None; timing.banks];
+        self
+    }
+}
+
+impl MemoryDevice for Dram {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::DRAM);
+        Self {
+            start_address,
+            end_address,
+            data: vec![0u8; (end_address - start_address) as usize],
+            timing: DramTiming::default(),
+            open_rows: vec![None],
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.data.len() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        assert!(request.data.is_some());
+        self.data[offset..offset + size].copy_from_slice(&request.data.unwrap()[..size]);
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.data.len() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        MemoryResponse {
+            data: MemoryData::new(&self.data[offset..offset + size]),
+            status: MemoryResponseType::Valid,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::DRAM
+    }
+
+    /// row buffer hit, activation of a row in an idle bank, or precharge of the open row followed by the activation
+    fn access_latency(&mut self, request: &MemoryRequest) -> u64 {
+        let timing = self.timing;
+        let row = (request.data_address - self.start_address) / timing.row_size as u64;
+        let bank = (row % timing.banks as u64) as usize;
+        let latency = match self.open_rows[bank] {
+            Some(open_row) if open_row == row => timing.cas_latency,
+            Some(_) => timing.precharge + timing.ras_to_cas_delay + timing.cas_latency,
+            None => timing.ras_to_cas_delay + timing.cas_latency,
+        };
+        self.open_rows[bank] = if timing.closed_page { None } else { Some(row) };
+        latency
+    }
+
+    fn init_mem(&mut self, address: Address, data: &[u8]) {
+        let offset = (address - self.start_address) as usize;
+        assert!(offset + data.len() <= self.data.len());
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    /// every bank is idle after a reset
+    fn reset(&mut self, clear_memory: bool) {
+        self.open_rows.iter_mut().for_each(|row| *row = None);
+        if clear_memory {
+            self.data.fill(0);
+        }
+    }
+
+    fn debug(&self, start_address: Address, end_address: Address) -> std::fmt::Result {
+        assert!(start_address >= self.start_address && end_address <= self.end_address);
+        println!("\nMemory {:?}: {{", MemoryDeviceType::DRAM);
+        let start = (start_address - self.start_address) as usize;
+        let end = (end_address - self.start_address) as usize;
+        for (line, words) in self.data[start..end].chunks(16).enumerate() {
+            print!("{:X}: ", start_address as usize + line * 16);
+            for byte in words {
+                print!("{:02X}", byte);
+            }
+            println!();
+        }
+        println!("}}");
+        Ok(())
+    }
+}
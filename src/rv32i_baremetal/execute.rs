@@ -1,78 +1,114 @@
-use crate::risc_soc::risc_soc::RiscCore;
+use crate::risc_soc::energy::InstructionClass;
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::isa_model::{MicroOp, MicroOpResult, Operands};
+use crate::risc_soc::risc_soc::{RiscCore, RiscSignedWord, XLEN, XLEN_BYTES};
 use crate::risc_soc::{pipeline_stage::PipelineData, risc_soc::RiscWord};
-use crate::rv32i_baremetal::core::{EX_STAGE, MEM_STAGE, WB_STAGE, ID_STAGE};
-use crate::rv32i_baremetal::decode::REG_MASK;
+use crate::rv32i_baremetal::bitmanip::bit_manipulation;
+use crate::rv32i_baremetal::core::{EX_STAGE, MEM_STAGE, WB_STAGE, ID_STAGE, McuState};
+use crate::rv32i_baremetal::decode::{FUNC3_CSRRC, FUNC3_CSRRS, FUNC3_CSRRW, FUNC3_CSR_IMM, MEM_EBREAK, MEM_ECALL, MEM_SIM_ERROR, MEM_TRAP, REG_MASK, sign_extend};
 use crate::rv32i_baremetal::decode::{
-    OP_ALU, OP_ALUI, OP_AUIPC, OP_BRANCH, OP_JAL, OP_JALR, OP_LOAD, OP_LUI, OP_STORE,
+    OP_ALU, OP_ALUI, OP_ALUI_W, OP_ALU_W, OP_AUIPC, OP_BRANCH, OP_FENCE, OP_JAL, OP_JALR, OP_LOAD,
+    OP_LUI, OP_STORE, OP_SYSTEM, OP_AMO, OP_V, OP_LOAD_FP, OP_STORE_FP,
 };
-use std::u32;
 
-pub fn rv32_mcu_execute_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
-    let opcode = pipeline_reg.get_u8(0x0);
-    let func3 = pipeline_reg.get_u8(0x1);
-    let func7 = pipeline_reg.get_u8(0x2);
-    let reg_write = pipeline_reg.get_u8(0x3);
-    let mem_read_write = pipeline_reg.get_u8(0x4);
-    let rd_address = pipeline_reg.get_u8(0x5);
-    let branch_or_jump = pipeline_reg.get_u8(0x6);
-
-    let imm = pipeline_reg.get_u32(0x7);
-    let mut rs1 = pipeline_reg.get_u32(0xB);
-    let mut rs2 = pipeline_reg.get_u32(0xF);
-    let mut pc = pipeline_reg.get_u32(0x13);
+/// shift amounts only use the lower log2(XLEN) bits of the operand
+const SHAMT_MASK: RiscWord = (XLEN - 1) as RiscWord;
+const SHAMT_W_MASK: u32 = 0b11111;
+/// shift immediates only have a 6 bit function field on RV64
+const FUNCT_6_MASK: u8 = 0b1111110;
+/// FUNCT7 of the multiplications and divisions of the M extension
+pub const FUNCT_7_MULDIV: u8 = 0b0000001;
+const FUNC3_MUL: u8 = 0b000;
+const FUNC3_MULH: u8 = 0b001;
+const FUNC3_MULHSU: u8 = 0b010;
+const FUNC3_MULHU: u8 = 0b011;
+const FUNC3_DIV: u8 = 0b100;
+const FUNC3_DIVU: u8 = 0b101;
+const FUNC3_REM: u8 = 0b110;
 
-    let rs1_address = pipeline_reg.get_u8(0x17);
-    let rs2_address = pipeline_reg.get_u8(0x18);
-
-    // send EX info to ID stage
-    let mut id_data = vec![];
-    id_data.push(mem_read_write);
-    id_data.push(rd_address);
-    let id_data = PipelineData(id_data);
-    rv32_core.cdb.assign(EX_STAGE, ID_STAGE, id_data);
+/// instruction occupying EX for several clock cycles, and results of the instructions not yet usable by the next ones
+/// EX is not ready for the following instruction while it is busy, so the younger stages stall meanwhile
+#[derive(Default)]
+pub struct ExecuteWait {
+    /// clock cycles left before the result is released to MEM
+    remaining: u64,
+    /// output of EX once the instruction completes, its operands were forwarded when it started
+    output: Option<PipelineData>,
+    /// clock cycles left before an instruction reading each register can start, according to the timing model
+    pending: [u64; 32],
+}
 
-    // check WB stage to get latest values for our registers
-    let wb_data = rv32_core.cdb.pull(WB_STAGE, EX_STAGE);
-    let wb_reg_write = wb_data.get_u8(0x0);
-    let wb_rd_address = wb_data.get_u8(0x1) & REG_MASK as u8;
-    let wb_rd_value = wb_data.get_u32(0x2);
-    if wb_reg_write == 0x1 && wb_rd_address == rs1_address {
-        rs1 = wb_rd_value;
-    }
-    if wb_reg_write == 0x1 && wb_rd_address == rs2_address {
-        rs2 = wb_rd_value;
+/// result of an operation of the M extension, division by zero and overflow follow the spec instead of trapping
+pub fn multiply_divide(func3: u8, rs1: RiscWord, rs2: RiscWord) -> RiscWord {
+    let (signed_rs1, signed_rs2) = (rs1 as RiscSignedWord as i128, rs2 as RiscSignedWord as i128);
+    match func3 {
+        FUNC3_MUL => rs1.wrapping_mul(rs2),
+        FUNC3_MULH => ((signed_rs1 * signed_rs2) >> XLEN) as RiscWord,
+        FUNC3_MULHSU => ((signed_rs1 * rs2 as i128) >> XLEN) as RiscWord,
+        FUNC3_MULHU => ((rs1 as u128 * rs2 as u128) >> XLEN) as RiscWord,
+        // division by zero returns all ones and its remainder is the dividend
+        _ if rs2 == 0 && func3 < FUNC3_REM => RiscWord::MAX,
+        _ if rs2 == 0 => rs1,
+        FUNC3_DIV => (rs1 as RiscSignedWord).wrapping_div(rs2 as RiscSignedWord) as RiscWord,
+        FUNC3_DIVU => rs1 / rs2,
+        FUNC3_REM => (rs1 as RiscSignedWord).wrapping_rem(rs2 as RiscSignedWord) as RiscWord,
+        _ => rs1 % rs2,
     }
+}
 
-    // check MEM stage to get latest values for our registers
-    // MEM has higher priority on produced values, so we assign its values last
-    let mem_data = rv32_core.cdb.pull(MEM_STAGE, EX_STAGE);
-    let mem_reg_write = mem_data.get_u8(0x0);
-    let mem_rd_address = mem_data.get_u8(0x1) & REG_MASK as u8;
-    let mem_rd_value = mem_data.get_u32(0x2);
-    if mem_reg_write == 0x1 && mem_rd_address == rs1_address {
-        rs1 = mem_rd_value;
-    }
-    if mem_reg_write == 0x1 && mem_rd_address == rs2_address {
-        rs2 = mem_rd_value;
+/// RV64 only: operate on the lower 32 bits and sign extend the result, there is no upper half multiplication
+fn multiply_divide_w(func3: u8, rs1: u32, rs2: u32) -> RiscWord {
+    match func3 {
+        FUNC3_MUL => sign_extend(rs1.wrapping_mul(rs2)),
+        _ if func3 < FUNC3_DIV => 0,
+        _ if rs2 == 0 && func3 < FUNC3_REM => RiscWord::MAX,
+        _ if rs2 == 0 => sign_extend(rs1),
+        FUNC3_DIV => sign_extend((rs1 as i32).wrapping_div(rs2 as i32) as u32),
+        FUNC3_DIVU => sign_extend(rs1 / rs2),
+        FUNC3_REM => sign_extend((rs1 as i32).wrapping_rem(rs2 as i32) as u32),
+        _ => sign_extend(rs1 % rs2),
     }
+}
+
+/// RV32/RV64 IMA, Zicsr, Zba, Zbb, Zbs and Zicond operations, the MEM side of loads, stores and atomics only gets its address here
+/// as do vector instructions, which only get their scalar operand
+pub fn execute_micro_op(op: &MicroOp, operands: Operands, rv32_core: &RiscCore) -> MicroOpResult {
+    let MicroOp { opcode, func3, func7, .. } = *op;
+    let Operands { mut pc, hold, .. } = operands;
+    let imm: RiscWord = op.imm;
+    let rs1: RiscWord = operands.rs1;
+    let rs2: RiscWord = operands.rs2;
+    let mut take_jump = false;
+    let mut alu_out: RiscWord = 0;
+    let mut exception = None;
 
-    let mut take_jump: u8 = 0u8;
-    let mut alu_out: u32 = 0u32;
+    if let Some((extension, value)) = bit_manipulation(op, rs1, rs2) {
+        // the B extensions and Zicond are only decoded by harts listing them in their machine information
+        let implemented = rv32_core.csrs.read().unwrap().info.has_extension(extension);
+        let exception = (!implemented).then_some(Exception::IllegalInstruction);
+        return MicroOpResult { value, target: pc, take_jump: false, exception };
+    }
 
     match opcode {
+        OP_ALU if func7 == FUNCT_7_MULDIV => {
+            alu_out = multiply_divide(func3, rs1, rs2);
+        }
+        OP_ALU_W if func7 == FUNCT_7_MULDIV => {
+            alu_out = multiply_divide_w(func3, rs1 as u32, rs2 as u32);
+        }
         OP_ALU => {
             if func3 == 0b0 && func7 == 0b0 {
                 //add
-                alu_out = ((rs1 as i32) + (rs2 as i32)) as RiscWord;
+                alu_out = rs1.wrapping_add(rs2);
             } else if func3 == 0b000 && func7 == 0b0100000 {
                 //sub
-                alu_out = (rs1 as i32 - rs2 as i32) as RiscWord;
+                alu_out = rs1.wrapping_sub(rs2);
             } else if func3 == 0b001 {
                 //sll
-                alu_out = rs1 << rs2;
+                alu_out = rs1 << (rs2 & SHAMT_MASK);
             } else if func3 == 0b010 {
                 //slt
-                alu_out = ((rs1 as i32) < (rs2 as i32)) as RiscWord;
+                alu_out = ((rs1 as RiscSignedWord) < (rs2 as RiscSignedWord)) as RiscWord;
             } else if func3 == 0b011 {
                 //sltu
                 alu_out = (rs1 < rs2) as RiscWord;
@@ -81,10 +117,10 @@ pub fn rv32_mcu_execute_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
                 alu_out = rs1 ^ rs2;
             } else if func3 == 0b101 && func7 == 0b0 {
                 //srl
-                alu_out = rs1 >> rs2;
+                alu_out = rs1 >> (rs2 & SHAMT_MASK);
             } else if func3 == 0b101 && func7 == 0b0100000 {
                 //sra
-                alu_out = (rs1 as i32 >> rs2) as RiscWord;
+                alu_out = (rs1 as RiscSignedWord >> (rs2 & SHAMT_MASK)) as RiscWord;
             } else if func3 == 0b110 {
                 //or
                 alu_out = rs1 | rs2;
@@ -96,25 +132,25 @@ pub fn rv32_mcu_execute_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
         OP_ALUI => {
             if func3 == 0b0 {
                 //add
-                alu_out = ((rs1 as i32) + (imm as i32)) as RiscWord;
+                alu_out = rs1.wrapping_add(imm);
             } else if func3 == 0b001 {
                 //slli
-                alu_out = rs1 << imm;
+                alu_out = rs1 << (imm & SHAMT_MASK);
             } else if func3 == 0b010 {
                 //slti
-                alu_out = ((rs1 as i32) < (imm as i32)) as RiscWord;
+                alu_out = ((rs1 as RiscSignedWord) < (imm as RiscSignedWord)) as RiscWord;
             } else if func3 == 0b011 {
                 //sltiu
                 alu_out = (rs1 < imm) as RiscWord;
             } else if func3 == 0b100 {
                 //xori
                 alu_out = rs1 ^ imm;
-            } else if func3 == 0b101 && func7 == 0b0 {
-                //srli
-                alu_out = rs1 >> imm;
-            } else if func3 == 0b101 && func7 == 0b0100000 {
+            } else if func3 == 0b101 && func7 & FUNCT_6_MASK == 0b0 {
+                //srli (on RV64 the lowest bit of func7 is the 6th bit of shamt)
+                alu_out = rs1 >> (imm & SHAMT_MASK);
+            } else if func3 == 0b101 && func7 & FUNCT_6_MASK == 0b0100000 {
                 //srai
-                alu_out = (rs1 as i32 >> imm) as RiscWord;
+                alu_out = (rs1 as RiscSignedWord >> (imm & SHAMT_MASK)) as RiscWord;
             } else if func3 == 0b110 {
                 //ori
                 alu_out = rs1 | imm;
@@ -123,61 +159,291 @@ pub fn rv32_mcu_execute_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
                 alu_out = rs1 & imm;
             }
         }
+        OP_ALU_W => {
+            // RV64 only: operate on the lower 32 bits and sign extend the result
+            let (rs1, rs2) = (rs1 as u32, rs2 as u32);
+            if func3 == 0b000 && func7 == 0b0 {
+                //addw
+                alu_out = sign_extend(rs1.wrapping_add(rs2));
+            } else if func3 == 0b000 && func7 == 0b0100000 {
+                //subw
+                alu_out = sign_extend(rs1.wrapping_sub(rs2));
+            } else if func3 == 0b001 {
+                //sllw
+                alu_out = sign_extend(rs1 << (rs2 & SHAMT_W_MASK));
+            } else if func3 == 0b101 && func7 == 0b0 {
+                //srlw
+                alu_out = sign_extend(rs1 >> (rs2 & SHAMT_W_MASK));
+            } else if func3 == 0b101 && func7 == 0b0100000 {
+                //sraw
+                alu_out = sign_extend((rs1 as i32 >> (rs2 & SHAMT_W_MASK)) as u32);
+            }
+        }
+        OP_ALUI_W => {
+            let (rs1, imm) = (rs1 as u32, imm as u32);
+            if func3 == 0b000 {
+                //addiw
+                alu_out = sign_extend(rs1.wrapping_add(imm));
+            } else if func3 == 0b001 {
+                //slliw
+                alu_out = sign_extend(rs1 << (imm & SHAMT_W_MASK));
+            } else if func3 == 0b101 && func7 == 0b0 {
+                //srliw
+                alu_out = sign_extend(rs1 >> (imm & SHAMT_W_MASK));
+            } else if func3 == 0b101 && func7 == 0b0100000 {
+                //sraiw
+                alu_out = sign_extend((rs1 as i32 >> (imm & SHAMT_W_MASK)) as u32);
+            }
+        }
         OP_JAL => {
-            alu_out = pc + 4;
-            pc = (pc as i32 + imm as i32) as RiscWord;
-            take_jump = 0x1;
+            alu_out = pc.wrapping_add(4);
+            pc = pc.wrapping_add(imm);
+            take_jump = true;
         }
         OP_JALR => {
-            alu_out = pc + 4;
-            pc = ((rs1 as i32) + (imm as i32)) as RiscWord;
-            take_jump = 0x1;
+            alu_out = pc.wrapping_add(4);
+            pc = rs1.wrapping_add(imm);
+            take_jump = true;
         }
         OP_LOAD | OP_STORE => {
-            alu_out = (rs1 as i32 + imm as i32) as RiscWord;
+            alu_out = rs1.wrapping_add(imm);
+        }
+        OP_AMO => {
+            // atomics have no address offset
+            alu_out = rs1;
         }
         OP_BRANCH => {
-            pc = (pc as i32 + imm as i32) as RiscWord;
+            pc = pc.wrapping_add(imm);
             if func3 == 0b000 {
                 //beq
-                take_jump = (rs1 == rs2) as u8;
+                take_jump = rs1 == rs2;
             } else if func3 == 0b001 {
                 //bne
-                take_jump = (rs1 != rs2) as u8;
+                take_jump = rs1 != rs2;
             } else if func3 == 0b100 {
                 //blt
-                take_jump = ((rs1 as i32) < (rs2 as i32)) as u8;
+                take_jump = (rs1 as RiscSignedWord) < (rs2 as RiscSignedWord);
             } else if func3 == 0b101 {
                 //bge
-                take_jump = ((rs1 as i32) >= (rs2 as i32)) as u8;
+                take_jump = (rs1 as RiscSignedWord) >= (rs2 as RiscSignedWord);
             } else if func3 == 0b110 {
                 //bltu
-                take_jump = (rs1 < rs2) as u8;
+                take_jump = rs1 < rs2;
             } else if func3 == 0b111 {
                 //bgeu
-                take_jump = (rs1 >= rs2) as u8;
+                take_jump = rs1 >= rs2;
             }
         }
         OP_LUI => {
             alu_out = imm;
         }
         OP_AUIPC => {
-            alu_out = (pc as i32 + imm as i32) as RiscWord;
+            alu_out = pc.wrapping_add(imm);
+        }
+        OP_FENCE => {
+            // restart fetching right after the fence
+            pc = pc.wrapping_add(4);
+            take_jump = true;
+        }
+        OP_SYSTEM if op.mem_op == MEM_ECALL => {
+            // restart fetching right after the call, as for fences
+            pc = pc.wrapping_add(4);
+            take_jump = true;
+        }
+        OP_SYSTEM if op.mem_op == MEM_EBREAK => {
+            // WB needs the address of the breakpoint to look for the semihosting sequence around it
+            alu_out = pc;
+            pc = pc.wrapping_add(4);
+            take_jump = true;
+        }
+        OP_V | OP_LOAD_FP | OP_STORE_FP => {
+            // performed by MEM on the vector registers, only by harts listing Zve32x in their machine information
+            let implemented = rv32_core.vector.is_some() && rv32_core.csrs.read().unwrap().info.has_extension("Zve32x");
+            if implemented {
+                alu_out = rs1;
+            } else {
+                exception = Some(Exception::IllegalInstruction);
+            }
+        }
+        OP_SYSTEM if func3 != 0 && !hold => {
+            let csr = (imm & 0xFFF) as u16;
+            // for the immediate variants the rs1 field holds a 5 bit zero extended immediate
+            let operand = if func3 & FUNC3_CSR_IMM != 0 { op.rs1 as RiscWord } else { rs1 };
+            // csrrs/csrrc with x0 (or a zero immediate) only read the CSR
+            let writes = func3 & 0b11 == FUNC3_CSRRW || op.rs1 != 0;
+            let old_value = rv32_core.read_csr(csr);
+            let new_value = old_value.map(|old_value| match func3 & 0b11 {
+                FUNC3_CSRRS => old_value | operand,
+                FUNC3_CSRRC => old_value & !operand,
+                _ => operand,
+            });
+            let legal = match (old_value, new_value) {
+                (Some(_), Some(new_value)) if writes => rv32_core.write_csr(csr, new_value).is_some(),
+                (Some(_), _) => true,
+                _ => false,
+            };
+            if legal {
+                alu_out = old_value.unwrap();
+            } else {
+                exception = Some(Exception::IllegalInstruction);
+            }
         }
         _ => {}
     }
 
+    MicroOpResult { value: alu_out, target: pc, take_jump, exception }
+}
+
+pub fn rv32_mcu_execute_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
+    let opcode = pipeline_reg.get_u8(0x0);
+    let func3 = pipeline_reg.get_u8(0x1);
+    let func7 = pipeline_reg.get_u8(0x2);
+    let mut reg_write = pipeline_reg.get_u8(0x3);
+    let mut mem_read_write = pipeline_reg.get_u8(0x4);
+    let rd_address = pipeline_reg.get_u8(0x5);
+    let branch_or_jump = pipeline_reg.get_u8(0x6);
+
+    let imm: RiscWord = pipeline_reg.get_word(0x7);
+    let mut rs1: RiscWord = pipeline_reg.get_word(0x7 + XLEN_BYTES);
+    let mut rs2: RiscWord = pipeline_reg.get_word(0x7 + 2 * XLEN_BYTES);
+    let mut pc = pipeline_reg.get_word(0x7 + 3 * XLEN_BYTES);
+    // pc is replaced by the target of branches and jumps, WB still needs the address of the instruction it retires
+    let instruction_pc = pc;
 
-    let mut pipeline_out = vec![];
-    pipeline_out.push(reg_write);
-    pipeline_out.push(mem_read_write);
-    pipeline_out.push(rd_address);
-    pipeline_out.push(func3);
+    let rs1_address = pipeline_reg.get_u8(0x7 + 4 * XLEN_BYTES);
+    let rs2_address = pipeline_reg.get_u8(0x8 + 4 * XLEN_BYTES);
+    let instruction = pipeline_reg.get_u32(0x9 + 4 * XLEN_BYTES);
+    // ID waits for the older instructions to leave EX and MEM before halting in Debug Mode
+    let busy = instruction != 0 || mem_read_write == MEM_TRAP;
+
+    // check WB stage to get latest values for our registers
+    let wb_data = rv32_core.cdb.pull(WB_STAGE, EX_STAGE);
+    let wb_reg_write = wb_data.get_u8(0x0);
+    let wb_rd_address = wb_data.get_u8(0x1) & REG_MASK as u8;
+    // x0 is hardwired to zero, the results written to it (ex. the old value read by csrw) are not forwarded
+    let wb_reg_write = if wb_rd_address == 0 { 0x0 } else { wb_reg_write };
+    let wb_rd_value = wb_data.get_word(0x2);
+    if wb_reg_write == 0x1 && wb_rd_address == rs1_address {
+        rs1 = wb_rd_value;
+    }
+    if wb_reg_write == 0x1 && wb_rd_address == rs2_address {
+        rs2 = wb_rd_value;
+    }
+
+    // check MEM stage to get latest values for our registers
+    // MEM has higher priority on produced values, so we assign its values last
+    let mem_data = rv32_core.cdb.pull(MEM_STAGE, EX_STAGE);
+    let mem_reg_write = mem_data.get_u8(0x0);
+    let mem_rd_address = mem_data.get_u8(0x1) & REG_MASK as u8;
+    let mem_reg_write = if mem_rd_address == 0 { 0x0 } else { mem_reg_write };
+    let mem_rd_value = mem_data.get_word(0x2);
+    if mem_reg_write == 0x1 && mem_rd_address == rs1_address {
+        rs1 = mem_rd_value;
+    }
+    if mem_reg_write == 0x1 && mem_rd_address == rs2_address {
+        rs2 = mem_rd_value;
+    }
+    // while MEM waits for a slow memory this instruction is held and executed again, so CSRs are not accessed yet
+    let mem_hold = mem_data.get_u8(0x2 + XLEN_BYTES);
+    // a taken branch in MEM flushes this instruction, even while a multi-cycle operation runs for it
+    let mem_flush = mem_data.get_u8(0x3 + XLEN_BYTES);
+
+    let state = rv32_core.microarchitecture::<McuState>();
+    let timing = *state.timing.read().unwrap();
+    let mut wait = state.execute_wait.lock().unwrap();
+    for pending in wait.pending.iter_mut() {
+        *pending = pending.saturating_sub(1);
+    }
+    if mem_flush == 0x1 {
+        wait.remaining = 0;
+        wait.output = None;
+    }
+    let bubble = PipelineData(vec![0u8; 11 + 4 * XLEN_BYTES]);
+    if let Some(output) = wait.output.take() {
+        // the result is only released in a cycle in which MEM takes it
+        wait.remaining = wait.remaining.saturating_sub(1);
+        let done = wait.remaining == 0 && mem_hold == 0x0;
+        rv32_core.set_stage_ready(EX_STAGE, done);
+        assign_hazards(rv32_core, mem_read_write, rd_address, &wait.pending, busy);
+        if done {
+            return output;
+        }
+        wait.output = Some(output);
+        return bubble;
+    }
+    rv32_core.set_stage_ready(EX_STAGE, true);
+
+    let isa = state.isa_model();
+    let (alu_out, take_jump) = if mem_read_write == MEM_TRAP {
+        // fault of an earlier stage (ex. fetch), its cause is carried in FUNCT7 and mtval in the immediate
+        rs2 = imm;
+        (func7 as RiscWord, 0u8)
+    } else if mem_read_write == MEM_SIM_ERROR {
+        // not executed, MEM stops the run with the error of the decoder
+        (0, 0u8)
+    } else {
+        let op = MicroOp {
+            opcode,
+            func3,
+            func7,
+            rd: rd_address,
+            rs1: rs1_address,
+            rs2: rs2_address,
+            imm,
+            reg_write: reg_write == 0x1,
+            mem_op: mem_read_write,
+            branch_or_jump: branch_or_jump == 0x1,
+            ..Default::default()
+        };
+        let result = isa.execute(&op, Operands { rs1, rs2, pc, hold: mem_hold == 0x1 }, rv32_core);
+        pc = result.target;
+        match result.exception {
+            Some(exception) => {
+                // the cause is carried in place of the result and mtval in place of rs2
+                reg_write = 0x0;
+                mem_read_write = MEM_TRAP;
+                rs2 = 0;
+                (exception as RiscWord, result.take_jump as u8)
+            }
+            None => (result.value, result.take_jump as u8),
+        }
+    };
+
+    let mut pipeline_out = vec![reg_write, mem_read_write, rd_address, func3];
     pipeline_out.extend_from_slice(&alu_out.to_le_bytes());
     pipeline_out.extend_from_slice(&rs2.to_le_bytes());
     pipeline_out.push(branch_or_jump);
     pipeline_out.push(take_jump);
     pipeline_out.extend_from_slice(&pc.to_le_bytes());
+    pipeline_out.push(func7);
+    pipeline_out.extend_from_slice(&instruction.to_le_bytes());
+    pipeline_out.extend_from_slice(&instruction_pc.to_le_bytes());
+    let pipeline_out = PipelineData(pipeline_out);
 
-    PipelineData(pipeline_out)
+    // the instruction only starts once its operands are forwarded, which does not happen while MEM holds
+    let started = mem_hold == 0x0 && mem_flush == 0x0 && mem_read_write != MEM_TRAP && instruction != 0;
+    let timing = timing.of(InstructionClass::of(instruction));
+    if started && reg_write == 0x1 && rd_address != 0 {
+        wait.pending[rd_address as usize] = timing.latency - 1;
+    }
+    assign_hazards(rv32_core, mem_read_write, rd_address, &wait.pending, busy);
+    if started && timing.throughput > 1 {
+        wait.remaining = timing.throughput - 1;
+        wait.output = Some(pipeline_out);
+        rv32_core.set_stage_ready(EX_STAGE, false);
+        return bubble;
+    }
+    pipeline_out
+}
+
+/// ID stalls an instruction reading the destination of a load in EX, or a register whose result is still pending
+/// `busy` tells whether EX holds an instruction this cycle
+fn assign_hazards(rv32_core: &RiscCore, mem_read_write: u8, rd_address: u8, pending: &[u64; 32], busy: bool) {
+    let pending_mask = (0..32).filter(|register| pending[*register] > 0).fold(0u32, |mask, register| mask | 1 << register);
+    let mut id_data = vec![];
+    id_data.push(mem_read_write);
+    id_data.push(rd_address);
+    id_data.extend_from_slice(&pending_mask.to_le_bytes());
+    id_data.push(busy as u8);
+    rv32_core.cdb.assign(EX_STAGE, ID_STAGE, PipelineData(id_data));
 }
@@ -1,7 +1,6 @@
-use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryRequestType};
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest};
 use crate::risc_soc::pipeline_stage::PipelineData;
 use crate::risc_soc::risc_soc::RiscCore;
-use crate::risc_soc::risc_soc::WordSize;
 use crate::rv32i_baremetal::core::{IF_STAGE, MEM_STAGE};
 
 pub fn rv32_mcu_fetch_stage(_pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
@@ -12,23 +11,31 @@ pub fn rv32_mcu_fetch_stage(_pipeline_reg: &PipelineData, rv32_core: &RiscCore)
     let mem_data = rv32_core.cdb.pull(MEM_STAGE, IF_STAGE);
     let branch_or_jump = mem_data.get_u8(0x0);
     let take_jump = mem_data.get_u8(0x1);
-    let pc = mem_data.get_u32(0x2);
+    let pc = mem_data.get_word(0x2);
     if branch_or_jump & take_jump == 0x1 {
-        println!("branch taken");
+        tracing::debug!("branch taken");
         current_pc = pc;
         rv32_core.set_pc(current_pc);
     }
 
+    // while the line of the PC is on its way from memory a bubble is fetched and the PC is held
+    if !rv32_core.fetch_ready(current_pc) {
+        let mut bubble = 0x0u32.to_le_bytes().to_vec();
+        bubble.extend_from_slice(&current_pc.to_le_bytes());
+        bubble.extend_from_slice(&[0x0, 0x0]);
+        return PipelineData(bubble);
+    }
+
     //get instruction from the current address
-    let request = MemoryRequest {
-        request_type: MemoryRequestType::READ,
-        data_address: current_pc as Address,
-        data_size: WordSize::WORD,
-        data: None,
+    // on a fetch fault a bubble is fetched, together with the exception to raise if it reaches MEM
+    let (instruction, fault) = match rv32_core.fetch_request(MemoryRequest::read_u32(current_pc as Address)) {
+        Ok(response) => (response.as_u32(), None),
+        Err(exception) => (0x0, Some(exception)),
     };
-    let response = rv32_core.icache_request(request);
-    let mut instruction = response.data;
+    let mut instruction = instruction.to_le_bytes().to_vec();
     instruction.extend_from_slice(&current_pc.to_le_bytes());
+    instruction.push(fault.is_some() as u8);
+    instruction.push(fault.map_or(0, |exception| exception as u8));
 
-    return PipelineData(instruction);
+    PipelineData(instruction)
 }
@@ -0,0 +1,212 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+
+/// subset of the Intel/Sharp command set of parallel NOR flashes (CFI command set 0001)
+/// commands are written to any address of the flash, the address only matters for program and erase
+pub const FLASH_CMD_READ_ARRAY: u8 = 0xFF;
+pub const FLASH_CMD_READ_STATUS: u8 = 0x70;
+pub const FLASH_CMD_CLEAR_STATUS: u8 = 0x50;
+pub const FLASH_CMD_WORD_PROGRAM: u8 = 0x40;
+pub const FLASH_CMD_BLOCK_ERASE: u8 = 0x20;
+pub const FLASH_CMD_CONFIRM: u8 = 0xD0;
+
+/// status register bits, operations complete within their latency so the device always reads ready
+pub const FLASH_STATUS_READY: u8 = 1 << 7;
+pub const FLASH_STATUS_ERASE_ERROR: u8 = 1 << 5;
+pub const FLASH_STATUS_PROGRAM_ERROR: u8 = 1 << 4;
+pub const FLASH_STATUS_PROTECTED: u8 = 1 << 1;
+
+pub const FLASH_BLOCK_SIZE: usize = 0x1_0000;
+
+/// what the next access to the flash does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlashMode {
+    ReadArray,
+    ReadStatus,
+    /// the next write holds the data to program
+    ProgramSetup,
+    /// the next write must confirm the erase of the block it addresses
+    EraseSetup,
+}
+
+/// memory mapped NOR flash, code is executed in place and reads behave as a ROM until a command is written
+/// erase sets a whole block to 0xFF and programming can only clear bits, as on the real devices
+/// after a program or erase the flash answers with its status register until FLASH_CMD_READ_ARRAY is written
+pub struct Flash {
+    start_address: Address,
+    end_address: Address,
+    data: Vec<u8>,
+    mode: FlashMode,
+    status: u8,
+    /// address ranges refusing program and erase, ex. the bootloader
+    protected: Vec<(Address, Address)>,
+    /// core clock cycles taken by a word program and by a block erase
+    program_latency: u64,
+    erase_latency: u64,
+}
+
+impl Flash {
+    pub fn with_latency(mut self, program_latency: u64, erase_latency: u64) -> Self {
+        self.program_latency = program_latency;
+        self.erase_latency = erase_latency;
+        self
+    }
+
+    /// refuse program and erase operations touching [start_address, end_address)
+    pub fn with_write_protect(mut self, start_address: Address, end_address: Address) -> Self {
+        assert!(start_address >= self.start_address && end_address <= self.end_address && start_address < end_address);
+        self.protected.push((start_address, end_address));
+        self
+    }
+
+    fn is_protected(&self, start_address: Address, end_address: Address) -> bool {
+        self.protected.iter().any(|&(start, end)| start_address < end && end_address > start)
+    }
+
+    fn program(&mut self, offset: usize, bytes: &[u8]) {
+        let address = self.start_address + offset as Address;
+        if self.is_protected(address, address + bytes.len() as Address) {
+            self.status |= FLASH_STATUS_PROGRAM_ERROR | FLASH_STATUS_PROTECTED;
+            return;
+        }
+        for (cell, byte) in self.data[offset..offset + bytes.len()].iter_mut().zip(bytes) {
+            *cell &= byte;
+        }
+    }
+
+    fn erase(&mut self, offset: usize) {
+        let block = offset & !(FLASH_BLOCK_SIZE - 1);
+        let address = self.start_address + block as Address;
+        if self.is_protected(address, address + FLASH_BLOCK_SIZE as Address) {
+            self.status |= FLASH_STATUS_ERASE_ERROR | FLASH_STATUS_PROTECTED;
+            return;
+        }
+        self.data[block..block + FLASH_BLOCK_SIZE].fill(0xFF);
+    }
+}
+
+impl MemoryDevice for Flash {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::FLASH);
+        assert!(((end_address - start_address) as usize).is_multiple_of(FLASH_BLOCK_SIZE));
+        Self {
+            start_address,
+            end_address,
+            data: vec![0xFF; (end_address - start_address) as usize],
+            mode: FlashMode::ReadArray,
+            status: FLASH_STATUS_READY,
+            protected: vec![],
+            program_latency: 0,
+            erase_latency: 0,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.data.len() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        assert!(request.data.is_some());
+        let bytes = request.data.unwrap();
+        let command = bytes[0];
+        self.mode = match self.mode {
+            FlashMode::ProgramSetup => {
+                self.program(offset, &bytes[..size]);
+                FlashMode::ReadStatus
+            }
+            FlashMode::EraseSetup if command == FLASH_CMD_CONFIRM => {
+                self.erase(offset);
+                FlashMode::ReadStatus
+            }
+            FlashMode::EraseSetup => {
+                // any other command aborts the erase as a command sequence error
+                self.status |= FLASH_STATUS_ERASE_ERROR | FLASH_STATUS_PROGRAM_ERROR;
+                FlashMode::ReadStatus
+            }
+            _ => match command {
+                FLASH_CMD_READ_ARRAY => FlashMode::ReadArray,
+                FLASH_CMD_READ_STATUS => FlashMode::ReadStatus,
+                FLASH_CMD_CLEAR_STATUS => {
+                    self.status = FLASH_STATUS_READY;
+                    self.mode
+                }
+                FLASH_CMD_WORD_PROGRAM => FlashMode::ProgramSetup,
+                FLASH_CMD_BLOCK_ERASE => FlashMode::EraseSetup,
+                _ => self.mode,
+            },
+        };
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.data.len() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        let data = match self.mode {
+            FlashMode::ReadArray => MemoryData::new(&self.data[offset..offset + size]),
+            _ => {
+                let mut status = MemoryData::zeroed(size);
+                status[0] = self.status;
+                status
+            }
+        };
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::FLASH
+    }
+
+    /// the write performing a program or confirming an erase keeps the requester waiting for the whole operation
+    fn access_latency(&mut self, request: &MemoryRequest) -> u64 {
+        if request.request_type == MemoryRequestType::READ {
+            return 0;
+        }
+        let command = request.data.as_ref().map_or(0, |bytes| bytes[0]);
+        match self.mode {
+            FlashMode::ProgramSetup => self.program_latency,
+            FlashMode::EraseSetup if command == FLASH_CMD_CONFIRM => self.erase_latency,
+            _ => 0,
+        }
+    }
+
+    /// loads the image as the content of the array, ignoring write protection
+    fn init_mem(&mut self, address: Address, data: &[u8]) {
+        let offset = (address - self.start_address) as usize;
+        assert!(offset + data.len() <= self.data.len());
+        self.data[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn debug(&self, start_address: Address, end_address: Address) -> std::fmt::Result {
+        assert!(start_address >= self.start_address && end_address <= self.end_address);
+        println!("\nMemory {:?}: mode {:?}, status {:02X} {{", MemoryDeviceType::FLASH, self.mode, self.status);
+        let start = (start_address - self.start_address) as usize;
+        let end = (end_address - self.start_address) as usize;
+        for (line, words) in self.data[start..end].chunks(16).enumerate() {
+            print!("{:X}: ", start_address as usize + line * 16);
+            for byte in words {
+                print!("{:02X}", byte);
+            }
+            println!();
+        }
+        println!("}}");
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use crate::risc_soc::dtb::FdtBuilder;
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PixelFormat {
+    GRAY8,
+    RGB565,
+    XRGB8888,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::GRAY8 => 1,
+            PixelFormat::RGB565 => 2,
+            PixelFormat::XRGB8888 => 4,
+        }
+    }
+
+    /// name of the format in the simple-framebuffer devicetree binding
+    pub fn devicetree_format(&self) -> &'static str {
+        match self {
+            PixelFormat::GRAY8 => "r8",
+            PixelFormat::RGB565 => "r5g6b5",
+            PixelFormat::XRGB8888 => "x8r8g8b8",
+        }
+    }
+}
+
+/// resolution and pixel layout of the framebuffer memory, pixels are stored row by row in LE
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferMode {
+    pub width: usize,
+    pub height: usize,
+    pub format: PixelFormat,
+}
+
+impl FramebufferMode {
+    pub fn size(&self) -> usize {
+        self.width * self.height * self.format.bytes_per_pixel()
+    }
+}
+
+/// read access to the guest framebuffer for host code, ex. a window or a test checking the drawn image
+#[derive(Clone)]
+pub struct FramebufferView {
+    pixels: Arc<RwLock<Vec<u8>>>,
+    mode: FramebufferMode,
+}
+
+impl FramebufferView {
+    pub fn mode(&self) -> FramebufferMode {
+        self.mode
+    }
+
+    /// current frame converted to 0RGB pixels
+    pub fn to_xrgb(&self) -> Vec<u32> {
+        let pixels = self.pixels.read().unwrap();
+        let bpp = self.mode.format.bytes_per_pixel();
+        pixels
+            .chunks_exact(bpp)
+            .take(self.mode.width * self.mode.height)
+            .map(|pixel| match self.mode.format {
+                PixelFormat::GRAY8 => {
+                    let gray = pixel[0] as u32;
+                    (gray << 16) | (gray << 8) | gray
+                }
+                PixelFormat::RGB565 => {
+                    let value = u16::from_le_bytes([pixel[0], pixel[1]]) as u32;
+                    let red = ((value >> 11) & 0x1F) * 255 / 31;
+                    let green = ((value >> 5) & 0x3F) * 255 / 63;
+                    let blue = (value & 0x1F) * 255 / 31;
+                    (red << 16) | (green << 8) | blue
+                }
+                PixelFormat::XRGB8888 => {
+                    u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]) & 0x00FF_FFFF
+                }
+            })
+            .collect()
+    }
+
+    /// show the framebuffer in a host window refreshed at 60Hz, until the window is closed
+    #[cfg(feature = "window")]
+    pub fn open_window(&self, title: &str) -> std::thread::JoinHandle<()> {
+        let view = self.clone();
+        let title = title.to_string();
+        std::thread::spawn(move || {
+            let mode = view.mode();
+            let mut window = match minifb::Window::new(&title, mode.width, mode.height, minifb::WindowOptions::default()) {
+                Ok(window) => window,
+                Err(e) => {
+                    tracing::warn!("Could not open framebuffer window: {e}");
+                    return;
+                }
+            };
+            window.set_target_fps(60);
+            while window.is_open() {
+                if let Err(e) = window.update_with_buffer(&view.to_xrgb(), mode.width, mode.height) {
+                    tracing::warn!("Could not refresh framebuffer window: {e}");
+                    return;
+                }
+            }
+        })
+    }
+}
+
+/// linear framebuffer mapped in memory: the guest draws by storing pixels at `start_address`
+pub struct Framebuffer {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    view: FramebufferView,
+}
+
+impl Framebuffer {
+    /// change the resolution/format, the memory range of the device must be large enough for it
+    pub fn with_mode(mut self, width: usize, height: usize, format: PixelFormat) -> Self {
+        let mode = FramebufferMode { width, height, format };
+        assert!(
+            mode.size() <= self.size(),
+            "Framebuffer memory range is too small for a {width}x{height} {format:?} mode!"
+        );
+        self.view.mode = mode;
+        self
+    }
+
+    pub fn view(&self) -> FramebufferView {
+        self.view.clone()
+    }
+}
+
+impl MemoryDevice for Framebuffer {
+    /// defaults to 320x240 in RGB565, or the largest square that fits a smaller memory range
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::FRAMEBUFFER0);
+        let size = (end_address - start_address) as usize;
+        let mut mode = FramebufferMode { width: 320, height: 240, format: PixelFormat::RGB565 };
+        if mode.size() > size {
+            let side = ((size / 2) as f64).sqrt() as usize;
+            mode.width = side;
+            mode.height = side;
+        }
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            view: FramebufferView {
+                pixels: Arc::new(RwLock::new(vec![0u8; size])),
+                mode,
+            },
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.size() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        let data = request.data.unwrap();
+        self.view.pixels.write().unwrap()[offset..offset + size].copy_from_slice(&data[..size]);
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let offset = (request.data_address - self.start_address) as usize;
+        let size = request.data_size as usize;
+        if offset + size > self.size() {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        }
+        let data = MemoryData::new(&self.view.pixels.read().unwrap()[offset..offset + size]);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, address: Address, data: &[u8]) {
+        let offset = (address - self.start_address) as usize;
+        self.view.pixels.write().unwrap()[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    fn devicetree_properties(&self, fdt: &mut FdtBuilder) {
+        let mode = self.view.mode;
+        fdt.property_u32("width", mode.width as u32);
+        fdt.property_u32("height", mode.height as u32);
+        fdt.property_u32("stride", (mode.width * mode.format.bytes_per_pixel()) as u32);
+        fdt.property_string("format", mode.format.devicetree_format());
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!("\nFramebuffer {:?}: {{ mode={:?} }}", self.memory_type, self.view.mode);
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// register offsets of the GPIO block, all registers hold one bit per pin
+pub const GPIO_OUTPUT: Address = 0x0;
+/// 1 configures the pin as output, 0 as input
+pub const GPIO_DIRECTION: Address = 0x4;
+pub const GPIO_INPUT: Address = 0x8;
+pub const GPIO_IRQ_ENABLE: Address = 0xC;
+/// pins that saw an input edge since the last clear, write 1 to clear
+pub const GPIO_IRQ_STATUS: Address = 0x10;
+
+pub const GPIO_NUM_PINS: u32 = 32;
+
+/// registers of the GPIO block, for the traces of the accesses
+pub const GPIO_REGISTERS: &[Register] = &[
+    Register::new("OUTPUT", GPIO_OUTPUT, RegisterAccess::ReadWrite),
+    Register::new("DIRECTION", GPIO_DIRECTION, RegisterAccess::ReadWrite),
+    Register::new("INPUT", GPIO_INPUT, RegisterAccess::ReadOnly),
+    Register::new("IRQ_ENABLE", GPIO_IRQ_ENABLE, RegisterAccess::ReadWrite),
+    Register::new("IRQ_STATUS", GPIO_IRQ_STATUS, RegisterAccess::ReadWrite),
+];
+
+/// called with the pin index and its new level every time an output pin toggles
+pub type GpioCallback = Box<dyn Fn(u32, bool) + Send + Sync>;
+
+/// handle given to host code (ex. tests or a simulated button) to drive the input pins
+/// it stays usable after the device itself was moved into the MMU
+#[derive(Clone)]
+pub struct GpioInputs {
+    levels: Arc<AtomicU32>,
+    edges: Arc<AtomicU32>,
+    irq_enable: Arc<AtomicU32>,
+    interrupt_line: Arc<AtomicBool>,
+}
+
+impl GpioInputs {
+    pub fn set_pin(&self, pin: u32, level: bool) {
+        assert!(pin < GPIO_NUM_PINS);
+        let mask = 1 << pin;
+        let previous = if level {
+            self.levels.fetch_or(mask, Ordering::SeqCst)
+        } else {
+            self.levels.fetch_and(!mask, Ordering::SeqCst)
+        };
+        if (previous & mask != 0) != level {
+            let edges = self.edges.fetch_or(mask, Ordering::SeqCst) | mask;
+            if edges & self.irq_enable.load(Ordering::SeqCst) != 0 {
+                self.interrupt_line.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn get_pin(&self, pin: u32) -> bool {
+        assert!(pin < GPIO_NUM_PINS);
+        self.levels.load(Ordering::SeqCst) & (1 << pin) != 0
+    }
+}
+
+/// memory mapped GPIO block, callbacks and the input handle should be set up before adding it to the MMU:
+/// `let mut gpio = GPIO::new(MemoryDeviceType::GPIO0, 0x4000_0000, 0x4000_0100);`
+/// `gpio.on_output_change(Box::new(|pin, level| println!("LED{pin}={level}")));`
+/// `let buttons = gpio.inputs();`
+/// `core.mmu.write().unwrap().add_memory_device(Box::new(gpio));`
+pub struct GPIO {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    output: u32,
+    direction: u32,
+    inputs: GpioInputs,
+    callbacks: Vec<GpioCallback>,
+}
+
+impl GPIO {
+    /// register a host function to observe output pin toggles (ex. a simulated LED)
+    pub fn on_output_change(&mut self, callback: GpioCallback) {
+        self.callbacks.push(callback);
+    }
+
+    pub fn inputs(&self) -> GpioInputs {
+        self.inputs.clone()
+    }
+
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.inputs.interrupt_line.clone()
+    }
+
+    /// only pins configured as outputs are driven by the output register
+    fn driven_pins(&self) -> u32 {
+        self.output & self.direction
+    }
+
+    fn update_outputs(&mut self, output: u32, direction: u32) {
+        let previous = self.driven_pins();
+        self.output = output;
+        self.direction = direction;
+        let current = self.driven_pins();
+        let toggled = previous ^ current;
+        for pin in 0..GPIO_NUM_PINS {
+            if toggled & (1 << pin) != 0 {
+                let level = current & (1 << pin) != 0;
+                for callback in &self.callbacks {
+                    callback(pin, level);
+                }
+            }
+        }
+    }
+
+    fn update_interrupt_line(&self) {
+        let pending = self.inputs.edges.load(Ordering::SeqCst) & self.inputs.irq_enable.load(Ordering::SeqCst);
+        self.inputs.interrupt_line.store(pending != 0, Ordering::SeqCst);
+    }
+}
+
+impl MemoryDevice for GPIO {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::GPIO0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            output: 0,
+            direction: 0,
+            inputs: GpioInputs {
+                levels: Arc::new(AtomicU32::new(0)),
+                edges: Arc::new(AtomicU32::new(0)),
+                irq_enable: Arc::new(AtomicU32::new(0)),
+                interrupt_line: Arc::new(AtomicBool::new(false)),
+            },
+            callbacks: vec![],
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match request.data_address - self.start_address {
+            GPIO_OUTPUT => self.update_outputs(value, self.direction),
+            GPIO_DIRECTION => self.update_outputs(self.output, value),
+            GPIO_IRQ_ENABLE => self.inputs.irq_enable.store(value, Ordering::SeqCst),
+            GPIO_IRQ_STATUS => {
+                self.inputs.edges.fetch_and(!value, Ordering::SeqCst);
+            }
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        self.update_interrupt_line();
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let value = match request.data_address - self.start_address {
+            GPIO_OUTPUT => self.output,
+            GPIO_DIRECTION => self.direction,
+            // output pins read back the level they drive
+            GPIO_INPUT => {
+                (self.inputs.levels.load(Ordering::SeqCst) & !self.direction) | self.driven_pins()
+            }
+            GPIO_IRQ_ENABLE => self.inputs.irq_enable.load(Ordering::SeqCst),
+            GPIO_IRQ_STATUS => self.inputs.edges.load(Ordering::SeqCst),
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+            }
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn pending_irq(&self) -> bool {
+        self.inputs.interrupt_line.load(Ordering::SeqCst)
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nGPIO {:?}: {{ OUT={:08X} DIR={:08X} IN={:08X} IRQ_EN={:08X} IRQ_STATUS={:08X} }}",
+            self.memory_type,
+            self.output,
+            self.direction,
+            self.inputs.levels.load(Ordering::SeqCst),
+            self.inputs.irq_enable.load(Ordering::SeqCst),
+            self.inputs.edges.load(Ordering::SeqCst)
+        );
+        Ok(())
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(GPIO_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+}
@@ -0,0 +1,195 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+
+/// register offsets of the I2C master, modeled after the OpenCores i2c_master core
+/// TXRX holds the byte to transmit on write and the last received byte on read
+pub const I2C_TXRX: Address = 0x0;
+/// COMMAND on write, STATUS on read
+pub const I2C_COMMAND_STATUS: Address = 0x4;
+
+/// command register bits
+pub const CMD_START: u8 = 0x80;
+pub const CMD_STOP: u8 = 0x40;
+pub const CMD_READ: u8 = 0x20;
+pub const CMD_WRITE: u8 = 0x10;
+/// when reading, answer the received byte with a NACK (last byte of a read)
+pub const CMD_NACK: u8 = 0x08;
+
+/// status register bits, transfers complete immediately so transfer-in-progress is never set
+pub const STATUS_NO_ACK: u8 = 0x80;
+pub const STATUS_BUSY: u8 = 0x40;
+pub const STATUS_INTERRUPT: u8 = 0x01;
+
+/// a simulated target on the I2C bus (ex. an EEPROM or a sensor)
+pub trait I2cDevice: Send + Sync {
+    /// 7-bit bus address the target answers to
+    fn address(&self) -> u8;
+
+    /// the target was addressed after a (repeated) START, returns the ACK
+    fn start(&mut self, _read: bool) -> bool {
+        true
+    }
+
+    /// byte written by the master, returns the ACK
+    fn write(&mut self, byte: u8) -> bool;
+
+    /// byte requested by the master
+    fn read(&mut self) -> u8;
+
+    /// STOP condition on the bus
+    fn stop(&mut self) {}
+}
+
+pub struct I2cController {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    devices: Vec<Box<dyn I2cDevice>>,
+    /// target addressed by the current transaction
+    target: Option<usize>,
+    tx_data: u8,
+    rx_data: u8,
+    no_ack: bool,
+    busy: bool,
+    interrupt: bool,
+}
+
+impl I2cController {
+    pub fn attach_device(&mut self, device: Box<dyn I2cDevice>) {
+        assert!(
+            self.devices.iter().all(|d| d.address() != device.address()),
+            "There is already an I2C device at address {:X}!",
+            device.address()
+        );
+        self.devices.push(device);
+    }
+
+    fn command(&mut self, command: u8) {
+        if command & CMD_START != 0 && command & CMD_WRITE != 0 {
+            // address phase: the transmitted byte holds the target address and the R/W bit
+            let address = self.tx_data >> 1;
+            let read = self.tx_data & 0x1 == 0x1;
+            self.busy = true;
+            self.target = self.devices.iter().position(|d| d.address() == address);
+            self.no_ack = match self.target {
+                Some(index) => !self.devices[index].start(read),
+                None => true,
+            };
+        } else if command & CMD_WRITE != 0 {
+            self.no_ack = match self.target {
+                Some(index) => !self.devices[index].write(self.tx_data),
+                None => true,
+            };
+        } else if command & CMD_READ != 0 {
+            // the bus is pulled up when nobody drives it
+            self.rx_data = match self.target {
+                Some(index) => self.devices[index].read(),
+                None => 0xFF,
+            };
+        }
+
+        if command & CMD_STOP != 0 {
+            if let Some(index) = self.target.take() {
+                self.devices[index].stop();
+            }
+            self.busy = false;
+        }
+        self.interrupt = true;
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if self.no_ack {
+            status |= STATUS_NO_ACK;
+        }
+        if self.busy {
+            status |= STATUS_BUSY;
+        }
+        if self.interrupt {
+            status |= STATUS_INTERRUPT;
+        }
+        status
+    }
+}
+
+impl MemoryDevice for I2cController {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::I2C0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            devices: vec![],
+            target: None,
+            tx_data: 0,
+            rx_data: 0,
+            no_ack: false,
+            busy: false,
+            interrupt: false,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        let offset = request.data_address - self.start_address;
+        if request.request_type == MemoryRequestType::WRITE {
+            assert!(request.data.is_some());
+            let value = request.data.unwrap()[0];
+            match offset {
+                I2C_TXRX => self.tx_data = value,
+                I2C_COMMAND_STATUS => self.command(value),
+                _ => {
+                    return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+                }
+            }
+            MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+        } else {
+            let response = self.read_request(request);
+            if offset == I2C_COMMAND_STATUS {
+                // reading the status acknowledges the interrupt
+                self.interrupt = false;
+            }
+            response
+        }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let value = match request.data_address - self.start_address {
+            I2C_TXRX => self.rx_data,
+            I2C_COMMAND_STATUS => self.status(),
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+            }
+        };
+        let mut data = MemoryData::zeroed(request.data_size as usize);
+        data[0] = value;
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nI2C {:?}: {{ devices={} target={:?} STATUS={:X} }}",
+            self.memory_type,
+            self.devices.len(),
+            self.target,
+            self.status()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::rv32i_baremetal::i2c::I2cDevice;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// 24Cxx style EEPROM with a single byte word address
+/// the first byte written after START sets the word address, the following ones are stored inside the current page
+pub struct I2cEeprom {
+    bus_address: u8,
+    data: Vec<u8>,
+    page_size: usize,
+    word_address: usize,
+    address_set: bool,
+}
+
+impl I2cEeprom {
+    /// ex. a 24C02 is `I2cEeprom::new(0x50, 256, 8)`
+    pub fn new(bus_address: u8, size: usize, page_size: usize) -> Self {
+        assert!(size <= 256 && page_size > 0 && size.is_multiple_of(page_size));
+        Self {
+            bus_address,
+            data: vec![0xFF; size],
+            page_size,
+            word_address: 0,
+            address_set: false,
+        }
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl I2cDevice for I2cEeprom {
+    fn address(&self) -> u8 {
+        self.bus_address
+    }
+
+    fn start(&mut self, _read: bool) -> bool {
+        self.address_set = false;
+        true
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        if !self.address_set {
+            self.word_address = byte as usize % self.data.len();
+            self.address_set = true;
+        } else {
+            self.data[self.word_address] = byte;
+            let page = self.word_address - self.word_address % self.page_size;
+            self.word_address = page + (self.word_address + 1) % self.page_size;
+        }
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        // sequential reads roll over the whole array
+        let value = self.data[self.word_address];
+        self.word_address = (self.word_address + 1) % self.data.len();
+        value
+    }
+}
+
+/// LM75 compatible temperature sensor
+/// the temperature is set from the host through the handle returned by `temperature()`
+pub struct I2cTemperatureSensor {
+    bus_address: u8,
+    /// temperature in millidegrees Celsius
+    temperature: Arc<AtomicI32>,
+    pointer: u8,
+    pointer_set: bool,
+    /// byte of the 16-bit register currently read
+    read_index: usize,
+    config: u8,
+    hysteresis: u16,
+    overtemperature: u16,
+}
+
+pub const LM75_TEMPERATURE: u8 = 0x0;
+pub const LM75_CONFIG: u8 = 0x1;
+pub const LM75_HYSTERESIS: u8 = 0x2;
+pub const LM75_OVERTEMPERATURE: u8 = 0x3;
+
+impl I2cTemperatureSensor {
+    pub fn new(bus_address: u8, millidegrees: i32) -> Self {
+        Self {
+            bus_address,
+            temperature: Arc::new(AtomicI32::new(millidegrees)),
+            pointer: LM75_TEMPERATURE,
+            pointer_set: false,
+            read_index: 0,
+            config: 0,
+            // power on defaults of the LM75: 75C and 80C in 0.5C steps
+            hysteresis: 75 << 8,
+            overtemperature: 80 << 8,
+        }
+    }
+
+    pub fn temperature(&self) -> Arc<AtomicI32> {
+        self.temperature.clone()
+    }
+
+    /// registers are left aligned 9-bit two's complement values in 0.5C steps
+    fn register(&self) -> u16 {
+        match self.pointer {
+            LM75_TEMPERATURE => {
+                let half_degrees = self.temperature.load(Ordering::SeqCst) / 500;
+                ((half_degrees as i16) << 7) as u16
+            }
+            LM75_CONFIG => (self.config as u16) << 8,
+            LM75_HYSTERESIS => self.hysteresis,
+            _ => self.overtemperature,
+        }
+    }
+}
+
+impl I2cDevice for I2cTemperatureSensor {
+    fn address(&self) -> u8 {
+        self.bus_address
+    }
+
+    fn start(&mut self, _read: bool) -> bool {
+        self.pointer_set = false;
+        self.read_index = 0;
+        true
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        if !self.pointer_set {
+            self.pointer = byte & 0x3;
+            self.pointer_set = true;
+            self.read_index = 0;
+            return true;
+        }
+        match self.pointer {
+            LM75_CONFIG => self.config = byte,
+            LM75_HYSTERESIS | LM75_OVERTEMPERATURE => {
+                let register = if self.pointer == LM75_HYSTERESIS {
+                    &mut self.hysteresis
+                } else {
+                    &mut self.overtemperature
+                };
+                if self.read_index == 0 {
+                    *register = (byte as u16) << 8;
+                } else {
+                    *register |= (byte & 0x80) as u16;
+                }
+                self.read_index += 1;
+            }
+            // the temperature register is read only
+            _ => return false,
+        }
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        let register = self.register();
+        let value = if self.read_index.is_multiple_of(2) {
+            (register >> 8) as u8
+        } else {
+            register as u8
+        };
+        self.read_index += 1;
+        value
+    }
+}
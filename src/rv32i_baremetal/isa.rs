@@ -0,0 +1,19 @@
+use crate::risc_soc::isa_model::{IsaModel, MicroOp, MicroOpResult, Operands};
+use crate::risc_soc::risc_soc::RiscCore;
+use crate::risc_soc::sim_error::SimErrorKind;
+use crate::rv32i_baremetal::{decode, execute};
+
+/// RV32IMA (RV64IMA on 64-bit builds) with Zicsr, every instruction is a single micro-op
+/// custom extensions can wrap it and only decode and execute their own opcodes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RiscvIsa;
+
+impl IsaModel for RiscvIsa {
+    fn decode(&self, instruction: u32) -> Result<Vec<MicroOp>, SimErrorKind> {
+        decode::decode_instruction(instruction).map(|op| vec![op])
+    }
+
+    fn execute(&self, op: &MicroOp, operands: Operands, core: &RiscCore) -> MicroOpResult {
+        execute::execute_micro_op(op, operands, core)
+    }
+}
@@ -0,0 +1,91 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::rv32i_baremetal::core::{init_core, load_bytes};
+
+/// assembled ISA tests of the `isa_tests` directory, embedded so the tests run from any working directory
+const ISA_TESTS: [(&str, &[u8]); 4] = [
+    ("add.elf", include_bytes!("../../isa_tests/add.elf")),
+    ("branch.elf", include_bytes!("../../isa_tests/branch.elf")),
+    ("jump_and_return.elf", include_bytes!("../../isa_tests/jump_and_return.elf")),
+    ("memory.elf", include_bytes!("../../isa_tests/memory.elf")),
+];
+
+/// ELF image of one of the ISA tests, ex. "add.elf"
+pub fn isa_test_image(program: &str) -> &'static [u8] {
+    ISA_TESTS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, image)| *image)
+        .unwrap_or_else(|| panic!("{program} is not one of the embedded ISA tests"))
+}
+
+/// golden-state check of a program from `isa_tests`: run it for a number of clock cycles, then compare registers and memory
+/// every mismatch is reported at once, together with the register file, before failing the test
+/// ex. `IsaTest::new("add.elf").max_cycles(50).expect_reg("x3", 3).run();`
+pub struct IsaTest {
+    program: String,
+    max_cycles: u64,
+    init: fn(Option<u128>) -> RiscCore,
+    registers: Vec<(String, RiscWord)>,
+    memory: Vec<(Address, Vec<u8>)>,
+}
+
+impl IsaTest {
+    pub fn new(program: &str) -> Self {
+        Self { program: program.to_string(), max_cycles: 100, init: init_core, registers: vec![], memory: vec![] }
+    }
+
+    pub fn max_cycles(mut self, cycles: u64) -> Self {
+        self.max_cycles = cycles;
+        self
+    }
+
+    /// run on another core sharing the memory map of the MCU (ex. the out-of-order core)
+    pub fn on_core(mut self, init: fn(Option<u128>) -> RiscCore) -> Self {
+        self.init = init;
+        self
+    }
+
+    /// register given by its ABI or architectural name
+    pub fn expect_reg(mut self, name: &str, value: RiscWord) -> Self {
+        self.registers.push((name.to_string(), value));
+        self
+    }
+
+    pub fn expect_mem(mut self, address: Address, bytes: &[u8]) -> Self {
+        self.memory.push((address, bytes.to_vec()));
+        self
+    }
+
+    /// returns the core for any further check
+    pub fn run(self) -> RiscCore {
+        let mut core = (self.init)(None);
+        load_bytes(&mut core, isa_test_image(&self.program)).unwrap();
+        let stop_reason = core.run_for_cycles(self.max_cycles);
+
+        let mut mismatches = vec![];
+        for (name, expected) in &self.registers {
+            let value = core.read_reg_by_name(name);
+            if value != *expected {
+                mismatches.push(format!("{name} = 0x{value:X}, expected 0x{expected:X}"));
+            }
+        }
+        for (address, expected) in &self.memory {
+            let bytes = core.peek_memory(*address, expected.len());
+            if bytes.as_ref() != Some(expected) {
+                mismatches.push(format!("memory at 0x{address:X} = {bytes:02X?}, expected {expected:02X?}"));
+            }
+        }
+        if !mismatches.is_empty() {
+            panic!(
+                "{} stopped with {stop_reason:?} at pc 0x{:X} (trap: {:?}):\n{}\n{}",
+                self.program,
+                core.get_pc(),
+                core.pending_trap(),
+                mismatches.join("\n"),
+                core.registers
+            );
+        }
+        core
+    }
+}
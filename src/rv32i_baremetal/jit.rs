@@ -0,0 +1,316 @@
+//! Dynamic binary translation of RV32I basic blocks
+//! Instead of going through the pipeline, hot blocks are decoded once by the ISA model of the core into a chain of host closures,
+//! which are then executed directly on the architectural state of the core (registers, PC and memories)
+//! Memory accesses still go through the L1 memories of the core, so the memory device model is kept
+//! Blocks touching MMIO devices or faulting are dropped and their instructions are executed by the interpreter from then on
+
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::isa_model::{IsaModel, MicroOp, Operands};
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryResponse, MemoryResponseType};
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::rv32i_baremetal::core::McuState;
+use crate::rv32i_baremetal::decode::{MEM_FENCE, MEM_FENCE_I, MEM_LOAD, MEM_NONE, MEM_STORE};
+use crate::rv32i_baremetal::memory::{load_size, load_value, store_request};
+use ahash::{AHashMap, AHashSet};
+use std::sync::Arc;
+use std::fmt::Display;
+
+/// number of executions of a block start address before it gets translated
+pub const JIT_DEFAULT_THRESHOLD: u32 = 16;
+/// longest straight-line sequence translated as a single block
+pub const JIT_MAX_BLOCK_LEN: usize = 64;
+
+/// reasons for which execution cannot continue, neither translated nor interpreted
+#[derive(Debug)]
+pub enum JitFault {
+    IllegalInstruction { pc: Address, instruction: u32 },
+    /// left to the pipeline, ex. an ecall, an atomic or a vector instruction
+    Unsupported { pc: Address, instruction: u32 },
+    /// raised by the execution of the instruction, traps are only taken by the pipeline
+    Exception { pc: Address, exception: Exception },
+    /// instruction memory not mapped at the PC
+    FetchFault { pc: Address },
+    /// a load or store was not served by any memory
+    MemoryFault { pc: Address, address: Address, status: MemoryResponseType },
+}
+
+impl Display for JitFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JitFault::IllegalInstruction { pc, instruction } => {
+                write!(f, "illegal instruction 0x{instruction:08X} @{pc:X}")
+            }
+            JitFault::Unsupported { pc, instruction } => {
+                write!(f, "instruction 0x{instruction:08X} @{pc:X} is not supported by the JIT")
+            }
+            JitFault::Exception { pc, exception } => write!(f, "{exception:?} @{pc:X}"),
+            JitFault::FetchFault { pc } => write!(f, "no instruction memory @{pc:X}"),
+            JitFault::MemoryFault { pc, address, status } => {
+                write!(f, "memory access @{address:X} from {pc:X} failed with {status:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for JitFault {}
+
+/// instructions that may change the control flow end a basic block
+fn ends_block(op: &MicroOp) -> bool {
+    op.branch_or_jump || op.mem_op == MEM_FENCE_I
+}
+
+/// true if the address is held by one of the L1 memories of the core, anything else is treated as a device
+fn is_plain_memory(core: &RiscCore, address: Address) -> bool {
+    [&core.icache, &core.dcache].into_iter().flatten().any(|cache| {
+        let (start, end) = cache.read().unwrap().start_end_addresses();
+        address >= start && address < end
+    })
+}
+
+fn memory_access(
+    core: &RiscCore,
+    pc: Address,
+    request: MemoryRequest,
+) -> Result<MemoryResponse, JitFault> {
+    let address = request.data_address;
+    let response = core.dcache_request(request);
+    match response.status {
+        MemoryResponseType::CacheHit | MemoryResponseType::Valid => Ok(response),
+        status => Err(JitFault::MemoryFault { pc, address, status }),
+    }
+}
+
+/// execute a single micro-op on the architectural state and return the next PC
+/// the ALU, branches and CSRs are those of the ISA model of the pipeline, only the memory side is performed here in place of MEM and WB
+fn interpret(core: &RiscCore, isa: &dyn IsaModel, pc: RiscWord, instruction: u32, op: &MicroOp) -> Result<RiscWord, JitFault> {
+    let (rs1, rs2) = core.read_regs(op.rs1 as usize, op.rs2 as usize);
+    let result = isa.execute(op, Operands { rs1, rs2, pc, hold: false }, core);
+    if let Some(exception) = result.exception {
+        return Err(JitFault::Exception { pc: pc as Address, exception });
+    }
+    let value = match op.mem_op {
+        MEM_NONE | MEM_FENCE => result.value,
+        MEM_FENCE_I => {
+            core.fence_i();
+            result.value
+        }
+        MEM_LOAD => {
+            let request = MemoryRequest::read(result.value as Address, load_size(op.func3));
+            let response = memory_access(core, pc as Address, request)?;
+            load_value(op.func3, &response)
+        }
+        MEM_STORE => {
+            memory_access(core, pc as Address, store_request(op.func3, result.value as Address, rs2))?;
+            result.value
+        }
+        // traps, calls to the environment, atomics and vectors need the MEM and WB stages of the pipeline
+        _ => return Err(JitFault::Unsupported { pc: pc as Address, instruction }),
+    };
+    if op.reg_write {
+        core.write_reg(op.rd as usize, value);
+    }
+    Ok(if op.branch_or_jump && result.take_jump { result.target } else { pc.wrapping_add(4) })
+}
+
+/// outcome of one translated instruction
+enum Step {
+    Continue,
+    Jump(RiscWord),
+    /// the instruction was not executed and must be handled by the interpreter
+    Bail,
+}
+
+type Translated = Box<dyn Fn(&RiscCore) -> Step>;
+
+/// translate an instruction at a known PC into a closure with the decoding already resolved
+fn translate(isa: Arc<dyn IsaModel>, pc: RiscWord, instruction: u32, op: MicroOp) -> Translated {
+    let next = pc.wrapping_add(4);
+    let accesses_memory = op.mem_op == MEM_LOAD || op.mem_op == MEM_STORE;
+    Box::new(move |core: &RiscCore| {
+        if accesses_memory {
+            // device accesses are left to the interpreter
+            let (base, _) = core.read_regs(op.rs1 as usize, 0);
+            if !is_plain_memory(core, base.wrapping_add(op.imm) as Address) {
+                return Step::Bail;
+            }
+        }
+        match interpret(core, isa.as_ref(), pc, instruction, &op) {
+            Ok(target) if target == next => Step::Continue,
+            Ok(target) => Step::Jump(target),
+            Err(_) => Step::Bail,
+        }
+    })
+}
+
+struct Block {
+    ops: Vec<Translated>,
+    /// PC following the last instruction, used when the block ends without a jump
+    end: RiscWord,
+}
+
+pub struct JitEngine {
+    blocks: AHashMap<Address, Block>,
+    /// block start addresses which must always be interpreted
+    blacklist: AHashSet<Address>,
+    hotness: AHashMap<Address, u32>,
+    threshold: u32,
+    /// statistics of the last runs
+    pub translated_instructions: u64,
+    pub interpreted_instructions: u64,
+}
+
+impl Default for JitEngine {
+    fn default() -> Self {
+        Self::new(JIT_DEFAULT_THRESHOLD)
+    }
+}
+
+impl JitEngine {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            blocks: AHashMap::new(),
+            blacklist: AHashSet::new(),
+            hotness: AHashMap::new(),
+            threshold,
+            translated_instructions: 0,
+            interpreted_instructions: 0,
+        }
+    }
+
+    /// drop all translations, ex. after the program in memory was replaced
+    pub fn invalidate(&mut self) {
+        self.blocks.clear();
+        self.blacklist.clear();
+        self.hotness.clear();
+    }
+
+    fn fetch(core: &RiscCore, pc: RiscWord) -> Result<u32, JitFault> {
+        let bytes = core
+            .peek_memory(pc as Address, 4)
+            .ok_or(JitFault::FetchFault { pc: pc as Address })?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// micro-op of the instruction at pc, decoded by the ISA model of the pipeline through the decode cache of the core
+    fn decode_at(core: &RiscCore, isa: &dyn IsaModel, pc: RiscWord) -> Result<(u32, MicroOp), JitFault> {
+        let instruction = Self::fetch(core, pc)?;
+        match core.decode_cached(pc, instruction, |instruction| isa.decode(instruction)) {
+            Ok(ops) if ops.len() == 1 => Ok((instruction, ops[0])),
+            Ok(_) => Err(JitFault::Unsupported { pc: pc as Address, instruction }),
+            Err(_) => Err(JitFault::IllegalInstruction { pc: pc as Address, instruction }),
+        }
+    }
+
+    /// translate the basic block starting at pc, None if its first instruction cannot be translated
+    fn translate_block(core: &RiscCore, isa: &Arc<dyn IsaModel>, start: RiscWord) -> Option<Block> {
+        let mut ops = vec![];
+        let mut pc = start;
+        while ops.len() < JIT_MAX_BLOCK_LEN {
+            let Ok((instruction, op)) = Self::decode_at(core, isa.as_ref(), pc) else {
+                break;
+            };
+            // code placed in device memory (ex. a boot ROM) is always interpreted
+            if !is_plain_memory(core, pc as Address) {
+                break;
+            }
+            ops.push(translate(isa.clone(), pc, instruction, op));
+            pc = pc.wrapping_add(4);
+            if ends_block(&op) {
+                break;
+            }
+        }
+        if ops.is_empty() {
+            None
+        } else {
+            Some(Block { ops, end: pc })
+        }
+    }
+
+    fn interpret_one(&mut self, core: &RiscCore, isa: &dyn IsaModel, pc: RiscWord) -> Result<RiscWord, JitFault> {
+        self.interpreted_instructions += 1;
+        let (instruction, op) = Self::decode_at(core, isa, pc)?;
+        interpret(core, isa, pc, instruction, &op)
+    }
+
+    /// execute at most `max_instructions` starting from the current PC of the core, the PC is updated when returning
+    /// the pipeline is not used, so it must not hold any instruction in flight (ex. call this right after loading a program)
+    pub fn run(&mut self, core: &RiscCore, max_instructions: u64) -> Result<u64, JitFault> {
+        let isa = core.microarchitecture::<McuState>().isa_model();
+        let mut pc = core.get_pc();
+        let mut executed = 0;
+        let result = loop {
+            if executed >= max_instructions {
+                break Ok(executed);
+            }
+            let start = pc as Address;
+            if let Some(block) = self.blocks.get(&start) {
+                let mut next = block.end;
+                let mut bailed_at = None;
+                let block_start_count = executed;
+                for (i, op) in block.ops.iter().enumerate() {
+                    if executed >= max_instructions {
+                        next = (start as RiscWord).wrapping_add((i as RiscWord) * 4);
+                        break;
+                    }
+                    match op(core) {
+                        Step::Continue => executed += 1,
+                        Step::Jump(target) => {
+                            executed += 1;
+                            next = target;
+                            break;
+                        }
+                        Step::Bail => {
+                            bailed_at = Some((start as RiscWord).wrapping_add((i as RiscWord) * 4));
+                            break;
+                        }
+                    }
+                }
+                self.translated_instructions += executed - block_start_count;
+                pc = next;
+                if let Some(bail_pc) = bailed_at {
+                    // the block touches a device or faults, keep interpreting it from now on
+                    self.blocks.remove(&start);
+                    self.blacklist.insert(start);
+                    match self.interpret_one(core, isa.as_ref(), bail_pc) {
+                        Ok(target) => {
+                            executed += 1;
+                            pc = target;
+                        }
+                        Err(fault) => {
+                            pc = bail_pc;
+                            break Err(fault);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if !self.blacklist.contains(&start) {
+                let count = self.hotness.entry(start).or_insert(0);
+                *count += 1;
+                if *count >= self.threshold {
+                    self.hotness.remove(&start);
+                    match Self::translate_block(core, &isa, pc) {
+                        Some(block) => {
+                            self.blocks.insert(start, block);
+                            continue;
+                        }
+                        None => {
+                            self.blacklist.insert(start);
+                        }
+                    }
+                }
+            }
+
+            match self.interpret_one(core, isa.as_ref(), pc) {
+                Ok(target) => {
+                    executed += 1;
+                    pc = target;
+                }
+                Err(fault) => break Err(fault),
+            }
+        };
+        core.set_pc(pc);
+        result
+    }
+}
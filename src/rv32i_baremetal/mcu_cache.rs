@@ -1,7 +1,7 @@
 use crate::risc_soc::memory_management_unit::{MemoryResponseType};
 use crate::risc_soc::{
     memory_management_unit::{
-        Address, MemoryDevice, MemoryDeviceType, MemoryRequest,
+        Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest,
         MemoryRequestType, MemoryResponse,
     },
     risc_soc::WordSize,
@@ -67,55 +67,48 @@ impl MemoryDevice for MCUCache {
 
     #[inline]
     fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
-
-        let response;
         if request.request_type == MemoryRequestType::READ {
-            response = self.read_request(request);
-        } else {
-            let data = match request.data {
-                Some(mut d) => {
-                    if d.len() == 0 || d.len() < request.data_size as usize {
-                        panic!("Trying to store less data then requested in cache memory!");
-                    }
-                    if (request.data_size as usize) < d.len() {
-                        d.truncate(request.data_size as usize);
-                    }
-                    d
-                }
-                None => {
-                    panic!("Made a request to store no data in cache memory!");
-                }
-            };
-            let cache_response = self.store_data(request.data_address, data);
-            response = MemoryResponse{
-                data: vec![],
-                status: cache_response.status
-            }
+            return self.read_request(request);
+        }
+        if let Err(reason) = request.check() {
+            return MemoryResponse::bad_request(reason);
+        }
+        let mut data = request.data.unwrap();
+        data.truncate(request.data_size as usize);
+        let cache_response = self.store_data(request.data_address, &data);
+        MemoryResponse{
+            data: MemoryData::default(),
+            status: cache_response.status
         }
-        return response;
     }
 
     //read only request available to not lock core for write
     #[inline]
     fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
         assert!(request.request_type == MemoryRequestType::READ);
-        let cache_response = self.load_data(request.data_address);
-        let byte_index = (request.data_address - self.start_address) % self.line_size as u64; 
-        let mut data = vec![0u8; request.data_size as usize];
+        // only the requested bytes are copied out of the line, not the whole line as in load_data
+        let cache_response = self.translate_address(request.data_address);
+        let mut data = MemoryData::zeroed(request.data_size as usize);
         if cache_response.status == MemoryResponseType::CacheHit { 
-            for i in 0..request.data_size as usize{
-                data[i] = cache_response.cache_line[byte_index as usize + i];
-            }
+            let byte_index = ((request.data_address - self.start_address) % self.line_size as u64) as usize;
+            let line = &self.data[cache_response.index as usize];
+            data.copy_from_slice(&line[byte_index..byte_index + request.data_size as usize]);
         }
         MemoryResponse { data, status: cache_response.status }
     }
 
     fn init_mem(&mut self, address: Address, data: &[u8]) {
-        for byte in 0..data.len() {
+        for (byte, value) in data.iter().enumerate() {
             let current_address = address as usize + byte;
             let byte_index = current_address % self.line_size;
             let row_index = current_address / self.line_size;
-            self.data[row_index][byte_index] = data[byte];
+            self.data[row_index][byte_index] = *value;
+        }
+    }
+
+    fn reset(&mut self, clear_memory: bool) {
+        if clear_memory {
+            self.data.iter_mut().for_each(|line| line.fill(0));
         }
     }
 
@@ -134,7 +127,7 @@ impl MemoryDevice for MCUCache {
                 let current_line = (current_line - self.start_address) as usize;
                 print!("{:X}", self.data[current_line][w]);
             }
-            print!("\n")
+            println!()
         }
         println!("}}");
         Ok(())
@@ -190,22 +183,19 @@ impl Cache for MCUCache {
 
     /// for data store, is the other way: we receive a byte array and its size and we store it in the memory
     /// its the job of the processor to give as an exact array, but if it passes a larger array, we use the provided size to store the needed amount
-    fn store_data(&mut self, address: Address, data: Vec<u8>) -> CacheResponse {
+    fn store_data(&mut self, address: Address, data: &[u8]) -> CacheResponse {
         let mut response = self.translate_address(address);
         if response.status == MemoryResponseType::CacheHit {
             let byte_index = address % self.line_size as u64;
-            if (address - self.start_address + (data.len() as Address) - 1)
-                >= (response.index + self.line_size as Address)
-            {
+            if byte_index as usize + data.len() > self.line_size {
                 response.index = 0;
                 response.status = MemoryResponseType::UnalignedAddress;
                 return response;
             }
 
-            for i in 0..data.len() {
-                //we respect the LE here: MSB on higher addresses in both cache memory and returned vector of bytes
-                self.data[response.index as usize][byte_index as usize + i] = data[i];
-            }
+            //we respect the LE here: MSB on higher addresses in both cache memory and returned vector of bytes
+            let start = byte_index as usize;
+            self.data[response.index as usize][start..start + data.len()].copy_from_slice(data);
         }
         response
     }
@@ -214,7 +204,7 @@ impl Cache for MCUCache {
     /// So we are using the start and end address to define the memory regions for .text and .data sections
     /// And whatever Virtual Address we are receiving, we are subtractng the defined start address from it
     fn translate_address(&self, address: Address) -> CacheResponse {
-        if address > self.end_address || address < self.start_address {
+        if address >= self.end_address || address < self.start_address {
             return CacheResponse {
                 cache_line: vec![],
                 index: 0,
@@ -1,8 +1,25 @@
-use crate::risc_soc::memory_management_unit::MemoryRequestType;
-use crate::risc_soc::memory_management_unit::{Address, MemoryRequest};
-use crate::risc_soc::risc_soc::{RiscCore, WordSize};
+use crate::risc_soc::csr::DebugCause;
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryResponse, MemoryResponseType};
+use crate::risc_soc::risc_soc::{RiscCore, WordSize, XLEN_BYTES, zero_extend_word};
+use crate::risc_soc::sim_error::SimErrorKind;
+use crate::risc_soc::trigger::{MCONTROL_LOAD, MCONTROL_STORE, TriggerAction};
 use crate::risc_soc::{pipeline_stage::PipelineData, risc_soc::RiscWord};
-use crate::rv32i_baremetal::core::{EX_STAGE, IF_STAGE, MEM_STAGE, ID_STAGE};
+use crate::rv32i_baremetal::core::{EX_STAGE, IF_STAGE, MEM_STAGE, ID_STAGE, McuState};
+use crate::rv32i_baremetal::vector::execute_vector;
+use crate::rv32i_baremetal::decode::{
+    AMO_ADD, AMO_AND, AMO_LR, AMO_MAX, AMO_MAXU, AMO_MIN, AMO_MINU, AMO_OR, AMO_SC, AMO_SWAP, AMO_XOR, MEM_AMO,
+    MEM_EBREAK, MEM_ECALL, MEM_FENCE, MEM_FENCE_I, MEM_LOAD, MEM_NONE, MEM_SIM_ERROR, MEM_STORE, MEM_TRAP, MEM_VECTOR, sign_extend,
+};
+
+/// access of MEM waiting for a slow memory (ex. DRAM), MEM is not ready until it completes so the stages before it stall
+#[derive(Default)]
+pub struct MemoryWait {
+    /// clock cycles left before the access completes
+    remaining: u64,
+    /// output of MEM once the access completes
+    output: PipelineData,
+}
 
 pub fn rv32_mcu_mem_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
     
@@ -10,81 +27,133 @@ pub fn rv32_mcu_mem_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) ->
     let mem_read_write = pipeline_reg.get_u8(0x1);
     let rd_address = pipeline_reg.get_u8(0x2);
     let func3 = pipeline_reg.get_u8(0x3);
-    let alu_out = pipeline_reg.get_u32(0x4);
-    let rs2 = pipeline_reg.get_u32(0x8);
-    let branch_or_jump = pipeline_reg.get_u8(0xC);
-    let take_jump = pipeline_reg.get_u8(0xD);
-    let pc = pipeline_reg.get_u32(0xE);
+    let alu_out = pipeline_reg.get_word(0x4);
+    let rs2: RiscWord = pipeline_reg.get_word(0x4 + XLEN_BYTES);
+    let branch_or_jump = pipeline_reg.get_u8(0x4 + 2 * XLEN_BYTES);
+    let take_jump = pipeline_reg.get_u8(0x5 + 2 * XLEN_BYTES);
+    let pc = pipeline_reg.get_word(0x6 + 2 * XLEN_BYTES);
+    let func7 = pipeline_reg.get_u8(0x6 + 3 * XLEN_BYTES);
+    let instruction = pipeline_reg.get_u32(0x7 + 3 * XLEN_BYTES);
+    let instruction_pc = pipeline_reg.get_word(0xB + 3 * XLEN_BYTES);
+
+    // while a slow access is pending, the instruction waiting in EX is not performed yet
+    let mut wait = rv32_core.microarchitecture::<McuState>().memory_wait.lock().unwrap();
+    let hold = wait.remaining > 0;
+    // the instruction waiting in EX is consumed in the cycle after the result of the access is released
+    rv32_core.set_stage_ready(MEM_STAGE, !hold);
+    if !hold {
+        rv32_core.data_access_pc.store(pc as Address, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    // a trigger on the accessed address fires before the access, which is then not performed
+    // entering Debug Mode flushes the younger instructions from EX, they are fetched again once the hart resumes
+    let accesses = match mem_read_write {
+        MEM_LOAD => MCONTROL_LOAD,
+        MEM_STORE => MCONTROL_STORE,
+        MEM_AMO => MCONTROL_LOAD | MCONTROL_STORE,
+        _ => 0,
+    };
+    let trigger = if hold || accesses == 0 { None } else { rv32_core.check_triggers(accesses, alu_out) };
+    match trigger {
+        Some(TriggerAction::Breakpoint) => rv32_core.raise_exception(Exception::Breakpoint, alu_out as Address),
+        Some(TriggerAction::DebugMode) => rv32_core.enter_debug_mode(pc, DebugCause::Trigger),
+        None => {}
+    }
+    let (reg_write, mem_read_write) = if trigger.is_some() { (0x0, MEM_NONE) } else { (reg_write, mem_read_write) };
+
+    // vector instructions are performed before driving the wires, so their scalar result is forwarded to EX in place of
+    // the scalar operand, and the CSR accesses of EX in this cycle see the vl and vtype they set
+    let (alu_out, vector_latency) = if mem_read_write == MEM_VECTOR && !hold {
+        execute_vector(rv32_core, instruction, alu_out, rs2)
+    } else {
+        (alu_out, 0)
+    };
 
     //send info about branch to IF and ID
     let mut if_data = vec![];
-    if_data.push(branch_or_jump);
+    if_data.push(branch_or_jump & !hold as u8);
     if_data.push(take_jump);
     if_data.extend_from_slice(&pc.to_le_bytes());
-    let wire_data = PipelineData(if_data);
-    rv32_core.cdb.assign(MEM_STAGE, IF_STAGE, wire_data.clone());
-    rv32_core.cdb.assign(MEM_STAGE, ID_STAGE, wire_data);
+    rv32_core.cdb.assign(MEM_STAGE, IF_STAGE, PipelineData(if_data.clone()));
+    // ID also needs to know whether an instruction is still in MEM before halting in Debug Mode
+    let mut id_data = if_data;
+    id_data.push((hold || instruction != 0 || mem_read_write == MEM_TRAP) as u8);
+    rv32_core.cdb.assign(MEM_STAGE, ID_STAGE, PipelineData(id_data));
 
     // send MEM info to EX stage for forwarding
     let mut ex_data = vec![];
-    ex_data.push(reg_write);
+    ex_data.push(reg_write & !hold as u8);
     ex_data.push(rd_address);
     ex_data.extend_from_slice(&alu_out.to_le_bytes());
+    ex_data.push(hold as u8);
+    ex_data.push(branch_or_jump & take_jump & !hold as u8 | (trigger == Some(TriggerAction::DebugMode)) as u8);
     let ex_data = PipelineData(ex_data);
     rv32_core.cdb.assign(MEM_STAGE, EX_STAGE, ex_data);
 
+    let bubble = PipelineData(vec![0u8; 7 + 3 * XLEN_BYTES]);
+    if hold {
+        // bubbles go to WB until the latency elapsed, then the result of the access is released
+        wait.remaining -= 1;
+        return if wait.remaining > 0 { bubble } else { std::mem::take(&mut wait.output) };
+    }
+
+    // the store buffer writes to memory in the cycles in which this stage does not use the data port
+    rv32_core.store_buffer_cycle(![MEM_LOAD, MEM_STORE, MEM_AMO, MEM_VECTOR].contains(&mem_read_write));
+
     let mut mem_value = 0x0;
     let mut reg_src = 0x0;
-    if mem_read_write == 0x1 {
+    let mut latency = 0;
+    if mem_read_write == MEM_LOAD {
         //load
-        let data_size = match func3 {
-            0x0 | 0x4 => WordSize::BYTE,
-            0x1 | 0x5 => WordSize::HALF,
-            _ => WordSize::WORD,
-        };
+        let data_size = load_size(func3);
         
-        //get instruction from the current address
-        let request = MemoryRequest {
-            request_type: MemoryRequestType::READ,
-            data_address: alu_out as Address,
-            data_size,
-            data: None,
-        };
-        
-        let response = rv32_core.dcache_request(request);
-        let data = response.data;
-        assert!(data.len() == data_size as usize);
-
-        mem_value = match func3 {
-            0x0 => data[0].cast_signed() as i32 as RiscWord,
-            0x4 => data[0] as RiscWord,
-            0x1 => (((data[1] as u16) << 8) | (data[0] as u16)) as i32 as RiscWord,
-            0x5 => (((data[1] as u16) << 8) | (data[0] as u16)) as RiscWord,
-            _ => {
-                ((data[3] as u32) << 24)
-                    | ((data[2] as u32) << 16)
-                    | ((data[1] as u32) << 8)
-                    | (data[0] as u32) as RiscWord
-            }
-        };
+        let request = MemoryRequest::read(alu_out as Address, data_size);
+        latency = rv32_core.drain_overlapping_stores(alu_out as Address, data_size as usize);
+        latency += rv32_core.data_access_latency(&request);
+        let response = rv32_core.data_request(request);
+        // misaligned (under the Trap policy) and denied loads raised an exception, the core stops at the end of this cycle
+        if response.status == MemoryResponseType::CacheHit || response.status == MemoryResponseType::Valid {
+            assert!(response.data.len() == data_size as usize);
+            mem_value = load_value(func3, &response);
+        }
         reg_src = 0x1;
-    } else if mem_read_write == 0x3 {
+    } else if mem_read_write == MEM_STORE {
         //store
-        let (data_size, data) = match func3 {
-            0x0 => (WordSize::BYTE, rs2 & 0xFF),
-            0x1 => (WordSize::HALF, rs2 & 0xFFFF),
-            _ => (WordSize::WORD, rs2),
-        };
-        
-        //get instruction from the current address
-        let request = MemoryRequest {
-            request_type: MemoryRequestType::WRITE,
-            data_address: alu_out as Address,
-            data_size,
-            data: Some(data.to_le_bytes().to_vec()),
-        };
-
-        rv32_core.dcache_request(request);
+        let request = store_request(func3, alu_out as Address, rs2);
+        latency = rv32_core.buffered_store(request);
+    } else if mem_read_write == MEM_FENCE {
+        // loads and stores are performed in order in this stage, only the buffered stores are not visible yet
+        // the pipeline flush requested through the branch signals drains the younger instructions
+        latency = rv32_core.drain_store_buffer(None);
+    } else if mem_read_write == MEM_FENCE_I {
+        // make previous stores visible to instruction fetch, the flush then refetches the following instructions
+        latency = rv32_core.drain_store_buffer(None);
+        rv32_core.fence_i();
+    } else if mem_read_write == MEM_AMO {
+        latency = rv32_core.drain_store_buffer(None);
+        latency += rv32_core.data_access_latency(&MemoryRequest::read(alu_out as Address, WordSize::WORD));
+        mem_value = atomic_access(rv32_core, alu_out as Address, rs2, func3, func7 >> 2, instruction, pc);
+        reg_src = 0x1;
+    } else if mem_read_write == MEM_ECALL {
+        // performed by WB, once the older instruction in WB wrote its result
+        // the handler may read the memory written by the program, so the buffered stores are drained first
+        latency = rv32_core.drain_store_buffer(None);
+        reg_src = 0x2;
+    } else if mem_read_write == MEM_EBREAK {
+        // performed by WB as well, the address of the breakpoint is passed in place of the ALU result
+        latency = rv32_core.drain_store_buffer(None);
+        reg_src = 0x3;
+    } else if mem_read_write == MEM_VECTOR {
+        latency = vector_latency;
+    } else if mem_read_write == MEM_TRAP {
+        let exception = Exception::from_cause(zero_extend_word(alu_out)).unwrap();
+        rv32_core.raise_exception(exception, rs2 as Address);
+    } else if mem_read_write == MEM_SIM_ERROR && !hold {
+        let mut decode_errors = rv32_core.microarchitecture::<McuState>().decode_errors.lock().unwrap();
+        let error = decode_errors.iter().rposition(|(error_pc, _)| *error_pc == pc).map(|index| decode_errors.remove(index).1);
+        if let Some(kind) = error {
+            rv32_core.raise_sim_error(kind, Some(pc));
+        }
     }
 
     let mut pipeline_out = vec![];
@@ -93,6 +162,110 @@ pub fn rv32_mcu_mem_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) ->
     pipeline_out.push(rd_address);
     pipeline_out.extend_from_slice(&alu_out.to_le_bytes());
     pipeline_out.extend_from_slice(&mem_value.to_le_bytes());
+    pipeline_out.extend_from_slice(&instruction_pc.to_le_bytes());
+    // a trapping instruction does not retire, nor does one stopped by a trigger or ID failed to decode
+    let retired = if mem_read_write == MEM_TRAP || mem_read_write == MEM_SIM_ERROR || trigger.is_some() { 0x0 } else { instruction };
+    pipeline_out.extend_from_slice(&retired.to_le_bytes());
+    let pipeline_out = PipelineData(pipeline_out);
 
-    PipelineData(pipeline_out)
+    // the younger instructions still advance this cycle, from the next one on they are held until the access completes
+    if latency > 0 {
+        wait.remaining = latency;
+        wait.output = pipeline_out;
+        return bubble;
+    }
+    pipeline_out
+}
+
+/// size of the access of a load or store, from its func3
+pub fn load_size(func3: u8) -> WordSize {
+    match func3 {
+        0x0 | 0x4 => WordSize::BYTE,
+        0x1 | 0x5 => WordSize::HALF,
+        // LD and SD are only decoded on RV64
+        0x3 if XLEN_BYTES == 8 => WordSize::DOUBLE,
+        _ => WordSize::WORD,
+    }
+}
+
+/// value written to rd by a load, sign or zero extended from the data returned by the memory
+pub fn load_value(func3: u8, response: &MemoryResponse) -> RiscWord {
+    match func3 {
+        0x0 => sign_extend(response.as_u8().cast_signed() as i32 as u32),
+        0x4 => response.as_u8() as RiscWord,
+        0x1 => sign_extend(response.as_u16().cast_signed() as i32 as u32),
+        0x5 => response.as_u16() as RiscWord,
+        //lwu (RV64 only)
+        0x6 if XLEN_BYTES == 8 => response.as_u32() as RiscWord,
+        //ld (RV64 only)
+        0x3 if XLEN_BYTES == 8 => response.as_u64() as RiscWord,
+        _ => sign_extend(response.as_u32()),
+    }
+}
+
+/// write of the lower bytes of rs2 performed by a store
+pub fn store_request(func3: u8, address: Address, value: RiscWord) -> MemoryRequest {
+    let rs2: RiscWord = value;
+    match func3 {
+        0x0 => MemoryRequest::write_u8(address, rs2 as u8),
+        0x1 => MemoryRequest::write_u16(address, rs2 as u16),
+        //sd (RV64 only)
+        0x3 if XLEN_BYTES == 8 => MemoryRequest::write_u64(address, rs2 as u64),
+        _ => MemoryRequest::write_u32(address, rs2 as u32),
+    }
+}
+
+/// LR/SC and AMOs on words (and double words on RV64), returning the value written to rd
+/// the aq/rl bits are ignored, as every access of this stage is already performed in program order
+fn atomic_access(
+    rv32_core: &RiscCore,
+    address: Address,
+    rs2: RiscWord,
+    func3: u8,
+    funct5: u8,
+    instruction: u32,
+    pc: RiscWord,
+) -> RiscWord {
+    let size = if func3 == 0x3 && XLEN_BYTES == 8 { WordSize::DOUBLE } else { WordSize::WORD };
+    // operands and results are sign extended from the accessed width
+    let extend = |value: u64| match size {
+        WordSize::DOUBLE => value as RiscWord,
+        _ => sign_extend(value as u32),
+    };
+    let signed = |value: u64| match size {
+        WordSize::DOUBLE => value as i64,
+        _ => value as u32 as i32 as i64,
+    };
+    let operand = zero_extend_word(rs2);
+    let response = match funct5 {
+        AMO_LR => rv32_core.load_reserved(address, size),
+        AMO_SC => {
+            let stored = rv32_core.store_conditional(address, &operand.to_le_bytes()[..size as usize]);
+            // rd is 0 on success and non zero on failure
+            return (!stored) as RiscWord;
+        }
+        _ => rv32_core.atomic_request(address, size, |old| match funct5 {
+            AMO_SWAP => operand,
+            AMO_ADD => old.wrapping_add(operand),
+            AMO_XOR => old ^ operand,
+            AMO_AND => old & operand,
+            AMO_OR => old | operand,
+            AMO_MIN => if signed(old) <= signed(operand) { old } else { operand },
+            AMO_MAX => if signed(old) >= signed(operand) { old } else { operand },
+            AMO_MINU => if extend(old) <= extend(operand) { old } else { operand },
+            AMO_MAXU => if extend(old) >= extend(operand) { old } else { operand },
+            _ => {
+                let reason = format!("unknown AMO {funct5:#b}");
+                rv32_core.raise_sim_error(SimErrorKind::UnsupportedOperation { instruction, reason }, Some(pc));
+                old
+            }
+        }),
+    };
+    if response.status != MemoryResponseType::CacheHit && response.status != MemoryResponseType::Valid {
+        return 0;
+    }
+    match size {
+        WordSize::DOUBLE => extend(response.as_u64()),
+        _ => extend(response.as_u32() as u64),
+    }
 }
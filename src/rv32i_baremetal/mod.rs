@@ -1,8 +1,39 @@
 mod fetch;
-mod decode;
+pub mod decode;
+pub mod isa;
 mod execute;
+mod bitmanip;
+mod vector;
 mod writeback;
-mod mcu_cache;
+pub mod mcu_cache;
 mod uart;
+mod uart16550;
+mod sifive_uart;
+pub mod serial;
+pub mod gpio;
+pub mod timer;
+pub mod spi;
+pub mod spi_flash;
+pub mod i2c;
+pub mod i2c_devices;
+pub mod virtio_blk;
+pub mod framebuffer;
+pub mod dma;
+pub mod network;
+pub mod nic;
+pub mod rtc;
+pub mod watchdog;
+pub mod boot_rom;
+pub mod test_finisher;
+pub mod clint;
+pub mod plic;
+pub mod dram;
+pub mod flash;
+#[cfg(feature = "jit")]
+pub mod jit;
 mod memory;
 pub mod core;
+#[cfg(not(feature = "rv64"))]
+pub mod torture;
+#[cfg(test)]
+pub mod isa_test;
@@ -0,0 +1,303 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+
+/// host side of an ethernet link: frames sent by the guest NIC and frames waiting to be received by it
+/// `receive` must not block, it is polled by the device
+pub trait NetworkBackend: Send {
+    fn send(&mut self, frame: &[u8]);
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const IP_PROTOCOL_ICMP: u8 = 1;
+pub const IP_PROTOCOL_TCP: u8 = 6;
+pub const IP_PROTOCOL_UDP: u8 = 17;
+
+const ETHERNET_HEADER: usize = 14;
+const IPV4_HEADER: usize = 20;
+const UDP_HEADER: usize = 8;
+/// largest frame without the frame check sequence
+pub const ETHERNET_MTU_FRAME: usize = 1514;
+
+/// user mode network, same addresses as the QEMU user networking: the guest lives in 10.0.2.0/24
+/// and the gateway 10.0.2.2 stands for the host loopback
+pub const USER_NETWORK: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 0);
+pub const USER_GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+pub const USER_GUEST: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
+pub const USER_GATEWAY_MAC: [u8; 6] = [0x52, 0x55, 0x0A, 0x00, 0x02, 0x02];
+
+/// one's complement sum of the 16 bit words of the data, as used by the IPv4 and ICMP headers
+pub fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or(0)]) as u32)
+        .sum();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn ethernet_header(destination: [u8; 6], source: [u8; 6], ethertype: u16) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETHERNET_MTU_FRAME);
+    frame.extend_from_slice(&destination);
+    frame.extend_from_slice(&source);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame
+}
+
+fn ipv4_header(source: Ipv4Addr, destination: Ipv4Addr, protocol: u8, payload_len: usize) -> [u8; IPV4_HEADER] {
+    let mut header = [0u8; IPV4_HEADER];
+    header[0] = 0x45;
+    header[2..4].copy_from_slice(&((IPV4_HEADER + payload_len) as u16).to_be_bytes());
+    // don't fragment
+    header[6] = 0x40;
+    header[8] = 64;
+    header[9] = protocol;
+    header[12..16].copy_from_slice(&source.octets());
+    header[16..20].copy_from_slice(&destination.octets());
+    let checksum = internet_checksum(&header);
+    header[10..12].copy_from_slice(&checksum.to_be_bytes());
+    header
+}
+
+/// minimal user mode stack in the spirit of SLIRP, no privileges or host configuration needed:
+/// ARP and ICMP echo requests for the gateway are answered, UDP datagrams are forwarded through host sockets
+/// (to the host loopback when sent to the gateway), TCP is not supported
+pub struct UserNetwork {
+    guest_mac: [u8; 6],
+    guest_ip: Ipv4Addr,
+    /// host socket of each UDP port used by the guest
+    sockets: HashMap<u16, UdpSocket>,
+    /// replies produced by the stack itself, delivered before the datagrams of the sockets
+    pending: VecDeque<Vec<u8>>,
+    tcp_warned: bool,
+}
+
+impl Default for UserNetwork {
+    fn default() -> Self {
+        Self {
+            guest_mac: [0xFF; 6],
+            guest_ip: USER_GUEST,
+            sockets: HashMap::new(),
+            pending: VecDeque::new(),
+            tcp_warned: false,
+        }
+    }
+}
+
+impl UserNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// host address the guest reaches when sending to `address`
+    fn host_address(address: Ipv4Addr) -> Ipv4Addr {
+        if address == USER_GATEWAY { Ipv4Addr::LOCALHOST } else { address }
+    }
+
+    /// address the guest sees for a datagram coming from the host `address`
+    fn guest_address(address: Ipv4Addr) -> Ipv4Addr {
+        if address.is_loopback() { USER_GATEWAY } else { address }
+    }
+
+    fn in_network(address: Ipv4Addr) -> bool {
+        address.octets()[..3] == USER_NETWORK.octets()[..3]
+    }
+
+    fn handle_arp(&mut self, packet: &[u8]) {
+        const ARP_REQUEST: u16 = 1;
+        const ARP_REPLY: u16 = 2;
+        if packet.len() < 28 || u16::from_be_bytes([packet[6], packet[7]]) != ARP_REQUEST {
+            return;
+        }
+        let sender_mac: [u8; 6] = packet[8..14].try_into().unwrap();
+        let sender_ip = Ipv4Addr::new(packet[14], packet[15], packet[16], packet[17]);
+        let target_ip = Ipv4Addr::new(packet[24], packet[25], packet[26], packet[27]);
+        if target_ip != USER_GATEWAY {
+            return;
+        }
+        let mut reply = ethernet_header(sender_mac, USER_GATEWAY_MAC, ETHERTYPE_ARP);
+        reply.extend_from_slice(&packet[0..6]);
+        reply.extend_from_slice(&ARP_REPLY.to_be_bytes());
+        reply.extend_from_slice(&USER_GATEWAY_MAC);
+        reply.extend_from_slice(&USER_GATEWAY.octets());
+        reply.extend_from_slice(&sender_mac);
+        reply.extend_from_slice(&sender_ip.octets());
+        self.pending.push_back(reply);
+    }
+
+    fn handle_icmp(&mut self, source: Ipv4Addr, destination: Ipv4Addr, message: &[u8]) {
+        const ICMP_ECHO_REPLY: u8 = 0;
+        const ICMP_ECHO_REQUEST: u8 = 8;
+        if destination != USER_GATEWAY || message.len() < 8 || message[0] != ICMP_ECHO_REQUEST {
+            return;
+        }
+        let mut reply = message.to_vec();
+        reply[0] = ICMP_ECHO_REPLY;
+        reply[2..4].fill(0);
+        let checksum = internet_checksum(&reply);
+        reply[2..4].copy_from_slice(&checksum.to_be_bytes());
+        let mut frame = ethernet_header(self.guest_mac, USER_GATEWAY_MAC, ETHERTYPE_IPV4);
+        frame.extend_from_slice(&ipv4_header(destination, source, IP_PROTOCOL_ICMP, reply.len()));
+        frame.extend_from_slice(&reply);
+        self.pending.push_back(frame);
+    }
+
+    fn handle_udp(&mut self, destination: Ipv4Addr, datagram: &[u8]) {
+        if datagram.len() < UDP_HEADER {
+            return;
+        }
+        let source_port = u16::from_be_bytes([datagram[0], datagram[1]]);
+        let destination_port = u16::from_be_bytes([datagram[2], datagram[3]]);
+        let length = (u16::from_be_bytes([datagram[4], datagram[5]]) as usize).clamp(UDP_HEADER, datagram.len());
+        let socket = match self.sockets.entry(source_port) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).and_then(|socket| {
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            }) {
+                Ok(socket) => entry.insert(socket),
+                Err(e) => {
+                    tracing::warn!("Failed to open a host socket for the UDP port {source_port} of the guest: {e}");
+                    return;
+                }
+            },
+        };
+        let target = SocketAddrV4::new(Self::host_address(destination), destination_port);
+        if let Err(e) = socket.send_to(&datagram[UDP_HEADER..length], target) {
+            tracing::warn!("Failed to forward a UDP datagram of the guest to {target}: {e}");
+        }
+    }
+
+    /// datagram received by a host socket, wrapped in the UDP, IPv4 and ethernet headers the guest expects
+    fn udp_frame(&self, source: SocketAddrV4, guest_port: u16, payload: &[u8]) -> Vec<u8> {
+        let mut datagram = Vec::with_capacity(UDP_HEADER + payload.len());
+        datagram.extend_from_slice(&source.port().to_be_bytes());
+        datagram.extend_from_slice(&guest_port.to_be_bytes());
+        datagram.extend_from_slice(&((UDP_HEADER + payload.len()) as u16).to_be_bytes());
+        // the UDP checksum is optional over IPv4
+        datagram.extend_from_slice(&[0, 0]);
+        datagram.extend_from_slice(payload);
+        let mut frame = ethernet_header(self.guest_mac, USER_GATEWAY_MAC, ETHERTYPE_IPV4);
+        frame.extend_from_slice(&ipv4_header(Self::guest_address(*source.ip()), self.guest_ip, IP_PROTOCOL_UDP, datagram.len()));
+        frame.extend_from_slice(&datagram);
+        frame
+    }
+}
+
+impl NetworkBackend for UserNetwork {
+    fn send(&mut self, frame: &[u8]) {
+        if frame.len() < ETHERNET_HEADER {
+            return;
+        }
+        self.guest_mac = frame[6..12].try_into().unwrap();
+        let packet = &frame[ETHERNET_HEADER..];
+        match u16::from_be_bytes([frame[12], frame[13]]) {
+            ETHERTYPE_ARP => self.handle_arp(packet),
+            ETHERTYPE_IPV4 if packet.len() >= IPV4_HEADER && packet[0] >> 4 == 4 => {
+                let header_len = (packet[0] as usize & 0xF) * 4;
+                let total_len = (u16::from_be_bytes([packet[2], packet[3]]) as usize).min(packet.len());
+                if header_len < IPV4_HEADER || total_len < header_len {
+                    return;
+                }
+                let source = Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]);
+                let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+                if Self::in_network(source) {
+                    self.guest_ip = source;
+                }
+                let payload = &packet[header_len..total_len];
+                match packet[9] {
+                    IP_PROTOCOL_ICMP => self.handle_icmp(source, destination, payload),
+                    IP_PROTOCOL_UDP => self.handle_udp(destination, payload),
+                    IP_PROTOCOL_TCP if !self.tcp_warned => {
+                        tracing::warn!("TCP is not supported by the user mode network, use a TAP device instead");
+                        self.tcp_warned = true;
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        if let Some(frame) = self.pending.pop_front() {
+            return Some(frame);
+        }
+        let mut buffer = [0u8; ETHERNET_MTU_FRAME - ETHERNET_HEADER - IPV4_HEADER - UDP_HEADER];
+        for (&guest_port, socket) in &self.sockets {
+            if let Ok((len, std::net::SocketAddr::V4(source))) = socket.recv_from(&mut buffer) {
+                return Some(self.udp_frame(source, guest_port, &buffer[..len]));
+            }
+        }
+        None
+    }
+}
+
+/// TAP interface of the host, bridged or routed by the host network configuration
+/// the interface must exist or the simulator must be allowed to create it (CAP_NET_ADMIN)
+#[cfg(target_os = "linux")]
+pub struct TapBackend {
+    device: std::fs::File,
+}
+
+#[cfg(target_os = "linux")]
+impl TapBackend {
+    pub fn open(name: &str) -> std::io::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::os::unix::io::AsRawFd;
+        const TUNSETIFF: u64 = 0x4004_54CA;
+        const IFF_TAP: libc::c_short = 0x0002;
+        const IFF_NO_PI: libc::c_short = 0x1000;
+        /// `struct ifreq` with the flags member of its union
+        #[repr(C)]
+        struct InterfaceRequest {
+            name: [libc::c_char; libc::IFNAMSIZ],
+            flags: libc::c_short,
+            _padding: [u8; 22],
+        }
+
+        if name.len() >= libc::IFNAMSIZ {
+            return Err(std::io::Error::other(format!("TAP interface name {name} is too long")));
+        }
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/net/tun")?;
+        let mut request = InterfaceRequest { name: [0; libc::IFNAMSIZ], flags: IFF_TAP | IFF_NO_PI, _padding: [0; 22] };
+        for (destination, byte) in request.name.iter_mut().zip(name.bytes()) {
+            *destination = byte as libc::c_char;
+        }
+        // SAFETY: the request outlives the call and has the layout of `struct ifreq`
+        if unsafe { libc::ioctl(device.as_raw_fd(), TUNSETIFF as _, &mut request) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { device })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl NetworkBackend for TapBackend {
+    fn send(&mut self, frame: &[u8]) {
+        use std::io::Write;
+        if let Err(e) = self.device.write_all(frame) {
+            tracing::warn!("Failed to send a frame on the TAP interface: {e}");
+        }
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        use std::io::Read;
+        let mut buffer = vec![0u8; ETHERNET_MTU_FRAME];
+        match self.device.read(&mut buffer) {
+            Ok(len) if len > 0 => {
+                buffer.truncate(len);
+                Some(buffer)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, BusMaster, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest,
+    MemoryRequestType, MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess, RegisterField};
+use crate::risc_soc::risc_soc::WordSize;
+use crate::rv32i_baremetal::network::NetworkBackend;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// register offsets of the NIC
+pub const NIC_ID: Address = 0x00;
+pub const NIC_CONTROL: Address = 0x04;
+/// write 1 to clear the RX_DONE, TX_DONE and RX_OVERRUN bits
+pub const NIC_STATUS: Address = 0x08;
+pub const NIC_MAC_LOW: Address = 0x0C;
+pub const NIC_MAC_HIGH: Address = 0x10;
+pub const NIC_TX_RING_BASE: Address = 0x14;
+pub const NIC_TX_RING_SIZE: Address = 0x18;
+/// written by the driver after filling descriptors, the doorbell of the transmitter
+pub const NIC_TX_HEAD: Address = 0x1C;
+/// next descriptor the device sends
+pub const NIC_TX_TAIL: Address = 0x20;
+pub const NIC_RX_RING_BASE: Address = 0x24;
+pub const NIC_RX_RING_SIZE: Address = 0x28;
+/// written by the driver after giving empty buffers to the device
+pub const NIC_RX_HEAD: Address = 0x2C;
+/// next descriptor the device fills
+pub const NIC_RX_TAIL: Address = 0x30;
+pub const NIC_TX_PACKETS: Address = 0x34;
+pub const NIC_RX_PACKETS: Address = 0x38;
+/// frames received while no buffer was available
+pub const NIC_RX_DROPPED: Address = 0x3C;
+
+/// "NIC0"
+pub const NIC_MAGIC: u32 = 0x3043_494E;
+
+/// control register bits
+pub const NIC_CONTROL_RX_ENABLE: u32 = 1 << 0;
+pub const NIC_CONTROL_TX_ENABLE: u32 = 1 << 1;
+pub const NIC_CONTROL_RX_IRQ_ENABLE: u32 = 1 << 2;
+pub const NIC_CONTROL_TX_IRQ_ENABLE: u32 = 1 << 3;
+/// self clearing, stops both rings and clears the counters
+pub const NIC_CONTROL_RESET: u32 = 1 << 31;
+
+/// status register bits
+pub const NIC_STATUS_RX_DONE: u32 = 1 << 0;
+pub const NIC_STATUS_TX_DONE: u32 = 1 << 1;
+pub const NIC_STATUS_RX_OVERRUN: u32 = 1 << 2;
+pub const NIC_STATUS_LINK_UP: u32 = 1 << 3;
+
+/// a descriptor is the buffer address (u32), its length (u16) and flags (u16)
+/// the driver gives the length of the buffer, the device writes back the length of the received frame
+pub const NIC_DESCRIPTOR_SIZE: Address = 8;
+/// set by the device once it sent or filled the buffer
+pub const NIC_DESCRIPTOR_DONE: u16 = 1 << 0;
+/// set by the device on a received frame truncated to the size of the buffer
+pub const NIC_DESCRIPTOR_TRUNCATED: u16 = 1 << 1;
+
+/// locally administered address, the same default as the QEMU NICs
+pub const NIC_DEFAULT_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+/// how long the rings thread sleeps when there is nothing to send or receive
+const NIC_POLL_INTERVAL: Duration = Duration::from_micros(200);
+
+const CONTROL_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("RX_EN", 0),
+    RegisterField::bit("TX_EN", 1),
+    RegisterField::bit("RX_IE", 2),
+    RegisterField::bit("TX_IE", 3),
+    RegisterField::bit("RESET", 31),
+];
+
+const STATUS_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("RX_DONE", 0),
+    RegisterField::bit("TX_DONE", 1),
+    RegisterField::bit("RX_OVERRUN", 2),
+    RegisterField::bit("LINK_UP", 3),
+];
+
+/// registers of the NIC, for the traces of the accesses
+pub const NIC_REGISTERS: &[Register] = &[
+    Register::new("ID", NIC_ID, RegisterAccess::ReadOnly),
+    Register::new("CONTROL", NIC_CONTROL, RegisterAccess::ReadWrite).with_fields(CONTROL_FIELDS),
+    Register::new("STATUS", NIC_STATUS, RegisterAccess::ReadWrite).with_fields(STATUS_FIELDS),
+    Register::new("MAC_LOW", NIC_MAC_LOW, RegisterAccess::ReadWrite),
+    Register::new("MAC_HIGH", NIC_MAC_HIGH, RegisterAccess::ReadWrite),
+    Register::new("TX_RING_BASE", NIC_TX_RING_BASE, RegisterAccess::ReadWrite),
+    Register::new("TX_RING_SIZE", NIC_TX_RING_SIZE, RegisterAccess::ReadWrite),
+    Register::new("TX_HEAD", NIC_TX_HEAD, RegisterAccess::ReadWrite),
+    Register::new("TX_TAIL", NIC_TX_TAIL, RegisterAccess::ReadOnly),
+    Register::new("RX_RING_BASE", NIC_RX_RING_BASE, RegisterAccess::ReadWrite),
+    Register::new("RX_RING_SIZE", NIC_RX_RING_SIZE, RegisterAccess::ReadWrite),
+    Register::new("RX_HEAD", NIC_RX_HEAD, RegisterAccess::ReadWrite),
+    Register::new("RX_TAIL", NIC_RX_TAIL, RegisterAccess::ReadOnly),
+    Register::new("TX_PACKETS", NIC_TX_PACKETS, RegisterAccess::ReadOnly),
+    Register::new("RX_PACKETS", NIC_RX_PACKETS, RegisterAccess::ReadOnly),
+    Register::new("RX_DROPPED", NIC_RX_DROPPED, RegisterAccess::ReadOnly),
+];
+
+/// descriptor ring in guest memory, the entries from `tail` up to `head` belong to the device
+#[derive(Default)]
+struct Ring {
+    base: AtomicU32,
+    size: AtomicU32,
+    head: AtomicU32,
+    tail: AtomicU32,
+}
+
+impl Ring {
+    fn clear(&self) {
+        for register in [&self.base, &self.size, &self.head, &self.tail] {
+            register.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// address of the next descriptor owned by the device, if any
+    fn next_descriptor(&self) -> Option<Address> {
+        let size = self.size.load(Ordering::SeqCst);
+        let tail = self.tail.load(Ordering::SeqCst);
+        if size == 0 || tail == self.head.load(Ordering::SeqCst) % size {
+            return None;
+        }
+        Some(self.base.load(Ordering::SeqCst) as Address + tail as Address * NIC_DESCRIPTOR_SIZE)
+    }
+
+    fn advance(&self) {
+        let size = self.size.load(Ordering::SeqCst);
+        self.tail.store((self.tail.load(Ordering::SeqCst) + 1) % size.max(1), Ordering::SeqCst);
+    }
+}
+
+/// state shared between the register interface and the rings thread
+#[derive(Default)]
+struct NicState {
+    control: AtomicU32,
+    status: AtomicU32,
+    tx: Ring,
+    rx: Ring,
+    tx_packets: AtomicU32,
+    rx_packets: AtomicU32,
+    rx_dropped: AtomicU32,
+    /// a backend is attached, kept apart from the backend so the registers never wait for the rings thread
+    link_up: AtomicBool,
+    /// the device was dropped, the rings thread must stop
+    stopped: AtomicBool,
+    interrupt_line: Arc<AtomicBool>,
+}
+
+impl NicState {
+    fn set_status(&self, bits: u32) {
+        self.status.fetch_or(bits, Ordering::SeqCst);
+        self.update_interrupt();
+    }
+
+    fn update_interrupt(&self) {
+        let control = self.control.load(Ordering::SeqCst);
+        let status = self.status.load(Ordering::SeqCst);
+        let mut enabled = 0;
+        if control & NIC_CONTROL_RX_IRQ_ENABLE != 0 {
+            enabled |= NIC_STATUS_RX_DONE | NIC_STATUS_RX_OVERRUN;
+        }
+        if control & NIC_CONTROL_TX_IRQ_ENABLE != 0 {
+            enabled |= NIC_STATUS_TX_DONE;
+        }
+        self.interrupt_line.store(status & enabled != 0, Ordering::SeqCst);
+    }
+
+    fn enabled(&self, bit: u32) -> bool {
+        self.control.load(Ordering::SeqCst) & bit != 0
+    }
+}
+
+/// ethernet controller with a transmit and a receive descriptor ring in guest memory, bridged to a host network backend
+/// the rings are served by a background thread reading and writing guest memory through the bus master,
+/// so `set_bus_master` and `attach_backend` must both be called before the guest enables the rings
+pub struct Nic {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    mac: [u8; 6],
+    bus: Option<BusMaster>,
+    backend: Arc<Mutex<Option<Box<dyn NetworkBackend>>>>,
+    state: Arc<NicState>,
+    worker_started: bool,
+}
+
+impl Nic {
+    /// the bus master should be `RiscCore::system_bus_master` so the buffers can be anywhere in memory
+    pub fn set_bus_master(&mut self, bus: BusMaster) {
+        self.bus = Some(bus);
+        self.start_worker();
+    }
+
+    pub fn attach_backend(&mut self, backend: Box<dyn NetworkBackend>) {
+        *self.backend.lock().unwrap() = Some(backend);
+        self.state.link_up.store(true, Ordering::SeqCst);
+    }
+
+    pub fn with_mac(mut self, mac: [u8; 6]) -> Self {
+        self.mac = mac;
+        self
+    }
+
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.state.interrupt_line.clone()
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = self.state.status.load(Ordering::SeqCst);
+        if self.state.link_up.load(Ordering::SeqCst) {
+            status |= NIC_STATUS_LINK_UP;
+        }
+        status
+    }
+
+    fn start_worker(&mut self) {
+        if self.worker_started {
+            return;
+        }
+        self.worker_started = true;
+        // the bus master holds the MMU, which holds the device: a strong reference would keep everything alive forever
+        let bus = Arc::downgrade(self.bus.as_ref().unwrap());
+        let backend = self.backend.clone();
+        let state = self.state.clone();
+        std::thread::spawn(move || {
+            while !state.stopped.load(Ordering::SeqCst) {
+                let Some(bus) = bus.upgrade() else {
+                    break;
+                };
+                let mut busy = false;
+                if let Some(backend) = backend.lock().unwrap().as_mut() {
+                    busy |= Self::transmit(&bus, &state, backend.as_mut());
+                    busy |= Self::receive(&bus, &state, backend.as_mut());
+                }
+                drop(bus);
+                if !busy {
+                    std::thread::sleep(NIC_POLL_INTERVAL);
+                }
+            }
+        });
+    }
+
+    /// send the next frame of the transmit ring, returns whether one was sent
+    fn transmit(bus: &BusMaster, state: &NicState, backend: &mut dyn NetworkBackend) -> bool {
+        if !state.enabled(NIC_CONTROL_TX_ENABLE) {
+            return false;
+        }
+        let Some(descriptor) = state.tx.next_descriptor() else {
+            return false;
+        };
+        let bytes = Self::bus_read(bus, descriptor, NIC_DESCRIPTOR_SIZE as usize);
+        let address = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as Address;
+        let len = u16::from_le_bytes([bytes[4], bytes[5]]) as usize;
+        backend.send(&Self::bus_read(bus, address, len));
+        Self::bus_write(bus, descriptor + 6, &NIC_DESCRIPTOR_DONE.to_le_bytes());
+        state.tx.advance();
+        state.tx_packets.fetch_add(1, Ordering::SeqCst);
+        state.set_status(NIC_STATUS_TX_DONE);
+        true
+    }
+
+    /// deliver the next frame of the backend to the receive ring, returns whether one arrived
+    fn receive(bus: &BusMaster, state: &NicState, backend: &mut dyn NetworkBackend) -> bool {
+        if !state.enabled(NIC_CONTROL_RX_ENABLE) {
+            return false;
+        }
+        let Some(frame) = backend.receive() else {
+            return false;
+        };
+        let Some(descriptor) = state.rx.next_descriptor() else {
+            state.rx_dropped.fetch_add(1, Ordering::SeqCst);
+            state.set_status(NIC_STATUS_RX_OVERRUN);
+            return true;
+        };
+        let bytes = Self::bus_read(bus, descriptor, NIC_DESCRIPTOR_SIZE as usize);
+        let address = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as Address;
+        let capacity = u16::from_le_bytes([bytes[4], bytes[5]]) as usize;
+        let mut flags = NIC_DESCRIPTOR_DONE;
+        if frame.len() > capacity {
+            flags |= NIC_DESCRIPTOR_TRUNCATED;
+        }
+        let len = frame.len().min(capacity);
+        Self::bus_write(bus, address, &frame[..len]);
+        let mut completion = (len as u16).to_le_bytes().to_vec();
+        completion.extend_from_slice(&flags.to_le_bytes());
+        Self::bus_write(bus, descriptor + 4, &completion);
+        state.rx.advance();
+        state.rx_packets.fetch_add(1, Ordering::SeqCst);
+        state.set_status(NIC_STATUS_RX_DONE);
+        true
+    }
+
+    fn bus_read(bus: &BusMaster, address: Address, len: usize) -> Vec<u8> {
+        (0..len as Address)
+            .map(|offset| {
+                let response = bus(MemoryRequest {
+                    request_type: MemoryRequestType::READ,
+                    data_address: address + offset,
+                    data_size: WordSize::BYTE,
+                    data: None,
+                });
+                response.data.first().copied().unwrap_or(0)
+            })
+            .collect()
+    }
+
+    fn bus_write(bus: &BusMaster, address: Address, data: &[u8]) {
+        for (offset, byte) in data.iter().enumerate() {
+            bus(MemoryRequest {
+                request_type: MemoryRequestType::WRITE,
+                data_address: address + offset as Address,
+                data_size: WordSize::BYTE,
+                data: Some(MemoryData::new(&[*byte])),
+            });
+        }
+    }
+
+    fn reset_registers(&mut self) {
+        let state = &self.state;
+        state.control.store(0, Ordering::SeqCst);
+        state.status.store(0, Ordering::SeqCst);
+        state.tx.clear();
+        state.rx.clear();
+        state.tx_packets.store(0, Ordering::SeqCst);
+        state.rx_packets.store(0, Ordering::SeqCst);
+        state.rx_dropped.store(0, Ordering::SeqCst);
+        state.interrupt_line.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Nic {
+    fn drop(&mut self) {
+        // not joined, the last reference to the bus master may be dropped by the rings thread itself
+        self.state.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+impl MemoryDevice for Nic {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address - start_address >= 0x40);
+        assert!(memory_type == MemoryDeviceType::NET0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            mac: NIC_DEFAULT_MAC,
+            bus: None,
+            backend: Arc::new(Mutex::new(None)),
+            state: Arc::new(NicState::default()),
+            worker_started: false,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let state = self.state.clone();
+        let (tx_enabled, rx_enabled) = (state.enabled(NIC_CONTROL_TX_ENABLE), state.enabled(NIC_CONTROL_RX_ENABLE));
+        match request.data_address - self.start_address {
+            NIC_CONTROL if value & NIC_CONTROL_RESET != 0 => self.reset_registers(),
+            NIC_CONTROL => {
+                state.control.store(value, Ordering::SeqCst);
+                state.update_interrupt();
+            }
+            NIC_STATUS => {
+                let clear = value & (NIC_STATUS_RX_DONE | NIC_STATUS_TX_DONE | NIC_STATUS_RX_OVERRUN);
+                state.status.fetch_and(!clear, Ordering::SeqCst);
+                state.update_interrupt();
+            }
+            NIC_MAC_LOW => self.mac[0..4].copy_from_slice(&value.to_le_bytes()),
+            NIC_MAC_HIGH => self.mac[4..6].copy_from_slice(&value.to_le_bytes()[0..2]),
+            // ring geometry is locked while the ring is enabled
+            NIC_TX_RING_BASE if !tx_enabled => state.tx.base.store(value, Ordering::SeqCst),
+            NIC_TX_RING_SIZE if !tx_enabled => {
+                state.tx.size.store(value, Ordering::SeqCst);
+                state.tx.tail.store(0, Ordering::SeqCst);
+            }
+            NIC_RX_RING_BASE if !rx_enabled => state.rx.base.store(value, Ordering::SeqCst),
+            NIC_RX_RING_SIZE if !rx_enabled => {
+                state.rx.size.store(value, Ordering::SeqCst);
+                state.rx.tail.store(0, Ordering::SeqCst);
+            }
+            NIC_TX_RING_BASE | NIC_TX_RING_SIZE | NIC_RX_RING_BASE | NIC_RX_RING_SIZE => {}
+            NIC_TX_HEAD => state.tx.head.store(value, Ordering::SeqCst),
+            NIC_RX_HEAD => state.rx.head.store(value, Ordering::SeqCst),
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let state = &self.state;
+        let value = match request.data_address - self.start_address {
+            NIC_ID => NIC_MAGIC,
+            NIC_CONTROL => state.control.load(Ordering::SeqCst),
+            NIC_STATUS => self.status(),
+            NIC_MAC_LOW => u32::from_le_bytes([self.mac[0], self.mac[1], self.mac[2], self.mac[3]]),
+            NIC_MAC_HIGH => u32::from_le_bytes([self.mac[4], self.mac[5], 0, 0]),
+            NIC_TX_RING_BASE => state.tx.base.load(Ordering::SeqCst),
+            NIC_TX_RING_SIZE => state.tx.size.load(Ordering::SeqCst),
+            NIC_TX_HEAD => state.tx.head.load(Ordering::SeqCst),
+            NIC_TX_TAIL => state.tx.tail.load(Ordering::SeqCst),
+            NIC_RX_RING_BASE => state.rx.base.load(Ordering::SeqCst),
+            NIC_RX_RING_SIZE => state.rx.size.load(Ordering::SeqCst),
+            NIC_RX_HEAD => state.rx.head.load(Ordering::SeqCst),
+            NIC_RX_TAIL => state.rx.tail.load(Ordering::SeqCst),
+            NIC_TX_PACKETS => state.tx_packets.load(Ordering::SeqCst),
+            NIC_RX_PACKETS => state.rx_packets.load(Ordering::SeqCst),
+            NIC_RX_DROPPED => state.rx_dropped.load(Ordering::SeqCst),
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+            }
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn pending_irq(&self) -> bool {
+        self.state.interrupt_line.load(Ordering::SeqCst)
+    }
+
+    fn reset(&mut self, _clear_memory: bool) {
+        self.reset_registers();
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(NIC_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        let state = &self.state;
+        println!(
+            "\nNIC {:?}: {{ MAC={:02X?} CONTROL={:X} STATUS={:X} TX={}..{} RX={}..{} tx_packets={} rx_packets={} rx_dropped={} }}",
+            self.memory_type,
+            self.mac,
+            state.control.load(Ordering::SeqCst),
+            self.status(),
+            state.tx.tail.load(Ordering::SeqCst),
+            state.tx.head.load(Ordering::SeqCst),
+            state.rx.tail.load(Ordering::SeqCst),
+            state.rx.head.load(Ordering::SeqCst),
+            state.tx_packets.load(Ordering::SeqCst),
+            state.rx_packets.load(Ordering::SeqCst),
+            state.rx_dropped.load(Ordering::SeqCst)
+        );
+        Ok(())
+    }
+}
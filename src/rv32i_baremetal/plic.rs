@@ -0,0 +1,222 @@
+use crate::risc_soc::csr::{InterruptLines, MIP_MEIP};
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// register offsets, same layout as the SiFive PLIC used by the QEMU virt machine and the FE310
+/// one priority word per source, a pending bit per source, and an enable bitmap, threshold and claim register per context
+pub const PLIC_PRIORITY: Address = 0x0;
+pub const PLIC_PENDING: Address = 0x1000;
+pub const PLIC_ENABLE: Address = 0x2000;
+pub const PLIC_ENABLE_STRIDE: Address = 0x80;
+pub const PLIC_CONTEXT: Address = 0x20_0000;
+pub const PLIC_CONTEXT_STRIDE: Address = 0x1000;
+/// offsets inside the registers of a context
+pub const PLIC_THRESHOLD: Address = 0x0;
+pub const PLIC_CLAIM_COMPLETE: Address = 0x4;
+
+/// sources 1 to 31, source 0 means no interrupt
+pub const PLIC_NUM_SOURCES: usize = 32;
+/// priorities go from 0 (never interrupts) to 7
+pub const PLIC_MAX_PRIORITY: u32 = 7;
+/// machine and supervisor contexts of each hart, as in the QEMU virt machine
+pub const PLIC_CONTEXTS_PER_HART: usize = 2;
+
+/// platform-level interrupt controller: routes the interrupt lines of the devices to the harts by priority
+/// the gateway of each source is level triggered, a claimed source is not pending again until the hart completes it
+/// only the machine context of each hart drives its MEIP, the supervisor contexts are kept for the register layout
+pub struct Plic {
+    start_address: Address,
+    end_address: Address,
+    harts: Vec<InterruptLines>,
+    priority: [u32; PLIC_NUM_SOURCES],
+    /// levels of the interrupt lines of the sources, as sampled by the MMU
+    levels: u32,
+    /// sources claimed by a context and not completed yet, kept atomic as the side-effect free reads do not claim
+    claimed: AtomicU32,
+    enable: Vec<u32>,
+    threshold: Vec<u32>,
+}
+
+impl Plic {
+    /// the machine context of the hart is 2 * its hart id, in the order the harts are connected
+    pub fn connect_hart(&mut self, interrupt_lines: InterruptLines) {
+        self.harts.push(interrupt_lines);
+        self.enable.extend([0; PLIC_CONTEXTS_PER_HART]);
+        self.threshold.extend([0; PLIC_CONTEXTS_PER_HART]);
+    }
+
+    fn pending(&self) -> u32 {
+        self.levels & !self.claimed.load(Ordering::SeqCst) & !1
+    }
+
+    /// highest priority source pending and enabled for the context above its threshold, the lowest id wins ties
+    fn best_source(&self, context: usize) -> u32 {
+        let candidates = self.pending() & self.enable[context];
+        let mut best = (0, 0);
+        for source in 1..PLIC_NUM_SOURCES {
+            let priority = self.priority[source];
+            if candidates & (1 << source) != 0 && priority > self.threshold[context] && priority > best.1 {
+                best = (source as u32, priority);
+            }
+        }
+        best.0
+    }
+
+    fn claim(&self, context: usize) -> u32 {
+        let source = self.best_source(context);
+        if source != 0 {
+            self.claimed.fetch_or(1 << source, Ordering::SeqCst);
+        }
+        self.update_interrupt_lines();
+        source
+    }
+
+    fn update_interrupt_lines(&self) {
+        for (hart, interrupt_lines) in self.harts.iter().enumerate() {
+            if self.best_source(hart * PLIC_CONTEXTS_PER_HART) != 0 {
+                interrupt_lines.fetch_or(MIP_MEIP, Ordering::SeqCst);
+            } else {
+                interrupt_lines.fetch_and(!MIP_MEIP, Ordering::SeqCst);
+            }
+        }
+    }
+
+    fn register(&self, offset: Address) -> Option<Register> {
+        let contexts = self.enable.len() as Address;
+        if offset < PLIC_PRIORITY + 4 * PLIC_NUM_SOURCES as Address {
+            Some(Register::Priority((offset / 4) as usize))
+        } else if offset == PLIC_PENDING {
+            Some(Register::Pending)
+        } else if (PLIC_ENABLE..PLIC_ENABLE + PLIC_ENABLE_STRIDE * contexts).contains(&offset) && offset.is_multiple_of(PLIC_ENABLE_STRIDE) {
+            Some(Register::Enable(((offset - PLIC_ENABLE) / PLIC_ENABLE_STRIDE) as usize))
+        } else if (PLIC_CONTEXT..PLIC_CONTEXT + PLIC_CONTEXT_STRIDE * contexts).contains(&offset) {
+            let context = ((offset - PLIC_CONTEXT) / PLIC_CONTEXT_STRIDE) as usize;
+            match (offset - PLIC_CONTEXT) % PLIC_CONTEXT_STRIDE {
+                PLIC_THRESHOLD => Some(Register::Threshold(context)),
+                PLIC_CLAIM_COMPLETE => Some(Register::ClaimComplete(context)),
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Register {
+    Priority(usize),
+    Pending,
+    Enable(usize),
+    Threshold(usize),
+    ClaimComplete(usize),
+}
+
+impl MemoryDevice for Plic {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::PLIC);
+        Self {
+            start_address,
+            end_address,
+            harts: vec![],
+            priority: [0; PLIC_NUM_SOURCES],
+            levels: 0,
+            claimed: AtomicU32::new(0),
+            enable: vec![],
+            threshold: vec![],
+        }
+    }
+
+    /// reading the claim register of a context claims its best pending source
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        let Some(register) = self.register(request.data_address - self.start_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        };
+        if request.request_type == MemoryRequestType::READ {
+            if let Register::ClaimComplete(context) = register {
+                let mut data = MemoryData::new(&self.claim(context).to_le_bytes());
+                data.truncate(request.data_size as usize);
+                return MemoryResponse { data, status: MemoryResponseType::Valid };
+            }
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match register {
+            Register::Priority(source) if source > 0 => self.priority[source] = value.min(PLIC_MAX_PRIORITY),
+            Register::Enable(context) => self.enable[context] = value & !1,
+            Register::Threshold(context) => self.threshold[context] = value.min(PLIC_MAX_PRIORITY),
+            // completing a source not enabled for the context is ignored, as in the specification
+            Register::ClaimComplete(context) => {
+                if (value as usize) < PLIC_NUM_SOURCES && self.enable[context] & (1 << value) != 0 {
+                    self.claimed.fetch_and(!(1 << value), Ordering::SeqCst);
+                }
+            }
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        self.update_interrupt_lines();
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    /// reads without side effects: the claim register shows the source a claim would return
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let Some(register) = self.register(request.data_address - self.start_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        };
+        let value = match register {
+            Register::Priority(source) => self.priority[source],
+            Register::Pending => self.pending(),
+            Register::Enable(context) => self.enable[context],
+            Register::Threshold(context) => self.threshold[context],
+            Register::ClaimComplete(context) => self.best_source(context),
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::PLIC
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!("\nMemory {:?}: {{", MemoryDeviceType::PLIC);
+        println!("levels={:X} pending={:X} claimed={:X}", self.levels, self.pending(), self.claimed.load(Ordering::SeqCst));
+        for context in 0..self.enable.len() {
+            println!("context {context}: enable={:X} threshold={}", self.enable[context], self.threshold[context]);
+        }
+        println!("}}");
+        Ok(())
+    }
+
+    fn interrupt_sources(&mut self, levels: u64) {
+        self.levels = levels as u32;
+        self.update_interrupt_lines();
+    }
+
+    fn reset(&mut self, _clear_memory: bool) {
+        self.priority = [0; PLIC_NUM_SOURCES];
+        self.levels = 0;
+        self.claimed.store(0, Ordering::SeqCst);
+        self.enable.iter_mut().for_each(|enable| *enable = 0);
+        self.threshold.iter_mut().for_each(|threshold| *threshold = 0);
+        self.update_interrupt_lines();
+    }
+}
@@ -0,0 +1,226 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// register offsets, same layout as the Goldfish RTC of the QEMU virt machine
+/// reading TIME_LOW latches the upper half of the time, read next from TIME_HIGH
+pub const RTC_TIME_LOW: Address = 0x00;
+pub const RTC_TIME_HIGH: Address = 0x04;
+/// writing ALARM_LOW arms the alarm, ALARM_HIGH must be written first
+pub const RTC_ALARM_LOW: Address = 0x08;
+pub const RTC_ALARM_HIGH: Address = 0x0C;
+pub const RTC_IRQ_ENABLED: Address = 0x10;
+pub const RTC_CLEAR_ALARM: Address = 0x14;
+/// 1 while an alarm is armed and did not fire yet
+pub const RTC_ALARM_STATUS: Address = 0x18;
+pub const RTC_CLEAR_INTERRUPT: Address = 0x1C;
+
+/// nanoseconds per edge of the RTC clock when the time is simulated, the CLINT timebase of the platform
+pub const RTC_DEFAULT_NS_PER_EDGE: u64 = 100;
+
+/// registers as named in the Goldfish RTC specification, for the traces of the accesses
+pub const RTC_REGISTERS: &[Register] = &[
+    Register::new("TIME_LOW", RTC_TIME_LOW, RegisterAccess::ReadWrite),
+    Register::new("TIME_HIGH", RTC_TIME_HIGH, RegisterAccess::ReadWrite),
+    Register::new("ALARM_LOW", RTC_ALARM_LOW, RegisterAccess::ReadWrite),
+    Register::new("ALARM_HIGH", RTC_ALARM_HIGH, RegisterAccess::ReadWrite),
+    Register::new("IRQ_ENABLED", RTC_IRQ_ENABLED, RegisterAccess::ReadWrite),
+    Register::new("CLEAR_ALARM", RTC_CLEAR_ALARM, RegisterAccess::WriteOnly),
+    Register::new("ALARM_STATUS", RTC_ALARM_STATUS, RegisterAccess::ReadOnly),
+    Register::new("CLEAR_INTERRUPT", RTC_CLEAR_INTERRUPT, RegisterAccess::WriteOnly),
+];
+
+/// where the time of the RTC comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcTimeSource {
+    /// wall clock of the host
+    Host,
+    /// starts at an epoch time in nanoseconds and follows the edges of the clock of the device, so runs are reproducible
+    Simulated { start: u64, ns_per_edge: u64 },
+}
+
+/// real-time clock counting nanoseconds since the UNIX epoch, with a single alarm raising its interrupt line
+/// the guest can set the time, which only moves an offset over the time source
+pub struct Rtc {
+    start_address: Address,
+    end_address: Address,
+    source: RtcTimeSource,
+    /// edges of the device clock seen so far, used by the simulated time
+    edges: u64,
+    /// difference between the time set by the guest and the time source
+    offset: i64,
+    /// upper half of the time latched by the last read of TIME_LOW
+    time_high: AtomicU32,
+    alarm: u64,
+    alarm_armed: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+}
+
+impl Rtc {
+    pub fn with_time_source(mut self, source: RtcTimeSource) -> Self {
+        if let RtcTimeSource::Simulated { ns_per_edge, .. } = source {
+            assert!(ns_per_edge > 0);
+        }
+        self.source = source;
+        self
+    }
+
+    /// current time in nanoseconds since the UNIX epoch
+    pub fn time(&self) -> u64 {
+        let source = match self.source {
+            RtcTimeSource::Host => SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64),
+            RtcTimeSource::Simulated { start, ns_per_edge } => start.wrapping_add(self.edges.wrapping_mul(ns_per_edge)),
+        };
+        source.wrapping_add_signed(self.offset)
+    }
+
+    fn set_time(&mut self, time: u64) {
+        self.offset = self.offset.wrapping_add(time.wrapping_sub(self.time()) as i64);
+    }
+
+    fn check_alarm(&mut self) {
+        if self.alarm_armed && self.time() >= self.alarm {
+            self.alarm_armed = false;
+            self.irq_pending = true;
+        }
+    }
+
+    fn register(&self, offset: Address) -> Option<u32> {
+        let value = match offset {
+            RTC_TIME_LOW => {
+                let time = self.time();
+                self.time_high.store((time >> 32) as u32, Ordering::SeqCst);
+                time as u32
+            }
+            RTC_TIME_HIGH => self.time_high.load(Ordering::SeqCst),
+            RTC_ALARM_LOW => self.alarm as u32,
+            RTC_ALARM_HIGH => (self.alarm >> 32) as u32,
+            RTC_IRQ_ENABLED => self.irq_enabled as u32,
+            RTC_ALARM_STATUS => self.alarm_armed as u32,
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+impl MemoryDevice for Rtc {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address - start_address >= 0x20);
+        assert!(memory_type == MemoryDeviceType::RTC);
+        Self {
+            start_address,
+            end_address,
+            source: RtcTimeSource::Host,
+            edges: 0,
+            offset: 0,
+            time_high: AtomicU32::new(0),
+            alarm: 0,
+            alarm_armed: false,
+            irq_enabled: false,
+            irq_pending: false,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64;
+        match request.data_address - self.start_address {
+            RTC_TIME_LOW => {
+                let time = self.time();
+                self.set_time((time & !0xFFFF_FFFF) | value);
+            }
+            RTC_TIME_HIGH => {
+                let time = self.time();
+                self.set_time((time & 0xFFFF_FFFF) | (value << 32));
+            }
+            RTC_ALARM_LOW => {
+                self.alarm = (self.alarm & !0xFFFF_FFFF) | value;
+                self.alarm_armed = true;
+            }
+            RTC_ALARM_HIGH => self.alarm = (self.alarm & 0xFFFF_FFFF) | (value << 32),
+            RTC_IRQ_ENABLED => self.irq_enabled = value & 0x1 != 0,
+            RTC_CLEAR_ALARM => self.alarm_armed = false,
+            RTC_CLEAR_INTERRUPT => self.irq_pending = false,
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        self.check_alarm();
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let Some(value) = self.register(request.data_address - self.start_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::RTC
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nRTC {:?}: {{ time={} alarm={} armed={} irq_enabled={} irq_pending={} }}",
+            self.source, self.time(), self.alarm, self.alarm_armed, self.irq_enabled, self.irq_pending
+        );
+        Ok(())
+    }
+
+    fn tick(&mut self, edges: u64) {
+        self.edges = self.edges.wrapping_add(edges);
+        self.check_alarm();
+    }
+
+    fn pending_irq(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+
+    /// edges until the armed alarm fires, estimated from the default period when following the host time
+    fn next_event(&self) -> Option<u64> {
+        if !self.alarm_armed {
+            return None;
+        }
+        let ns_per_edge = match self.source {
+            RtcTimeSource::Host => RTC_DEFAULT_NS_PER_EDGE,
+            RtcTimeSource::Simulated { ns_per_edge, .. } => ns_per_edge,
+        };
+        Some(self.alarm.saturating_sub(self.time()).div_ceil(ns_per_edge).max(1))
+    }
+
+    /// the time keeps running, only the alarm and the interrupt are cleared
+    fn reset(&mut self, _clear_memory: bool) {
+        self.alarm = 0;
+        self.alarm_armed = false;
+        self.irq_enabled = false;
+        self.irq_pending = false;
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(RTC_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+}
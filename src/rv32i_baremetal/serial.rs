@@ -0,0 +1,84 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// host end of a simulated serial line, attached to a UART in place of stdin and stdout with `core::attach_serial`
+pub struct SerialConnection {
+    /// bytes typed in the terminal, received by the guest
+    pub input: Box<dyn Read + Send>,
+    /// bytes sent by the guest, shown by the terminal
+    pub output: Box<dyn Write + Send>,
+}
+
+impl SerialConnection {
+    /// wait for a terminal (ex. `telnet`, `nc` or `socat`) to connect to the address, ex. "127.0.0.1:4444"
+    pub fn tcp(address: &str) -> io::Result<Self> {
+        Self::accept(&TcpListener::bind(address)?)
+    }
+
+    /// wait for the next client of a listener, ex. one bound to port 0 by a test
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        tracing::info!("Waiting for a terminal to connect to the serial port on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        tracing::info!("Serial terminal connected from {peer}");
+        Self::from_stream(stream)
+    }
+
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        // firmware shells echo one character at a time
+        stream.set_nodelay(true)?;
+        Ok(Self { input: Box::new(stream.try_clone()?), output: Box::new(stream) })
+    }
+
+    /// pseudo terminal for picocom or minicom, returned with the path of its terminal side (ex. /dev/pts/3)
+    /// the terminal side is kept open and in raw mode, so the guest output is buffered until a terminal attaches
+    #[cfg(unix)]
+    pub fn pty() -> io::Result<(Self, String)> {
+        use std::fs::OpenOptions;
+        use std::os::fd::AsRawFd;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let master = OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOCTTY).open("/dev/ptmx")?;
+        let fd = master.as_raw_fd();
+        // SAFETY: fd is the open master side of a new pseudo terminal, ptsname is only called from this thread
+        let path = unsafe {
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            std::ffi::CStr::from_ptr(name).to_string_lossy().into_owned()
+        };
+        let terminal = OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOCTTY).open(&path)?;
+        // SAFETY: termios is plain data filled by tcgetattr before being used
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(terminal.as_raw_fd(), &mut termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(terminal.as_raw_fd(), libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        tracing::info!("Serial port available on {path}, ex. picocom {path}");
+        let output = master.try_clone()?;
+        Ok((Self { input: Box::new(PtyInput { master, _terminal: terminal }), output: Box::new(output) }, path))
+    }
+}
+
+/// reads of the master side of a pseudo terminal fail once no process holds its terminal side, so the simulator keeps it open
+#[cfg(unix)]
+struct PtyInput {
+    master: std::fs::File,
+    _terminal: std::fs::File,
+}
+
+#[cfg(unix)]
+impl Read for PtyInput {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        self.master.read(buffer)
+    }
+}
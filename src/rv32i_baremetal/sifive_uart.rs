@@ -0,0 +1,200 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess, RegisterField};
+use crate::rv32i_baremetal::serial::SerialConnection;
+use crate::rv32i_baremetal::uart::{UartReceiver, UartTransmitter, UART_FIFO_DEPTH, attach_lines};
+
+/// register offsets of the SiFive UART of the FE310 and the other Freedom SoCs
+/// reading TXDATA reports if the transmit FIFO is full, reading RXDATA pops a byte or reports the FIFO empty
+pub const SIFIVE_UART_TXDATA: Address = 0x00;
+pub const SIFIVE_UART_RXDATA: Address = 0x04;
+pub const SIFIVE_UART_TXCTRL: Address = 0x08;
+pub const SIFIVE_UART_RXCTRL: Address = 0x0C;
+pub const SIFIVE_UART_IE: Address = 0x10;
+pub const SIFIVE_UART_IP: Address = 0x14;
+pub const SIFIVE_UART_DIV: Address = 0x18;
+
+/// TXDATA full and RXDATA empty flags
+pub const SIFIVE_UART_TX_FULL: u32 = 1 << 31;
+pub const SIFIVE_UART_RX_EMPTY: u32 = 1 << 31;
+
+/// transmit and receive watermark interrupts, in IE and IP
+pub const SIFIVE_UART_TXWM: u32 = 1 << 0;
+pub const SIFIVE_UART_RXWM: u32 = 1 << 1;
+
+/// enable bit of TXCTRL and RXCTRL, the watermark level is in bits 16 to 18
+pub const SIFIVE_UART_ENABLE: u32 = 1 << 0;
+
+/// divisor of the bus clock out of reset, 115200 bauds from the 16 MHz HFROSC of the FE310
+pub const SIFIVE_UART_DEFAULT_DIV: u32 = 0x8A;
+
+const CTRL_FIELDS: &[RegisterField] = &[RegisterField::bit("EN", 0), RegisterField::bit("NSTOP", 1), RegisterField::bits("CNT", 16, 3)];
+const IE_IP_FIELDS: &[RegisterField] = &[RegisterField::bit("TXWM", 0), RegisterField::bit("RXWM", 1)];
+
+/// registers as named in the FE310 manual, for the traces of the accesses
+pub const SIFIVE_UART_REGISTERS: &[Register] = &[
+    Register::new("TXDATA", SIFIVE_UART_TXDATA, RegisterAccess::ReadWrite).character(),
+    Register::new("RXDATA", SIFIVE_UART_RXDATA, RegisterAccess::ReadOnly).character(),
+    Register::new("TXCTRL", SIFIVE_UART_TXCTRL, RegisterAccess::ReadWrite).with_fields(CTRL_FIELDS),
+    Register::new("RXCTRL", SIFIVE_UART_RXCTRL, RegisterAccess::ReadWrite).with_fields(CTRL_FIELDS),
+    Register::new("IE", SIFIVE_UART_IE, RegisterAccess::ReadWrite).with_fields(IE_IP_FIELDS),
+    Register::new("IP", SIFIVE_UART_IP, RegisterAccess::ReadOnly).with_fields(IE_IP_FIELDS),
+    Register::new("DIV", SIFIVE_UART_DIV, RegisterAccess::ReadWrite),
+];
+
+/// UART of the SiFive Freedom SoCs, with watermark interrupts on its transmit and receive FIFOs
+/// bytes are sent as soon as they are written, the transmitter and receiver enables are only kept for the firmware
+pub struct SifiveUart {
+    start_address: Address,
+    end_address: Address,
+    receiver: UartReceiver,
+    transmitter: UartTransmitter,
+    txctrl: u32,
+    rxctrl: u32,
+    ie: u32,
+    div: u32,
+}
+
+impl SifiveUart {
+    /// serial line of the SiFive UART on a terminal, see `attach_lines`
+    pub fn attach(&mut self, connection: SerialConnection) {
+        attach_lines(&self.receiver, &mut self.transmitter, connection);
+    }
+
+    /// the transmit watermark is pending while fewer bytes than TXCNT wait in the FIFO,
+    /// the receive one while more bytes than RXCNT were received
+    fn interrupt_pending(&self) -> u32 {
+        let txcnt = ((self.txctrl >> 16) & 0x7) as usize;
+        let rxcnt = ((self.rxctrl >> 16) & 0x7) as usize;
+        let tx_level = if self.transmitter.is_full() { UART_FIFO_DEPTH } else if self.transmitter.is_empty() { 0 } else { 1 };
+        let mut ip = 0;
+        if tx_level < txcnt {
+            ip |= SIFIVE_UART_TXWM;
+        }
+        if self.receiver.len() > rxcnt {
+            ip |= SIFIVE_UART_RXWM;
+        }
+        ip
+    }
+
+    fn register(&self, offset: Address) -> Option<u32> {
+        let value = match offset {
+            SIFIVE_UART_TXDATA if self.transmitter.is_full() => SIFIVE_UART_TX_FULL,
+            SIFIVE_UART_TXDATA => 0,
+            SIFIVE_UART_RXDATA => self.receiver.peek().map_or(SIFIVE_UART_RX_EMPTY, |byte| byte as u32),
+            SIFIVE_UART_TXCTRL => self.txctrl,
+            SIFIVE_UART_RXCTRL => self.rxctrl,
+            SIFIVE_UART_IE => self.ie,
+            SIFIVE_UART_IP => self.interrupt_pending(),
+            SIFIVE_UART_DIV => self.div,
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+fn word_response(value: u32, request: &MemoryRequest) -> MemoryResponse {
+    let mut data = MemoryData::new(&value.to_le_bytes());
+    data.truncate(request.data_size as usize);
+    MemoryResponse { data, status: MemoryResponseType::Valid }
+}
+
+impl MemoryDevice for SifiveUart {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address - start_address >= 0x1C);
+        assert!(memory_type == MemoryDeviceType::UART2);
+        Self {
+            start_address,
+            end_address,
+            receiver: UartReceiver::new(),
+            transmitter: UartTransmitter::new(),
+            txctrl: 0,
+            rxctrl: 0,
+            ie: 0,
+            div: SIFIVE_UART_DEFAULT_DIV,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        let offset = request.data_address - self.start_address;
+        if request.request_type == MemoryRequestType::READ {
+            if offset == SIFIVE_UART_RXDATA {
+                self.receiver.start();
+                let value = self.receiver.pop().map_or(SIFIVE_UART_RX_EMPTY, |byte| byte as u32);
+                return word_response(value, &request);
+            }
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match offset {
+            SIFIVE_UART_TXDATA => {
+                self.transmitter.send(value as u8);
+            }
+            SIFIVE_UART_TXCTRL => self.txctrl = value & 0x7_0003,
+            SIFIVE_UART_RXCTRL => {
+                self.rxctrl = value & 0x7_0001;
+                if self.rxctrl & SIFIVE_UART_ENABLE != 0 {
+                    self.receiver.start();
+                }
+            }
+            SIFIVE_UART_IE => self.ie = value & (SIFIVE_UART_TXWM | SIFIVE_UART_RXWM),
+            SIFIVE_UART_DIV => self.div = value & 0xFFFF,
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    /// reads without side effects: RXDATA only peeks the FIFO
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        match self.register(request.data_address - self.start_address) {
+            Some(value) => word_response(value, &request),
+            None => MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable },
+        }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::UART2
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn pending_irq(&self) -> bool {
+        self.ie & self.interrupt_pending() != 0
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nSiFive UART: {{ TXCTRL={:X} RXCTRL={:X} IE={:X} IP={:X} DIV={:X} }}",
+            self.txctrl, self.rxctrl, self.ie, self.interrupt_pending(), self.div
+        );
+        Ok(())
+    }
+
+    fn reset(&mut self, _clear_memory: bool) {
+        self.receiver.clear();
+        self.txctrl = 0;
+        self.rxctrl = 0;
+        self.ie = 0;
+        self.div = SIFIVE_UART_DEFAULT_DIV;
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(SIFIVE_UART_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+}
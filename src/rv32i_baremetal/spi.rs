@@ -0,0 +1,151 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+
+/// register offsets of the SPI master
+/// writing TXDATA shifts one byte out to the selected slave and latches the byte shifted in into RXDATA
+pub const SPI_TXDATA: Address = 0x0;
+pub const SPI_RXDATA: Address = 0x4;
+pub const SPI_STATUS: Address = 0x8;
+/// index of the selected slave, `SPI_CS_NONE` releases the chip select lines
+pub const SPI_CS: Address = 0xC;
+
+pub const SPI_CS_NONE: u32 = 0xFFFF_FFFF;
+
+/// status register bits, transfers complete immediately so the controller is never busy
+pub const SPI_STATUS_RX_VALID: u8 = 1 << 0;
+
+/// a simulated device on the SPI bus (ex. a flash chip or a sensor)
+pub trait SpiSlave: Send + Sync {
+    /// chip select asserted, a new command starts
+    fn select(&mut self) {}
+
+    /// chip select released, the current command ends
+    fn deselect(&mut self) {}
+
+    /// full duplex exchange of one byte: receive MOSI and return MISO
+    fn transfer(&mut self, mosi: u8) -> u8;
+}
+
+pub struct SpiController {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    slaves: Vec<Box<dyn SpiSlave>>,
+    selected: Option<usize>,
+    rx_data: u8,
+    rx_valid: bool,
+}
+
+impl SpiController {
+    /// connect a slave to the bus and return its chip select index
+    pub fn attach_slave(&mut self, slave: Box<dyn SpiSlave>) -> usize {
+        self.slaves.push(slave);
+        self.slaves.len() - 1
+    }
+
+    fn select(&mut self, chip_select: u32) {
+        if let Some(previous) = self.selected.take() {
+            self.slaves[previous].deselect();
+        }
+        if chip_select != SPI_CS_NONE {
+            let index = chip_select as usize;
+            if index < self.slaves.len() {
+                self.slaves[index].select();
+                self.selected = Some(index);
+            } else {
+                tracing::warn!("No SPI slave connected on chip select {index}");
+            }
+        }
+    }
+
+    fn transfer(&mut self, mosi: u8) {
+        // with no slave selected MISO floats high
+        self.rx_data = match self.selected {
+            Some(index) => self.slaves[index].transfer(mosi),
+            None => 0xFF,
+        };
+        self.rx_valid = true;
+    }
+}
+
+impl MemoryDevice for SpiController {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::SPI0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            slaves: vec![],
+            selected: None,
+            rx_data: 0,
+            rx_valid: false,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        let offset = request.data_address - self.start_address;
+        if request.request_type == MemoryRequestType::WRITE {
+            assert!(request.data.is_some());
+            let mut bytes = request.data.unwrap();
+            bytes.resize(4, 0);
+            match offset {
+                SPI_TXDATA => self.transfer(bytes[0]),
+                SPI_CS => self.select(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+                _ => {
+                    return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+                }
+            }
+            MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+        } else {
+            let response = self.read_request(request);
+            if offset == SPI_RXDATA {
+                self.rx_valid = false;
+            }
+            response
+        }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let value = match request.data_address - self.start_address {
+            SPI_RXDATA => self.rx_data as u32,
+            SPI_STATUS => {
+                if self.rx_valid { SPI_STATUS_RX_VALID as u32 } else { 0 }
+            }
+            SPI_CS => self.selected.map_or(SPI_CS_NONE, |index| index as u32),
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+            }
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nSPI {:?}: {{ slaves={} selected={:?} RX={:X} }}",
+            self.memory_type,
+            self.slaves.len(),
+            self.selected,
+            self.rx_data
+        );
+        Ok(())
+    }
+}
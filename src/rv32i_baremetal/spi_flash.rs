@@ -0,0 +1,135 @@
+use crate::rv32i_baremetal::spi::SpiSlave;
+
+/// subset of the common SPI NOR flash commands (ex. Winbond W25Q series)
+pub const CMD_PAGE_PROGRAM: u8 = 0x02;
+pub const CMD_READ: u8 = 0x03;
+pub const CMD_WRITE_DISABLE: u8 = 0x04;
+pub const CMD_READ_STATUS: u8 = 0x05;
+pub const CMD_WRITE_ENABLE: u8 = 0x06;
+pub const CMD_SECTOR_ERASE: u8 = 0x20;
+pub const CMD_READ_JEDEC_ID: u8 = 0x9F;
+pub const CMD_CHIP_ERASE: u8 = 0xC7;
+
+/// status register bits, operations complete immediately so WIP always reads 0
+pub const STATUS_WRITE_ENABLED: u8 = 1 << 1;
+
+pub const FLASH_PAGE_SIZE: usize = 256;
+pub const FLASH_SECTOR_SIZE: usize = 4096;
+
+const JEDEC_ID: [u8; 3] = [0xEF, 0x40, 0x18];
+
+/// NOR flash behind an SPI bus: erase sets bytes to 0xFF and programming can only clear bits
+pub struct SpiNorFlash {
+    data: Vec<u8>,
+    command: Option<u8>,
+    address: usize,
+    /// number of address bytes still expected for the current command
+    address_bytes: u8,
+    /// bytes exchanged since the command byte, used for the JEDEC id
+    position: usize,
+    write_enabled: bool,
+}
+
+impl SpiNorFlash {
+    pub fn new(size: usize) -> Self {
+        assert!(size > 0 && size.is_multiple_of(FLASH_SECTOR_SIZE));
+        Self {
+            data: vec![0xFF; size],
+            command: None,
+            address: 0,
+            address_bytes: 0,
+            position: 0,
+            write_enabled: false,
+        }
+    }
+
+    /// preload the flash content, ex. with a firmware image
+    pub fn init_mem(&mut self, address: usize, data: &[u8]) {
+        assert!(address + data.len() <= self.data.len());
+        self.data[address..address + data.len()].copy_from_slice(data);
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.data
+    }
+
+    fn start_command(&mut self, command: u8) {
+        self.command = Some(command);
+        self.address = 0;
+        self.position = 0;
+        self.address_bytes = match command {
+            CMD_READ | CMD_PAGE_PROGRAM | CMD_SECTOR_ERASE => 3,
+            _ => 0,
+        };
+        match command {
+            CMD_WRITE_ENABLE => self.write_enabled = true,
+            CMD_WRITE_DISABLE => self.write_enabled = false,
+            CMD_CHIP_ERASE if self.write_enabled => {
+                self.data.fill(0xFF);
+                self.write_enabled = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn sector_erase(&mut self) {
+        if self.write_enabled {
+            let start = (self.address % self.data.len()) & !(FLASH_SECTOR_SIZE - 1);
+            self.data[start..start + FLASH_SECTOR_SIZE].fill(0xFF);
+        }
+        self.write_enabled = false;
+    }
+}
+
+impl SpiSlave for SpiNorFlash {
+    fn select(&mut self) {
+        self.command = None;
+    }
+
+    fn deselect(&mut self) {
+        // program and erase commands are committed when chip select is released
+        if self.command == Some(CMD_PAGE_PROGRAM) {
+            self.write_enabled = false;
+        }
+        self.command = None;
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        let Some(command) = self.command else {
+            self.start_command(mosi);
+            return 0xFF;
+        };
+
+        if self.address_bytes > 0 {
+            self.address = (self.address << 8) | mosi as usize;
+            self.address_bytes -= 1;
+            if self.address_bytes == 0 && command == CMD_SECTOR_ERASE {
+                self.sector_erase();
+            }
+            return 0xFF;
+        }
+
+        let miso = match command {
+            CMD_READ => {
+                let value = self.data[self.address % self.data.len()];
+                self.address += 1;
+                value
+            }
+            CMD_PAGE_PROGRAM if self.write_enabled => {
+                let address = self.address % self.data.len();
+                self.data[address] &= mosi;
+                // programming wraps around inside the current page
+                let page = self.address & !(FLASH_PAGE_SIZE - 1);
+                self.address = page | ((self.address + 1) & (FLASH_PAGE_SIZE - 1));
+                0xFF
+            }
+            CMD_READ_STATUS => {
+                if self.write_enabled { STATUS_WRITE_ENABLED } else { 0 }
+            }
+            CMD_READ_JEDEC_ID => JEDEC_ID.get(self.position).copied().unwrap_or(0xFF),
+            _ => 0xFF,
+        };
+        self.position += 1;
+        miso
+    }
+}
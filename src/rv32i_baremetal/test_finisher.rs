@@ -0,0 +1,106 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::risc_soc::{ExitSignal, ExitStatus};
+use std::io::Write;
+
+/// register offsets of the debug device
+/// same layout as the SiFive test finisher of the QEMU virt machine, followed by a byte wide console
+pub const FINISHER_CONTROL: Address = 0x0;
+pub const FINISHER_CONSOLE: Address = 0x4;
+
+/// values written to the finisher register, the upper 16 bits hold the exit code for FAIL
+pub const FINISHER_FAIL: u32 = 0x3333;
+pub const FINISHER_PASS: u32 = 0x5555;
+pub const FINISHER_RESET: u32 = 0x7777;
+
+/// lets baremetal test programs end the simulation with a result and print without a full UART driver:
+/// `let finisher = TestFinisher::new(MemoryDeviceType::DEBUG, 0x10_0000, 0x10_1000).with_exit_signal(core.exit_signal.clone());`
+pub struct TestFinisher {
+    start_address: Address,
+    end_address: Address,
+    exit_signal: ExitSignal,
+    console: Box<dyn Write + Send + Sync>,
+}
+
+impl TestFinisher {
+    /// the core stops at the end of the clock cycle in which the program wrote to the finisher
+    pub fn with_exit_signal(mut self, exit_signal: ExitSignal) -> Self {
+        self.exit_signal = exit_signal;
+        self
+    }
+
+    /// where the bytes written to the console register go, the host stdout by default
+    pub fn set_console(&mut self, console: Box<dyn Write + Send + Sync>) {
+        self.console = console;
+    }
+
+    fn finish(&self, value: u32) -> MemoryResponseType {
+        let status = match value & 0xFFFF {
+            FINISHER_PASS => ExitStatus::Pass,
+            FINISHER_FAIL => ExitStatus::Fail((value >> 16) as u16),
+            FINISHER_RESET => ExitStatus::Reset,
+            _ => return MemoryResponseType::Valid,
+        };
+        tracing::info!("Program requested the end of the simulation: {:?}", status);
+        self.exit_signal.exit(status);
+        MemoryResponseType::Valid
+    }
+}
+
+impl MemoryDevice for TestFinisher {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::DEBUG);
+        Self {
+            start_address,
+            end_address,
+            exit_signal: ExitSignal::default(),
+            console: Box::new(std::io::stdout()),
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let status = match request.data_address - self.start_address {
+            FINISHER_CONTROL => self.finish(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            FINISHER_CONSOLE => {
+                self.console.write_all(&bytes[..1]).unwrap();
+                self.console.flush().unwrap();
+                MemoryResponseType::Valid
+            }
+            _ => MemoryResponseType::NotWrittable,
+        };
+        MemoryResponse { data: MemoryData::default(), status }
+    }
+
+    /// all registers are write only and read as zero
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        MemoryResponse { data: MemoryData::zeroed(request.data_size as usize), status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::DEBUG
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!("\nMemory {:?}: exit status {:?}", MemoryDeviceType::DEBUG, self.exit_signal.status());
+        Ok(())
+    }
+}
@@ -0,0 +1,373 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess, RegisterField};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// register offsets of the timer block
+pub const TIMER_CONTROL: Address = 0x00;
+/// the counter advances once every PRESCALER + 1 edges of the timer clock
+pub const TIMER_PRESCALER: Address = 0x04;
+/// the counter counts from 0 up to PERIOD included, then overflows back to 0
+pub const TIMER_PERIOD: Address = 0x08;
+pub const TIMER_COUNTER: Address = 0x0C;
+/// write 1 to clear the flags
+pub const TIMER_STATUS: Address = 0x10;
+pub const TIMER_IRQ_ENABLE: Address = 0x14;
+/// each channel has a mode and a compare/capture register, starting here
+pub const TIMER_CHANNEL_BASE: Address = 0x20;
+pub const TIMER_CHANNEL_STRIDE: Address = 0x8;
+pub const TIMER_CHANNEL_MODE: Address = 0x0;
+pub const TIMER_CHANNEL_COMPARE: Address = 0x4;
+
+pub const TIMER_CHANNELS: usize = 4;
+
+/// control register bits
+pub const TIMER_CONTROL_ENABLE: u32 = 1 << 0;
+/// the timer stops after the first overflow
+pub const TIMER_CONTROL_ONE_SHOT: u32 = 1 << 1;
+
+/// status and interrupt enable bits, the flag of a channel is set on a compare match or a capture
+pub const TIMER_STATUS_OVERFLOW: u32 = 1 << 0;
+pub const fn timer_status_channel(channel: usize) -> u32 {
+    1 << (1 + channel)
+}
+
+/// channel modes
+pub const TIMER_MODE_DISABLED: u32 = 0;
+/// sets the flag of the channel when the counter reaches the compare value
+pub const TIMER_MODE_COMPARE: u32 = 1;
+/// output high while the counter is below the compare value, so the duty cycle is COMPARE / (PERIOD + 1)
+pub const TIMER_MODE_PWM: u32 = 2;
+/// latches the counter into the compare register on an edge of the input of the channel
+pub const TIMER_MODE_CAPTURE: u32 = 3;
+
+const CONTROL_FIELDS: &[RegisterField] = &[RegisterField::bit("EN", 0), RegisterField::bit("ONE_SHOT", 1)];
+
+const STATUS_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("OVF", 0),
+    RegisterField::bit("CH0", 1),
+    RegisterField::bit("CH1", 2),
+    RegisterField::bit("CH2", 3),
+    RegisterField::bit("CH3", 4),
+];
+
+/// registers of the timer block, for the traces of the accesses
+pub const TIMER_REGISTERS: &[Register] = &[
+    Register::new("CONTROL", TIMER_CONTROL, RegisterAccess::ReadWrite).with_fields(CONTROL_FIELDS),
+    Register::new("PRESCALER", TIMER_PRESCALER, RegisterAccess::ReadWrite),
+    Register::new("PERIOD", TIMER_PERIOD, RegisterAccess::ReadWrite),
+    Register::new("COUNTER", TIMER_COUNTER, RegisterAccess::ReadWrite),
+    Register::new("STATUS", TIMER_STATUS, RegisterAccess::ReadWrite).with_fields(STATUS_FIELDS),
+    Register::new("IRQ_ENABLE", TIMER_IRQ_ENABLE, RegisterAccess::ReadWrite).with_fields(STATUS_FIELDS),
+    Register::new("CH0_MODE", TIMER_CHANNEL_BASE, RegisterAccess::ReadWrite),
+    Register::new("CH0_COMPARE", TIMER_CHANNEL_BASE + 0x4, RegisterAccess::ReadWrite),
+    Register::new("CH1_MODE", TIMER_CHANNEL_BASE + 0x8, RegisterAccess::ReadWrite),
+    Register::new("CH1_COMPARE", TIMER_CHANNEL_BASE + 0xC, RegisterAccess::ReadWrite),
+    Register::new("CH2_MODE", TIMER_CHANNEL_BASE + 0x10, RegisterAccess::ReadWrite),
+    Register::new("CH2_COMPARE", TIMER_CHANNEL_BASE + 0x14, RegisterAccess::ReadWrite),
+    Register::new("CH3_MODE", TIMER_CHANNEL_BASE + 0x18, RegisterAccess::ReadWrite),
+    Register::new("CH3_COMPARE", TIMER_CHANNEL_BASE + 0x1C, RegisterAccess::ReadWrite),
+];
+
+/// called with the channel index and its new level every time a PWM output toggles
+pub type TimerCallback = Box<dyn Fn(usize, bool) + Send + Sync>;
+
+/// handle given to host code (ex. tests or a simulated motor) to observe the PWM outputs and drive the capture inputs
+/// it stays usable after the device itself was moved into the MMU
+#[derive(Clone, Default)]
+pub struct TimerPins {
+    levels: Arc<AtomicU32>,
+    /// counter steps spent high by each output, and counter steps counted in total, for the duty cycles
+    high_steps: Arc<[AtomicU64; TIMER_CHANNELS]>,
+    steps: Arc<AtomicU64>,
+    /// channels whose input saw an edge not yet captured by the timer
+    captures: Arc<AtomicU32>,
+}
+
+impl TimerPins {
+    pub fn output(&self, channel: usize) -> bool {
+        assert!(channel < TIMER_CHANNELS);
+        self.levels.load(Ordering::SeqCst) & (1 << channel) != 0
+    }
+
+    /// fraction of the counter steps the output was high, since the timer was created or the last `clear_duty_cycles`
+    pub fn duty_cycle(&self, channel: usize) -> f64 {
+        assert!(channel < TIMER_CHANNELS);
+        let steps = self.steps.load(Ordering::SeqCst);
+        if steps == 0 {
+            return 0.0;
+        }
+        self.high_steps[channel].load(Ordering::SeqCst) as f64 / steps as f64
+    }
+
+    pub fn clear_duty_cycles(&self) {
+        self.steps.store(0, Ordering::SeqCst);
+        self.high_steps.iter().for_each(|high| high.store(0, Ordering::SeqCst));
+    }
+
+    /// edge on the input of a channel, the counter is captured on the next edge of the timer clock
+    pub fn capture(&self, channel: usize) {
+        assert!(channel < TIMER_CHANNELS);
+        self.captures.fetch_or(1 << channel, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Channel {
+    mode: u32,
+    compare: u32,
+}
+
+/// general purpose timer: a prescaled up counter with an auto-reload period and compare/capture channels,
+/// each channel can flag compare matches, drive a PWM output or capture the counter on an input edge
+/// callbacks and the pins handle should be set up before adding it to the MMU, like for the GPIO block
+pub struct Timer {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    control: u32,
+    prescaler: u32,
+    /// edges counted towards the next step of the counter
+    prescaled_edges: u64,
+    period: u32,
+    counter: u32,
+    status: u32,
+    irq_enable: u32,
+    channels: [Channel; TIMER_CHANNELS],
+    pins: TimerPins,
+    callbacks: Vec<TimerCallback>,
+}
+
+impl Timer {
+    /// register a host function to observe PWM output toggles (ex. a simulated LED or motor driver)
+    pub fn on_output_change(&mut self, callback: TimerCallback) {
+        self.callbacks.push(callback);
+    }
+
+    pub fn pins(&self) -> TimerPins {
+        self.pins.clone()
+    }
+
+    fn enabled(&self) -> bool {
+        self.control & TIMER_CONTROL_ENABLE != 0
+    }
+
+    fn pwm_level(&self, channel: usize) -> bool {
+        let channel = self.channels[channel];
+        channel.mode == TIMER_MODE_PWM && self.counter < channel.compare
+    }
+
+    fn update_outputs(&self) {
+        let levels = (0..TIMER_CHANNELS).filter(|channel| self.pwm_level(*channel)).fold(0, |levels, channel| levels | (1 << channel));
+        let toggled = self.pins.levels.swap(levels, Ordering::SeqCst) ^ levels;
+        for channel in (0..TIMER_CHANNELS).filter(|channel| toggled & (1 << channel) != 0) {
+            for callback in &self.callbacks {
+                callback(channel, levels & (1 << channel) != 0);
+            }
+        }
+    }
+
+    /// counter steps until something changes: an overflow, a compare match or a PWM output toggling
+    fn steps_to_next_event(&self) -> u64 {
+        let counter = self.counter as u64;
+        let overflow = self.period as u64 + 1 - counter.min(self.period as u64);
+        self.channels
+            .iter()
+            .filter(|channel| matches!(channel.mode, TIMER_MODE_COMPARE | TIMER_MODE_PWM) && channel.compare as u64 > counter)
+            .map(|channel| channel.compare as u64 - counter)
+            .fold(overflow, u64::min)
+    }
+
+    fn take_captures(&mut self) {
+        let captures = self.pins.captures.swap(0, Ordering::SeqCst);
+        for (index, channel) in self.channels.iter_mut().enumerate() {
+            if captures & (1 << index) != 0 && channel.mode == TIMER_MODE_CAPTURE {
+                channel.compare = self.counter;
+                self.status |= timer_status_channel(index);
+            }
+        }
+    }
+
+    /// advance the counter by whole steps, in chunks over which the outputs stay the same
+    fn advance(&mut self, mut steps: u64) {
+        while steps > 0 && self.enabled() {
+            let chunk = steps.min(self.steps_to_next_event());
+            let levels = self.pins.levels.load(Ordering::SeqCst);
+            for channel in 0..TIMER_CHANNELS {
+                if levels & (1 << channel) != 0 {
+                    self.pins.high_steps[channel].fetch_add(chunk, Ordering::SeqCst);
+                }
+            }
+            self.pins.steps.fetch_add(chunk, Ordering::SeqCst);
+            steps -= chunk;
+
+            let counter = self.counter as u64 + chunk;
+            if counter > self.period as u64 {
+                self.counter = 0;
+                self.status |= TIMER_STATUS_OVERFLOW;
+                if self.control & TIMER_CONTROL_ONE_SHOT != 0 {
+                    self.control &= !TIMER_CONTROL_ENABLE;
+                }
+            } else {
+                self.counter = counter as u32;
+            }
+            for (index, channel) in self.channels.iter().enumerate() {
+                if channel.mode == TIMER_MODE_COMPARE && channel.compare == self.counter {
+                    self.status |= timer_status_channel(index);
+                }
+            }
+            self.update_outputs();
+        }
+    }
+
+    fn channel_register(offset: Address) -> Option<(usize, Address)> {
+        let offset = offset.checked_sub(TIMER_CHANNEL_BASE)?;
+        let channel = (offset / TIMER_CHANNEL_STRIDE) as usize;
+        (channel < TIMER_CHANNELS).then_some((channel, offset % TIMER_CHANNEL_STRIDE))
+    }
+}
+
+impl MemoryDevice for Timer {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address - start_address >= TIMER_CHANNEL_BASE + TIMER_CHANNEL_STRIDE * TIMER_CHANNELS as Address);
+        assert!(memory_type == MemoryDeviceType::TIMER0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            control: 0,
+            prescaler: 0,
+            prescaled_edges: 0,
+            period: u32::MAX,
+            counter: 0,
+            status: 0,
+            irq_enable: 0,
+            channels: [Channel::default(); TIMER_CHANNELS],
+            pins: TimerPins::default(),
+            callbacks: vec![],
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let offset = request.data_address - self.start_address;
+        match offset {
+            TIMER_CONTROL => self.control = value,
+            TIMER_PRESCALER => {
+                self.prescaler = value;
+                self.prescaled_edges = 0;
+            }
+            TIMER_PERIOD => self.period = value,
+            TIMER_COUNTER => self.counter = value,
+            TIMER_STATUS => self.status &= !value,
+            TIMER_IRQ_ENABLE => self.irq_enable = value,
+            _ => match Self::channel_register(offset) {
+                Some((channel, TIMER_CHANNEL_MODE)) => self.channels[channel].mode = value,
+                Some((channel, TIMER_CHANNEL_COMPARE)) => self.channels[channel].compare = value,
+                _ => {
+                    return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+                }
+            },
+        }
+        self.update_outputs();
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let offset = request.data_address - self.start_address;
+        let value = match offset {
+            TIMER_CONTROL => self.control,
+            TIMER_PRESCALER => self.prescaler,
+            TIMER_PERIOD => self.period,
+            TIMER_COUNTER => self.counter,
+            TIMER_STATUS => self.status,
+            TIMER_IRQ_ENABLE => self.irq_enable,
+            _ => match Self::channel_register(offset) {
+                Some((channel, TIMER_CHANNEL_MODE)) => self.channels[channel].mode,
+                Some((channel, TIMER_CHANNEL_COMPARE)) => self.channels[channel].compare,
+                _ => {
+                    return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+                }
+            },
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nTimer {:?}: {{ CONTROL={:X} PRESCALER={:X} PERIOD={:X} COUNTER={:X} STATUS={:X} IRQ_ENABLE={:X} channels={:?} }}",
+            self.memory_type, self.control, self.prescaler, self.period, self.counter, self.status, self.irq_enable, self.channels
+        );
+        Ok(())
+    }
+
+    fn tick(&mut self, edges: u64) {
+        self.take_captures();
+        if !self.enabled() {
+            return;
+        }
+        let edges = self.prescaled_edges + edges;
+        let divider = self.prescaler as u64 + 1;
+        self.prescaled_edges = edges % divider;
+        self.advance(edges / divider);
+    }
+
+    fn pending_irq(&self) -> bool {
+        self.status & self.irq_enable != 0
+    }
+
+    /// edges until the next overflow, compare match or PWM toggle, or the next pending capture
+    fn next_event(&self) -> Option<u64> {
+        if self.pins.captures.load(Ordering::SeqCst) != 0 {
+            return Some(1);
+        }
+        if !self.enabled() {
+            return None;
+        }
+        let divider = self.prescaler as u64 + 1;
+        Some((self.steps_to_next_event() * divider).saturating_sub(self.prescaled_edges).max(1))
+    }
+
+    fn reset(&mut self, _clear_memory: bool) {
+        self.control = 0;
+        self.prescaler = 0;
+        self.prescaled_edges = 0;
+        self.period = u32::MAX;
+        self.counter = 0;
+        self.status = 0;
+        self.irq_enable = 0;
+        self.channels = [Channel::default(); TIMER_CHANNELS];
+        self.pins.captures.store(0, Ordering::SeqCst);
+        self.update_outputs();
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(TIMER_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+}
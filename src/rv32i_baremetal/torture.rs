@@ -0,0 +1,348 @@
+use crate::risc_soc::asm::{b_type, i_type, j_type, load_immediate, r_type, s_type, u_type};
+use crate::risc_soc::fault_injection::Rng;
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{ExitStatus, RiscCore};
+use crate::risc_soc::run_control::RunControl;
+use crate::rv32i_baremetal::core::{init_core, DRAM_ADDRESS, L1_ADDRESS, TEST_FINISHER_ADDRESS};
+use crate::rv32i_baremetal::decode::{OP_ALU, OP_ALUI, OP_AUIPC, OP_BRANCH, OP_JAL, OP_LOAD, OP_LUI, OP_STORE};
+
+/// bytes the loads and stores of the sequence access, from the start of the DRAM
+pub const TORTURE_DATA_SIZE: usize = 256;
+/// the self-checking stores write x1-x30 right after the data, as the signature of the sequence
+pub const TORTURE_SIGNATURE_SIZE: usize = 30 * 4;
+/// base register of the loads and stores, never written by the sequence
+const BASE_REG: u32 = 31;
+/// longest forward jump of the branches, in instructions
+const MAX_SKIP: u64 = 4;
+
+/// instruction of a random sequence, the branches keep their target as a number of instructions to skip
+/// so the sequence can lose instructions while it is shrunk and still jump forward inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TortureInstruction {
+    /// encoding that does not depend on the place of the instruction in the sequence
+    Plain(u32),
+    /// BEQ to BGEU, as given by func3
+    Branch { func3: u32, rs1: u32, rs2: u32, skip: usize },
+    Jal { rd: u32, skip: usize },
+}
+
+/// Random but architecturally valid RV32IM sequence: the registers start with random values, the loads and stores stay aligned
+/// in the data region, the branches only jump forward, and the sequence ends with the self-checking stores of x1-x30
+/// Running it on the pipeline and on the reference interpreter must leave the same data and signature, as in riscv-torture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TortureTest {
+    pub seed: u64,
+    /// values of x1-x30 before the sequence
+    pub initial: Vec<u32>,
+    pub body: Vec<TortureInstruction>,
+}
+
+/// first difference found between the pipeline and the reference interpreter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TortureFailure {
+    /// the pipeline did not reach the end of the sequence, ex. it trapped or ran out of cycles
+    NotFinished(String),
+    /// register of the signature: number, value on the reference and on the pipeline
+    Register(usize, u32, u32),
+    /// byte of the data region: address, value on the reference and on the pipeline
+    Memory(Address, u8, u8),
+}
+
+impl TortureTest {
+    pub fn generate(seed: u64, length: usize) -> Self {
+        let mut rng = Rng::new(seed);
+        let initial = (1..=30).map(|_| rng.next_u64() as u32).collect();
+        let body = (0..length).map(|_| random_instruction(&mut rng)).collect();
+        Self { seed, initial, body }
+    }
+
+    /// address right after the self-checking stores, where the sequence writes the test finisher
+    fn end_address(&self) -> Address {
+        L1_ADDRESS + 4 * self.checked_program().len() as Address
+    }
+
+    /// initialization, body and self-checking stores, everything the reference interpreter runs
+    fn checked_program(&self) -> Vec<u32> {
+        let mut program = vec![];
+        for (index, value) in self.initial.iter().enumerate() {
+            program.extend(load_immediate(index as u32 + 1, *value));
+        }
+        program.extend(load_immediate(BASE_REG, DRAM_ADDRESS as u32));
+        for (index, instruction) in self.body.iter().enumerate() {
+            program.push(match *instruction {
+                TortureInstruction::Plain(word) => word,
+                // a jump past the end of the body lands on the first self-checking store
+                TortureInstruction::Branch { func3, rs1, rs2, skip } => {
+                    b_type(4 * (skip.min(self.body.len() - index - 1) as u32 + 1), rs2, rs1, func3)
+                }
+                TortureInstruction::Jal { rd, skip } => j_type(4 * (skip.min(self.body.len() - index - 1) as u32 + 1), rd),
+            });
+        }
+        for reg in 1..=30 {
+            program.push(s_type(TORTURE_DATA_SIZE as u32 + 4 * (reg - 1), reg, BASE_REG, 0b010, OP_STORE as u32));
+        }
+        program
+    }
+
+    /// whole sequence, ending with a PASS written to the test finisher
+    pub fn program(&self) -> Vec<u32> {
+        let mut program = self.checked_program();
+        program.extend(load_immediate(BASE_REG, TEST_FINISHER_ADDRESS as u32));
+        program.extend(load_immediate(30, 0x5555));
+        program.push(s_type(0, 30, BASE_REG, 0b010, OP_STORE as u32));
+        // j .
+        program.push(j_type(0, 0));
+        program
+    }
+
+    /// data region followed by the signature, as left by the reference interpreter
+    pub fn run_reference(&self) -> Vec<u8> {
+        let program = self.checked_program();
+        let mut model = Reference { regs: [0; 32], pc: L1_ADDRESS as u32, memory: vec![0; TORTURE_DATA_SIZE + TORTURE_SIGNATURE_SIZE] };
+        while model.pc != self.end_address() as u32 {
+            let word = program[((model.pc - L1_ADDRESS as u32) / 4) as usize];
+            model.step(word);
+        }
+        model.memory
+    }
+
+    /// data region followed by the signature, as left by the core built with `build` (ex. `init_core`)
+    pub fn run_pipeline(&self, build: impl Fn() -> RiscCore) -> Result<Vec<u8>, TortureFailure> {
+        let mut core = build();
+        let bytes: Vec<u8> = self.program().iter().flat_map(|word| word.to_le_bytes()).collect();
+        core.init_memory(L1_ADDRESS, &bytes);
+        core.set_reset_vector(L1_ADDRESS as _);
+        // every instruction retires in a few cycles, the multiplications and divisions included
+        let reason = core.run_sequential_with(RunControl::cycles(1000 + 50 * bytes.len() as u64));
+        if core.exit_status() != Some(ExitStatus::Pass) {
+            let reason = match core.pending_trap() {
+                Some(trap) => format!("{trap}"),
+                None => format!("{reason:?}"),
+            };
+            return Err(TortureFailure::NotFinished(reason));
+        }
+        core.read_mem(DRAM_ADDRESS, TORTURE_DATA_SIZE + TORTURE_SIGNATURE_SIZE)
+            .map_err(|error| TortureFailure::NotFinished(error.to_string()))
+    }
+
+    /// compare the pipeline of the MCU with the reference interpreter
+    pub fn check(&self) -> Result<(), TortureFailure> {
+        self.check_with(|| init_core(None))
+    }
+
+    pub fn check_with(&self, build: impl Fn() -> RiscCore) -> Result<(), TortureFailure> {
+        let expected = self.run_reference();
+        let actual = self.run_pipeline(build)?;
+        for reg in 0..30 {
+            let offset = TORTURE_DATA_SIZE + 4 * reg;
+            let word = |bytes: &[u8]| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+            if word(&expected) != word(&actual) {
+                return Err(TortureFailure::Register(reg + 1, word(&expected), word(&actual)));
+            }
+        }
+        match (0..TORTURE_DATA_SIZE).find(|&offset| expected[offset] != actual[offset]) {
+            Some(offset) => Err(TortureFailure::Memory(DRAM_ADDRESS + offset as Address, expected[offset], actual[offset])),
+            None => Ok(()),
+        }
+    }
+
+    /// smallest sequence found that still fails, removing chunks of instructions from the body while `fails` holds
+    /// ex. `test.shrink(|test| test.check().is_err())`
+    pub fn shrink(&self, fails: impl Fn(&TortureTest) -> bool) -> TortureTest {
+        assert!(fails(self), "only a failing sequence can be shrunk");
+        let mut smallest = self.clone();
+        let mut chunk = smallest.body.len().div_ceil(2);
+        while chunk > 0 {
+            let mut start = 0;
+            while start < smallest.body.len() {
+                let mut candidate = smallest.clone();
+                candidate.body.drain(start..(start + chunk).min(candidate.body.len()));
+                if fails(&candidate) {
+                    smallest = candidate;
+                } else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+        smallest
+    }
+}
+
+fn random_reg(rng: &mut Rng) -> u32 {
+    rng.range(0..31) as u32
+}
+
+/// any register but x0 and the base register of the loads and stores
+fn random_rd(rng: &mut Rng) -> u32 {
+    rng.range(1..31) as u32
+}
+
+fn random_instruction(rng: &mut Rng) -> TortureInstruction {
+    let rd = random_rd(rng);
+    let (rs1, rs2) = (random_reg(rng), random_reg(rng));
+    let word = match rng.range(0..100) {
+        0..=29 => {
+            // ADD, SUB, SLL, SLT, SLTU, XOR, SRL, SRA, OR, AND
+            let (funct7, funct3) = [(0, 0), (0x20, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (0x20, 5), (0, 6), (0, 7)][rng.range(0..10) as usize];
+            r_type(funct7, rs2, rs1, funct3, rd, OP_ALU as u32)
+        }
+        30..=44 => r_type(0b0000001, rs2, rs1, rng.range(0..8) as u32, rd, OP_ALU as u32),
+        45..=64 => match rng.range(0..9) {
+            // SLLI, SRLI, SRAI
+            0 => i_type(rng.range(0..32) as u32, rs1, 1, rd, OP_ALUI as u32),
+            1 => i_type(rng.range(0..32) as u32, rs1, 5, rd, OP_ALUI as u32),
+            2 => i_type(0x400 | rng.range(0..32) as u32, rs1, 5, rd, OP_ALUI as u32),
+            // ADDI, SLTI, SLTIU, XORI, ORI, ANDI
+            funct3 => i_type(rng.next_u64() as u32, rs1, [0, 2, 3, 4, 6, 7][funct3 as usize - 3], rd, OP_ALUI as u32),
+        },
+        65..=69 => u_type(rng.range(0..1 << 20) as u32, rd, [OP_LUI, OP_AUIPC][rng.range(0..2) as usize] as u32),
+        70..=81 => {
+            // LB, LH, LW, LBU, LHU, aligned on their size
+            let (funct3, size) = [(0, 1), (1, 2), (2, 4), (4, 1), (5, 2)][rng.range(0..5) as usize];
+            let offset = rng.range(0..(TORTURE_DATA_SIZE / size) as u64) as u32 * size as u32;
+            i_type(offset, BASE_REG, funct3, rd, OP_LOAD as u32)
+        }
+        82..=91 => {
+            // SB, SH, SW
+            let funct3 = rng.range(0..3) as u32;
+            let size = 1 << funct3;
+            let offset = rng.range(0..(TORTURE_DATA_SIZE / size) as u64) as u32 * size as u32;
+            s_type(offset, rs2, BASE_REG, funct3, OP_STORE as u32)
+        }
+        92..=97 => {
+            let func3 = [0, 1, 4, 5, 6, 7][rng.range(0..6) as usize];
+            return TortureInstruction::Branch { func3, rs1, rs2, skip: rng.range(0..MAX_SKIP + 1) as usize };
+        }
+        _ => return TortureInstruction::Jal { rd, skip: rng.range(0..MAX_SKIP + 1) as usize },
+    };
+    TortureInstruction::Plain(word)
+}
+
+/// Instruction set simulator of RV32IM, written apart from the stage functions so both can be checked against each other
+struct Reference {
+    regs: [u32; 32],
+    pc: u32,
+    /// data region and signature, at the start of the DRAM
+    memory: Vec<u8>,
+}
+
+impl Reference {
+    fn offset(&self, address: u32, size: usize) -> usize {
+        let offset = address.wrapping_sub(DRAM_ADDRESS as u32) as usize;
+        assert!(offset + size <= self.memory.len() && offset.is_multiple_of(size), "access outside of the data region at 0x{address:X}");
+        offset
+    }
+
+    fn load(&self, address: u32, size: usize) -> u32 {
+        let offset = self.offset(address, size);
+        let mut bytes = [0; 4];
+        bytes[..size].copy_from_slice(&self.memory[offset..offset + size]);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn store(&mut self, address: u32, size: usize, value: u32) {
+        let offset = self.offset(address, size);
+        self.memory[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    fn step(&mut self, word: u32) {
+        let opcode = (word & 0x7F) as u8;
+        let rd = ((word >> 7) & 0x1F) as usize;
+        let funct3 = (word >> 12) & 0x7;
+        let rs1 = self.regs[((word >> 15) & 0x1F) as usize];
+        let rs2 = self.regs[((word >> 20) & 0x1F) as usize];
+        let funct7 = word >> 25;
+        let imm_i = (word as i32 >> 20) as u32;
+        let imm_s = ((word as i32 >> 25) << 5) as u32 | ((word >> 7) & 0x1F);
+        let imm_b = ((word as i32 >> 31) << 12) as u32 | ((word >> 7) & 0x1) << 11 | ((word >> 25) & 0x3F) << 5 | ((word >> 8) & 0xF) << 1;
+        let imm_j = ((word as i32 >> 31) << 20) as u32 | ((word >> 12) & 0xFF) << 12 | ((word >> 20) & 0x1) << 11 | ((word >> 21) & 0x3FF) << 1;
+        let mut next_pc = self.pc.wrapping_add(4);
+        let result = match opcode {
+            OP_LUI => Some(word & 0xFFFF_F000),
+            OP_AUIPC => Some(self.pc.wrapping_add(word & 0xFFFF_F000)),
+            OP_JAL => {
+                next_pc = self.pc.wrapping_add(imm_j);
+                Some(self.pc.wrapping_add(4))
+            }
+            OP_BRANCH => {
+                let taken = match funct3 {
+                    0 => rs1 == rs2,
+                    1 => rs1 != rs2,
+                    4 => (rs1 as i32) < (rs2 as i32),
+                    5 => (rs1 as i32) >= (rs2 as i32),
+                    6 => rs1 < rs2,
+                    7 => rs1 >= rs2,
+                    _ => panic!("invalid branch 0x{word:08X}"),
+                };
+                if taken {
+                    next_pc = self.pc.wrapping_add(imm_b);
+                }
+                None
+            }
+            OP_LOAD => {
+                let address = rs1.wrapping_add(imm_i);
+                Some(match funct3 {
+                    0 => self.load(address, 1) as i8 as u32,
+                    1 => self.load(address, 2) as i16 as u32,
+                    2 => self.load(address, 4),
+                    4 => self.load(address, 1),
+                    5 => self.load(address, 2),
+                    _ => panic!("invalid load 0x{word:08X}"),
+                })
+            }
+            OP_STORE => {
+                self.store(rs1.wrapping_add(imm_s), 1 << funct3, rs2);
+                None
+            }
+            OP_ALUI => {
+                let shamt = imm_i & 0x1F;
+                Some(match funct3 {
+                    0 => rs1.wrapping_add(imm_i),
+                    1 => rs1 << shamt,
+                    2 => ((rs1 as i32) < (imm_i as i32)) as u32,
+                    3 => (rs1 < imm_i) as u32,
+                    4 => rs1 ^ imm_i,
+                    5 if funct7 == 0x20 => ((rs1 as i32) >> shamt) as u32,
+                    5 => rs1 >> shamt,
+                    6 => rs1 | imm_i,
+                    _ => rs1 & imm_i,
+                })
+            }
+            OP_ALU if funct7 == 0b0000001 => Some(match funct3 {
+                0 => rs1.wrapping_mul(rs2),
+                1 => ((rs1 as i32 as i64 * rs2 as i32 as i64) >> 32) as u32,
+                2 => ((rs1 as i32 as i64 * rs2 as i64) >> 32) as u32,
+                3 => ((rs1 as u64 * rs2 as u64) >> 32) as u32,
+                4 if rs2 == 0 => u32::MAX,
+                4 => (rs1 as i32).wrapping_div(rs2 as i32) as u32,
+                5 if rs2 == 0 => u32::MAX,
+                5 => rs1 / rs2,
+                6 if rs2 == 0 => rs1,
+                6 => (rs1 as i32).wrapping_rem(rs2 as i32) as u32,
+                _ if rs2 == 0 => rs1,
+                _ => rs1 % rs2,
+            }),
+            OP_ALU => {
+                let shamt = rs2 & 0x1F;
+                Some(match (funct3, funct7) {
+                    (0, 0x20) => rs1.wrapping_sub(rs2),
+                    (0, _) => rs1.wrapping_add(rs2),
+                    (1, _) => rs1 << shamt,
+                    (2, _) => ((rs1 as i32) < (rs2 as i32)) as u32,
+                    (3, _) => (rs1 < rs2) as u32,
+                    (4, _) => rs1 ^ rs2,
+                    (5, 0x20) => ((rs1 as i32) >> shamt) as u32,
+                    (5, _) => rs1 >> shamt,
+                    (6, _) => rs1 | rs2,
+                    _ => rs1 & rs2,
+                })
+            }
+            _ => panic!("the reference interpreter does not run 0x{word:08X}"),
+        };
+        if let Some(value) = result.filter(|_| rd != 0) {
+            self.regs[rd] = value;
+        }
+        self.pc = next_pc;
+    }
+}
@@ -3,40 +3,397 @@ use crate::risc_soc::memory_management_unit::Address;
 use crate::risc_soc::memory_management_unit::MemoryRequest;
 use crate::risc_soc::memory_management_unit::MemoryRequestType;
 use crate::risc_soc::memory_management_unit::MemoryResponse;
+use crate::risc_soc::memory_management_unit::MemoryData;
 use crate::risc_soc::memory_management_unit::MemoryDeviceType;
 use crate::risc_soc::memory_management_unit::MemoryResponseType;
+use crate::risc_soc::register_map::{self, Register, RegisterAccess, RegisterField};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use crate::rv32i_baremetal::serial::SerialConnection;
 
+/// register offsets, following the layout of the AXI UART Lite used by MicroBlaze systems
+pub const UART_RX_FIFO: Address = 0x0;
+pub const UART_TX_FIFO: Address = 0x4;
+pub const UART_STATUS: Address = 0x8;
+pub const UART_CONTROL: Address = 0xC;
+
+/// status register bits
+pub const STATUS_RX_VALID: u8 = 1 << 0;
+pub const STATUS_RX_FULL: u8 = 1 << 1;
+pub const STATUS_TX_EMPTY: u8 = 1 << 2;
+pub const STATUS_TX_FULL: u8 = 1 << 3;
+pub const STATUS_INTR_ENABLED: u8 = 1 << 4;
+
+/// control register bits
+pub const CONTROL_RST_RX_FIFO: u8 = 1 << 1;
+pub const CONTROL_ENABLE_INTR: u8 = 1 << 4;
+
+const STATUS_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("RX_VALID", 0),
+    RegisterField::bit("RX_FULL", 1),
+    RegisterField::bit("TX_EMPTY", 2),
+    RegisterField::bit("TX_FULL", 3),
+    RegisterField::bit("INTR_ENABLED", 4),
+];
+const CONTROL_FIELDS: &[RegisterField] = &[RegisterField::bit("RST_RX_FIFO", 1), RegisterField::bit("ENABLE_INTR", 4)];
+
+/// registers as named in the data sheet of the AXI UART Lite, for the traces of the accesses
+pub const UART_REGISTERS: &[Register] = &[
+    Register::new("RX_FIFO", UART_RX_FIFO, RegisterAccess::ReadOnly).character(),
+    Register::new("TX_FIFO", UART_TX_FIFO, RegisterAccess::WriteOnly).character(),
+    Register::new("STAT", UART_STATUS, RegisterAccess::ReadOnly).with_fields(STATUS_FIELDS),
+    Register::new("CTRL", UART_CONTROL, RegisterAccess::WriteOnly).with_fields(CONTROL_FIELDS),
+];
+
+/// same depth as the hardware FIFO, bytes arriving while it is full are dropped
+pub const UART_FIFO_DEPTH: usize = 16;
+
+/// host side of a serial line: bytes from a host source are queued in the RX FIFO by a background thread
+/// shared by the UART models of this SoC
+pub struct UartReceiver {
+    /// bytes received from the host and not yet read by the guest
+    fifo: Arc<Mutex<VecDeque<u8>>>,
+    /// notified when the guest frees room in the FIFO
+    space: Arc<Condvar>,
+    /// the host is not read while the FIFO is full (RTS flow control), instead of dropping the received bytes
+    flow_control: Arc<AtomicBool>,
+    /// host side source of the received bytes, moved into a background thread on first use
+    /// kept behind a Mutex as the device must be shareable between the stage threads
+    source: Mutex<Option<Box<dyn Read + Send>>>,
+    interrupt_enabled: Arc<AtomicBool>,
+    /// interrupt line towards the interrupt controller, asserted while received data is waiting
+    interrupt_line: Arc<AtomicBool>,
+}
+
+impl UartReceiver {
+    pub fn new() -> Self {
+        Self {
+            fifo: Arc::new(Mutex::new(VecDeque::with_capacity(UART_FIFO_DEPTH))),
+            space: Arc::new(Condvar::new()),
+            flow_control: Arc::new(AtomicBool::new(false)),
+            source: Mutex::new(Some(Box::new(std::io::stdin()))),
+            interrupt_enabled: Arc::new(AtomicBool::new(false)),
+            interrupt_line: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// replace the host stdin with another source for the received bytes, ex. a TcpStream
+    pub fn bind_reader(&self, reader: Box<dyn Read + Send>) {
+        *self.source.lock().unwrap() = Some(reader);
+    }
+
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.interrupt_line.clone()
+    }
+
+    pub fn set_flow_control(&self, enable: bool) {
+        self.flow_control.store(enable, Ordering::SeqCst);
+        self.space.notify_all();
+    }
+
+    /// the reader thread is only started once the guest touches the receive side
+    /// so programs that only print never block on the host input
+    pub fn start(&self) {
+        let Some(mut source) = self.source.lock().unwrap().take() else {
+            return;
+        };
+        let fifo = self.fifo.clone();
+        let space = self.space.clone();
+        let flow_control = self.flow_control.clone();
+        let interrupt_enabled = self.interrupt_enabled.clone();
+        let interrupt_line = self.interrupt_line.clone();
+        std::thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            while let Ok(1) = source.read(&mut byte) {
+                let fifo = fifo.lock().unwrap();
+                let mut fifo = space
+                    .wait_while(fifo, |fifo| flow_control.load(Ordering::SeqCst) && fifo.len() >= UART_FIFO_DEPTH)
+                    .unwrap();
+                if fifo.len() < UART_FIFO_DEPTH {
+                    fifo.push_back(byte[0]);
+                } else {
+                    tracing::warn!("UART RX FIFO overrun, dropping received byte");
+                }
+                // receiving data can only raise the line, the device lowers it on guest accesses
+                if interrupt_enabled.load(Ordering::SeqCst) {
+                    interrupt_line.store(true, Ordering::SeqCst);
+                }
+            }
+        });
+    }
+
+    pub fn pop(&self) -> Option<u8> {
+        let byte = self.fifo.lock().unwrap().pop_front();
+        self.space.notify_all();
+        byte
+    }
+
+    pub fn peek(&self) -> Option<u8> {
+        self.fifo.lock().unwrap().front().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fifo.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fifo.lock().unwrap().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.fifo.lock().unwrap().clear();
+        self.space.notify_all();
+    }
+
+    pub fn set_interrupt_enabled(&self, enable: bool) {
+        self.interrupt_enabled.store(enable, Ordering::SeqCst);
+    }
+
+    pub fn interrupt_enabled(&self) -> bool {
+        self.interrupt_enabled.load(Ordering::SeqCst)
+    }
+
+    /// `other_sources` lets a device combine the RX condition with its own interrupt causes
+    pub fn update_interrupt_line(&self, other_sources: bool) {
+        let rx_pending = self.interrupt_enabled() && !self.is_empty();
+        self.interrupt_line.store(rx_pending || other_sources, Ordering::SeqCst);
+    }
+}
+
+impl Default for UartReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// host side of the transmit line: bytes are printed to stdout, or queued for the terminal bound with `bind_writer`
+/// the queue is as deep as the hardware FIFO and a byte leaves it once the terminal took it (CTS flow control)
+/// so a guest polling the status of the UART waits for a slow terminal instead of losing its output
+pub struct UartTransmitter {
+    fifo: Arc<Mutex<VecDeque<u8>>>,
+    /// notified when a byte is queued for the writer thread
+    queued: Arc<Condvar>,
+    bound: bool,
+    /// the terminal went away, the output is dropped from then on
+    detached: Arc<AtomicBool>,
+}
+
+impl UartTransmitter {
+    pub fn new() -> Self {
+        Self {
+            fifo: Arc::new(Mutex::new(VecDeque::with_capacity(UART_FIFO_DEPTH))),
+            queued: Arc::new(Condvar::new()),
+            bound: false,
+            detached: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// send the output to a terminal instead of stdout, ex. a TcpStream or a pseudo terminal
+    pub fn bind_writer(&mut self, mut writer: Box<dyn Write + Send>) {
+        assert!(!self.bound, "the transmitter is already bound to a terminal");
+        self.bound = true;
+        let fifo = self.fifo.clone();
+        let queued = self.queued.clone();
+        let detached = self.detached.clone();
+        std::thread::spawn(move || {
+            loop {
+                let byte = queued.wait_while(fifo.lock().unwrap(), |fifo| fifo.is_empty()).unwrap()[0];
+                if writer.write_all(&[byte]).and_then(|_| writer.flush()).is_err() {
+                    tracing::warn!("Serial terminal detached, the output of the UART is dropped");
+                    detached.store(true, Ordering::SeqCst);
+                    fifo.lock().unwrap().clear();
+                    return;
+                }
+                fifo.lock().unwrap().pop_front();
+            }
+        });
+    }
+
+    /// false if the byte was dropped because the FIFO was full
+    pub fn send(&self, byte: u8) -> bool {
+        if !self.bound {
+            print!("{}", byte as char);
+            std::io::stdout().flush().unwrap();
+            return true;
+        }
+        if self.detached.load(Ordering::SeqCst) {
+            return true;
+        }
+        let mut fifo = self.fifo.lock().unwrap();
+        if fifo.len() >= UART_FIFO_DEPTH {
+            tracing::warn!("UART TX FIFO overrun, dropping transmitted byte");
+            return false;
+        }
+        fifo.push_back(byte);
+        self.queued.notify_one();
+        true
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fifo.lock().unwrap().is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.fifo.lock().unwrap().len() >= UART_FIFO_DEPTH
+    }
+}
+
+impl Default for UartTransmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct UART {
     start_address: Address,
     end_address: Address,
+    receiver: UartReceiver,
+    transmitter: UartTransmitter,
+}
+
+impl UART {
+    /// receive from the given source instead of the host stdin, ex. a file or a socket
+    #[cfg(test)]
+    pub fn with_input(self, source: impl Read + Send + 'static) -> Self {
+        self.receiver.bind_reader(Box::new(source));
+        self
+    }
+
+    /// serial line of the UART Lite on a terminal, see `attach_lines`
+    pub fn attach(&mut self, connection: SerialConnection) {
+        attach_lines(&self.receiver, &mut self.transmitter, connection);
+    }
+
+    /// line that an interrupt controller can sample to know if the UART requests an interrupt
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.receiver.interrupt_line()
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if self.transmitter.is_empty() {
+            status |= STATUS_TX_EMPTY;
+        }
+        if self.transmitter.is_full() {
+            status |= STATUS_TX_FULL;
+        }
+        if !self.receiver.is_empty() {
+            status |= STATUS_RX_VALID;
+        }
+        if self.receiver.len() == UART_FIFO_DEPTH {
+            status |= STATUS_RX_FULL;
+        }
+        if self.receiver.interrupt_enabled() {
+            status |= STATUS_INTR_ENABLED;
+        }
+        status
+    }
+}
+
+/// connect both lines of a UART to a terminal in place of stdin and stdout, with flow control on both directions
+pub fn attach_lines(receiver: &UartReceiver, transmitter: &mut UartTransmitter, connection: SerialConnection) {
+    receiver.bind_reader(connection.input);
+    receiver.set_flow_control(true);
+    transmitter.bind_writer(connection.output);
+}
+
+/// UART registers are byte wide, the rest of the requested word is zero filled
+pub fn register_response(value: u8, request: &MemoryRequest) -> MemoryResponse {
+    let mut data = MemoryData::zeroed(request.data_size as usize);
+    data[0] = value;
+    MemoryResponse {
+        data,
+        status: MemoryResponseType::Valid,
+    }
 }
 
 impl MemoryDevice for UART {
     fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
         assert!(memory_type == MemoryDeviceType::UART0);
-        Self { 
-            start_address, 
-            end_address
+        Self {
+            start_address,
+            end_address,
+            receiver: UartReceiver::new(),
+            transmitter: UartTransmitter::new(),
         }
     }
 
     fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        let offset = request.data_address - self.start_address;
         if request.request_type == MemoryRequestType::WRITE {
-            assert!(request.data_address == self.start_address + 0x4 && request.data.is_some());
-            for char in request.data.unwrap() {
-                print!("{}", char as char);
+            let Some(data) = request.data else {
+                return MemoryResponse::bad_request("store without data".to_string());
+            };
+            match offset {
+                UART_TX_FIFO => {
+                    for byte in data.iter() {
+                        self.transmitter.send(*byte);
+                    }
+                }
+                UART_CONTROL => {
+                    let control = data[0];
+                    if control & CONTROL_RST_RX_FIFO != 0 {
+                        self.receiver.clear();
+                    }
+                    let enable = control & CONTROL_ENABLE_INTR != 0;
+                    self.receiver.set_interrupt_enabled(enable);
+                    if enable {
+                        self.receiver.start();
+                    }
+                    self.receiver.update_interrupt_line(false);
+                }
+                _ => {
+                    return MemoryResponse {
+                        data: MemoryData::default(),
+                        status: MemoryResponseType::NotWrittable,
+                    };
+                }
             }
             MemoryResponse{
-                data: vec![],
+                data: MemoryData::default(),
                 status: MemoryResponseType::Valid
             }
         } else {
-            panic!("The UART Device does not have the read operation implemented yet!")
-        }   
+            match offset {
+                UART_RX_FIFO => {
+                    self.receiver.start();
+                    let value = self.receiver.pop().unwrap_or(0);
+                    self.receiver.update_interrupt_line(false);
+                    register_response(value, &request)
+                }
+                UART_STATUS => {
+                    self.receiver.start();
+                    register_response(self.status(), &request)
+                }
+                _ => self.read_request(request),
+            }
+        }
     }
 
+    /// reads without side effects: the RX FIFO is only peeked
     fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
-        unimplemented!()
+        let offset = request.data_address - self.start_address;
+        let value = match offset {
+            UART_RX_FIFO => self.receiver.peek().unwrap_or(0),
+            UART_STATUS => self.status(),
+            UART_CONTROL => {
+                if self.receiver.interrupt_enabled() {
+                    CONTROL_ENABLE_INTR
+                } else {
+                    0
+                }
+            }
+            _ => {
+                return MemoryResponse {
+                    data: MemoryData::default(),
+                    status: MemoryResponseType::NotReadable,
+                };
+            }
+        };
+        register_response(value, &request)
     }
 
     fn start_end_addresses(&self) -> (Address, Address) {
@@ -47,15 +404,22 @@ impl MemoryDevice for UART {
         MemoryDeviceType::UART0
     }
 
-    fn init_mem(&mut self, address: Address, data: &[u8]) {
-        unimplemented!()        
-    }
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
 
     fn size(&self) -> usize {
-        unimplemented!()
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn pending_irq(&self) -> bool {
+        self.interrupt_line().load(Ordering::SeqCst)
     }
 
     fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
-        unimplemented!()        
+        unimplemented!()
     }
-}
\ No newline at end of file
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(UART_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+}
@@ -0,0 +1,315 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess, RegisterField};
+use crate::rv32i_baremetal::serial::SerialConnection;
+use crate::rv32i_baremetal::uart::{UartReceiver, UartTransmitter, attach_lines, register_response};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// register offsets of the 16550A, byte spaced as in the QEMU virt machine
+pub const RBR_THR_DLL: Address = 0x0;
+pub const IER_DLM: Address = 0x1;
+pub const IIR_FCR: Address = 0x2;
+pub const LCR: Address = 0x3;
+pub const MCR: Address = 0x4;
+pub const LSR: Address = 0x5;
+pub const MSR: Address = 0x6;
+pub const SCR: Address = 0x7;
+
+/// interrupt enable register bits
+pub const IER_RX_DATA: u8 = 1 << 0;
+pub const IER_THR_EMPTY: u8 = 1 << 1;
+
+/// interrupt identification values
+pub const IIR_NO_INTERRUPT: u8 = 0x01;
+pub const IIR_THR_EMPTY: u8 = 0x02;
+pub const IIR_RX_DATA: u8 = 0x04;
+pub const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+/// FIFO control register bits
+pub const FCR_ENABLE_FIFO: u8 = 1 << 0;
+pub const FCR_CLEAR_RX: u8 = 1 << 1;
+
+/// line control register divisor latch access bit
+pub const LCR_DLAB: u8 = 1 << 7;
+
+/// line status register bits
+pub const LSR_DATA_READY: u8 = 1 << 0;
+pub const LSR_THR_EMPTY: u8 = 1 << 5;
+pub const LSR_TX_EMPTY: u8 = 1 << 6;
+
+const IER_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("ERBFI", 0),
+    RegisterField::bit("ETBEI", 1),
+    RegisterField::bit("ELSI", 2),
+    RegisterField::bit("EDSSI", 3),
+];
+const IIR_FIELDS: &[RegisterField] =
+    &[RegisterField::bit("NO_INT", 0), RegisterField::bits("IID", 1, 3), RegisterField::bits("FIFOS", 6, 2)];
+const FCR_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("FIFOE", 0),
+    RegisterField::bit("RFIFOR", 1),
+    RegisterField::bit("XFIFOR", 2),
+    RegisterField::bits("RT", 6, 2),
+];
+const LCR_FIELDS: &[RegisterField] = &[
+    RegisterField::bits("WLS", 0, 2),
+    RegisterField::bit("STB", 2),
+    RegisterField::bit("PEN", 3),
+    RegisterField::bit("EPS", 4),
+    RegisterField::bit("SP", 5),
+    RegisterField::bit("BC", 6),
+    RegisterField::bit("DLAB", 7),
+];
+const MCR_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("DTR", 0),
+    RegisterField::bit("RTS", 1),
+    RegisterField::bit("OUT1", 2),
+    RegisterField::bit("OUT2", 3),
+    RegisterField::bit("LOOP", 4),
+];
+const LSR_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("DR", 0),
+    RegisterField::bit("OE", 1),
+    RegisterField::bit("PE", 2),
+    RegisterField::bit("FE", 3),
+    RegisterField::bit("BI", 4),
+    RegisterField::bit("THRE", 5),
+    RegisterField::bit("TEMT", 6),
+    RegisterField::bit("RXFE", 7),
+];
+
+/// registers of the 16550A at their index, for the traces of the accesses
+pub const UART16550_REGISTERS: &[Register] = &[
+    Register::new("RBR", RBR_THR_DLL, RegisterAccess::ReadOnly).with_size(1).character(),
+    Register::new("THR", RBR_THR_DLL, RegisterAccess::WriteOnly).with_size(1).character(),
+    Register::new("IER", IER_DLM, RegisterAccess::ReadWrite).with_size(1).with_fields(IER_FIELDS),
+    Register::new("IIR", IIR_FCR, RegisterAccess::ReadOnly).with_size(1).with_fields(IIR_FIELDS),
+    Register::new("FCR", IIR_FCR, RegisterAccess::WriteOnly).with_size(1).with_fields(FCR_FIELDS),
+    Register::new("LCR", LCR, RegisterAccess::ReadWrite).with_size(1).with_fields(LCR_FIELDS),
+    Register::new("MCR", MCR, RegisterAccess::ReadWrite).with_size(1).with_fields(MCR_FIELDS),
+    Register::new("LSR", LSR, RegisterAccess::ReadOnly).with_size(1).with_fields(LSR_FIELDS),
+    Register::new("MSR", MSR, RegisterAccess::ReadOnly).with_size(1),
+    Register::new("SCR", SCR, RegisterAccess::ReadWrite).with_size(1),
+];
+
+/// registers replacing RBR, THR and IER while the divisor latch is accessible
+pub const UART16550_DLAB_REGISTERS: &[Register] = &[
+    Register::new("DLL", RBR_THR_DLL, RegisterAccess::ReadWrite).with_size(1),
+    Register::new("DLM", IER_DLM, RegisterAccess::ReadWrite).with_size(1),
+];
+
+/// 16550A compatible UART, as found in the QEMU virt machine and expected by most OS drivers (ex. xv6, Zephyr, Linux 8250)
+/// transmission to stdout is immediate, a terminal attached with `attach` keeps the transmitter busy until it took the bytes
+pub struct Uart16550 {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    receiver: UartReceiver,
+    transmitter: UartTransmitter,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    divisor: u16,
+    /// THR empty interrupt is cleared by reading IIR and raised again by the next write to THR
+    thr_empty_pending: bool,
+}
+
+impl Uart16550 {
+    /// serial line of the 16550 on a terminal, see `attach_lines`
+    pub fn attach(&mut self, connection: SerialConnection) {
+        attach_lines(&self.receiver, &mut self.transmitter, connection);
+    }
+
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.receiver.interrupt_line()
+    }
+
+    fn register_index(&self, address: Address) -> Option<Address> {
+        let index = address - self.start_address;
+        if index > SCR { None } else { Some(index) }
+    }
+
+    fn thr_empty_interrupt(&self) -> bool {
+        self.ier & IER_THR_EMPTY != 0 && self.thr_empty_pending
+    }
+
+    fn update_interrupt_line(&self) {
+        self.receiver.set_interrupt_enabled(self.ier & IER_RX_DATA != 0);
+        self.receiver.update_interrupt_line(self.thr_empty_interrupt());
+    }
+
+    fn interrupt_identification(&self) -> u8 {
+        let fifo = if self.fcr & FCR_ENABLE_FIFO != 0 { IIR_FIFO_ENABLED } else { 0 };
+        if self.ier & IER_RX_DATA != 0 && !self.receiver.is_empty() {
+            fifo | IIR_RX_DATA
+        } else if self.thr_empty_interrupt() {
+            fifo | IIR_THR_EMPTY
+        } else {
+            fifo | IIR_NO_INTERRUPT
+        }
+    }
+
+    fn line_status(&self) -> u8 {
+        let mut lsr = 0;
+        if self.transmitter.is_empty() {
+            lsr |= LSR_THR_EMPTY | LSR_TX_EMPTY;
+        }
+        if !self.receiver.is_empty() {
+            lsr |= LSR_DATA_READY;
+        }
+        lsr
+    }
+
+    fn dlab(&self) -> bool {
+        self.lcr & LCR_DLAB != 0
+    }
+}
+
+impl MemoryDevice for Uart16550 {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address > start_address);
+        assert!(memory_type == MemoryDeviceType::UART0 || memory_type == MemoryDeviceType::UART1);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            receiver: UartReceiver::new(),
+            transmitter: UartTransmitter::new(),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            divisor: 0,
+            thr_empty_pending: false,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        let Some(index) = self.register_index(request.data_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        };
+
+        if request.request_type == MemoryRequestType::WRITE {
+            assert!(request.data.is_some());
+            let value = request.data.unwrap()[0];
+            match index {
+                RBR_THR_DLL if self.dlab() => self.divisor = (self.divisor & 0xFF00) | value as u16,
+                RBR_THR_DLL => {
+                    self.transmitter.send(value);
+                    self.thr_empty_pending = true;
+                }
+                IER_DLM if self.dlab() => self.divisor = (self.divisor & 0x00FF) | ((value as u16) << 8),
+                IER_DLM => {
+                    self.ier = value & 0x0F;
+                    if self.ier & IER_RX_DATA != 0 {
+                        self.receiver.start();
+                    }
+                    // enabling the THR empty interrupt while the transmitter is empty raises it immediately
+                    self.thr_empty_pending = self.ier & IER_THR_EMPTY != 0;
+                }
+                IIR_FCR => {
+                    if value & FCR_CLEAR_RX != 0 {
+                        self.receiver.clear();
+                    }
+                    self.fcr = value & FCR_ENABLE_FIFO;
+                }
+                LCR => self.lcr = value,
+                MCR => self.mcr = value,
+                SCR => self.scr = value,
+                // LSR and MSR are read only
+                _ => {}
+            }
+            self.update_interrupt_line();
+            MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+        } else {
+            let value = match index {
+                RBR_THR_DLL if !self.dlab() => {
+                    self.receiver.start();
+                    self.receiver.pop().unwrap_or(0)
+                }
+                IIR_FCR => {
+                    let iir = self.interrupt_identification();
+                    if iir & 0x0F == IIR_THR_EMPTY {
+                        self.thr_empty_pending = false;
+                    }
+                    iir
+                }
+                LSR => {
+                    self.receiver.start();
+                    self.line_status()
+                }
+                _ => {
+                    return self.read_request(request);
+                }
+            };
+            self.update_interrupt_line();
+            register_response(value, &request)
+        }
+    }
+
+    /// reads without side effects: the RX FIFO is only peeked and IIR does not acknowledge interrupts
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let Some(index) = self.register_index(request.data_address) else {
+            return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::InvalidAddress };
+        };
+        let value = match index {
+            RBR_THR_DLL if self.dlab() => self.divisor as u8,
+            RBR_THR_DLL => self.receiver.peek().unwrap_or(0),
+            IER_DLM if self.dlab() => (self.divisor >> 8) as u8,
+            IER_DLM => self.ier,
+            IIR_FCR => self.interrupt_identification(),
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => self.line_status(),
+            // report CTS, DSR and DCD as asserted so drivers waiting for a modem see the line as ready
+            MSR => 0xB0,
+            _ => self.scr,
+        };
+        register_response(value, &request)
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn pending_irq(&self) -> bool {
+        self.interrupt_line().load(Ordering::SeqCst)
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nUART16550 {:?}: {{ IER={:X} IIR={:X} LCR={:X} MCR={:X} LSR={:X} SCR={:X} DIV={:X} }}",
+            self.memory_type,
+            self.ier,
+            self.interrupt_identification(),
+            self.lcr,
+            self.mcr,
+            self.line_status(),
+            self.scr,
+            self.divisor
+        );
+        Ok(())
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let index = self.register_index(request.data_address)?;
+        let registers = if self.dlab() && index <= IER_DLM { UART16550_DLAB_REGISTERS } else { UART16550_REGISTERS };
+        register_map::describe_access(registers, index, request.request_type == MemoryRequestType::WRITE, value)
+    }
+}
@@ -0,0 +1,196 @@
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryResponseType};
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord, WordSize, zero_extend_word};
+use crate::risc_soc::vector::{VTYPE_VILL, VectorRegisters};
+use crate::rv32i_baremetal::decode::{FUNC3_VSETVL, FUNCT6_VWXUNARY0, OP_LOAD_FP, OP_STORE_FP, OP_V, OPCODE_MASK};
+
+// FUNCT3 of the arithmetic instructions under OP_V: the source of the second operand and the integer or multiply group
+const FUNC3_OPIVV: u32 = 0b000;
+const FUNC3_OPMVV: u32 = 0b010;
+const FUNC3_OPIVI: u32 = 0b011;
+const FUNC3_OPIVX: u32 = 0b100;
+const FUNC3_OPMVX: u32 = 0b110;
+
+/// FUNCT6 of vmerge and vmv.v, which select their operand with the mask instead of skipping the masked-off elements
+const FUNCT6_VMERGE: u32 = 0b010111;
+
+/// Zve32x instructions: vsetvl, unit-stride loads and stores of 8, 16 and 32 bit elements and the basic integer operations
+/// `rs1` holds the scalar operand or the base address and `rs2` the scalar operand of vsetvl, returns the value of the scalar
+/// destination (if any) and the clock cycles taken by the memory accesses
+/// the instructions the hart does not support raise an illegal instruction exception
+pub fn execute_vector(rv32_core: &RiscCore, instruction: u32, rs1: RiscWord, rs2: RiscWord) -> (RiscWord, u64) {
+    let vector = rv32_core.vector.as_ref().expect("EX only lets vector instructions through with a vector register file");
+    let mut vector = vector.write().unwrap();
+    let func3 = (instruction >> 12) & 0b111;
+    let result = match (instruction & OPCODE_MASK) as u8 {
+        OP_V if func3 == FUNC3_VSETVL as u32 => Some((vsetvl(&mut vector, instruction, rs1, rs2), 0)),
+        OP_V => arithmetic(&mut vector, instruction, rs1).map(|value| (value, 0)),
+        OP_LOAD_FP | OP_STORE_FP => unit_stride(rv32_core, &mut vector, instruction, rs1),
+        _ => None,
+    };
+    result.unwrap_or_else(|| {
+        rv32_core.raise_exception(Exception::IllegalInstruction, 0);
+        (0, 0)
+    })
+}
+
+/// vsetvli, vsetivli and vsetvl, returning the new vl
+fn vsetvl(vector: &mut VectorRegisters, instruction: u32, rs1: RiscWord, rs2: RiscWord) -> RiscWord {
+    let rd = (instruction >> 7) & 0x1F;
+    let rs1_index = (instruction >> 15) & 0x1F;
+    let (vtype, immediate_avl) = match instruction >> 30 {
+        0b11 => (((instruction >> 20) & 0x3FF) as RiscWord, Some(rs1_index as RiscWord)),
+        0b10 => (rs2, None),
+        _ => (((instruction >> 20) & 0x7FF) as RiscWord, None),
+    };
+    // x0 as rs1 requests the longest vector, or keeps vl when rd is x0 as well
+    let avl = match immediate_avl {
+        Some(avl) => Some(avl),
+        None if rs1_index != 0 => Some(rs1),
+        None if rd != 0 => Some(RiscWord::MAX),
+        None => None,
+    };
+    vector.set_vtype(vtype, avl)
+}
+
+/// integer arithmetic on the elements vstart..vl, the masked-off and tail elements are left undisturbed
+fn arithmetic(vector: &mut VectorRegisters, instruction: u32, rs1: RiscWord) -> Option<RiscWord> {
+    let func3 = (instruction >> 12) & 0b111;
+    // the floating point operations are not part of Zve32x
+    if vector.vtype & VTYPE_VILL != 0 || !matches!(func3, FUNC3_OPIVV | FUNC3_OPMVV | FUNC3_OPIVI | FUNC3_OPIVX | FUNC3_OPMVX) {
+        return None;
+    }
+    let funct6 = instruction >> 26;
+    let masked = (instruction >> 25) & 0x1 == 0x0;
+    let vd = ((instruction >> 7) & 0x1F) as usize;
+    let vs1 = ((instruction >> 15) & 0x1F) as usize;
+    let vs2 = ((instruction >> 20) & 0x1F) as usize;
+    let sew = vector.sew();
+    let bits = 8 * sew as u32;
+    let ones = (1u64 << bits) - 1;
+    let signed = |value: u64| ((value << (64 - bits)) as i64) >> (64 - bits);
+
+    // vmv.x.s and vmv.s.x only move the first element, whatever vl and LMUL
+    if funct6 == FUNCT6_VWXUNARY0 as u32 {
+        return match func3 {
+            FUNC3_OPMVV if vs1 == 0 => Some(signed(vector.element(vs2, 0, sew)) as RiscWord),
+            FUNC3_OPMVX if vs2 == 0 => {
+                if vector.vstart < vector.vl {
+                    vector.set_element(vd, 0, sew, zero_extend_word(rs1) & ones);
+                }
+                vector.vstart = 0;
+                Some(0)
+            }
+            _ => None,
+        };
+    }
+
+    let scalar = match func3 {
+        FUNC3_OPIVX | FUNC3_OPMVX => Some(zero_extend_word(rs1) & ones),
+        // 5 bit signed immediate in the vs1 field
+        FUNC3_OPIVI => Some((((vs1 as i64) << 59) >> 59) as u64 & ones),
+        _ => None,
+    };
+    let integer = matches!(func3, FUNC3_OPIVV | FUNC3_OPIVI | FUNC3_OPIVX);
+    let shift = |b: u64| (b & (bits as u64 - 1)) as u32;
+    // `a` is the element of vs2 and `b` the one of vs1 or the scalar operand
+    let operation = |a: u64, b: u64| {
+        let result = match (integer, funct6) {
+            (true, 0b000000) => a.wrapping_add(b),
+            (true, 0b000010) if func3 != FUNC3_OPIVI => a.wrapping_sub(b),
+            (true, 0b000011) if func3 != FUNC3_OPIVV => b.wrapping_sub(a),
+            (true, 0b000100) if func3 != FUNC3_OPIVI => a.min(b),
+            (true, 0b000101) if func3 != FUNC3_OPIVI => if signed(a) <= signed(b) { a } else { b },
+            (true, 0b000110) if func3 != FUNC3_OPIVI => a.max(b),
+            (true, 0b000111) if func3 != FUNC3_OPIVI => if signed(a) >= signed(b) { a } else { b },
+            (true, 0b001001) => a & b,
+            (true, 0b001010) => a | b,
+            (true, 0b001011) => a ^ b,
+            (true, 0b100101) => a << shift(b),
+            (true, 0b101000) => a >> shift(b),
+            (true, 0b101001) => (signed(a) >> shift(b)) as u64,
+            (false, 0b100101) => a.wrapping_mul(b),
+            _ => return None,
+        };
+        Some(result & ones)
+    };
+
+    let merge = integer && funct6 == FUNCT6_VMERGE;
+    // vmv.v has no vs2 operand, vmerge takes the mask
+    let valid = if merge { masked || vs2 == 0 } else { operation(0, 0).is_some() };
+    // register groups start at a multiple of LMUL, and v0 cannot be both the mask and the destination
+    let group = vector.group_registers();
+    let aligned = vd.is_multiple_of(group) && vs2.is_multiple_of(group) && (scalar.is_some() || vs1.is_multiple_of(group));
+    if !valid || !aligned || masked && vd == 0 {
+        return None;
+    }
+    for index in vector.vstart as usize..vector.vl as usize {
+        let active = !masked || vector.mask(index);
+        let b = scalar.unwrap_or_else(|| vector.element(vs1, index, sew));
+        let value = if merge {
+            if active { b } else { vector.element(vs2, index, sew) }
+        } else if active {
+            operation(vector.element(vs2, index, sew), b).unwrap()
+        } else {
+            continue;
+        };
+        vector.set_element(vd, index, sew, value);
+    }
+    vector.vstart = 0;
+    Some(0)
+}
+
+/// vle8/16/32 and vse8/16/32 on the elements vstart..vl, the masked-off elements are not accessed
+/// an access raising an exception stops the instruction, leaving the index of its element in vstart
+fn unit_stride(rv32_core: &RiscCore, vector: &mut VectorRegisters, instruction: u32, address: RiscWord) -> Option<(RiscWord, u64)> {
+    let store = (instruction & OPCODE_MASK) as u8 == OP_STORE_FP;
+    let size = match (instruction >> 12) & 0b111 {
+        0b000 => WordSize::BYTE,
+        0b101 => WordSize::HALF,
+        0b110 => WordSize::WORD,
+        // wider elements than ELEN, or scalar floating point accesses
+        _ => return None,
+    };
+    let masked = (instruction >> 25) & 0x1 == 0x0;
+    let vd = ((instruction >> 7) & 0x1F) as usize;
+    let (start, end) = (vector.vstart as usize, vector.vl as usize);
+    // no segments, strides, indices, whole registers or masks: nf, mew, mop and lumop/sumop are all zero
+    let unit_stride = instruction >> 26 == 0 && (instruction >> 20) & 0x1F == 0;
+    if vector.vtype & VTYPE_VILL != 0 || !unit_stride || !vector.fits(vd, end, size as usize) || masked && vd == 0 && !store {
+        return None;
+    }
+
+    let base = address as Address;
+    let mut latency = if store {
+        rv32_core.drain_store_buffer(None)
+    } else {
+        rv32_core.drain_overlapping_stores(base + (start * size as usize) as Address, end.saturating_sub(start) * size as usize)
+    };
+    for index in start..end {
+        if masked && !vector.mask(index) {
+            continue;
+        }
+        let element_address = base.wrapping_add((index * size as usize) as Address);
+        let request = if store {
+            MemoryRequest::write(element_address, &vector.element(vd, index, size as usize).to_le_bytes()[..size as usize])
+        } else {
+            MemoryRequest::read(element_address, size)
+        };
+        latency += rv32_core.data_access_latency(&request);
+        let response = rv32_core.data_request(request);
+        if response.status != MemoryResponseType::CacheHit && response.status != MemoryResponseType::Valid {
+            vector.vstart = index as RiscWord;
+            return Some((0, latency));
+        }
+        if !store {
+            let value = match size {
+                WordSize::BYTE => response.as_u8() as u64,
+                WordSize::HALF => response.as_u16() as u64,
+                _ => response.as_u32() as u64,
+            };
+            vector.set_element(vd, index, size as usize, value);
+        }
+    }
+    vector.vstart = 0;
+    Some((0, latency))
+}
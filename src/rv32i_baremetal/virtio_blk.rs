@@ -0,0 +1,396 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, BusMaster, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest,
+    MemoryRequestType, MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::risc_soc::WordSize;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// virtio-mmio (version 2) register offsets
+pub const VIRTIO_MMIO_MAGIC_VALUE: Address = 0x000;
+pub const VIRTIO_MMIO_VERSION: Address = 0x004;
+pub const VIRTIO_MMIO_DEVICE_ID: Address = 0x008;
+pub const VIRTIO_MMIO_VENDOR_ID: Address = 0x00C;
+pub const VIRTIO_MMIO_DEVICE_FEATURES: Address = 0x010;
+pub const VIRTIO_MMIO_DEVICE_FEATURES_SEL: Address = 0x014;
+pub const VIRTIO_MMIO_DRIVER_FEATURES: Address = 0x020;
+pub const VIRTIO_MMIO_DRIVER_FEATURES_SEL: Address = 0x024;
+pub const VIRTIO_MMIO_QUEUE_SEL: Address = 0x030;
+pub const VIRTIO_MMIO_QUEUE_NUM_MAX: Address = 0x034;
+pub const VIRTIO_MMIO_QUEUE_NUM: Address = 0x038;
+pub const VIRTIO_MMIO_QUEUE_READY: Address = 0x044;
+pub const VIRTIO_MMIO_QUEUE_NOTIFY: Address = 0x050;
+pub const VIRTIO_MMIO_INTERRUPT_STATUS: Address = 0x060;
+pub const VIRTIO_MMIO_INTERRUPT_ACK: Address = 0x064;
+pub const VIRTIO_MMIO_STATUS: Address = 0x070;
+pub const VIRTIO_MMIO_QUEUE_DESC_LOW: Address = 0x080;
+pub const VIRTIO_MMIO_QUEUE_DESC_HIGH: Address = 0x084;
+pub const VIRTIO_MMIO_QUEUE_DRIVER_LOW: Address = 0x090;
+pub const VIRTIO_MMIO_QUEUE_DRIVER_HIGH: Address = 0x094;
+pub const VIRTIO_MMIO_QUEUE_DEVICE_LOW: Address = 0x0A0;
+pub const VIRTIO_MMIO_QUEUE_DEVICE_HIGH: Address = 0x0A4;
+pub const VIRTIO_MMIO_CONFIG_GENERATION: Address = 0x0FC;
+/// device specific configuration, for a block device it starts with the capacity in sectors
+pub const VIRTIO_MMIO_CONFIG: Address = 0x100;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976; // "virt"
+const VIRTIO_VENDOR: u32 = 0x554D_4551; // "QEMU"
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+/// bit 32 of the feature set, required for non legacy devices
+const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+pub const VIRTQ_DESC_F_NEXT: u16 = 1;
+pub const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+pub const VIRTIO_BLK_T_IN: u32 = 0;
+pub const VIRTIO_BLK_T_OUT: u32 = 1;
+pub const VIRTIO_BLK_T_FLUSH: u32 = 4;
+pub const VIRTIO_BLK_S_OK: u8 = 0;
+pub const VIRTIO_BLK_S_IOERR: u8 = 1;
+pub const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+pub const VIRTIO_BLK_SECTOR_SIZE: u64 = 512;
+pub const VIRTIO_QUEUE_SIZE: u32 = 16;
+
+/// interrupt status bit for a used buffer notification
+const VIRTIO_INTERRUPT_USED_RING: u32 = 1;
+
+/// split virtqueue as configured by the driver
+#[derive(Debug, Default, Clone, Copy)]
+struct VirtQueue {
+    num: u32,
+    ready: bool,
+    desc: u64,
+    driver: u64,
+    device: u64,
+    /// next entry of the available ring that the device did not consume yet
+    last_avail_idx: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VirtqDesc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// virtio-mmio block device whose storage is a disk image file on the host
+/// the device reads descriptors and buffers directly from guest memory through its bus master port,
+/// so `set_bus_master` must be called before the guest starts using it
+pub struct VirtioBlk {
+    memory_type: MemoryDeviceType,
+    start_address: Address,
+    end_address: Address,
+    disk: Option<File>,
+    capacity: u64,
+    read_only: bool,
+    bus: Option<BusMaster>,
+    status: u32,
+    device_features_sel: u32,
+    driver_features: u64,
+    driver_features_sel: u32,
+    queue: VirtQueue,
+    interrupt_status: u32,
+    interrupt_line: Arc<AtomicBool>,
+}
+
+impl VirtioBlk {
+    /// open a host disk image as the backing storage, falling back to read only if it cannot be written
+    pub fn attach_disk(&mut self, path: &str) -> std::io::Result<()> {
+        let (file, read_only) = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => (file, false),
+            Err(_) => (File::open(path)?, true),
+        };
+        self.capacity = file.metadata()?.len() / VIRTIO_BLK_SECTOR_SIZE;
+        self.read_only = read_only;
+        self.disk = Some(file);
+        Ok(())
+    }
+
+    pub fn set_bus_master(&mut self, bus: BusMaster) {
+        self.bus = Some(bus);
+    }
+
+    pub fn interrupt_line(&self) -> Arc<AtomicBool> {
+        self.interrupt_line.clone()
+    }
+
+    fn device_features(&self) -> u64 {
+        const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+        let mut features = VIRTIO_F_VERSION_1;
+        if self.read_only {
+            features |= VIRTIO_BLK_F_RO;
+        }
+        features
+    }
+
+    fn reset(&mut self) {
+        self.status = 0;
+        self.driver_features = 0;
+        self.queue = VirtQueue::default();
+        self.interrupt_status = 0;
+        self.interrupt_line.store(false, Ordering::SeqCst);
+    }
+
+    fn bus_read(&self, address: u64, len: usize) -> Vec<u8> {
+        let bus = self.bus.as_ref().expect("virtio device has no bus master connected!");
+        let mut data = Vec::with_capacity(len);
+        for offset in 0..len as u64 {
+            let response = bus(MemoryRequest {
+                request_type: MemoryRequestType::READ,
+                data_address: address + offset,
+                data_size: WordSize::BYTE,
+                data: None,
+            });
+            data.push(response.data.first().copied().unwrap_or(0));
+        }
+        data
+    }
+
+    fn bus_write(&self, address: u64, data: &[u8]) {
+        let bus = self.bus.as_ref().expect("virtio device has no bus master connected!");
+        for (offset, byte) in data.iter().enumerate() {
+            bus(MemoryRequest {
+                request_type: MemoryRequestType::WRITE,
+                data_address: address + offset as u64,
+                data_size: WordSize::BYTE,
+                data: Some(MemoryData::new(&[*byte])),
+            });
+        }
+    }
+
+    fn bus_read_u16(&self, address: u64) -> u16 {
+        let bytes = self.bus_read(address, 2);
+        u16::from_le_bytes([bytes[0], bytes[1]])
+    }
+
+    fn read_desc(&self, index: u16) -> VirtqDesc {
+        let bytes = self.bus_read(self.queue.desc + 16 * index as u64, 16);
+        VirtqDesc {
+            addr: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            len: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            flags: u16::from_le_bytes([bytes[12], bytes[13]]),
+            next: u16::from_le_bytes([bytes[14], bytes[15]]),
+        }
+    }
+
+    /// consume all new entries of the available ring and complete them in the used ring
+    fn process_queue(&mut self) {
+        if !self.queue.ready || self.queue.num == 0 {
+            return;
+        }
+        let avail_idx = self.bus_read_u16(self.queue.driver + 2);
+        while self.queue.last_avail_idx != avail_idx {
+            let ring_slot = self.queue.last_avail_idx as u64 % self.queue.num as u64;
+            let head = self.bus_read_u16(self.queue.driver + 4 + 2 * ring_slot);
+            let written = self.process_request(head);
+
+            // append the completed chain to the used ring, then publish it by bumping the index
+            let used_idx = self.bus_read_u16(self.queue.device + 2);
+            let used_slot = used_idx as u64 % self.queue.num as u64;
+            let mut element = (head as u32).to_le_bytes().to_vec();
+            element.extend_from_slice(&written.to_le_bytes());
+            self.bus_write(self.queue.device + 4 + 8 * used_slot, &element);
+            self.bus_write(self.queue.device + 2, &used_idx.wrapping_add(1).to_le_bytes());
+
+            self.queue.last_avail_idx = self.queue.last_avail_idx.wrapping_add(1);
+        }
+        self.interrupt_status |= VIRTIO_INTERRUPT_USED_RING;
+        self.interrupt_line.store(true, Ordering::SeqCst);
+    }
+
+    /// a block request is a header descriptor, the data descriptors and a final one byte status descriptor
+    /// returns the number of bytes written into guest memory
+    fn process_request(&mut self, head: u16) -> u32 {
+        let mut chain = vec![self.read_desc(head)];
+        while chain.last().unwrap().flags & VIRTQ_DESC_F_NEXT != 0 && chain.len() <= self.queue.num as usize {
+            let next = chain.last().unwrap().next;
+            chain.push(self.read_desc(next));
+        }
+        if chain.len() < 2 {
+            tracing::warn!("Malformed virtio-blk request with a single descriptor");
+            return 0;
+        }
+
+        let header = self.bus_read(chain[0].addr, 16);
+        let request_type = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let sector = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        let status_desc = *chain.last().unwrap();
+        let data_descs = &chain[1..chain.len() - 1];
+
+        let mut written = 0u32;
+        let mut offset = sector * VIRTIO_BLK_SECTOR_SIZE;
+        let mut status = VIRTIO_BLK_S_OK;
+        match request_type {
+            VIRTIO_BLK_T_IN => {
+                for desc in data_descs {
+                    let mut buffer = vec![0u8; desc.len as usize];
+                    if self.disk_io(offset, |disk| disk.read_exact(&mut buffer)).is_err() {
+                        status = VIRTIO_BLK_S_IOERR;
+                        break;
+                    }
+                    self.bus_write(desc.addr, &buffer);
+                    offset += desc.len as u64;
+                    written += desc.len;
+                }
+            }
+            VIRTIO_BLK_T_OUT if self.read_only => status = VIRTIO_BLK_S_IOERR,
+            VIRTIO_BLK_T_OUT => {
+                for desc in data_descs {
+                    let buffer = self.bus_read(desc.addr, desc.len as usize);
+                    if self.disk_io(offset, |disk| disk.write_all(&buffer)).is_err() {
+                        status = VIRTIO_BLK_S_IOERR;
+                        break;
+                    }
+                    offset += desc.len as u64;
+                }
+            }
+            VIRTIO_BLK_T_FLUSH => {
+                if self.disk.as_mut().map(|disk| disk.sync_data()).transpose().is_err() {
+                    status = VIRTIO_BLK_S_IOERR;
+                }
+            }
+            _ => status = VIRTIO_BLK_S_UNSUPP,
+        }
+
+        self.bus_write(status_desc.addr, &[status]);
+        written + 1
+    }
+
+    fn disk_io(
+        &mut self,
+        offset: u64,
+        operation: impl FnOnce(&mut File) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        if offset >= self.capacity * VIRTIO_BLK_SECTOR_SIZE {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let disk = self.disk.as_mut().ok_or(std::io::ErrorKind::NotFound)?;
+        disk.seek(SeekFrom::Start(offset))?;
+        operation(disk)
+    }
+
+    fn register(&self, offset: Address) -> u32 {
+        match offset {
+            VIRTIO_MMIO_MAGIC_VALUE => VIRTIO_MAGIC,
+            VIRTIO_MMIO_VERSION => 2,
+            VIRTIO_MMIO_DEVICE_ID => VIRTIO_DEVICE_BLOCK,
+            VIRTIO_MMIO_VENDOR_ID => VIRTIO_VENDOR,
+            VIRTIO_MMIO_DEVICE_FEATURES => {
+                (self.device_features() >> (32 * self.device_features_sel.min(1))) as u32
+            }
+            VIRTIO_MMIO_QUEUE_NUM_MAX => VIRTIO_QUEUE_SIZE,
+            VIRTIO_MMIO_QUEUE_NUM => self.queue.num,
+            VIRTIO_MMIO_QUEUE_READY => self.queue.ready as u32,
+            VIRTIO_MMIO_INTERRUPT_STATUS => self.interrupt_status,
+            VIRTIO_MMIO_STATUS => self.status,
+            VIRTIO_MMIO_CONFIG_GENERATION => 0,
+            VIRTIO_MMIO_CONFIG => self.capacity as u32,
+            0x104 => (self.capacity >> 32) as u32,
+            _ => 0,
+        }
+    }
+
+    fn set_register(&mut self, offset: Address, value: u32) {
+        let low = |reg: u64| (reg & 0xFFFF_FFFF_0000_0000) | value as u64;
+        let high = |reg: u64| (reg & 0xFFFF_FFFF) | ((value as u64) << 32);
+        match offset {
+            VIRTIO_MMIO_DEVICE_FEATURES_SEL => self.device_features_sel = value,
+            VIRTIO_MMIO_DRIVER_FEATURES => {
+                let shift = 32 * self.driver_features_sel.min(1);
+                self.driver_features &= !(0xFFFF_FFFFu64 << shift);
+                self.driver_features |= (value as u64) << shift;
+            }
+            VIRTIO_MMIO_DRIVER_FEATURES_SEL => self.driver_features_sel = value,
+            // a single request queue is provided
+            VIRTIO_MMIO_QUEUE_SEL => {}
+            VIRTIO_MMIO_QUEUE_NUM => self.queue.num = value.min(VIRTIO_QUEUE_SIZE),
+            VIRTIO_MMIO_QUEUE_READY => self.queue.ready = value == 1,
+            VIRTIO_MMIO_QUEUE_NOTIFY => self.process_queue(),
+            VIRTIO_MMIO_INTERRUPT_ACK => {
+                self.interrupt_status &= !value;
+                self.interrupt_line.store(self.interrupt_status != 0, Ordering::SeqCst);
+            }
+            VIRTIO_MMIO_STATUS if value == 0 => self.reset(),
+            VIRTIO_MMIO_STATUS => self.status = value,
+            VIRTIO_MMIO_QUEUE_DESC_LOW => self.queue.desc = low(self.queue.desc),
+            VIRTIO_MMIO_QUEUE_DESC_HIGH => self.queue.desc = high(self.queue.desc),
+            VIRTIO_MMIO_QUEUE_DRIVER_LOW => self.queue.driver = low(self.queue.driver),
+            VIRTIO_MMIO_QUEUE_DRIVER_HIGH => self.queue.driver = high(self.queue.driver),
+            VIRTIO_MMIO_QUEUE_DEVICE_LOW => self.queue.device = low(self.queue.device),
+            VIRTIO_MMIO_QUEUE_DEVICE_HIGH => self.queue.device = high(self.queue.device),
+            _ => tracing::warn!("Write to read only virtio register {:X}", offset),
+        }
+    }
+}
+
+impl MemoryDevice for VirtioBlk {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address - start_address >= 0x200);
+        assert!(memory_type == MemoryDeviceType::VIRTIO0);
+        Self {
+            memory_type,
+            start_address,
+            end_address,
+            disk: None,
+            capacity: 0,
+            read_only: false,
+            bus: None,
+            status: 0,
+            device_features_sel: 0,
+            driver_features: 0,
+            driver_features_sel: 0,
+            queue: VirtQueue::default(),
+            interrupt_status: 0,
+            interrupt_line: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        self.set_register(request.data_address - self.start_address, value);
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let value = self.register(request.data_address - self.start_address);
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        self.memory_type
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn pending_irq(&self) -> bool {
+        self.interrupt_line.load(Ordering::SeqCst)
+    }
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nVirtio-blk {:?}: {{ capacity={} sectors status={:X} queue={:?} interrupt={:X} }}",
+            self.memory_type, self.capacity, self.status, self.queue, self.interrupt_status
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use crate::risc_soc::memory_management_unit::{
+    Address, MemoryData, MemoryDevice, MemoryDeviceType, MemoryRequest, MemoryRequestType,
+    MemoryResponse, MemoryResponseType,
+};
+use crate::risc_soc::register_map::{self, Register, RegisterAccess, RegisterField};
+use crate::risc_soc::risc_soc::{ExitSignal, ExitStatus};
+
+/// register offsets of the watchdog
+/// edges of the watchdog clock between two kicks
+pub const WDT_LOAD: Address = 0x00;
+/// edges left before the watchdog expires
+pub const WDT_VALUE: Address = 0x04;
+pub const WDT_CONTROL: Address = 0x08;
+/// writing the kick key reloads the counter, other values are ignored
+pub const WDT_KICK: Address = 0x0C;
+/// write 1 to clear the TIMEOUT and RESET_CAUSE bits
+pub const WDT_STATUS: Address = 0x10;
+
+pub const WDT_KICK_KEY: u32 = 0x1ACC_E551;
+
+/// control register bits
+pub const WDT_CONTROL_ENABLE: u32 = 1 << 0;
+pub const WDT_CONTROL_IRQ_ENABLE: u32 = 1 << 1;
+pub const WDT_CONTROL_RESET_ENABLE: u32 = 1 << 2;
+
+/// status register bits
+/// the counter expired and the interrupt was raised
+pub const WDT_STATUS_TIMEOUT: u32 = 1 << 0;
+/// the last reset of the SoC was requested by the watchdog, kept across warm resets
+pub const WDT_STATUS_RESET_CAUSE: u32 = 1 << 1;
+
+const CONTROL_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("EN", 0),
+    RegisterField::bit("IE", 1),
+    RegisterField::bit("RE", 2),
+];
+
+const STATUS_FIELDS: &[RegisterField] = &[
+    RegisterField::bit("TIMEOUT", 0),
+    RegisterField::bit("RESET_CAUSE", 1),
+];
+
+/// registers of the watchdog, for the traces of the accesses
+pub const WDT_REGISTERS: &[Register] = &[
+    Register::new("LOAD", WDT_LOAD, RegisterAccess::ReadWrite),
+    Register::new("VALUE", WDT_VALUE, RegisterAccess::ReadOnly),
+    Register::new("CONTROL", WDT_CONTROL, RegisterAccess::ReadWrite).with_fields(CONTROL_FIELDS),
+    Register::new("KICK", WDT_KICK, RegisterAccess::WriteOnly),
+    Register::new("STATUS", WDT_STATUS, RegisterAccess::ReadWrite).with_fields(STATUS_FIELDS),
+];
+
+/// watchdog timer counting down the edges of its clock while enabled, the firmware must kick it before it reaches zero
+/// on expiry the interrupt is raised first if enabled and the counter reloaded, like the ARM SP805: if it expires again
+/// with the interrupt still pending, or with the interrupt disabled, a reset of the SoC is requested through the exit signal
+/// of the cores when the reset is enabled, the run stops with `ExitStatus::Reset` and `RiscCore::reboot` restarts the program
+pub struct Watchdog {
+    start_address: Address,
+    end_address: Address,
+    exit_signal: ExitSignal,
+    load: u32,
+    value: u32,
+    control: u32,
+    status: u32,
+}
+
+impl Watchdog {
+    pub fn with_exit_signal(mut self, exit_signal: ExitSignal) -> Self {
+        self.exit_signal = exit_signal;
+        self
+    }
+
+    fn enabled(&self) -> bool {
+        self.control & WDT_CONTROL_ENABLE != 0
+    }
+
+    fn expire(&mut self) {
+        let irq_enabled = self.control & WDT_CONTROL_IRQ_ENABLE != 0;
+        if irq_enabled && self.status & WDT_STATUS_TIMEOUT == 0 {
+            self.status |= WDT_STATUS_TIMEOUT;
+            self.value = self.load;
+            return;
+        }
+        if self.control & WDT_CONTROL_RESET_ENABLE != 0 {
+            tracing::warn!("Watchdog expired, requesting a reset of the SoC");
+            self.status |= WDT_STATUS_RESET_CAUSE;
+            self.exit_signal.exit(ExitStatus::Reset);
+        }
+        // the watchdog stops at zero until the firmware enables it again, the reset is requested once
+        self.control &= !WDT_CONTROL_ENABLE;
+    }
+}
+
+impl MemoryDevice for Watchdog {
+    fn new(memory_type: MemoryDeviceType, start_address: Address, end_address: Address) -> Self {
+        assert!(end_address - start_address >= 0x14);
+        assert!(memory_type == MemoryDeviceType::WATCHDOG);
+        Self {
+            start_address,
+            end_address,
+            exit_signal: ExitSignal::default(),
+            load: u32::MAX,
+            value: u32::MAX,
+            control: 0,
+            status: 0,
+        }
+    }
+
+    fn send_data_request(&mut self, request: MemoryRequest) -> MemoryResponse {
+        if request.request_type == MemoryRequestType::READ {
+            return self.read_request(request);
+        }
+        assert!(request.data.is_some());
+        let mut bytes = request.data.unwrap();
+        bytes.resize(4, 0);
+        let value = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        match request.data_address - self.start_address {
+            WDT_LOAD => {
+                self.load = value;
+                self.value = value;
+            }
+            WDT_CONTROL => {
+                // enabling the watchdog starts a new period
+                if !self.enabled() && value & WDT_CONTROL_ENABLE != 0 {
+                    self.value = self.load;
+                }
+                self.control = value;
+            }
+            WDT_KICK if value == WDT_KICK_KEY => {
+                self.value = self.load;
+                self.status &= !WDT_STATUS_TIMEOUT;
+            }
+            WDT_KICK => tracing::warn!("Watchdog kicked with a wrong key {:X}", value),
+            WDT_STATUS => self.status &= !(value & (WDT_STATUS_TIMEOUT | WDT_STATUS_RESET_CAUSE)),
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotWrittable };
+            }
+        }
+        MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::Valid }
+    }
+
+    fn read_request(&self, request: MemoryRequest) -> MemoryResponse {
+        let value = match request.data_address - self.start_address {
+            WDT_LOAD => self.load,
+            WDT_VALUE => self.value,
+            WDT_CONTROL => self.control,
+            WDT_KICK => 0,
+            WDT_STATUS => self.status,
+            _ => {
+                return MemoryResponse { data: MemoryData::default(), status: MemoryResponseType::NotReadable };
+            }
+        };
+        let mut data = MemoryData::new(&value.to_le_bytes());
+        data.truncate(request.data_size as usize);
+        MemoryResponse { data, status: MemoryResponseType::Valid }
+    }
+
+    fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+
+    fn start_end_addresses(&self) -> (Address, Address) {
+        (self.start_address, self.end_address)
+    }
+
+    fn get_memory_type(&self) -> MemoryDeviceType {
+        MemoryDeviceType::WATCHDOG
+    }
+
+    fn init_mem(&mut self, _address: Address, _data: &[u8]) {}
+
+    fn debug(&self, _start_address: Address, _end_address: Address) -> std::fmt::Result {
+        println!(
+            "\nWatchdog: {{ LOAD={:X} VALUE={:X} CONTROL={:X} STATUS={:X} }}",
+            self.load, self.value, self.control, self.status
+        );
+        Ok(())
+    }
+
+    fn tick(&mut self, edges: u64) {
+        let mut edges = edges;
+        while self.enabled() && edges > 0 {
+            let elapsed = edges.min(self.value as u64);
+            self.value -= elapsed as u32;
+            edges -= elapsed;
+            if self.value == 0 {
+                self.expire();
+                // with a reload of zero the counter expires on every edge
+                if elapsed == 0 {
+                    edges -= 1;
+                }
+            }
+        }
+    }
+
+    fn pending_irq(&self) -> bool {
+        self.control & WDT_CONTROL_IRQ_ENABLE != 0 && self.status & WDT_STATUS_TIMEOUT != 0
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.enabled().then_some((self.value as u64).max(1))
+    }
+
+    /// a warm reset keeps the reset cause so the firmware can tell why it restarted
+    fn reset(&mut self, clear_memory: bool) {
+        self.load = u32::MAX;
+        self.value = u32::MAX;
+        self.control = 0;
+        self.status &= if clear_memory { 0 } else { WDT_STATUS_RESET_CAUSE };
+    }
+
+    fn describe_access(&self, request: &MemoryRequest, value: u64) -> Option<String> {
+        let write = request.request_type == MemoryRequestType::WRITE;
+        register_map::describe_access(WDT_REGISTERS, request.data_address - self.start_address, write, value)
+    }
+}
@@ -1,16 +1,36 @@
-use crate::risc_soc::risc_soc::{RiscCore};
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::risc_soc::{RiscCore, XLEN_BYTES};
 use crate::risc_soc::{pipeline_stage::PipelineData};
 use crate::rv32i_baremetal::core::{ID_STAGE, EX_STAGE, WB_STAGE};
 
+/// register receiving the return value of environment and semihosting calls
+const REG_A0: u8 = 10;
+
 pub fn rv32_mcu_commit_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
-    let reg_write = pipeline_reg.get_u8(0x0);
+    let mut reg_write = pipeline_reg.get_u8(0x0);
     let reg_src = pipeline_reg.get_u8(0x1);
-    let rd_address = pipeline_reg.get_u8(0x2);
-    let alu_out = pipeline_reg.get_u32(0x3);
-    let mem_out = pipeline_reg.get_u32(0x7);
+    let mut rd_address = pipeline_reg.get_u8(0x2);
+    let alu_out = pipeline_reg.get_word(0x3);
+    let mem_out = pipeline_reg.get_word(0x3 + XLEN_BYTES);
+    let pc = pipeline_reg.get_word(0x3 + 2 * XLEN_BYTES);
+    let instruction = pipeline_reg.get_u32(0x3 + 3 * XLEN_BYTES);
 
     let rd_value;
-    if reg_src == 0x1 {
+    if reg_src == 0x2 || reg_src == 0x3 {
+        // environment call or breakpoint, the result (if any) is written to a0
+        let result = if reg_src == 0x2 { rv32_core.environment_call(pc) } else { rv32_core.breakpoint(alu_out) };
+        match result {
+            Some(value) => {
+                reg_write = 0x1;
+                rd_address = REG_A0;
+                rd_value = value;
+            }
+            None => {
+                reg_write = 0x0;
+                rd_value = 0x0;
+            }
+        }
+    } else if reg_src == 0x1 {
         rd_value = mem_out;
     } else {
         rd_value = alu_out;
@@ -25,5 +45,17 @@ pub fn rv32_mcu_commit_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore)
     rv32_core.cdb.assign(WB_STAGE, ID_STAGE, wb_data.clone());
     rv32_core.cdb.assign(WB_STAGE, EX_STAGE, wb_data.clone());
 
+    // bubbles carry no instruction, a zero word is not a valid encoding
+    // an environment call or breakpoint that trapped did not complete, younger instructions never reach this stage after a trap
+    // neither did a breakpoint entering Debug Mode, which is executed again once the hart resumes
+    let trapped = (reg_src == 0x2 || reg_src == 0x3)
+        && rv32_core
+            .pending_trap()
+            .is_some_and(|trap| matches!(trap.exception, Exception::EnvironmentCallFromMMode | Exception::Breakpoint))
+        || reg_src == 0x3 && rv32_core.in_debug_mode();
+    if instruction != 0x0 && !trapped {
+        rv32_core.retire_instruction(pc, instruction);
+    }
+
     PipelineData(vec![])
 }
@@ -0,0 +1,72 @@
+use crate::risc_soc::exception::Exception;
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest};
+use crate::risc_soc::pipeline_stage::PipelineData;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::rv32i_ooo::core::{CM_STAGE, EX_STAGE, IF_STAGE, IS_STAGE, tomasulo};
+use crate::rv32i_ooo::tomasulo::OpKind;
+
+pub fn rv32_ooo_commit_stage(_pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
+    let mut state = tomasulo(rv32_core);
+    let mut redirect: Option<RiscWord> = None;
+    let mut retired = 0u8;
+    let mut retired_instructions = vec![];
+
+    // retire the oldest instructions once their results are known, so the architectural state is updated in program order
+    // up to one instruction per issue slot, stopping after a redirect or an exception since the younger ones are discarded
+    while (retired as usize) < rv32_core.issue_width && redirect.is_none() && rv32_core.pending_trap().is_none() {
+        let Some((tag, entry)) = state.rob.head().filter(|(_, entry)| entry.ready) else {
+            break;
+        };
+        state.rob.pop();
+        match entry.op.kind {
+            OpKind::Store => {
+                let size = match entry.op.func3 {
+                    0x0 => 1,
+                    0x1 => 2,
+                    _ => 4,
+                };
+                rv32_core.data_access_pc.store(entry.pc as Address, std::sync::atomic::Ordering::SeqCst);
+                rv32_core.data_request(MemoryRequest::write(entry.address, &entry.value.to_le_bytes()[..size]));
+            }
+            OpKind::FenceI => {
+                // younger instructions may have been fetched before the stores made visible by the fence
+                rv32_core.fence_i();
+                redirect = Some(entry.pc.wrapping_add(4));
+            }
+            OpKind::Illegal => rv32_core.raise_exception_at(Exception::IllegalInstruction, 0, entry.pc as Address),
+            _ => {}
+        }
+        if entry.op.kind != OpKind::Illegal {
+            retired_instructions.push((entry.pc, entry.instruction));
+        }
+        if entry.op.writes_rd() {
+            rv32_core.write_reg(entry.op.rd, entry.value);
+            // younger writers of the same register keep their mapping
+            if state.rat[entry.op.rd] == Some(tag) {
+                state.rat[entry.op.rd] = None;
+            }
+        }
+        if entry.mispredicted() {
+            redirect = Some(entry.next_pc);
+        }
+        state.retired += 1;
+        retired += 1;
+    }
+    if redirect.is_some() {
+        state.flush();
+    }
+    drop(state);
+    for (pc, instruction) in retired_instructions {
+        rv32_core.retire_instruction(pc, instruction);
+    }
+
+    // send the redirect to every earlier stage, this also orders them after commit within the cycle
+    let mut redirect_data = vec![redirect.is_some() as u8];
+    redirect_data.extend_from_slice(&redirect.unwrap_or(0).to_le_bytes());
+    let redirect_data = PipelineData(redirect_data);
+    rv32_core.cdb.assign(CM_STAGE, EX_STAGE, redirect_data.clone());
+    rv32_core.cdb.assign(CM_STAGE, IS_STAGE, redirect_data.clone());
+    rv32_core.cdb.assign(CM_STAGE, IF_STAGE, redirect_data);
+
+    PipelineData(vec![retired])
+}
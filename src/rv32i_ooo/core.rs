@@ -0,0 +1,180 @@
+use crossbeam_channel::bounded;
+use std::sync::{Mutex, MutexGuard};
+use crate::{risc_soc::{csr::MachineInfo, load_error::LoadError, memory_management_unit::{Address, MemoryDevice, MemoryDeviceType}, pipeline_stage::{FETCH_SLOT_SIZE, PipelineStage, PipelineStageInterface}, risc_soc::{RiscCore, XLEN, XLEN_BYTES}}, rv32i_baremetal::{core::add_platform_devices, mcu_cache::MCUCache}, rv32i_ooo::{commit, execute, fetch, issue, tomasulo::TomasuloState}};
+use crate::risc_soc::cache::Cache;
+
+pub const IF_STAGE: usize = 0x0;
+pub const IS_STAGE: usize = 0x1;
+pub const EX_STAGE: usize = 0x2;
+pub const CM_STAGE: usize = 0x3;
+
+/// the tags of the ROB entries are broadcast as a single byte on the CDB
+pub const ROB_SIZE: usize = 16;
+pub const NUM_STATIONS: usize = 8;
+
+/// out-of-order core with the same memories and platform devices as the in-order MCU
+/// IF fetches sequentially, IS renames and dispatches to the reservation stations, EX starts the ready instructions
+/// and broadcasts their results, CM retires them in program order from the reorder buffer
+/// the speculative state is shared by the stages as the microarchitecture state of the core, the CDB wires from commit
+/// to the earlier stages and from execute to issue order their accesses within a clock cycle
+pub fn init_core(clock_period: Option<u128>) -> RiscCore {
+    init_superscalar_core(clock_period, 1)
+}
+
+/// same core fetching, issuing and retiring up to `issue_width` instructions per cycle, with one ALU per issue slot
+/// an instruction is not issued in the same cycle as an older instruction of its fetch packet producing one of its operands
+pub fn init_superscalar_core(clock_period: Option<u128>, issue_width: usize) -> RiscCore {
+    let mut rv32i_core = RiscCore::new(4, clock_period, false);
+    rv32i_core.set_issue_width(issue_width);
+    let start_address = 0x8000_0000;
+    let icache = MCUCache::new_with_lines(MemoryDeviceType::L1ICACHE, 64, 1024, start_address);
+    let dcache = MCUCache::new_with_lines(MemoryDeviceType::L1DCACHE, 64, 1024, start_address + icache.size() as Address);
+    rv32i_core.add_l1_cache(Box::new(icache), Box::new(dcache));
+
+    let (if_is_sender, if_is_receiver) = bounded(1);
+    let (is_ex_sender, is_ex_receiver) = bounded(1);
+    let (ex_cm_sender, ex_cm_receiver) = bounded(1);
+    // only the fetch packet travels through pipeline registers, everything else lives in the ROB and the stations
+    let if_is_size = issue_width * FETCH_SLOT_SIZE;
+    let is_ex_size = 1;
+    let ex_cm_size = 1;
+    let if_stage = PipelineStage::new("IF".to_string(), IF_STAGE, 0usize, if_is_size, fetch::rv32_ooo_fetch_stage, None, Some(if_is_sender));
+    let is_stage = PipelineStage::new("IS".to_string(), IS_STAGE, if_is_size, is_ex_size, issue::rv32_ooo_issue_stage, Some(if_is_receiver), Some(is_ex_sender));
+    let ex_stage = PipelineStage::new("EX".to_string(), EX_STAGE, is_ex_size, ex_cm_size, execute::rv32_ooo_execute_stage, Some(is_ex_receiver), Some(ex_cm_sender));
+    let cm_stage = PipelineStage::new("CM".to_string(), CM_STAGE, ex_cm_size, 0usize, commit::rv32_ooo_commit_stage, Some(ex_cm_receiver), None);
+    rv32i_core.add_stage(if_stage);
+    rv32i_core.add_stage(is_stage);
+    rv32i_core.add_stage(ex_stage);
+    rv32i_core.add_stage(cm_stage);
+
+    // commit redirects every earlier stage, execute broadcasts its results to the stations and issue replays the front end
+    let redirect_size = 8 * (1 + XLEN_BYTES);
+    rv32i_core.cdb.declare(CM_STAGE, EX_STAGE, "commit_redirect_to_ex", redirect_size);
+    rv32i_core.cdb.declare(CM_STAGE, IS_STAGE, "commit_redirect_to_is", redirect_size);
+    rv32i_core.cdb.declare(CM_STAGE, IF_STAGE, "commit_redirect_to_if", redirect_size);
+    rv32i_core.cdb.declare(EX_STAGE, IS_STAGE, "result_broadcast", 8 * broadcast_size(issue_width));
+    rv32i_core.cdb.declare(IS_STAGE, IF_STAGE, "issue_replay", redirect_size);
+    rv32i_core.set_microarchitecture(Mutex::new(TomasuloState::new(ROB_SIZE, NUM_STATIONS)));
+    rv32i_core.add_reset_hook(|core| *tomasulo(core) = TomasuloState::new(ROB_SIZE, NUM_STATIONS));
+    rv32i_core.set_machine_info(MachineInfo::new(0, "I"));
+    {
+        let mut mmu = rv32i_core.mmu.write().unwrap();
+        add_platform_devices(&mut mmu, rv32i_core.exit_signal.clone(), &[rv32i_core.interrupt_lines()]);
+    }
+    tracing::info!(
+        "Configured {}-wide out-of-order RV{}I core with {} reservation stations and {} ROB entries",
+        issue_width, XLEN, NUM_STATIONS, ROB_SIZE
+    );
+    rv32i_core
+}
+
+/// each result broadcast on the CDB holds the ROB tag, the value and the next PC (or the address of a store)
+pub const RESULT_SIZE: usize = 1 + 2 * XLEN_BYTES;
+
+/// the result broadcast has room for the results of all the functional units, after the number of valid results
+pub fn broadcast_size(issue_width: usize) -> usize {
+    1 + (issue_width + 1) * RESULT_SIZE
+}
+
+/// reorder buffer, reservation stations and register alias table of an out-of-order core
+pub fn tomasulo(core: &RiscCore) -> MutexGuard<'_, TomasuloState> {
+    core.microarchitecture::<Mutex<TomasuloState>>().lock().unwrap()
+}
+
+pub fn load_elf(core: &mut RiscCore, path: &str) -> Result<(), LoadError> {
+    core.load_binary(path, MemoryDeviceType::L1ICACHE)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::risc_soc::memory_management_unit::Address;
+    use crate::risc_soc::risc_soc::RiscWord;
+
+    /// addi x1, x0, 5; addi x2, x0, 7; add x3, x1, x2; auipc x4, 0x10; sw x3, 0(x4); lw x5, 0(x4)
+    /// beq x5, x3, 8; addi x6, x0, 1 (skipped); addi x7, x0, 9; jal x0, 0
+    const PROGRAM: [u32; 10] = [
+        0x00500093, 0x00700113, 0x002081B3, 0x00010217, 0x00322023, 0x00022283, 0x00328463, 0x00100313,
+        0x00900393, 0x0000006F,
+    ];
+
+    /// addi xN, x0, N for N in 1..=8; jal x0, 0
+    const INDEPENDENT_PROGRAM: [u32; 9] = [
+        0x00100093, 0x00200113, 0x00300193, 0x00400213, 0x00500293, 0x00600313, 0x00700393, 0x00800413,
+        0x0000006F,
+    ];
+
+    fn load_program(core: &mut crate::risc_soc::risc_soc::RiscCore) {
+        load(core, &PROGRAM);
+    }
+
+    fn load(core: &mut crate::risc_soc::risc_soc::RiscCore, program: &[u32]) {
+        let program: Vec<u8> = program.iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        core.init_memory(0x8000_0000 as Address, &program);
+        core.set_pc(0x8000_0000);
+    }
+
+    #[test]
+    fn test_tomasulo_program_order() {
+        let mut ooo_core = super::init_core(None);
+        load_program(&mut ooo_core);
+        ooo_core.run_sequential(Some(60));
+        assert_eq!(ooo_core.read_regs(3, 5), (12, 12));
+        assert_eq!(ooo_core.read_regs(6, 7), (0, 9));
+        assert_eq!(ooo_core.peek_memory(0x8001_000C, 4), Some(12u32.to_le_bytes().to_vec()));
+        // the branch and every iteration of the final loop redirect the front end
+        let state = super::tomasulo(&ooo_core);
+        assert!(state.retired >= PROGRAM.len() as u64 - 1);
+        assert!(state.flushes >= 2);
+    }
+
+    #[test]
+    #[cfg(not(feature = "rv64"))]
+    fn test_tomasulo_isa_add() {
+        use crate::rv32i_baremetal::isa_test::IsaTest;
+
+        IsaTest::new("add.elf")
+            .on_core(super::init_core)
+            .max_cycles(100)
+            .expect_reg("x3", 3)
+            .expect_reg("x6", -2i32 as RiscWord)
+            .expect_reg("x7", 3)
+            .run();
+    }
+
+    #[test]
+    fn test_tomasulo_sequential_matches_threaded() {
+        let mut threaded_core = super::init_core(None);
+        load_program(&mut threaded_core);
+        threaded_core.run(Some(60));
+
+        let mut sequential_core = super::init_core(None);
+        load_program(&mut sequential_core);
+        sequential_core.run_sequential(Some(60));
+
+        assert_eq!(threaded_core.registers.to_string(), sequential_core.registers.to_string());
+        assert_eq!(super::tomasulo(&threaded_core).retired, super::tomasulo(&sequential_core).retired);
+    }
+
+    #[test]
+    fn test_superscalar_issue() {
+        // dependent pairs are split over two cycles, the results stay the same as with a single issue slot
+        let mut wide_core = super::init_superscalar_core(None, 2);
+        load_program(&mut wide_core);
+        wide_core.run_sequential(Some(60));
+        assert_eq!(wide_core.read_regs(3, 5), (12, 12));
+        assert_eq!(wide_core.read_regs(6, 7), (0, 9));
+
+        // independent instructions retire two by two
+        let mut narrow_core = super::init_core(None);
+        load(&mut narrow_core, &INDEPENDENT_PROGRAM);
+        narrow_core.run_sequential(Some(7));
+        let mut wide_core = super::init_superscalar_core(None, 2);
+        load(&mut wide_core, &INDEPENDENT_PROGRAM);
+        wide_core.run_sequential(Some(7));
+        assert!(super::tomasulo(&wide_core).retired > super::tomasulo(&narrow_core).retired);
+        wide_core.run_sequential(Some(20));
+        for register in 1..=8 {
+            assert_eq!(wide_core.read_regs(register, 0).0, register as RiscWord);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryResponseType};
+use crate::risc_soc::pipeline_stage::PipelineData;
+use crate::risc_soc::risc_soc::{RiscCore, RiscSignedWord, RiscWord, WordSize, XLEN};
+use crate::rv32i_baremetal::decode::sign_extend;
+use crate::rv32i_ooo::core::{CM_STAGE, EX_STAGE, IS_STAGE, broadcast_size, tomasulo};
+use crate::rv32i_ooo::tomasulo::{OpKind, Station};
+
+/// shift amounts only use the lower log2(XLEN) bits of the operand
+const SHAMT_MASK: RiscWord = (XLEN - 1) as RiscWord;
+
+/// functional units, each one starts at most one instruction per cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    /// integer operations, jumps and branches
+    Alu,
+    /// address generation of loads and stores, loads also access memory here
+    LoadStore,
+}
+
+pub fn rv32_ooo_execute_stage(_pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
+    // wait for commit, a flush of this cycle empties the reservation stations
+    rv32_core.cdb.pull(CM_STAGE, EX_STAGE);
+
+    let mut state = tomasulo(rv32_core);
+    let mut results = vec![];
+    // one ALU per issue slot and a single load/store unit
+    let units = std::iter::repeat_n(Unit::Alu, rv32_core.issue_width).chain([Unit::LoadStore]);
+    for unit in units {
+        // the oldest ready instruction goes first, so the head of the ROB is never starved
+        let selected = state
+            .stations
+            .iter()
+            .enumerate()
+            .filter_map(|(index, station)| station.map(|station| (index, station)))
+            .filter(|(_, station)| station.is_ready() && unit_of(station.op.kind) == unit)
+            .filter(|(_, station)| station.op.kind != OpKind::Load || state.load_may_execute(station.tag))
+            .min_by_key(|(_, station)| state.rob.age(station.tag));
+        if let Some((index, station)) = selected {
+            state.stations[index] = None;
+            let (value, target) = execute(rv32_core, &station);
+            results.push((station.tag, value, target));
+        }
+    }
+    drop(state);
+
+    // broadcast the results on the CDB: number of results, then tag, value and next PC (or store address) of each one
+    let mut cdb_data = vec![results.len() as u8];
+    for (tag, value, target) in &results {
+        cdb_data.push(*tag as u8);
+        cdb_data.extend_from_slice(&value.to_le_bytes());
+        cdb_data.extend_from_slice(&target.to_le_bytes());
+    }
+    cdb_data.resize(broadcast_size(rv32_core.issue_width), 0x0);
+    rv32_core.cdb.assign(EX_STAGE, IS_STAGE, PipelineData(cdb_data));
+
+    PipelineData(vec![results.len() as u8])
+}
+
+fn unit_of(kind: OpKind) -> Unit {
+    match kind {
+        OpKind::Load | OpKind::Store => Unit::LoadStore,
+        _ => Unit::Alu,
+    }
+}
+
+/// result of an instruction and the PC of its successor, or the address of a store
+fn execute(rv32_core: &RiscCore, station: &Station) -> (RiscWord, RiscWord) {
+    let op = station.op;
+    let pc = station.pc;
+    let rs1 = station.src1.value().unwrap();
+    let rs2 = station.src2.value().unwrap();
+    let next_pc = pc.wrapping_add(4);
+    match op.kind {
+        OpKind::Alu => (alu(op.func3, op.func7, rs1, rs2), next_pc),
+        OpKind::AluImm => {
+            // only the right shifts have a function field in their immediate, whose lowest bit is part of shamt on RV64
+            let func7 = if op.func3 == 0b101 { op.func7 & !0x1 } else { 0x0 };
+            (alu(op.func3, func7, rs1, op.imm), next_pc)
+        }
+        OpKind::Lui => (op.imm, next_pc),
+        OpKind::Auipc => (pc.wrapping_add(op.imm), next_pc),
+        OpKind::Jal => (next_pc, pc.wrapping_add(op.imm)),
+        OpKind::Jalr => (next_pc, rs1.wrapping_add(op.imm) & !0x1),
+        OpKind::Branch => {
+            let taken = match op.func3 {
+                0b000 => rs1 == rs2,
+                0b001 => rs1 != rs2,
+                0b100 => (rs1 as RiscSignedWord) < (rs2 as RiscSignedWord),
+                0b101 => (rs1 as RiscSignedWord) >= (rs2 as RiscSignedWord),
+                0b110 => rs1 < rs2,
+                _ => rs1 >= rs2,
+            };
+            (0, if taken { pc.wrapping_add(op.imm) } else { next_pc })
+        }
+        OpKind::Load => {
+            let address = rs1.wrapping_add(op.imm);
+            let size = match op.func3 {
+                0x0 | 0x4 => WordSize::BYTE,
+                0x1 | 0x5 => WordSize::HALF,
+                _ => WordSize::WORD,
+            };
+            // a faulting load raised its exception, which is precise since no older instruction can still trap or flush
+            rv32_core.data_access_pc.store(pc as Address, std::sync::atomic::Ordering::SeqCst);
+            let response = rv32_core.data_request(MemoryRequest::read(address as Address, size));
+            if response.status != MemoryResponseType::CacheHit && response.status != MemoryResponseType::Valid {
+                return (0, address);
+            }
+            let value = match op.func3 {
+                0x0 => sign_extend(response.as_u8().cast_signed() as i32 as u32),
+                0x4 => response.as_u8() as RiscWord,
+                0x1 => sign_extend(response.as_u16().cast_signed() as i32 as u32),
+                0x5 => response.as_u16() as RiscWord,
+                _ => sign_extend(response.as_u32()),
+            };
+            (value, address)
+        }
+        // stores only compute their address here, memory is written when they retire
+        OpKind::Store => (rs2, rs1.wrapping_add(op.imm)),
+        OpKind::Fence | OpKind::FenceI | OpKind::Illegal => unreachable!("{:?} is never issued to a reservation station", op.kind),
+    }
+}
+
+fn alu(func3: u8, func7: u8, rs1: RiscWord, rs2: RiscWord) -> RiscWord {
+    match (func3, func7) {
+        (0b000, 0b0100000) => rs1.wrapping_sub(rs2),
+        (0b000, _) => rs1.wrapping_add(rs2),
+        (0b001, _) => rs1 << (rs2 & SHAMT_MASK),
+        (0b010, _) => ((rs1 as RiscSignedWord) < (rs2 as RiscSignedWord)) as RiscWord,
+        (0b011, _) => (rs1 < rs2) as RiscWord,
+        (0b100, _) => rs1 ^ rs2,
+        (0b101, 0b0100000) => (rs1 as RiscSignedWord >> (rs2 & SHAMT_MASK)) as RiscWord,
+        (0b101, _) => rs1 >> (rs2 & SHAMT_MASK),
+        (0b110, _) => rs1 | rs2,
+        _ => rs1 & rs2,
+    }
+}
@@ -0,0 +1,36 @@
+use crate::risc_soc::memory_management_unit::{Address, MemoryRequest, MemoryResponseType};
+use crate::risc_soc::pipeline_stage::PipelineData;
+use crate::risc_soc::risc_soc::{RiscCore, RiscWord};
+use crate::rv32i_ooo::core::{CM_STAGE, IF_STAGE, IS_STAGE};
+
+pub fn rv32_ooo_fetch_stage(_pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
+    let mut current_pc = rv32_core.get_pc();
+
+    // the front end always predicts not taken, commit redirects it when a control flow instruction retires on another path
+    let commit_data = rv32_core.cdb.pull(CM_STAGE, IF_STAGE);
+    // issue asks to fetch again the instructions of the previous packet it could not accept
+    let issue_data = rv32_core.cdb.pull(IS_STAGE, IF_STAGE);
+    if commit_data.get_u8(0x0) == 0x1 {
+        current_pc = commit_data.get_word(0x1);
+        rv32_core.set_pc(current_pc);
+    } else if issue_data.get_u8(0x0) == 0x1 {
+        current_pc = issue_data.get_word(0x1);
+        rv32_core.set_pc(current_pc);
+    }
+
+    // fetching runs ahead of commit, possibly past the end of the program or into data, so failed fetches are only bubbles
+    // and do not raise an exception: the instruction may never be executed
+    let slots: Vec<_> = (0..rv32_core.issue_width)
+        .map(|slot| {
+            let pc = current_pc.wrapping_add(4 * slot as RiscWord);
+            let response = rv32_core.icache_request(MemoryRequest::read_u32(pc as Address));
+            let instruction = match response.status {
+                MemoryResponseType::CacheHit | MemoryResponseType::Valid => response.as_u32(),
+                _ => 0x0,
+            };
+            (instruction, pc)
+        })
+        .collect();
+
+    PipelineData::fetch_packet(&slots)
+}
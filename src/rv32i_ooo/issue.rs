@@ -0,0 +1,104 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::pipeline_stage::PipelineData;
+use crate::risc_soc::risc_soc::{RiscCore, XLEN_BYTES};
+use crate::rv32i_ooo::core::{CM_STAGE, EX_STAGE, IF_STAGE, IS_STAGE, RESULT_SIZE, tomasulo};
+use crate::rv32i_ooo::tomasulo::{MicroOp, OpKind, Operand, RobEntry, Station, TomasuloState};
+
+pub fn rv32_ooo_issue_stage(pipeline_reg: &PipelineData, rv32_core: &RiscCore) -> PipelineData {
+    // commit and execute already updated the state of this cycle once their wires are assigned
+    let commit_data = rv32_core.cdb.pull(CM_STAGE, IS_STAGE);
+    let flush = commit_data.get_u8(0x0) == 0x1;
+    let cdb_data = rv32_core.cdb.pull(EX_STAGE, IS_STAGE);
+
+    let mut state = tomasulo(rv32_core);
+
+    // CDB broadcast: results go to their ROB entry and wake up the stations waiting for them
+    for result in 0..cdb_data.get_u8(0x0) as usize {
+        let offset = 0x1 + result * RESULT_SIZE;
+        let tag = cdb_data.get_u8(offset) as usize;
+        let value = cdb_data.get_word(offset + 1);
+        let target = cdb_data.get_word(offset + 1 + XLEN_BYTES);
+        if let Some(entry) = state.rob.get_mut(tag) {
+            entry.ready = true;
+            entry.value = value;
+            if entry.op.kind == OpKind::Store {
+                entry.address = target as Address;
+            } else {
+                entry.next_pc = target;
+            }
+        }
+        for station in state.stations.iter_mut().flatten() {
+            if station.src1 == Operand::Pending(tag) {
+                station.src1 = Operand::Value(value);
+            }
+            if station.src2 == Operand::Pending(tag) {
+                station.src2 = Operand::Value(value);
+            }
+        }
+    }
+
+    // instructions fetched before a redirect are on the wrong path
+    let mut issued: Vec<MicroOp> = vec![];
+    let mut replay = None;
+    for slot in 0..pipeline_reg.fetch_slots() {
+        let (instruction, pc) = pipeline_reg.fetch_slot(slot);
+        // a zero instruction is a bubble
+        if flush || instruction == 0x0 {
+            continue;
+        }
+
+        let op = MicroOp::decode(instruction);
+        // fences and illegal instructions have nothing to execute, they only act when they retire
+        let needs_station = !matches!(op.kind, OpKind::Fence | OpKind::FenceI | OpKind::Illegal);
+        let free_station = state.stations.iter().position(Option::is_none);
+        // the sources of a packet are renamed in parallel, so an instruction cannot issue together with the producer of its operands
+        let dependent = issued.iter().any(|older| op.depends_on(older));
+        if state.rob.is_full() || (needs_station && free_station.is_none()) || dependent {
+            // this instruction and the younger ones of the packet are fetched again next cycle
+            replay = Some(pc);
+            break;
+        }
+
+        // rename the sources before the destination, an instruction may read the register it writes
+        let src1 = operand(&state, rv32_core, op.rs1, op.reads_rs1());
+        let src2 = operand(&state, rv32_core, op.rs2, op.reads_rs2());
+        let tag = state.rob.push(RobEntry {
+            pc,
+            instruction,
+            op,
+            ready: !needs_station,
+            value: 0,
+            next_pc: pc.wrapping_add(4),
+            address: 0,
+        });
+        if op.writes_rd() {
+            state.rat[op.rd] = Some(tag);
+        }
+        if let Some(station) = free_station.filter(|_| needs_station) {
+            state.stations[station] = Some(Station { tag, pc, op, src1, src2 });
+        }
+        issued.push(op);
+    }
+    drop(state);
+
+    let mut if_data = vec![replay.is_some() as u8];
+    if_data.extend_from_slice(&replay.unwrap_or(0).to_le_bytes());
+    rv32_core.cdb.assign(IS_STAGE, IF_STAGE, PipelineData(if_data));
+
+    PipelineData(vec![issued.len() as u8])
+}
+
+/// latest value of a source register: from the register file if no instruction in flight writes it,
+/// from the ROB if its producer already executed, otherwise the tag of the producer to wait for on the CDB
+fn operand(state: &TomasuloState, rv32_core: &RiscCore, register: usize, used: bool) -> Operand {
+    if !used || register == 0 {
+        return Operand::Value(0);
+    }
+    match state.rat[register] {
+        Some(tag) => match state.rob.get(tag) {
+            Some(entry) if entry.ready => Operand::Value(entry.value),
+            _ => Operand::Pending(tag),
+        },
+        None => Operand::Value(rv32_core.read_regs(register, 0).0),
+    }
+}
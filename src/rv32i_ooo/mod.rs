@@ -0,0 +1,6 @@
+mod fetch;
+mod issue;
+mod execute;
+mod commit;
+pub mod tomasulo;
+pub mod core;
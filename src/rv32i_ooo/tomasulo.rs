@@ -0,0 +1,273 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::RiscWord;
+use crate::rv32i_baremetal::decode::{
+    FUNCT_3_MASK, FUNCT_7_MASK, FUNC3_FENCE_I, OP_ALU, OP_ALUI, OP_AUIPC, OP_BRANCH, OP_FENCE,
+    OP_JAL, OP_JALR, OP_LOAD, OP_LUI, OP_STORE, OPCODE_MASK, REG_MASK, sign_extend,
+};
+
+/// entries of the reorder buffer are identified by their slot, which is also the tag broadcast with their result
+pub type RobTag = usize;
+
+/// kind of operation, selecting the functional unit and how the instruction retires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpKind {
+    Alu,
+    AluImm,
+    Lui,
+    Auipc,
+    Jal,
+    Jalr,
+    Branch,
+    Load,
+    Store,
+    Fence,
+    FenceI,
+    /// not supported by this core, raises an illegal instruction exception when it reaches the head of the ROB
+    Illegal,
+}
+
+/// decoded instruction as held by the reservation stations and the reorder buffer
+#[derive(Debug, Clone, Copy)]
+pub struct MicroOp {
+    pub kind: OpKind,
+    pub func3: u8,
+    pub func7: u8,
+    pub rd: usize,
+    pub rs1: usize,
+    pub rs2: usize,
+    pub imm: RiscWord,
+}
+
+impl MicroOp {
+    pub fn decode(instruction: u32) -> Self {
+        let opcode = (instruction & OPCODE_MASK) as u8;
+        let func3 = ((instruction >> 12) & FUNCT_3_MASK) as u8;
+        let func7 = ((instruction >> 25) & FUNCT_7_MASK) as u8;
+        let rd = ((instruction >> 7) & REG_MASK) as usize;
+        let rs1 = ((instruction >> 15) & REG_MASK) as usize;
+        let rs2 = ((instruction >> 20) & REG_MASK) as usize;
+        let imm_i = sign_extend((instruction as i32 >> 20) as u32);
+        let (kind, imm) = match opcode {
+            OP_ALU => (OpKind::Alu, 0),
+            OP_ALUI => (OpKind::AluImm, imm_i),
+            OP_LUI => (OpKind::Lui, sign_extend(instruction & 0xFFFF_F000)),
+            OP_AUIPC => (OpKind::Auipc, sign_extend(instruction & 0xFFFF_F000)),
+            OP_JAL => {
+                let imm = (((instruction as i32 >> 31) as u32) << 20)
+                    | (instruction & 0xFF000)
+                    | ((instruction >> 20 & 0x1) << 11)
+                    | ((instruction >> 21 & 0x3FF) << 1);
+                (OpKind::Jal, sign_extend(imm))
+            }
+            OP_JALR => (OpKind::Jalr, imm_i),
+            OP_BRANCH => {
+                let imm = (((instruction as i32 >> 31) as u32) << 12)
+                    | ((instruction >> 7 & 0x1) << 11)
+                    | ((instruction >> 25 & 0x3F) << 5)
+                    | ((instruction >> 8 & 0xF) << 1);
+                (OpKind::Branch, sign_extend(imm))
+            }
+            OP_LOAD => (OpKind::Load, imm_i),
+            OP_STORE => {
+                let imm = (((instruction as i32 >> 25) << 5) as u32) | ((instruction >> 7) & REG_MASK);
+                (OpKind::Store, sign_extend(imm))
+            }
+            OP_FENCE if func3 == FUNC3_FENCE_I => (OpKind::FenceI, 0),
+            OP_FENCE => (OpKind::Fence, 0),
+            _ => (OpKind::Illegal, 0),
+        };
+        Self { kind, func3, func7, rd, rs1, rs2, imm }
+    }
+
+    pub fn writes_rd(&self) -> bool {
+        self.rd != 0
+            && matches!(
+                self.kind,
+                OpKind::Alu | OpKind::AluImm | OpKind::Lui | OpKind::Auipc | OpKind::Jal | OpKind::Jalr | OpKind::Load
+            )
+    }
+
+    pub fn reads_rs1(&self) -> bool {
+        !matches!(self.kind, OpKind::Lui | OpKind::Auipc | OpKind::Jal | OpKind::Fence | OpKind::FenceI | OpKind::Illegal)
+    }
+
+    pub fn reads_rs2(&self) -> bool {
+        matches!(self.kind, OpKind::Alu | OpKind::Branch | OpKind::Store)
+    }
+
+    /// true if the instruction reads the register written by an older one
+    pub fn depends_on(&self, older: &MicroOp) -> bool {
+        older.writes_rd()
+            && ((self.reads_rs1() && self.rs1 == older.rd) || (self.reads_rs2() && self.rs2 == older.rd))
+    }
+
+    /// instructions that may redirect the front end, which always keeps fetching sequentially
+    pub fn is_control_flow(&self) -> bool {
+        matches!(self.kind, OpKind::Jal | OpKind::Jalr | OpKind::Branch)
+    }
+}
+
+/// source operand of a reservation station: either already known or produced by the ROB entry with the given tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Value(RiscWord),
+    Pending(RobTag),
+}
+
+impl Operand {
+    pub fn value(&self) -> Option<RiscWord> {
+        match self {
+            Operand::Value(value) => Some(*value),
+            Operand::Pending(_) => None,
+        }
+    }
+}
+
+/// instruction waiting in a reservation station for its operands
+#[derive(Debug, Clone, Copy)]
+pub struct Station {
+    pub tag: RobTag,
+    pub pc: RiscWord,
+    pub op: MicroOp,
+    pub src1: Operand,
+    pub src2: Operand,
+}
+
+impl Station {
+    pub fn is_ready(&self) -> bool {
+        self.src1.value().is_some() && self.src2.value().is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RobEntry {
+    pub pc: RiscWord,
+    /// encoding of the instruction, reported to the observers of the core when it retires
+    pub instruction: u32,
+    pub op: MicroOp,
+    /// result has been broadcast on the CDB, the entry can retire once it reaches the head
+    pub ready: bool,
+    /// value written to rd, or the data of a store
+    pub value: RiscWord,
+    /// PC of the next instruction in program order, known once control flow instructions are executed
+    pub next_pc: RiscWord,
+    /// address of a store, written to memory only when the store retires
+    pub address: Address,
+}
+
+impl RobEntry {
+    /// the front end fetched the sequential instruction, so any other successor means the younger entries are on the wrong path
+    pub fn mispredicted(&self) -> bool {
+        self.op.is_control_flow() && self.next_pc != self.pc.wrapping_add(4)
+    }
+}
+
+/// circular buffer keeping the instructions in program order until they retire
+#[derive(Debug)]
+pub struct ReorderBuffer {
+    entries: Vec<Option<RobEntry>>,
+    head: usize,
+    len: usize,
+}
+
+impl ReorderBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: vec![None; capacity], head: 0, len: 0 }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn push(&mut self, entry: RobEntry) -> RobTag {
+        assert!(!self.is_full());
+        let tag = (self.head + self.len) % self.entries.len();
+        self.entries[tag] = Some(entry);
+        self.len += 1;
+        tag
+    }
+
+    pub fn head(&self) -> Option<(RobTag, RobEntry)> {
+        self.entries[self.head].map(|entry| (self.head, entry))
+    }
+
+    pub fn pop(&mut self) -> Option<RobEntry> {
+        let entry = self.entries[self.head].take()?;
+        self.head = (self.head + 1) % self.entries.len();
+        self.len -= 1;
+        Some(entry)
+    }
+
+    pub fn get(&self, tag: RobTag) -> Option<&RobEntry> {
+        self.entries[tag].as_ref()
+    }
+
+    pub fn get_mut(&mut self, tag: RobTag) -> Option<&mut RobEntry> {
+        self.entries[tag].as_mut()
+    }
+
+    /// distance of an entry from the head, the oldest instruction has age 0
+    pub fn age(&self, tag: RobTag) -> usize {
+        (tag + self.entries.len() - self.head) % self.entries.len()
+    }
+
+    /// entries older than the given one, from the head
+    pub fn older_than(&self, tag: RobTag) -> impl Iterator<Item = &RobEntry> {
+        (0..self.age(tag)).filter_map(move |i| self.entries[(self.head + i) % self.entries.len()].as_ref())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+/// speculative state of the out-of-order core, shared by its stages through `RiscCore::microarchitecture`
+/// within a clock cycle the stages access it one after the other (commit, execute, issue), ordered by the CDB wires between them
+#[derive(Debug)]
+pub struct TomasuloState {
+    pub rob: ReorderBuffer,
+    pub stations: Vec<Option<Station>>,
+    /// register alias table: ROB entry that will produce the latest value of each register, if it did not retire yet
+    pub rat: [Option<RobTag>; 32],
+    pub retired: u64,
+    pub flushes: u64,
+}
+
+impl TomasuloState {
+    pub fn new(rob_size: usize, num_stations: usize) -> Self {
+        Self {
+            rob: ReorderBuffer::new(rob_size),
+            stations: vec![None; num_stations],
+            rat: [None; 32],
+            retired: 0,
+            flushes: 0,
+        }
+    }
+
+    /// drop every speculative instruction, the architectural registers already hold the state of the retired ones
+    pub fn flush(&mut self) {
+        self.rob.clear();
+        self.stations.iter_mut().for_each(|station| *station = None);
+        self.rat = [None; 32];
+        self.flushes += 1;
+    }
+
+    /// loads only execute once no older store is pending and no older instruction can still trap or flush them
+    /// so the loads reaching memory (ex. MMIO registers with side effects) are never speculative
+    pub fn load_may_execute(&self, tag: RobTag) -> bool {
+        self.rob.older_than(tag).all(|entry| {
+            !matches!(entry.op.kind, OpKind::Store | OpKind::Illegal | OpKind::FenceI)
+                && (!entry.op.is_control_flow() || (entry.ready && !entry.mispredicted()))
+        })
+    }
+}
@@ -0,0 +1,291 @@
+use crate::risc_soc::load_error::LoadError;
+use crate::risc_soc::memory_management_unit::{Address, MemoryDevice, MemoryDeviceType, Permissions};
+use crate::risc_soc::risc_soc::{EI_CLASS, RiscCore, RiscWord, XLEN};
+use crate::rv32i_baremetal::{core::init_hart, dram::Dram};
+use crate::user_mode::syscalls::LinuxSyscalls;
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::{Endianness, elf};
+use std::sync::Arc;
+
+/// memory of the process, the first page is left unmapped to catch null pointer accesses
+pub const USER_MEMORY_START: Address = 0x1000;
+pub const USER_MEMORY_END: Address = 0x400_0000;
+/// the stack grows down from the end of the memory, the heap (see brk) grows up until the stack
+pub const STACK_SIZE: Address = 0x10_0000;
+pub const PAGE_SIZE: Address = 0x1000;
+
+// entries of the auxiliary vector placed on the initial stack, as the Linux ELF loader does
+const AT_NULL: RiscWord = 0;
+const AT_PHDR: RiscWord = 3;
+const AT_PHENT: RiscWord = 4;
+const AT_PHNUM: RiscWord = 5;
+const AT_PAGESZ: RiscWord = 6;
+const AT_ENTRY: RiscWord = 9;
+const AT_RANDOM: RiscWord = 25;
+
+/// what the C runtime of the program needs to know about its own image
+struct ProgramImage {
+    entry: Address,
+    /// first address after the loaded segments, where the heap starts
+    end: Address,
+    /// address of the program headers in memory
+    phdr: Address,
+    phent: usize,
+    phnum: usize,
+}
+
+/// MCU core running a statically linked Linux program (ex. built against newlib or musl), whose system calls are emulated on the host
+/// argv[0] is the path of the program, followed by `args`, the returned handler holds the console output of the program
+pub fn init_user_core(path: &str, args: &[&str], env: &[&str]) -> Result<(RiscCore, Arc<LinuxSyscalls>), LoadError> {
+    let mut core = init_hart(None);
+    {
+        let mut mmu = core.mmu.write().unwrap();
+        let memory = Dram::new(MemoryDeviceType::DRAM, USER_MEMORY_START, USER_MEMORY_END);
+        mmu.add_memory_device_with_permissions(Box::new(memory), Permissions::RWX);
+    }
+
+    let data = std::fs::read(path)?;
+    let image = match data.get(EI_CLASS).copied() {
+        Some(elf::ELFCLASS64) if XLEN == 64 => load_segments::<elf::FileHeader64<Endianness>>(&mut core, &data)?,
+        Some(elf::ELFCLASS32) if XLEN == 32 => load_segments::<elf::FileHeader32<Endianness>>(&mut core, &data)?,
+        Some(elf::ELFCLASS64) => return Err(LoadError::WrongClass { elf_bits: 64, core_bits: XLEN }),
+        Some(elf::ELFCLASS32) => return Err(LoadError::WrongClass { elf_bits: 32, core_bits: XLEN }),
+        // let the parser report what is wrong with the header
+        _ => load_segments::<elf::FileHeader32<Endianness>>(&mut core, &data)?,
+    };
+
+    let argv: Vec<&str> = std::iter::once(path).chain(args.iter().copied()).collect();
+    let sp = setup_stack(&mut core, &image, &argv, env);
+    core.write_reg(2, sp as RiscWord);
+    core.set_reset_vector(image.entry as RiscWord);
+
+    let syscalls = Arc::new(LinuxSyscalls::new(image.end.next_multiple_of(PAGE_SIZE), USER_MEMORY_END - STACK_SIZE));
+    core.set_environment_call_handler(syscalls.clone());
+    Ok((core, syscalls))
+}
+
+/// copy the PT_LOAD segments to the memory of the process, the rest of the segments is already zeroed (ex. .bss)
+fn load_segments<Elf: FileHeader<Endian = Endianness>>(core: &mut RiscCore, data: &[u8]) -> Result<ProgramImage, LoadError> {
+    let elf = Elf::parse(data)?;
+    let endian = elf.endian()?;
+    if endian == Endianness::Big {
+        return Err(LoadError::BigEndian);
+    }
+    let machine = elf.e_machine(endian);
+    if machine != elf::EM_RISCV {
+        return Err(LoadError::NotRiscV(machine));
+    }
+
+    let segments = elf.program_headers(endian, data)?;
+    let phoff: Address = elf.e_phoff(endian).into();
+    let mut image = ProgramImage {
+        entry: elf.e_entry(endian).into(),
+        end: USER_MEMORY_START,
+        phdr: 0,
+        phent: elf.e_phentsize(endian) as usize,
+        phnum: segments.len(),
+    };
+    for segment in segments {
+        let address: Address = segment.p_vaddr(endian).into();
+        let offset: Address = segment.p_offset(endian).into();
+        let file_size: Address = segment.p_filesz(endian).into();
+        let memory_size: Address = segment.p_memsz(endian).into();
+        match segment.p_type(endian) {
+            elf::PT_PHDR => image.phdr = address,
+            elf::PT_LOAD => {
+                let name = format!("segment @0x{offset:X}");
+                if address < USER_MEMORY_START || address + memory_size > USER_MEMORY_END - STACK_SIZE {
+                    return Err(LoadError::OutOfRange { name, address, size: memory_size as usize });
+                }
+                let bytes = segment.data(endian, data).map_err(|()| {
+                    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("{name} is past the end of the file"))
+                })?;
+                core.init_memory(address, bytes);
+                // without a PT_PHDR the program headers can still be found in the segment holding them
+                if image.phdr == 0 && (offset..offset + file_size).contains(&phoff) {
+                    image.phdr = address + phoff - offset;
+                }
+                image.end = image.end.max(address + memory_size);
+            }
+            _ => {}
+        }
+    }
+    Ok(image)
+}
+
+/// lay out argc, argv, envp and the auxiliary vector at the top of the memory as the Linux ELF loader does, returning sp
+fn setup_stack(core: &mut RiscCore, image: &ProgramImage, argv: &[&str], env: &[&str]) -> Address {
+    let mut top = USER_MEMORY_END;
+    let mut push = |core: &mut RiscCore, bytes: &[u8]| {
+        top -= bytes.len() as Address;
+        core.init_memory(top, bytes);
+        top
+    };
+    let c_string = |string: &str| string.bytes().chain(std::iter::once(0)).collect::<Vec<u8>>();
+    let env_pointers: Vec<Address> = env.iter().map(|var| push(core, &c_string(var))).collect();
+    let arg_pointers: Vec<Address> = argv.iter().map(|arg| push(core, &c_string(arg))).collect();
+    // seed of the stack protector and of the pointer guards of the C library, fixed so that runs are reproducible
+    let random = push(core, &[0x5A; 16]);
+
+    let mut table: Vec<RiscWord> = vec![argv.len() as RiscWord];
+    table.extend(arg_pointers.iter().map(|pointer| *pointer as RiscWord));
+    table.push(0);
+    table.extend(env_pointers.iter().map(|pointer| *pointer as RiscWord));
+    table.push(0);
+    let auxv = [
+        (AT_PHDR, image.phdr as RiscWord),
+        (AT_PHENT, image.phent as RiscWord),
+        (AT_PHNUM, image.phnum as RiscWord),
+        (AT_PAGESZ, PAGE_SIZE as RiscWord),
+        (AT_ENTRY, image.entry as RiscWord),
+        (AT_RANDOM, random as RiscWord),
+        (AT_NULL, 0),
+    ];
+    for (key, value) in auxv {
+        table.extend([key, value]);
+    }
+    let bytes: Vec<u8> = table.iter().flat_map(|word| word.to_le_bytes()).collect();
+    // the ABI requires a 16 byte aligned stack pointer
+    let sp = (random - bytes.len() as Address) & !0xF;
+    core.init_memory(sp, &bytes);
+    sp
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::risc_soc::risc_soc::{ExitStatus, RiscCore, RiscWord, XLEN};
+
+    /// executable of the class of the core with a single segment holding the headers, the code and the data, as the usual linker scripts lay it out
+    fn user_program(code: &[u32], data: &[u8]) -> Vec<u8> {
+        // addresses and offsets are as wide as the registers, the sizes of the headers follow
+        fn push_word(elf: &mut Vec<u8>, word: u64) {
+            if XLEN == 64 {
+                elf.extend_from_slice(&word.to_le_bytes());
+            } else {
+                elf.extend_from_slice(&(word as u32).to_le_bytes());
+            }
+        }
+        let (class, header_size, phdr_size, shdr_size) = if XLEN == 64 { (2, 64u64, 56u64, 64u16) } else { (1, 52, 32, 40) };
+        let base: u64 = 0x1_0000;
+        let headers_size = header_size + phdr_size;
+        let mut contents: Vec<u8> = code.iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        contents.extend_from_slice(data);
+        let file_size = headers_size + contents.len() as u64;
+
+        let mut elf = vec![0x7F, b'E', b'L', b'F', class, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        // executable for RISC-V, version 1
+        elf.extend_from_slice(&2u16.to_le_bytes());
+        elf.extend_from_slice(&243u16.to_le_bytes());
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        // entry, program headers right after this header, no section headers, no flags
+        for word in [base + headers_size, header_size, 0] {
+            push_word(&mut elf, word);
+        }
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        for half in [header_size as u16, phdr_size as u16, 1, shdr_size, 0, 0] {
+            elf.extend_from_slice(&half.to_le_bytes());
+        }
+        // PT_LOAD of the whole file, with some zeroed memory after it, RWX, the flags come second in ELF64
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        if XLEN == 64 {
+            elf.extend_from_slice(&7u32.to_le_bytes());
+        }
+        for word in [0, base, base, file_size, file_size + 0x100] {
+            push_word(&mut elf, word);
+        }
+        if XLEN == 32 {
+            elf.extend_from_slice(&7u32.to_le_bytes());
+        }
+        push_word(&mut elf, 4);
+        elf.extend_from_slice(&contents);
+        elf
+    }
+
+    #[test]
+    fn test_user_mode_hello() {
+        let program = user_program(
+            &[
+                0x00012483, // lw s1, 0(sp)
+                0x04000893, // li a7, 64
+                0x00100513, // li a0, 1
+                0x00000597, // auipc a1, 0
+                0x03458593, // addi a1, a1, 52
+                0x00600613, // li a2, 6
+                0x00000073, // ecall
+                0x00050413, // mv s0, a0
+                0x05d00893, // li a7, 93
+                0x00048513, // mv a0, s1
+                0x00000073, // ecall
+                0x0000006f, // j 0
+                // keeps the string out of the instructions fetched after the exit
+                0x00000013, // nop
+                0x00000013, // nop
+                0x00000013, // nop
+                0x00000013, // nop
+            ],
+            b"hello\n",
+        );
+        let path = std::env::temp_dir().join("riscv_on_rust_user_hello.elf");
+        std::fs::write(&path, program).unwrap();
+        let (mut core, syscalls) = super::init_user_core(path.to_str().unwrap(), &["a", "b"], &["HOME=/"]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        core.run_sequential(Some(100));
+
+        assert_eq!(syscalls.console_output(), "hello\n");
+        // write returned the number of bytes written, the exit code is argc
+        assert_eq!(core.read_regs(8, 0).0, 6);
+        assert_eq!(core.exit_status(), Some(ExitStatus::Fail(3)));
+    }
+
+    /// system call `number` with the given arguments, as an ECALL of the program would make it
+    fn syscall(core: &RiscCore, number: RiscWord, args: &[RiscWord]) -> RiscWord {
+        for (register, value) in args.iter().enumerate() {
+            core.write_reg(10 + register, *value);
+        }
+        core.write_reg(17, number);
+        core.environment_call(0).unwrap()
+    }
+
+    #[test]
+    fn test_user_mode_file_syscalls() {
+        use crate::user_mode::syscalls::*;
+
+        let program = std::env::temp_dir().join("riscv_on_rust_user_files.elf");
+        std::fs::write(&program, user_program(&[0x0000006f], &[])).unwrap();
+        let file = std::env::temp_dir().join("riscv_on_rust_user_files.txt");
+        std::fs::write(&file, b"0123456789").unwrap();
+        let (core, _) = super::init_user_core(program.to_str().unwrap(), &[], &[]).unwrap();
+        std::fs::remove_file(&program).unwrap();
+
+        let (path, empty, buffer) = (0x20_0000, 0x20_1000, 0x20_2000);
+        core.poke_memory(path, format!("{}\0", file.display()).as_bytes()).unwrap();
+        core.poke_memory(empty, &[0]).unwrap();
+        let errno = |errno: RiscWord| errno.wrapping_neg();
+        let fd = syscall(&core, SYS_OPENAT, &[-100i32 as RiscWord, path as RiscWord, 0]);
+        assert_eq!(fd, 3);
+
+        // the position is passed and returned as 64 bits on RV32, lseek and fstat do not exist there
+        if XLEN == 32 {
+            assert_eq!(syscall(&core, SYS_LSEEK, &[fd, 0, 4, buffer as RiscWord, 0]), 0);
+            assert_eq!(core.peek_memory(buffer, 8), Some(4u64.to_le_bytes().to_vec()));
+            assert_eq!(syscall(&core, SYS_FSTAT, &[fd, buffer as RiscWord]), errno(38));
+        } else {
+            assert_eq!(syscall(&core, SYS_LSEEK, &[fd, 4, 0]), 4);
+            assert_eq!(syscall(&core, SYS_FSTAT, &[fd, buffer as RiscWord]), 0);
+            assert_eq!(core.peek_memory(buffer + 48, 8), Some(10u64.to_le_bytes().to_vec()));
+        }
+        assert_eq!(syscall(&core, SYS_READ, &[fd, buffer as RiscWord, 2]), 2);
+        assert_eq!(core.peek_memory(buffer, 2), Some(b"45".to_vec()));
+
+        // statx of the descriptor itself, and of a path
+        let mode_and_size = || (core.peek_memory(buffer + 28, 2).unwrap(), core.peek_memory(buffer + 40, 8).unwrap());
+        assert_eq!(syscall(&core, SYS_STATX, &[fd, empty as RiscWord, 0x1000, 0x7FF, buffer as RiscWord]), 0);
+        assert_eq!(mode_and_size(), (0o100644u16.to_le_bytes().to_vec(), 10u64.to_le_bytes().to_vec()));
+        assert_eq!(syscall(&core, SYS_STATX, &[1, empty as RiscWord, 0x1000, 0x7FF, buffer as RiscWord]), 0);
+        assert_eq!(mode_and_size().0, 0o020620u16.to_le_bytes().to_vec());
+        assert_eq!(syscall(&core, SYS_STATX, &[-100i32 as RiscWord, path as RiscWord, 0, 0x7FF, buffer as RiscWord]), 0);
+        assert_eq!(mode_and_size().1, 10u64.to_le_bytes().to_vec());
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(syscall(&core, SYS_STATX, &[-100i32 as RiscWord, path as RiscWord, 0, 0x7FF, buffer as RiscWord]), errno(2));
+    }
+}
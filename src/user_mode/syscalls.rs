@@ -0,0 +1,282 @@
+use crate::risc_soc::memory_management_unit::Address;
+use crate::risc_soc::risc_soc::{EnvironmentCallHandler, ExitStatus, RiscCore, RiscSignedWord, RiscWord, XLEN, sign_extend_word, zero_extend_word};
+use ahash::AHashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+// numbers of the system calls of the generic Linux ABI used by RISC-V, passed in a7
+pub const SYS_IOCTL: RiscWord = 29;
+pub const SYS_OPENAT: RiscWord = 56;
+pub const SYS_CLOSE: RiscWord = 57;
+/// lseek on RV64, llseek with the offset split in two registers on RV32
+pub const SYS_LSEEK: RiscWord = 62;
+pub const SYS_READ: RiscWord = 63;
+pub const SYS_WRITE: RiscWord = 64;
+pub const SYS_WRITEV: RiscWord = 66;
+/// RV64 only, the C libraries of RV32 (with a 64 bit time_t) use statx instead
+pub const SYS_FSTAT: RiscWord = 80;
+pub const SYS_EXIT: RiscWord = 93;
+pub const SYS_EXIT_GROUP: RiscWord = 94;
+pub const SYS_SET_TID_ADDRESS: RiscWord = 96;
+pub const SYS_BRK: RiscWord = 214;
+pub const SYS_STATX: RiscWord = 291;
+
+// error numbers, returned negated in a0
+const ENOENT: RiscSignedWord = 2;
+const EBADF: RiscSignedWord = 9;
+const EFAULT: RiscSignedWord = 14;
+const EINVAL: RiscSignedWord = 22;
+const ENOTTY: RiscSignedWord = 25;
+const ENOSYS: RiscSignedWord = 38;
+
+// flags of openat
+const O_ACCMODE: RiscWord = 0o3;
+const O_WRONLY: RiscWord = 0o1;
+const O_RDWR: RiscWord = 0o2;
+const O_CREAT: RiscWord = 0o100;
+const O_TRUNC: RiscWord = 0o1000;
+const O_APPEND: RiscWord = 0o2000;
+
+// file types of st_mode
+const S_IFCHR: u32 = 0o020000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+/// size of the asm-generic struct stat used by the RISC-V ports of the C libraries
+const STAT_SIZE: usize = 128;
+
+/// size of struct statx, and the fields filled in by this emulation: type, mode, nlink and size
+const STATX_SIZE: usize = 256;
+const STATX_BASIC: u32 = 0x1 | 0x2 | 0x4 | 0x200;
+/// flag of statx asking for the file of the descriptor itself when the path is empty
+const AT_EMPTY_PATH: RiscWord = 0x1000;
+
+// registers holding the system call number and its arguments
+const REG_A0: usize = 10;
+const REG_A7: usize = 17;
+
+enum Descriptor {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+struct Process {
+    descriptors: AHashMap<RiscWord, Descriptor>,
+    /// start of the heap, its current end and the address it cannot grow past
+    heap_start: Address,
+    brk: Address,
+    brk_limit: Address,
+    /// everything written to stdout and stderr
+    console: Vec<u8>,
+}
+
+/// emulation of the Linux system calls needed by statically linked programs (ex. newlib or musl "hello world")
+/// standard streams are the ones of the simulator, files are opened on the host relative to its working directory
+pub struct LinuxSyscalls {
+    process: Mutex<Process>,
+}
+
+impl LinuxSyscalls {
+    pub fn new(heap_start: Address, heap_limit: Address) -> Self {
+        let mut descriptors = AHashMap::new();
+        descriptors.insert(0, Descriptor::Stdin);
+        descriptors.insert(1, Descriptor::Stdout);
+        descriptors.insert(2, Descriptor::Stderr);
+        Self { process: Mutex::new(Process { descriptors, heap_start, brk: heap_start, brk_limit: heap_limit, console: vec![] }) }
+    }
+
+    /// what the program wrote to stdout and stderr so far
+    pub fn console_output(&self) -> String {
+        String::from_utf8_lossy(&self.process.lock().unwrap().console).to_string()
+    }
+}
+
+impl EnvironmentCallHandler for LinuxSyscalls {
+    fn environment_call(&self, core: &RiscCore) -> Option<RiscWord> {
+        let number = core.read_regs(REG_A7, 0).0;
+        let args: Vec<RiscWord> = (REG_A0..REG_A0 + 6).map(|register| core.read_regs(register, 0).0).collect();
+        let mut process = self.process.lock().unwrap();
+        let result: Result<RiscWord, RiscSignedWord> = match number {
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                let code = args[0] as u8;
+                core.exit_signal.exit(if code == 0 { ExitStatus::Pass } else { ExitStatus::Fail(code as u16) });
+                return None;
+            }
+            SYS_WRITE => process.write(core, args[0], args[1] as Address, args[2] as usize),
+            SYS_WRITEV => process.writev(core, args[0], args[1] as Address, args[2] as usize),
+            SYS_READ => process.read(core, args[0], args[1] as Address, args[2] as usize),
+            SYS_OPENAT => process.open(core, args[1] as Address, args[2]),
+            SYS_CLOSE => match process.descriptors.remove(&args[0]) {
+                Some(_) => Ok(0),
+                None => Err(EBADF),
+            },
+            SYS_LSEEK if XLEN == 32 => process.llseek(core, args[0], args[1], args[2], args[3] as Address, args[4]),
+            SYS_LSEEK => process.seek(args[0], sign_extend_word(args[1]), args[2]).map(|position| position as RiscWord),
+            SYS_FSTAT if XLEN == 64 => process.fstat(core, args[0], args[1] as Address),
+            SYS_STATX => process.statx(core, args[0], args[1] as Address, args[2], args[4] as Address),
+            SYS_BRK => Ok(process.brk(args[0] as Address) as RiscWord),
+            SYS_IOCTL => match process.descriptors.get(&args[0]) {
+                // only terminal queries are expected, and the files are not terminals
+                Some(Descriptor::File(_)) => Err(ENOTTY),
+                Some(_) => Ok(0),
+                None => Err(EBADF),
+            },
+            // a single thread, whose id is the one of the process
+            SYS_SET_TID_ADDRESS => Ok(1),
+            _ => {
+                tracing::warn!("Unsupported system call {number}");
+                Err(ENOSYS)
+            }
+        };
+        Some(result.unwrap_or_else(|errno| errno.wrapping_neg() as RiscWord))
+    }
+}
+
+impl Process {
+    fn write(&mut self, core: &RiscCore, fd: RiscWord, buffer: Address, len: usize) -> Result<RiscWord, RiscSignedWord> {
+        let bytes = core.peek_memory(buffer, len).ok_or(EFAULT)?;
+        match self.descriptors.get_mut(&fd).ok_or(EBADF)? {
+            Descriptor::Stdout => {
+                self.console.extend_from_slice(&bytes);
+                let _ = std::io::stdout().write_all(&bytes);
+            }
+            Descriptor::Stderr => {
+                self.console.extend_from_slice(&bytes);
+                let _ = std::io::stderr().write_all(&bytes);
+            }
+            Descriptor::File(file) => file.write_all(&bytes).map_err(|_| EINVAL)?,
+            Descriptor::Stdin => return Err(EBADF),
+        }
+        Ok(len as RiscWord)
+    }
+
+    /// array of (base, len) pairs, written one after the other
+    fn writev(&mut self, core: &RiscCore, fd: RiscWord, iov: Address, count: usize) -> Result<RiscWord, RiscSignedWord> {
+        let word = std::mem::size_of::<RiscWord>();
+        let mut written = 0;
+        for index in 0..count {
+            let entry = core.peek_memory(iov + (index * 2 * word) as Address, 2 * word).ok_or(EFAULT)?;
+            let base = RiscWord::from_le_bytes(entry[..word].try_into().unwrap());
+            let len = RiscWord::from_le_bytes(entry[word..].try_into().unwrap());
+            written += self.write(core, fd, base as Address, len as usize)?;
+        }
+        Ok(written)
+    }
+
+    fn read(&mut self, core: &RiscCore, fd: RiscWord, buffer: Address, len: usize) -> Result<RiscWord, RiscSignedWord> {
+        let mut bytes = vec![0u8; len];
+        let read = match self.descriptors.get_mut(&fd).ok_or(EBADF)? {
+            Descriptor::Stdin => std::io::stdin().read(&mut bytes),
+            Descriptor::File(file) => file.read(&mut bytes),
+            _ => return Err(EBADF),
+        }
+        .map_err(|_| EINVAL)?;
+        core.poke_memory(buffer, &bytes[..read]).ok_or(EFAULT)?;
+        Ok(read as RiscWord)
+    }
+
+    /// the directory argument of openat is ignored, paths are relative to the working directory of the simulator
+    fn open(&mut self, core: &RiscCore, path: Address, flags: RiscWord) -> Result<RiscWord, RiscSignedWord> {
+        let path = read_c_string(core, path)?;
+        let mut options = OpenOptions::new();
+        match flags & O_ACCMODE {
+            O_WRONLY => options.write(true),
+            O_RDWR => options.read(true).write(true),
+            _ => options.read(true),
+        };
+        options.create(flags & O_CREAT != 0).truncate(flags & O_TRUNC != 0).append(flags & O_APPEND != 0);
+        let file = options.open(&path).map_err(|e| e.raw_os_error().map_or(EINVAL, |errno| errno as RiscSignedWord))?;
+        let fd = (3..).find(|fd| !self.descriptors.contains_key(fd)).unwrap();
+        self.descriptors.insert(fd, Descriptor::File(file));
+        Ok(fd)
+    }
+
+    /// new position in the file, from the start
+    fn seek(&mut self, fd: RiscWord, offset: i64, whence: RiscWord) -> Result<u64, RiscSignedWord> {
+        let Descriptor::File(file) = self.descriptors.get_mut(&fd).ok_or(EBADF)? else {
+            return Err(EINVAL);
+        };
+        let position = match whence {
+            0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return Err(EINVAL),
+        };
+        file.seek(position).map_err(|_| EINVAL)
+    }
+
+    /// llseek of RV32: the offset is split in two registers, and the new position is stored at `result` as it may not fit in a0
+    fn llseek(&mut self, core: &RiscCore, fd: RiscWord, offset_high: RiscWord, offset_low: RiscWord, result: Address, whence: RiscWord) -> Result<RiscWord, RiscSignedWord> {
+        let offset = (zero_extend_word(offset_high) << 32 | zero_extend_word(offset_low) & 0xFFFF_FFFF) as i64;
+        let position = self.seek(fd, offset, whence)?;
+        core.poke_memory(result, &position.to_le_bytes()).ok_or(EFAULT)?;
+        Ok(0)
+    }
+
+    /// type and permissions, and size of the file behind a descriptor, the standard streams are terminals
+    fn descriptor_mode(&self, fd: RiscWord) -> Result<(u32, u64), RiscSignedWord> {
+        match self.descriptors.get(&fd).ok_or(EBADF)? {
+            Descriptor::File(file) => Ok((S_IFREG | 0o644, file.metadata().map(|metadata| metadata.len()).unwrap_or(0))),
+            _ => Ok((S_IFCHR | 0o620, 0)),
+        }
+    }
+
+    /// only the type, permissions and size of the file are filled in
+    fn fstat(&mut self, core: &RiscCore, fd: RiscWord, stat: Address) -> Result<RiscWord, RiscSignedWord> {
+        let (mode, size) = self.descriptor_mode(fd)?;
+        let mut bytes = vec![0u8; STAT_SIZE];
+        bytes[16..20].copy_from_slice(&mode.to_le_bytes());
+        // st_nlink
+        bytes[20..24].copy_from_slice(&1u32.to_le_bytes());
+        bytes[48..56].copy_from_slice(&size.to_le_bytes());
+        // st_blksize
+        bytes[56..60].copy_from_slice(&4096u32.to_le_bytes());
+        core.poke_memory(stat, &bytes).ok_or(EFAULT)?;
+        Ok(0)
+    }
+
+    /// the file of the descriptor with AT_EMPTY_PATH and an empty path, otherwise a path relative to the working directory of the simulator
+    /// only the fields of STATX_BASIC are filled in
+    fn statx(&mut self, core: &RiscCore, fd: RiscWord, path: Address, flags: RiscWord, statx: Address) -> Result<RiscWord, RiscSignedWord> {
+        let path = read_c_string(core, path)?;
+        let (mode, size) = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            self.descriptor_mode(fd)?
+        } else {
+            let metadata = std::fs::metadata(&path).map_err(|e| e.raw_os_error().map_or(ENOENT, |errno| errno as RiscSignedWord))?;
+            let mode = if metadata.is_dir() { S_IFDIR | 0o755 } else { S_IFREG | 0o644 };
+            (mode, metadata.len())
+        };
+        let mut bytes = vec![0u8; STATX_SIZE];
+        bytes[0..4].copy_from_slice(&STATX_BASIC.to_le_bytes());
+        // stx_blksize
+        bytes[4..8].copy_from_slice(&4096u32.to_le_bytes());
+        // stx_nlink
+        bytes[16..20].copy_from_slice(&1u32.to_le_bytes());
+        bytes[28..30].copy_from_slice(&(mode as u16).to_le_bytes());
+        bytes[40..48].copy_from_slice(&size.to_le_bytes());
+        core.poke_memory(statx, &bytes).ok_or(EFAULT)?;
+        Ok(0)
+    }
+
+    /// the heap can only move between its start and the stack, otherwise the current break is returned as failure
+    fn brk(&mut self, address: Address) -> Address {
+        if address >= self.heap_start && address <= self.brk_limit {
+            self.brk = address;
+        }
+        self.brk
+    }
+}
+
+fn read_c_string(core: &RiscCore, mut address: Address) -> Result<String, RiscSignedWord> {
+    let mut bytes = vec![];
+    loop {
+        let byte = core.peek_memory(address, 1).ok_or(EFAULT)?[0];
+        if byte == 0 {
+            return Ok(String::from_utf8_lossy(&bytes).to_string());
+        }
+        bytes.push(byte);
+        address += 1;
+    }
+}